@@ -0,0 +1,39 @@
+use std::env;
+use std::error::Error;
+use std::time::Instant;
+use xml_dom::{chunk, Context, XmlDocument};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let count = env::args().nth(1).map_or(Ok(100_000), |v| v.parse())?;
+    let threads = env::args().nth(2).map_or(Ok(0), |v| v.parse())?;
+
+    let mut xml = String::from("<root>");
+    for i in 0..count {
+        xml.push_str(&format!(
+            "<item id='{0}' kind=\"record\"><name>item {0}</name><value>{1}</value>\
+            <!-- note --><tags><tag>a</tag><tag>b</tag></tags></item>",
+            i,
+            i * 7
+        ));
+    }
+    xml.push_str("</root>");
+    println!("{} bytes, {} children", xml.len(), count);
+
+    // The syntax pass is the part `parse_parallel` spreads over threads.
+    let start = Instant::now();
+    let tree = xml_parser::document(&xml).map_err(|e| e.to_string())?;
+    println!("syntax pass:    {:?}", start.elapsed());
+    drop(tree);
+
+    let start = Instant::now();
+    let (_, dom) = XmlDocument::from_raw(&xml)?;
+    println!("from_raw:       {:?}", start.elapsed());
+    drop(dom);
+
+    let start = Instant::now();
+    let dom = chunk::parse_parallel(&xml, Context::default(), threads)?;
+    println!("parse_parallel: {:?}", start.elapsed());
+    drop(dom);
+
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use super::error;
+use super::{
+    Attr, CharacterData, Element, Node, NodeList, ProcessingInstruction, XmlDocument, XmlElement,
+    XmlNode,
+};
+
+pub fn canonicalize(document: &XmlDocument, with_comments: bool) -> error::Result<String> {
+    let mut value = String::new();
+    let mut before_root = true;
+    for child in document.child_nodes().iter() {
+        let mut node = String::new();
+        match &child {
+            XmlNode::Element(v) => {
+                write_element(&mut node, v, &[], with_comments)?;
+                before_root = false;
+            }
+            XmlNode::PI(_) => write_node(&mut node, &child, &[], with_comments)?,
+            XmlNode::Comment(_) if with_comments => {
+                write_node(&mut node, &child, &[], with_comments)?
+            }
+            _ => continue,
+        }
+
+        // Nodes outside the document element are separated from it by a line feed.
+        match child {
+            XmlNode::Element(_) => value.push_str(node.as_str()),
+            _ if before_root => {
+                value.push_str(node.as_str());
+                value.push('\n');
+            }
+            _ => {
+                value.push('\n');
+                value.push_str(node.as_str());
+            }
+        }
+    }
+    Ok(value)
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn write_element(
+    value: &mut String,
+    element: &XmlElement,
+    rendered: &[(String, String)],
+    with_comments: bool,
+) -> error::Result<()> {
+    let name = match element.prefix() {
+        Some(prefix) => format!("{}:{}", prefix, element.tag_name()),
+        None => element.tag_name(),
+    };
+
+    let mut namespaces = vec![];
+    for namespace in element.in_scope_namespace()? {
+        if namespace.implicit() {
+            continue;
+        }
+
+        let prefix = match namespace.node_name().as_str() {
+            "xmlns" => String::new(),
+            v => v.to_string(),
+        };
+        let uri = namespace.node_value()?.unwrap_or_default();
+        namespaces.push((prefix, uri));
+    }
+    if !namespaces.iter().any(|(prefix, _)| prefix.is_empty()) {
+        namespaces.push((String::new(), String::new()));
+    }
+    namespaces.sort();
+
+    let mut attributes = vec![];
+    if let Some(attrs) = element.attributes() {
+        for attr in attrs.iter() {
+            let name = match attr.prefix() {
+                Some(prefix) if prefix == "xmlns" => continue,
+                Some(prefix) => format!("{}:{}", prefix, attr.local_name()),
+                None if attr.local_name() == "xmlns" => continue,
+                None => attr.local_name(),
+            };
+
+            let key = (attr.namespace_uri().unwrap_or_default(), attr.local_name());
+            attributes.push((key, name, attr.value()?));
+        }
+    }
+    attributes.sort();
+
+    value.push('<');
+    value.push_str(name.as_str());
+    for (prefix, uri) in namespaces.iter() {
+        let inherited = rendered
+            .iter()
+            .find(|v| v.0 == *prefix)
+            .map(|v| v.1.as_str())
+            .unwrap_or_default();
+        if inherited == uri {
+            continue;
+        }
+
+        if prefix.is_empty() {
+            value.push_str(" xmlns=\"");
+        } else {
+            value.push_str(format!(" xmlns:{}=\"", prefix).as_str());
+        }
+        value.push_str(escape_attribute(uri).as_str());
+        value.push('"');
+    }
+    for (_, name, v) in attributes {
+        value.push_str(format!(" {}=\"{}\"", name, escape_attribute(v.as_str())).as_str());
+    }
+    value.push('>');
+
+    for child in element.child_nodes().iter() {
+        write_node(value, &child, &namespaces, with_comments)?;
+    }
+
+    value.push_str(format!("</{}>", name).as_str());
+    Ok(())
+}
+
+fn write_node(
+    value: &mut String,
+    node: &XmlNode,
+    rendered: &[(String, String)],
+    with_comments: bool,
+) -> error::Result<()> {
+    match node {
+        XmlNode::Element(v) => write_element(value, v, rendered, with_comments)?,
+        XmlNode::Text(v) => value.push_str(escape_text(v.data()?.as_str()).as_str()),
+        XmlNode::CData(v) => value.push_str(escape_text(v.data()?.as_str()).as_str()),
+        XmlNode::ExpandedText(v) => value.push_str(escape_text(v.data()?.as_str()).as_str()),
+        XmlNode::EntityReference(v) => {
+            let children = v.child_nodes();
+            if children.length() == 0 {
+                value.push_str(escape_text(v.value()?.as_str()).as_str());
+            }
+            for child in children.iter() {
+                write_node(value, &child, rendered, with_comments)?;
+            }
+        }
+        XmlNode::PI(v) => {
+            value.push_str("<?");
+            value.push_str(v.target().as_str());
+            let data = ProcessingInstruction::data(v);
+            if !data.is_empty() {
+                value.push(' ');
+                value.push_str(data.as_str());
+            }
+            value.push_str("?>");
+        }
+        XmlNode::Comment(v) if with_comments => {
+            value.push_str(format!("<!--{}-->", v.data()?).as_str());
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn escape_text(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\r' => escaped.push_str("&#xD;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_attribute(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' => escaped.push_str("&#x9;"),
+            '\n' => escaped.push_str("&#xA;"),
+            '\r' => escaped.push_str("&#xD;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,663 @@
+use super::error;
+use super::{Context, XmlDocument};
+use std::io;
+use std::ops::Range;
+use std::thread;
+use xml_nom::xmlchar::{is_name_char, is_name_start_char};
+
+pub fn child_ranges(value: &str) -> error::Result<Vec<Range<usize>>> {
+    let bytes = value.as_bytes();
+    let mut ranges = vec![];
+    let mut names = vec![];
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'<' {
+            i += 1;
+            continue;
+        }
+
+        let rest = &value[i..];
+        if rest.starts_with("<!--") {
+            i = skip_to(value, i, "-->")?;
+        } else if rest.starts_with("<![CDATA[") {
+            i = skip_to(value, i, "]]>")?;
+        } else if rest.starts_with("<?") {
+            i = skip_to(value, i, "?>")?;
+        } else if rest.starts_with("<!") {
+            i = skip_declaration(value, i)?;
+        } else if rest.starts_with("</") {
+            let tag_start = i;
+            i = skip_tag(value, i)?;
+            let name = tag_name(&value[tag_start..i])?;
+            if names.pop() != Some(name) {
+                return Err(error::Error::Parse(value[..i].to_string()));
+            }
+            if names.len() == 1 {
+                ranges.push(start..i);
+            }
+        } else {
+            let tag_start = i;
+            i = skip_tag(value, i)?;
+            let tag = &value[tag_start..i];
+            let name = tag_name(tag)?;
+            let empty = tag.ends_with("/>");
+            if names.len() == 1 {
+                start = tag_start;
+                if empty {
+                    ranges.push(start..i);
+                }
+            }
+            if !empty {
+                names.push(name);
+            }
+        }
+    }
+
+    if !names.is_empty() {
+        return Err(error::Error::Parse(value[start..].to_string()));
+    }
+
+    Ok(ranges)
+}
+
+/// Parse `value` using up to `threads` threads.
+///
+/// Only the syntax pass is parallel. The children of the document element are
+/// located by [`child_ranges`] and parsed into syntax trees concurrently, then
+/// the document is built from the merged syntax tree on the current thread, as
+/// nodes cannot be shared between threads. The syntax pass is roughly a third
+/// of [`XmlDocument::from_raw`], which bounds the speedup; run
+/// `cargo run --release --example parse-parallel` to measure it on a machine.
+///
+/// `0` uses the available parallelism. Contexts that track source spans or skip
+/// elements are parsed on the current thread.
+pub fn parse_parallel(value: &str, context: Context, threads: usize) -> error::Result<XmlDocument> {
+    if context.spans_tracked || context.skipped_elements.is_some() {
+        return parse(value, context);
+    }
+
+    if let Some(max_length) = context.max_length {
+        if value.len() > max_length {
+            return Err(error::Error::LengthLimitExceeded(max_length));
+        }
+    }
+
+    let mut buffer = String::new();
+    let normalized = if context.eol_normalized {
+        super::normalize_eol(value, &mut buffer);
+        buffer.as_str()
+    } else {
+        value
+    };
+
+    // Each child is replaced by a placeholder, its start tag made empty.
+    let ranges = child_ranges(normalized)?;
+    let mut shell = String::new();
+    let mut pos = 0;
+    for range in ranges.iter() {
+        shell.push_str(&normalized[pos..range.start]);
+        let tag = &normalized[range.start..skip_tag(normalized, range.start)?];
+        match tag.strip_suffix("/>") {
+            Some(_) => shell.push_str(tag),
+            None => {
+                shell.push_str(&tag[..tag.len() - 1]);
+                shell.push_str("/>");
+            }
+        }
+        pos = range.end;
+    }
+    shell.push_str(&normalized[pos..]);
+
+    let (rest, mut tree) = super::parse_tree(shell.as_str(), &context)?;
+    if !rest.is_empty() {
+        return Err(error::Error::Parse(rest.to_string()));
+    }
+
+    let elements = parse_elements(normalized, ranges.as_slice(), &context, threads)?;
+    let placeholders = tree
+        .element
+        .content
+        .iter_mut()
+        .flat_map(|v| v.children.iter_mut())
+        .filter(|v| matches!(v.child, xml_parser::model::Contents::Element(_)));
+    for (cell, element) in placeholders.zip(elements) {
+        cell.child = xml_parser::model::Contents::Element(element);
+    }
+
+    let pool = &mut super::info::NodePool::default();
+    XmlDocument::from_tree(value, normalized, tree, context, None, pool)
+}
+
+pub fn split_records<R: io::BufRead>(reader: R, name: &str) -> XmlRecordIter<R> {
+    split_records_with_context(reader, name, Context::default())
+}
+
+pub fn split_records_with_context<R: io::BufRead>(
+    reader: R,
+    name: &str,
+    context: Context,
+) -> XmlRecordIter<R> {
+    let name = name.to_string();
+    XmlRecordIter {
+        scanner: Scanner::new(reader, Box::new(move |_, tag| Ok(tag_name(tag)? == name))),
+        context,
+    }
+}
+
+/// Scan `reader` for elements matching `simple_path` whose attributes satisfy
+/// `predicate`, without building a document.
+///
+/// `simple_path` is a `/` separated list of element names, `*` matches any name.
+/// A leading `/` anchors the path at the document element, otherwise it matches
+/// at any depth.
+pub fn find_streaming<R, P>(reader: R, simple_path: &str, predicate: P) -> XmlMatchIter<R>
+where
+    R: io::BufRead,
+    P: Fn(&[(String, String)]) -> bool + 'static,
+{
+    let anchored = simple_path.starts_with('/') && !simple_path.starts_with("//");
+    let steps = simple_path
+        .split('/')
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>();
+
+    let matcher = move |ancestors: &[String], tag: &str| {
+        let depth = ancestors.len() + 1;
+        if steps.is_empty() || steps.len() > depth || (anchored && steps.len() != depth) {
+            return Ok(false);
+        }
+
+        let names = ancestors.iter().map(|v| v.as_str()).chain([tag_name(tag)?]);
+        let matched = names
+            .skip(depth - steps.len())
+            .zip(steps.iter())
+            .all(|(name, step)| step == "*" || step == name);
+        Ok(matched && predicate(&attributes(tag)?))
+    };
+
+    XmlMatchIter {
+        scanner: Scanner::new(reader, Box::new(matcher)),
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+pub struct XmlRecordIter<R: io::BufRead> {
+    scanner: Scanner<R>,
+    context: Context,
+}
+
+impl<R: io::BufRead> Iterator for XmlRecordIter<R> {
+    type Item = error::Result<XmlDocument>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let document = self
+            .scanner
+            .next()?
+            .and_then(|v| parse(v.as_str(), self.context.clone()));
+        self.scanner.done |= document.is_err();
+        Some(document)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+pub struct XmlMatchIter<R: io::BufRead> {
+    scanner: Scanner<R>,
+}
+
+impl<R: io::BufRead> Iterator for XmlMatchIter<R> {
+    type Item = error::Result<XmlMatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.scanner.next()
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct XmlMatch {
+    path: Vec<String>,
+    attributes: Vec<(String, String)>,
+    value: String,
+}
+
+impl XmlMatch {
+    pub fn name(&self) -> &str {
+        self.path.last().map(|v| v.as_str()).unwrap_or_default()
+    }
+
+    pub fn path(&self) -> String {
+        format!("/{}", self.path.join("/"))
+    }
+
+    pub fn attributes(&self) -> &[(String, String)] {
+        self.attributes.as_slice()
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|v| v.0 == name)
+            .map(|v| v.1.as_str())
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.value.as_str()
+    }
+
+    pub fn to_document(&self) -> error::Result<XmlDocument> {
+        parse(self.value.as_str(), Context::default())
+    }
+}
+
+impl AsRef<str> for XmlMatch {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+type Matcher = Box<dyn Fn(&[String], &str) -> error::Result<bool>>;
+
+struct Scanner<R: io::BufRead> {
+    reader: R,
+    matcher: Matcher,
+    buffer: String,
+    pos: usize,
+    scopes: Vec<(String, Vec<(String, String)>)>,
+    record: Option<(usize, usize)>,
+    done: bool,
+}
+
+impl<R: io::BufRead> Iterator for Scanner<R> {
+    type Item = error::Result<XmlMatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            match self.scan() {
+                Ok(Some(value)) => return Some(Ok(value)),
+                Ok(None) => {}
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+
+            let mut bytes = vec![];
+            match self.reader.read_until(b'>', &mut bytes) {
+                Ok(0) => {
+                    self.done = true;
+                    let rest = &self.buffer[self.pos..];
+                    if self.record.is_some() || !self.scopes.is_empty() || rest.contains('<') {
+                        return Some(Err(error::Error::Parse(rest.to_string())));
+                    }
+                }
+                Ok(_) => match String::from_utf8(bytes) {
+                    Ok(v) => self.buffer.push_str(v.as_str()),
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(error::Error::Io(e.to_string())));
+                    }
+                },
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<R: io::BufRead> Scanner<R> {
+    fn new(reader: R, matcher: Matcher) -> Self {
+        Scanner {
+            reader,
+            matcher,
+            buffer: String::new(),
+            pos: 0,
+            scopes: vec![],
+            record: None,
+            done: false,
+        }
+    }
+
+    fn scan(&mut self) -> error::Result<Option<XmlMatch>> {
+        loop {
+            if self.record.is_none() {
+                self.buffer.drain(..self.pos);
+                self.pos = 0;
+            }
+
+            let i = match self.buffer[self.pos..].find('<') {
+                Some(v) => self.pos + v,
+                None => {
+                    self.pos = self.buffer.len();
+                    return Ok(None);
+                }
+            };
+
+            let rest = &self.buffer[i..];
+            let end = if rest.starts_with("<!--") {
+                skip_to(&self.buffer, i, "-->")
+            } else if rest.starts_with("<![CDATA[") {
+                skip_to(&self.buffer, i, "]]>")
+            } else if rest.starts_with("<?") {
+                skip_to(&self.buffer, i, "?>")
+            } else if rest.starts_with("<!") {
+                skip_declaration(&self.buffer, i)
+            } else {
+                skip_tag(&self.buffer, i)
+            };
+            let end = match end {
+                Ok(v) => v,
+                Err(_) => return Ok(None),
+            };
+            self.pos = end;
+
+            let tag = &self.buffer[i..end];
+            if tag.starts_with("<!") || tag.starts_with("<?") {
+                continue;
+            }
+
+            let empty = tag.ends_with("/>");
+            let name = tag_name(tag)?;
+            let matched = self.record.is_none() && !tag.starts_with("</") && self.matches(tag)?;
+            match self.record.as_mut() {
+                Some((start, depth)) => {
+                    if tag.starts_with("</") {
+                        *depth -= 1;
+                    } else if !empty {
+                        *depth += 1;
+                    }
+                    if *depth == 0 {
+                        let start = *start;
+                        self.record = None;
+                        return self.record(start, end).map(Some);
+                    }
+                }
+                None if tag.starts_with("</") => {
+                    self.scopes
+                        .pop()
+                        .ok_or_else(|| error::Error::Parse(tag.to_string()))?;
+                }
+                None if matched => {
+                    if empty {
+                        return self.record(i, end).map(Some);
+                    }
+                    self.record = Some((i, 1));
+                }
+                None if !empty => {
+                    let name = name.to_string();
+                    let declarations = namespace_declarations(tag)?;
+                    self.scopes.push((name, declarations));
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn matches(&self, tag: &str) -> error::Result<bool> {
+        let ancestors = self
+            .scopes
+            .iter()
+            .map(|v| v.0.clone())
+            .collect::<Vec<String>>();
+        (self.matcher)(ancestors.as_slice(), tag)
+    }
+
+    fn record(&self, start: usize, end: usize) -> error::Result<XmlMatch> {
+        let value = &self.buffer[start..end];
+        let (name, _) = split_name(value)?;
+        let declared = namespace_declarations(value)?;
+
+        let mut inherited: Vec<&(String, String)> = vec![];
+        for declaration in self.scopes.iter().flat_map(|v| v.1.iter()) {
+            inherited.retain(|v| v.0 != declaration.0);
+            inherited.push(declaration);
+        }
+
+        let at = 1 + name.len();
+        let mut text = value[..at].to_string();
+        for (name, raw) in inherited {
+            if !declared.iter().any(|v| v.0 == *name) {
+                text.push(' ');
+                text.push_str(raw);
+            }
+        }
+        text.push_str(&value[at..]);
+
+        let mut path = self
+            .scopes
+            .iter()
+            .map(|v| v.0.clone())
+            .collect::<Vec<String>>();
+        path.push(name.to_string());
+
+        let tag_end = skip_tag(value, 0).unwrap_or(value.len());
+        Ok(XmlMatch {
+            path,
+            attributes: attributes(&value[..tag_end])?,
+            value: text,
+        })
+    }
+}
+
+fn parse_elements<'a>(
+    value: &'a str,
+    ranges: &[Range<usize>],
+    context: &Context,
+    threads: usize,
+) -> error::Result<Vec<xml_parser::model::Element<'a>>> {
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |v| v.get()),
+        v => v,
+    };
+    let size = ((ranges.len() + threads - 1) / threads).max(1);
+
+    // Children of the document element are at depth 2.
+    let max_depth = context.max_nesting.map(|v| v.saturating_sub(1));
+    let parse_element = |range: &Range<usize>| {
+        let options = xml_parser::Options {
+            max_depth,
+            ..xml_parser::Options::default()
+        };
+        match xml_parser::element_with_options(&value[range.clone()], &options)? {
+            ("", element) => Ok(element),
+            (rest, _) => Err(xml_parser::nom::Err::Error(
+                xml_parser::nom::error::Error::new(rest, xml_parser::nom::error::ErrorKind::Eof),
+            )),
+        }
+    };
+
+    thread::scope(|scope| {
+        let handles = ranges
+            .chunks(size)
+            .map(|batch| {
+                scope.spawn(move || {
+                    batch
+                        .iter()
+                        .map(parse_element)
+                        .collect::<Result<Vec<_>, _>>()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut elements = vec![];
+        for handle in handles {
+            match handle.join() {
+                Ok(batch) => elements.extend(batch.map_err(|e| super::parse_error(e, context))?),
+                Err(e) => std::panic::resume_unwind(e),
+            }
+        }
+        Ok(elements)
+    })
+}
+
+fn parse(value: &str, context: Context) -> error::Result<XmlDocument> {
+    let (rest, document) = XmlDocument::from_raw_with_context(value, context)?;
+    if rest.is_empty() {
+        Ok(document)
+    } else {
+        Err(error::Error::Parse(rest.to_string()))
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn skip_to(value: &str, from: usize, terminator: &str) -> error::Result<usize> {
+    match value[from..].find(terminator) {
+        Some(i) => Ok(from + i + terminator.len()),
+        None => Err(error::Error::Parse(value[from..].to_string())),
+    }
+}
+
+fn skip_tag(value: &str, from: usize) -> error::Result<usize> {
+    let mut quote = None;
+    for (i, c) in value[from..].char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return Ok(from + i + 1),
+            _ => {}
+        }
+    }
+    Err(error::Error::Parse(value[from..].to_string()))
+}
+
+fn skip_declaration(value: &str, from: usize) -> error::Result<usize> {
+    let bytes = value.as_bytes();
+    let mut quote = None;
+    let mut brackets = 0;
+    let mut i = from + 2;
+    while i < bytes.len() {
+        match (quote, bytes[i]) {
+            (None, b'"' | b'\'') => quote = Some(bytes[i]),
+            (Some(q), c) if q == c => quote = None,
+            (None, b'<') if value[i..].starts_with("<!--") => {
+                i = skip_to(value, i, "-->")?;
+                continue;
+            }
+            (None, b'[') => brackets += 1,
+            (None, b']') => brackets -= 1,
+            (None, b'>') if brackets == 0 => return Ok(i + 1),
+            _ => {}
+        }
+        i += 1;
+    }
+    Err(error::Error::Parse(value[from..].to_string()))
+}
+
+fn split_name(tag: &str) -> error::Result<(&str, &str)> {
+    let name = tag
+        .strip_prefix("</")
+        .or_else(|| tag.strip_prefix('<'))
+        .unwrap_or(tag);
+    let end = name.find(|c| !is_name_char(c)).unwrap_or(name.len());
+    let (name, rest) = name.split_at(end);
+
+    let start = name.chars().next().map(is_name_start_char);
+    let terminated = rest
+        .chars()
+        .next()
+        .map(|c| c.is_ascii_whitespace() || c == '/' || c == '>');
+    match (start, terminated) {
+        (Some(true), Some(true) | None) => Ok((name, rest)),
+        _ => Err(error::Error::Parse(tag.to_string())),
+    }
+}
+
+fn tag_name(tag: &str) -> error::Result<&str> {
+    split_name(tag).map(|(name, _)| name)
+}
+
+fn namespace_declarations(tag: &str) -> error::Result<Vec<(String, String)>> {
+    let declarations = raw_attributes(tag)?
+        .into_iter()
+        .filter(|(name, _)| *name == "xmlns" || name.starts_with("xmlns:"))
+        .map(|(name, quoted)| (name.to_string(), format!("{}={}", name, quoted)))
+        .collect();
+    Ok(declarations)
+}
+
+fn attributes(tag: &str) -> error::Result<Vec<(String, String)>> {
+    let attributes = raw_attributes(tag)?
+        .into_iter()
+        .map(|(name, quoted)| (name.to_string(), unescape(&quoted[1..quoted.len() - 1])))
+        .collect();
+    Ok(attributes)
+}
+
+fn raw_attributes(tag: &str) -> error::Result<Vec<(&str, &str)>> {
+    let mut attributes = vec![];
+    let (_, mut rest) = split_name(tag)?;
+    loop {
+        rest = rest.trim_start();
+        let end = rest
+            .find(|c: char| c.is_ascii_whitespace() || c == '=')
+            .unwrap_or(rest.len());
+        let name = &rest[..end];
+        if name.is_empty() || name.starts_with(['/', '>']) {
+            break;
+        }
+
+        let value = rest[end..]
+            .trim_start()
+            .trim_start_matches('=')
+            .trim_start();
+        let quote = match value.chars().next() {
+            Some(c @ ('"' | '\'')) => c,
+            _ => break,
+        };
+        let close = match value[1..].find(quote) {
+            Some(v) => v + 2,
+            None => break,
+        };
+
+        attributes.push((name, &value[..close]));
+        rest = &value[close..];
+    }
+    Ok(attributes)
+}
+
+fn unescape(value: &str) -> String {
+    let mut text = String::new();
+    let mut rest = value;
+    while let Some(i) = rest.find('&') {
+        text.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        let end = match rest.find(';') {
+            Some(v) => v,
+            None => break,
+        };
+        let c = match &rest[1..end] {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "apos" => Some('\''),
+            "quot" => Some('"'),
+            v if v.starts_with("#x") => u32::from_str_radix(&v[2..], 16)
+                .ok()
+                .and_then(char::from_u32),
+            v if v.starts_with('#') => v[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        };
+
+        // Other entities cannot be resolved without the DTD, so they are kept as is.
+        match c {
+            Some(c) => text.push(c),
+            None => text.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    text.push_str(rest);
+    text
+}
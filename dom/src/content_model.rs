@@ -0,0 +1,112 @@
+use std::collections::BTreeSet;
+use xml_info::XmlDeclarationContentItem;
+
+type States = BTreeSet<usize>;
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Debug, Default)]
+pub(crate) struct Automaton {
+    transitions: Vec<Vec<(Option<String>, usize)>>,
+    accept: usize,
+}
+
+impl Automaton {
+    pub(crate) fn new(item: &XmlDeclarationContentItem) -> Self {
+        let mut automaton = Automaton::default();
+        let start = automaton.state();
+        automaton.accept = automaton.build(item, start);
+        automaton
+    }
+
+    pub(crate) fn start(&self) -> States {
+        self.closure([0].into_iter().collect())
+    }
+
+    pub(crate) fn step(&self, states: &States, name: &str) -> States {
+        let next = states
+            .iter()
+            .flat_map(|v| self.transitions[*v].iter())
+            .filter(|(label, _)| label.as_deref() == Some(name))
+            .map(|(_, to)| *to)
+            .collect();
+        self.closure(next)
+    }
+
+    pub(crate) fn steps<'a>(
+        &self,
+        states: &States,
+        names: impl Iterator<Item = &'a str>,
+    ) -> States {
+        names.fold(states.clone(), |states, name| self.step(&states, name))
+    }
+
+    pub(crate) fn accepts(&self, states: &States) -> bool {
+        states.contains(&self.accept)
+    }
+
+    pub(crate) fn names(&self, states: &States) -> BTreeSet<String> {
+        states
+            .iter()
+            .flat_map(|v| self.transitions[*v].iter())
+            .filter_map(|(label, _)| label.clone())
+            .collect()
+    }
+
+    fn build(&mut self, item: &XmlDeclarationContentItem, from: usize) -> usize {
+        let entry = self.state();
+        self.epsilon(from, entry);
+
+        let (exit, occurrence) = match item {
+            XmlDeclarationContentItem::Name(name, o) => {
+                let exit = self.state();
+                self.transitions[entry].push((Some(name.clone()), exit));
+                (exit, o)
+            }
+            XmlDeclarationContentItem::Seq(items, o) => {
+                let exit = items.iter().fold(entry, |v, item| self.build(item, v));
+                (exit, o)
+            }
+            XmlDeclarationContentItem::Choice(items, o) => {
+                let exit = self.state();
+                for item in items {
+                    let end = self.build(item, entry);
+                    self.epsilon(end, exit);
+                }
+                (exit, o)
+            }
+        };
+
+        match occurrence {
+            Some('?') => self.epsilon(entry, exit),
+            Some('*') => {
+                self.epsilon(entry, exit);
+                self.epsilon(exit, entry);
+            }
+            Some('+') => self.epsilon(exit, entry),
+            _ => {}
+        }
+        exit
+    }
+
+    fn closure(&self, mut states: States) -> States {
+        let mut stack = states.iter().copied().collect::<Vec<usize>>();
+        while let Some(state) = stack.pop() {
+            for (label, to) in &self.transitions[state] {
+                if label.is_none() && states.insert(*to) {
+                    stack.push(*to);
+                }
+            }
+        }
+        states
+    }
+
+    fn epsilon(&mut self, from: usize, to: usize) {
+        self.transitions[from].push((None, to));
+    }
+
+    fn state(&mut self) -> usize {
+        self.transitions.push(vec![]);
+        self.transitions.len() - 1
+    }
+}
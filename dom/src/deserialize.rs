@@ -0,0 +1,118 @@
+use super::error;
+use super::{AsNode, AsStringValue, Element, HasChild, Node, XmlElement};
+use std::fmt;
+use std::str::FromStr;
+
+pub trait XmlDeserialize: Sized {
+    fn deserialize(reader: &XmlElementReader) -> error::Result<Self>;
+}
+
+macro_rules! impl_from_str {
+    ($($t:ty),*) => {
+        $(
+            impl XmlDeserialize for $t {
+                fn deserialize(reader: &XmlElementReader) -> error::Result<Self> {
+                    reader.text()
+                }
+            }
+        )*
+    };
+}
+
+impl_from_str!(String, bool, char, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+pub fn from_element<T: XmlDeserialize>(element: &XmlElement) -> error::Result<T> {
+    T::deserialize(&XmlElementReader::new(element.clone()))
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug)]
+pub struct XmlElementReader {
+    element: XmlElement,
+    path: String,
+}
+
+impl XmlElementReader {
+    pub fn new(element: XmlElement) -> Self {
+        let path = element.as_node().location_path();
+        XmlElementReader { element, path }
+    }
+
+    pub fn element(&self) -> &XmlElement {
+        &self.element
+    }
+
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    pub fn attribute<T>(&self, name: &str) -> error::Result<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.optional_attribute(name)?
+            .ok_or_else(|| self.error(format!("missing attribute `{}`", name)))
+    }
+
+    pub fn optional_attribute<T>(&self, name: &str) -> error::Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let attribute = match self.element.get_attribute_node(name) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let value = attribute.as_string_value()?;
+        value.parse::<T>().map(Some).map_err(|e| {
+            let path = format!("{}/@{}", self.path, name);
+            error::Error::Deserialize(format!("{}: {}", path, e))
+        })
+    }
+
+    pub fn text<T>(&self) -> error::Result<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let value = self.element.as_string_value()?;
+        value.parse::<T>().map_err(|e| self.error(e))
+    }
+
+    pub fn child<T: XmlDeserialize>(&self, name: &str) -> error::Result<T> {
+        self.optional_child(name)?
+            .ok_or_else(|| self.error(format!("missing element `{}`", name)))
+    }
+
+    pub fn optional_child<T: XmlDeserialize>(&self, name: &str) -> error::Result<Option<T>> {
+        match self.readers(name).into_iter().next() {
+            Some(reader) => T::deserialize(&reader).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn children<T: XmlDeserialize>(&self, name: &str) -> error::Result<Vec<T>> {
+        self.readers(name)
+            .iter()
+            .map(|v| T::deserialize(v))
+            .collect()
+    }
+
+    pub fn error(&self, message: impl fmt::Display) -> error::Error {
+        error::Error::Deserialize(format!("{}: {}", self.path, message))
+    }
+
+    fn readers(&self, name: &str) -> Vec<XmlElementReader> {
+        let mut readers = vec![];
+        for child in self.element.child_iter() {
+            if let Some(element) = child.as_element().filter(|v| v.node_name() == name) {
+                let path = format!("{}/{}[{}]", self.path, name, readers.len() + 1);
+                readers.push(XmlElementReader { element, path });
+            }
+        }
+        readers
+    }
+}
@@ -0,0 +1,135 @@
+use super::Repertoire;
+use std::io;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Ascii,
+    Latin1,
+    Utf8,
+    Utf16Be,
+    Utf16Le,
+}
+
+impl Encoding {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let value = match name.to_ascii_uppercase().as_str() {
+            "US-ASCII" | "ASCII" => Encoding::Ascii,
+            "ISO-8859-1" | "LATIN1" => Encoding::Latin1,
+            "UTF-8" => Encoding::Utf8,
+            "UTF-16" | "UTF-16LE" => Encoding::Utf16Le,
+            "UTF-16BE" => Encoding::Utf16Be,
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Ascii => "US-ASCII",
+            Encoding::Latin1 => "ISO-8859-1",
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Be | Encoding::Utf16Le => "UTF-16",
+        }
+    }
+
+    pub fn repertoire(&self) -> Repertoire {
+        match self {
+            Encoding::Ascii => Repertoire::Ascii,
+            Encoding::Latin1 => Repertoire::Latin1,
+            _ => Repertoire::Unicode,
+        }
+    }
+
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf16Be => &[0xFE, 0xFF],
+            Encoding::Utf16Le => &[0xFF, 0xFE],
+            _ => &[],
+        }
+    }
+
+    pub fn encode(&self, value: &str) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        for c in value.chars() {
+            match self {
+                Encoding::Utf8 => {
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                Encoding::Utf16Be | Encoding::Utf16Le => {
+                    let mut buf = [0; 2];
+                    for unit in c.encode_utf16(&mut buf) {
+                        match self {
+                            Encoding::Utf16Be => bytes.extend_from_slice(&unit.to_be_bytes()),
+                            _ => bytes.extend_from_slice(&unit.to_le_bytes()),
+                        }
+                    }
+                }
+                _ if self.repertoire().contains(c) => bytes.push(c as u8),
+                _ => {
+                    let message = format!("character U+{:04X} is not in {}", c as u32, self.name());
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                }
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+pub struct EncodingWriter<W: io::Write> {
+    writer: W,
+    encoding: Encoding,
+    pending: Vec<u8>,
+    started: bool,
+}
+
+impl<W: io::Write> io::Write for EncodingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.started {
+            self.started = true;
+            self.writer.write_all(self.encoding.bom())?;
+        }
+
+        self.pending.extend_from_slice(buf);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(v) => v.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+
+        let value = std::str::from_utf8(&self.pending[..valid]).unwrap_or_default();
+        let bytes = self.encoding.encode(value)?;
+        self.writer.write_all(&bytes)?;
+        self.pending.drain(..valid);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let message = "incomplete UTF-8 sequence";
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        self.writer.flush()
+    }
+}
+
+impl<W: io::Write> EncodingWriter<W> {
+    pub fn new(writer: W, encoding: Encoding) -> Self {
+        EncodingWriter {
+            writer,
+            encoding,
+            pending: vec![],
+            started: false,
+        }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
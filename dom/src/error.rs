@@ -4,9 +4,20 @@ type ParseError<'a> = nom::Err<nom::error::Error<&'a str>>;
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
+    Aborted(String),
+    AccessDenied(String),
+    DepthLimitExceeded(usize),
+    Deserialize(String),
+    DoctypeRejected,
     Dom(DomException),
+    EntityExpansionLimitExceeded(usize),
     Info(xml_info::error::Error),
+    InvalidTypedValue(String),
+    Io(String),
+    LengthLimitExceeded(usize),
     Parse(String),
+    PrefixUndeclared(String),
+    UnboundPlaceholder(String),
 }
 
 #[derive(Debug, PartialEq)]
@@ -21,6 +32,7 @@ pub enum DomException {
     NotFoundErr,
     NotSupportErr,
     InuseAttributeErr,
+    NamespaceErr,
 }
 
 impl From<DomException> for Error {
@@ -35,6 +47,19 @@ impl<'a> From<ParseError<'a>> for Error {
     }
 }
 
+#[cfg(feature = "quick-xml")]
+impl From<quick_xml::Error> for Error {
+    fn from(value: quick_xml::Error) -> Self {
+        Error::Parse(value.to_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::Io(value.to_string())
+    }
+}
+
 impl From<xml_info::error::Error> for Error {
     fn from(value: xml_info::error::Error) -> Self {
         Error::Info(value)
@@ -0,0 +1,390 @@
+use super::Event;
+use super::XmlComment;
+use xml_nom::xmlchar::is_char;
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+type Attributes = Vec<(String, String)>;
+
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+const BOOLEAN_ATTRIBUTES: &[&str] = &[
+    "allowfullscreen",
+    "async",
+    "autofocus",
+    "autoplay",
+    "checked",
+    "controls",
+    "default",
+    "defer",
+    "disabled",
+    "formnovalidate",
+    "hidden",
+    "inert",
+    "ismap",
+    "loop",
+    "multiple",
+    "muted",
+    "nomodule",
+    "novalidate",
+    "open",
+    "readonly",
+    "required",
+    "reversed",
+    "selected",
+];
+
+const ENTITIES: &[(&str, &str)] = &[
+    ("amp", "&"),
+    ("apos", "'"),
+    ("copy", "\u{a9}"),
+    ("gt", ">"),
+    ("hellip", "\u{2026}"),
+    ("laquo", "\u{ab}"),
+    ("lt", "<"),
+    ("mdash", "\u{2014}"),
+    ("nbsp", "\u{a0}"),
+    ("ndash", "\u{2013}"),
+    ("quot", "\""),
+    ("raquo", "\u{bb}"),
+    ("reg", "\u{ae}"),
+];
+
+// -----------------------------------------------------------------------------------------------
+
+pub fn events(input: &str) -> Vec<Event> {
+    let mut builder = Builder::default();
+    let mut rest = input;
+    while !rest.is_empty() {
+        if let Some(v) = rest.strip_prefix("<!--") {
+            let (comment, tail) = v.split_once("-->").unwrap_or((v, ""));
+            builder.comment(comment);
+            rest = tail;
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            let end = rest.find('>').map(|v| v + 1).unwrap_or(rest.len());
+            rest = &rest[end..];
+        } else if let Some(v) = rest.strip_prefix("</") {
+            match tag_name(v) {
+                Some((name, tail)) => {
+                    let end = tail.find('>').map(|v| v + 1).unwrap_or(tail.len());
+                    builder.end(name.as_str());
+                    rest = &tail[end..];
+                }
+                None => {
+                    builder.text("</");
+                    rest = v;
+                }
+            }
+        } else if let Some(v) = rest.strip_prefix('<') {
+            match start_tag(v) {
+                Some((name, attributes, empty, tail)) => {
+                    rest = tail;
+                    if !empty && RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                        let end = find_end_tag(rest, name.as_str()).unwrap_or(rest.len());
+                        builder.start(name, attributes, empty);
+                        builder.text(&rest[..end]);
+                        rest = &rest[end..];
+                    } else {
+                        builder.start(name, attributes, empty);
+                    }
+                }
+                None => {
+                    builder.text("<");
+                    rest = v;
+                }
+            }
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            builder.text(decode(&rest[..end]).as_str());
+            rest = &rest[end..];
+        }
+    }
+    builder.finish()
+}
+
+pub(crate) fn is_void_element(name: &str) -> bool {
+    VOID_ELEMENTS.contains(&name.to_ascii_lowercase().as_str())
+}
+
+pub(crate) fn is_raw_text_element(name: &str) -> bool {
+    RAW_TEXT_ELEMENTS.contains(&name.to_ascii_lowercase().as_str())
+}
+
+pub(crate) fn is_boolean_attribute(name: &str, value: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    BOOLEAN_ATTRIBUTES.contains(&name.as_str())
+        && (value.is_empty() || value.eq_ignore_ascii_case(name.as_str()))
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Default)]
+struct Builder {
+    events: Vec<Event>,
+    stack: Vec<String>,
+}
+
+impl Builder {
+    fn start(&mut self, name: String, attributes: Attributes, empty: bool) {
+        if self.stack.is_empty() {
+            if name == "html" {
+                self.events
+                    .push(Event::StartElement(name.clone(), attributes));
+                self.stack.push(name);
+                return;
+            }
+            self.open_root();
+        } else if name == "html" {
+            return;
+        }
+
+        while let Some(open) = self.stack.last() {
+            if self.stack.len() > 1 && closes(open, name.as_str()) {
+                self.pop();
+            } else {
+                break;
+            }
+        }
+
+        self.events
+            .push(Event::StartElement(name.clone(), attributes));
+        if empty || VOID_ELEMENTS.contains(&name.as_str()) {
+            self.events.push(Event::EndElement(name));
+        } else {
+            self.stack.push(name);
+        }
+    }
+
+    fn end(&mut self, name: &str) {
+        if matches!(name, "html" | "body") {
+            return;
+        }
+
+        if let Some(index) = self.stack.iter().rposition(|v| v == name) {
+            while self.stack.len() > index.max(1) {
+                self.pop();
+            }
+        }
+    }
+
+    fn text(&mut self, value: &str) {
+        let value = value.chars().filter(|c| is_char(*c)).collect::<String>();
+        if value.is_empty() {
+            return;
+        }
+
+        if self.stack.is_empty() {
+            if value.chars().all(|c| c.is_ascii_whitespace()) {
+                return;
+            }
+            self.open_root();
+        }
+
+        match self.events.last_mut() {
+            Some(Event::Text(v)) => v.push_str(value.as_str()),
+            _ => self.events.push(Event::Text(value)),
+        }
+    }
+
+    fn comment(&mut self, value: &str) {
+        let value = value.chars().filter(|c| is_char(*c)).collect::<String>();
+        self.events
+            .push(Event::Comment(XmlComment::sanitize(value.as_str())));
+    }
+
+    fn finish(mut self) -> Vec<Event> {
+        if self.stack.is_empty() {
+            self.open_root();
+        }
+
+        while !self.stack.is_empty() {
+            self.pop();
+        }
+        self.events
+    }
+
+    fn open_root(&mut self) {
+        self.events
+            .push(Event::StartElement("html".to_string(), vec![]));
+        self.stack.push("html".to_string());
+    }
+
+    fn pop(&mut self) {
+        if let Some(name) = self.stack.pop() {
+            self.events.push(Event::EndElement(name));
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn closes(open: &str, name: &str) -> bool {
+    match open {
+        "p" => matches!(
+            name,
+            "address"
+                | "article"
+                | "aside"
+                | "blockquote"
+                | "details"
+                | "div"
+                | "dl"
+                | "fieldset"
+                | "figure"
+                | "footer"
+                | "form"
+                | "h1"
+                | "h2"
+                | "h3"
+                | "h4"
+                | "h5"
+                | "h6"
+                | "header"
+                | "hr"
+                | "main"
+                | "menu"
+                | "nav"
+                | "ol"
+                | "p"
+                | "pre"
+                | "section"
+                | "table"
+                | "ul"
+        ),
+        "li" => name == "li",
+        "dt" | "dd" => matches!(name, "dt" | "dd"),
+        "option" => matches!(name, "option" | "optgroup"),
+        "tr" => name == "tr",
+        "td" | "th" => matches!(name, "td" | "th" | "tr"),
+        "head" => name == "body",
+        _ => false,
+    }
+}
+
+fn decode(value: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = value;
+    while let Some(index) = rest.find('&') {
+        decoded.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        let resolved = rest[1..]
+            .split_once(';')
+            .map(|v| v.0)
+            .filter(|v| v.len() < 32)
+            .and_then(|v| reference(v).map(|c| (c, v.len() + 2)));
+        match resolved {
+            Some((c, len)) => {
+                decoded.push_str(c.as_str());
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn find_end_tag(value: &str, name: &str) -> Option<usize> {
+    let tag = format!("</{}", name);
+    value.to_ascii_lowercase().find(tag.as_str())
+}
+
+fn is_space(value: char) -> bool {
+    value.is_ascii_whitespace()
+}
+
+fn reference(name: &str) -> Option<String> {
+    let code = if let Some(v) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+        u32::from_str_radix(v, 16).ok()?
+    } else if let Some(v) = name.strip_prefix('#') {
+        v.parse::<u32>().ok()?
+    } else {
+        let (_, value) = ENTITIES.iter().find(|(v, _)| *v == name)?;
+        return Some(value.to_string());
+    };
+    char::from_u32(code).map(|c| c.to_string())
+}
+
+fn start_tag(value: &str) -> Option<(String, Attributes, bool, &str)> {
+    let (name, mut rest) = tag_name(value)?;
+    let mut attributes: Attributes = vec![];
+    loop {
+        rest = rest.trim_start_matches(is_space);
+        if let Some(tail) = rest.strip_prefix("/>") {
+            return Some((name, attributes, true, tail));
+        }
+        if let Some(tail) = rest.strip_prefix('>') {
+            return Some((name, attributes, false, tail));
+        }
+        if rest.is_empty() {
+            return Some((name, attributes, false, rest));
+        }
+
+        let end = rest
+            .find(|c: char| is_space(c) || matches!(c, '=' | '>' | '/'))
+            .unwrap_or(rest.len());
+        if end == 0 {
+            let c = rest.chars().next().unwrap();
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+
+        let attr_name = rest[..end].to_ascii_lowercase();
+        rest = &rest[end..];
+
+        let mut attr_value = String::new();
+        if let Some(tail) = rest.trim_start_matches(is_space).strip_prefix('=') {
+            let tail = tail.trim_start_matches(is_space);
+            let (value, tail) = match tail.chars().next() {
+                Some(quote) if quote == '"' || quote == '\'' => {
+                    let tail = &tail[1..];
+                    let end = tail.find(quote).unwrap_or(tail.len());
+                    (&tail[..end], &tail[(end + 1).min(tail.len())..])
+                }
+                _ => {
+                    let end = tail
+                        .find(|c: char| is_space(c) || c == '>')
+                        .unwrap_or(tail.len());
+                    (&tail[..end], &tail[end..])
+                }
+            };
+            attr_value = decode(value).chars().filter(|c| is_char(*c)).collect();
+            rest = tail;
+        }
+
+        let declaration = attr_name == "xmlns" || attr_name.starts_with("xmlns:");
+        if valid_name(attr_name.as_str())
+            && !declaration
+            && !attributes.iter().any(|(v, _)| *v == attr_name)
+        {
+            attributes.push((attr_name, attr_value));
+        }
+    }
+}
+
+fn tag_name(value: &str) -> Option<(String, &str)> {
+    if !value.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let end = value
+        .find(|c: char| is_space(c) || matches!(c, '/' | '>'))
+        .unwrap_or(value.len());
+    let name = value[..end].to_ascii_lowercase();
+    if valid_name(name.as_str()) {
+        Some((name, &value[end..]))
+    } else {
+        None
+    }
+}
+
+fn valid_name(value: &str) -> bool {
+    xml_parser::is_qname(value)
+}
@@ -1,10 +1,39 @@
+pub mod canonical;
+pub mod chunk;
+mod content_model;
+pub mod deserialize;
+pub mod encoding;
 pub mod error;
-
+pub mod html;
+pub mod merge;
+mod normalization;
+pub mod parser;
+#[cfg(feature = "quick-xml")]
+pub mod quick;
+pub mod resolver;
+pub mod schema;
+pub mod serialize;
+pub mod template;
+#[cfg(feature = "testutil")]
+pub mod testutil;
+pub mod uri;
+pub mod validation;
+
+use std::any;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert;
 use std::fmt;
+use std::fs;
 use std::io;
 use std::iter::Iterator;
+use std::marker::PhantomData;
+use std::ops;
+use std::path;
 use std::rc::Rc;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::{Arc, OnceLock};
 use xml_info as info;
 use xml_info::IndentedDisplay;
 use xml_info::{
@@ -19,15 +48,30 @@ use xml_info::{
 // TODO: Improve performance.
 // TODO: re-implement DocumentFragment
 
-pub type ExpandedName = (String, Option<String>, Option<String>);
-pub type NamedMapAdd<T> = dyn Fn(&XmlNode, T) -> error::Result<Option<T>>;
-pub type NamedMapGet<T> = dyn Fn(&XmlNode) -> Vec<(String, T)>;
-pub type NamedMapRemove<T> = dyn Fn(&XmlNode, &str) -> error::Result<T>;
+pub type NameHistogram = BTreeMap<QName, usize>;
+
+type NodeListCache = Rc<RefCell<Option<(usize, Vec<XmlNode>)>>>;
+
+type ElementListCache = Rc<RefCell<Option<(usize, Vec<XmlElement>)>>>;
 
 // -----------------------------------------------------------------------------------------------
 
 pub trait DomImplementation {
     fn has_feature(&self, feature: &str, version: Option<&str>) -> bool;
+
+    fn create_document_type(
+        &self,
+        qualified_name: &str,
+        public_id: Option<&str>,
+        system_id: Option<&str>,
+    ) -> error::Result<XmlDocumentType>;
+
+    fn create_document(
+        &self,
+        namespace_uri: Option<&str>,
+        qualified_name: Option<&str>,
+        doctype: Option<&XmlDocumentType>,
+    ) -> error::Result<XmlDocument>;
 }
 
 // -----------------------------------------------------------------------------------------------
@@ -49,11 +93,17 @@ pub trait Document: Node {
 pub trait DocumentMut: Document + NodeMut {
     fn create_element(&self, tag_name: &str) -> error::Result<XmlElement>;
 
+    fn create_element_ns(
+        &self,
+        namespace_uri: Option<&str>,
+        qualified_name: &str,
+    ) -> error::Result<XmlElement>;
+
     fn create_document_fragment(&self) -> XmlDocumentFragment;
 
     fn create_text_node(&self, data: &str) -> XmlText;
 
-    fn create_comment(&self, data: &str) -> XmlComment;
+    fn create_comment(&self, data: &str) -> error::Result<XmlComment>;
 
     fn create_cdata_section(&self, data: &str) -> XmlCDataSection;
 
@@ -65,6 +115,12 @@ pub trait DocumentMut: Document + NodeMut {
 
     fn create_attribute(&self, name: &str) -> error::Result<XmlAttr>;
 
+    fn create_attribute_ns(
+        &self,
+        namespace_uri: Option<&str>,
+        qualified_name: &str,
+    ) -> error::Result<XmlAttr>;
+
     fn create_entity_reference(&self, name: &str) -> error::Result<XmlEntityReference>;
 }
 
@@ -94,6 +150,46 @@ pub trait Node {
     fn owner_document(&self) -> Option<XmlDocument>;
 
     fn has_child(&self) -> bool;
+
+    fn namespace_uri(&self) -> Option<String> {
+        None
+    }
+
+    fn prefix(&self) -> Option<String> {
+        None
+    }
+
+    fn local_name(&self) -> Option<String> {
+        None
+    }
+
+    fn clone_node(&self, _deep: bool) -> error::Result<XmlNode> {
+        Err(error::DomException::NotSupportErr)?
+    }
+
+    fn base_uri(&self) -> Option<String>
+    where
+        Self: AsNode,
+    {
+        self.as_node().base_uri()
+    }
+
+    fn has_attributes(&self) -> bool {
+        self.attributes().is_some_and(|v| v.length() > 0)
+    }
+
+    fn lookup_namespace_uri(&self, prefix: Option<&str>) -> Option<String> {
+        self.parent_node()?.lookup_namespace_uri(prefix)
+    }
+
+    fn lookup_prefix(&self, namespace_uri: &str) -> Option<String> {
+        self.parent_node()?.lookup_prefix(namespace_uri)
+    }
+
+    fn is_default_namespace(&self, namespace_uri: Option<&str>) -> bool {
+        let namespace_uri = namespace_uri.filter(|v| !v.is_empty());
+        self.lookup_namespace_uri(None).as_deref() == namespace_uri
+    }
 }
 
 pub trait NodeMut {
@@ -115,6 +211,10 @@ pub trait NodeMut {
     fn append_child(&self, new_child: XmlNode) -> error::Result<XmlNode> {
         self.insert_before(new_child, None)
     }
+
+    fn set_prefix(&self, _prefix: Option<&str>) -> error::Result<()> {
+        Ok(())
+    }
 }
 
 // -----------------------------------------------------------------------------------------------
@@ -137,6 +237,329 @@ pub enum NodeType {
 
 // -----------------------------------------------------------------------------------------------
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WhatToShow(u32);
+
+impl ops::BitOr for WhatToShow {
+    type Output = WhatToShow;
+
+    fn bitor(self, rhs: WhatToShow) -> Self::Output {
+        WhatToShow(self.0 | rhs.0)
+    }
+}
+
+impl WhatToShow {
+    pub const ALL: WhatToShow = WhatToShow(0xFFFFFFFF);
+    pub const ELEMENT: WhatToShow = WhatToShow(0x00000001);
+    pub const ATTRIBUTE: WhatToShow = WhatToShow(0x00000002);
+    pub const TEXT: WhatToShow = WhatToShow(0x00000004);
+    pub const CDATA_SECTION: WhatToShow = WhatToShow(0x00000008);
+    pub const ENTITY_REFERENCE: WhatToShow = WhatToShow(0x00000010);
+    pub const ENTITY: WhatToShow = WhatToShow(0x00000020);
+    pub const PROCESSING_INSTRUCTION: WhatToShow = WhatToShow(0x00000040);
+    pub const COMMENT: WhatToShow = WhatToShow(0x00000080);
+    pub const DOCUMENT: WhatToShow = WhatToShow(0x00000100);
+    pub const DOCUMENT_TYPE: WhatToShow = WhatToShow(0x00000200);
+    pub const DOCUMENT_FRAGMENT: WhatToShow = WhatToShow(0x00000400);
+    pub const NOTATION: WhatToShow = WhatToShow(0x00000800);
+
+    pub fn contains(&self, node_type: NodeType) -> bool {
+        self.0 & (1 << (node_type as u32 - 1)) != 0
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FilterResult {
+    Accept = 1,
+    Reject,
+    Skip,
+}
+
+impl From<bool> for FilterResult {
+    fn from(value: bool) -> Self {
+        if value {
+            FilterResult::Accept
+        } else {
+            FilterResult::Skip
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DocumentPosition(u16);
+
+impl ops::BitOr for DocumentPosition {
+    type Output = DocumentPosition;
+
+    fn bitor(self, rhs: DocumentPosition) -> Self::Output {
+        DocumentPosition(self.0 | rhs.0)
+    }
+}
+
+impl DocumentPosition {
+    pub const DISCONNECTED: DocumentPosition = DocumentPosition(0x01);
+    pub const PRECEDING: DocumentPosition = DocumentPosition(0x02);
+    pub const FOLLOWING: DocumentPosition = DocumentPosition(0x04);
+    pub const CONTAINS: DocumentPosition = DocumentPosition(0x08);
+    pub const CONTAINED_BY: DocumentPosition = DocumentPosition(0x10);
+    pub const IMPLEMENTATION_SPECIFIC: DocumentPosition = DocumentPosition(0x20);
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub fn contains(&self, flag: DocumentPosition) -> bool {
+        self.0 & flag.0 == flag.0
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+pub type UserData = Rc<dyn any::Any>;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UserDataOperation {
+    NodeCloned = 1,
+    NodeImported = 2,
+    /// Reported when the document holding the data is dropped.
+    NodeDeleted = 3,
+    NodeRenamed = 4,
+    NodeAdopted = 5,
+}
+
+pub trait UserDataHandler {
+    fn handle(
+        &self,
+        operation: UserDataOperation,
+        key: &str,
+        data: &UserData,
+        src: Option<&XmlNode>,
+        dst: Option<&XmlNode>,
+    );
+}
+
+impl<F> UserDataHandler for F
+where
+    F: Fn(UserDataOperation, &str, &UserData, Option<&XmlNode>, Option<&XmlNode>),
+{
+    fn handle(
+        &self,
+        operation: UserDataOperation,
+        key: &str,
+        data: &UserData,
+        src: Option<&XmlNode>,
+        dst: Option<&XmlNode>,
+    ) {
+        self(operation, key, data, src, dst)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum DomErrorSeverity {
+    Warning = 1,
+    Error = 2,
+    FatalError = 3,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DomError {
+    severity: DomErrorSeverity,
+    kind: String,
+    node: XmlNode,
+    location: validation::Diagnostic,
+}
+
+impl DomError {
+    pub fn new(
+        severity: DomErrorSeverity,
+        kind: &str,
+        node: &XmlNode,
+        message: impl fmt::Display,
+    ) -> Self {
+        DomError {
+            severity,
+            kind: kind.to_string(),
+            node: node.clone(),
+            location: validation::Diagnostic::new(node, message),
+        }
+    }
+
+    pub fn severity(&self) -> DomErrorSeverity {
+        self.severity
+    }
+
+    pub fn kind(&self) -> &str {
+        self.kind.as_str()
+    }
+
+    pub fn message(&self) -> &str {
+        self.location.message()
+    }
+
+    pub fn related_node(&self) -> &XmlNode {
+        &self.node
+    }
+
+    pub fn location(&self) -> &validation::Diagnostic {
+        &self.location
+    }
+}
+
+impl fmt::Display for DomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{:?} [{}] {}", self.severity, self.kind, self.location)
+    }
+}
+
+pub trait DomErrorHandler {
+    /// Returns `false` to stop the operation that reported the error.
+    fn handle_error(&self, error: &DomError) -> bool;
+}
+
+impl<F> DomErrorHandler for F
+where
+    F: Fn(&DomError) -> bool,
+{
+    fn handle_error(&self, error: &DomError) -> bool {
+        self(error)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone)]
+pub struct DomConfiguration {
+    pub cdata_sections: bool,
+    pub comments: bool,
+    pub entities: bool,
+    pub namespaces: bool,
+    pub element_content_whitespace: bool,
+    pub validate: bool,
+    pub error_handler: Option<Rc<dyn DomErrorHandler>>,
+}
+
+impl Default for DomConfiguration {
+    fn default() -> Self {
+        DomConfiguration {
+            cdata_sections: true,
+            comments: true,
+            entities: true,
+            namespaces: true,
+            element_content_whitespace: true,
+            validate: false,
+            error_handler: None,
+        }
+    }
+}
+
+impl PartialEq<DomConfiguration> for DomConfiguration {
+    fn eq(&self, other: &DomConfiguration) -> bool {
+        let error_handler = match (&self.error_handler, &other.error_handler) {
+            (Some(s), Some(o)) => Rc::ptr_eq(s, o),
+            (None, None) => true,
+            _ => false,
+        };
+
+        self.cdata_sections == other.cdata_sections
+            && self.comments == other.comments
+            && self.entities == other.entities
+            && self.namespaces == other.namespaces
+            && self.element_content_whitespace == other.element_content_whitespace
+            && self.validate == other.validate
+            && error_handler
+    }
+}
+
+impl fmt::Debug for DomConfiguration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("DomConfiguration")
+            .field("cdata_sections", &self.cdata_sections)
+            .field("comments", &self.comments)
+            .field("entities", &self.entities)
+            .field("namespaces", &self.namespaces)
+            .field(
+                "element_content_whitespace",
+                &self.element_content_whitespace,
+            )
+            .field("validate", &self.validate)
+            .field("error_handler", &self.error_handler.is_some())
+            .finish()
+    }
+}
+
+impl DomConfiguration {
+    pub fn cdata_sections(mut self, value: bool) -> Self {
+        self.cdata_sections = value;
+        self
+    }
+
+    pub fn comments(mut self, value: bool) -> Self {
+        self.comments = value;
+        self
+    }
+
+    pub fn entities(mut self, value: bool) -> Self {
+        self.entities = value;
+        self
+    }
+
+    pub fn namespaces(mut self, value: bool) -> Self {
+        self.namespaces = value;
+        self
+    }
+
+    pub fn element_content_whitespace(mut self, value: bool) -> Self {
+        self.element_content_whitespace = value;
+        self
+    }
+
+    pub fn validate(mut self, value: bool) -> Self {
+        self.validate = value;
+        self
+    }
+
+    pub fn error_handler(mut self, value: impl DomErrorHandler + 'static) -> Self {
+        self.error_handler = Some(Rc::new(value));
+        self
+    }
+
+    fn report(&self, error: DomError) -> error::Result<()> {
+        // Without a handler only warnings are tolerated.
+        let proceed = match self.error_handler.as_deref() {
+            Some(handler) => handler.handle_error(&error),
+            None => error.severity() == DomErrorSeverity::Warning,
+        };
+        if proceed && error.severity() != DomErrorSeverity::FatalError {
+            Ok(())
+        } else {
+            Err(error::Error::Aborted(error.to_string()))
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+struct UserDataEntry {
+    key: String,
+    data: UserData,
+    handler: RefCell<Option<Rc<dyn UserDataHandler>>>,
+}
+
+impl Drop for UserDataEntry {
+    fn drop(&mut self) {
+        if let Some(handler) = self.handler.get_mut().take() {
+            let operation = UserDataOperation::NodeDeleted;
+            handler.handle(operation, self.key.as_str(), &self.data, None, None);
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
 pub trait NodeList {
     fn item(&self, index: usize) -> Option<XmlNode>;
 
@@ -196,6 +619,8 @@ pub trait Attr: Node {
     fn specified(&self) -> bool;
 
     fn value(&self) -> error::Result<String>;
+
+    fn owner_element(&self) -> Option<XmlElement>;
 }
 
 pub trait AttrMut: Attr + NodeMut {
@@ -214,6 +639,12 @@ pub trait Element: Node {
     fn get_attribute_node(&self, name: &str) -> Option<XmlAttr>;
 
     fn get_elements_by_tag_name(&self, tag_name: &str) -> XmlElementList;
+
+    fn has_attribute(&self, name: &str) -> bool {
+        self.get_attribute_node(name).is_some()
+    }
+
+    fn has_attribute_ns(&self, namespace_uri: Option<&str>, local_name: &str) -> bool;
 }
 
 pub trait ElementMut: Element + NodeMut {
@@ -235,6 +666,50 @@ pub trait ElementMut: Element + NodeMut {
     fn normalize(&self);
 }
 
+pub trait ElementTraversal: Node + AsNode {
+    fn first_element_child(&self) -> Option<XmlElement> {
+        self.child_nodes().iter().find_map(|v| v.as_element())
+    }
+
+    fn last_element_child(&self) -> Option<XmlElement> {
+        self.child_nodes()
+            .iter()
+            .filter_map(|v| v.as_element())
+            .last()
+    }
+
+    fn previous_element_sibling(&self) -> Option<XmlElement> {
+        let node = self.as_node();
+        let mut previous = None;
+        for child in self.parent_node()?.child_nodes().iter() {
+            if child.ptr_eq(&node) {
+                return previous;
+            }
+
+            if let Some(element) = child.as_element() {
+                previous = Some(element);
+            }
+        }
+
+        None
+    }
+
+    fn next_element_sibling(&self) -> Option<XmlElement> {
+        let node = self.as_node();
+        let parent = self.parent_node()?;
+        let mut children = parent.child_nodes().iter();
+        children.find(|v| v.ptr_eq(&node))?;
+        children.find_map(|v| v.as_element())
+    }
+
+    fn child_element_count(&self) -> usize {
+        self.child_nodes()
+            .iter()
+            .filter(|v| v.as_element().is_some())
+            .count()
+    }
+}
+
 // -----------------------------------------------------------------------------------------------
 
 pub trait Text: CharacterData {}
@@ -260,6 +735,10 @@ pub trait CDataSectionMut: TextMut {}
 pub trait DocumentType: Node {
     fn name(&self) -> String;
 
+    fn public_id(&self) -> Option<String>;
+
+    fn system_id(&self) -> Option<String>;
+
     fn entities(&self) -> XmlNamedNodeMap<XmlEntity>;
 
     fn notations(&self) -> XmlNamedNodeMap<XmlNotation>;
@@ -547,10 +1026,54 @@ impl Node for XmlNode {
             XmlNode::ExpandedText(v) => v.has_child(),
         }
     }
+
+    fn namespace_uri(&self) -> Option<String> {
+        match self {
+            XmlNode::Element(v) => Node::namespace_uri(v),
+            XmlNode::Attribute(v) => Node::namespace_uri(v),
+            _ => None,
+        }
+    }
+
+    fn prefix(&self) -> Option<String> {
+        match self {
+            XmlNode::Element(v) => Node::prefix(v),
+            XmlNode::Attribute(v) => Node::prefix(v),
+            _ => None,
+        }
+    }
+
+    fn local_name(&self) -> Option<String> {
+        match self {
+            XmlNode::Element(v) => Node::local_name(v),
+            XmlNode::Attribute(v) => Node::local_name(v),
+            _ => None,
+        }
+    }
+    fn clone_node(&self, deep: bool) -> error::Result<XmlNode> {
+        duplicate_node(self, deep)
+    }
+    fn lookup_namespace_uri(&self, prefix: Option<&str>) -> Option<String> {
+        match self {
+            XmlNode::Element(v) => v.lookup_namespace_uri(prefix),
+            XmlNode::Attribute(v) => v.lookup_namespace_uri(prefix),
+            XmlNode::Document(v) => v.lookup_namespace_uri(prefix),
+            _ => self.parent_node()?.lookup_namespace_uri(prefix),
+        }
+    }
+
+    fn lookup_prefix(&self, namespace_uri: &str) -> Option<String> {
+        match self {
+            XmlNode::Element(v) => v.lookup_prefix(namespace_uri),
+            XmlNode::Attribute(v) => v.lookup_prefix(namespace_uri),
+            XmlNode::Document(v) => v.lookup_prefix(namespace_uri),
+            _ => self.parent_node()?.lookup_prefix(namespace_uri),
+        }
+    }
 }
 
 impl AsExpandedName for XmlNode {
-    fn as_expanded_name(&self) -> error::Result<Option<ExpandedName>> {
+    fn as_expanded_name(&self) -> error::Result<Option<QName>> {
         match self {
             XmlNode::Element(v) => v.as_expanded_name(),
             XmlNode::Attribute(v) => v.as_expanded_name(),
@@ -613,90 +1136,479 @@ impl PrettyPrint for XmlNode {
 }
 
 impl XmlNode {
-    pub fn id(&self) -> usize {
-        match self {
-            XmlNode::Attribute(v) => v.attribute.borrow().id(),
-            XmlNode::CData(v) => v.data.borrow().id(),
-            XmlNode::Comment(v) => v.data.borrow().id(),
-            XmlNode::Document(v) => v.document.borrow().id(),
-            XmlNode::DocumentFragment(v) => v.document.borrow().id(),
-            XmlNode::DocumentType(v) => v.declaration.borrow().id(),
-            XmlNode::Element(v) => v.element.borrow().id(),
-            XmlNode::Entity(v) => v.entity.borrow().id(),
-            XmlNode::EntityReference(v) => v.inner().id(),
-            XmlNode::Namespace(v) => v.namespace.borrow().id(),
-            XmlNode::Notation(v) => v.notation.borrow().id(),
-            XmlNode::PI(v) => v.pi.borrow().id(),
-            XmlNode::ExpandedText(v) => v.data[0].id(),
-            XmlNode::Text(v) => v.data.borrow().id(),
+    pub fn child_nodes_filtered(
+        &self,
+        what_to_show: WhatToShow,
+        filter: impl Fn(&XmlNode) -> bool + 'static,
+    ) -> XmlFilteredNodeList {
+        XmlFilteredNodeList {
+            list: self.child_nodes(),
+            what_to_show,
+            filter: Rc::new(filter),
         }
     }
 
-    pub fn order(&self) -> usize {
-        match self {
-            XmlNode::Attribute(v) => v.attribute.borrow().order(),
-            XmlNode::CData(v) => v.data.borrow().order(),
-            XmlNode::Comment(v) => v.data.borrow().order(),
-            XmlNode::Document(v) => v.document.borrow().order(),
-            XmlNode::DocumentFragment(v) => v.document.borrow().order(),
-            XmlNode::DocumentType(v) => v.declaration.borrow().order(),
-            XmlNode::Element(v) => v.element.borrow().order(),
-            XmlNode::Entity(_) => 0,
-            XmlNode::EntityReference(v) => v.inner().order(),
-            XmlNode::Namespace(v) => v.namespace.borrow().order(),
-            XmlNode::Notation(_) => 0,
-            XmlNode::PI(_) => 0,
-            XmlNode::ExpandedText(v) => v.data[0].order(),
-            XmlNode::Text(v) => v.data.borrow().order(),
+    pub fn tree_walker(
+        &self,
+        what_to_show: WhatToShow,
+        filter: Option<Rc<dyn NodeFilter>>,
+    ) -> XmlTreeWalker {
+        XmlTreeWalker {
+            root: self.clone(),
+            current: self.clone(),
+            what_to_show,
+            filter,
         }
     }
 
-    fn previous_sibling_child(&self, node: XmlNode) -> Option<XmlNode> {
-        let children = match &self {
-            XmlNode::Element(v) => v.children(),
-            XmlNode::Attribute(v) => v.children(),
-            XmlNode::EntityReference(v) => v.children(),
-            XmlNode::Entity(v) => v.children(),
-            XmlNode::Document(v) => v.children(),
-            XmlNode::DocumentFragment(v) => v.children(),
-            _ => return None,
+    pub fn node_iterator(
+        &self,
+        what_to_show: WhatToShow,
+        filter: Option<Rc<dyn NodeFilter>>,
+    ) -> XmlNodeIterator {
+        let mut iterator = XmlNodeIterator {
+            root: self.clone(),
+            reference: self.clone(),
+            pointer_before_reference: true,
+            what_to_show,
+            filter,
+            path: vec![],
         };
+        iterator.update_path();
+        iterator
+    }
 
-        children
-            .iter()
-            .rev()
-            .skip_while(|&v| v.order() != node.order())
-            .nth(1)
-            .cloned()
+    pub fn descendants(&self) -> XmlDescendantIter {
+        XmlDescendantIter {
+            root: self.clone(),
+            next: self.first_child(),
+        }
     }
 
-    fn next_sibling_child(&self, node: XmlNode) -> Option<XmlNode> {
-        let children = match &self {
-            XmlNode::Element(v) => v.children(),
-            XmlNode::Attribute(v) => v.children(),
-            XmlNode::EntityReference(v) => v.children(),
-            XmlNode::Entity(v) => v.children(),
-            XmlNode::Document(v) => v.children(),
-            XmlNode::DocumentFragment(v) => v.children(),
-            _ => return None,
-        };
+    pub fn descendant_elements(&self) -> XmlDescendantElementIter {
+        XmlDescendantElementIter {
+            nodes: self.descendants(),
+        }
+    }
 
-        children
-            .iter()
-            .skip_while(|&v| v.order() != node.order())
-            .nth(1)
-            .cloned()
+    pub fn ancestors(&self) -> XmlAncestorIter {
+        XmlAncestorIter {
+            next: self.parent_node(),
+        }
     }
-}
 
-impl From<Rc<info::XmlItem>> for XmlNode {
-    fn from(value: Rc<info::XmlItem>) -> Self {
-        match &*value {
-            info::XmlItem::Attribute(v) => XmlAttr::from(v.clone()).as_node(),
-            info::XmlItem::CData(v) => XmlCDataSection::from(v.clone()).as_node(),
-            info::XmlItem::CharReference(v) => XmlEntityReference::from(v.clone()).as_node(),
+    pub fn preceding_siblings(&self) -> XmlSiblingIter {
+        XmlSiblingIter {
+            next: self.previous_sibling(),
+            forward: false,
+        }
+    }
+
+    pub fn following_siblings(&self) -> XmlSiblingIter {
+        XmlSiblingIter {
+            next: self.next_sibling(),
+            forward: true,
+        }
+    }
+
+    pub fn events(&self) -> XmlEventIter {
+        XmlEventIter::new(self.clone())
+    }
+
+    pub fn source_span(&self) -> Option<info::SourceSpan> {
+        self.info_context()?.span(self.id())
+    }
+
+    pub fn type_annotation(&self) -> Option<schema::SchemaType> {
+        let name = self.info_context()?.type_annotation(self.id())?;
+        schema::SchemaType::from_name(&name)
+    }
+
+    pub fn set_user_data(
+        &self,
+        key: &str,
+        data: Option<UserData>,
+        handler: Option<Rc<dyn UserDataHandler>>,
+    ) -> Option<UserData> {
+        let entry = data.map(|data| {
+            let entry = UserDataEntry {
+                key: key.to_string(),
+                data,
+                handler: RefCell::new(handler),
+            };
+            Rc::new(entry) as Rc<dyn any::Any>
+        });
+        let old = self.info_context()?.set_user_data(self.id(), key, entry)?;
+        let old = old.downcast::<UserDataEntry>().ok()?;
+
+        // Replaced data is not reported as deleted.
+        old.handler.borrow_mut().take();
+        Some(old.data.clone())
+    }
+
+    pub fn get_user_data(&self, key: &str) -> Option<UserData> {
+        let entry = self.info_context()?.user_data(self.id(), key)?;
+        let entry = entry.downcast::<UserDataEntry>().ok()?;
+        Some(entry.data.clone())
+    }
+
+    pub fn normalized_value(&self) -> error::Result<String> {
+        let value = self.as_string_value()?;
+        match self.type_annotation() {
+            Some(v) => Ok(v.white_space().apply(&value)),
+            None => Ok(value),
+        }
+    }
+
+    pub fn typed_value(&self) -> error::Result<schema::TypedValue> {
+        let value = self.as_string_value()?;
+        match self.type_annotation() {
+            Some(_) if self.child_iter().any(|v| v.as_element().is_some()) => {
+                Err(error::Error::InvalidTypedValue(value))
+            }
+            Some(v) => v.parse(&value),
+            None => Ok(schema::TypedValue::String(value)),
+        }
+    }
+
+    pub fn inherited_attribute(&self, name: &str) -> Option<String> {
+        let (prefix, local_name) = xml_parser::split_qname(name)?;
+
+        let mut node = match self {
+            XmlNode::Attribute(v) => v.owner_element().map(|v| v.as_node()),
+            _ => Some(self.clone()),
+        };
+
+        while let Some(current) = node {
+            if let XmlNode::Element(element) = &current {
+                if let Some(value) = element.qualified_attribute(prefix, local_name) {
+                    return Some(value);
+                }
+            }
+            node = current.parent_node();
+        }
+
+        None
+    }
+
+    pub fn language(&self) -> Option<String> {
+        self.inherited_attribute("xml:lang")
+            .filter(|v| !v.is_empty())
+    }
+
+    pub fn space_preserved(&self) -> bool {
+        self.inherited_attribute("xml:space").as_deref() == Some("preserve")
+    }
+
+    pub fn base_uri(&self) -> Option<String> {
+        let mut node = match self {
+            XmlNode::Attribute(v) => v.owner_element().map(|v| v.as_node()),
+            _ => Some(self.clone()),
+        };
+
+        let mut bases = vec![];
+        let mut document_uri = self.owner_document().and_then(|v| v.document_uri());
+        while let Some(current) = node {
+            match &current {
+                XmlNode::Element(element) => {
+                    if let Some(base) = element.xml_attribute("base") {
+                        bases.push(base);
+                    }
+                }
+                XmlNode::Document(document) => document_uri = document.document_uri(),
+                _ => {}
+            }
+            node = current.parent_node();
+        }
+
+        let base_uri = bases
+            .iter()
+            .rev()
+            .fold(document_uri.unwrap_or_default(), |base, v| {
+                uri::resolve(base.as_str(), uri::iri_to_uri(v).as_str())
+            });
+        Some(base_uri).filter(|v| !v.is_empty())
+    }
+
+    pub fn location_path(&self) -> String {
+        let (parent, step) = match self {
+            XmlNode::Document(_) => return "/".to_string(),
+            XmlNode::Attribute(v) => {
+                let owner = v.owner_element().map(|v| v.as_node());
+                (owner, format!("@{}", v.name()))
+            }
+            XmlNode::Element(v) => {
+                let name = v.node_name();
+                let position = self
+                    .sibling_position(|n| n.as_element().is_some_and(|v| v.node_name() == name));
+                (self.parent_node(), format!("{}[{}]", name, position))
+            }
+            XmlNode::CData(_) | XmlNode::ExpandedText(_) | XmlNode::Text(_) => {
+                let position = self.sibling_position(|n| {
+                    matches!(
+                        n,
+                        XmlNode::CData(_) | XmlNode::ExpandedText(_) | XmlNode::Text(_)
+                    )
+                });
+                (self.parent_node(), format!("text()[{}]", position))
+            }
+            XmlNode::Comment(_) => {
+                let position = self.sibling_position(|n| matches!(n, XmlNode::Comment(_)));
+                (self.parent_node(), format!("comment()[{}]", position))
+            }
+            XmlNode::PI(v) => {
+                let target = v.target();
+                let position =
+                    self.sibling_position(|n| n.as_pi().is_some_and(|v| v.target() == target));
+                let step = format!("processing-instruction('{}')[{}]", target, position);
+                (self.parent_node(), step)
+            }
+            _ => return String::new(),
+        };
+
+        match parent.map(|v| v.location_path()) {
+            Some(path) if path.ends_with('/') => format!("{}{}", path, step),
+            Some(path) if !path.is_empty() => format!("{}/{}", path, step),
+            _ => format!("/{}", step),
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        match self {
+            XmlNode::Attribute(v) => v.attribute.borrow().id(),
+            XmlNode::CData(v) => v.data.borrow().id(),
+            XmlNode::Comment(v) => v.data.borrow().id(),
+            XmlNode::Document(v) => v.document.borrow().id(),
+            XmlNode::DocumentFragment(v) => v.document.borrow().id(),
+            XmlNode::DocumentType(v) => v.declaration.borrow().id(),
+            XmlNode::Element(v) => v.element.borrow().id(),
+            XmlNode::Entity(v) => v.entity.borrow().id(),
+            XmlNode::EntityReference(v) => v.inner().id(),
+            XmlNode::Namespace(v) => v.namespace.borrow().id(),
+            XmlNode::Notation(v) => v.notation.borrow().id(),
+            XmlNode::PI(v) => v.pi.borrow().id(),
+            XmlNode::ExpandedText(v) => v.data[0].id(),
+            XmlNode::Text(v) => v.data.borrow().id(),
+        }
+    }
+
+    pub fn cmp_document_order(&self, other: &XmlNode) -> cmp::Ordering {
+        if self.ptr_eq(other) {
+            return cmp::Ordering::Equal;
+        }
+
+        self.document_id()
+            .cmp(&other.document_id())
+            .then_with(|| self.document_position().cmp(&other.document_position()))
+            .then_with(|| self.id().cmp(&other.id()))
+    }
+
+    pub fn compare_document_position(&self, other: &XmlNode) -> DocumentPosition {
+        if self.ptr_eq(other) {
+            return DocumentPosition::default();
+        }
+
+        let position = self.document_position();
+        let other_position = other.document_position();
+        let direction = match self.cmp_document_order(other) {
+            cmp::Ordering::Greater => DocumentPosition::PRECEDING,
+            _ => DocumentPosition::FOLLOWING,
+        };
+
+        // Nodes in different documents or detached subtrees have different roots.
+        if self.document_id() != other.document_id() || position.first() != other_position.first() {
+            return DocumentPosition::DISCONNECTED
+                | DocumentPosition::IMPLEMENTATION_SPECIFIC
+                | direction;
+        }
+
+        if other_position.starts_with(&position) {
+            DocumentPosition::CONTAINED_BY | DocumentPosition::FOLLOWING
+        } else if position.starts_with(&other_position) {
+            DocumentPosition::CONTAINS | DocumentPosition::PRECEDING
+        } else {
+            direction
+        }
+    }
+
+    pub fn is_same_node(&self, other: &XmlNode) -> bool {
+        self.ptr_eq(other)
+    }
+
+    pub fn is_equal_node(&self, other: &XmlNode) -> bool {
+        if self.ptr_eq(other) {
+            return true;
+        }
+
+        if self.node_type() != other.node_type()
+            || self.node_name() != other.node_name()
+            || self.local_name() != other.local_name()
+            || self.namespace_uri() != other.namespace_uri()
+            || self.prefix() != other.prefix()
+            || self.node_value().ok() != other.node_value().ok()
+        {
+            return false;
+        }
+
+        let equal = match (self, other) {
+            (XmlNode::DocumentType(l), XmlNode::DocumentType(r)) => {
+                l.public_id() == r.public_id()
+                    && l.system_id() == r.system_id()
+                    && equal_named_node_map(&l.entities(), &r.entities())
+                    && equal_named_node_map(&l.notations(), &r.notations())
+            }
+            // The replacement text is only available from the declaration.
+            (XmlNode::Entity(l), XmlNode::Entity(r)) => l.to_string() == r.to_string(),
+            (XmlNode::Notation(l), XmlNode::Notation(r)) => {
+                l.public_id() == r.public_id() && l.system_id() == r.system_id()
+            }
+            _ => match (self.attributes(), other.attributes()) {
+                (Some(l), Some(r)) => equal_named_node_map(&l, &r),
+                (None, None) => true,
+                _ => false,
+            },
+        };
+        if !equal {
+            return false;
+        }
+
+        let children = self.child_nodes();
+        let other_children = other.child_nodes();
+        children.length() == other_children.length()
+            && children
+                .iter()
+                .zip(other_children.iter())
+                .all(|(l, r)| l.is_equal_node(&r))
+    }
+
+    pub fn document_id(&self) -> usize {
+        match self {
+            XmlNode::Attribute(v) => v.attribute.borrow().document_id(),
+            XmlNode::CData(v) => v.data.borrow().document_id(),
+            XmlNode::Comment(v) => v.data.borrow().document_id(),
+            XmlNode::Document(v) => v.document.borrow().document_id(),
+            XmlNode::DocumentFragment(v) => v.document.borrow().document_id(),
+            XmlNode::DocumentType(v) => v.declaration.borrow().document_id(),
+            XmlNode::Element(v) => v.element.borrow().document_id(),
+            XmlNode::Entity(v) => v.entity.borrow().document_id(),
+            XmlNode::EntityReference(v) => v.inner().document_id(),
+            XmlNode::Namespace(v) => v.namespace.borrow().document_id(),
+            XmlNode::Notation(v) => v.notation.borrow().document_id(),
+            XmlNode::PI(v) => v.pi.borrow().document_id(),
+            XmlNode::ExpandedText(v) => v.data[0].document_id(),
+            XmlNode::Text(v) => v.data.borrow().document_id(),
+        }
+    }
+
+    pub fn handle(&self) -> NodeHandle {
+        NodeHandle {
+            document_id: self.document_id(),
+            node_id: self.id(),
+        }
+    }
+
+    pub fn ptr_eq(&self, other: &XmlNode) -> bool {
+        self.handle() == other.handle()
+    }
+
+    pub fn order(&self) -> usize {
+        match self {
+            XmlNode::Attribute(v) => v.attribute.borrow().order(),
+            XmlNode::CData(v) => v.data.borrow().order(),
+            XmlNode::Comment(v) => v.data.borrow().order(),
+            XmlNode::Document(v) => v.document.borrow().order(),
+            XmlNode::DocumentFragment(v) => v.document.borrow().order(),
+            XmlNode::DocumentType(v) => v.declaration.borrow().order(),
+            XmlNode::Element(v) => v.element.borrow().order(),
+            XmlNode::Entity(_) => 0,
+            XmlNode::EntityReference(v) => v.inner().order(),
+            XmlNode::Namespace(v) => v.namespace.borrow().order(),
+            XmlNode::Notation(_) => 0,
+            XmlNode::PI(_) => 0,
+            XmlNode::ExpandedText(v) => v.data[0].order(),
+            XmlNode::Text(v) => v.data.borrow().order(),
+        }
+    }
+
+    fn sibling_position(&self, f: impl Fn(&XmlNode) -> bool) -> usize {
+        let siblings = match self.parent_node() {
+            Some(parent) => parent.child_iter(),
+            None => return 1,
+        };
+        siblings
+            .filter(|v| f(v))
+            .take_while(|v| !v.ptr_eq(self))
+            .count()
+            + 1
+    }
+
+    fn notify_user_data(&self, operation: UserDataOperation, dst: Option<&XmlNode>) {
+        let entries = match self.info_context() {
+            Some(context) => context.user_data_entries(self.id()),
+            None => return,
+        };
+        for (_, entry) in entries {
+            let entry = match entry.downcast::<UserDataEntry>() {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            let handler = entry.handler.borrow().clone();
+            if let Some(handler) = handler {
+                handler.handle(operation, entry.key.as_str(), &entry.data, Some(self), dst);
+            }
+        }
+    }
+
+    fn info_context(&self) -> Option<info::Context> {
+        let document = match self {
+            XmlNode::Document(v) => v.clone(),
+            _ => self.owner_document()?,
+        };
+        let context = document.document.borrow().context().clone();
+        Some(context)
+    }
+
+    fn document_position(&self) -> Vec<usize> {
+        match self {
+            XmlNode::ExpandedText(v) => v.data[0].document_position(),
+            _ => Rc::<info::XmlItem>::try_from(self.clone())
+                .map(|v| v.document_position())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn previous_sibling_child(&self, node: XmlNode) -> Option<XmlNode> {
+        let order = node.order();
+        let mut previous = None;
+        for child in self.child_iter() {
+            if child.order() == order {
+                return previous;
+            }
+
+            previous = Some(child);
+        }
+
+        None
+    }
+
+    fn next_sibling_child(&self, node: XmlNode) -> Option<XmlNode> {
+        let order = node.order();
+        let mut children = self.child_iter();
+        children.find(|v| v.order() == order)?;
+        children.next()
+    }
+}
+
+impl convert::TryFrom<Rc<info::XmlItem>> for XmlNode {
+    type Error = error::Error;
+
+    fn try_from(value: Rc<info::XmlItem>) -> Result<Self, Self::Error> {
+        let v = match &*value {
+            info::XmlItem::Attribute(v) => XmlAttr::from(v.clone()).as_node(),
+            info::XmlItem::CData(v) => XmlCDataSection::from(v.clone()).as_node(),
+            info::XmlItem::CharReference(v) => XmlEntityReference::from(v.clone()).as_node(),
             info::XmlItem::Comment(v) => XmlComment::from(v.clone()).as_node(),
-            info::XmlItem::DeclarationAttList(_) => unimplemented!("declaration attribute"),
+            info::XmlItem::DeclarationAttList(_) => Err(error::DomException::NotSupportErr)?,
             info::XmlItem::Document(v) => XmlDocument::from(v.clone()).as_node(),
             info::XmlItem::DocumentType(v) => XmlDocumentType::from(v.clone()).as_node(),
             info::XmlItem::Element(v) => XmlElement::from(v.clone()).as_node(),
@@ -707,7 +1619,8 @@ impl From<Rc<info::XmlItem>> for XmlNode {
             info::XmlItem::Unexpanded(v) => XmlEntityReference::from(v.clone()).as_node(),
             info::XmlItem::Unparsed(v) => XmlEntity::from(v.clone()).as_node(),
             info::XmlItem::Entity(v) => XmlEntity::from(v.clone()).as_node(),
-        }
+        };
+        Ok(v)
     }
 }
 
@@ -738,6 +1651,39 @@ impl convert::TryFrom<XmlNode> for Rc<info::XmlItem> {
     }
 }
 
+impl convert::TryFrom<XmlNode> for XmlAttr {
+    type Error = error::Error;
+
+    fn try_from(value: XmlNode) -> Result<Self, Self::Error> {
+        match value {
+            XmlNode::Attribute(v) => Ok(v),
+            _ => Err(error::DomException::HierarchyRequestErr)?,
+        }
+    }
+}
+
+impl convert::TryFrom<XmlNode> for XmlEntity {
+    type Error = error::Error;
+
+    fn try_from(value: XmlNode) -> Result<Self, Self::Error> {
+        match value {
+            XmlNode::Entity(v) => Ok(v),
+            _ => Err(error::DomException::HierarchyRequestErr)?,
+        }
+    }
+}
+
+impl convert::TryFrom<XmlNode> for XmlNotation {
+    type Error = error::Error;
+
+    fn try_from(value: XmlNode) -> Result<Self, Self::Error> {
+        match value {
+            XmlNode::Notation(v) => Ok(v),
+            _ => Err(error::DomException::HierarchyRequestErr)?,
+        }
+    }
+}
+
 impl fmt::Display for XmlNode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match self {
@@ -824,121 +1770,378 @@ impl XmlNode {
         }
     }
 
-    fn children(&self) -> Vec<XmlNode> {
+    fn child_at(&self, index: usize) -> Option<XmlNode> {
         match self {
-            XmlNode::Element(v) => v.children(),
-            XmlNode::Attribute(v) => v.children(),
-            XmlNode::Text(_) => Vec::new(),
-            XmlNode::CData(_) => Vec::new(),
-            XmlNode::EntityReference(v) => v.children(),
-            XmlNode::Entity(v) => v.children(),
-            XmlNode::PI(_) => Vec::new(),
-            XmlNode::Comment(_) => Vec::new(),
-            XmlNode::Document(v) => v.children(),
-            XmlNode::DocumentType(_) => Vec::new(),
-            XmlNode::DocumentFragment(v) => v.children(),
-            XmlNode::Notation(_) => Vec::new(),
-            XmlNode::Namespace(_) => Vec::new(),
-            XmlNode::ExpandedText(_) => Vec::new(),
+            XmlNode::Element(v) => v.child_at(index),
+            XmlNode::Attribute(v) => v.child_at(index),
+            XmlNode::Text(_) => None,
+            XmlNode::CData(_) => None,
+            XmlNode::EntityReference(v) => v.child_at(index),
+            XmlNode::Entity(v) => v.child_at(index),
+            XmlNode::PI(_) => None,
+            XmlNode::Comment(_) => None,
+            XmlNode::Document(v) => v.child_at(index),
+            XmlNode::DocumentType(_) => None,
+            XmlNode::DocumentFragment(v) => v.child_at(index),
+            XmlNode::Notation(_) => None,
+            XmlNode::Namespace(_) => None,
+            XmlNode::ExpandedText(_) => None,
         }
     }
-}
-
-// -----------------------------------------------------------------------------------------------
 
-pub trait AsNode {
-    fn as_node(&self) -> XmlNode;
+    fn child_iter(&self) -> XmlChildIter {
+        let text_expanded = match self {
+            XmlNode::Element(v) => v.text_expanded(),
+            _ => false,
+        };
+        XmlChildIter::new(self.clone(), text_expanded)
+    }
 }
 
 // -----------------------------------------------------------------------------------------------
 
-pub trait AsExpandedName {
-    fn as_expanded_name(&self) -> error::Result<Option<ExpandedName>>;
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    StartDocument,
+    EndDocument,
+    StartElement(String, Vec<(String, String)>),
+    EndElement(String),
+    Text(String),
+    CData(String),
+    EntityReference(String),
+    PI(String, String),
+    Comment(String),
 }
 
 // -----------------------------------------------------------------------------------------------
 
-pub trait AsStringValue {
-    fn as_string_value(&self) -> error::Result<String>;
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct NodeHandle {
+    document_id: usize,
+    node_id: usize,
 }
 
-// -----------------------------------------------------------------------------------------------
+impl NodeHandle {
+    pub fn document_id(&self) -> usize {
+        self.document_id
+    }
 
-pub trait PrettyPrint {
-    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()>;
+    pub fn node_id(&self) -> usize {
+        self.node_id
+    }
 }
 
 // -----------------------------------------------------------------------------------------------
 
-trait HasChild {
-    fn children(&self) -> Vec<XmlNode>;
+/// Qualified name compared by its expanded name, the prefix is ignored.
+#[derive(Clone, Debug, Default)]
+pub struct QName {
+    local_name: String,
+    prefix: Option<String>,
+    namespace_uri: Option<String>,
+}
 
-    fn first_child_node(&self) -> Option<XmlNode> {
-        let mut children = self.children();
-        if children.is_empty() {
-            None
-        } else {
-            Some(children.remove(0))
+impl QName {
+    pub fn new(local_name: &str, prefix: Option<&str>, namespace_uri: Option<&str>) -> Self {
+        QName {
+            local_name: local_name.to_string(),
+            prefix: prefix.map(|v| v.to_string()),
+            namespace_uri: namespace_uri.map(|v| v.to_string()),
         }
     }
 
-    fn last_child_node(&self) -> Option<XmlNode> {
-        let mut children = self.children();
-        if children.is_empty() {
-            None
-        } else {
-            Some(children.remove(children.len() - 1))
-        }
+    pub fn local_name(&self) -> &str {
+        self.local_name.as_str()
     }
 
-    fn has_child_node(&self) -> bool {
-        !self.children().is_empty()
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
     }
-}
 
-// -----------------------------------------------------------------------------------------------
-
-#[derive(Clone, Debug, PartialEq)]
-pub struct XmlDomImplementation;
+    pub fn namespace_uri(&self) -> Option<&str> {
+        self.namespace_uri.as_deref()
+    }
 
-impl DomImplementation for XmlDomImplementation {
-    fn has_feature(&self, feature: &str, version: Option<&str>) -> bool {
-        feature.to_ascii_lowercase() == "xml" && version.map(|v| v == "1.0").unwrap_or(true)
+    pub fn qualified_name(&self) -> String {
+        match self.prefix() {
+            Some(prefix) => format!("{}:{}", prefix, self.local_name),
+            None => self.local_name.clone(),
+        }
     }
 }
 
-// -----------------------------------------------------------------------------------------------
-
-#[derive(Clone, PartialEq)]
-pub struct XmlDocumentFragment {
-    document: info::XmlNode<info::XmlDocument>,
-    parent: Option<info::XmlNode<info::XmlDocument>>,
+impl PartialEq for QName {
+    fn eq(&self, other: &Self) -> bool {
+        self.namespace_uri == other.namespace_uri && self.local_name == other.local_name
+    }
 }
 
-impl DocumentFragment for XmlDocumentFragment {}
+impl Eq for QName {}
 
-impl Node for XmlDocumentFragment {
-    fn node_name(&self) -> String {
-        "#document-fragment".to_string()
+impl std::hash::Hash for QName {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.namespace_uri.hash(state);
+        self.local_name.hash(state);
     }
+}
 
-    fn node_value(&self) -> error::Result<Option<String>> {
-        Ok(None)
+impl PartialOrd for QName {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
     }
+}
 
-    fn node_type(&self) -> NodeType {
-        NodeType::DocumentFragment
+impl Ord for QName {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        (&self.namespace_uri, &self.local_name).cmp(&(&other.namespace_uri, &other.local_name))
     }
+}
 
-    fn parent_node(&self) -> Option<XmlNode> {
-        None
+impl fmt::Display for QName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.qualified_name())
     }
+}
 
-    fn child_nodes(&self) -> XmlNodeList {
-        XmlNodeList {
-            node: self.as_node(),
+/// Parse `prefix:local`, `local` or `{uri}local` (Clark notation).
+impl std::str::FromStr for QName {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(rest) = s.strip_prefix('{') {
+            let (uri, local_name) = rest
+                .split_once('}')
+                .ok_or(error::DomException::NamespaceErr)?;
+            if !xml_parser::is_ncname(local_name) {
+                return Err(error::DomException::InvalidCharacterErr)?;
+            }
+            let uri = Some(uri).filter(|v| !v.is_empty());
+            return Ok(QName::new(local_name, None, uri));
+        }
+
+        match xml_parser::split_qname(s) {
+            Some((prefix, local_name)) => Ok(QName::new(local_name, prefix, None)),
+            None => Err(error::DomException::InvalidCharacterErr)?,
         }
     }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug)]
+pub struct DocumentOrdered(pub XmlNode);
+
+impl PartialEq for DocumentOrdered {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl Eq for DocumentOrdered {}
+
+impl PartialOrd for DocumentOrdered {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DocumentOrdered {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.0.cmp_document_order(&other.0)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+pub trait AsNode {
+    fn as_node(&self) -> XmlNode;
+}
+
+// -----------------------------------------------------------------------------------------------
+
+pub trait AsExpandedName {
+    fn as_expanded_name(&self) -> error::Result<Option<QName>>;
+}
+
+// -----------------------------------------------------------------------------------------------
+
+pub trait AsStringValue {
+    fn as_string_value(&self) -> error::Result<String>;
+}
+
+// -----------------------------------------------------------------------------------------------
+
+pub trait PrettyPrint {
+    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()>;
+}
+
+// -----------------------------------------------------------------------------------------------
+
+pub trait EntityResolver {
+    fn resolve(&self, public_id: Option<&str>, system_id: &str) -> error::Result<Option<String>>;
+}
+
+pub type ElementFilter = dyn Fn(&str) -> bool;
+
+pub type WarningHandler = dyn Fn(&validation::Diagnostic);
+
+// -----------------------------------------------------------------------------------------------
+
+pub trait NodeFilter {
+    fn accept_node(&self, node: &XmlNode) -> FilterResult;
+}
+
+impl<F, R> NodeFilter for F
+where
+    F: Fn(&XmlNode) -> R,
+    R: Into<FilterResult>,
+{
+    fn accept_node(&self, node: &XmlNode) -> FilterResult {
+        self(node).into()
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+trait HasChild: AsNode {
+    fn child_at(&self, index: usize) -> Option<XmlNode>;
+
+    fn text_expanded(&self) -> bool {
+        false
+    }
+
+    fn child_iter(&self) -> XmlChildIter {
+        XmlChildIter::new(self.as_node(), self.text_expanded())
+    }
+
+    fn first_child_node(&self) -> Option<XmlNode> {
+        self.child_iter().next()
+    }
+
+    fn last_child_node(&self) -> Option<XmlNode> {
+        self.child_iter().last()
+    }
+
+    fn has_child_node(&self) -> bool {
+        self.child_at(0).is_some()
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct XmlDomImplementation;
+
+impl DomImplementation for XmlDomImplementation {
+    fn has_feature(&self, feature: &str, version: Option<&str>) -> bool {
+        let feature = feature.strip_prefix('+').unwrap_or(feature);
+        matches!(feature.to_ascii_lowercase().as_str(), "core" | "xml")
+            && matches!(version, None | Some("") | Some("1.0") | Some("2.0"))
+    }
+
+    fn create_document_type(
+        &self,
+        qualified_name: &str,
+        public_id: Option<&str>,
+        system_id: Option<&str>,
+    ) -> error::Result<XmlDocumentType> {
+        let xml = format!("<{} />", qualified_name);
+        match xml_parser::element(xml.as_str()) {
+            Ok(("", _)) => {}
+            _ => return Err(error::DomException::InvalidCharacterErr)?,
+        }
+
+        let document = info::XmlDocument::empty();
+        let declaration =
+            info::XmlDocumentTypeDeclaration::empty(qualified_name, document.borrow().context());
+        let declaration = declaration.as_document_type().unwrap();
+        declaration.borrow_mut().set_public_identifier(public_id);
+        declaration.borrow_mut().set_system_identifier(system_id);
+        Ok(XmlDocumentType::from(declaration))
+    }
+
+    fn create_document(
+        &self,
+        namespace_uri: Option<&str>,
+        qualified_name: Option<&str>,
+        doctype: Option<&XmlDocumentType>,
+    ) -> error::Result<XmlDocument> {
+        let document = XmlDocument::new();
+
+        if let Some(doctype) = doctype {
+            if doctype.owner_document().and_then(|v| v.doc_type()).as_ref() == Some(doctype) {
+                return Err(error::DomException::WrongDocumentErr)?;
+            }
+
+            let declaration = info::XmlDocumentTypeDeclaration::empty(
+                doctype.name().as_str(),
+                document.document.borrow().context(),
+            );
+            {
+                let declaration = declaration.as_document_type().unwrap();
+                let mut declaration = declaration.borrow_mut();
+                declaration.set_public_identifier(doctype.public_id().as_deref());
+                declaration.set_system_identifier(doctype.system_id().as_deref());
+            }
+            document.document.borrow().append(declaration)?;
+        }
+
+        if let Some(qualified_name) = qualified_name {
+            let prefix = qualified_name.split_once(':').map(|v| v.0);
+            match (prefix, namespace_uri) {
+                (Some(_), None) => return Err(error::DomException::NamespaceErr)?,
+                (Some("xml"), Some(v)) if v != info::NamespaceUri::xml().value() => {
+                    return Err(error::DomException::NamespaceErr)?
+                }
+                _ => {}
+            }
+
+            let element = document.create_element(qualified_name)?;
+            if let Some(namespace_uri) = namespace_uri {
+                match prefix {
+                    Some("xml") => {}
+                    Some(prefix) => element
+                        .set_attribute(format!("xmlns:{}", prefix).as_str(), namespace_uri)?,
+                    None => element.set_attribute("xmlns", namespace_uri)?,
+                }
+            }
+            document.append_child(element.as_node())?;
+        }
+
+        Ok(document)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, PartialEq)]
+pub struct XmlDocumentFragment {
+    document: info::XmlNode<info::XmlDocument>,
+    parent: Option<info::XmlNode<info::XmlDocument>>,
+}
+
+impl DocumentFragment for XmlDocumentFragment {}
+
+impl Node for XmlDocumentFragment {
+    fn node_name(&self) -> String {
+        "#document-fragment".to_string()
+    }
+
+    fn node_value(&self) -> error::Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::DocumentFragment
+    }
+
+    fn parent_node(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn child_nodes(&self) -> XmlNodeList {
+        XmlNodeList::from(self.as_node())
+    }
 
     fn first_child(&self) -> Option<XmlNode> {
         self.first_child_node()
@@ -988,13 +2191,11 @@ impl PrettyPrint for XmlDocumentFragment {
 }
 
 impl HasChild for XmlDocumentFragment {
-    fn children(&self) -> Vec<XmlNode> {
+    fn child_at(&self, index: usize) -> Option<XmlNode> {
         self.document
             .borrow()
-            .children()
-            .iter()
-            .map(XmlNode::from)
-            .collect()
+            .child_by_index(index)
+            .and_then(|v| XmlNode::try_from(v).ok())
     }
 }
 
@@ -1041,10 +2242,7 @@ impl Document for XmlDocument {
     }
 
     fn get_elements_by_tag_name(&self, tag_name: &str) -> XmlElementList {
-        XmlElementList {
-            node: self.as_node(),
-            tag_name: tag_name.to_string(),
-        }
+        XmlElementList::new(self.as_node(), tag_name, None)
     }
 }
 
@@ -1056,6 +2254,28 @@ impl DocumentMut for XmlDocument {
         Ok(XmlElement { element })
     }
 
+    fn create_element_ns(
+        &self,
+        namespace_uri: Option<&str>,
+        qualified_name: &str,
+    ) -> error::Result<XmlElement> {
+        let (prefix, _) = check_qualified_name(namespace_uri, qualified_name)?;
+        let xmlns = info::NamespaceUri::xmlns();
+        if prefix == Some("xmlns") || namespace_uri == Some(xmlns.value()) {
+            return Err(error::DomException::NamespaceErr)?;
+        }
+
+        let element = self.create_element(qualified_name)?;
+        match (prefix, namespace_uri) {
+            (Some("xml"), _) | (Some(_), None) => {}
+            (Some(prefix), Some(uri)) => element.declare_namespace_prefix(prefix, uri)?,
+            // Undeclared so that the element stays out of a default namespace it is inserted in.
+            (None, None) => element.declare_namespace("xmlns", "")?,
+            (None, Some(uri)) => element.declare_namespace("xmlns", uri)?,
+        }
+        Ok(element)
+    }
+
     fn create_document_fragment(&self) -> XmlDocumentFragment {
         let document = info::XmlDocument::empty();
         XmlDocumentFragment {
@@ -1067,17 +2287,16 @@ impl DocumentMut for XmlDocument {
     fn create_text_node(&self, data: &str) -> XmlText {
         let text = info::XmlText::empty(self.document.borrow().context());
         let text = text.as_text().unwrap();
-        // TODO: escape
         text.borrow_mut().insert(0, data).unwrap();
         XmlText { data: text }
     }
 
-    fn create_comment(&self, data: &str) -> XmlComment {
+    fn create_comment(&self, data: &str) -> error::Result<XmlComment> {
+        check_comment(data)?;
         let comment = info::XmlComment::empty(self.document.borrow().context());
         let comment = comment.as_comment().unwrap();
-        // TODO: escape?
-        comment.borrow_mut().insert(0, data).unwrap();
-        XmlComment { data: comment }
+        comment.borrow_mut().insert(0, data)?;
+        Ok(XmlComment { data: comment })
     }
 
     fn create_cdata_section(&self, data: &str) -> XmlCDataSection {
@@ -1109,6 +2328,31 @@ impl DocumentMut for XmlDocument {
         Ok(XmlAttr { attribute })
     }
 
+    fn create_attribute_ns(
+        &self,
+        namespace_uri: Option<&str>,
+        qualified_name: &str,
+    ) -> error::Result<XmlAttr> {
+        let (prefix, _) = check_qualified_name(namespace_uri, qualified_name)?;
+        let xmlns = info::NamespaceUri::xmlns();
+        let declaration = prefix == Some("xmlns") || qualified_name == "xmlns";
+        if declaration != (namespace_uri == Some(xmlns.value())) {
+            return Err(error::DomException::NamespaceErr)?;
+        }
+        // An unprefixed attribute is always in no namespace.
+        if prefix.is_none() && namespace_uri.is_some() && !declaration {
+            return Err(error::DomException::NamespaceErr)?;
+        }
+
+        let attr = self.create_attribute(qualified_name)?;
+        if !declaration {
+            attr.attribute
+                .borrow_mut()
+                .set_declared_namespace_uri(namespace_uri);
+        }
+        Ok(attr)
+    }
+
     fn create_entity_reference(&self, name: &str) -> error::Result<XmlEntityReference> {
         let ref_name = format!("&{};", name);
         xml_parser::reference(ref_name.as_str())
@@ -1143,9 +2387,7 @@ impl Node for XmlDocument {
     }
 
     fn child_nodes(&self) -> XmlNodeList {
-        XmlNodeList {
-            node: self.as_node(),
-        }
+        XmlNodeList::from(self.as_node())
     }
 
     fn first_child(&self) -> Option<XmlNode> {
@@ -1175,6 +2417,13 @@ impl Node for XmlDocument {
     fn has_child(&self) -> bool {
         self.has_child_node()
     }
+    fn lookup_namespace_uri(&self, prefix: Option<&str>) -> Option<String> {
+        self.document_element().ok()?.lookup_namespace_uri(prefix)
+    }
+
+    fn lookup_prefix(&self, namespace_uri: &str) -> Option<String> {
+        self.document_element().ok()?.lookup_prefix(namespace_uri)
+    }
 }
 
 impl NodeMut for XmlDocument {
@@ -1212,7 +2461,7 @@ impl NodeMut for XmlDocument {
                 .map_err(|_| error::DomException::HierarchyRequestErr)?
         };
 
-        Ok(XmlNode::from(value))
+        XmlNode::try_from(value)
     }
 
     fn remove_child(&self, old_child: &XmlNode) -> error::Result<XmlNode> {
@@ -1221,7 +2470,7 @@ impl NodeMut for XmlDocument {
         }
 
         match self.document.borrow().delete(old_child.id()) {
-            Some(v) => Ok(XmlNode::from(v)),
+            Some(v) => XmlNode::try_from(v),
             _ => Err(error::DomException::NotFoundErr)?,
         }
     }
@@ -1246,13 +2495,11 @@ impl PrettyPrint for XmlDocument {
 }
 
 impl HasChild for XmlDocument {
-    fn children(&self) -> Vec<XmlNode> {
+    fn child_at(&self, index: usize) -> Option<XmlNode> {
         self.document
             .borrow()
-            .children()
-            .iter()
-            .map(XmlNode::from)
-            .collect()
+            .child_by_index(index)
+            .and_then(|v| XmlNode::try_from(v).ok())
     }
 }
 
@@ -1274,1311 +2521,2576 @@ impl fmt::Display for XmlDocument {
     }
 }
 
+impl Default for XmlDocument {
+    fn default() -> Self {
+        XmlDocument::new()
+    }
+}
+
 impl XmlDocument {
-    pub fn from_raw(value: &str) -> error::Result<(&str, Self)> {
-        let (rest, tree) = xml_parser::document(value)?;
-        let document = info::XmlDocument::new(&tree)?;
-        let dom = XmlDocument::from(document);
-        Ok((rest, dom))
+    pub fn new() -> Self {
+        XmlDocument::from(info::XmlDocument::empty())
     }
 
-    pub fn from_raw_with_context(value: &str, context: Context) -> error::Result<(&str, Self)> {
+    pub fn from_raw(value: &str) -> error::Result<(&str, Self)> {
         let (rest, tree) = xml_parser::document(value)?;
         let document = info::XmlDocument::new(&tree)?;
-        document
-            .borrow_mut()
-            .context_mut()
-            .set_text_expanded(context.text_expanded);
         let dom = XmlDocument::from(document);
         Ok((rest, dom))
     }
 
-    fn elements_by_tag_name(&self, tag_name: &str) -> Vec<XmlElement> {
-        let mut elements: Vec<XmlElement> = vec![];
+    pub fn from_file(path: impl AsRef<path::Path>, context: Context) -> error::Result<Self> {
+        let path = fs::canonicalize(path)?;
+        let value = fs::read_to_string(&path)?;
+        let document_uri = format!("file://{}", path.to_string_lossy().replace('\\', "/"));
+        let (rest, dom) =
+            XmlDocument::from_raw_with_uri(value.as_str(), context, Some(document_uri.as_str()))?;
+        if rest.is_empty() {
+            Ok(dom)
+        } else {
+            Err(error::Error::Parse(rest.to_string()))
+        }
+    }
 
-        if let Ok(root) = self.root_element() {
-            for v in root.elements_by_tag_name(tag_name) {
-                elements.push(v)
-            }
+    pub fn from_reader(mut reader: impl io::Read, context: Context) -> error::Result<Self> {
+        let mut value = String::new();
+        reader.read_to_string(&mut value)?;
+        let (rest, dom) = XmlDocument::from_raw_with_context(value.as_str(), context)?;
+        if rest.is_empty() {
+            Ok(dom)
+        } else {
+            Err(error::Error::Parse(rest.to_string()))
         }
+    }
 
-        elements
+    pub fn from_html(value: &str) -> error::Result<Self> {
+        XmlDocument::from_events(html::events(value).into_iter())
     }
 
-    fn root_element(&self) -> error::Result<XmlElement> {
-        let element = self.document.borrow().document_element()?;
-        Ok(XmlElement::from(element))
+    pub fn to_html(&self) -> error::Result<String> {
+        let mut writer = XmlEventWriter::new(vec![]);
+        writer.set_html(true);
+        writer.write_events(self.as_node().events())?;
+        String::from_utf8(writer.into_inner()).map_err(|e| error::Error::Io(e.to_string()))
     }
-}
 
-// -----------------------------------------------------------------------------------------------
+    pub fn to_encoded(&self, encoding: encoding::Encoding) -> error::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        self.write_encoded(&mut bytes, encoding)?;
+        Ok(bytes)
+    }
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct XmlElementList {
-    node: XmlNode,
-    tag_name: String,
-}
+    pub fn write_encoded(
+        &self,
+        writer: impl io::Write,
+        encoding: encoding::Encoding,
+    ) -> io::Result<()> {
+        let declaration = {
+            let document = self.document.borrow();
+            let version = document.version().unwrap_or("1.0");
+            let standalone = match document.standalone() {
+                Some(true) => " standalone=\"yes\"",
+                Some(false) => " standalone=\"no\"",
+                None => "",
+            };
+            format!(
+                "<?xml version=\"{}\" encoding=\"{}\"{}?>",
+                version,
+                encoding.name(),
+                standalone
+            )
+        };
 
-impl NodeList for XmlElementList {
-    fn item(&self, index: usize) -> Option<XmlNode> {
-        self.items().get(index).map(|v| v.as_node())
-    }
+        let mut writer = encoding::EncodingWriter::new(writer, encoding);
+        io::Write::write_all(&mut writer, declaration.as_bytes())?;
 
-    fn length(&self) -> usize {
-        self.items().len()
+        let mut writer = XmlEventWriter::new(writer);
+        writer.set_repertoire(encoding.repertoire());
+        writer.write_events(self.as_node().events())
     }
-}
 
-impl XmlElementList {
-    pub fn iter(&self) -> XmlNodeIter {
-        XmlNodeIter {
-            nodes: self.items().iter().map(|v| v.as_node()).collect(),
-            index: 0,
+    /// Write the document to `path` through a temporary file, so that `path` holds either the
+    /// old or the new contents.
+    ///
+    /// A symbolic link at `path` is followed, the file it points to is replaced and the link
+    /// is kept. The backup, if any, is written next to that file.
+    pub fn save_atomic(
+        &self,
+        path: impl AsRef<path::Path>,
+        options: &SaveOptions,
+    ) -> error::Result<()> {
+        static SAVE_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let path = resolve_symlinks(path.as_ref())?;
+        let path = path.as_path();
+        let name = path
+            .file_name()
+            .ok_or_else(|| error::Error::Io(path.to_string_lossy().to_string()))?
+            .to_string_lossy()
+            .to_string();
+        // Unique per call, as threads of a process share its id.
+        let temp = path.with_file_name(format!(
+            ".{}.{}.{}.tmp",
+            name,
+            std::process::id(),
+            SAVE_ID.fetch_add(1, atomic::Ordering::Relaxed)
+        ));
+
+        let write = || -> error::Result<()> {
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temp)?;
+            match options.encoding() {
+                Some(encoding) => self.write_encoded(&mut file, encoding)?,
+                None => io::Write::write_all(&mut file, self.to_string().as_bytes())?,
+            }
+            if let Ok(metadata) = fs::metadata(path) {
+                file.set_permissions(metadata.permissions())?;
+            }
+            file.sync_all()?;
+            Ok(())
+        };
+        if let Err(e) = write() {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
         }
-    }
 
-    fn items(&self) -> Vec<XmlElement> {
-        // TODO: cached
-        match &self.node {
-            XmlNode::Document(v) => v.elements_by_tag_name(self.tag_name.as_str()),
-            XmlNode::Element(v) => v.elements_by_tag_name(self.tag_name.as_str()),
-            _ => unreachable!(),
+        if options.backup() && path.exists() {
+            let backup = path.with_file_name(format!("{}.bak", name));
+            let copy = || -> io::Result<()> {
+                fs::copy(path, &backup)?;
+                fs::OpenOptions::new().write(true).open(&backup)?.sync_all()
+            };
+            if let Err(e) = copy() {
+                let _ = fs::remove_file(&temp);
+                return Err(e.into());
+            }
         }
-    }
-}
 
-// -----------------------------------------------------------------------------------------------
+        if let Err(e) = fs::rename(&temp, path) {
+            let _ = fs::remove_file(&temp);
+            return Err(e.into());
+        }
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct XmlNodeList {
-    node: XmlNode,
-}
+        #[cfg(unix)]
+        if let Some(directory) = path.parent().filter(|v| !v.as_os_str().is_empty()) {
+            fs::File::open(directory)?.sync_all()?;
+        }
 
-impl NodeList for XmlNodeList {
-    fn item(&self, index: usize) -> Option<XmlNode> {
-        self.items().get(index).cloned()
+        Ok(())
     }
 
-    fn length(&self) -> usize {
-        self.items().len()
+    pub fn to_preserved_string(&self) -> String {
+        let mut value = String::new();
+        let _ = self.document.borrow().write_preserving(&mut value);
+        value
     }
-}
 
-impl XmlNodeList {
-    pub fn iter(&self) -> XmlNodeIter {
-        XmlNodeIter {
-            nodes: self.items(),
-            index: 0,
+    pub fn from_events(events: impl Iterator<Item = Event>) -> error::Result<Self> {
+        let document = XmlDocument::new();
+        let mut stack: Vec<XmlElement> = vec![];
+        for event in events {
+            let node = match event {
+                Event::StartDocument => continue,
+                Event::EndDocument => break,
+                Event::StartElement(name, attributes) => {
+                    let element = document.create_element(name.as_str())?;
+                    for (name, value) in attributes {
+                        element.set_attribute(name.as_str(), value.as_str())?;
+                    }
+                    XmlNode::Element(element)
+                }
+                Event::EndElement(name) => match stack.pop() {
+                    Some(element) if element.tag_name() == name => continue,
+                    _ => return Err(error::Error::Parse(name)),
+                },
+                Event::Text(data) => XmlNode::Text(document.create_text_node(data.as_str())),
+                Event::CData(data) => XmlNode::CData(document.create_cdata_section(data.as_str())),
+                Event::EntityReference(name) => {
+                    XmlNode::EntityReference(document.create_entity_reference(name.as_str())?)
+                }
+                Event::PI(target, data) => XmlNode::PI(
+                    document.create_processing_instruction(target.as_str(), data.as_str())?,
+                ),
+                Event::Comment(data) => XmlNode::Comment(document.create_comment(data.as_str())?),
+            };
+
+            if let Some(parent) = stack.last() {
+                parent.append_child(node.clone())?;
+            } else {
+                document.append_child(node.clone())?;
+            }
+
+            if let XmlNode::Element(element) = node {
+                stack.push(element);
+            }
+        }
+
+        if let Some(element) = stack.pop() {
+            return Err(error::Error::Parse(element.tag_name()));
         }
+
+        Ok(document)
     }
 
-    fn items(&self) -> Vec<XmlNode> {
-        self.node.children()
+    pub fn from_raw_with_context(value: &str, context: Context) -> error::Result<(&str, Self)> {
+        XmlDocument::from_raw_with_uri(value, context, None)
     }
-}
 
-// -----------------------------------------------------------------------------------------------
+    pub fn document_uri(&self) -> Option<String> {
+        let document = self.document.borrow();
+        let uri = document.base_uri();
+        if uri.is_empty() {
+            None
+        } else {
+            Some(uri.to_string())
+        }
+    }
 
-pub struct XmlNodeIter {
-    nodes: Vec<XmlNode>,
-    index: usize,
-}
+    pub fn set_document_uri(&self, value: Option<&str>) {
+        self.document
+            .borrow_mut()
+            .set_base_uri(value.unwrap_or_default());
+    }
 
-impl Iterator for XmlNodeIter {
-    type Item = XmlNode;
+    /// Copies the document without reparsing it.
+    ///
+    /// Elements of the copy load their children on first access, and source spans, type
+    /// annotations and user data are carried over. The frozen tree the copies are made from is
+    /// kept until this document changes, so that forking an unchanged document again shares it
+    /// instead of copying the tree.
+    pub fn fork(&self) -> error::Result<Self> {
+        let (tree, user_data) = self.frozen();
+        self.snapshot_of(tree).fork_with(user_data)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let item = self.nodes.get(self.index);
-        self.index += 1;
-        item.cloned()
+    pub fn snapshot(&self) -> XmlSnapshot {
+        self.snapshot_of(self.frozen().0)
     }
-}
 
-// -----------------------------------------------------------------------------------------------
+    fn frozen(&self) -> (Arc<SnapshotTree>, Rc<UserDataMap>) {
+        let context = self.document.borrow().context().clone();
+        if let Some(frozen) = context.frozen() {
+            if let Ok(frozen) = frozen.downcast::<(Arc<SnapshotTree>, Rc<UserDataMap>)>() {
+                return (*frozen).clone();
+            }
+        }
 
-pub struct XmlNamedNodeMap<T>
-where
-    T: Node + Clone,
-{
-    node: XmlNode,
-    get: Box<NamedMapGet<T>>,
-    add: Box<NamedMapAdd<T>>,
-    remove: Box<NamedMapRemove<T>>,
-}
+        let mut user_data = HashMap::new();
+        let tree = Arc::new(SnapshotTree::freeze(self, Some(&mut user_data)));
+        let frozen = (tree, Rc::new(user_data));
+        context.set_frozen(Some(Rc::new(frozen.clone())));
+        frozen
+    }
+
+    fn snapshot_of(&self, tree: Arc<SnapshotTree>) -> XmlSnapshot {
+        let document = self.document.borrow();
+        let context = document.context();
+        XmlSnapshot {
+            tree,
+            document_uri: self.document_uri(),
+            text_expanded: context.text_expanded(),
+            spaces_preserved: context.spaces_preserved(),
+            line_ending: context.line_ending().map(|v| v.to_string()),
+            max_depth: context.max_depth(),
+            namespace_aware: context.namespace_aware(),
+            attributes_defaulted: context.attributes_defaulted(),
+            prefix_undeclaration: context.prefix_undeclaration(),
+        }
+    }
 
-impl<T> NamedNodeMap<T> for XmlNamedNodeMap<T>
-where
-    T: Node + Clone,
-{
-    fn get_named_item(&self, name: &str) -> Option<T> {
-        let nodes = (self.get)(&self.node);
-        let node = nodes.iter().find(|v| v.0 == name).map(|v| &v.1);
-        node.cloned()
+    fn from_raw_with_uri<'a>(
+        value: &'a str,
+        context: Context,
+        document_uri: Option<&str>,
+    ) -> error::Result<(&'a str, Self)> {
+        let buffer = &mut String::new();
+        let pool = &mut info::NodePool::default();
+        XmlDocument::from_raw_with_buffer(value, context, document_uri, buffer, pool)
+    }
+
+    fn from_raw_with_buffer<'a>(
+        value: &'a str,
+        context: Context,
+        document_uri: Option<&str>,
+        buffer: &mut String,
+        pool: &mut info::NodePool,
+    ) -> error::Result<(&'a str, Self)> {
+        if let Some(max_length) = context.max_length {
+            if value.len() > max_length {
+                return Err(error::Error::LengthLimitExceeded(max_length));
+            }
+        }
+
+        buffer.clear();
+        if context.eol_normalized {
+            normalize_eol(value, buffer);
+        } else {
+            buffer.push_str(value);
+        }
+        let normalized = buffer.as_str();
+
+        let (rest, tree) = parse_tree(normalized, &context)?;
+        let dom = XmlDocument::from_tree(value, normalized, tree, context, document_uri, pool)?;
+        let rest = original_rest(value, normalized.len() - rest.len());
+        Ok((rest, dom))
     }
 
-    fn item(&self, index: usize) -> Option<T> {
-        let nodes = (self.get)(&self.node);
-        let node = nodes.get(index).map(|v| &v.1);
-        node.cloned()
+    fn from_tree(
+        value: &str,
+        normalized: &str,
+        tree: xml_parser::model::Document<'_>,
+        context: Context,
+        document_uri: Option<&str>,
+        pool: &mut info::NodePool,
+    ) -> error::Result<Self> {
+        let external_subset = load_external_subset(&tree, &context, document_uri)?;
+        let mut tree = tree;
+        let mut skipped_subset = None;
+        if let (Some(value), Some(declaration)) = (
+            external_subset.as_deref(),
+            tree.prolog.declaration_doc.as_mut(),
+        ) {
+            // Constructs the parser does not support, such as conditional sections, leave the
+            // subset out instead of failing the document.
+            match xml_parser::ext_subset(value) {
+                Ok(("", subset)) => declaration.external_subset = subset,
+                Ok((rest, _)) => {
+                    skipped_subset = Some(error::Error::Parse(rest.into()).to_string())
+                }
+                Err(e) => skipped_subset = Some(parse_error(e, &context).to_string()),
+            }
+        }
+
+        let document = if context.spans_tracked {
+            let document = info::XmlDocument::new_with_spans_in(&tree, normalized, pool)?;
+            if normalized.len() != value.len() {
+                let offsets = original_offsets(value);
+                document.borrow().context().map_spans(|v| offsets[v]);
+            }
+            document
+        } else {
+            info::XmlDocument::new_in(&tree, pool)?
+        };
+        let prefix_undeclaration =
+            context.prefix_undeclaration && document.borrow().version() == Some("1.1");
+        if context.namespace_aware && !prefix_undeclaration {
+            check_prefix_undeclaration(&document)?;
+        }
+
+        {
+            let mut document = document.borrow_mut();
+            let info_context = document.context_mut();
+            info_context.set_text_expanded(context.text_expanded);
+            info_context.set_spaces_preserved(context.spaces_preserved);
+            info_context.set_max_depth(context.max_depth);
+            info_context.set_namespace_aware(context.namespace_aware);
+            info_context.set_attributes_defaulted(context.attributes_defaulted);
+            info_context.set_prefix_undeclaration(prefix_undeclaration);
+            if context.line_ending_preserved && value.contains("\r\n") {
+                info_context.set_line_ending(Some("\r\n"));
+            }
+        }
+
+        if let Some(document_uri) = document_uri.or(context.base_uri.as_deref()) {
+            document.borrow_mut().set_base_uri(document_uri);
+        }
+
+        if context.whitespace_stripped {
+            if let Ok(root) = document.borrow().document_element() {
+                root.borrow().strip_whitespace();
+            }
+        }
+
+        if let Some(resolver) = context.resolver.as_deref() {
+            resolve_entities(&document, resolver)?;
+        }
+
+        // Checked after resolving, so that replacement text read through the resolver counts.
+        if let Some(max_entity_expansion) = context.max_entity_expansion {
+            check_entity_expansion(&document, max_entity_expansion)?;
+        }
+
+        if context.spans_tracked {
+            document.borrow().context().set_source(Some(value));
+            document.borrow().record_digests();
+        }
+
+        let dom = XmlDocument::from(document);
+        if let (Some(reason), Some(handler)) = (skipped_subset, context.warning_handler.as_deref())
+        {
+            let message = format!("external subset skipped: {}", reason);
+            handler(&validation::Diagnostic::new(&dom.as_node(), message));
+        }
+        if context.unicode_normalized {
+            normalize_unicode(&dom, context.warning_handler.as_deref())?;
+        }
+
+        Ok(dom)
     }
 
-    fn length(&self) -> usize {
-        let nodes = (self.get)(&self.node);
-        nodes.len()
+    pub fn node_at_offset(&self, offset: usize) -> Option<(XmlNode, Option<XmlAttr>)> {
+        let covers = |node: &XmlNode| {
+            node.source_span()
+                .map(|v| v.contains(offset))
+                .unwrap_or_default()
+        };
+
+        let mut node = self.as_node();
+        if !covers(&node) {
+            return None;
+        }
+
+        loop {
+            if let Some(attributes) = node.attributes() {
+                for i in 0..attributes.length() {
+                    if let Some(attribute) = attributes.item(i).filter(|v| covers(&v.as_node())) {
+                        return Some((node, Some(attribute)));
+                    }
+                }
+            }
+
+            match node.child_nodes().iter().find(|v| covers(v)) {
+                Some(child) => node = child,
+                None => return Some((node, None)),
+            }
+        }
     }
-}
 
-impl<T> NamedNodeMapMut<T> for XmlNamedNodeMap<T>
-where
-    T: Node + Clone,
-{
-    fn set_named_item(&self, arg: T) -> error::Result<Option<T>> {
-        let name = arg.node_name();
-        if let Ok(v) = self.remove_named_item(name.as_str()) {
-            (self.add)(&self.node, arg)?; // FIXME: revert on failed.
-            Ok(Some(v))
+    pub fn elements_by_language(&self, tag: &str) -> error::Result<Vec<XmlElement>> {
+        if let Ok(root) = self.root_element() {
+            root.elements_by_language(tag)
         } else {
-            (self.add)(&self.node, arg)?;
-            Ok(None)
+            Ok(vec![])
         }
     }
 
-    fn remove_named_item(&self, name: &str) -> error::Result<T> {
-        (self.remove)(&self.node, name)
+    pub fn stylesheets(&self) -> Vec<XmlStylesheet> {
+        self.child_iter()
+            .take_while(|v| v.as_element().is_none())
+            .filter_map(|v| v.as_pi())
+            .filter_map(|v| XmlStylesheet::from_pi(&v))
+            .collect()
     }
-}
 
-impl<T> PartialEq<XmlNamedNodeMap<T>> for XmlNamedNodeMap<T>
-where
-    T: Node + Clone + PartialEq,
-{
-    fn eq(&self, other: &XmlNamedNodeMap<T>) -> bool {
-        let s = (self.get)(&self.node);
-        let o = (other.get)(&other.node);
-        s.eq(&o)
+    pub fn get_elements_by_tag_name_ns(
+        &self,
+        namespace_uri: &str,
+        local_name: &str,
+    ) -> XmlElementList {
+        XmlElementList::new(self.as_node(), local_name, Some(namespace_uri))
+    }
+
+    pub fn get_element_by_id(&self, element_id: &str) -> Option<XmlElement> {
+        let element = self.document.borrow().element_by_id(element_id).ok()??;
+        Some(XmlElement { element })
+    }
+
+    pub fn normalize_document(&self, config: &DomConfiguration) -> error::Result<()> {
+        normalize_children(self, &self.as_node(), config)?;
+
+        if config.validate {
+            for diagnostic in validation::validate_dtd(self)? {
+                let node =
+                    find_node(&self.as_node(), diagnostic.node()).unwrap_or_else(|| self.as_node());
+                let error = DomError::new(
+                    DomErrorSeverity::Error,
+                    "validation",
+                    &node,
+                    diagnostic.message(),
+                );
+                config.report(error)?;
+            }
+        }
+        Ok(())
     }
-}
 
-impl<T> fmt::Debug for XmlNamedNodeMap<T>
-where
-    T: Node + Clone + fmt::Debug,
-{
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        let s = (self.get)(&self.node);
-        s.fmt(f)
+    pub fn write_to_string(&self, config: &DomConfiguration) -> error::Result<String> {
+        let mut nodes = vec![self.as_node()];
+        while let Some(node) = nodes.pop() {
+            match &node {
+                XmlNode::Element(v) => {
+                    check_bound_prefixes(v, config)?;
+                }
+                XmlNode::CData(v) if v.data()?.contains("]]>") => {
+                    let message = "CDATA section is split at `]]>`";
+                    let error = DomError::new(
+                        DomErrorSeverity::Warning,
+                        "cdata-sections-splitted",
+                        &node,
+                        message,
+                    );
+                    config.report(error)?;
+                }
+                _ => {}
+            }
+
+            let mut children = XmlChildIter::new(node, false).collect::<Vec<XmlNode>>();
+            children.reverse();
+            nodes.extend(children);
+        }
+        Ok(self.to_string())
     }
-}
 
-impl<T> XmlNamedNodeMap<T>
-where
-    T: Node + Clone,
-{
-    pub fn iter(&self) -> XmlNamedNodeIter<T> {
-        let nodes = (self.get)(&self.node);
-        XmlNamedNodeIter { nodes, index: 0 }
+    pub fn adopt_node(&self, source: &XmlNode) -> error::Result<XmlNode> {
+        if let XmlNode::Document(_) | XmlNode::DocumentType(_) = source {
+            return Err(error::DomException::NotSupportErr.into());
+        }
+
+        if same_document(Some(self.clone()), source.owner_document()) {
+            detach_node(source)?;
+            source.notify_user_data(UserDataOperation::NodeAdopted, None);
+            return Ok(source.clone());
+        }
+
+        // Node ids are allocated per document, so the subtree is rebuilt in this one.
+        let node = duplicate_node_in(self, source, true, UserDataOperation::NodeImported)?;
+        if let (XmlNode::Element(source), XmlNode::Element(element)) = (source, &node) {
+            for namespace in source.in_scope_namespace()? {
+                if namespace.implicit() {
+                    continue;
+                }
+
+                let name = match namespace.node_name().as_str() {
+                    "xmlns" => "xmlns".to_string(),
+                    prefix => format!("xmlns:{}", prefix),
+                };
+                if element.get_attribute_node(name.as_str()).is_none() {
+                    let uri = namespace.node_value()?.unwrap_or_default();
+                    element.declare_namespace(name.as_str(), uri.as_str())?;
+                }
+            }
+        }
+        detach_node(source)?;
+        Ok(node)
+    }
+
+    pub fn rename_node(
+        &self,
+        node: &XmlNode,
+        namespace_uri: Option<&str>,
+        qualified_name: &str,
+    ) -> error::Result<XmlNode> {
+        if !same_document(Some(self.clone()), node.owner_document()) {
+            return Err(error::DomException::WrongDocumentErr.into());
+        }
+
+        let (prefix, local_name) = check_qualified_name(namespace_uri, qualified_name)?;
+        let xmlns = info::NamespaceUri::xmlns();
+        match node {
+            XmlNode::Element(v) => {
+                if prefix == Some("xmlns") || namespace_uri == Some(xmlns.value()) {
+                    return Err(error::DomException::NamespaceErr.into());
+                }
+
+                let mut element = v.element.borrow_mut();
+                element.set_local_name(local_name);
+                element.set_prefix(prefix);
+                drop(element);
+
+                let bound = v.lookup_namespace_uri(prefix);
+                match (prefix, namespace_uri) {
+                    (Some("xml"), _) => {}
+                    (_, uri) if bound.as_deref() == uri => {}
+                    (Some(prefix), Some(uri)) => v.declare_namespace_prefix(prefix, uri)?,
+                    (None, uri) => v.declare_namespace("xmlns", uri.unwrap_or_default())?,
+                    (Some(_), None) => unreachable!(),
+                }
+            }
+            XmlNode::Attribute(v) => {
+                let namespace_declaration = prefix == Some("xmlns") || qualified_name == "xmlns";
+                if namespace_declaration != (namespace_uri == Some(xmlns.value()))
+                    || (prefix.is_none() && namespace_uri.is_some() && !namespace_declaration)
+                {
+                    return Err(error::DomException::NamespaceErr.into());
+                }
+
+                let owner = v.owner_element();
+                let attributes = owner.as_ref().and_then(|v| v.attributes());
+                let in_use = attributes.is_some_and(|v| {
+                    v.iter().any(|a| {
+                        a.prefix().as_deref() == prefix
+                            && a.local_name() == local_name
+                            && !a.as_node().ptr_eq(node)
+                    })
+                });
+                if in_use {
+                    return Err(error::DomException::InuseAttributeErr.into());
+                }
+
+                let mut attribute = v.attribute.borrow_mut();
+                attribute.set_local_name(local_name);
+                attribute.set_prefix(prefix);
+                attribute.set_declared_namespace_uri(match prefix {
+                    Some("xmlns") | None => None,
+                    Some(_) => namespace_uri,
+                });
+                drop(attribute);
+
+                if let Some(owner) = owner {
+                    owner.bind_declared_namespace(v)?;
+                }
+            }
+            _ => return Err(error::DomException::NotSupportErr.into()),
+        }
+
+        self.document.borrow().context().invalidate();
+        node.notify_user_data(UserDataOperation::NodeRenamed, Some(node));
+        Ok(node.clone())
+    }
+
+    pub fn tag_histogram(&self) -> error::Result<NameHistogram> {
+        let mut histogram = NameHistogram::new();
+        for element in self.elements_by_tag_name("*", None)? {
+            let element = element.element.borrow();
+            let ns = element.namespace_name()?;
+            let name = QName::new(element.local_name(), element.prefix(), ns.as_deref());
+            *histogram.entry(name).or_default() += 1;
+        }
+        Ok(histogram)
+    }
+
+    pub fn attribute_histogram(&self) -> error::Result<NameHistogram> {
+        let mut histogram = NameHistogram::new();
+        for element in self.elements_by_tag_name("*", None)? {
+            for attr in element.element.borrow().attributes().iter() {
+                let attr = attr.borrow();
+                let ns = attr.namespace_name()?;
+                let name = QName::new(attr.local_name(), attr.prefix(), ns.as_deref());
+                *histogram.entry(name).or_default() += 1;
+            }
+        }
+        Ok(histogram)
+    }
+
+    pub fn to_dot(&self) -> error::Result<String> {
+        let mut dot = "digraph xml {\n    node [shape=box];\n".to_string();
+        let mut index = 0;
+        let mut stack = vec![(self.as_node(), None)];
+        while let Some((node, parent)) = stack.pop() {
+            let current = index;
+            index += 1;
+            dot.push_str(&format!(
+                "    n{} [label=\"{}\"];\n",
+                current,
+                dot_label(&node)?
+            ));
+            if let Some(parent) = parent {
+                dot.push_str(&format!("    n{} -> n{};\n", parent, current));
+            }
+
+            for attr in node.attributes().iter().flat_map(|v| v.iter()) {
+                let label = dot_label(&attr.as_node())?;
+                dot.push_str(&format!("    n{} [label=\"{}\"];\n", index, label));
+                dot.push_str(&format!("    n{} -> n{} [style=dashed];\n", current, index));
+                index += 1;
+            }
+
+            let children = node.child_nodes().iter().collect::<Vec<XmlNode>>();
+            stack.extend(children.into_iter().rev().map(|v| (v, Some(current))));
+        }
+        dot.push_str("}\n");
+        Ok(dot)
+    }
+
+    fn elements_by_tag_name(
+        &self,
+        tag_name: &str,
+        namespace_uri: Option<&str>,
+    ) -> error::Result<Vec<XmlElement>> {
+        if let Ok(root) = self.root_element() {
+            root.elements_by_tag_name(tag_name, namespace_uri)
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    fn root_element(&self) -> error::Result<XmlElement> {
+        let element = self.document.borrow().document_element()?;
+        Ok(XmlElement::from(element))
     }
 }
 
 // -----------------------------------------------------------------------------------------------
 
-pub struct XmlNamedNodeIter<T>
-where
-    T: Node + Clone,
-{
-    nodes: Vec<(String, T)>,
-    index: usize,
+type UserDataMap = HashMap<usize, Vec<(String, Rc<dyn any::Any>)>>;
+
+/// An immutable copy of a document that can be shared between threads.
+///
+/// The nodes are kept in a flat arena and can be navigated through [`XmlSnapshotNode`].
+/// [`XmlSnapshot::to_document`] gives a mutable document, e.g. to evaluate XPath on another
+/// thread, whose elements are built from the arena as they are visited.
+#[derive(Clone, Debug, PartialEq)]
+pub struct XmlSnapshot {
+    tree: Arc<SnapshotTree>,
+    document_uri: Option<String>,
+    text_expanded: bool,
+    spaces_preserved: bool,
+    line_ending: Option<String>,
+    max_depth: Option<usize>,
+    namespace_aware: bool,
+    attributes_defaulted: bool,
+    prefix_undeclaration: bool,
 }
 
-impl<T> Iterator for XmlNamedNodeIter<T>
-where
-    T: Node + Clone,
-{
-    type Item = T;
+impl fmt::Display for XmlSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.as_str())
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let item = self.nodes.get(self.index);
-        self.index += 1;
-        item.cloned().map(|v| v.1)
+impl XmlSnapshot {
+    pub fn as_str(&self) -> &str {
+        self.tree
+            .text
+            .get_or_init(|| self.fork().map(|v| v.to_string()).unwrap_or_default())
+    }
+
+    pub fn document_uri(&self) -> Option<&str> {
+        self.document_uri.as_deref()
+    }
+
+    pub fn document(&self) -> XmlSnapshotNode<'_> {
+        XmlSnapshotNode {
+            tree: &self.tree,
+            index: 0,
+        }
+    }
+
+    pub fn document_element(&self) -> Option<XmlSnapshotNode<'_>> {
+        self.document()
+            .child_nodes()
+            .find(|v| v.node_type() == NodeType::Element)
+    }
+
+    pub fn get_elements_by_tag_name(&self, tag_name: &str) -> Vec<XmlSnapshotNode<'_>> {
+        self.document()
+            .descendants()
+            .filter(|v| v.node_type() == NodeType::Element)
+            .filter(|v| tag_name == "*" || v.node_name() == tag_name)
+            .collect()
+    }
+
+    /// Builds a mutable document that shares the nodes of this snapshot until they are
+    /// visited.
+    pub fn fork(&self) -> error::Result<XmlDocument> {
+        self.fork_with(Rc::new(HashMap::new()))
+    }
+
+    pub fn to_document(&self) -> error::Result<XmlDocument> {
+        self.fork()
+    }
+
+    fn fork_with(&self, user_data: Rc<UserDataMap>) -> error::Result<XmlDocument> {
+        let (rest, dom) = XmlDocument::from_raw(self.tree.skeleton.as_str())?;
+        if !rest.is_empty() {
+            return Err(error::Error::Parse(rest.to_string()));
+        }
+
+        let context = {
+            let mut document = dom.document.borrow_mut();
+            let context = document.context_mut();
+            context.set_text_expanded(self.text_expanded);
+            context.set_spaces_preserved(self.spaces_preserved);
+            context.set_line_ending(self.line_ending.as_deref());
+            context.set_max_depth(self.max_depth);
+            context.set_namespace_aware(self.namespace_aware);
+            context.set_attributes_defaulted(self.attributes_defaulted);
+            context.set_prefix_undeclaration(self.prefix_undeclaration);
+            context.clone()
+        };
+        context.set_source(self.tree.source.as_deref());
+        dom.set_document_uri(self.document_uri());
+
+        // The skeleton holds the document level nodes in order, with a placeholder for the
+        // document element.
+        self.tree
+            .annotate(0, dom.as_node().id(), &context, &user_data);
+        let children = XmlChildIter::new(dom.as_node(), false).collect::<Vec<_>>();
+        for (child, &index) in children.iter().zip(self.tree.nodes[0].children.iter()) {
+            if let XmlNode::Element(_) = child {
+                let root = thaw_node(&self.tree, index, &context, &user_data)?;
+                let next = child.next_sibling();
+                dom.remove_child(child)?;
+                dom.insert_before(XmlNode::try_from(root)?, next.as_ref())?;
+            } else {
+                self.tree.annotate(index, child.id(), &context, &user_data);
+            }
+        }
+
+        Ok(dom)
     }
 }
 
 // -----------------------------------------------------------------------------------------------
 
-#[derive(Clone, PartialEq)]
-pub struct XmlAttr {
-    attribute: info::XmlNode<info::XmlAttribute>,
+#[derive(Clone, Copy, Debug)]
+pub struct XmlSnapshotNode<'a> {
+    tree: &'a SnapshotTree,
+    index: usize,
 }
 
-impl Attr for XmlAttr {
-    fn name(&self) -> String {
-        self.attribute.borrow().local_name().to_string()
+impl PartialEq for XmlSnapshotNode<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.tree, other.tree) && self.index == other.index
     }
+}
 
-    fn specified(&self) -> bool {
-        self.attribute.borrow().owner_element().is_ok()
+impl<'a> XmlSnapshotNode<'a> {
+    pub fn node_type(&self) -> NodeType {
+        self.node().node_type.clone()
     }
 
-    fn value(&self) -> error::Result<String> {
-        Ok(self.attribute.borrow().normalized_value()?)
+    pub fn node_name(&self) -> &'a str {
+        self.node().node_name.as_str()
     }
-}
 
-impl AttrMut for XmlAttr {}
-
-impl Node for XmlAttr {
-    fn node_name(&self) -> String {
-        self.name()
+    pub fn node_value(&self) -> Option<&'a str> {
+        self.node().node_value.as_deref()
     }
 
-    fn node_value(&self) -> error::Result<Option<String>> {
-        Ok(Some(self.value()?))
+    pub fn local_name(&self) -> Option<&'a str> {
+        self.node().local_name.as_deref()
     }
 
-    fn node_type(&self) -> NodeType {
-        NodeType::Attribute
+    pub fn prefix(&self) -> Option<&'a str> {
+        self.node().prefix.as_deref()
     }
 
-    fn parent_node(&self) -> Option<XmlNode> {
-        None
+    pub fn namespace_uri(&self) -> Option<&'a str> {
+        self.node().namespace_uri.as_deref()
     }
 
-    fn child_nodes(&self) -> XmlNodeList {
-        XmlNodeList {
-            node: self.as_node(),
+    pub fn text_content(&self) -> String {
+        match self.node_type() {
+            NodeType::Document | NodeType::Element => self
+                .descendants()
+                .filter(|v| {
+                    matches!(
+                        v.node_type(),
+                        NodeType::Text | NodeType::CData | NodeType::EntityReference
+                    )
+                })
+                .filter_map(|v| v.node_value())
+                .collect(),
+            _ => self.node_value().unwrap_or_default().to_string(),
         }
     }
 
-    fn first_child(&self) -> Option<XmlNode> {
-        self.first_child_node()
+    pub fn parent_node(&self) -> Option<Self> {
+        self.node().parent.map(|v| self.at(v))
     }
 
-    fn last_child(&self) -> Option<XmlNode> {
-        self.last_child_node()
+    pub fn child_nodes(&self) -> impl Iterator<Item = XmlSnapshotNode<'a>> + 'a {
+        let node = *self;
+        self.node().children.iter().map(move |&v| node.at(v))
     }
 
-    fn previous_sibling(&self) -> Option<XmlNode> {
-        None
+    pub fn first_child(&self) -> Option<Self> {
+        self.node().children.first().map(|&v| self.at(v))
     }
 
-    fn next_sibling(&self) -> Option<XmlNode> {
-        None
+    pub fn last_child(&self) -> Option<Self> {
+        self.node().children.last().map(|&v| self.at(v))
     }
 
-    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
-        None
+    pub fn previous_sibling(&self) -> Option<Self> {
+        let siblings = self.siblings()?;
+        let position = siblings.iter().position(|&v| v == self.index)?;
+        let index = siblings.get(position.checked_sub(1)?)?;
+        Some(self.at(*index))
     }
 
-    fn owner_document(&self) -> Option<XmlDocument> {
-        Some(XmlDocument::from(self.attribute.borrow().owner()))
+    pub fn next_sibling(&self) -> Option<Self> {
+        let siblings = self.siblings()?;
+        let position = siblings.iter().position(|&v| v == self.index)?;
+        siblings.get(position + 1).map(|&v| self.at(v))
     }
 
-    fn has_child(&self) -> bool {
-        self.has_child_node()
+    pub fn attributes(&self) -> impl Iterator<Item = XmlSnapshotNode<'a>> + 'a {
+        let node = *self;
+        self.node().attributes.iter().map(move |&v| node.at(v))
     }
-}
 
-impl NodeMut for XmlAttr {
-    fn set_node_value(&self, value: &str) -> error::Result<()> {
-        self.attribute.borrow().set_values(value)?;
-        Ok(())
+    pub fn get_attribute(&self, name: &str) -> Option<&'a str> {
+        self.attributes()
+            .find(|v| v.node_name() == name)
+            .and_then(|v| v.node_value())
     }
 
-    fn insert_before(
-        &self,
-        new_child: XmlNode,
-        ref_child: Option<&XmlNode>,
-    ) -> error::Result<XmlNode> {
-        if self.owner_document() != new_child.owner_document() {
-            return Err(error::DomException::WrongDocumentErr)?;
+    /// All nodes below this one in document order, attributes excluded.
+    pub fn descendants(&self) -> XmlSnapshotDescendants<'a> {
+        let mut stack = self.node().children.clone();
+        stack.reverse();
+        XmlSnapshotDescendants {
+            tree: self.tree,
+            stack,
         }
+    }
 
-        let value = if let Some(r) = ref_child {
-            if self.owner_document() != r.owner_document() {
-                return Err(error::DomException::WrongDocumentErr)?;
-            }
+    /// The handle of the node this one was copied from.
+    pub fn handle(&self) -> NodeHandle {
+        self.node().handle
+    }
 
-            match self
-                .attribute
-                .borrow()
-                .insert_before(new_child.try_into()?, r.id())
-            {
-                Ok(v) => Ok(v),
-                Err(xml_info::error::Error::OufOfIndex(_)) => Err(error::DomException::NotFoundErr),
-                _ => Err(error::DomException::HierarchyRequestErr),
-            }?
-        } else {
-            self.attribute
-                .borrow()
-                .append(new_child.try_into()?)
-                .map_err(|_| error::DomException::HierarchyRequestErr)?
-        };
+    pub fn source_span(&self) -> Option<&'a info::SourceSpan> {
+        self.node().span.as_ref()
+    }
 
-        Ok(XmlNode::from(value))
+    pub fn type_annotation(&self) -> Option<schema::SchemaType> {
+        schema::SchemaType::from_name(self.node().type_annotation.as_deref()?)
     }
 
-    fn remove_child(&self, old_child: &XmlNode) -> error::Result<XmlNode> {
-        if self.owner_document() != old_child.owner_document() {
-            return Err(error::DomException::WrongDocumentErr)?;
+    fn node(&self) -> &'a SnapshotNode {
+        &self.tree.nodes[self.index]
+    }
+
+    fn at(&self, index: usize) -> Self {
+        XmlSnapshotNode {
+            tree: self.tree,
+            index,
         }
+    }
 
-        match self.attribute.borrow().delete(old_child.id()) {
-            Some(v) => Ok(XmlNode::from(v)),
-            _ => Err(error::DomException::NotFoundErr)?,
+    fn siblings(&self) -> Option<&'a [usize]> {
+        let parent = &self.tree.nodes[self.node().parent?];
+        if self.node_type() == NodeType::Attribute {
+            None
+        } else {
+            Some(parent.children.as_slice())
         }
     }
 }
 
-impl AsNode for XmlAttr {
-    fn as_node(&self) -> XmlNode {
-        XmlNode::Attribute(self.clone())
-    }
+pub struct XmlSnapshotDescendants<'a> {
+    tree: &'a SnapshotTree,
+    stack: Vec<usize>,
 }
 
-impl AsExpandedName for XmlAttr {
-    fn as_expanded_name(&self) -> error::Result<Option<ExpandedName>> {
-        let local_name = self.attribute.borrow().local_name().to_string();
-        let (prefix, ns) = if let Ok(element) = self.attribute.borrow().owner_element() {
-            // TODO: prefix is None
-            let prefix = self
-                .attribute
-                .borrow()
-                .prefix()
-                .unwrap_or("xmlns")
-                .to_string();
-            let namespaces = XmlElement::from(element).in_scope_namespace()?;
-            if let Some(ns) = namespaces.iter().find(|v| v.node_name() == prefix) {
-                (Some(prefix), ns.node_value()?)
-            } else {
-                (Some(prefix), None)
-            }
-        } else {
-            (None, None)
-        };
-        Ok(Some((local_name, prefix, ns)))
+impl<'a> Iterator for XmlSnapshotDescendants<'a> {
+    type Item = XmlSnapshotNode<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.stack.pop()?;
+        self.stack
+            .extend(self.tree.nodes[index].children.iter().rev().copied());
+        Some(XmlSnapshotNode {
+            tree: self.tree,
+            index,
+        })
     }
 }
 
-impl AsStringValue for XmlAttr {
-    fn as_string_value(&self) -> error::Result<String> {
-        self.value()
-    }
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Debug)]
+struct SnapshotTree {
+    // Nodes in document order, the document node first and attributes after their element.
+    nodes: Vec<SnapshotNode>,
+    skeleton: String,
+    source: Option<Arc<str>>,
+    text: OnceLock<String>,
 }
 
-impl PrettyPrint for XmlAttr {
-    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
-        self.attribute.borrow().indented(0, f)
+impl PartialEq for SnapshotTree {
+    fn eq(&self, other: &Self) -> bool {
+        self.nodes == other.nodes && self.skeleton == other.skeleton && self.source == other.source
     }
 }
 
-impl HasChild for XmlAttr {
-    fn children(&self) -> Vec<XmlNode> {
-        let mut nodes: Vec<XmlNode> = vec![];
+impl SnapshotTree {
+    fn freeze(document: &XmlDocument, mut user_data: Option<&mut UserDataMap>) -> Self {
+        let context = document.document.borrow().context().clone();
+        let mut skeleton = String::new();
+        let _ = document.document.borrow().write_declaration(&mut skeleton);
+
+        let mut nodes: Vec<SnapshotNode> = vec![];
+        let mut stack = vec![(document.as_node(), None)];
+        while let Some((node, parent)) = stack.pop() {
+            let index = nodes.len();
+            let mut frozen = vec![(node.clone(), parent)];
+            if let XmlNode::Element(element) = &node {
+                for attr in element.element.borrow().attribute_nodes() {
+                    frozen.push((XmlAttr::from(attr).as_node(), Some(index)));
+                }
+            }
 
-        for v in self.attribute.borrow().values().borrow().iter() {
-            match v {
-                info::XmlAttributeValue::Char(v) => {
-                    nodes.push(XmlNode::from(v.clone()));
+            for (node, parent) in frozen {
+                let position = nodes.len();
+                if let Some(user_data) = user_data.as_deref_mut() {
+                    let entries = context.user_data_entries(node.id());
+                    if !entries.is_empty() {
+                        user_data.insert(position, entries);
+                    }
                 }
-                info::XmlAttributeValue::Entity(v) => {
-                    nodes.push(XmlNode::from(v.clone()));
+                match (&node, parent) {
+                    (XmlNode::Attribute(_), Some(parent)) => {
+                        nodes[parent].attributes.push(position)
+                    }
+                    (_, Some(parent)) => nodes[parent].children.push(position),
+                    _ => {}
                 }
-                info::XmlAttributeValue::Text(v) => {
-                    nodes.push(XmlNode::from(v.clone()));
+                nodes.push(SnapshotNode::new(&node, parent, &context));
+            }
+
+            if let XmlNode::Document(_) | XmlNode::Element(_) = &node {
+                let children = XmlChildIter::new(node.clone(), false).collect::<Vec<_>>();
+                if parent.is_none() {
+                    for child in &children {
+                        match child {
+                            XmlNode::Element(_) => skeleton.push_str("<_/>"),
+                            _ => skeleton.push_str(child.to_string().as_str()),
+                        }
+                    }
                 }
+                stack.extend(children.into_iter().rev().map(|v| (v, Some(index))));
             }
         }
 
-        nodes
+        SnapshotTree {
+            nodes,
+            skeleton,
+            source: context.source().map(|v| Arc::from(&*v)),
+            text: OnceLock::new(),
+        }
     }
-}
 
-impl From<info::XmlNode<info::XmlAttribute>> for XmlAttr {
-    fn from(value: info::XmlNode<info::XmlAttribute>) -> Self {
-        XmlAttr { attribute: value }
+    fn annotate(&self, index: usize, id: usize, context: &info::Context, user_data: &UserDataMap) {
+        let node = &self.nodes[index];
+        context.set_span(id, node.span.clone());
+        context.set_type_annotation(id, node.type_annotation.as_deref());
+        for (key, value) in user_data.get(&index).into_iter().flatten() {
+            context.set_user_data(id, key, Some(value.clone()));
+        }
     }
 }
 
-impl fmt::Debug for XmlAttr {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "XmlAttr {{ {} }}", self.name())
+#[derive(Debug, PartialEq)]
+struct SnapshotNode {
+    node_type: NodeType,
+    node_name: String,
+    node_value: Option<String>,
+    local_name: Option<String>,
+    prefix: Option<String>,
+    namespace_uri: Option<String>,
+    content: SnapshotContent,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    attributes: Vec<usize>,
+    handle: NodeHandle,
+    span: Option<info::SourceSpan>,
+    type_annotation: Option<String>,
+}
+
+impl SnapshotNode {
+    fn new(node: &XmlNode, parent: Option<usize>, context: &info::Context) -> Self {
+        let content = match node {
+            XmlNode::Attribute(v) => SnapshotContent::Attribute(raw_attribute_value(v)),
+            XmlNode::EntityReference(v) => match v.inner() {
+                XmlEntityReferenceValue::Char(v) => {
+                    let v = v.borrow();
+                    SnapshotContent::CharReference(v.num().to_string(), v.radix())
+                }
+                XmlEntityReferenceValue::Entity(_) => SnapshotContent::None,
+            },
+            _ => SnapshotContent::None,
+        };
+
+        SnapshotNode {
+            node_type: node.node_type(),
+            node_name: node.node_name(),
+            node_value: node.node_value().ok().flatten(),
+            local_name: node.local_name(),
+            prefix: node.prefix(),
+            namespace_uri: node.namespace_uri(),
+            content,
+            parent,
+            children: vec![],
+            attributes: vec![],
+            handle: node.handle(),
+            span: context.span(node.id()),
+            type_annotation: context.type_annotation(node.id()),
+        }
+    }
+
+    fn qualified_name(&self) -> String {
+        match (self.prefix.as_deref(), self.local_name.as_deref()) {
+            (Some(prefix), Some(local_name)) => format!("{}:{}", prefix, local_name),
+            _ => self.node_name.clone(),
+        }
     }
 }
 
-impl fmt::Display for XmlAttr {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        self.attribute.borrow().fmt(f)
+#[derive(Debug, PartialEq)]
+enum SnapshotContent {
+    None,
+    // The attribute value as written, with its references.
+    Attribute(String),
+    CharReference(String, u32),
+}
+
+struct SnapshotChildren {
+    tree: Arc<SnapshotTree>,
+    index: usize,
+    user_data: Rc<UserDataMap>,
+}
+
+impl info::ChildSource for SnapshotChildren {
+    fn load(&self, parent: &info::XmlElement) -> Vec<Rc<info::XmlItem>> {
+        // Snapshot nodes come from a well-formed tree, so thawing them does not fail.
+        self.tree.nodes[self.index]
+            .children
+            .iter()
+            .filter_map(|&v| thaw_node(&self.tree, v, parent.context(), &self.user_data).ok())
+            .collect()
     }
 }
 
+fn thaw_node(
+    tree: &Arc<SnapshotTree>,
+    index: usize,
+    context: &info::Context,
+    user_data: &Rc<UserDataMap>,
+) -> error::Result<Rc<info::XmlItem>> {
+    let node = &tree.nodes[index];
+    let value = node.node_value.as_deref().unwrap_or_default();
+    let item = match node.node_type {
+        NodeType::Element => {
+            let item = info::XmlElement::empty(node.qualified_name().as_str(), context)?;
+            if let Some(element) = item.as_element() {
+                for &attr in node.attributes.iter() {
+                    let attr = thaw_node(tree, attr, context, user_data)?;
+                    element.borrow_mut().append_attribute(attr);
+                }
+                if !node.children.is_empty() {
+                    let source = SnapshotChildren {
+                        tree: tree.clone(),
+                        index,
+                        user_data: user_data.clone(),
+                    };
+                    element.borrow().set_child_source(Some(Rc::new(source)));
+                }
+            }
+            item
+        }
+        NodeType::Attribute => {
+            let item = info::XmlAttribute::empty(node.qualified_name().as_str(), context)?;
+            if let Some(attr) = item.as_attribute() {
+                if let SnapshotContent::Attribute(raw) = &node.content {
+                    attr.borrow().set_values(raw.as_str())?;
+                }
+                if node.prefix.is_some() {
+                    attr.borrow_mut()
+                        .set_declared_namespace_uri(node.namespace_uri.as_deref());
+                }
+            }
+            item
+        }
+        NodeType::Text => info::XmlText::node(value, None, context),
+        NodeType::CData => info::XmlCData::node(value, None, context),
+        NodeType::Comment => info::XmlComment::node(value, None, context),
+        NodeType::PI => {
+            let item = info::XmlProcessingInstruction::empty(node.node_name.as_str(), context)?;
+            if let (Some(pi), false) = (item.as_pi(), value.is_empty()) {
+                pi.borrow_mut().set_content(value)?;
+            }
+            item
+        }
+        NodeType::EntityReference => match (&node.content, context.entity(&node.node_name)) {
+            (SnapshotContent::CharReference(num, radix), _) => {
+                info::XmlCharReference::node(num.as_str(), *radix, None, context)?
+            }
+            (_, Ok(entity)) => info::XmlUnexpandedEntityReference::node(entity, None, context),
+            // The declaration is not available, the replacement text is kept instead.
+            (_, Err(_)) => info::XmlText::node(value, None, context),
+        },
+        _ => unreachable!(),
+    };
+
+    tree.annotate(index, item.id(), context, user_data);
+    Ok(item)
+}
+
 // -----------------------------------------------------------------------------------------------
 
-#[derive(Clone, PartialEq)]
-pub struct XmlElement {
-    element: info::XmlNode<info::XmlElement>,
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SaveOptions {
+    backup: bool,
+    encoding: Option<encoding::Encoding>,
 }
 
-impl Element for XmlElement {
-    fn tag_name(&self) -> String {
-        self.element.borrow().local_name().to_string()
+impl SaveOptions {
+    pub fn set_backup(&mut self, value: bool) {
+        self.backup = value;
     }
 
-    fn get_attribute(&self, name: &str) -> String {
-        let attr = self.get_attribute_node(name);
-        if let Some(attr) = attr {
-            // FIXME:
-            attr.value().unwrap()
-        } else {
-            String::new()
-        }
+    pub fn backup(&self) -> bool {
+        self.backup
     }
 
-    fn get_attribute_node(&self, name: &str) -> Option<XmlAttr> {
-        self.element
-            .borrow()
-            .attributes()
-            .iter()
-            .find(|v| v.borrow().local_name() == name)
-            .map(XmlAttr::from)
+    pub fn set_encoding(&mut self, value: Option<encoding::Encoding>) {
+        self.encoding = value;
     }
 
-    fn get_elements_by_tag_name(&self, tag_name: &str) -> XmlElementList {
-        XmlElementList {
-            node: self.as_node(),
-            tag_name: tag_name.to_string(),
-        }
+    pub fn encoding(&self) -> Option<encoding::Encoding> {
+        self.encoding
     }
 }
 
-impl ElementMut for XmlElement {
-    fn set_attribute(&self, name: &str, value: &str) -> error::Result<()> {
-        let attr = self.owner_document().unwrap().create_attribute(name)?;
-        attr.set_value(value)?;
-        self.set_attribute_node(attr)?;
-        Ok(())
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug)]
+pub struct XmlElementList {
+    node: XmlNode,
+    tag_name: String,
+    namespace_uri: Option<String>,
+    cache: ElementListCache,
+}
+
+/// `item` and `length` see no elements when the search fails, e.g. when the depth limit is
+/// exceeded. Use [`XmlElementList::items`] to get the error.
+impl NodeList for XmlElementList {
+    fn item(&self, index: usize) -> Option<XmlNode> {
+        self.items().ok()?.get(index).map(|v| v.as_node())
     }
 
-    fn remove_attribute(&self, name: &str) -> error::Result<()> {
-        self.element.borrow_mut().remove_attribute(name);
-        Ok(())
+    fn length(&self) -> usize {
+        self.items().map(|v| v.len()).unwrap_or_default()
     }
+}
 
-    fn set_attribute_node(&self, new_attr: XmlAttr) -> error::Result<Option<XmlAttr>> {
-        if self.owner_document() != new_attr.owner_document() {
-            return Err(error::DomException::WrongDocumentErr)?;
-        }
+impl PartialEq<XmlElementList> for XmlElementList {
+    fn eq(&self, other: &XmlElementList) -> bool {
+        self.node == other.node
+            && self.tag_name == other.tag_name
+            && self.namespace_uri == other.namespace_uri
+    }
+}
 
-        if new_attr.attribute.borrow().order() != 0 {
-            return Err(error::DomException::InuseAttributeErr)?;
+impl XmlElementList {
+    fn new(node: XmlNode, tag_name: &str, namespace_uri: Option<&str>) -> Self {
+        XmlElementList {
+            node,
+            tag_name: tag_name.to_string(),
+            namespace_uri: namespace_uri.map(|v| v.to_string()),
+            cache: Rc::new(RefCell::new(None)),
         }
+    }
 
-        let attr = self
-            .element
-            .borrow_mut()
-            .remove_attribute(new_attr.name().as_str())
-            .and_then(|v| v.as_attribute());
-
-        self.element
-            .borrow_mut()
-            .append_attribute(Rc::new(new_attr.attribute.into()));
-
-        Ok(attr.map(XmlAttr::from))
+    pub fn iter(&self) -> XmlNodeIter {
+        XmlNodeIter {
+            nodes: self
+                .items()
+                .unwrap_or_default()
+                .iter()
+                .map(|v| v.as_node())
+                .collect(),
+            index: 0,
+        }
     }
 
-    fn normalize(&self) {
-        todo!()
+    /// Returns the matching elements in document order.
+    ///
+    /// The result is kept until the document changes.
+    pub fn items(&self) -> error::Result<Vec<XmlElement>> {
+        // Matching depends on names and namespace declarations too, so the revision is used
+        // rather than the generation.
+        let revision = || match &self.node {
+            XmlNode::Document(v) => v.document.borrow().context().revision(),
+            XmlNode::Element(v) => v.element.borrow().context().revision(),
+            _ => unreachable!(),
+        };
+        if let Some((r, items)) = self.cache.borrow().as_ref() {
+            if *r == revision() {
+                return Ok(items.clone());
+            }
+        }
+
+        let tag_name = self.tag_name.as_str();
+        let namespace_uri = self.namespace_uri.as_deref();
+        let items = match &self.node {
+            XmlNode::Document(v) => v.elements_by_tag_name(tag_name, namespace_uri),
+            XmlNode::Element(v) => v.elements_by_tag_name(tag_name, namespace_uri),
+            _ => unreachable!(),
+        }?;
+        // Taken after the search, which may load the children of a forked document.
+        *self.cache.borrow_mut() = Some((revision(), items.clone()));
+        Ok(items)
     }
 }
 
-impl Node for XmlElement {
-    fn node_name(&self) -> String {
-        self.tag_name()
-    }
+// -----------------------------------------------------------------------------------------------
 
-    fn node_value(&self) -> error::Result<Option<String>> {
-        Ok(None)
-    }
+#[derive(Clone, Debug)]
+pub struct XmlNodeList {
+    node: XmlNode,
+    cache: NodeListCache,
+}
 
-    fn node_type(&self) -> NodeType {
-        NodeType::Element
+impl NodeList for XmlNodeList {
+    fn item(&self, index: usize) -> Option<XmlNode> {
+        self.with_items(|v| v.get(index).cloned())
     }
 
-    fn parent_node(&self) -> Option<XmlNode> {
-        self.element.borrow().parent().ok().map(XmlNode::from)
+    fn length(&self) -> usize {
+        self.with_items(|v| v.len())
     }
+}
 
-    fn child_nodes(&self) -> XmlNodeList {
+impl From<XmlNode> for XmlNodeList {
+    fn from(value: XmlNode) -> Self {
         XmlNodeList {
-            node: self.as_node(),
+            node: value,
+            cache: Rc::new(RefCell::new(None)),
         }
     }
+}
 
-    fn first_child(&self) -> Option<XmlNode> {
-        self.first_child_node()
+impl PartialEq<XmlNodeList> for XmlNodeList {
+    fn eq(&self, other: &XmlNodeList) -> bool {
+        self.node == other.node
     }
+}
 
-    fn last_child(&self) -> Option<XmlNode> {
-        self.last_child_node()
+impl XmlNodeList {
+    pub fn iter(&self) -> XmlNodeIter {
+        XmlNodeIter {
+            nodes: self.with_items(|v| v.to_vec()),
+            index: 0,
+        }
     }
 
-    fn previous_sibling(&self) -> Option<XmlNode> {
-        self.parent_node()
-            .as_ref()
-            .and_then(|parent| parent.previous_sibling_child(self.as_node()))
+    /// Iterate the children without taking a snapshot first.
+    ///
+    /// Unlike [`XmlNodeList::iter`], the children are read as the iterator
+    /// advances, so changes to the parent during iteration are observed.
+    pub fn live_iter(&self) -> XmlChildIter {
+        self.node.child_iter()
     }
 
-    fn next_sibling(&self) -> Option<XmlNode> {
-        self.parent_node()
-            .as_ref()
-            .and_then(|parent| parent.next_sibling_child(self.as_node()))
+    fn generation(&self) -> usize {
+        match &self.node {
+            XmlNode::Document(v) => v.document.borrow().context().generation(),
+            XmlNode::DocumentFragment(v) => v.document.borrow().context().generation(),
+            XmlNode::Element(v) => v.element.borrow().context().generation(),
+            XmlNode::Attribute(v) => v.attribute.borrow().context().generation(),
+            v => v
+                .owner_document()
+                .map(|v| v.document.borrow().context().generation())
+                .unwrap_or_default(),
+        }
     }
 
-    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
-        fn get(node: &XmlNode) -> Vec<(String, XmlAttr)> {
-            node.as_element()
-                .unwrap()
-                .element
-                .borrow()
-                .attributes()
-                .iter()
-                .map(XmlAttr::from)
-                .map(|v| (v.name(), v))
-                .collect()
+    fn with_items<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&[XmlNode]) -> T,
+    {
+        let generation = self.generation();
+        let mut cache = self.cache.borrow_mut();
+        match cache.as_ref() {
+            Some((g, items)) if *g == generation => f(items.as_slice()),
+            _ => {
+                let items = self.node.child_iter().collect::<Vec<_>>();
+                let value = f(items.as_slice());
+                *cache = Some((generation, items));
+                value
+            }
         }
+    }
+}
 
-        fn add(node: &XmlNode, attr: XmlAttr) -> error::Result<Option<XmlAttr>> {
-            let element = node.as_element().unwrap();
-            element.set_attribute_node(attr)
-        }
+// -----------------------------------------------------------------------------------------------
 
-        fn remove(node: &XmlNode, name: &str) -> error::Result<XmlAttr> {
-            let element = node.as_element().unwrap();
-            if let Some(attr) = element.get_attribute_node(name) {
-                element.remove_attribute(name)?;
-                Ok(attr)
-            } else {
-                Err(error::DomException::NotFoundErr)?
-            }
-        }
+#[derive(Clone)]
+pub struct XmlFilteredNodeList {
+    list: XmlNodeList,
+    what_to_show: WhatToShow,
+    filter: Rc<dyn NodeFilter>,
+}
 
-        Some(XmlNamedNodeMap {
-            node: self.as_node(),
-            get: Box::new(get),
-            add: Box::new(add),
-            remove: Box::new(remove),
-        })
+impl NodeList for XmlFilteredNodeList {
+    fn item(&self, index: usize) -> Option<XmlNode> {
+        self.list
+            .with_items(|v| v.iter().filter(|v| self.accept(v)).nth(index).cloned())
     }
 
-    fn owner_document(&self) -> Option<XmlDocument> {
-        Some(XmlDocument::from(self.element.borrow().owner()))
+    fn length(&self) -> usize {
+        self.list
+            .with_items(|v| v.iter().filter(|v| self.accept(v)).count())
     }
+}
 
-    fn has_child(&self) -> bool {
-        self.has_child_node()
+impl fmt::Debug for XmlFilteredNodeList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("XmlFilteredNodeList")
+            .field("node", &self.list.node)
+            .field("what_to_show", &self.what_to_show)
+            .finish()
     }
 }
 
-impl NodeMut for XmlElement {
-    fn set_node_value(&self, _: &str) -> error::Result<()> {
-        Err(error::DomException::NoDataAllowedErr)?
+impl XmlFilteredNodeList {
+    pub fn iter(&self) -> XmlNodeIter {
+        XmlNodeIter {
+            nodes: self.list.with_items(|v| {
+                v.iter()
+                    .filter(|v| self.accept(v))
+                    .cloned()
+                    .collect::<Vec<_>>()
+            }),
+            index: 0,
+        }
     }
 
-    fn insert_before(
-        &self,
-        new_child: XmlNode,
-        ref_child: Option<&XmlNode>,
-    ) -> error::Result<XmlNode> {
-        if self.owner_document() != new_child.owner_document() {
-            return Err(error::DomException::WrongDocumentErr)?;
-        }
+    fn accept(&self, node: &XmlNode) -> bool {
+        self.what_to_show.contains(node.node_type())
+            && self.filter.accept_node(node) == FilterResult::Accept
+    }
+}
 
-        let value = if let Some(r) = ref_child {
-            if self.owner_document() != r.owner_document() {
-                return Err(error::DomException::WrongDocumentErr)?;
-            }
+// -----------------------------------------------------------------------------------------------
 
-            match self
-                .element
-                .borrow()
-                .insert_before(new_child.try_into()?, r.id())
-            {
-                Ok(v) => Ok(v),
-                Err(xml_info::error::Error::OufOfIndex(_)) => Err(error::DomException::NotFoundErr),
-                _ => Err(error::DomException::HierarchyRequestErr),
-            }?
-        } else {
-            self.element
-                .borrow()
-                .append(new_child.try_into()?)
-                .map_err(|_| error::DomException::HierarchyRequestErr)?
-        };
+#[derive(Clone)]
+pub struct XmlTreeWalker {
+    root: XmlNode,
+    current: XmlNode,
+    what_to_show: WhatToShow,
+    filter: Option<Rc<dyn NodeFilter>>,
+}
 
-        Ok(XmlNode::from(value))
+impl fmt::Debug for XmlTreeWalker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("XmlTreeWalker")
+            .field("root", &self.root)
+            .field("current", &self.current)
+            .field("what_to_show", &self.what_to_show)
+            .finish()
     }
+}
 
-    fn remove_child(&self, old_child: &XmlNode) -> error::Result<XmlNode> {
-        if self.owner_document() != old_child.owner_document() {
-            return Err(error::DomException::WrongDocumentErr)?;
-        }
+impl XmlTreeWalker {
+    pub fn root(&self) -> XmlNode {
+        self.root.clone()
+    }
 
-        match self.element.borrow().delete(old_child.id()) {
-            Some(v) => Ok(XmlNode::from(v)),
-            _ => Err(error::DomException::NotFoundErr)?,
-        }
+    pub fn what_to_show(&self) -> WhatToShow {
+        self.what_to_show
     }
-}
 
-impl AsNode for XmlElement {
-    fn as_node(&self) -> XmlNode {
-        XmlNode::Element(self.clone())
+    pub fn current_node(&self) -> XmlNode {
+        self.current.clone()
     }
-}
 
-impl AsExpandedName for XmlElement {
-    fn as_expanded_name(&self) -> error::Result<Option<ExpandedName>> {
-        let local_name = self.element.borrow().local_name().to_string();
-        // TODO: prefix is None
-        let prefix = self
-            .element
-            .borrow()
-            .prefix()
-            .unwrap_or("xmlns")
-            .to_string();
-        let namespaces = self.in_scope_namespace()?;
-        let ns = if let Some(ns) = namespaces.iter().find(|v| v.node_name() == prefix) {
-            ns.node_value()?
-        } else {
-            None
-        };
-        Ok(Some((local_name, Some(prefix), ns)))
+    pub fn set_current_node(&mut self, node: XmlNode) {
+        self.current = node;
     }
-}
 
-impl AsStringValue for XmlElement {
-    fn as_string_value(&self) -> error::Result<String> {
-        let mut s = String::new();
-        for child in self.children() {
-            match child {
-                XmlNode::Attribute(_) => {}
-                XmlNode::CData(v) => s.push_str(&v.as_string_value()?),
-                XmlNode::Comment(_) => {}
-                XmlNode::Document(_) => {}
-                XmlNode::DocumentFragment(_) => {}
-                XmlNode::DocumentType(_) => {}
-                XmlNode::Element(v) => s.push_str(&v.as_string_value()?),
-                XmlNode::Entity(_) => {}
-                XmlNode::EntityReference(_) => {}
-                XmlNode::Namespace(_) => {}
-                XmlNode::Notation(_) => {}
-                XmlNode::PI(_) => {}
-                XmlNode::ExpandedText(v) => s.push_str(&v.as_string_value()?),
-                XmlNode::Text(v) => s.push_str(&v.as_string_value()?),
+    pub fn parent_node(&mut self) -> Option<XmlNode> {
+        let mut node = self.current.clone();
+        while !node.ptr_eq(&self.root) {
+            node = node.parent_node()?;
+            if self.filter(&node) == FilterResult::Accept {
+                return Some(self.move_to(node));
             }
         }
-        Ok(s)
+        None
     }
-}
 
-impl PrettyPrint for XmlElement {
-    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
-        self.element.borrow().indented(0, f)
+    pub fn first_child(&mut self) -> Option<XmlNode> {
+        self.traverse_children(true)
     }
-}
 
-impl HasChild for XmlElement {
-    fn children(&self) -> Vec<XmlNode> {
-        let text_expanded = self
-            .owner_document()
-            .unwrap()
-            .document
-            .borrow()
-            .context()
-            .text_expanded();
+    pub fn last_child(&mut self) -> Option<XmlNode> {
+        self.traverse_children(false)
+    }
+
+    pub fn previous_sibling(&mut self) -> Option<XmlNode> {
+        self.traverse_siblings(false)
+    }
 
-        let mut children = vec![];
+    pub fn next_sibling(&mut self) -> Option<XmlNode> {
+        self.traverse_siblings(true)
+    }
 
-        let mut text: Option<XmlExpandedText> = None;
-        for child in self.element.borrow().children().iter() {
-            let child = XmlNode::from(child);
-            match child {
-                XmlNode::CData(v) if text_expanded => {
-                    if let Some(t) = text.as_mut() {
-                        t.push_cdata(v);
-                    } else {
-                        text = Some(XmlExpandedText::from(v));
+    pub fn previous_node(&mut self) -> Option<XmlNode> {
+        let mut node = self.current.clone();
+        while !node.ptr_eq(&self.root) {
+            let mut sibling = node.previous_sibling();
+            while let Some(v) = sibling {
+                node = v;
+                let mut result = self.filter(&node);
+                while result != FilterResult::Reject {
+                    match node.last_child() {
+                        Some(child) => {
+                            node = child;
+                            result = self.filter(&node);
+                        }
+                        None => break,
                     }
                 }
-                XmlNode::EntityReference(v) if text_expanded => {
-                    if let Some(t) = text.as_mut() {
-                        t.push_reference(v);
-                    } else {
-                        text = Some(XmlExpandedText::from(v));
-                    }
+                if result == FilterResult::Accept {
+                    return Some(self.move_to(node));
                 }
-                XmlNode::Text(v) if text_expanded => {
-                    if let Some(t) = text.as_mut() {
-                        t.push_text(v);
-                    } else {
-                        text = Some(XmlExpandedText::from(v));
-                    }
+                sibling = node.previous_sibling();
+            }
+
+            if node.ptr_eq(&self.root) {
+                return None;
+            }
+            node = node.parent_node()?;
+            if self.filter(&node) == FilterResult::Accept {
+                return Some(self.move_to(node));
+            }
+        }
+        None
+    }
+
+    pub fn next_node(&mut self) -> Option<XmlNode> {
+        let mut node = self.current.clone();
+        let mut result = FilterResult::Accept;
+        loop {
+            while result != FilterResult::Reject {
+                match node.first_child() {
+                    Some(child) => node = child,
+                    None => break,
                 }
-                _ => {
-                    if let Some(t) = text {
-                        children.push(t.as_node());
+                result = self.filter(&node);
+                if result == FilterResult::Accept {
+                    return Some(self.move_to(node));
+                }
+            }
+
+            node = following_node(&self.root, &node)?;
+            result = self.filter(&node);
+            if result == FilterResult::Accept {
+                return Some(self.move_to(node));
+            }
+        }
+    }
+
+    fn traverse_children(&mut self, forward: bool) -> Option<XmlNode> {
+        let mut node = first_or_last_child(&self.current, forward);
+        while let Some(v) = node {
+            match self.filter(&v) {
+                FilterResult::Accept => return Some(self.move_to(v)),
+                FilterResult::Skip => {
+                    if let Some(child) = first_or_last_child(&v, forward) {
+                        node = Some(child);
+                        continue;
                     }
+                }
+                FilterResult::Reject => {}
+            }
+
+            let mut v = v;
+            loop {
+                if let Some(sibling) = sibling_node(&v, forward) {
+                    node = Some(sibling);
+                    break;
+                }
 
-                    text = None;
-                    children.push(child);
+                let parent = v.parent_node()?;
+                if parent.ptr_eq(&self.root) || parent.ptr_eq(&self.current) {
+                    return None;
                 }
+                v = parent;
             }
         }
+        None
+    }
+
+    fn traverse_siblings(&mut self, forward: bool) -> Option<XmlNode> {
+        let mut node = self.current.clone();
+        if node.ptr_eq(&self.root) {
+            return None;
+        }
+
+        loop {
+            let mut sibling = sibling_node(&node, forward);
+            while let Some(v) = sibling {
+                node = v;
+                let result = self.filter(&node);
+                if result == FilterResult::Accept {
+                    return Some(self.move_to(node));
+                }
+
+                sibling = match result {
+                    FilterResult::Reject => None,
+                    _ => first_or_last_child(&node, forward),
+                }
+                .or_else(|| sibling_node(&node, forward));
+            }
 
-        if let Some(t) = text {
-            children.push(t.as_node());
+            node = node.parent_node()?;
+            if node.ptr_eq(&self.root) || self.filter(&node) == FilterResult::Accept {
+                return None;
+            }
         }
+    }
 
-        children
+    fn move_to(&mut self, node: XmlNode) -> XmlNode {
+        self.current = node.clone();
+        node
     }
-}
 
-impl From<info::XmlNode<info::XmlElement>> for XmlElement {
-    fn from(value: info::XmlNode<info::XmlElement>) -> Self {
-        XmlElement { element: value }
+    fn filter(&self, node: &XmlNode) -> FilterResult {
+        filter_node(self.what_to_show, self.filter.as_deref(), node)
     }
 }
 
-impl fmt::Debug for XmlElement {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "XmlElement {{ {} }}", self.node_name())
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone)]
+pub struct XmlNodeIterator {
+    root: XmlNode,
+    reference: XmlNode,
+    pointer_before_reference: bool,
+    what_to_show: WhatToShow,
+    filter: Option<Rc<dyn NodeFilter>>,
+    path: Vec<(XmlNode, Option<XmlNode>, Option<XmlNode>)>,
+}
+
+impl Iterator for XmlNodeIterator {
+    type Item = XmlNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_node()
     }
 }
 
-impl fmt::Display for XmlElement {
+impl fmt::Debug for XmlNodeIterator {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        self.element.borrow().fmt(f)
+        f.debug_struct("XmlNodeIterator")
+            .field("root", &self.root)
+            .field("reference", &self.reference)
+            .field("pointer_before_reference", &self.pointer_before_reference)
+            .field("what_to_show", &self.what_to_show)
+            .finish()
     }
 }
 
-impl XmlElement {
-    pub fn in_scope_namespace(&self) -> error::Result<Vec<XmlNamespace>> {
-        Ok(self
-            .element
-            .borrow()
-            .in_scope_namespace()?
-            .iter()
-            .map(XmlNamespace::from)
-            .collect())
+impl XmlNodeIterator {
+    pub fn root(&self) -> XmlNode {
+        self.root.clone()
     }
 
-    fn elements_by_tag_name(&self, tag_name: &str) -> Vec<XmlElement> {
-        let mut elems = vec![];
-
-        if self.match_tag_name(tag_name) {
-            elems.push(self.clone());
-        }
+    pub fn what_to_show(&self) -> WhatToShow {
+        self.what_to_show
+    }
 
-        for child in self.children() {
-            if let XmlNode::Element(child) = child {
-                let mut descendant = child.elements_by_tag_name(tag_name);
-                elems.append(&mut descendant);
-            }
-        }
+    pub fn reference_node(&self) -> XmlNode {
+        self.reference.clone()
+    }
 
-        elems
+    pub fn pointer_before_reference_node(&self) -> bool {
+        self.pointer_before_reference
     }
 
-    fn match_tag_name(&self, tag_name: &str) -> bool {
-        tag_name == "*" || self.node_name() == tag_name
+    pub fn next_node(&mut self) -> Option<XmlNode> {
+        self.traverse(true)
     }
-}
 
-// -----------------------------------------------------------------------------------------------
+    pub fn previous_node(&mut self) -> Option<XmlNode> {
+        self.traverse(false)
+    }
 
-#[derive(Clone, PartialEq)]
-pub struct XmlText {
-    data: info::XmlNode<info::XmlText>,
-}
+    fn traverse(&mut self, forward: bool) -> Option<XmlNode> {
+        self.adjust_reference();
 
-impl Text for XmlText {}
+        let mut node = self.reference.clone();
+        let mut before = self.pointer_before_reference;
+        loop {
+            if forward && before {
+                before = false;
+            } else if forward {
+                node = match node.first_child() {
+                    Some(child) => child,
+                    None => following_node(&self.root, &node)?,
+                };
+            } else if before {
+                node = preceding_node(&self.root, &node)?;
+            } else {
+                before = true;
+            }
 
-impl TextMut for XmlText {
-    fn split_text(&self, offset: usize) -> error::Result<XmlText> {
-        if self.length() < offset {
-            return Err(error::DomException::IndexSizeErr)?;
+            // Nodes are visited in a flat order, so rejected and skipped nodes are alike.
+            if filter_node(self.what_to_show, self.filter.as_deref(), &node) == FilterResult::Accept
+            {
+                break;
+            }
         }
 
-        let parent = self.data.borrow().parent_item();
-        match parent {
-            Some(parent) => match &*parent {
-                info::XmlItem::Attribute(v) => {
-                    let data2 = self.data.borrow_mut().split_at(offset);
-                    let data2_node: Rc<info::XmlItem> = Rc::new(data2.clone().into());
-
-                    let inserted = v
-                        .borrow()
-                        .insert_after(data2_node.clone(), self.data.borrow().id());
+        self.reference = node.clone();
+        self.pointer_before_reference = before;
+        self.update_path();
+        Some(node)
+    }
 
-                    match inserted {
-                        Ok(_) => {}
-                        Err(info::error::Error::OufOfIndex(_)) => {
-                            v.borrow().append(data2_node)?;
-                        }
-                        Err(e) => {
-                            return Err(error::Error::from(e));
-                        }
-                    }
+    // The reference is moved off a removed subtree when the iterator is used next.
+    fn adjust_reference(&mut self) {
+        let mut parent = self.root.clone();
+        for (node, previous, next) in self.path.clone() {
+            if node.parent_node().is_some_and(|v| v.ptr_eq(&parent)) {
+                parent = node;
+                continue;
+            }
 
-                    Ok(XmlText::from(data2))
+            let is_child = |v: &XmlNode| v.parent_node().is_some_and(|v| v.ptr_eq(&parent));
+            if self.pointer_before_reference {
+                let following = match next.filter(is_child) {
+                    Some(v) => Some(v),
+                    None => following_node(&self.root, &parent),
+                };
+                if let Some(following) = following {
+                    self.reference = following;
+                    self.update_path();
+                    return;
                 }
-                info::XmlItem::Element(v) => {
-                    let data2 = self.data.borrow_mut().split_at(offset);
-                    let data2_node: Rc<info::XmlItem> = Rc::new(data2.clone().into());
-
-                    let inserted = v
-                        .borrow()
-                        .insert_after(data2_node.clone(), self.data.borrow().id());
+                self.pointer_before_reference = false;
+            }
 
-                    match inserted {
-                        Ok(_) => {}
-                        Err(info::error::Error::OufOfIndex(_)) => {
-                            v.borrow().append(data2_node)?;
-                        }
-                        Err(e) => {
-                            return Err(error::Error::from(e));
-                        }
+            self.reference = match previous.filter(is_child) {
+                Some(mut v) => {
+                    while let Some(child) = v.last_child() {
+                        v = child;
                     }
-
-                    Ok(XmlText::from(data2))
+                    v
                 }
-                _ => Err(error::DomException::HierarchyRequestErr)?,
-            },
-            _ => Err(error::DomException::HierarchyRequestErr)?,
+                None => parent,
+            };
+            self.update_path();
+            return;
         }
     }
-}
 
-impl CharacterData for XmlText {
-    fn data(&self) -> error::Result<String> {
-        Ok(self.data.borrow().character_code().to_string())
+    fn update_path(&mut self) {
+        self.path.clear();
+        let mut node = self.reference.clone();
+        while !node.ptr_eq(&self.root) {
+            let parent = match node.parent_node() {
+                Some(v) => v,
+                None => break,
+            };
+            self.path
+                .push((node.clone(), node.previous_sibling(), node.next_sibling()));
+            node = parent;
+        }
+        self.path.reverse();
     }
+}
 
-    fn length(&self) -> usize {
-        self.data.borrow().len()
-    }
+// -----------------------------------------------------------------------------------------------
 
-    fn substring_data(&self, offset: usize, count: usize) -> error::Result<String> {
-        if self.length() < offset {
-            Err(error::DomException::IndexSizeErr)?
-        } else {
-            Ok(self.data.borrow().substring(offset..(offset + count)))
+pub struct XmlChildIter {
+    parent: XmlNode,
+    index: usize,
+    text_expanded: bool,
+    pending: Option<XmlNode>,
+}
+
+impl Iterator for XmlChildIter {
+    type Item = XmlNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let child = self.pending.take().or_else(|| self.next_child())?;
+        if !self.text_expanded {
+            return Some(child);
+        }
+
+        let mut text = match child {
+            XmlNode::CData(v) => XmlExpandedText::from(v),
+            XmlNode::EntityReference(v) => XmlExpandedText::from(v),
+            XmlNode::Text(v) => XmlExpandedText::from(v),
+            _ => return Some(child),
+        };
+
+        while let Some(child) = self.next_child() {
+            match child {
+                XmlNode::CData(v) => text.push_cdata(v),
+                XmlNode::EntityReference(v) => text.push_reference(v),
+                XmlNode::Text(v) => text.push_text(v),
+                _ => {
+                    self.pending = Some(child);
+                    break;
+                }
+            }
         }
+
+        Some(text.as_node())
     }
 }
 
-impl CharacterDataMut for XmlText {
-    fn insert_data(&self, offset: usize, arg: &str) -> error::Result<()> {
-        if self.length() < offset {
-            Err(error::DomException::IndexSizeErr)?
-        } else {
-            self.data.borrow_mut().insert(offset, arg)?;
-            Ok(())
+impl XmlChildIter {
+    fn new(parent: XmlNode, text_expanded: bool) -> Self {
+        XmlChildIter {
+            parent,
+            index: 0,
+            text_expanded,
+            pending: None,
         }
     }
 
-    fn delete_data(&self, offset: usize, count: usize) -> error::Result<()> {
-        if self.length() < (offset + count) {
-            Err(error::DomException::IndexSizeErr)?
-        } else {
-            self.data.borrow_mut().delete(offset, count);
-            Ok(())
-        }
+    fn next_child(&mut self) -> Option<XmlNode> {
+        let child = self.parent.child_at(self.index)?;
+        self.index += 1;
+        Some(child)
     }
 }
 
-impl Node for XmlText {
-    fn node_name(&self) -> String {
-        "#text".to_string()
-    }
-
-    fn node_value(&self) -> error::Result<Option<String>> {
-        Ok(Some(self.data()?))
-    }
+// -----------------------------------------------------------------------------------------------
 
-    fn node_type(&self) -> NodeType {
-        NodeType::Text
-    }
+pub struct XmlNodeIter {
+    nodes: Vec<XmlNode>,
+    index: usize,
+}
 
-    fn parent_node(&self) -> Option<XmlNode> {
-        self.data.borrow().parent_item().map(XmlNode::from)
-    }
+impl Iterator for XmlNodeIter {
+    type Item = XmlNode;
 
-    fn child_nodes(&self) -> XmlNodeList {
-        XmlNodeList {
-            node: self.as_node(),
-        }
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.nodes.get(self.index);
+        self.index += 1;
+        item.cloned()
     }
+}
 
-    fn first_child(&self) -> Option<XmlNode> {
-        None
-    }
+// -----------------------------------------------------------------------------------------------
 
-    fn last_child(&self) -> Option<XmlNode> {
-        None
-    }
+pub struct XmlEventIter {
+    root: XmlNode,
+    next: Option<XmlNode>,
+    stack: Vec<XmlNode>,
+}
 
-    fn previous_sibling(&self) -> Option<XmlNode> {
-        self.parent_node()
-            .as_ref()
-            .and_then(|parent| parent.previous_sibling_child(self.as_node()))
-    }
+impl Iterator for XmlEventIter {
+    type Item = Event;
 
-    fn next_sibling(&self) -> Option<XmlNode> {
-        self.parent_node()
-            .as_ref()
-            .and_then(|parent| parent.next_sibling_child(self.as_node()))
-    }
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(node) = self.next.take() {
+                let event = match &node {
+                    XmlNode::Document(_) => Some(Event::StartDocument),
+                    XmlNode::DocumentFragment(_) => None,
+                    XmlNode::Element(v) => {
+                        let attributes = v
+                            .attributes()
+                            .map(|m| {
+                                m.iter()
+                                    .map(|a| (a.name(), a.value().unwrap_or_default()))
+                                    .collect()
+                            })
+                            .unwrap_or_default();
+                        Some(Event::StartElement(v.tag_name(), attributes))
+                    }
+                    XmlNode::Text(_) | XmlNode::ExpandedText(_) => {
+                        Some(Event::Text(node.as_string_value().unwrap_or_default()))
+                    }
+                    XmlNode::CData(v) => Some(Event::CData(v.data().unwrap_or_default())),
+                    XmlNode::EntityReference(v) => Some(Event::EntityReference(v.node_name())),
+                    XmlNode::PI(v) => Some(Event::PI(v.target(), v.data())),
+                    XmlNode::Comment(v) => Some(Event::Comment(v.data().unwrap_or_default())),
+                    _ => None,
+                };
+
+                if matches!(
+                    node,
+                    XmlNode::Document(_) | XmlNode::DocumentFragment(_) | XmlNode::Element(_)
+                ) {
+                    self.next = node.first_child();
+                    self.stack.push(node);
+                } else {
+                    self.next = self.next_sibling(&node);
+                }
 
-    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
-        None
+                if event.is_some() {
+                    return event;
+                }
+            } else if let Some(node) = self.stack.pop() {
+                self.next = self.next_sibling(&node);
+                match node {
+                    XmlNode::Document(_) => return Some(Event::EndDocument),
+                    XmlNode::Element(v) => return Some(Event::EndElement(v.tag_name())),
+                    _ => {}
+                }
+            } else {
+                return None;
+            }
+        }
     }
+}
 
-    fn owner_document(&self) -> Option<XmlDocument> {
-        Some(XmlDocument::from(self.data.borrow().owner()))
+impl XmlEventIter {
+    fn new(root: XmlNode) -> Self {
+        XmlEventIter {
+            root: root.clone(),
+            next: Some(root),
+            stack: vec![],
+        }
     }
 
-    fn has_child(&self) -> bool {
-        false
+    fn next_sibling(&self, node: &XmlNode) -> Option<XmlNode> {
+        if node.ptr_eq(&self.root) {
+            None
+        } else {
+            node.next_sibling()
+        }
     }
 }
 
-impl NodeMut for XmlText {
-    fn set_node_value(&self, value: &str) -> error::Result<()> {
-        self.set_data(value)
-    }
-
-    fn insert_before(&self, _: XmlNode, _: Option<&XmlNode>) -> error::Result<XmlNode> {
-        Err(error::DomException::HierarchyRequestErr)?
-    }
+// -----------------------------------------------------------------------------------------------
 
-    fn remove_child(&self, _: &XmlNode) -> error::Result<XmlNode> {
-        Err(error::DomException::HierarchyRequestErr)?
-    }
+pub struct XmlDescendantIter {
+    root: XmlNode,
+    next: Option<XmlNode>,
 }
 
-impl AsNode for XmlText {
-    fn as_node(&self) -> XmlNode {
-        XmlNode::Text(self.clone())
-    }
-}
+impl Iterator for XmlDescendantIter {
+    type Item = XmlNode;
 
-impl AsStringValue for XmlText {
-    fn as_string_value(&self) -> error::Result<String> {
-        self.data()
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        self.next = node
+            .first_child()
+            .or_else(|| following_node(&self.root, &node));
+        Some(node)
     }
 }
 
-impl PrettyPrint for XmlText {
-    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
-        self.data.borrow().indented(0, f)
-    }
+// -----------------------------------------------------------------------------------------------
+
+pub struct XmlDescendantElementIter {
+    nodes: XmlDescendantIter,
 }
 
-impl From<info::XmlNode<info::XmlText>> for XmlText {
-    fn from(value: info::XmlNode<info::XmlText>) -> Self {
-        XmlText { data: value }
+impl Iterator for XmlDescendantElementIter {
+    type Item = XmlElement;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nodes.find_map(|v| v.as_element())
     }
 }
 
-impl fmt::Debug for XmlText {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "XmlText {{ {} }}", self.data.borrow().character_code())
-    }
+// -----------------------------------------------------------------------------------------------
+
+pub struct XmlAncestorIter {
+    next: Option<XmlNode>,
 }
 
-impl fmt::Display for XmlText {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        self.data.borrow().fmt(f)
+impl Iterator for XmlAncestorIter {
+    type Item = XmlNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        self.next = node.parent_node();
+        Some(node)
     }
 }
 
 // -----------------------------------------------------------------------------------------------
 
-#[derive(Clone, PartialEq)]
-pub struct XmlComment {
-    data: info::XmlNode<info::XmlComment>,
+pub struct XmlSiblingIter {
+    next: Option<XmlNode>,
+    forward: bool,
 }
 
-impl Comment for XmlComment {}
-
-impl CommentMut for XmlComment {}
+impl Iterator for XmlSiblingIter {
+    type Item = XmlNode;
 
-impl CharacterData for XmlComment {
-    fn data(&self) -> error::Result<String> {
-        Ok(self.data.borrow().comment().to_string())
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        self.next = sibling_node(&node, self.forward);
+        Some(node)
     }
+}
 
-    fn length(&self) -> usize {
-        self.data.borrow().len()
-    }
+// -----------------------------------------------------------------------------------------------
 
-    fn substring_data(&self, offset: usize, count: usize) -> error::Result<String> {
-        if self.length() < offset {
-            Err(error::DomException::IndexSizeErr)?
-        } else {
-            Ok(self.data.borrow().substring(offset..(offset + count)))
-        }
-    }
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Repertoire {
+    Ascii,
+    Latin1,
+    #[default]
+    Unicode,
 }
 
-impl CharacterDataMut for XmlComment {
-    fn insert_data(&self, offset: usize, arg: &str) -> error::Result<()> {
-        if self.length() < offset {
-            Err(error::DomException::IndexSizeErr)?
-        } else {
-            self.data.borrow_mut().insert(offset, arg)?;
-            Ok(())
+impl Repertoire {
+    pub fn contains(&self, value: char) -> bool {
+        match self {
+            Repertoire::Ascii => value.is_ascii(),
+            Repertoire::Latin1 => (value as u32) < 0x100,
+            Repertoire::Unicode => true,
         }
     }
+}
 
-    fn delete_data(&self, offset: usize, count: usize) -> error::Result<()> {
-        if self.length() < (offset + count) {
-            Err(error::DomException::IndexSizeErr)?
-        } else {
-            self.data.borrow_mut().delete(offset, count);
-            Ok(())
-        }
-    }
+// -----------------------------------------------------------------------------------------------
+
+pub struct XmlEventWriter<W: io::Write> {
+    writer: W,
+    pretty: bool,
+    html: bool,
+    repertoire: Repertoire,
+    hex_char_ref: bool,
+    stack: Vec<(String, bool)>,
+    tag_opened: bool,
+    written: bool,
 }
 
-impl Node for XmlComment {
-    fn node_name(&self) -> String {
-        "#comment".to_string()
+impl<W: io::Write> XmlEventWriter<W> {
+    pub fn new(writer: W) -> Self {
+        XmlEventWriter {
+            writer,
+            pretty: false,
+            html: false,
+            repertoire: Repertoire::Unicode,
+            hex_char_ref: true,
+            stack: vec![],
+            tag_opened: false,
+            written: false,
+        }
     }
 
-    fn node_value(&self) -> error::Result<Option<String>> {
-        Ok(Some(self.data()?))
+    pub fn set_pretty(&mut self, value: bool) {
+        self.pretty = value;
     }
 
-    fn node_type(&self) -> NodeType {
-        NodeType::Comment
+    pub fn pretty(&self) -> bool {
+        self.pretty
     }
 
-    fn parent_node(&self) -> Option<XmlNode> {
-        self.data.borrow().parent().ok().map(XmlNode::from)
+    pub fn set_html(&mut self, value: bool) {
+        self.html = value;
     }
 
-    fn child_nodes(&self) -> XmlNodeList {
-        XmlNodeList {
-            node: self.as_node(),
-        }
+    pub fn html(&self) -> bool {
+        self.html
     }
 
-    fn first_child(&self) -> Option<XmlNode> {
-        None
+    pub fn set_repertoire(&mut self, value: Repertoire) {
+        self.repertoire = value;
     }
 
-    fn last_child(&self) -> Option<XmlNode> {
-        None
+    pub fn repertoire(&self) -> Repertoire {
+        self.repertoire
     }
 
-    fn previous_sibling(&self) -> Option<XmlNode> {
-        self.parent_node()
-            .as_ref()
-            .and_then(|parent| parent.previous_sibling_child(self.as_node()))
+    pub fn set_hex_char_ref(&mut self, value: bool) {
+        self.hex_char_ref = value;
     }
 
-    fn next_sibling(&self) -> Option<XmlNode> {
-        self.parent_node()
-            .as_ref()
-            .and_then(|parent| parent.next_sibling_child(self.as_node()))
+    pub fn hex_char_ref(&self) -> bool {
+        self.hex_char_ref
     }
 
-    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
-        None
+    pub fn into_inner(self) -> W {
+        self.writer
     }
 
-    fn owner_document(&self) -> Option<XmlDocument> {
-        Some(XmlDocument::from(self.data.borrow().owner()))
-    }
+    pub fn write_events(&mut self, events: impl Iterator<Item = Event>) -> io::Result<()> {
+        for event in events {
+            self.write(&event)?;
+        }
+        self.writer.flush()
+    }
+
+    pub fn write(&mut self, event: &Event) -> io::Result<()> {
+        match event {
+            Event::StartDocument | Event::EndDocument => return Ok(()),
+            Event::StartElement(name, attributes) => {
+                self.close_tag()?;
+                self.newline()?;
+                self.check_repertoire(name)?;
+                write!(self.writer, "<{}", name)?;
+                for (name, value) in attributes {
+                    self.check_repertoire(name)?;
+                    if self.html && html::is_boolean_attribute(name, value) {
+                        write!(self.writer, " {}", name)?;
+                    } else {
+                        let value = self.char_ref(escape_attribute(value));
+                        write!(self.writer, " {}=\"{}\"", name, value)?;
+                    }
+                }
+                self.stack.push((name.clone(), false));
+                self.tag_opened = true;
+            }
+            Event::EndElement(name) => {
+                let (_, inline) = match self.stack.pop() {
+                    Some(v) if &v.0 == name => v,
+                    _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, name.as_str())),
+                };
+
+                if self.tag_opened && self.html {
+                    self.tag_opened = false;
+                    if html::is_void_element(name) {
+                        write!(self.writer, ">")?;
+                    } else {
+                        write!(self.writer, "></{}>", name)?;
+                    }
+                } else if self.tag_opened {
+                    self.tag_opened = false;
+                    write!(self.writer, " />")?;
+                } else {
+                    if !inline {
+                        self.newline()?;
+                    }
+                    write!(self.writer, "</{}>", name)?;
+                }
+            }
+            Event::Text(data) => {
+                if self.pretty && !self.inline() && data.trim().is_empty() {
+                    return Ok(());
+                }
+                self.close_tag()?;
+                self.set_inline();
+                if self.html && self.raw_text() {
+                    self.check_repertoire(data)?;
+                    write!(self.writer, "{}", data)?;
+                } else {
+                    write!(self.writer, "{}", self.char_ref(escape_text(data)))?;
+                }
+            }
+            Event::EntityReference(name) => {
+                self.close_tag()?;
+                self.set_inline();
+                if name.starts_with("&#") {
+                    write!(self.writer, "{}", name)?;
+                } else {
+                    write!(self.writer, "&{};", name)?;
+                }
+            }
+            Event::CData(data) if self.html => {
+                self.close_tag()?;
+                self.set_inline();
+                write!(self.writer, "{}", self.char_ref(escape_text(data)))?;
+            }
+            Event::CData(data) => {
+                self.close_tag()?;
+                self.newline()?;
+                write!(self.writer, "<![CDATA[{}]]>", self.cdata_char_ref(data))?;
+            }
+            Event::PI(target, data) => {
+                self.close_tag()?;
+                self.newline()?;
+                self.check_repertoire(target)?;
+                self.check_repertoire(data)?;
+                if data.is_empty() {
+                    write!(self.writer, "<?{}?>", target)?;
+                } else {
+                    write!(self.writer, "<?{} {}?>", target, data)?;
+                }
+            }
+            Event::Comment(data) => {
+                self.close_tag()?;
+                self.newline()?;
+                self.check_repertoire(data)?;
+                write!(self.writer, "<!--{}-->", data)?;
+            }
+        }
 
-    fn has_child(&self) -> bool {
-        false
+        self.written = true;
+        Ok(())
     }
-}
 
-impl NodeMut for XmlComment {
-    fn set_node_value(&self, value: &str) -> error::Result<()> {
-        self.set_data(value)
+    fn close_tag(&mut self) -> io::Result<()> {
+        if self.tag_opened {
+            self.tag_opened = false;
+            write!(self.writer, ">")?;
+        }
+        Ok(())
     }
 
-    fn insert_before(&self, _: XmlNode, _: Option<&XmlNode>) -> error::Result<XmlNode> {
-        Err(error::DomException::HierarchyRequestErr)?
+    fn char_ref(&self, value: String) -> String {
+        if value.chars().all(|c| self.repertoire.contains(c)) {
+            return value;
+        }
+
+        let mut escaped = String::new();
+        for c in value.chars() {
+            if self.repertoire.contains(c) {
+                escaped.push(c);
+            } else if self.hex_char_ref {
+                escaped.push_str(format!("&#x{:X};", c as u32).as_str());
+            } else {
+                escaped.push_str(format!("&#{};", c as u32).as_str());
+            }
+        }
+        escaped
     }
 
-    fn remove_child(&self, _: &XmlNode) -> error::Result<XmlNode> {
-        Err(error::DomException::HierarchyRequestErr)?
+    fn cdata_char_ref(&self, value: &str) -> String {
+        let mut escaped = String::new();
+        for c in escape_cdata(value).chars() {
+            if self.repertoire.contains(c) {
+                escaped.push(c);
+            } else {
+                let c = self.char_ref(c.to_string());
+                escaped.push_str(format!("]]>{}<![CDATA[", c).as_str());
+            }
+        }
+        escaped
     }
-}
 
-impl AsNode for XmlComment {
-    fn as_node(&self) -> XmlNode {
-        XmlNode::Comment(self.clone())
+    fn check_repertoire(&self, value: &str) -> io::Result<()> {
+        match value.chars().find(|c| !self.repertoire.contains(*c)) {
+            Some(c) => {
+                let message = format!("character U+{:04X} cannot be referenced here", c as u32);
+                Err(io::Error::new(io::ErrorKind::InvalidData, message))
+            }
+            None => Ok(()),
+        }
     }
-}
 
-impl AsStringValue for XmlComment {
-    fn as_string_value(&self) -> error::Result<String> {
-        self.data()
+    fn inline(&self) -> bool {
+        self.stack.last().map(|v| v.1).unwrap_or_default()
     }
-}
 
-impl PrettyPrint for XmlComment {
-    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
-        self.data.borrow().indented(0, f)
+    fn raw_text(&self) -> bool {
+        self.stack
+            .last()
+            .map(|v| html::is_raw_text_element(v.0.as_str()))
+            .unwrap_or_default()
     }
-}
 
-impl From<info::XmlNode<info::XmlComment>> for XmlComment {
-    fn from(value: info::XmlNode<info::XmlComment>) -> Self {
-        XmlComment { data: value }
+    fn set_inline(&mut self) {
+        if let Some(v) = self.stack.last_mut() {
+            v.1 = true;
+        }
     }
-}
 
-impl fmt::Debug for XmlComment {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "XmlComment {{ {} }}", self.data.borrow().comment())
+    fn newline(&mut self) -> io::Result<()> {
+        if self.pretty && self.written && !self.inline() {
+            write!(self.writer, "\n{}", " ".repeat(self.stack.len() * 4))?;
+        }
+        Ok(())
     }
 }
 
-impl fmt::Display for XmlComment {
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone)]
+pub struct XmlNamedNodeMap<T>
+where
+    T: Node + Clone,
+{
+    node: XmlNode,
+    source: XmlNamedNodeSource,
+    item: PhantomData<T>,
+}
+
+impl<T> NamedNodeMap<T> for XmlNamedNodeMap<T>
+where
+    T: Node + Clone + convert::TryFrom<XmlNode>,
+{
+    fn get_named_item(&self, name: &str) -> Option<T> {
+        let nodes = self.nodes();
+        nodes.into_iter().find(|v| v.0 == name).map(|v| v.1)
+    }
+
+    fn item(&self, index: usize) -> Option<T> {
+        let nodes = self.nodes();
+        nodes.into_iter().nth(index).map(|v| v.1)
+    }
+
+    fn length(&self) -> usize {
+        self.source.items(&self.node).len()
+    }
+}
+
+impl<T> NamedNodeMapMut<T> for XmlNamedNodeMap<T>
+where
+    T: Node + Clone + AsNode + convert::TryFrom<XmlNode>,
+{
+    fn set_named_item(&self, arg: T) -> error::Result<Option<T>> {
+        let name = arg.node_name();
+        if let Ok(v) = self.remove_named_item(name.as_str()) {
+            self.source.add(&self.node, arg.as_node())?; // FIXME: revert on failed.
+            Ok(Some(v))
+        } else {
+            self.source.add(&self.node, arg.as_node())?;
+            Ok(None)
+        }
+    }
+
+    fn remove_named_item(&self, name: &str) -> error::Result<T> {
+        let node = self.source.remove(&self.node, name)?;
+        T::try_from(node).map_err(|_| error::DomException::HierarchyRequestErr.into())
+    }
+}
+
+impl<T> PartialEq<XmlNamedNodeMap<T>> for XmlNamedNodeMap<T>
+where
+    T: Node + Clone,
+{
+    fn eq(&self, other: &XmlNamedNodeMap<T>) -> bool {
+        let s = self.source.items(&self.node);
+        let o = other.source.items(&other.node);
+        s.eq(&o)
+    }
+}
+
+impl<T> fmt::Debug for XmlNamedNodeMap<T>
+where
+    T: Node + Clone + convert::TryFrom<XmlNode> + fmt::Debug,
+{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        self.data.borrow().fmt(f)
+        let s = self.nodes();
+        s.fmt(f)
     }
 }
 
-// -----------------------------------------------------------------------------------------------
+impl<T> XmlNamedNodeMap<T>
+where
+    T: Node + Clone + convert::TryFrom<XmlNode>,
+{
+    pub fn iter(&self) -> XmlNamedNodeIter<T> {
+        let nodes = self.nodes();
+        XmlNamedNodeIter { nodes, index: 0 }
+    }
 
-#[derive(Clone, PartialEq)]
-pub struct XmlCDataSection {
-    data: info::XmlNode<info::XmlCData>,
+    fn nodes(&self) -> Vec<(String, T)> {
+        self.source
+            .items(&self.node)
+            .into_iter()
+            .filter_map(|(k, v)| T::try_from(v).ok().map(|v| (k, v)))
+            .collect()
+    }
 }
 
-impl CDataSection for XmlCDataSection {}
+impl<T> XmlNamedNodeMap<T>
+where
+    T: Node + Clone,
+{
+    fn new(node: XmlNode, source: XmlNamedNodeSource) -> Self {
+        XmlNamedNodeMap {
+            node,
+            source,
+            item: PhantomData,
+        }
+    }
+}
 
-impl Text for XmlCDataSection {}
+impl XmlNamedNodeMap<XmlAttr> {
+    pub fn get_named_item_ns(
+        &self,
+        namespace_uri: Option<&str>,
+        local_name: &str,
+    ) -> Option<XmlAttr> {
+        self.iter()
+            .find(|v| v.local_name() == local_name && v.namespace_uri().as_deref() == namespace_uri)
+    }
 
-impl TextMut for XmlCDataSection {
-    fn split_text(&self, offset: usize) -> error::Result<XmlCDataSection> {
-        if self.length() < offset {
-            return Err(error::DomException::IndexSizeErr)?;
+    pub fn set_named_item_ns(&self, arg: XmlAttr) -> error::Result<Option<XmlAttr>> {
+        let element = self.node.as_element().unwrap();
+        if !same_document(element.owner_document(), arg.owner_document()) {
+            return Err(error::DomException::WrongDocumentErr)?;
         }
 
-        let v = self.data.borrow().parent()?;
+        if element.owns_attribute(&arg)? {
+            return Ok(Some(arg));
+        }
 
-        let data2 = self.data.borrow_mut().split_at(offset);
-        let data2_node: Rc<info::XmlItem> = Rc::new(data2.clone().into());
+        element.bind_declared_namespace(&arg)?;
+        let namespace_uri = match arg.attribute.borrow().prefix() {
+            Some(prefix) => element.element.borrow().lookup_namespace_uri(prefix)?,
+            None => None,
+        };
+        let index = element
+            .element
+            .borrow()
+            .attribute_index_ns(namespace_uri.as_deref(), arg.local_name().as_str());
 
-        let inserted = v
+        let attr = element
+            .element
+            .borrow_mut()
+            .replace_attribute_at(index, Rc::new(arg.attribute.into()))
+            .and_then(|v| v.as_attribute());
+
+        Ok(attr.map(XmlAttr::from))
+    }
+
+    pub fn remove_named_item_ns(
+        &self,
+        namespace_uri: Option<&str>,
+        local_name: &str,
+    ) -> error::Result<XmlAttr> {
+        let element = self.node.as_element().unwrap();
+        let index = element
+            .element
             .borrow()
-            .insert_after(data2_node.clone(), self.data.borrow().id());
+            .attribute_index_ns(namespace_uri, local_name)
+            .ok_or(error::DomException::NotFoundErr)?;
 
-        match inserted {
-            Ok(_) => {}
-            Err(info::error::Error::OufOfIndex(_)) => {
-                v.borrow().append(data2_node)?;
+        let attr = element.element.borrow_mut().remove_attribute_at(index);
+        Ok(XmlAttr::from(attr.as_attribute().unwrap()))
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum XmlNamedNodeSource {
+    ElementAttributes,
+    DoctypeEntities,
+    DoctypeNotations,
+}
+
+impl XmlNamedNodeSource {
+    fn items(&self, node: &XmlNode) -> Vec<(String, XmlNode)> {
+        match self {
+            XmlNamedNodeSource::ElementAttributes => node
+                .as_element()
+                .unwrap()
+                .element
+                .borrow()
+                .attributes()
+                .iter()
+                .map(XmlAttr::from)
+                .map(|v| (v.name(), v.as_node()))
+                .collect(),
+            XmlNamedNodeSource::DoctypeEntities => node
+                .as_doctype()
+                .unwrap()
+                .declaration
+                .borrow()
+                .entities()
+                .iter()
+                .cloned()
+                .map(XmlEntity::from)
+                .map(|v| (v.node_name(), v.as_node()))
+                .collect(),
+            XmlNamedNodeSource::DoctypeNotations => node
+                .as_doctype()
+                .unwrap()
+                .declaration
+                .borrow()
+                .notations()
+                .iter()
+                .cloned()
+                .map(XmlNotation::from)
+                .map(|v| (v.node_name(), v.as_node()))
+                .collect(),
+        }
+    }
+
+    fn add(&self, node: &XmlNode, item: XmlNode) -> error::Result<Option<XmlNode>> {
+        match self {
+            XmlNamedNodeSource::ElementAttributes => {
+                let element = node.as_element().unwrap();
+                let attr = XmlAttr::try_from(item)?;
+                let attr = element.set_attribute_node(attr)?;
+                Ok(attr.map(|v| v.as_node()))
             }
-            Err(e) => {
-                return Err(error::Error::from(e));
+            XmlNamedNodeSource::DoctypeEntities | XmlNamedNodeSource::DoctypeNotations => {
+                Err(error::DomException::NoModificationAllowedErr)?
             }
         }
+    }
 
-        Ok(XmlCDataSection::from(data2))
+    fn remove(&self, node: &XmlNode, name: &str) -> error::Result<XmlNode> {
+        match self {
+            XmlNamedNodeSource::ElementAttributes => {
+                let element = node.as_element().unwrap();
+                if let Some(attr) = element.get_attribute_node(name) {
+                    element.remove_attribute(name)?;
+                    Ok(attr.as_node())
+                } else {
+                    Err(error::DomException::NotFoundErr)?
+                }
+            }
+            XmlNamedNodeSource::DoctypeEntities | XmlNamedNodeSource::DoctypeNotations => {
+                Err(error::DomException::NoModificationAllowedErr)?
+            }
+        }
     }
 }
 
-impl CharacterData for XmlCDataSection {
-    fn data(&self) -> error::Result<String> {
-        Ok(self.data.borrow().character_code().to_string())
+// -----------------------------------------------------------------------------------------------
+
+pub struct XmlNamedNodeIter<T>
+where
+    T: Node + Clone,
+{
+    nodes: Vec<(String, T)>,
+    index: usize,
+}
+
+impl<T> Iterator for XmlNamedNodeIter<T>
+where
+    T: Node + Clone,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.nodes.get(self.index);
+        self.index += 1;
+        item.cloned().map(|v| v.1)
     }
+}
 
-    fn length(&self) -> usize {
-        self.data.borrow().len()
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, PartialEq)]
+pub struct XmlAttr {
+    attribute: info::XmlNode<info::XmlAttribute>,
+}
+
+impl Attr for XmlAttr {
+    fn name(&self) -> String {
+        self.attribute.borrow().local_name().to_string()
     }
 
-    fn substring_data(&self, offset: usize, count: usize) -> error::Result<String> {
-        if self.length() < offset {
-            Err(error::DomException::IndexSizeErr)?
-        } else {
-            Ok(self.data.borrow().substring(offset..(offset + count)))
-        }
+    fn specified(&self) -> bool {
+        self.attribute.borrow().owner_element().is_ok()
     }
-}
 
-impl CharacterDataMut for XmlCDataSection {
-    fn insert_data(&self, offset: usize, arg: &str) -> error::Result<()> {
-        if self.length() < offset {
-            Err(error::DomException::IndexSizeErr)?
-        } else {
-            self.data.borrow_mut().insert(offset, arg)?;
-            Ok(())
-        }
+    fn value(&self) -> error::Result<String> {
+        Ok(self.attribute.borrow().normalized_value()?)
     }
 
-    fn delete_data(&self, offset: usize, count: usize) -> error::Result<()> {
-        if self.length() < (offset + count) {
-            Err(error::DomException::IndexSizeErr)?
-        } else {
-            self.data.borrow_mut().delete(offset, count);
-            Ok(())
-        }
+    fn owner_element(&self) -> Option<XmlElement> {
+        let element = self.attribute.borrow().owner_element().ok()?;
+        Some(XmlElement { element })
     }
 }
 
-impl Node for XmlCDataSection {
+impl AttrMut for XmlAttr {}
+
+impl Node for XmlAttr {
     fn node_name(&self) -> String {
-        "#cdata-section".to_string()
+        self.name()
     }
 
     fn node_value(&self) -> error::Result<Option<String>> {
-        Ok(Some(self.data()?))
+        Ok(Some(self.value()?))
     }
 
     fn node_type(&self) -> NodeType {
-        NodeType::CData
+        NodeType::Attribute
     }
 
     fn parent_node(&self) -> Option<XmlNode> {
-        self.data
-            .borrow()
-            .parent()
-            .ok()
-            .map(XmlElement::from)
-            .map(|v| v.as_node())
+        None
     }
 
     fn child_nodes(&self) -> XmlNodeList {
-        XmlNodeList {
-            node: self.as_node(),
-        }
+        XmlNodeList::from(self.as_node())
     }
 
     fn first_child(&self) -> Option<XmlNode> {
-        None
+        self.first_child_node()
     }
 
     fn last_child(&self) -> Option<XmlNode> {
-        None
+        self.last_child_node()
     }
 
     fn previous_sibling(&self) -> Option<XmlNode> {
-        self.parent_node()
-            .as_ref()
-            .and_then(|parent| parent.previous_sibling_child(self.as_node()))
+        None
     }
 
     fn next_sibling(&self) -> Option<XmlNode> {
-        self.parent_node()
-            .as_ref()
-            .and_then(|parent| parent.next_sibling_child(self.as_node()))
+        None
     }
 
     fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
@@ -2586,144 +5098,314 @@ impl Node for XmlCDataSection {
     }
 
     fn owner_document(&self) -> Option<XmlDocument> {
-        Some(XmlDocument::from(self.data.borrow().owner()))
+        self.attribute.borrow().owner().ok().map(XmlDocument::from)
     }
 
     fn has_child(&self) -> bool {
-        false
+        self.has_child_node()
     }
-}
 
-impl NodeMut for XmlCDataSection {
-    fn set_node_value(&self, value: &str) -> error::Result<()> {
-        self.set_data(value)
+    fn namespace_uri(&self) -> Option<String> {
+        XmlAttr::namespace_uri(self)
     }
 
-    fn insert_before(&self, _: XmlNode, _: Option<&XmlNode>) -> error::Result<XmlNode> {
-        Err(error::DomException::HierarchyRequestErr)?
+    fn prefix(&self) -> Option<String> {
+        XmlAttr::prefix(self)
     }
 
-    fn remove_child(&self, _: &XmlNode) -> error::Result<XmlNode> {
-        Err(error::DomException::HierarchyRequestErr)?
+    fn local_name(&self) -> Option<String> {
+        Some(XmlAttr::local_name(self))
     }
-}
-
-impl AsNode for XmlCDataSection {
-    fn as_node(&self) -> XmlNode {
-        XmlNode::CData(self.clone())
+    fn clone_node(&self, deep: bool) -> error::Result<XmlNode> {
+        duplicate_node(&self.as_node(), deep)
+    }
+    fn lookup_namespace_uri(&self, prefix: Option<&str>) -> Option<String> {
+        self.owner_element()?.lookup_namespace_uri(prefix)
     }
-}
 
-impl AsStringValue for XmlCDataSection {
-    fn as_string_value(&self) -> error::Result<String> {
-        self.data()
+    fn lookup_prefix(&self, namespace_uri: &str) -> Option<String> {
+        self.owner_element()?.lookup_prefix(namespace_uri)
     }
 }
 
-impl PrettyPrint for XmlCDataSection {
+impl NodeMut for XmlAttr {
+    fn set_node_value(&self, value: &str) -> error::Result<()> {
+        self.attribute
+            .borrow()
+            .set_values(escape_attribute(value).as_str())?;
+        Ok(())
+    }
+
+    fn insert_before(
+        &self,
+        new_child: XmlNode,
+        ref_child: Option<&XmlNode>,
+    ) -> error::Result<XmlNode> {
+        if !same_document(self.owner_document(), new_child.owner_document()) {
+            return Err(error::DomException::WrongDocumentErr)?;
+        }
+
+        let value = if let Some(r) = ref_child {
+            if !same_document(self.owner_document(), r.owner_document()) {
+                return Err(error::DomException::WrongDocumentErr)?;
+            }
+
+            match self
+                .attribute
+                .borrow()
+                .insert_before(new_child.try_into()?, r.id())
+            {
+                Ok(v) => Ok(v),
+                Err(xml_info::error::Error::OufOfIndex(_)) => Err(error::DomException::NotFoundErr),
+                _ => Err(error::DomException::HierarchyRequestErr),
+            }?
+        } else {
+            self.attribute
+                .borrow()
+                .append(new_child.try_into()?)
+                .map_err(|_| error::DomException::HierarchyRequestErr)?
+        };
+
+        XmlNode::try_from(value)
+    }
+
+    fn remove_child(&self, old_child: &XmlNode) -> error::Result<XmlNode> {
+        if !same_document(self.owner_document(), old_child.owner_document()) {
+            return Err(error::DomException::WrongDocumentErr)?;
+        }
+
+        match self.attribute.borrow().delete(old_child.id()) {
+            Some(v) => XmlNode::try_from(v),
+            _ => Err(error::DomException::NotFoundErr)?,
+        }
+    }
+    fn set_prefix(&self, prefix: Option<&str>) -> error::Result<()> {
+        let namespace_uri = XmlAttr::namespace_uri(self);
+        let namespace_uri = match (prefix, namespace_uri) {
+            (Some(prefix), Some(uri)) => {
+                check_prefix(prefix, uri.as_str())?;
+                uri
+            }
+            _ => return Err(error::DomException::NamespaceErr.into()),
+        };
+        let xmlns = info::NamespaceUri::xmlns();
+        if namespace_uri == xmlns.value() || prefix == Some("xmlns") {
+            return Err(error::DomException::NamespaceErr.into());
+        }
+
+        let owner = self
+            .attribute
+            .borrow()
+            .owner_element()
+            .ok()
+            .map(|element| XmlElement { element });
+        if let (Some(element), Some(prefix)) = (owner.as_ref(), prefix) {
+            let bound = element.element.borrow().lookup_namespace_uri(prefix)?;
+            match bound {
+                Some(v) if *v == *namespace_uri => {}
+                Some(_) => return Err(error::DomException::NamespaceErr.into()),
+                None => element.declare_namespace_prefix(prefix, namespace_uri.as_str())?,
+            }
+        }
+
+        let old = self.prefix();
+        self.attribute.borrow_mut().set_prefix(prefix);
+        if let (Some(element), Some(old)) = (owner, old.as_deref().filter(|v| Some(*v) != prefix)) {
+            element.release_namespace_prefix(old);
+        }
+        Ok(())
+    }
+}
+
+impl AsNode for XmlAttr {
+    fn as_node(&self) -> XmlNode {
+        XmlNode::Attribute(self.clone())
+    }
+}
+
+impl AsExpandedName for XmlAttr {
+    fn as_expanded_name(&self) -> error::Result<Option<QName>> {
+        let local_name = self.attribute.borrow().local_name().to_string();
+        let prefix = self.attribute.borrow().prefix().map(|v| v.to_string());
+        let ns = if let Ok(element) = self.attribute.borrow().owner_element() {
+            let key = prefix.as_deref().unwrap_or("xmlns");
+            let namespaces = XmlElement::from(element).in_scope_namespace()?;
+            if let Some(ns) = namespaces.iter().find(|v| v.node_name() == key) {
+                ns.node_value()?
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+        Ok(Some(QName::new(
+            local_name.as_str(),
+            prefix.as_deref(),
+            ns.as_deref(),
+        )))
+    }
+}
+
+impl AsStringValue for XmlAttr {
+    fn as_string_value(&self) -> error::Result<String> {
+        self.value()
+    }
+}
+
+impl PrettyPrint for XmlAttr {
     fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
-        self.data.borrow().indented(0, f)
+        self.attribute.borrow().indented(0, f)
     }
 }
 
-impl From<info::XmlNode<info::XmlCData>> for XmlCDataSection {
-    fn from(value: info::XmlNode<info::XmlCData>) -> Self {
-        XmlCDataSection { data: value }
+impl HasChild for XmlAttr {
+    fn child_at(&self, index: usize) -> Option<XmlNode> {
+        let attribute = self.attribute.borrow();
+        let values = attribute.values();
+        let node = match values.borrow().get(index)? {
+            info::XmlAttributeValue::Char(v) => XmlNode::try_from(v.clone()).ok()?,
+            info::XmlAttributeValue::Entity(v) => XmlNode::try_from(v.clone()).ok()?,
+            info::XmlAttributeValue::Text(v) => XmlNode::try_from(v.clone()).ok()?,
+        };
+        Some(node)
     }
 }
 
-impl fmt::Debug for XmlCDataSection {
+impl XmlAttr {
+    pub fn set_type_annotation(&self, value: Option<schema::SchemaType>) {
+        let context = self.attribute.borrow().context().clone();
+        context.set_type_annotation(self.attribute.borrow().id(), value.map(|v| v.name()));
+    }
+
+    pub fn resolve_uri(&self) -> error::Result<String> {
+        let reference = uri::iri_to_uri(trim_whitespace(self.value()?.as_str()));
+        let base_uri = self.as_node().base_uri().unwrap_or_default();
+        Ok(uri::resolve(base_uri.as_str(), reference.as_str()))
+    }
+
+    pub fn local_name(&self) -> String {
+        self.attribute.borrow().local_name().to_string()
+    }
+
+    pub fn prefix(&self) -> Option<String> {
+        self.attribute.borrow().prefix().map(|v| v.to_string())
+    }
+
+    pub fn namespace_uri(&self) -> Option<String> {
+        let namespace_uri = self.attribute.borrow().namespace_name().ok()?;
+        namespace_uri.map(|v| v.to_string())
+    }
+}
+
+impl From<info::XmlNode<info::XmlAttribute>> for XmlAttr {
+    fn from(value: info::XmlNode<info::XmlAttribute>) -> Self {
+        XmlAttr { attribute: value }
+    }
+}
+
+impl fmt::Debug for XmlAttr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(
-            f,
-            "XmlCDataSection {{ {} }}",
-            self.data.borrow().character_code()
-        )
+        write!(f, "XmlAttr {{ {} }}", self.name())
     }
 }
 
-impl fmt::Display for XmlCDataSection {
+impl fmt::Display for XmlAttr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        self.data.borrow().fmt(f)
+        self.attribute.borrow().fmt(f)
     }
 }
 
 // -----------------------------------------------------------------------------------------------
 
 #[derive(Clone, PartialEq)]
-pub struct XmlDocumentType {
-    declaration: info::XmlNode<info::XmlDocumentTypeDeclaration>,
+pub struct XmlElement {
+    element: info::XmlNode<info::XmlElement>,
 }
 
-impl DocumentType for XmlDocumentType {
-    fn name(&self) -> String {
-        self.declaration.borrow().local_name().to_string()
+impl Element for XmlElement {
+    fn tag_name(&self) -> String {
+        self.element.borrow().local_name().to_string()
     }
 
-    fn entities(&self) -> XmlNamedNodeMap<XmlEntity> {
-        fn get(node: &XmlNode) -> Vec<(String, XmlEntity)> {
-            node.as_doctype()
-                .unwrap()
-                .declaration
-                .borrow()
-                .entities()
-                .iter()
-                .cloned()
-                .map(XmlEntity::from)
-                .map(|v| (v.node_name(), v))
-                .collect()
+    fn get_attribute(&self, name: &str) -> String {
+        let attr = self.get_attribute_node(name);
+        if let Some(attr) = attr {
+            // FIXME:
+            attr.value().unwrap()
+        } else {
+            String::new()
         }
+    }
 
-        fn add(_: &XmlNode, _: XmlEntity) -> error::Result<Option<XmlEntity>> {
-            Err(error::DomException::NoModificationAllowedErr)?
-        }
+    fn get_attribute_node(&self, name: &str) -> Option<XmlAttr> {
+        self.element
+            .borrow()
+            .attributes()
+            .iter()
+            .find(|v| v.borrow().local_name() == name)
+            .map(XmlAttr::from)
+    }
 
-        fn remove(_: &XmlNode, _: &str) -> error::Result<XmlEntity> {
-            Err(error::DomException::NoModificationAllowedErr)?
-        }
+    fn get_elements_by_tag_name(&self, tag_name: &str) -> XmlElementList {
+        XmlElementList::new(self.as_node(), tag_name, None)
+    }
 
-        XmlNamedNodeMap {
-            node: self.as_node(),
-            get: Box::new(get),
-            add: Box::new(add),
-            remove: Box::new(remove),
-        }
+    fn has_attribute_ns(&self, namespace_uri: Option<&str>, local_name: &str) -> bool {
+        self.get_attribute_node_ns(namespace_uri, local_name)
+            .is_some()
     }
+}
 
-    fn notations(&self) -> XmlNamedNodeMap<XmlNotation> {
-        fn get(node: &XmlNode) -> Vec<(String, XmlNotation)> {
-            node.as_doctype()
-                .unwrap()
-                .declaration
-                .borrow()
-                .notations()
-                .iter()
-                .cloned()
-                .map(XmlNotation::from)
-                .map(|v| (v.node_name(), v))
-                .collect()
-        }
+impl ElementMut for XmlElement {
+    fn set_attribute(&self, name: &str, value: &str) -> error::Result<()> {
+        let attr = self
+            .owner_document()
+            .ok_or(error::DomException::WrongDocumentErr)?
+            .create_attribute(name)?;
+        attr.set_value(value)?;
+        self.set_attribute_node(attr)?;
+        Ok(())
+    }
+
+    fn remove_attribute(&self, name: &str) -> error::Result<()> {
+        self.element.borrow_mut().remove_attribute(name);
+        Ok(())
+    }
 
-        fn add(_: &XmlNode, _: XmlNotation) -> error::Result<Option<XmlNotation>> {
-            Err(error::DomException::NoModificationAllowedErr)?
+    fn set_attribute_node(&self, new_attr: XmlAttr) -> error::Result<Option<XmlAttr>> {
+        if !same_document(self.owner_document(), new_attr.owner_document()) {
+            return Err(error::DomException::WrongDocumentErr)?;
         }
 
-        fn remove(_: &XmlNode, _: &str) -> error::Result<XmlNotation> {
-            Err(error::DomException::NoModificationAllowedErr)?
+        if self.owns_attribute(&new_attr)? {
+            return Ok(Some(new_attr));
         }
 
-        XmlNamedNodeMap {
-            node: self.as_node(),
-            get: Box::new(get),
-            add: Box::new(add),
-            remove: Box::new(remove),
+        self.bind_declared_namespace(&new_attr)?;
+        let attr = self
+            .element
+            .borrow_mut()
+            .replace_attribute(Rc::new(new_attr.attribute.into()))
+            .and_then(|v| v.as_attribute());
+
+        Ok(attr.map(XmlAttr::from))
+    }
+
+    fn normalize(&self) {
+        let mut elements = vec![self.clone()];
+        while let Some(element) = elements.pop() {
+            // Only text children are merged or removed, which does not fail on a live element.
+            let _ = merge_text_nodes(&element.as_node(), true);
+            elements.extend(element.child_nodes().iter().filter_map(|v| v.as_element()));
         }
     }
 }
 
-impl Node for XmlDocumentType {
+impl ElementTraversal for XmlElement {}
+
+impl Node for XmlElement {
     fn node_name(&self) -> String {
-        self.name()
+        self.tag_name()
     }
 
     fn node_value(&self) -> error::Result<Option<String>> {
@@ -2731,25 +5413,27 @@ impl Node for XmlDocumentType {
     }
 
     fn node_type(&self) -> NodeType {
-        NodeType::DocumentType
+        NodeType::Element
     }
 
     fn parent_node(&self) -> Option<XmlNode> {
-        Some(XmlDocument::from(self.declaration.borrow().parent()).as_node())
+        self.element
+            .borrow()
+            .parent()
+            .ok()
+            .and_then(|v| XmlNode::try_from(v).ok())
     }
 
     fn child_nodes(&self) -> XmlNodeList {
-        XmlNodeList {
-            node: self.as_node(),
-        }
+        XmlNodeList::from(self.as_node())
     }
 
     fn first_child(&self) -> Option<XmlNode> {
-        None
+        self.first_child_node()
     }
 
     fn last_child(&self) -> Option<XmlNode> {
-        None
+        self.last_child_node()
     }
 
     fn previous_sibling(&self) -> Option<XmlNode> {
@@ -2765,617 +5449,974 @@ impl Node for XmlDocumentType {
     }
 
     fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
-        None
+        Some(XmlNamedNodeMap::new(
+            self.as_node(),
+            XmlNamedNodeSource::ElementAttributes,
+        ))
     }
 
     fn owner_document(&self) -> Option<XmlDocument> {
-        Some(XmlDocument::from(self.declaration.borrow().parent()))
+        self.element.borrow().owner().ok().map(XmlDocument::from)
     }
 
     fn has_child(&self) -> bool {
-        false
+        self.has_child_node()
     }
-}
 
-impl AsNode for XmlDocumentType {
-    fn as_node(&self) -> XmlNode {
-        XmlNode::DocumentType(self.clone())
+    fn namespace_uri(&self) -> Option<String> {
+        let namespace_uri = self.element.borrow().namespace_name().ok()?;
+        namespace_uri.map(|v| v.to_string())
     }
-}
 
-impl PrettyPrint for XmlDocumentType {
-    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
-        self.declaration.borrow().indented(0, f)
+    fn prefix(&self) -> Option<String> {
+        XmlElement::prefix(self)
     }
-}
 
-impl From<info::XmlNode<info::XmlDocumentTypeDeclaration>> for XmlDocumentType {
-    fn from(value: info::XmlNode<info::XmlDocumentTypeDeclaration>) -> Self {
-        XmlDocumentType { declaration: value }
+    fn local_name(&self) -> Option<String> {
+        Some(self.element.borrow().local_name().to_string())
     }
-}
-
-impl fmt::Debug for XmlDocumentType {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "XmlDocumentType {{ {} }}", self.name())
+    fn clone_node(&self, deep: bool) -> error::Result<XmlNode> {
+        duplicate_node(&self.as_node(), deep)
     }
-}
-
-impl fmt::Display for XmlDocumentType {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        self.declaration.borrow().fmt(f)
+    fn lookup_namespace_uri(&self, prefix: Option<&str>) -> Option<String> {
+        let prefix = match prefix {
+            Some("xmlns") => return Some(info::NamespaceUri::xmlns().value().to_string()),
+            Some(v) => v,
+            None => "xmlns",
+        };
+        let namespace_uri = self.element.borrow().lookup_namespace_uri(prefix).ok()??;
+        Some(namespace_uri.value().to_string()).filter(|v| !v.is_empty())
     }
-}
 
-// -----------------------------------------------------------------------------------------------
+    fn lookup_prefix(&self, namespace_uri: &str) -> Option<String> {
+        if namespace_uri.is_empty() {
+            return None;
+        }
 
-#[derive(Clone, PartialEq)]
-pub struct XmlNotation {
-    notation: info::XmlNode<info::XmlNotation>,
+        self.in_scope_namespace()
+            .ok()?
+            .into_iter()
+            .filter(|v| v.node_name() != "xmlns")
+            .find(|v| v.node_value().ok().flatten().as_deref() == Some(namespace_uri))
+            .map(|v| v.node_name())
+    }
 }
 
-impl Notation for XmlNotation {
-    fn public_id(&self) -> Option<String> {
-        self.notation
-            .borrow()
-            .public_identifier()
-            .map(|v| v.to_string())
+impl NodeMut for XmlElement {
+    fn set_node_value(&self, _: &str) -> error::Result<()> {
+        Err(error::DomException::NoDataAllowedErr)?
     }
 
-    fn system_id(&self) -> Option<String> {
-        self.notation
-            .borrow()
-            .system_identifier()
-            .map(|v| v.to_string())
-    }
-}
-
-impl Node for XmlNotation {
-    fn node_name(&self) -> String {
-        self.notation.borrow().name().to_string()
-    }
+    fn insert_before(
+        &self,
+        new_child: XmlNode,
+        ref_child: Option<&XmlNode>,
+    ) -> error::Result<XmlNode> {
+        if !same_document(self.owner_document(), new_child.owner_document()) {
+            return Err(error::DomException::WrongDocumentErr)?;
+        }
 
-    fn node_value(&self) -> error::Result<Option<String>> {
-        Ok(None)
-    }
+        let value = if let Some(r) = ref_child {
+            if !same_document(self.owner_document(), r.owner_document()) {
+                return Err(error::DomException::WrongDocumentErr)?;
+            }
 
-    fn node_type(&self) -> NodeType {
-        NodeType::Notation
-    }
+            match self
+                .element
+                .borrow()
+                .insert_before(new_child.try_into()?, r.id())
+            {
+                Ok(v) => Ok(v),
+                Err(xml_info::error::Error::OufOfIndex(_)) => Err(error::DomException::NotFoundErr),
+                _ => Err(error::DomException::HierarchyRequestErr),
+            }?
+        } else {
+            self.element
+                .borrow()
+                .append(new_child.try_into()?)
+                .map_err(|_| error::DomException::HierarchyRequestErr)?
+        };
 
-    fn parent_node(&self) -> Option<XmlNode> {
-        None
+        XmlNode::try_from(value)
     }
 
-    fn child_nodes(&self) -> XmlNodeList {
-        XmlNodeList {
-            node: self.as_node(),
+    fn remove_child(&self, old_child: &XmlNode) -> error::Result<XmlNode> {
+        if !same_document(self.owner_document(), old_child.owner_document()) {
+            return Err(error::DomException::WrongDocumentErr)?;
         }
-    }
 
-    fn first_child(&self) -> Option<XmlNode> {
-        None
+        match self.element.borrow().delete(old_child.id()) {
+            Some(v) => XmlNode::try_from(v),
+            _ => Err(error::DomException::NotFoundErr)?,
+        }
     }
+    fn set_prefix(&self, prefix: Option<&str>) -> error::Result<()> {
+        let namespace_uri = Node::namespace_uri(self).ok_or(error::DomException::NamespaceErr)?;
+        if let Some(prefix) = prefix {
+            check_prefix(prefix, namespace_uri.as_str())?;
+        }
+        if prefix == Some("xmlns") {
+            return Err(error::DomException::NamespaceErr.into());
+        }
 
-    fn last_child(&self) -> Option<XmlNode> {
-        None
-    }
+        let bound = self
+            .element
+            .borrow()
+            .lookup_namespace_uri(prefix.unwrap_or("xmlns"))?;
+        match (bound, prefix) {
+            (Some(v), _) if *v == *namespace_uri => {}
+            (None, Some(prefix)) => {
+                self.declare_namespace_prefix(prefix, namespace_uri.as_str())?
+            }
+            _ => return Err(error::DomException::NamespaceErr.into()),
+        }
 
-    fn previous_sibling(&self) -> Option<XmlNode> {
-        let parent = XmlNode::from(self.notation.borrow().parent());
-        parent.previous_sibling_child(self.as_node())
+        let old = self.prefix();
+        self.element.borrow_mut().set_prefix(prefix);
+        if let Some(old) = old.as_deref().filter(|v| Some(*v) != prefix) {
+            self.release_namespace_prefix(old);
+        }
+        Ok(())
     }
+}
 
-    fn next_sibling(&self) -> Option<XmlNode> {
-        let parent = XmlNode::from(self.notation.borrow().parent());
-        parent.next_sibling_child(self.as_node())
+impl AsNode for XmlElement {
+    fn as_node(&self) -> XmlNode {
+        XmlNode::Element(self.clone())
     }
+}
 
-    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
-        None
+impl AsExpandedName for XmlElement {
+    fn as_expanded_name(&self) -> error::Result<Option<QName>> {
+        let local_name = self.element.borrow().local_name().to_string();
+        let prefix = self.prefix();
+        let key = prefix.as_deref().unwrap_or("xmlns");
+        let namespaces = self.in_scope_namespace()?;
+        let ns = if let Some(ns) = namespaces.iter().find(|v| v.node_name() == key) {
+            ns.node_value()?
+        } else {
+            None
+        };
+        Ok(Some(QName::new(
+            local_name.as_str(),
+            prefix.as_deref(),
+            ns.as_deref(),
+        )))
     }
+}
 
-    fn owner_document(&self) -> Option<XmlDocument> {
-        Some(XmlDocument::from(self.notation.borrow().owner()))
+impl AsStringValue for XmlElement {
+    fn as_string_value(&self) -> error::Result<String> {
+        let mut s = String::new();
+        self.visit_descendants(|child| {
+            match child {
+                XmlNode::Attribute(_) => {}
+                XmlNode::CData(v) => s.push_str(&v.as_string_value()?),
+                XmlNode::Comment(_) => {}
+                XmlNode::Document(_) => {}
+                XmlNode::DocumentFragment(_) => {}
+                XmlNode::DocumentType(_) => {}
+                XmlNode::Element(_) => {}
+                XmlNode::Entity(_) => {}
+                XmlNode::EntityReference(_) => {}
+                XmlNode::Namespace(_) => {}
+                XmlNode::Notation(_) => {}
+                XmlNode::PI(_) => {}
+                XmlNode::ExpandedText(v) => s.push_str(&v.as_string_value()?),
+                XmlNode::Text(v) => s.push_str(&v.as_string_value()?),
+            }
+            Ok(())
+        })?;
+        Ok(s)
     }
+}
 
-    fn has_child(&self) -> bool {
-        false
+impl PrettyPrint for XmlElement {
+    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
+        self.element.borrow().indented(0, f)
     }
 }
 
-impl AsNode for XmlNotation {
-    fn as_node(&self) -> XmlNode {
-        XmlNode::Notation(self.clone())
+impl HasChild for XmlElement {
+    fn child_at(&self, index: usize) -> Option<XmlNode> {
+        self.element
+            .borrow()
+            .child_by_index(index)
+            .and_then(|v| XmlNode::try_from(v).ok())
     }
-}
 
-impl PrettyPrint for XmlNotation {
-    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
-        self.notation.borrow().indented(0, f)
+    fn text_expanded(&self) -> bool {
+        self.owner_document()
+            .map(|v| v.document.borrow().context().text_expanded())
+            .unwrap_or_default()
     }
 }
 
-impl From<info::XmlNode<info::XmlNotation>> for XmlNotation {
-    fn from(value: info::XmlNode<info::XmlNotation>) -> Self {
-        XmlNotation { notation: value }
+impl From<info::XmlNode<info::XmlElement>> for XmlElement {
+    fn from(value: info::XmlNode<info::XmlElement>) -> Self {
+        XmlElement { element: value }
     }
 }
 
-impl fmt::Debug for XmlNotation {
+impl fmt::Debug for XmlElement {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "XmlNotation {{ {} }}", self.node_name())
+        write!(f, "XmlElement {{ {} }}", self.node_name())
     }
 }
 
-impl fmt::Display for XmlNotation {
+impl fmt::Display for XmlElement {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        self.notation.borrow().fmt(f)
+        self.element.borrow().fmt(f)
     }
 }
 
-// -----------------------------------------------------------------------------------------------
-
-#[derive(Clone, PartialEq)]
-pub struct XmlEntity {
-    entity: info::XmlNode<info::XmlEntity>,
-}
-
-impl Entity for XmlEntity {
-    fn public_id(&self) -> Option<String> {
-        self.entity
-            .borrow()
-            .public_identifier()
-            .map(|v| v.to_string())
+impl XmlElement {
+    pub fn prefix(&self) -> Option<String> {
+        self.element.borrow().prefix().map(|v| v.to_string())
     }
 
-    fn system_id(&self) -> Option<String> {
-        self.entity
-            .borrow()
-            .system_identifier()
-            .map(|v| v.to_string())
+    pub fn descendants(&self) -> XmlDescendantIter {
+        self.as_node().descendants()
     }
 
-    fn notation_name(&self) -> Option<String> {
-        self.entity.borrow().notation_name().map(|v| v.to_string())
+    pub fn descendant_elements(&self) -> XmlDescendantElementIter {
+        self.as_node().descendant_elements()
     }
-}
 
-impl Node for XmlEntity {
-    fn node_name(&self) -> String {
-        self.entity.borrow().name().to_string()
+    pub fn ancestors(&self) -> XmlAncestorIter {
+        self.as_node().ancestors()
     }
 
-    fn node_value(&self) -> error::Result<Option<String>> {
-        Ok(None)
+    pub fn preceding_siblings(&self) -> XmlSiblingIter {
+        self.as_node().preceding_siblings()
     }
 
-    fn node_type(&self) -> NodeType {
-        NodeType::Entity
+    pub fn following_siblings(&self) -> XmlSiblingIter {
+        self.as_node().following_siblings()
     }
 
-    fn parent_node(&self) -> Option<XmlNode> {
-        None
+    pub fn in_scope_namespace(&self) -> error::Result<Vec<XmlNamespace>> {
+        Ok(self
+            .element
+            .borrow()
+            .in_scope_namespace()?
+            .iter()
+            .map(XmlNamespace::from)
+            .collect())
     }
 
-    fn child_nodes(&self) -> XmlNodeList {
-        XmlNodeList {
-            node: self.as_node(),
-        }
+    pub fn get_elements_by_tag_name_ns(
+        &self,
+        namespace_uri: &str,
+        local_name: &str,
+    ) -> XmlElementList {
+        XmlElementList::new(self.as_node(), local_name, Some(namespace_uri))
     }
 
-    fn first_child(&self) -> Option<XmlNode> {
-        self.first_child_node()
-    }
+    pub fn elements_by_language(&self, tag: &str) -> error::Result<Vec<XmlElement>> {
+        let mut elems = vec![];
 
-    fn last_child(&self) -> Option<XmlNode> {
-        self.last_child_node()
-    }
+        if language_matches(self.as_node().language().as_deref(), tag) {
+            elems.push(self.clone());
+        }
 
-    fn previous_sibling(&self) -> Option<XmlNode> {
-        let parent = self.entity.borrow().parent().map(XmlNode::from);
-        parent.and_then(|parent| parent.previous_sibling_child(self.as_node()))
-    }
+        self.visit_descendants(|child| {
+            if let XmlNode::Element(child) = child {
+                if language_matches(child.as_node().language().as_deref(), tag) {
+                    elems.push(child.clone());
+                }
+            }
+            Ok(())
+        })?;
 
-    fn next_sibling(&self) -> Option<XmlNode> {
-        let parent = self.entity.borrow().parent().map(XmlNode::from);
-        parent.and_then(|parent| parent.next_sibling_child(self.as_node()))
+        Ok(elems)
     }
 
-    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
-        None
+    pub fn text_trimmed(&self) -> error::Result<String> {
+        let mut s = String::new();
+        self.visit_descendants(|child| {
+            match child {
+                XmlNode::CData(v) => s.push_str(&v.data()?),
+                XmlNode::EntityReference(v) => s.push_str(&v.value()?),
+                XmlNode::ExpandedText(v) => s.push_str(&v.data()?),
+                XmlNode::Text(v) => s.push_str(&v.data()?),
+                _ => {}
+            }
+            Ok(())
+        })?;
+        Ok(trim_whitespace(s.as_str()).to_string())
     }
 
-    fn owner_document(&self) -> Option<XmlDocument> {
-        Some(XmlDocument::from(self.entity.borrow().owner()))
+    pub fn set_type_annotation(&self, value: Option<schema::SchemaType>) {
+        let context = self.element.borrow().context().clone();
+        context.set_type_annotation(self.element.borrow().id(), value.map(|v| v.name()));
     }
 
-    fn has_child(&self) -> bool {
-        !self.children().is_empty()
+    pub fn get_attribute_uri(&self, name: &str) -> error::Result<Option<String>> {
+        match self.get_attribute_node(name) {
+            Some(v) => v.resolve_uri().map(Some),
+            None => Ok(None),
+        }
     }
-}
 
-impl AsNode for XmlEntity {
-    fn as_node(&self) -> XmlNode {
-        XmlNode::Entity(self.clone())
+    pub fn append_element(&self, tag_name: &str) -> error::Result<XmlElement> {
+        let element = self
+            .owner_document()
+            .ok_or(error::DomException::WrongDocumentErr)?
+            .create_element(tag_name)?;
+        let element = self.append_child(element.as_node())?;
+        Ok(element.as_element().unwrap())
     }
-}
 
-impl HasChild for XmlEntity {
-    fn children(&self) -> Vec<XmlNode> {
-        // TODO:
-        vec![]
-    }
-}
+    pub fn append_element_ns(
+        &self,
+        namespace_uri: Option<&str>,
+        qualified_name: &str,
+    ) -> error::Result<XmlElement> {
+        let prefix = qualified_name.split_once(':').map(|v| v.0);
+        if prefix == Some("xmlns") {
+            return Err(error::DomException::NamespaceErr)?;
+        }
 
-impl PrettyPrint for XmlEntity {
-    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
-        self.entity.borrow().indented(0, f)
-    }
-}
+        let namespace_uri = match namespace_uri {
+            Some(v) => v,
+            None => {
+                if let Some(prefix) = prefix {
+                    self.check_prefix_bound(prefix)?;
+                }
+                return self.append_element(qualified_name);
+            }
+        };
 
-impl From<info::XmlNode<info::XmlEntity>> for XmlEntity {
-    fn from(value: info::XmlNode<info::XmlEntity>) -> Self {
-        XmlEntity { entity: value }
-    }
-}
+        if prefix == Some("xml") && namespace_uri != info::NamespaceUri::xml().value() {
+            return Err(error::DomException::NamespaceErr)?;
+        }
 
-impl From<info::XmlNode<info::XmlUnparsedEntity>> for XmlEntity {
-    fn from(value: info::XmlNode<info::XmlUnparsedEntity>) -> Self {
-        XmlEntity {
-            entity: value.borrow().entity(),
+        let element = self.append_element(qualified_name)?;
+        let uri = element
+            .element
+            .borrow()
+            .lookup_namespace_uri(prefix.unwrap_or("xmlns"))?;
+        if uri.as_deref().unwrap_or_default() != namespace_uri {
+            match prefix {
+                Some(prefix) => element.declare_namespace_prefix(prefix, namespace_uri)?,
+                None => element.declare_namespace("xmlns", namespace_uri)?,
+            }
         }
+        Ok(element)
     }
-}
 
-impl fmt::Debug for XmlEntity {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "XmlEntity {{ {} }}", self.node_name())
+    pub fn get_attribute_ns(&self, namespace_uri: Option<&str>, local_name: &str) -> String {
+        match self.get_attribute_node_ns(namespace_uri, local_name) {
+            // FIXME:
+            Some(attr) => attr.value().unwrap(),
+            None => String::new(),
+        }
     }
-}
 
-impl fmt::Display for XmlEntity {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        self.entity.borrow().fmt(f)
+    pub fn get_attribute_node_ns(
+        &self,
+        namespace_uri: Option<&str>,
+        local_name: &str,
+    ) -> Option<XmlAttr> {
+        self.attributes()?
+            .get_named_item_ns(namespace_uri, local_name)
     }
-}
 
-// -----------------------------------------------------------------------------------------------
+    pub fn set_attribute_ns(
+        &self,
+        namespace_uri: Option<&str>,
+        qualified_name: &str,
+        value: &str,
+    ) -> error::Result<()> {
+        let (prefix, local_name) = match qualified_name.split_once(':') {
+            Some((prefix, local_name)) => (Some(prefix), local_name),
+            None => (None, qualified_name),
+        };
 
-#[derive(Clone, PartialEq)]
-pub struct XmlEntityReference {
-    value: XmlEntityReferenceValue,
-}
-
-impl EntityReference for XmlEntityReference {}
-
-impl Node for XmlEntityReference {
-    fn node_name(&self) -> String {
-        match &self.value {
-            XmlEntityReferenceValue::Char(v) => format!("{}", v.borrow()).to_string(),
-            XmlEntityReferenceValue::Entity(v) => v.borrow().name().to_string(),
+        let declaration = prefix == Some("xmlns") || qualified_name == "xmlns";
+        let xmlns = info::NamespaceUri::xmlns();
+        if declaration {
+            if namespace_uri.is_some_and(|v| v != xmlns.value()) {
+                return Err(error::DomException::NamespaceErr)?;
+            }
+            if prefix.is_some() && value.is_empty() && !self.prefix_undeclaration() {
+                return Err(error::DomException::NamespaceErr)?;
+            }
+        } else {
+            match (prefix, namespace_uri) {
+                (Some("xml"), Some(v)) if v != info::NamespaceUri::xml().value() => {
+                    return Err(error::DomException::NamespaceErr)?
+                }
+                (Some(prefix), None) => self.check_prefix_bound(prefix)?,
+                (Some(prefix), Some(uri)) => {
+                    let bound = self.element.borrow().lookup_namespace_uri(prefix)?;
+                    match bound {
+                        Some(v) if *v == *uri => {}
+                        Some(_) => return Err(error::DomException::NamespaceErr)?,
+                        None => self.declare_namespace_prefix(prefix, uri)?,
+                    }
+                }
+                (None, Some(_)) => return Err(error::DomException::NamespaceErr)?,
+                (None, None) => {}
+            }
         }
-    }
 
-    fn node_value(&self) -> error::Result<Option<String>> {
-        Ok(None)
-    }
+        let attr = self
+            .owner_document()
+            .ok_or(error::DomException::WrongDocumentErr)?
+            .create_attribute(qualified_name)?;
+        attr.set_value(value)?;
 
-    fn node_type(&self) -> NodeType {
-        NodeType::EntityReference
+        let namespace_uri = match prefix {
+            _ if declaration => Some(xmlns),
+            Some(prefix) => self.element.borrow().lookup_namespace_uri(prefix)?,
+            None => None,
+        };
+        let index = self
+            .element
+            .borrow()
+            .attribute_index_ns(namespace_uri.as_deref(), local_name);
+        self.element
+            .borrow_mut()
+            .replace_attribute_at(index, Rc::new(attr.attribute.into()));
+        Ok(())
     }
 
-    fn parent_node(&self) -> Option<XmlNode> {
-        match &self.value {
-            XmlEntityReferenceValue::Char(v) => v.borrow().parent_item().map(XmlNode::from),
-            XmlEntityReferenceValue::Entity(v) => v.borrow().parent_item().map(XmlNode::from),
+    pub fn remove_attribute_ns(
+        &self,
+        namespace_uri: Option<&str>,
+        local_name: &str,
+    ) -> error::Result<()> {
+        let index = self
+            .element
+            .borrow()
+            .attribute_index_ns(namespace_uri, local_name);
+        if let Some(index) = index {
+            self.element.borrow_mut().remove_attribute_at(index);
         }
+        Ok(())
     }
 
-    fn child_nodes(&self) -> XmlNodeList {
-        XmlNodeList {
-            node: self.as_node(),
+    pub fn undeclare_namespace_prefix(&self, prefix: &str) -> error::Result<()> {
+        if matches!(prefix, "xml" | "xmlns") || !self.prefix_undeclaration() {
+            return Err(error::DomException::NamespaceErr.into());
         }
-    }
 
-    fn first_child(&self) -> Option<XmlNode> {
-        self.first_child_node()
-    }
+        let in_use = self.element.borrow().prefix() == Some(prefix)
+            || self
+                .element
+                .borrow()
+                .attributes()
+                .iter()
+                .any(|v| v.borrow().prefix() == Some(prefix));
+        if in_use {
+            return Err(error::DomException::NamespaceErr.into());
+        }
 
-    fn last_child(&self) -> Option<XmlNode> {
-        self.last_child_node()
+        self.declare_namespace_prefix(prefix, "")
     }
 
-    fn previous_sibling(&self) -> Option<XmlNode> {
-        self.parent_node()
-            .as_ref()
-            .and_then(|parent| parent.previous_sibling_child(self.as_node()))
-    }
+    pub fn rename_prefix(&self, prefix: &str, new_prefix: &str) -> error::Result<()> {
+        if prefix == new_prefix {
+            return Ok(());
+        }
 
-    fn next_sibling(&self) -> Option<XmlNode> {
-        self.parent_node()
-            .as_ref()
-            .and_then(|parent| parent.next_sibling_child(self.as_node()))
-    }
+        let reserved = |v: &str| matches!(v, "xml" | "xmlns");
+        let valid = xml_parser::is_ncname(new_prefix);
+        if reserved(prefix) || reserved(new_prefix) || !valid {
+            return Err(error::DomException::NamespaceErr)?;
+        }
 
-    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
-        None
-    }
+        let uri = match self.element.borrow().lookup_namespace_uri(prefix)? {
+            Some(v) => v.to_string(),
+            None => return Err(error::DomException::NamespaceErr)?,
+        };
 
-    fn owner_document(&self) -> Option<XmlDocument> {
-        Some(self.inner().owner())
-    }
+        let mut elements = vec![self.clone()];
+        self.visit_descendants(|child| {
+            if let XmlNode::Element(child) = child {
+                elements.push(child.clone());
+            }
+            Ok(())
+        })?;
+
+        // Validate the whole subtree before rewriting anything.
+        let mut targets = vec![];
+        for element in elements {
+            let e = element.element.borrow();
+            if e.lookup_namespace_uri(prefix)?.as_deref() != Some(uri.as_str()) {
+                continue;
+            }
 
-    fn has_child(&self) -> bool {
-        !self.children().is_empty()
-    }
-}
+            match e.lookup_namespace_uri(new_prefix)? {
+                Some(v) if *v != *uri => return Err(error::DomException::NamespaceErr)?,
+                _ => {}
+            }
 
-impl AsNode for XmlEntityReference {
-    fn as_node(&self) -> XmlNode {
-        XmlNode::EntityReference(self.clone())
-    }
-}
+            drop(e);
+            targets.push(element);
+        }
 
-impl HasChild for XmlEntityReference {
-    fn children(&self) -> Vec<XmlNode> {
-        // TODO:
-        vec![]
-    }
-}
+        let inherited = !self.declares_prefix(prefix);
+        for element in targets {
+            let declared = element.declares_prefix(new_prefix);
+            let mut e = element.element.borrow_mut();
+            if e.prefix() == Some(prefix) {
+                e.set_prefix(Some(new_prefix));
+            }
 
-impl PrettyPrint for XmlEntityReference {
-    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
-        match &self.value {
-            XmlEntityReferenceValue::Char(v) => v.borrow().indented(0, f),
-            XmlEntityReferenceValue::Entity(v) => v.borrow().indented(0, f),
+            for attr in e.attributes().iter() {
+                let mut attr = attr.borrow_mut();
+                if attr.prefix() == Some(prefix) {
+                    attr.set_prefix(Some(new_prefix));
+                }
+            }
+
+            if !declared {
+                for attr in e.namespace_attributes().iter() {
+                    let mut attr = attr.borrow_mut();
+                    if attr.prefix() == Some("xmlns") && attr.local_name() == prefix {
+                        attr.set_local_name(new_prefix);
+                    }
+                }
+            }
         }
-    }
-}
 
-impl From<info::XmlNode<info::XmlCharReference>> for XmlEntityReference {
-    fn from(value: info::XmlNode<info::XmlCharReference>) -> Self {
-        XmlEntityReference {
-            value: XmlEntityReferenceValue::Char(value),
+        if inherited && !self.declares_prefix(new_prefix) {
+            self.declare_namespace_prefix(new_prefix, uri.as_str())?;
         }
+
+        Ok(())
     }
-}
 
-impl From<info::XmlNode<info::XmlUnexpandedEntityReference>> for XmlEntityReference {
-    fn from(value: info::XmlNode<info::XmlUnexpandedEntityReference>) -> Self {
-        XmlEntityReference {
-            value: XmlEntityReferenceValue::Entity(value),
+    pub fn rename_namespace_prefix(
+        &self,
+        namespace_uri: &str,
+        new_prefix: &str,
+    ) -> error::Result<()> {
+        let prefix = self
+            .in_scope_namespace()?
+            .iter()
+            .find(|v| v.namespace.borrow().namespace_name() == namespace_uri)
+            .and_then(|v| v.namespace.borrow().prefix().map(|v| v.to_string()));
+        match prefix {
+            Some(prefix) => self.rename_prefix(prefix.as_str(), new_prefix),
+            None => Err(error::DomException::NamespaceErr)?,
         }
     }
-}
 
-impl fmt::Debug for XmlEntityReference {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "XmlEntityReference {{ {} }}", self.node_name())
+    pub fn set_default_namespace(&self, namespace_uri: &str) -> error::Result<()> {
+        self.replace_default_namespace(namespace_uri, true)
     }
-}
 
-impl fmt::Display for XmlEntityReference {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        match &self.value {
-            XmlEntityReferenceValue::Char(v) => v.borrow().fmt(f),
-            XmlEntityReferenceValue::Entity(v) => v.borrow().fmt(f),
-        }
+    pub fn declare_default_namespace(&self, namespace_uri: &str) -> error::Result<()> {
+        self.replace_default_namespace(namespace_uri, false)
     }
-}
 
-impl XmlEntityReference {
-    pub fn value(&self) -> error::Result<String> {
-        match &self.value {
-            XmlEntityReferenceValue::Char(v) => Ok(v.borrow().character_code().to_string()),
-            XmlEntityReferenceValue::Entity(v) => Ok(v.borrow().value()?),
+    fn replace_default_namespace(&self, namespace_uri: &str, migrate: bool) -> error::Result<()> {
+        if namespace_uri == info::NamespaceUri::xml().value()
+            || namespace_uri == info::NamespaceUri::xmlns().value()
+        {
+            return Err(error::DomException::NamespaceErr)?;
         }
-    }
 
-    fn inner(&self) -> &XmlEntityReferenceValue {
-        &self.value
-    }
-}
+        let old = self.default_namespace()?;
+
+        // Descendants declaring the old namespace become redundant when migrated,
+        // otherwise the outermost unprefixed ones have to keep the old namespace.
+        let mut redundant = vec![];
+        let mut pinned = vec![];
+        let mut stack: Vec<XmlElement> = self.child_elements();
+        while let Some(element) = stack.pop() {
+            match element.declared_default_namespace()? {
+                Some(v) if migrate && v == old => redundant.push(element.clone()),
+                Some(_) => continue,
+                None if !migrate && element.element.borrow().prefix().is_none() => {
+                    pinned.push(element);
+                    continue;
+                }
+                None => {}
+            }
+            stack.extend(element.child_elements());
+        }
 
-// -----------------------------------------------------------------------------------------------
+        self.declare_namespace("xmlns", namespace_uri)?;
 
-#[derive(Clone, PartialEq)]
-pub enum XmlEntityReferenceValue {
-    Char(info::XmlNode<info::XmlCharReference>),
-    Entity(info::XmlNode<info::XmlUnexpandedEntityReference>),
-}
+        for element in redundant {
+            element.element.borrow_mut().remove_attribute("xmlns");
+        }
 
-impl XmlEntityReferenceValue {
-    pub fn id(&self) -> usize {
-        match self {
-            XmlEntityReferenceValue::Char(v) => v.borrow().id(),
-            XmlEntityReferenceValue::Entity(v) => v.borrow().id(),
+        for element in pinned {
+            element.declare_namespace("xmlns", old.as_str())?;
         }
+
+        Ok(())
     }
 
-    pub fn order(&self) -> usize {
-        match self {
-            XmlEntityReferenceValue::Char(v) => v.borrow().order(),
-            XmlEntityReferenceValue::Entity(v) => v.borrow().order(),
-        }
+    fn default_namespace(&self) -> error::Result<String> {
+        Ok(self
+            .element
+            .borrow()
+            .in_scope_namespace()?
+            .iter()
+            .find(|v| v.borrow().prefix().is_none())
+            .map(|v| v.borrow().namespace_name().to_string())
+            .unwrap_or_default())
     }
 
-    pub fn owner(&self) -> XmlDocument {
-        match self {
-            XmlEntityReferenceValue::Char(v) => XmlDocument::from(v.borrow().owner()),
-            XmlEntityReferenceValue::Entity(v) => XmlDocument::from(v.borrow().owner()),
+    fn declared_default_namespace(&self) -> error::Result<Option<String>> {
+        let attr = self
+            .element
+            .borrow()
+            .namespace_attributes()
+            .iter()
+            .find(|v| v.borrow().prefix().is_none());
+        match attr {
+            Some(v) => Ok(Some(v.borrow().normalized_value()?)),
+            None => Ok(None),
         }
     }
-}
 
-// -----------------------------------------------------------------------------------------------
+    fn declare_namespace(&self, name: &str, namespace_uri: &str) -> error::Result<()> {
+        let attr = self
+            .owner_document()
+            .ok_or(error::DomException::WrongDocumentErr)?
+            .create_attribute(name)?;
+        attr.set_value(namespace_uri)?;
+        self.element
+            .borrow_mut()
+            .replace_attribute(Rc::new(attr.attribute.into()));
+        Ok(())
+    }
 
-#[derive(Clone, PartialEq)]
-pub struct XmlProcessingInstruction {
-    pi: info::XmlNode<info::XmlProcessingInstruction>,
-}
+    fn owns_attribute(&self, attr: &XmlAttr) -> error::Result<bool> {
+        match attr.owner_element() {
+            Some(owner) if owner.as_node().ptr_eq(&self.as_node()) => Ok(true),
+            Some(_) => Err(error::DomException::InuseAttributeErr)?,
+            None => Ok(false),
+        }
+    }
 
-impl ProcessingInstruction for XmlProcessingInstruction {
-    fn target(&self) -> String {
-        self.pi.borrow().target().to_string()
+    fn bind_declared_namespace(&self, attr: &XmlAttr) -> error::Result<()> {
+        let (prefix, uri) = match attr.attribute.borrow().declared_namespace_uri() {
+            Some(uri) => (attr.prefix().unwrap_or_default(), uri.to_string()),
+            None => return Ok(()),
+        };
+
+        let bound = self
+            .element
+            .borrow()
+            .lookup_namespace_uri(prefix.as_str())?;
+        match bound {
+            Some(v) if *v == *uri => Ok(()),
+            Some(_) => Err(error::DomException::NamespaceErr)?,
+            None => self.declare_namespace_prefix(prefix.as_str(), uri.as_str()),
+        }
     }
 
-    fn data(&self) -> String {
-        self.pi.borrow().content().to_string()
+    fn check_prefix_bound(&self, prefix: &str) -> error::Result<()> {
+        match self.element.borrow().lookup_namespace_uri(prefix)? {
+            Some(_) => Ok(()),
+            None => Err(error::DomException::NamespaceErr)?,
+        }
     }
-}
 
-impl ProcessingInstructionMut for XmlProcessingInstruction {
-    fn set_data(&self, data: &str) -> error::Result<()> {
-        self.pi.borrow_mut().set_content(data)?;
+    fn declare_namespace_prefix(&self, prefix: &str, namespace_uri: &str) -> error::Result<()> {
+        let name = format!("xmlns:{}", prefix);
+        let attr = self
+            .owner_document()
+            .ok_or(error::DomException::WrongDocumentErr)?
+            .create_attribute(name.as_str())?;
+        attr.set_value(namespace_uri)?;
+        self.element
+            .borrow_mut()
+            .replace_attribute_at(None, Rc::new(attr.attribute.into()));
         Ok(())
     }
-}
 
-impl Node for XmlProcessingInstruction {
-    fn node_name(&self) -> String {
-        self.target()
+    fn child_elements(&self) -> Vec<XmlElement> {
+        self.child_nodes()
+            .iter()
+            .filter_map(|v| v.as_element())
+            .collect()
     }
 
-    fn node_value(&self) -> error::Result<Option<String>> {
-        Ok(Some(self.data()))
+    fn prefix_undeclaration(&self) -> bool {
+        let document = match self.element.borrow().owner() {
+            Ok(v) => v,
+            Err(_) => return false,
+        };
+        let document = document.borrow();
+        document.context().prefix_undeclaration() && document.version() == Some("1.1")
     }
 
-    fn node_type(&self) -> NodeType {
-        NodeType::PI
+    fn declares_prefix(&self, prefix: &str) -> bool {
+        self.element
+            .borrow()
+            .namespace_attributes()
+            .iter()
+            .any(|v| v.borrow().prefix() == Some("xmlns") && v.borrow().local_name() == prefix)
     }
 
-    fn parent_node(&self) -> Option<XmlNode> {
-        self.pi.borrow().parent().ok().map(XmlNode::from)
-    }
+    fn release_namespace_prefix(&self, prefix: &str) {
+        let uses = |element: &XmlElement| {
+            let element = element.element.borrow();
+            element.prefix() == Some(prefix)
+                || element
+                    .attributes()
+                    .iter()
+                    .any(|v| v.borrow().prefix() == Some(prefix))
+        };
+        if uses(self) || self.descendant_elements().any(|v| uses(&v)) {
+            return;
+        }
 
-    fn child_nodes(&self) -> XmlNodeList {
-        XmlNodeList {
-            node: self.as_node(),
+        let xmlns = info::NamespaceUri::xmlns();
+        let index = self
+            .element
+            .borrow()
+            .attribute_index_ns(Some(xmlns.value()), prefix);
+        if let Some(index) = index {
+            self.element.borrow_mut().remove_attribute_at(index);
         }
     }
 
-    fn first_child(&self) -> Option<XmlNode> {
-        None
+    fn xml_attribute(&self, local_name: &str) -> Option<String> {
+        self.qualified_attribute(Some("xml"), local_name)
     }
 
-    fn last_child(&self) -> Option<XmlNode> {
-        None
+    fn qualified_attribute(&self, prefix: Option<&str>, local_name: &str) -> Option<String> {
+        self.element
+            .borrow()
+            .attributes()
+            .iter()
+            .find(|v| v.borrow().prefix() == prefix && v.borrow().local_name() == local_name)
+            .and_then(|v| XmlAttr::from(v).value().ok())
     }
 
-    fn previous_sibling(&self) -> Option<XmlNode> {
-        self.parent_node()
-            .as_ref()
-            .and_then(|parent| parent.previous_sibling_child(self.as_node()))
-    }
+    fn elements_by_tag_name(
+        &self,
+        tag_name: &str,
+        namespace_uri: Option<&str>,
+    ) -> error::Result<Vec<XmlElement>> {
+        let mut elems = vec![];
 
-    fn next_sibling(&self) -> Option<XmlNode> {
-        self.parent_node()
-            .as_ref()
-            .and_then(|parent| parent.next_sibling_child(self.as_node()))
-    }
+        if self.match_name(tag_name, namespace_uri)? {
+            elems.push(self.clone());
+        }
 
-    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
-        None
-    }
+        self.visit_descendants(|child| {
+            if let XmlNode::Element(child) = child {
+                if child.match_name(tag_name, namespace_uri)? {
+                    elems.push(child.clone());
+                }
+            }
+            Ok(())
+        })?;
 
-    fn owner_document(&self) -> Option<XmlDocument> {
-        Some(XmlDocument::from(self.pi.borrow().owner()))
+        Ok(elems)
     }
 
-    fn has_child(&self) -> bool {
-        false
+    fn match_name(&self, name: &str, namespace_uri: Option<&str>) -> error::Result<bool> {
+        match namespace_uri {
+            Some(namespace_uri) => self.match_namespace(namespace_uri, name),
+            None => Ok(self.match_tag_name(name)),
+        }
     }
-}
 
-impl NodeMut for XmlProcessingInstruction {
-    fn set_node_value(&self, value: &str) -> error::Result<()> {
-        self.set_data(value)
-    }
+    fn match_namespace(&self, namespace_uri: &str, local_name: &str) -> error::Result<bool> {
+        let element = self.element.borrow();
+        if local_name != "*" && element.local_name() != local_name {
+            return Ok(false);
+        }
 
-    fn insert_before(&self, _: XmlNode, _: Option<&XmlNode>) -> error::Result<XmlNode> {
-        Err(error::DomException::HierarchyRequestErr)?
-    }
+        if namespace_uri == "*" {
+            return Ok(true);
+        }
 
-    fn remove_child(&self, _: &XmlNode) -> error::Result<XmlNode> {
-        Err(error::DomException::HierarchyRequestErr)?
+        let ns = element.namespace_name()?;
+        Ok(ns.as_deref().unwrap_or_default() == namespace_uri)
     }
-}
 
-impl AsNode for XmlProcessingInstruction {
-    fn as_node(&self) -> XmlNode {
-        XmlNode::PI(self.clone())
+    fn match_tag_name(&self, tag_name: &str) -> bool {
+        let element = self.element.borrow();
+        match tag_name.split_once(':') {
+            Some(("*", local_name)) => local_name == "*" || element.local_name() == local_name,
+            Some((prefix, "*")) => element.prefix() == Some(prefix),
+            Some((prefix, local_name)) => {
+                element.prefix() == Some(prefix) && element.local_name() == local_name
+            }
+            None => tag_name == "*" || self.node_name() == tag_name,
+        }
     }
-}
 
-impl AsExpandedName for XmlProcessingInstruction {
-    fn as_expanded_name(&self) -> error::Result<Option<ExpandedName>> {
-        Ok(Some((self.node_name(), None, None)))
+    fn max_depth(&self) -> Option<usize> {
+        self.owner_document()?
+            .document
+            .borrow()
+            .context()
+            .max_depth()
+    }
+
+    fn visit_descendants<F>(&self, mut f: F) -> error::Result<()>
+    where
+        F: FnMut(&XmlNode) -> error::Result<()>,
+    {
+        let max_depth = self.max_depth();
+
+        let mut stack = vec![self.child_iter()];
+        while let Some(children) = stack.last_mut() {
+            let child = match children.next() {
+                Some(child) => child,
+                None => {
+                    stack.pop();
+                    continue;
+                }
+            };
+
+            if let Some(max_depth) = max_depth {
+                if stack.len() > max_depth {
+                    return Err(error::Error::DepthLimitExceeded(max_depth));
+                }
+            }
+
+            f(&child)?;
+
+            if let XmlNode::Element(v) = &child {
+                stack.push(v.child_iter());
+            }
+        }
+
+        Ok(())
     }
 }
 
-impl AsStringValue for XmlProcessingInstruction {
-    fn as_string_value(&self) -> error::Result<String> {
-        Ok(self.pi.borrow().content().to_string())
-    }
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, PartialEq)]
+pub struct XmlText {
+    data: info::XmlNode<info::XmlText>,
 }
 
-impl PrettyPrint for XmlProcessingInstruction {
-    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
-        self.pi.borrow().indented(0, f)
+impl Text for XmlText {}
+
+impl TextMut for XmlText {
+    fn split_text(&self, offset: usize) -> error::Result<XmlText> {
+        if self.length() < offset {
+            return Err(error::DomException::IndexSizeErr.into());
+        }
+
+        let parent = self.data.borrow().parent_item();
+        match parent {
+            Some(parent) => match &*parent {
+                info::XmlItem::Attribute(v) => {
+                    let data2 = self.data.borrow_mut().split_at(offset);
+                    let data2_node: Rc<info::XmlItem> = Rc::new(data2.clone().into());
+
+                    let inserted = v
+                        .borrow()
+                        .insert_after(data2_node.clone(), self.data.borrow().id());
+
+                    match inserted {
+                        Ok(_) => {}
+                        Err(info::error::Error::OufOfIndex(_)) => {
+                            v.borrow().append(data2_node)?;
+                        }
+                        Err(e) => {
+                            return Err(error::Error::from(e));
+                        }
+                    }
+
+                    Ok(XmlText::from(data2))
+                }
+                info::XmlItem::Element(v) => {
+                    let data2 = self.data.borrow_mut().split_at(offset);
+                    let data2_node: Rc<info::XmlItem> = Rc::new(data2.clone().into());
+
+                    let inserted = v
+                        .borrow()
+                        .insert_after(data2_node.clone(), self.data.borrow().id());
+
+                    match inserted {
+                        Ok(_) => {}
+                        Err(info::error::Error::OufOfIndex(_)) => {
+                            v.borrow().append(data2_node)?;
+                        }
+                        Err(e) => {
+                            return Err(error::Error::from(e));
+                        }
+                    }
+
+                    Ok(XmlText::from(data2))
+                }
+                _ => Err(error::DomException::HierarchyRequestErr)?,
+            },
+            _ => Err(error::DomException::HierarchyRequestErr)?,
+        }
     }
 }
 
-impl From<info::XmlNode<info::XmlProcessingInstruction>> for XmlProcessingInstruction {
-    fn from(value: info::XmlNode<info::XmlProcessingInstruction>) -> Self {
-        XmlProcessingInstruction { pi: value }
+impl CharacterData for XmlText {
+    fn data(&self) -> error::Result<String> {
+        Ok(self.data.borrow().character_code().to_string())
     }
-}
 
-impl fmt::Debug for XmlProcessingInstruction {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "XmlProcessingInstruction {{ {} }}", self.node_name())
+    fn length(&self) -> usize {
+        self.data.borrow().len()
     }
-}
 
-impl fmt::Display for XmlProcessingInstruction {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        self.pi.borrow().fmt(f)
+    fn substring_data(&self, offset: usize, count: usize) -> error::Result<String> {
+        if self.length() < offset {
+            Err(error::DomException::IndexSizeErr)?
+        } else {
+            Ok(self.data.borrow().substring(offset..(offset + count)))
+        }
     }
 }
 
-// -----------------------------------------------------------------------------------------------
+impl CharacterDataMut for XmlText {
+    fn insert_data(&self, offset: usize, arg: &str) -> error::Result<()> {
+        if self.length() < offset {
+            Err(error::DomException::IndexSizeErr)?
+        } else {
+            self.data.borrow_mut().insert(offset, arg)?;
+            Ok(())
+        }
+    }
 
-#[derive(Clone, PartialEq)]
-pub struct XmlNamespace {
-    namespace: info::XmlNode<info::XmlNamespace>,
+    fn delete_data(&self, offset: usize, count: usize) -> error::Result<()> {
+        if self.length() < (offset + count) {
+            Err(error::DomException::IndexSizeErr)?
+        } else {
+            self.data.borrow_mut().delete(offset, count);
+            Ok(())
+        }
+    }
 }
 
-impl Node for XmlNamespace {
+impl Node for XmlText {
     fn node_name(&self) -> String {
-        self.namespace
-            .borrow()
-            .prefix()
-            .unwrap_or("xmlns")
-            .to_string()
+        "#text".to_string()
     }
 
     fn node_value(&self) -> error::Result<Option<String>> {
-        Ok(Some(self.namespace.borrow().namespace_name().to_string()))
+        Ok(Some(self.data()?))
     }
 
     fn node_type(&self) -> NodeType {
-        NodeType::Attribute
+        NodeType::Text
     }
 
     fn parent_node(&self) -> Option<XmlNode> {
-        None
+        self.data
+            .borrow()
+            .parent_item()
+            .and_then(|v| XmlNode::try_from(v).ok())
     }
 
     fn child_nodes(&self) -> XmlNodeList {
-        XmlNodeList {
-            node: self.as_node(),
-        }
+        XmlNodeList::from(self.as_node())
     }
 
     fn first_child(&self) -> Option<XmlNode> {
@@ -3387,11 +6428,15 @@ impl Node for XmlNamespace {
     }
 
     fn previous_sibling(&self) -> Option<XmlNode> {
-        None
+        self.parent_node()
+            .as_ref()
+            .and_then(|parent| parent.previous_sibling_child(self.as_node()))
     }
 
     fn next_sibling(&self) -> Option<XmlNode> {
-        None
+        self.parent_node()
+            .as_ref()
+            .and_then(|parent| parent.next_sibling_child(self.as_node()))
     }
 
     fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
@@ -3399,112 +6444,159 @@ impl Node for XmlNamespace {
     }
 
     fn owner_document(&self) -> Option<XmlDocument> {
-        None
+        self.data.borrow().owner().ok().map(XmlDocument::from)
     }
 
     fn has_child(&self) -> bool {
         false
     }
+    fn clone_node(&self, deep: bool) -> error::Result<XmlNode> {
+        duplicate_node(&self.as_node(), deep)
+    }
 }
 
-impl AsNode for XmlNamespace {
-    fn as_node(&self) -> XmlNode {
-        XmlNode::Namespace(self.clone())
+impl NodeMut for XmlText {
+    fn set_node_value(&self, value: &str) -> error::Result<()> {
+        self.set_data(value)
+    }
+
+    fn insert_before(&self, _: XmlNode, _: Option<&XmlNode>) -> error::Result<XmlNode> {
+        Err(error::DomException::HierarchyRequestErr)?
+    }
+
+    fn remove_child(&self, _: &XmlNode) -> error::Result<XmlNode> {
+        Err(error::DomException::HierarchyRequestErr)?
     }
 }
 
-impl AsExpandedName for XmlNamespace {
-    fn as_expanded_name(&self) -> error::Result<Option<ExpandedName>> {
-        Ok(Some((self.node_name(), None, None)))
+impl AsNode for XmlText {
+    fn as_node(&self) -> XmlNode {
+        XmlNode::Text(self.clone())
     }
 }
 
-impl AsStringValue for XmlNamespace {
+impl AsStringValue for XmlText {
     fn as_string_value(&self) -> error::Result<String> {
-        Ok(self.namespace.borrow().namespace_name().to_string())
+        self.data()
     }
 }
 
-impl PrettyPrint for XmlNamespace {
+impl PrettyPrint for XmlText {
     fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
-        self.namespace.borrow().indented(0, f)
+        self.data.borrow().indented(0, f)
     }
 }
 
-impl From<info::XmlNode<info::XmlNamespace>> for XmlNamespace {
-    fn from(value: info::XmlNode<info::XmlNamespace>) -> Self {
-        XmlNamespace { namespace: value }
+impl From<info::XmlNode<info::XmlText>> for XmlText {
+    fn from(value: info::XmlNode<info::XmlText>) -> Self {
+        XmlText { data: value }
     }
 }
 
-impl fmt::Debug for XmlNamespace {
+impl fmt::Debug for XmlText {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(
-            f,
-            "XmlNamespace {{ {} }}",
-            self.node_value()
-                .map_err(|_| fmt::Error)?
-                .unwrap_or_default()
-        )
+        write!(f, "XmlText {{ {} }}", self.data.borrow().character_code())
     }
 }
 
-impl fmt::Display for XmlNamespace {
+impl fmt::Display for XmlText {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        if self.implicit() {
-            Ok(())
-        } else {
-            self.namespace.borrow().fmt(f)
-        }
+        self.data.borrow().fmt(f)
     }
 }
 
-impl XmlNamespace {
-    pub fn implicit(&self) -> bool {
-        self.namespace.borrow().implicit()
+impl XmlText {
+    pub fn is_whitespace(&self) -> bool {
+        self.data
+            .borrow()
+            .character_code()
+            .chars()
+            .all(is_whitespace)
+    }
+
+    pub fn trimmed(&self) -> String {
+        trim_whitespace(self.data.borrow().character_code()).to_string()
+    }
+
+    pub fn whole_text(&self) -> error::Result<String> {
+        whole_text(&self.as_node())
+    }
+
+    pub fn replace_whole_text(&self, content: &str) -> error::Result<Option<XmlText>> {
+        replace_whole_text(&self.as_node(), content)
+    }
+
+    pub fn is_element_content_whitespace(&self) -> bool {
+        self.is_whitespace() && in_element_content(&self.as_node())
     }
 }
 
 // -----------------------------------------------------------------------------------------------
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct XmlExpandedText {
-    data: Vec<XmlNode>,
+#[derive(Clone, PartialEq)]
+pub struct XmlComment {
+    data: info::XmlNode<info::XmlComment>,
 }
 
-impl Text for XmlExpandedText {}
+impl Comment for XmlComment {}
 
-impl CharacterData for XmlExpandedText {
+impl CommentMut for XmlComment {}
+
+impl CharacterData for XmlComment {
     fn data(&self) -> error::Result<String> {
-        let mut s = String::new();
-        for d in self.data.as_slice() {
-            match d {
-                XmlNode::CData(v) => s.push_str(v.data()?.as_str()),
-                XmlNode::EntityReference(v) => s.push_str(v.value()?.as_str()),
-                XmlNode::Text(v) => s.push_str(v.data()?.as_str()),
-                _ => unreachable!(),
-            }
-        }
-        Ok(s)
+        Ok(self.data.borrow().comment().to_string())
     }
 
     fn length(&self) -> usize {
-        self.data().unwrap_or_default().chars().count()
+        self.data.borrow().len()
     }
 
     fn substring_data(&self, offset: usize, count: usize) -> error::Result<String> {
-        let data = self.data().unwrap_or_default();
-        if data.chars().count() < offset {
+        if self.length() < offset {
             Err(error::DomException::IndexSizeErr)?
         } else {
-            Ok(data.chars().skip(offset).take(count).collect())
+            Ok(self.data.borrow().substring(offset..(offset + count)))
         }
     }
 }
 
-impl Node for XmlExpandedText {
+impl CharacterDataMut for XmlComment {
+    fn insert_data(&self, offset: usize, arg: &str) -> error::Result<()> {
+        if self.length() < offset {
+            Err(error::DomException::IndexSizeErr)?
+        } else {
+            check_comment(self.spliced(offset, 0, arg).as_str())?;
+            self.data.borrow_mut().insert(offset, arg)?;
+            Ok(())
+        }
+    }
+
+    fn delete_data(&self, offset: usize, count: usize) -> error::Result<()> {
+        if self.length() < (offset + count) {
+            Err(error::DomException::IndexSizeErr)?
+        } else {
+            check_comment(self.spliced(offset, count, "").as_str())?;
+            self.data.borrow_mut().delete(offset, count);
+            Ok(())
+        }
+    }
+
+    fn replace_data(&self, offset: usize, count: usize, arg: &str) -> error::Result<()> {
+        if self.length() < (offset + count) {
+            Err(error::DomException::IndexSizeErr)?
+        } else {
+            check_comment(self.spliced(offset, count, arg).as_str())?;
+            let mut data = self.data.borrow_mut();
+            data.delete(offset, count);
+            data.insert(offset, arg)?;
+            Ok(())
+        }
+    }
+}
+
+impl Node for XmlComment {
     fn node_name(&self) -> String {
-        "#text".to_string()
+        "#comment".to_string()
     }
 
     fn node_value(&self) -> error::Result<Option<String>> {
@@ -3512,17 +6604,19 @@ impl Node for XmlExpandedText {
     }
 
     fn node_type(&self) -> NodeType {
-        NodeType::Text
+        NodeType::Comment
     }
 
     fn parent_node(&self) -> Option<XmlNode> {
-        self.data[0].parent_node()
+        self.data
+            .borrow()
+            .parent()
+            .ok()
+            .and_then(|v| XmlNode::try_from(v).ok())
     }
 
     fn child_nodes(&self) -> XmlNodeList {
-        XmlNodeList {
-            node: self.as_node(),
-        }
+        XmlNodeList::from(self.as_node())
     }
 
     fn first_child(&self) -> Option<XmlNode> {
@@ -3550,619 +6644,6311 @@ impl Node for XmlExpandedText {
     }
 
     fn owner_document(&self) -> Option<XmlDocument> {
-        self.data[0].owner_document()
+        self.data.borrow().owner().ok().map(XmlDocument::from)
     }
 
     fn has_child(&self) -> bool {
         false
     }
+    fn clone_node(&self, deep: bool) -> error::Result<XmlNode> {
+        duplicate_node(&self.as_node(), deep)
+    }
 }
 
-impl AsNode for XmlExpandedText {
+impl NodeMut for XmlComment {
+    fn set_node_value(&self, value: &str) -> error::Result<()> {
+        self.set_data(value)
+    }
+
+    fn insert_before(&self, _: XmlNode, _: Option<&XmlNode>) -> error::Result<XmlNode> {
+        Err(error::DomException::HierarchyRequestErr)?
+    }
+
+    fn remove_child(&self, _: &XmlNode) -> error::Result<XmlNode> {
+        Err(error::DomException::HierarchyRequestErr)?
+    }
+}
+
+impl AsNode for XmlComment {
     fn as_node(&self) -> XmlNode {
-        XmlNode::ExpandedText(self.clone())
+        XmlNode::Comment(self.clone())
     }
 }
 
-impl AsStringValue for XmlExpandedText {
+impl AsStringValue for XmlComment {
     fn as_string_value(&self) -> error::Result<String> {
         self.data()
     }
 }
 
-impl PrettyPrint for XmlExpandedText {
+impl PrettyPrint for XmlComment {
     fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
-        for d in self.data.as_slice() {
-            d.pretty(f)?;
-        }
+        self.data.borrow().indented(0, f)
+    }
+}
 
-        Ok(())
+impl From<info::XmlNode<info::XmlComment>> for XmlComment {
+    fn from(value: info::XmlNode<info::XmlComment>) -> Self {
+        XmlComment { data: value }
     }
 }
 
-impl From<XmlCDataSection> for XmlExpandedText {
-    fn from(value: XmlCDataSection) -> Self {
-        XmlExpandedText {
-            data: vec![value.as_node()],
-        }
+impl fmt::Debug for XmlComment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "XmlComment {{ {} }}", self.data.borrow().comment())
     }
 }
 
-impl From<XmlEntityReference> for XmlExpandedText {
-    fn from(value: XmlEntityReference) -> Self {
-        XmlExpandedText {
-            data: vec![value.as_node()],
-        }
+impl fmt::Display for XmlComment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        self.data.borrow().fmt(f)
     }
 }
 
-impl From<XmlText> for XmlExpandedText {
-    fn from(value: XmlText) -> Self {
-        XmlExpandedText {
-            data: vec![value.as_node()],
+impl XmlComment {
+    pub fn sanitize(data: &str) -> String {
+        let mut sanitized = String::new();
+        for c in data.chars() {
+            if c == '-' && sanitized.ends_with('-') {
+                sanitized.push(' ');
+            }
+            sanitized.push(c);
+        }
+        if sanitized.ends_with('-') {
+            sanitized.push(' ');
         }
+        sanitized
+    }
+
+    fn spliced(&self, offset: usize, count: usize, arg: &str) -> String {
+        let data = self.data.borrow();
+        let comment = data.comment();
+        let mut value = comment.chars().take(offset).collect::<String>();
+        value.push_str(arg);
+        value.extend(comment.chars().skip(offset + count));
+        value
     }
 }
 
-impl fmt::Display for XmlExpandedText {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        for d in self.data.as_slice() {
-            d.fmt(f)?;
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, PartialEq)]
+pub struct XmlCDataSection {
+    data: info::XmlNode<info::XmlCData>,
+}
+
+impl CDataSection for XmlCDataSection {}
+
+impl Text for XmlCDataSection {}
+
+impl TextMut for XmlCDataSection {
+    fn split_text(&self, offset: usize) -> error::Result<XmlCDataSection> {
+        if self.length() < offset {
+            return Err(error::DomException::IndexSizeErr.into());
         }
 
-        Ok(())
+        let v = self.data.borrow().parent()?;
+
+        let data2 = self.data.borrow_mut().split_at(offset);
+        let data2_node: Rc<info::XmlItem> = Rc::new(data2.clone().into());
+
+        let inserted = v
+            .borrow()
+            .insert_after(data2_node.clone(), self.data.borrow().id());
+
+        match inserted {
+            Ok(_) => {}
+            Err(info::error::Error::OufOfIndex(_)) => {
+                v.borrow().append(data2_node)?;
+            }
+            Err(e) => {
+                return Err(error::Error::from(e));
+            }
+        }
+
+        Ok(XmlCDataSection::from(data2))
     }
 }
 
-impl XmlExpandedText {
-    fn push_cdata(&mut self, value: XmlCDataSection) {
-        self.data.push(value.as_node());
+impl CharacterData for XmlCDataSection {
+    fn data(&self) -> error::Result<String> {
+        Ok(self.data.borrow().character_code().to_string())
     }
 
-    fn push_reference(&mut self, value: XmlEntityReference) {
-        self.data.push(value.as_node());
+    fn length(&self) -> usize {
+        self.data.borrow().len()
     }
 
-    fn push_text(&mut self, value: XmlText) {
-        self.data.push(value.as_node());
+    fn substring_data(&self, offset: usize, count: usize) -> error::Result<String> {
+        if self.length() < offset {
+            Err(error::DomException::IndexSizeErr)?
+        } else {
+            Ok(self.data.borrow().substring(offset..(offset + count)))
+        }
     }
 }
 
-// -----------------------------------------------------------------------------------------------
-
-#[derive(Clone, Debug, Default, PartialEq)]
-pub struct Context {
-    text_expanded: bool,
-}
-
-impl Context {
-    pub fn from_text_expanded(value: bool) -> Self {
-        Context {
-            text_expanded: value,
+impl CharacterDataMut for XmlCDataSection {
+    fn insert_data(&self, offset: usize, arg: &str) -> error::Result<()> {
+        if self.length() < offset {
+            Err(error::DomException::IndexSizeErr)?
+        } else {
+            self.data.borrow_mut().insert(offset, arg)?;
+            Ok(())
         }
     }
 
-    pub fn text_expanded(&self) -> bool {
-        self.text_expanded
+    fn delete_data(&self, offset: usize, count: usize) -> error::Result<()> {
+        if self.length() < (offset + count) {
+            Err(error::DomException::IndexSizeErr)?
+        } else {
+            self.data.borrow_mut().delete(offset, count);
+            Ok(())
+        }
     }
 }
 
-// -----------------------------------------------------------------------------------------------
+impl Node for XmlCDataSection {
+    fn node_name(&self) -> String {
+        "#cdata-section".to_string()
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    fn node_value(&self) -> error::Result<Option<String>> {
+        Ok(Some(self.data()?))
+    }
 
-    #[test]
-    fn test_dom_implmentation_html() {
-        let m = XmlDomImplementation {};
-        assert!(!m.has_feature("html", None));
+    fn node_type(&self) -> NodeType {
+        NodeType::CData
     }
 
-    #[test]
-    fn test_dom_implmentation_xml() {
-        let m = XmlDomImplementation {};
-        assert!(m.has_feature("xml", None));
+    fn parent_node(&self) -> Option<XmlNode> {
+        self.data
+            .borrow()
+            .parent()
+            .ok()
+            .map(XmlElement::from)
+            .map(|v| v.as_node())
     }
 
-    #[test]
-    fn test_dom_implmentation_xml_09() {
-        let m = XmlDomImplementation {};
-        assert!(!m.has_feature("xml", Some("0.9")));
+    fn child_nodes(&self) -> XmlNodeList {
+        XmlNodeList::from(self.as_node())
     }
 
-    #[test]
-    fn test_dom_implmentation_xml_10() {
-        let m = XmlDomImplementation {};
-        assert!(m.has_feature("xml", Some("1.0")));
+    fn first_child(&self) -> Option<XmlNode> {
+        None
     }
 
-    #[test]
-    fn test_document_fragment_node() {
-        let (_, tree) = xml_parser::document("<root></root>").unwrap();
-        let document = info::XmlDocument::new(&tree).unwrap();
+    fn last_child(&self) -> Option<XmlNode> {
+        None
+    }
 
-        let root = XmlNode::Element(XmlElement {
-            element: document.borrow().document_element().unwrap(),
-        });
+    fn previous_sibling(&self) -> Option<XmlNode> {
+        self.parent_node()
+            .as_ref()
+            .and_then(|parent| parent.previous_sibling_child(self.as_node()))
+    }
 
-        let flag = XmlDocumentFragment {
-            document: document.clone(),
-            parent: Some(document.clone()),
-        };
+    fn next_sibling(&self) -> Option<XmlNode> {
+        self.parent_node()
+            .as_ref()
+            .and_then(|parent| parent.next_sibling_child(self.as_node()))
+    }
 
-        // Node
-        assert_eq!("#document-fragment", flag.node_name());
-        assert_eq!(None, flag.node_value().unwrap());
+    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
+        None
+    }
+
+    fn owner_document(&self) -> Option<XmlDocument> {
+        self.data.borrow().owner().ok().map(XmlDocument::from)
+    }
+
+    fn has_child(&self) -> bool {
+        false
+    }
+    fn clone_node(&self, deep: bool) -> error::Result<XmlNode> {
+        duplicate_node(&self.as_node(), deep)
+    }
+}
+
+impl NodeMut for XmlCDataSection {
+    fn set_node_value(&self, value: &str) -> error::Result<()> {
+        self.set_data(value)
+    }
+
+    fn insert_before(&self, _: XmlNode, _: Option<&XmlNode>) -> error::Result<XmlNode> {
+        Err(error::DomException::HierarchyRequestErr)?
+    }
+
+    fn remove_child(&self, _: &XmlNode) -> error::Result<XmlNode> {
+        Err(error::DomException::HierarchyRequestErr)?
+    }
+}
+
+impl AsNode for XmlCDataSection {
+    fn as_node(&self) -> XmlNode {
+        XmlNode::CData(self.clone())
+    }
+}
+
+impl AsStringValue for XmlCDataSection {
+    fn as_string_value(&self) -> error::Result<String> {
+        self.data()
+    }
+}
+
+impl PrettyPrint for XmlCDataSection {
+    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
+        self.data.borrow().indented(0, f)
+    }
+}
+
+impl From<info::XmlNode<info::XmlCData>> for XmlCDataSection {
+    fn from(value: info::XmlNode<info::XmlCData>) -> Self {
+        XmlCDataSection { data: value }
+    }
+}
+
+impl fmt::Debug for XmlCDataSection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "XmlCDataSection {{ {} }}",
+            self.data.borrow().character_code()
+        )
+    }
+}
+
+impl fmt::Display for XmlCDataSection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        self.data.borrow().fmt(f)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, PartialEq)]
+pub struct XmlDocumentType {
+    declaration: info::XmlNode<info::XmlDocumentTypeDeclaration>,
+}
+
+impl DocumentType for XmlDocumentType {
+    fn name(&self) -> String {
+        self.declaration.borrow().local_name().to_string()
+    }
+
+    fn public_id(&self) -> Option<String> {
+        self.declaration
+            .borrow()
+            .public_identifier()
+            .map(|v| v.to_string())
+    }
+
+    fn system_id(&self) -> Option<String> {
+        self.declaration
+            .borrow()
+            .system_identifier()
+            .map(|v| v.to_string())
+    }
+
+    fn entities(&self) -> XmlNamedNodeMap<XmlEntity> {
+        XmlNamedNodeMap::new(self.as_node(), XmlNamedNodeSource::DoctypeEntities)
+    }
+
+    fn notations(&self) -> XmlNamedNodeMap<XmlNotation> {
+        XmlNamedNodeMap::new(self.as_node(), XmlNamedNodeSource::DoctypeNotations)
+    }
+}
+
+impl Node for XmlDocumentType {
+    fn node_name(&self) -> String {
+        self.name()
+    }
+
+    fn node_value(&self) -> error::Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::DocumentType
+    }
+
+    fn parent_node(&self) -> Option<XmlNode> {
+        self.declaration
+            .borrow()
+            .parent()
+            .ok()
+            .map(|v| XmlDocument::from(v).as_node())
+    }
+
+    fn child_nodes(&self) -> XmlNodeList {
+        XmlNodeList::from(self.as_node())
+    }
+
+    fn first_child(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn last_child(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn previous_sibling(&self) -> Option<XmlNode> {
+        self.parent_node()
+            .as_ref()
+            .and_then(|parent| parent.previous_sibling_child(self.as_node()))
+    }
+
+    fn next_sibling(&self) -> Option<XmlNode> {
+        self.parent_node()
+            .as_ref()
+            .and_then(|parent| parent.next_sibling_child(self.as_node()))
+    }
+
+    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
+        None
+    }
+
+    fn owner_document(&self) -> Option<XmlDocument> {
+        self.declaration
+            .borrow()
+            .parent()
+            .ok()
+            .map(XmlDocument::from)
+    }
+
+    fn has_child(&self) -> bool {
+        false
+    }
+}
+
+impl AsNode for XmlDocumentType {
+    fn as_node(&self) -> XmlNode {
+        XmlNode::DocumentType(self.clone())
+    }
+}
+
+impl PrettyPrint for XmlDocumentType {
+    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
+        self.declaration.borrow().indented(0, f)
+    }
+}
+
+impl XmlDocumentType {
+    pub fn allowed_children(
+        &self,
+        element: &XmlElement,
+        index: usize,
+    ) -> error::Result<Option<Vec<String>>> {
+        let children = element.child_nodes();
+        if index > children.length() {
+            return Err(error::DomException::IndexSizeErr.into());
+        }
+
+        let declaration = self.declaration.borrow();
+        let tag_name = element.tag_name();
+        let content = match declaration
+            .elements()
+            .iter()
+            .find(|&v| declared_name(v) == tag_name)
+        {
+            Some(v) => v.content(),
+            None => return Ok(None),
+        };
+
+        let names = match content {
+            info::XmlDeclarationContent::Empty => vec![],
+            info::XmlDeclarationContent::Any => {
+                let names = declaration.elements().iter().map(declared_name);
+                names.collect::<BTreeSet<String>>().into_iter().collect()
+            }
+            info::XmlDeclarationContent::Mixed(v) => v
+                .iter()
+                .cloned()
+                .collect::<BTreeSet<String>>()
+                .into_iter()
+                .collect(),
+            info::XmlDeclarationContent::Children(v) => {
+                let names = children
+                    .iter()
+                    .filter_map(|v| v.as_element())
+                    .map(|v| v.tag_name())
+                    .collect::<Vec<String>>();
+                let before = children
+                    .iter()
+                    .take(index)
+                    .filter(|v| v.as_element().is_some())
+                    .count();
+
+                let automaton = content_model::Automaton::new(v);
+                let states = automaton.steps(
+                    &automaton.start(),
+                    names[..before].iter().map(|v| v.as_str()),
+                );
+                automaton
+                    .names(&states)
+                    .into_iter()
+                    .filter(|name| {
+                        let next = automaton.step(&states, name.as_str());
+                        let rest = names[before..].iter().map(|v| v.as_str());
+                        !automaton.steps(&next, rest).is_empty()
+                    })
+                    .collect()
+            }
+        };
+        Ok(Some(names))
+    }
+
+    pub fn allowed_attributes(
+        &self,
+        element_name: &str,
+    ) -> error::Result<Vec<AttributeDeclaration>> {
+        let mut declarations: Vec<AttributeDeclaration> = vec![];
+        for att_list in self.declaration.borrow().attributes() {
+            let att_list = att_list.borrow();
+            if declared_name(&*att_list) != element_name {
+                continue;
+            }
+
+            for definition in att_list.definitions() {
+                let name = declared_name(definition);
+                if declarations.iter().any(|v| v.name == name) {
+                    continue;
+                }
+
+                let default = match definition.default_declaration() {
+                    info::XmlDeclarationAttDefault::Required => AttributeDefault::Required,
+                    info::XmlDeclarationAttDefault::Implied => AttributeDefault::Implied,
+                    info::XmlDeclarationAttDefault::Value(fixed, _) => {
+                        let attribute = info::XmlAttribute::new_from_declaration(
+                            definition,
+                            att_list.context(),
+                        );
+                        let value = attribute.borrow().normalized_value()?;
+                        if fixed.is_some() {
+                            AttributeDefault::Fixed(value)
+                        } else {
+                            AttributeDefault::Value(value)
+                        }
+                    }
+                };
+
+                declarations.push(AttributeDeclaration {
+                    name,
+                    attribute_type: definition.attribute_type().clone(),
+                    default,
+                });
+            }
+        }
+        Ok(declarations)
+    }
+}
+
+impl From<info::XmlNode<info::XmlDocumentTypeDeclaration>> for XmlDocumentType {
+    fn from(value: info::XmlNode<info::XmlDocumentTypeDeclaration>) -> Self {
+        XmlDocumentType { declaration: value }
+    }
+}
+
+impl fmt::Debug for XmlDocumentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "XmlDocumentType {{ {} }}", self.name())
+    }
+}
+
+impl fmt::Display for XmlDocumentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        self.declaration.borrow().fmt(f)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttributeDeclaration {
+    name: String,
+    attribute_type: info::XmlDeclarationAttType,
+    default: AttributeDefault,
+}
+
+impl AttributeDeclaration {
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn attribute_type(&self) -> &info::XmlDeclarationAttType {
+        &self.attribute_type
+    }
+
+    pub fn enumerated_values(&self) -> Option<&[String]> {
+        match &self.attribute_type {
+            info::XmlDeclarationAttType::Enumeration(v) => Some(v.as_slice()),
+            info::XmlDeclarationAttType::Notation(v) => Some(v.as_slice()),
+            _ => None,
+        }
+    }
+
+    pub fn default(&self) -> &AttributeDefault {
+        &self.default
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttributeDefault {
+    Required,
+    Implied,
+    Value(String),
+    Fixed(String),
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, PartialEq)]
+pub struct XmlNotation {
+    notation: info::XmlNode<info::XmlNotation>,
+}
+
+impl Notation for XmlNotation {
+    fn public_id(&self) -> Option<String> {
+        self.notation
+            .borrow()
+            .public_identifier()
+            .map(|v| v.to_string())
+    }
+
+    fn system_id(&self) -> Option<String> {
+        self.notation
+            .borrow()
+            .system_identifier()
+            .map(|v| v.to_string())
+    }
+}
+
+impl Node for XmlNotation {
+    fn node_name(&self) -> String {
+        self.notation.borrow().name().to_string()
+    }
+
+    fn node_value(&self) -> error::Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::Notation
+    }
+
+    fn parent_node(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn child_nodes(&self) -> XmlNodeList {
+        XmlNodeList::from(self.as_node())
+    }
+
+    fn first_child(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn last_child(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn previous_sibling(&self) -> Option<XmlNode> {
+        let parent = XmlNode::try_from(self.notation.borrow().parent()).ok()?;
+        parent.previous_sibling_child(self.as_node())
+    }
+
+    fn next_sibling(&self) -> Option<XmlNode> {
+        let parent = XmlNode::try_from(self.notation.borrow().parent()).ok()?;
+        parent.next_sibling_child(self.as_node())
+    }
+
+    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
+        None
+    }
+
+    fn owner_document(&self) -> Option<XmlDocument> {
+        self.notation.borrow().owner().ok().map(XmlDocument::from)
+    }
+
+    fn has_child(&self) -> bool {
+        false
+    }
+}
+
+impl AsNode for XmlNotation {
+    fn as_node(&self) -> XmlNode {
+        XmlNode::Notation(self.clone())
+    }
+}
+
+impl PrettyPrint for XmlNotation {
+    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
+        self.notation.borrow().indented(0, f)
+    }
+}
+
+impl From<info::XmlNode<info::XmlNotation>> for XmlNotation {
+    fn from(value: info::XmlNode<info::XmlNotation>) -> Self {
+        XmlNotation { notation: value }
+    }
+}
+
+impl fmt::Debug for XmlNotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "XmlNotation {{ {} }}", self.node_name())
+    }
+}
+
+impl fmt::Display for XmlNotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        self.notation.borrow().fmt(f)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, PartialEq)]
+pub struct XmlEntity {
+    entity: info::XmlNode<info::XmlEntity>,
+}
+
+impl Entity for XmlEntity {
+    fn public_id(&self) -> Option<String> {
+        self.entity
+            .borrow()
+            .public_identifier()
+            .map(|v| v.to_string())
+    }
+
+    fn system_id(&self) -> Option<String> {
+        self.entity
+            .borrow()
+            .system_identifier()
+            .map(|v| v.to_string())
+    }
+
+    fn notation_name(&self) -> Option<String> {
+        self.entity.borrow().notation_name().map(|v| v.to_string())
+    }
+}
+
+impl Node for XmlEntity {
+    fn node_name(&self) -> String {
+        self.entity.borrow().name().to_string()
+    }
+
+    fn node_value(&self) -> error::Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::Entity
+    }
+
+    fn parent_node(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn child_nodes(&self) -> XmlNodeList {
+        XmlNodeList::from(self.as_node())
+    }
+
+    fn first_child(&self) -> Option<XmlNode> {
+        self.first_child_node()
+    }
+
+    fn last_child(&self) -> Option<XmlNode> {
+        self.last_child_node()
+    }
+
+    fn previous_sibling(&self) -> Option<XmlNode> {
+        let parent = self
+            .entity
+            .borrow()
+            .parent()
+            .and_then(|v| XmlNode::try_from(v).ok());
+        parent.and_then(|parent| parent.previous_sibling_child(self.as_node()))
+    }
+
+    fn next_sibling(&self) -> Option<XmlNode> {
+        let parent = self
+            .entity
+            .borrow()
+            .parent()
+            .and_then(|v| XmlNode::try_from(v).ok());
+        parent.and_then(|parent| parent.next_sibling_child(self.as_node()))
+    }
+
+    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
+        None
+    }
+
+    fn owner_document(&self) -> Option<XmlDocument> {
+        self.entity.borrow().owner().ok().map(XmlDocument::from)
+    }
+
+    fn has_child(&self) -> bool {
+        self.has_child_node()
+    }
+}
+
+impl AsNode for XmlEntity {
+    fn as_node(&self) -> XmlNode {
+        XmlNode::Entity(self.clone())
+    }
+}
+
+impl HasChild for XmlEntity {
+    fn child_at(&self, _: usize) -> Option<XmlNode> {
+        // TODO:
+        None
+    }
+}
+
+impl PrettyPrint for XmlEntity {
+    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
+        self.entity.borrow().indented(0, f)
+    }
+}
+
+impl From<info::XmlNode<info::XmlEntity>> for XmlEntity {
+    fn from(value: info::XmlNode<info::XmlEntity>) -> Self {
+        XmlEntity { entity: value }
+    }
+}
+
+impl From<info::XmlNode<info::XmlUnparsedEntity>> for XmlEntity {
+    fn from(value: info::XmlNode<info::XmlUnparsedEntity>) -> Self {
+        XmlEntity {
+            entity: value.borrow().entity(),
+        }
+    }
+}
+
+impl fmt::Debug for XmlEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "XmlEntity {{ {} }}", self.node_name())
+    }
+}
+
+impl fmt::Display for XmlEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        self.entity.borrow().fmt(f)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, PartialEq)]
+pub struct XmlEntityReference {
+    value: XmlEntityReferenceValue,
+}
+
+impl EntityReference for XmlEntityReference {}
+
+impl Node for XmlEntityReference {
+    fn node_name(&self) -> String {
+        match &self.value {
+            XmlEntityReferenceValue::Char(v) => format!("{}", v.borrow()).to_string(),
+            XmlEntityReferenceValue::Entity(v) => v.borrow().name().to_string(),
+        }
+    }
+
+    fn node_value(&self) -> error::Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::EntityReference
+    }
+
+    fn parent_node(&self) -> Option<XmlNode> {
+        match &self.value {
+            XmlEntityReferenceValue::Char(v) => v
+                .borrow()
+                .parent_item()
+                .and_then(|v| XmlNode::try_from(v).ok()),
+            XmlEntityReferenceValue::Entity(v) => v
+                .borrow()
+                .parent_item()
+                .and_then(|v| XmlNode::try_from(v).ok()),
+        }
+    }
+
+    fn child_nodes(&self) -> XmlNodeList {
+        XmlNodeList::from(self.as_node())
+    }
+
+    fn first_child(&self) -> Option<XmlNode> {
+        self.first_child_node()
+    }
+
+    fn last_child(&self) -> Option<XmlNode> {
+        self.last_child_node()
+    }
+
+    fn previous_sibling(&self) -> Option<XmlNode> {
+        self.parent_node()
+            .as_ref()
+            .and_then(|parent| parent.previous_sibling_child(self.as_node()))
+    }
+
+    fn next_sibling(&self) -> Option<XmlNode> {
+        self.parent_node()
+            .as_ref()
+            .and_then(|parent| parent.next_sibling_child(self.as_node()))
+    }
+
+    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
+        None
+    }
+
+    fn owner_document(&self) -> Option<XmlDocument> {
+        self.inner().owner()
+    }
+
+    fn has_child(&self) -> bool {
+        self.has_child_node()
+    }
+    fn clone_node(&self, deep: bool) -> error::Result<XmlNode> {
+        duplicate_node(&self.as_node(), deep)
+    }
+}
+
+impl AsNode for XmlEntityReference {
+    fn as_node(&self) -> XmlNode {
+        XmlNode::EntityReference(self.clone())
+    }
+}
+
+impl HasChild for XmlEntityReference {
+    fn child_at(&self, _: usize) -> Option<XmlNode> {
+        // TODO:
+        None
+    }
+}
+
+impl PrettyPrint for XmlEntityReference {
+    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
+        match &self.value {
+            XmlEntityReferenceValue::Char(v) => v.borrow().indented(0, f),
+            XmlEntityReferenceValue::Entity(v) => v.borrow().indented(0, f),
+        }
+    }
+}
+
+impl From<info::XmlNode<info::XmlCharReference>> for XmlEntityReference {
+    fn from(value: info::XmlNode<info::XmlCharReference>) -> Self {
+        XmlEntityReference {
+            value: XmlEntityReferenceValue::Char(value),
+        }
+    }
+}
+
+impl From<info::XmlNode<info::XmlUnexpandedEntityReference>> for XmlEntityReference {
+    fn from(value: info::XmlNode<info::XmlUnexpandedEntityReference>) -> Self {
+        XmlEntityReference {
+            value: XmlEntityReferenceValue::Entity(value),
+        }
+    }
+}
+
+impl fmt::Debug for XmlEntityReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "XmlEntityReference {{ {} }}", self.node_name())
+    }
+}
+
+impl fmt::Display for XmlEntityReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match &self.value {
+            XmlEntityReferenceValue::Char(v) => v.borrow().fmt(f),
+            XmlEntityReferenceValue::Entity(v) => v.borrow().fmt(f),
+        }
+    }
+}
+
+impl XmlEntityReference {
+    pub fn value(&self) -> error::Result<String> {
+        match &self.value {
+            XmlEntityReferenceValue::Char(v) => Ok(v.borrow().character_code().to_string()),
+            XmlEntityReferenceValue::Entity(v) => Ok(v.borrow().value()?),
+        }
+    }
+
+    fn inner(&self) -> &XmlEntityReferenceValue {
+        &self.value
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, PartialEq)]
+pub enum XmlEntityReferenceValue {
+    Char(info::XmlNode<info::XmlCharReference>),
+    Entity(info::XmlNode<info::XmlUnexpandedEntityReference>),
+}
+
+impl XmlEntityReferenceValue {
+    pub fn document_id(&self) -> usize {
+        match self {
+            XmlEntityReferenceValue::Char(v) => v.borrow().document_id(),
+            XmlEntityReferenceValue::Entity(v) => v.borrow().document_id(),
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        match self {
+            XmlEntityReferenceValue::Char(v) => v.borrow().id(),
+            XmlEntityReferenceValue::Entity(v) => v.borrow().id(),
+        }
+    }
+
+    pub fn order(&self) -> usize {
+        match self {
+            XmlEntityReferenceValue::Char(v) => v.borrow().order(),
+            XmlEntityReferenceValue::Entity(v) => v.borrow().order(),
+        }
+    }
+
+    pub fn owner(&self) -> Option<XmlDocument> {
+        let owner = match self {
+            XmlEntityReferenceValue::Char(v) => v.borrow().owner(),
+            XmlEntityReferenceValue::Entity(v) => v.borrow().owner(),
+        };
+        owner.ok().map(XmlDocument::from)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, PartialEq)]
+pub struct XmlProcessingInstruction {
+    pi: info::XmlNode<info::XmlProcessingInstruction>,
+}
+
+impl ProcessingInstruction for XmlProcessingInstruction {
+    fn target(&self) -> String {
+        self.pi.borrow().target().to_string()
+    }
+
+    fn data(&self) -> String {
+        self.pi.borrow().content().to_string()
+    }
+}
+
+impl ProcessingInstructionMut for XmlProcessingInstruction {
+    fn set_data(&self, data: &str) -> error::Result<()> {
+        self.pi.borrow_mut().set_content(data)?;
+        Ok(())
+    }
+}
+
+impl Node for XmlProcessingInstruction {
+    fn node_name(&self) -> String {
+        self.target()
+    }
+
+    fn node_value(&self) -> error::Result<Option<String>> {
+        Ok(Some(self.data()))
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::PI
+    }
+
+    fn parent_node(&self) -> Option<XmlNode> {
+        self.pi
+            .borrow()
+            .parent()
+            .ok()
+            .and_then(|v| XmlNode::try_from(v).ok())
+    }
+
+    fn child_nodes(&self) -> XmlNodeList {
+        XmlNodeList::from(self.as_node())
+    }
+
+    fn first_child(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn last_child(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn previous_sibling(&self) -> Option<XmlNode> {
+        self.parent_node()
+            .as_ref()
+            .and_then(|parent| parent.previous_sibling_child(self.as_node()))
+    }
+
+    fn next_sibling(&self) -> Option<XmlNode> {
+        self.parent_node()
+            .as_ref()
+            .and_then(|parent| parent.next_sibling_child(self.as_node()))
+    }
+
+    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
+        None
+    }
+
+    fn owner_document(&self) -> Option<XmlDocument> {
+        self.pi.borrow().owner().ok().map(XmlDocument::from)
+    }
+
+    fn has_child(&self) -> bool {
+        false
+    }
+    fn clone_node(&self, deep: bool) -> error::Result<XmlNode> {
+        duplicate_node(&self.as_node(), deep)
+    }
+}
+
+impl NodeMut for XmlProcessingInstruction {
+    fn set_node_value(&self, value: &str) -> error::Result<()> {
+        self.set_data(value)
+    }
+
+    fn insert_before(&self, _: XmlNode, _: Option<&XmlNode>) -> error::Result<XmlNode> {
+        Err(error::DomException::HierarchyRequestErr)?
+    }
+
+    fn remove_child(&self, _: &XmlNode) -> error::Result<XmlNode> {
+        Err(error::DomException::HierarchyRequestErr)?
+    }
+}
+
+impl AsNode for XmlProcessingInstruction {
+    fn as_node(&self) -> XmlNode {
+        XmlNode::PI(self.clone())
+    }
+}
+
+impl AsExpandedName for XmlProcessingInstruction {
+    fn as_expanded_name(&self) -> error::Result<Option<QName>> {
+        Ok(Some(QName::new(self.node_name().as_str(), None, None)))
+    }
+}
+
+impl AsStringValue for XmlProcessingInstruction {
+    fn as_string_value(&self) -> error::Result<String> {
+        Ok(self.pi.borrow().content().to_string())
+    }
+}
+
+impl PrettyPrint for XmlProcessingInstruction {
+    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
+        self.pi.borrow().indented(0, f)
+    }
+}
+
+impl From<info::XmlNode<info::XmlProcessingInstruction>> for XmlProcessingInstruction {
+    fn from(value: info::XmlNode<info::XmlProcessingInstruction>) -> Self {
+        XmlProcessingInstruction { pi: value }
+    }
+}
+
+impl fmt::Debug for XmlProcessingInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "XmlProcessingInstruction {{ {} }}", self.node_name())
+    }
+}
+
+impl fmt::Display for XmlProcessingInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        self.pi.borrow().fmt(f)
+    }
+}
+
+impl XmlProcessingInstruction {
+    pub fn pseudo_attributes(&self) -> error::Result<Vec<(String, String)>> {
+        let attributes = self.pi.borrow().pseudo_attributes()?;
+        Ok(attributes)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct XmlStylesheet {
+    pub href: String,
+    pub ty: Option<String>,
+    pub title: Option<String>,
+    pub media: Option<String>,
+    pub charset: Option<String>,
+    pub alternate: bool,
+}
+
+impl XmlStylesheet {
+    fn from_pi(pi: &XmlProcessingInstruction) -> Option<Self> {
+        if pi.target() != "xml-stylesheet" {
+            return None;
+        }
+
+        let mut stylesheet = XmlStylesheet::default();
+        let mut href = None;
+        for (name, value) in pi.pseudo_attributes().ok()? {
+            match name.as_str() {
+                "href" => href = Some(value),
+                "type" => stylesheet.ty = Some(value),
+                "title" => stylesheet.title = Some(value),
+                "media" => stylesheet.media = Some(value),
+                "charset" => stylesheet.charset = Some(value),
+                "alternate" => match value.as_str() {
+                    "yes" => stylesheet.alternate = true,
+                    "no" => stylesheet.alternate = false,
+                    _ => return None,
+                },
+                _ => {}
+            }
+        }
+
+        stylesheet.href = href?;
+        Some(stylesheet)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, PartialEq)]
+pub struct XmlNamespace {
+    namespace: info::XmlNode<info::XmlNamespace>,
+}
+
+impl Node for XmlNamespace {
+    fn node_name(&self) -> String {
+        self.namespace
+            .borrow()
+            .prefix()
+            .unwrap_or("xmlns")
+            .to_string()
+    }
+
+    fn node_value(&self) -> error::Result<Option<String>> {
+        Ok(Some(self.namespace.borrow().namespace_name().to_string()))
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::Attribute
+    }
+
+    fn parent_node(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn child_nodes(&self) -> XmlNodeList {
+        XmlNodeList::from(self.as_node())
+    }
+
+    fn first_child(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn last_child(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn previous_sibling(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn next_sibling(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
+        None
+    }
+
+    fn owner_document(&self) -> Option<XmlDocument> {
+        None
+    }
+
+    fn has_child(&self) -> bool {
+        false
+    }
+}
+
+impl AsNode for XmlNamespace {
+    fn as_node(&self) -> XmlNode {
+        XmlNode::Namespace(self.clone())
+    }
+}
+
+impl AsExpandedName for XmlNamespace {
+    fn as_expanded_name(&self) -> error::Result<Option<QName>> {
+        Ok(Some(QName::new(self.node_name().as_str(), None, None)))
+    }
+}
+
+impl AsStringValue for XmlNamespace {
+    fn as_string_value(&self) -> error::Result<String> {
+        Ok(self.namespace.borrow().namespace_name().to_string())
+    }
+}
+
+impl PrettyPrint for XmlNamespace {
+    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
+        self.namespace.borrow().indented(0, f)
+    }
+}
+
+impl From<info::XmlNode<info::XmlNamespace>> for XmlNamespace {
+    fn from(value: info::XmlNode<info::XmlNamespace>) -> Self {
+        XmlNamespace { namespace: value }
+    }
+}
+
+impl fmt::Debug for XmlNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "XmlNamespace {{ {} }}",
+            self.node_value()
+                .map_err(|_| fmt::Error)?
+                .unwrap_or_default()
+        )
+    }
+}
+
+impl fmt::Display for XmlNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        if self.implicit() {
+            Ok(())
+        } else {
+            self.namespace.borrow().fmt(f)
+        }
+    }
+}
+
+impl XmlNamespace {
+    pub fn implicit(&self) -> bool {
+        self.namespace.borrow().implicit()
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct XmlExpandedText {
+    data: Vec<XmlNode>,
+}
+
+impl Text for XmlExpandedText {}
+
+impl CharacterData for XmlExpandedText {
+    fn data(&self) -> error::Result<String> {
+        let mut s = String::new();
+        for d in self.data.as_slice() {
+            match d {
+                XmlNode::CData(v) => s.push_str(v.data()?.as_str()),
+                XmlNode::EntityReference(v) => s.push_str(v.value()?.as_str()),
+                XmlNode::Text(v) => s.push_str(v.data()?.as_str()),
+                _ => unreachable!(),
+            }
+        }
+        Ok(s)
+    }
+
+    fn length(&self) -> usize {
+        self.data().unwrap_or_default().chars().count()
+    }
+
+    fn substring_data(&self, offset: usize, count: usize) -> error::Result<String> {
+        let data = self.data().unwrap_or_default();
+        if data.chars().count() < offset {
+            Err(error::DomException::IndexSizeErr)?
+        } else {
+            Ok(data.chars().skip(offset).take(count).collect())
+        }
+    }
+}
+
+impl Node for XmlExpandedText {
+    fn node_name(&self) -> String {
+        "#text".to_string()
+    }
+
+    fn node_value(&self) -> error::Result<Option<String>> {
+        Ok(Some(self.data()?))
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::Text
+    }
+
+    fn parent_node(&self) -> Option<XmlNode> {
+        self.data[0].parent_node()
+    }
+
+    fn child_nodes(&self) -> XmlNodeList {
+        XmlNodeList::from(self.as_node())
+    }
+
+    fn first_child(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn last_child(&self) -> Option<XmlNode> {
+        None
+    }
+
+    fn previous_sibling(&self) -> Option<XmlNode> {
+        self.parent_node()
+            .as_ref()
+            .and_then(|parent| parent.previous_sibling_child(self.as_node()))
+    }
+
+    fn next_sibling(&self) -> Option<XmlNode> {
+        self.parent_node()
+            .as_ref()
+            .and_then(|parent| parent.next_sibling_child(self.as_node()))
+    }
+
+    fn attributes(&self) -> Option<XmlNamedNodeMap<XmlAttr>> {
+        None
+    }
+
+    fn owner_document(&self) -> Option<XmlDocument> {
+        self.data[0].owner_document()
+    }
+
+    fn has_child(&self) -> bool {
+        false
+    }
+    fn clone_node(&self, deep: bool) -> error::Result<XmlNode> {
+        duplicate_node(&self.as_node(), deep)
+    }
+}
+
+impl AsNode for XmlExpandedText {
+    fn as_node(&self) -> XmlNode {
+        XmlNode::ExpandedText(self.clone())
+    }
+}
+
+impl AsStringValue for XmlExpandedText {
+    fn as_string_value(&self) -> error::Result<String> {
+        self.data()
+    }
+}
+
+impl PrettyPrint for XmlExpandedText {
+    fn pretty(&self, f: &mut impl io::Write) -> io::Result<()> {
+        for d in self.data.as_slice() {
+            d.pretty(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<XmlCDataSection> for XmlExpandedText {
+    fn from(value: XmlCDataSection) -> Self {
+        XmlExpandedText {
+            data: vec![value.as_node()],
+        }
+    }
+}
+
+impl From<XmlEntityReference> for XmlExpandedText {
+    fn from(value: XmlEntityReference) -> Self {
+        XmlExpandedText {
+            data: vec![value.as_node()],
+        }
+    }
+}
+
+impl From<XmlText> for XmlExpandedText {
+    fn from(value: XmlText) -> Self {
+        XmlExpandedText {
+            data: vec![value.as_node()],
+        }
+    }
+}
+
+impl fmt::Display for XmlExpandedText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        for d in self.data.as_slice() {
+            d.fmt(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl XmlExpandedText {
+    pub fn is_whitespace(&self) -> error::Result<bool> {
+        Ok(self.data()?.chars().all(is_whitespace))
+    }
+
+    pub fn trimmed(&self) -> error::Result<String> {
+        Ok(trim_whitespace(self.data()?.as_str()).to_string())
+    }
+
+    pub fn whole_text(&self) -> error::Result<String> {
+        whole_text(&self.as_node())
+    }
+
+    pub fn replace_whole_text(&self, content: &str) -> error::Result<Option<XmlText>> {
+        replace_whole_text(&self.as_node(), content)
+    }
+
+    pub fn is_element_content_whitespace(&self) -> error::Result<bool> {
+        Ok(self.is_whitespace()? && in_element_content(&self.as_node()))
+    }
+}
+
+impl XmlExpandedText {
+    fn push_cdata(&mut self, value: XmlCDataSection) {
+        self.data.push(value.as_node());
+    }
+
+    fn push_reference(&mut self, value: XmlEntityReference) {
+        self.data.push(value.as_node());
+    }
+
+    fn push_text(&mut self, value: XmlText) {
+        self.data.push(value.as_node());
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone)]
+pub struct Context {
+    text_expanded: bool,
+    spaces_preserved: bool,
+    eol_normalized: bool,
+    line_ending_preserved: bool,
+    max_depth: Option<usize>,
+    max_length: Option<usize>,
+    namespace_aware: bool,
+    whitespace_stripped: bool,
+    attributes_defaulted: bool,
+    resolver: Option<Rc<dyn EntityResolver>>,
+    max_nesting: Option<usize>,
+    max_entity_expansion: Option<usize>,
+    doctype_rejected: bool,
+    dtd_skipped: bool,
+    skipped_elements: Option<Rc<ElementFilter>>,
+    spans_tracked: bool,
+    prefix_undeclaration: bool,
+    unicode_normalized: bool,
+    warning_handler: Option<Rc<WarningHandler>>,
+    base_uri: Option<String>,
+    external_subset_loaded: bool,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context {
+            text_expanded: false,
+            spaces_preserved: false,
+            eol_normalized: false,
+            line_ending_preserved: false,
+            max_depth: None,
+            max_length: None,
+            namespace_aware: true,
+            whitespace_stripped: false,
+            attributes_defaulted: true,
+            resolver: None,
+            max_nesting: None,
+            max_entity_expansion: None,
+            doctype_rejected: false,
+            dtd_skipped: false,
+            skipped_elements: None,
+            spans_tracked: false,
+            prefix_undeclaration: true,
+            unicode_normalized: false,
+            warning_handler: None,
+            base_uri: None,
+            external_subset_loaded: false,
+        }
+    }
+}
+
+impl PartialEq<Context> for Context {
+    fn eq(&self, other: &Context) -> bool {
+        let resolver = match (&self.resolver, &other.resolver) {
+            (Some(s), Some(o)) => Rc::ptr_eq(s, o),
+            (None, None) => true,
+            _ => false,
+        };
+        let skipped_elements = match (&self.skipped_elements, &other.skipped_elements) {
+            (Some(s), Some(o)) => Rc::ptr_eq(s, o),
+            (None, None) => true,
+            _ => false,
+        };
+        let warning_handler = match (&self.warning_handler, &other.warning_handler) {
+            (Some(s), Some(o)) => Rc::ptr_eq(s, o),
+            (None, None) => true,
+            _ => false,
+        };
+
+        self.text_expanded == other.text_expanded
+            && self.spaces_preserved == other.spaces_preserved
+            && self.eol_normalized == other.eol_normalized
+            && self.line_ending_preserved == other.line_ending_preserved
+            && self.max_depth == other.max_depth
+            && self.max_length == other.max_length
+            && self.namespace_aware == other.namespace_aware
+            && self.whitespace_stripped == other.whitespace_stripped
+            && self.attributes_defaulted == other.attributes_defaulted
+            && resolver
+            && self.max_nesting == other.max_nesting
+            && self.max_entity_expansion == other.max_entity_expansion
+            && self.doctype_rejected == other.doctype_rejected
+            && self.dtd_skipped == other.dtd_skipped
+            && skipped_elements
+            && self.spans_tracked == other.spans_tracked
+            && self.prefix_undeclaration == other.prefix_undeclaration
+            && self.unicode_normalized == other.unicode_normalized
+            && warning_handler
+            && self.base_uri == other.base_uri
+            && self.external_subset_loaded == other.external_subset_loaded
+    }
+}
+
+impl fmt::Debug for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("Context")
+            .field("text_expanded", &self.text_expanded)
+            .field("spaces_preserved", &self.spaces_preserved)
+            .field("eol_normalized", &self.eol_normalized)
+            .field("line_ending_preserved", &self.line_ending_preserved)
+            .field("max_depth", &self.max_depth)
+            .field("max_length", &self.max_length)
+            .field("namespace_aware", &self.namespace_aware)
+            .field("whitespace_stripped", &self.whitespace_stripped)
+            .field("attributes_defaulted", &self.attributes_defaulted)
+            .field("resolver", &self.resolver.is_some())
+            .field("max_nesting", &self.max_nesting)
+            .field("max_entity_expansion", &self.max_entity_expansion)
+            .field("doctype_rejected", &self.doctype_rejected)
+            .field("dtd_skipped", &self.dtd_skipped)
+            .field("skipped_elements", &self.skipped_elements.is_some())
+            .field("spans_tracked", &self.spans_tracked)
+            .field("prefix_undeclaration", &self.prefix_undeclaration)
+            .field("unicode_normalized", &self.unicode_normalized)
+            .field("warning_handler", &self.warning_handler.is_some())
+            .field("base_uri", &self.base_uri)
+            .field("external_subset_loaded", &self.external_subset_loaded)
+            .finish()
+    }
+}
+
+impl Context {
+    pub fn builder() -> ContextBuilder {
+        ContextBuilder::default()
+    }
+
+    pub fn secure() -> Self {
+        ContextBuilder::secure().build()
+    }
+
+    pub fn from_text_expanded(value: bool) -> Self {
+        Context {
+            text_expanded: value,
+            ..Default::default()
+        }
+    }
+
+    pub fn text_expanded(&self) -> bool {
+        self.text_expanded
+    }
+
+    pub fn set_spaces_preserved(&mut self, value: bool) {
+        self.spaces_preserved = value;
+    }
+
+    pub fn spaces_preserved(&self) -> bool {
+        self.spaces_preserved
+    }
+
+    pub fn set_eol_normalized(&mut self, value: bool) {
+        self.eol_normalized = value;
+    }
+
+    pub fn eol_normalized(&self) -> bool {
+        self.eol_normalized
+    }
+
+    pub fn set_line_ending_preserved(&mut self, value: bool) {
+        self.line_ending_preserved = value;
+    }
+
+    pub fn line_ending_preserved(&self) -> bool {
+        self.line_ending_preserved
+    }
+
+    pub fn set_max_depth(&mut self, value: Option<usize>) {
+        self.max_depth = value;
+    }
+
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    pub fn set_max_length(&mut self, value: Option<usize>) {
+        self.max_length = value;
+    }
+
+    pub fn max_length(&self) -> Option<usize> {
+        self.max_length
+    }
+
+    pub fn set_namespace_aware(&mut self, value: bool) {
+        self.namespace_aware = value;
+    }
+
+    pub fn namespace_aware(&self) -> bool {
+        self.namespace_aware
+    }
+
+    pub fn set_whitespace_stripped(&mut self, value: bool) {
+        self.whitespace_stripped = value;
+    }
+
+    pub fn whitespace_stripped(&self) -> bool {
+        self.whitespace_stripped
+    }
+
+    pub fn set_attributes_defaulted(&mut self, value: bool) {
+        self.attributes_defaulted = value;
+    }
+
+    pub fn attributes_defaulted(&self) -> bool {
+        self.attributes_defaulted
+    }
+
+    pub fn set_resolver(&mut self, value: Option<Rc<dyn EntityResolver>>) {
+        self.resolver = value;
+    }
+
+    pub fn resolver(&self) -> Option<&Rc<dyn EntityResolver>> {
+        self.resolver.as_ref()
+    }
+
+    pub fn set_max_nesting(&mut self, value: Option<usize>) {
+        self.max_nesting = value;
+    }
+
+    pub fn max_nesting(&self) -> Option<usize> {
+        self.max_nesting
+    }
+
+    pub fn set_max_entity_expansion(&mut self, value: Option<usize>) {
+        self.max_entity_expansion = value;
+    }
+
+    pub fn max_entity_expansion(&self) -> Option<usize> {
+        self.max_entity_expansion
+    }
+
+    pub fn set_doctype_rejected(&mut self, value: bool) {
+        self.doctype_rejected = value;
+    }
+
+    pub fn doctype_rejected(&self) -> bool {
+        self.doctype_rejected
+    }
+
+    pub fn set_dtd_skipped(&mut self, value: bool) {
+        self.dtd_skipped = value;
+    }
+
+    pub fn dtd_skipped(&self) -> bool {
+        self.dtd_skipped
+    }
+
+    pub fn set_external_subset_loaded(&mut self, value: bool) {
+        self.external_subset_loaded = value;
+    }
+
+    pub fn external_subset_loaded(&self) -> bool {
+        self.external_subset_loaded
+    }
+
+    pub fn set_skipped_elements(&mut self, value: Option<Rc<ElementFilter>>) {
+        self.skipped_elements = value;
+    }
+
+    pub fn skipped_elements(&self) -> Option<&Rc<ElementFilter>> {
+        self.skipped_elements.as_ref()
+    }
+
+    pub fn set_spans_tracked(&mut self, value: bool) {
+        self.spans_tracked = value;
+    }
+
+    pub fn spans_tracked(&self) -> bool {
+        self.spans_tracked
+    }
+
+    pub fn set_prefix_undeclaration(&mut self, value: bool) {
+        self.prefix_undeclaration = value;
+    }
+
+    pub fn prefix_undeclaration(&self) -> bool {
+        self.prefix_undeclaration
+    }
+
+    pub fn set_unicode_normalized(&mut self, value: bool) {
+        self.unicode_normalized = value;
+    }
+
+    pub fn unicode_normalized(&self) -> bool {
+        self.unicode_normalized
+    }
+
+    pub fn set_warning_handler(&mut self, value: Option<Rc<WarningHandler>>) {
+        self.warning_handler = value;
+    }
+
+    pub fn warning_handler(&self) -> Option<&Rc<WarningHandler>> {
+        self.warning_handler.as_ref()
+    }
+
+    pub fn set_base_uri(&mut self, value: Option<&str>) {
+        self.base_uri = value.map(|v| v.to_string());
+    }
+
+    pub fn base_uri(&self) -> Option<&str> {
+        self.base_uri.as_deref()
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ContextBuilder {
+    context: Context,
+}
+
+impl ContextBuilder {
+    pub fn secure() -> Self {
+        ContextBuilder::default()
+            .max_nesting(Some(256))
+            .max_entity_expansion(Some(64 * 1024))
+    }
+
+    pub fn text_expanded(mut self, value: bool) -> Self {
+        self.context.text_expanded = value;
+        self
+    }
+
+    pub fn spaces_preserved(mut self, value: bool) -> Self {
+        self.context.spaces_preserved = value;
+        self
+    }
+
+    pub fn eol_normalized(mut self, value: bool) -> Self {
+        self.context.eol_normalized = value;
+        self
+    }
+
+    pub fn line_ending_preserved(mut self, value: bool) -> Self {
+        self.context.line_ending_preserved = value;
+        self
+    }
+
+    pub fn max_depth(mut self, value: Option<usize>) -> Self {
+        self.context.max_depth = value;
+        self
+    }
+
+    pub fn max_length(mut self, value: Option<usize>) -> Self {
+        self.context.max_length = value;
+        self
+    }
+
+    pub fn namespace_aware(mut self, value: bool) -> Self {
+        self.context.namespace_aware = value;
+        self
+    }
+
+    pub fn whitespace_stripped(mut self, value: bool) -> Self {
+        self.context.whitespace_stripped = value;
+        self
+    }
+
+    pub fn attributes_defaulted(mut self, value: bool) -> Self {
+        self.context.attributes_defaulted = value;
+        self
+    }
+
+    pub fn resolver(mut self, value: impl EntityResolver + 'static) -> Self {
+        self.context.resolver = Some(Rc::new(value));
+        self
+    }
+
+    pub fn max_nesting(mut self, value: Option<usize>) -> Self {
+        self.context.max_nesting = value;
+        self
+    }
+
+    pub fn max_entity_expansion(mut self, value: Option<usize>) -> Self {
+        self.context.max_entity_expansion = value;
+        self
+    }
+
+    pub fn doctype_rejected(mut self, value: bool) -> Self {
+        self.context.doctype_rejected = value;
+        self
+    }
+
+    pub fn dtd_skipped(mut self, value: bool) -> Self {
+        self.context.dtd_skipped = value;
+        self
+    }
+
+    pub fn external_subset_loaded(mut self, value: bool) -> Self {
+        self.context.external_subset_loaded = value;
+        self
+    }
+
+    pub fn skipped_elements(mut self, value: impl Fn(&str) -> bool + 'static) -> Self {
+        self.context.skipped_elements = Some(Rc::new(value));
+        self
+    }
+
+    pub fn skipped_element_names(self, names: &[&str]) -> Self {
+        let names: Vec<String> = names.iter().map(|v| v.to_string()).collect();
+        self.skipped_elements(move |v| names.iter().any(|n| n == v))
+    }
+
+    pub fn spans_tracked(mut self, value: bool) -> Self {
+        self.context.spans_tracked = value;
+        self
+    }
+
+    pub fn prefix_undeclaration(mut self, value: bool) -> Self {
+        self.context.prefix_undeclaration = value;
+        self
+    }
+
+    pub fn unicode_normalized(mut self, value: bool) -> Self {
+        self.context.unicode_normalized = value;
+        self
+    }
+
+    pub fn warning_handler(mut self, value: impl Fn(&validation::Diagnostic) + 'static) -> Self {
+        self.context.warning_handler = Some(Rc::new(value));
+        self
+    }
+
+    pub fn base_uri(mut self, value: Option<&str>) -> Self {
+        self.context.base_uri = value.map(|v| v.to_string());
+        self
+    }
+
+    pub fn build(self) -> Context {
+        self.context
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+        .replace('\t', "&#x9;")
+        .replace('\n', "&#xA;")
+        .replace('\r', "&#xD;")
+}
+
+fn dot_label(node: &XmlNode) -> error::Result<String> {
+    let mut label = format!(
+        "{:?}\\n{}\\nid={} order={}",
+        node.node_type(),
+        dot_escape(node.node_name().as_str()),
+        node.id(),
+        node.order()
+    );
+    if let Some(value) = node.node_value()? {
+        let mut text = value.chars().take(20).collect::<String>();
+        if value.chars().count() > 20 {
+            text.push_str("...");
+        }
+        label.push_str("\\n");
+        label.push_str(dot_escape(text.as_str()).as_str());
+    }
+    Ok(label)
+}
+
+fn dot_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn is_whitespace(value: char) -> bool {
+    matches!(value, ' ' | '\t' | '\r' | '\n')
+}
+
+fn trim_whitespace(value: &str) -> &str {
+    value.trim_matches(is_whitespace)
+}
+
+fn check_comment(value: &str) -> error::Result<()> {
+    let comment = format!("<!--{}-->", value);
+    match xml_parser::comment(comment.as_str()) {
+        Ok(("", _)) => Ok(()),
+        _ => Err(error::DomException::InvalidCharacterErr)?,
+    }
+}
+
+fn duplicate_node(node: &XmlNode, deep: bool) -> error::Result<XmlNode> {
+    if let XmlNode::Document(_) = node {
+        return Err(error::DomException::NotSupportErr.into());
+    }
+
+    let document = node
+        .owner_document()
+        .ok_or(error::DomException::WrongDocumentErr)?;
+    duplicate_node_in(&document, node, deep, UserDataOperation::NodeCloned)
+}
+
+fn duplicate_node_in(
+    document: &XmlDocument,
+    node: &XmlNode,
+    deep: bool,
+    operation: UserDataOperation,
+) -> error::Result<XmlNode> {
+    if let XmlNode::Document(_)
+    | XmlNode::DocumentType(_)
+    | XmlNode::DocumentFragment(_)
+    | XmlNode::Entity(_)
+    | XmlNode::Notation(_)
+    | XmlNode::Namespace(_) = node
+    {
+        return Err(error::DomException::NotSupportErr.into());
+    }
+
+    let copy = match node {
+        XmlNode::Element(v) => {
+            let name = match v.prefix() {
+                Some(prefix) => format!("{}:{}", prefix, v.tag_name()),
+                None => v.tag_name(),
+            };
+            let copy = document.create_element(name.as_str())?;
+            for attr in v.element.borrow().attribute_nodes() {
+                let source = XmlAttr::from(attr);
+                let attr = duplicate_attribute(document, &source)?;
+                source
+                    .as_node()
+                    .notify_user_data(operation, Some(&attr.as_node()));
+                copy.element
+                    .borrow_mut()
+                    .append_attribute(Rc::new(attr.attribute.into()));
+            }
+            XmlNode::Element(copy)
+        }
+        XmlNode::Attribute(v) => XmlNode::Attribute(duplicate_attribute(document, v)?),
+        XmlNode::Text(v) => XmlNode::Text(document.create_text_node(v.data()?.as_str())),
+        XmlNode::ExpandedText(v) => XmlNode::Text(document.create_text_node(v.data()?.as_str())),
+        XmlNode::CData(v) => XmlNode::CData(document.create_cdata_section(v.data()?.as_str())),
+        XmlNode::Comment(v) => XmlNode::Comment(document.create_comment(v.data()?.as_str())?),
+        XmlNode::PI(v) => XmlNode::PI(document.create_processing_instruction(
+            v.target().as_str(),
+            ProcessingInstruction::data(v).as_str(),
+        )?),
+        XmlNode::EntityReference(v) => match v.inner() {
+            XmlEntityReferenceValue::Entity(_) => {
+                XmlNode::EntityReference(document.create_entity_reference(v.node_name().as_str())?)
+            }
+            // Character references are kept as the character they denote.
+            XmlEntityReferenceValue::Char(_) => {
+                XmlNode::Text(document.create_text_node(v.value()?.as_str()))
+            }
+        },
+        _ => unreachable!(),
+    };
+
+    if let (true, XmlNode::Element(element)) = (deep, &copy) {
+        for child in node.child_nodes().iter() {
+            element.append_child(duplicate_node_in(document, &child, true, operation)?)?;
+        }
+    }
+
+    node.notify_user_data(operation, Some(&copy));
+    Ok(copy)
+}
+
+fn duplicate_attribute(document: &XmlDocument, attr: &XmlAttr) -> error::Result<XmlAttr> {
+    let name = match attr.prefix() {
+        Some(prefix) => format!("{}:{}", prefix, attr.local_name()),
+        None => attr.local_name(),
+    };
+    let copy = document.create_attribute(name.as_str())?;
+
+    let value = raw_attribute_value(attr);
+    copy.attribute.borrow().set_values(value.as_str())?;
+    if attr.prefix().is_some() {
+        copy.attribute
+            .borrow_mut()
+            .set_declared_namespace_uri(attr.namespace_uri().as_deref());
+    }
+    Ok(copy)
+}
+
+fn raw_attribute_value(attr: &XmlAttr) -> String {
+    attr.attribute
+        .borrow()
+        .values()
+        .borrow()
+        .iter()
+        .map(|v| v.to_string())
+        .collect()
+}
+
+fn filter_node(
+    what_to_show: WhatToShow,
+    filter: Option<&dyn NodeFilter>,
+    node: &XmlNode,
+) -> FilterResult {
+    if !what_to_show.contains(node.node_type()) {
+        return FilterResult::Skip;
+    }
+
+    filter
+        .map(|f| f.accept_node(node))
+        .unwrap_or(FilterResult::Accept)
+}
+
+fn first_or_last_child(node: &XmlNode, forward: bool) -> Option<XmlNode> {
+    if forward {
+        node.first_child()
+    } else {
+        node.last_child()
+    }
+}
+
+fn sibling_node(node: &XmlNode, forward: bool) -> Option<XmlNode> {
+    if forward {
+        node.next_sibling()
+    } else {
+        node.previous_sibling()
+    }
+}
+
+fn following_node(root: &XmlNode, node: &XmlNode) -> Option<XmlNode> {
+    let mut node = node.clone();
+    loop {
+        if node.ptr_eq(root) {
+            return None;
+        }
+        if let Some(sibling) = node.next_sibling() {
+            return Some(sibling);
+        }
+        node = node.parent_node()?;
+    }
+}
+
+fn preceding_node(root: &XmlNode, node: &XmlNode) -> Option<XmlNode> {
+    if node.ptr_eq(root) {
+        return None;
+    }
+
+    match node.previous_sibling() {
+        Some(mut v) => {
+            while let Some(child) = v.last_child() {
+                v = child;
+            }
+            Some(v)
+        }
+        None => node.parent_node(),
+    }
+}
+
+fn detach_node(node: &XmlNode) -> error::Result<()> {
+    match node {
+        XmlNode::Attribute(v) => {
+            if let Some(owner) = v.owner_element() {
+                owner.remove_attribute_ns(v.namespace_uri().as_deref(), v.local_name().as_str())?;
+            }
+        }
+        _ => match node.parent_node() {
+            Some(XmlNode::Document(v)) => {
+                v.remove_child(node)?;
+            }
+            Some(XmlNode::Element(v)) => {
+                v.remove_child(node)?;
+            }
+            Some(XmlNode::Attribute(v)) => {
+                v.remove_child(node)?;
+            }
+            Some(_) => return Err(error::DomException::NoModificationAllowedErr.into()),
+            None => {}
+        },
+    }
+    Ok(())
+}
+
+fn normalize_children(
+    document: &XmlDocument,
+    parent: &XmlNode,
+    config: &DomConfiguration,
+) -> error::Result<()> {
+    // Replacement nodes may need replacing too, e.g. references nested in an entity.
+    while replace_configured_nodes(document, parent, config)? {}
+
+    for child in XmlChildIter::new(parent.clone(), false).collect::<Vec<XmlNode>>() {
+        if let XmlNode::Element(v) = &child {
+            if config.namespaces {
+                remove_redundant_namespaces(v)?;
+                check_bound_prefixes(v, config)?;
+            }
+            normalize_children(document, &child, config)?;
+        }
+    }
+
+    merge_text_nodes(parent, config.element_content_whitespace)
+}
+
+fn merge_text_nodes(parent: &XmlNode, element_content_whitespace: bool) -> error::Result<()> {
+    let mut previous: Option<XmlText> = None;
+    for child in XmlChildIter::new(parent.clone(), false).collect::<Vec<XmlNode>>() {
+        match &child {
+            XmlNode::Text(v) => {
+                let data = v.data()?;
+                let whitespace = !element_content_whitespace && v.is_element_content_whitespace();
+                if data.is_empty() || whitespace {
+                    detach_node(&child)?;
+                } else if let Some(previous) = &previous {
+                    previous.append_data(data.as_str())?;
+                    detach_node(&child)?;
+                } else {
+                    previous = Some(v.clone());
+                }
+            }
+            _ => previous = None,
+        }
+    }
+    Ok(())
+}
+
+fn replace_configured_nodes(
+    document: &XmlDocument,
+    parent: &XmlNode,
+    config: &DomConfiguration,
+) -> error::Result<bool> {
+    let mut replaced = false;
+    for child in XmlChildIter::new(parent.clone(), false).collect::<Vec<XmlNode>>() {
+        let nodes = match &child {
+            XmlNode::EntityReference(v) if !config.entities => match v.inner() {
+                XmlEntityReferenceValue::Char(_) => {
+                    vec![document.create_text_node(v.value()?.as_str()).as_node()]
+                }
+                XmlEntityReferenceValue::Entity(_) => {
+                    let mut nodes = vec![];
+                    for node in XmlChildIter::new(child.clone(), false) {
+                        let operation = UserDataOperation::NodeCloned;
+                        nodes.push(duplicate_node_in(document, &node, true, operation)?);
+                    }
+                    // Without expanded children the replacement text is used as is.
+                    if nodes.is_empty() {
+                        let value = v.value()?;
+                        if !value.is_empty() {
+                            nodes.push(document.create_text_node(value.as_str()).as_node());
+                        }
+                    }
+                    nodes
+                }
+            },
+            XmlNode::CData(v) if !config.cdata_sections => {
+                vec![document.create_text_node(v.data()?.as_str()).as_node()]
+            }
+            XmlNode::Comment(_) if !config.comments => vec![],
+            _ => continue,
+        };
+
+        for node in nodes {
+            match parent {
+                XmlNode::Document(v) => v.insert_before(node, Some(&child))?,
+                XmlNode::Element(v) => v.insert_before(node, Some(&child))?,
+                _ => return Err(error::DomException::NoModificationAllowedErr.into()),
+            };
+        }
+        detach_node(&child)?;
+        replaced = true;
+    }
+    Ok(replaced)
+}
+
+fn find_node(root: &XmlNode, handle: NodeHandle) -> Option<XmlNode> {
+    let mut nodes = vec![root.clone()];
+    while let Some(node) = nodes.pop() {
+        if node.handle() == handle {
+            return Some(node);
+        }
+
+        let attributes = node.attributes().into_iter().flat_map(|v| v.iter());
+        nodes.extend(attributes.map(|v| v.as_node()));
+        nodes.extend(XmlChildIter::new(node, false));
+    }
+    None
+}
+
+fn check_bound_prefixes(element: &XmlElement, config: &DomConfiguration) -> error::Result<()> {
+    let node = element.as_node();
+    if let (Some(prefix), None) = (element.prefix(), node.namespace_uri()) {
+        let message = format!("prefix `{}` is not bound", prefix);
+        config.report(DomError::new(
+            DomErrorSeverity::Error,
+            "unbound-prefix",
+            &node,
+            message,
+        ))?;
+    }
+
+    for attr in element.attributes().iter().flat_map(|v| v.iter()) {
+        let node = attr.as_node();
+        match (attr.prefix(), node.namespace_uri()) {
+            (Some(prefix), None) if !matches!(prefix.as_str(), "xml" | "xmlns") => {
+                let message = format!("prefix `{}` is not bound", prefix);
+                config.report(DomError::new(
+                    DomErrorSeverity::Error,
+                    "unbound-prefix",
+                    &node,
+                    message,
+                ))?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn remove_redundant_namespaces(element: &XmlElement) -> error::Result<()> {
+    let parent = element.parent_node().and_then(|v| v.as_element());
+    let declarations = element.element.borrow().namespace_attributes();
+    for declaration in declarations.iter() {
+        let declaration = XmlAttr::from(declaration.clone());
+        let prefix = declaration.prefix().map(|_| declaration.local_name());
+        let inherited = parent
+            .as_ref()
+            .and_then(|v| v.lookup_namespace_uri(prefix.as_deref()));
+        let value = Some(declaration.value()?).filter(|v| !v.is_empty());
+        if inherited == value {
+            let xmlns = info::NamespaceUri::xmlns();
+            let local_name = declaration.local_name();
+            element.remove_attribute_ns(Some(xmlns.value()), local_name.as_str())?;
+        }
+    }
+    Ok(())
+}
+
+fn adjacent_text_nodes(node: &XmlNode) -> Vec<XmlNode> {
+    let is_text = |v: &XmlNode| {
+        matches!(
+            v,
+            XmlNode::Text(_) | XmlNode::CData(_) | XmlNode::ExpandedText(_)
+        )
+    };
+
+    let mut nodes = vec![node.clone()];
+    let mut sibling = node.previous_sibling();
+    while let Some(v) = sibling.filter(is_text) {
+        sibling = v.previous_sibling();
+        nodes.insert(0, v);
+    }
+    let mut sibling = node.next_sibling();
+    while let Some(v) = sibling.filter(is_text) {
+        sibling = v.next_sibling();
+        nodes.push(v);
+    }
+    nodes
+}
+
+fn whole_text(node: &XmlNode) -> error::Result<String> {
+    let mut value = String::new();
+    for v in adjacent_text_nodes(node) {
+        match v {
+            XmlNode::Text(v) => value.push_str(v.data()?.as_str()),
+            XmlNode::CData(v) => value.push_str(v.data()?.as_str()),
+            XmlNode::ExpandedText(v) => value.push_str(v.data()?.as_str()),
+            _ => unreachable!(),
+        }
+    }
+    Ok(value)
+}
+
+fn replace_whole_text(node: &XmlNode, content: &str) -> error::Result<Option<XmlText>> {
+    let components = |v: XmlNode| match v {
+        XmlNode::ExpandedText(v) => v.data,
+        v => vec![v],
+    };
+    let nodes = adjacent_text_nodes(node)
+        .into_iter()
+        .flat_map(components)
+        .collect::<Vec<XmlNode>>();
+
+    let text = match components(node.clone()).iter().find_map(|v| v.as_text()) {
+        _ if content.is_empty() => None,
+        Some(v) => Some(v),
+        None => {
+            let text = node
+                .owner_document()
+                .ok_or(error::DomException::WrongDocumentErr)?
+                .create_text_node(content);
+            match node.parent_node() {
+                Some(XmlNode::Element(v)) => v.insert_before(text.as_node(), Some(&nodes[0]))?,
+                Some(XmlNode::Attribute(v)) => v.insert_before(text.as_node(), Some(&nodes[0]))?,
+                Some(_) => return Err(error::DomException::NoModificationAllowedErr.into()),
+                None => return Err(error::DomException::HierarchyRequestErr.into()),
+            };
+            Some(text)
+        }
+    };
+
+    for v in nodes {
+        if !text.as_ref().is_some_and(|t| t.as_node().ptr_eq(&v)) {
+            detach_node(&v)?;
+        }
+    }
+    if let Some(text) = &text {
+        text.set_data(content)?;
+    }
+    Ok(text)
+}
+
+fn in_element_content(node: &XmlNode) -> bool {
+    let element = match node.parent_node() {
+        Some(XmlNode::Element(v)) => v,
+        _ => return false,
+    };
+    let doctype = match node.owner_document().and_then(|v| v.doc_type()) {
+        Some(v) => v,
+        None => return false,
+    };
+
+    let name = match element.prefix() {
+        Some(prefix) => format!("{}:{}", prefix, element.tag_name()),
+        None => element.tag_name(),
+    };
+    let declaration = doctype.declaration.borrow();
+    declaration
+        .elements()
+        .iter()
+        .find(|&v| declared_name(v) == name)
+        .is_some_and(|v| matches!(v.content(), info::XmlDeclarationContent::Children(_)))
+}
+
+fn equal_named_node_map<T>(map: &XmlNamedNodeMap<T>, other: &XmlNamedNodeMap<T>) -> bool
+where
+    T: Node + AsNode + Clone + convert::TryFrom<XmlNode>,
+{
+    // Named items are unordered, so every item only needs an equal counterpart.
+    map.length() == other.length()
+        && map.iter().all(|v| {
+            other
+                .iter()
+                .any(|o| v.as_node().is_equal_node(&o.as_node()))
+        })
+}
+
+fn check_prefix(prefix: &str, namespace_uri: &str) -> error::Result<()> {
+    if !xml_parser::is_name(prefix) {
+        return Err(error::DomException::InvalidCharacterErr.into());
+    }
+
+    let xml = info::NamespaceUri::xml();
+    if !xml_parser::is_ncname(prefix) || (prefix == "xml" && namespace_uri != xml.value()) {
+        return Err(error::DomException::NamespaceErr.into());
+    }
+    Ok(())
+}
+
+fn check_qualified_name<'a>(
+    namespace_uri: Option<&str>,
+    qualified_name: &'a str,
+) -> error::Result<(Option<&'a str>, &'a str)> {
+    if !xml_parser::is_name(qualified_name) {
+        return Err(error::DomException::InvalidCharacterErr.into());
+    }
+
+    let (prefix, local_name) =
+        xml_parser::split_qname(qualified_name).ok_or(error::DomException::NamespaceErr)?;
+    match (prefix, namespace_uri) {
+        (Some(_), None) => Err(error::DomException::NamespaceErr)?,
+        (Some("xml"), Some(v)) if v != info::NamespaceUri::xml().value() => {
+            Err(error::DomException::NamespaceErr)?
+        }
+        _ => Ok((prefix, local_name)),
+    }
+}
+
+fn escape_cdata(value: &str) -> String {
+    value.replace("]]>", "]]]]><![CDATA[>")
+}
+
+fn escape_text(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn declared_name(declaration: &impl InfoHasQName) -> String {
+    match declaration.prefix() {
+        Some(prefix) => format!("{}:{}", prefix, declaration.local_name()),
+        None => declaration.local_name().to_string(),
+    }
+}
+
+fn check_entity_expansion(
+    document: &info::XmlNode<info::XmlDocument>,
+    max_entity_expansion: usize,
+) -> error::Result<()> {
+    let declaration = match document.borrow().document_declaration() {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+
+    let entities: HashMap<String, Vec<info::XmlEntityValue>> = declaration
+        .borrow()
+        .entities()
+        .iter()
+        .filter_map(|v| {
+            let v = v.borrow();
+            v.values()
+                .map(|values| (v.name().to_string(), values.to_vec()))
+        })
+        .collect();
+
+    let exceeded = || error::Error::EntityExpansionLimitExceeded(max_entity_expansion);
+    let mut lengths: HashMap<&str, usize> = HashMap::new();
+    for name in entities.keys() {
+        if lengths.contains_key(name.as_str()) {
+            continue;
+        }
+
+        // Mark in progress so that a recursive reference exceeds the limit.
+        lengths.insert(name, max_entity_expansion + 1);
+
+        // (entity name, index of the next value, expanded length so far)
+        let mut stack = vec![(name.as_str(), 0, 0usize)];
+        while let Some((name, index, length)) = stack.pop() {
+            let value = match entities[name].get(index) {
+                Some(v) => v,
+                None => {
+                    lengths.insert(name, length);
+                    if let Some(parent) = stack.last_mut() {
+                        parent.2 = parent.2.saturating_add(length);
+                        if parent.2 > max_entity_expansion {
+                            return Err(exceeded());
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            let expanded = match value {
+                info::XmlEntityValue::Character(_, _) => 1,
+                info::XmlEntityValue::Entity(v) => match lengths.get(v.as_str()) {
+                    Some(length) => *length,
+                    None => match entities.get_key_value(v.as_str()) {
+                        Some((v, _)) => {
+                            lengths.insert(v, max_entity_expansion + 1);
+                            stack.push((name, index + 1, length));
+                            stack.push((v, 0, 0));
+                            continue;
+                        }
+                        None => 1,
+                    },
+                },
+                info::XmlEntityValue::Parameter(_) => 0,
+                info::XmlEntityValue::Text(v) => v.len(),
+            };
+
+            let length = length.saturating_add(expanded);
+            if length > max_entity_expansion {
+                return Err(exceeded());
+            }
+            stack.push((name, index + 1, length));
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_tree<'a>(
+    value: &'a str,
+    context: &Context,
+) -> error::Result<(&'a str, xml_parser::model::Document<'a>)> {
+    let skip = |name: &xml_nom::model::QName| match (context.skipped_elements.as_deref(), name) {
+        (Some(skipped), xml_nom::model::QName::Prefixed(v)) => {
+            skipped(format!("{}:{}", v.prefix, v.local_part).as_str())
+        }
+        (Some(skipped), xml_nom::model::QName::Unprefixed(v)) => skipped(v),
+        (None, _) => false,
+    };
+    let options = xml_parser::Options {
+        dtd_rejected: context.dtd_skipped,
+        skip: context.skipped_elements.as_ref().map(|_| &skip as _),
+        max_depth: context.max_nesting,
+    };
+
+    let (rest, tree) =
+        xml_parser::document_with_options(value, &options).map_err(|e| parse_error(e, context))?;
+    if (context.doctype_rejected || context.dtd_skipped) && tree.prolog.declaration_doc.is_some() {
+        return Err(error::Error::DoctypeRejected);
+    }
+
+    Ok((rest, tree))
+}
+
+fn parse_error(
+    e: xml_parser::nom::Err<xml_parser::nom::error::Error<&str>>,
+    context: &Context,
+) -> error::Error {
+    match e {
+        xml_parser::nom::Err::Failure(v)
+            if v.code == xml_parser::nom::error::ErrorKind::TooLarge =>
+        {
+            error::Error::DepthLimitExceeded(context.max_nesting.unwrap_or_default())
+        }
+        xml_parser::nom::Err::Error(v)
+            if context.dtd_skipped && v.input.starts_with("<!DOCTYPE") =>
+        {
+            error::Error::DoctypeRejected
+        }
+        e => error::Error::from(e),
+    }
+}
+
+fn check_prefix_undeclaration(document: &info::XmlNode<info::XmlDocument>) -> error::Result<()> {
+    let mut stack = vec![];
+    if let Ok(root) = document.borrow().document_element() {
+        stack.push(root);
+    }
+
+    while let Some(element) = stack.pop() {
+        for attr in element.borrow().namespace_attributes().iter() {
+            let attr = attr.borrow();
+            if attr.prefix().is_some() && attr.normalized_value()?.is_empty() {
+                let prefix = attr.local_name().to_string();
+                return Err(error::Error::PrefixUndeclared(prefix));
+            }
+        }
+
+        for child in element.borrow().children().iter() {
+            if let Some(child) = child.as_element() {
+                stack.push(child);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn normalize_unicode(
+    document: &XmlDocument,
+    handler: Option<&WarningHandler>,
+) -> error::Result<()> {
+    let warn = |node: &XmlNode, name: String| {
+        if let Some(handler) = handler.filter(|_| !normalization::is_nfc(name.as_str())) {
+            let message = format!("name `{}` is not in normalization form C", name);
+            handler(&validation::Diagnostic::new(node, message));
+        }
+    };
+
+    let mut stack = vec![document.as_node()];
+    while let Some(node) = stack.pop() {
+        match &node {
+            XmlNode::Element(element) => {
+                warn(&node, element.node_name());
+                for attr in element.attributes().iter().flat_map(|v| v.iter()) {
+                    warn(&attr.as_node(), attr.name());
+                    let value = attr.value()?;
+                    let normalized = normalization::to_nfc(value.as_str());
+                    if attr.specified() && normalized != value {
+                        attr.set_value(normalized.as_str())?;
+                    }
+                }
+            }
+            XmlNode::Text(text) => {
+                let data = text.data()?;
+                let normalized = normalization::to_nfc(data.as_str());
+                if normalized != data {
+                    text.set_data(normalized.as_str())?;
+                }
+            }
+            XmlNode::CData(cdata) => {
+                let data = cdata.data()?;
+                let normalized = normalization::to_nfc(data.as_str());
+                if normalized != data {
+                    cdata.set_data(normalized.as_str())?;
+                }
+            }
+            XmlNode::PI(pi) => warn(&node, pi.target()),
+            _ => {}
+        }
+
+        let children = node.child_nodes();
+        stack.extend(
+            (0..children.length())
+                .rev()
+                .filter_map(|i| children.item(i)),
+        );
+    }
+
+    Ok(())
+}
+
+/// Reads the external subset through the resolver of `context`, when it is enabled with
+/// `external_subset_loaded`.
+///
+/// Without a resolver, a subset in the directory of a local document is read, and a subset that
+/// cannot be read is skipped.
+fn load_external_subset(
+    tree: &xml_parser::model::Document<'_>,
+    context: &Context,
+    document_uri: Option<&str>,
+) -> error::Result<Option<String>> {
+    let external_id = match tree.prolog.declaration_doc.as_ref() {
+        Some(v) if context.external_subset_loaded => v.external_id.as_ref(),
+        _ => None,
+    };
+    let (public_id, system_id) = match external_id {
+        Some(xml_parser::model::ExternalId::System(s)) => (None, *s),
+        Some(xml_parser::model::ExternalId::Public(p, s)) => (Some(*p), *s),
+        None => return Ok(None),
+    };
+
+    let base_uri = document_uri
+        .or(context.base_uri.as_deref())
+        .unwrap_or_default();
+    let system_id = uri::resolve(base_uri, system_id);
+    match context.resolver.as_deref() {
+        Some(resolver) => resolver.resolve(public_id, system_id.as_str()),
+        None => Ok(resolver::FileResolver::for_document(base_uri)
+            .and_then(|v| v.resolve(public_id, system_id.as_str()).ok())
+            .flatten()),
+    }
+}
+
+fn resolve_entities(
+    document: &info::XmlNode<info::XmlDocument>,
+    resolver: &dyn EntityResolver,
+) -> error::Result<()> {
+    let declaration = match document.borrow().document_declaration() {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    let base_uri = document.borrow().base_uri().to_string();
+
+    for entity in declaration.borrow().entities() {
+        let mut entity = entity.borrow_mut();
+        if entity.notation_name().is_some() {
+            continue;
+        }
+
+        if let Some(system_id) = entity.system_identifier() {
+            let system_id = uri::resolve(base_uri.as_str(), system_id);
+            let public_id = entity.public_identifier().map(|v| v.to_string());
+            if let Some(text) = resolver.resolve(public_id.as_deref(), system_id.as_str())? {
+                entity.set_values(Some(vec![info::XmlEntityValue::Text(text)]));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn language_matches(language: Option<&str>, tag: &str) -> bool {
+    match language {
+        Some(language) if language.len() > tag.len() => {
+            language.as_bytes()[tag.len()] == b'-'
+                && language[..tag.len()].eq_ignore_ascii_case(tag)
+        }
+        Some(language) => language.eq_ignore_ascii_case(tag),
+        None => false,
+    }
+}
+
+fn same_document(a: Option<XmlDocument>, b: Option<XmlDocument>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => Rc::ptr_eq(&a.document, &b.document),
+        (a, b) => a.is_none() && b.is_none(),
+    }
+}
+
+fn normalize_eol(value: &str, buffer: &mut String) {
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\r' {
+            chars.next_if_eq(&'\n');
+            buffer.push('\n');
+        } else {
+            buffer.push(c);
+        }
+    }
+}
+
+fn original_offsets(value: &str) -> Vec<usize> {
+    let bytes = value.as_bytes();
+    let mut offsets = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        offsets.push(i);
+        if bytes[i] == b'\r' && bytes.get(i + 1) == Some(&b'\n') {
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    offsets.push(bytes.len());
+    offsets
+}
+
+fn original_rest(value: &str, consumed: usize) -> &str {
+    let mut chars = value.char_indices().peekable();
+    let mut length = 0;
+
+    while let Some((i, c)) = chars.next() {
+        if length >= consumed {
+            return &value[i..];
+        }
+
+        if c != '\r' || chars.peek().map(|(_, v)| *v) != Some('\n') {
+            length += c.len_utf8();
+        }
+    }
+
+    ""
+}
+
+fn resolve_symlinks(path: &path::Path) -> io::Result<path::PathBuf> {
+    let mut path = path.to_path_buf();
+    // The same limit as Linux, to stop on a cycle.
+    for _ in 0..40 {
+        match fs::symlink_metadata(&path) {
+            Ok(v) if v.file_type().is_symlink() => {
+                let target = fs::read_link(&path)?;
+                path = match path.parent() {
+                    Some(parent) => parent.join(target),
+                    None => target,
+                };
+            }
+            _ => return Ok(path),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "too many levels of symbolic links",
+    ))
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dom_implmentation_html() {
+        let m = XmlDomImplementation {};
+        assert!(!m.has_feature("html", None));
+    }
+
+    #[test]
+    fn test_dom_implmentation_xml() {
+        let m = XmlDomImplementation {};
+        assert!(m.has_feature("xml", None));
+    }
+
+    #[test]
+    fn test_dom_implmentation_xml_09() {
+        let m = XmlDomImplementation {};
+        assert!(!m.has_feature("xml", Some("0.9")));
+    }
+
+    #[test]
+    fn test_dom_implmentation_xml_10() {
+        let m = XmlDomImplementation {};
+        assert!(m.has_feature("xml", Some("1.0")));
+    }
+
+    #[test]
+    fn test_dom_implmentation_core_20() {
+        let m = XmlDomImplementation {};
+        assert!(m.has_feature("Core", Some("2.0")));
+        assert!(m.has_feature("+XML", Some("2.0")));
+        assert!(m.has_feature("core", Some("")));
+        assert!(!m.has_feature("core", Some("3.0")));
+    }
+
+    #[test]
+    fn test_dom_implmentation_create_document() {
+        let m = XmlDomImplementation {};
+        let doctype = m
+            .create_document_type("a:root", Some("-//A//DTD"), Some("a.dtd"))
+            .unwrap();
+        assert_eq!("a:root", doctype.name());
+        assert_eq!(Some("-//A//DTD".to_string()), doctype.public_id());
+        assert_eq!(Some("a.dtd".to_string()), doctype.system_id());
+
+        let doc = m
+            .create_document(Some("http://a"), Some("a:root"), Some(&doctype))
+            .unwrap();
+        assert_eq!(
+            "<!DOCTYPE a:root PUBLIC \"-//A//DTD\" \"a.dtd\"><a:root xmlns:a=\"http://a\" />",
+            format!("{}", doc)
+        );
+        assert_eq!("a:root", doc.doc_type().unwrap().name());
+        assert_eq!(
+            Some("http://a".to_string()),
+            doc.document_element()
+                .unwrap()
+                .as_expanded_name()
+                .unwrap()
+                .and_then(|v| v.namespace_uri().map(|v| v.to_string()))
+        );
+
+        let doc = m
+            .create_document(Some("http://b"), Some("root"), None)
+            .unwrap();
+        assert_eq!("<root xmlns=\"http://b\" />", format!("{}", doc));
+
+        let doc = m.create_document(None, None, None).unwrap();
+        assert_eq!("", format!("{}", doc));
+        assert!(doc.document_element().is_err());
+
+        let err = m.create_document(None, Some("a:root"), None).err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NamespaceErr), err);
+
+        let err = m.create_document_type("<", None, None).err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::InvalidCharacterErr),
+            err
+        );
+
+        let (_, parsed) = XmlDocument::from_raw("<!DOCTYPE root><root />").unwrap();
+        let err = m
+            .create_document(None, Some("root"), parsed.doc_type().as_ref())
+            .err()
+            .unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::WrongDocumentErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_document_fragment_node() {
+        let (_, tree) = xml_parser::document("<root></root>").unwrap();
+        let document = info::XmlDocument::new(&tree).unwrap();
+
+        let root = XmlNode::Element(XmlElement {
+            element: document.borrow().document_element().unwrap(),
+        });
+
+        let flag = XmlDocumentFragment {
+            document: document.clone(),
+            parent: Some(document.clone()),
+        };
+
+        // Node
+        assert_eq!("#document-fragment", flag.node_name());
+        assert_eq!(None, flag.node_value().unwrap());
         assert_eq!(NodeType::DocumentFragment, flag.node_type());
         assert_eq!(None, flag.parent_node());
         for child in flag.child_nodes().iter() {
             assert_eq!(root, child);
         }
-        assert_eq!(Some(root.clone()), flag.first_child());
-        assert_eq!(Some(root.clone()), flag.last_child());
-        assert_eq!(None, flag.previous_sibling());
-        assert_eq!(None, flag.next_sibling());
-        assert_eq!(None, flag.attributes());
+        assert_eq!(Some(root.clone()), flag.first_child());
+        assert_eq!(Some(root.clone()), flag.last_child());
+        assert_eq!(None, flag.previous_sibling());
+        assert_eq!(None, flag.next_sibling());
+        assert_eq!(None, flag.attributes());
+        assert_eq!(
+            Some(XmlDocument::from(document.clone())),
+            flag.owner_document()
+        );
+        assert!(flag.has_child());
+    }
+
+    #[test]
+    fn test_document_fragment_as_node() {
+        let (_, tree) = xml_parser::document("<root></root>").unwrap();
+        let document = info::XmlDocument::new(&tree).unwrap();
+
+        let root = XmlNode::Element(XmlElement {
+            element: document.borrow().document_element().unwrap(),
+        });
+
+        let flag = XmlDocumentFragment {
+            document: document.clone(),
+            parent: Some(document.clone()),
+        };
+
+        // AsNode
+        let node = flag.as_node();
+        assert_eq!("#document-fragment", node.node_name());
+        assert_eq!(None, node.node_value().unwrap());
+        assert_eq!(NodeType::DocumentFragment, node.node_type());
+        assert_eq!(None, node.parent_node());
+        for child in node.child_nodes().iter() {
+            assert_eq!(root, child);
+        }
+        assert_eq!(Some(root.clone()), node.first_child());
+        assert_eq!(Some(root.clone()), node.last_child());
+        assert_eq!(None, node.previous_sibling());
+        assert_eq!(None, node.next_sibling());
+        assert_eq!(None, node.attributes());
+        assert_eq!(
+            Some(XmlDocument::from(document.clone())),
+            node.owner_document()
+        );
+        assert!(node.has_child());
+    }
+
+    #[test]
+    fn test_document_fragment_as_string_value() {
+        let (_, tree) = xml_parser::document("<root></root>").unwrap();
+        let document = info::XmlDocument::new(&tree).unwrap();
+
+        let flag = XmlDocumentFragment {
+            document: document.clone(),
+            parent: Some(document.clone()),
+        };
+
+        // AsStringValue
+        assert_eq!("", flag.as_string_value().unwrap());
+    }
+
+    #[test]
+    fn test_document_fragment_children() {
+        let (_, tree) = xml_parser::document("<root></root>").unwrap();
+        let document = info::XmlDocument::new(&tree).unwrap();
+
+        let root = XmlNode::Element(XmlElement {
+            element: document.borrow().document_element().unwrap(),
+        });
+
+        let flag = XmlDocumentFragment {
+            document: document.clone(),
+            parent: Some(document.clone()),
+        };
+
+        // HasChild
+        assert_eq!(vec![root], flag.child_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_document_fragment_debug() {
+        let (_, tree) = xml_parser::document("<root></root>").unwrap();
+        let document = info::XmlDocument::new(&tree).unwrap();
+
+        let flag = XmlDocumentFragment {
+            document: document.clone(),
+            parent: Some(document.clone()),
+        };
+
+        // fmt::Debug
+        assert_eq!(
+            "XmlDocumentFragment { Ok(XmlElement { root }) }",
+            format!("{:?}", flag)
+        );
+    }
+
+    #[test]
+    fn test_document_fragment_display() {
+        let (_, tree) = xml_parser::document("<root></root>").unwrap();
+        let document = info::XmlDocument::new(&tree).unwrap();
+
+        let flag = XmlDocumentFragment {
+            document: document.clone(),
+            parent: Some(document.clone()),
+        };
+
+        // fmt::Display
+        assert_eq!("<root />", format!("{}", flag));
+    }
+
+    #[test]
+    fn test_document_fragment_impl() {
+        let (_, tree) = xml_parser::document("<root></root>").unwrap();
+        let document = info::XmlDocument::new(&tree).unwrap();
+
+        let root = XmlElement {
+            element: document.borrow().document_element().unwrap(),
+        };
+
+        let flag = XmlDocumentFragment {
+            document: document.clone(),
+            parent: Some(document.clone()),
+        };
+
+        // XmlDocumentFragment
+        assert_eq!(root, flag.root_element().unwrap());
+    }
+
+    #[test]
+    fn test_document_document() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+        let elem = doc.root_element().unwrap();
+        let root = elem.as_node();
+
+        // Document
+        assert_eq!(None, doc.doc_type());
+        assert_eq!(XmlDomImplementation {}, doc.implementation());
+        assert_eq!(elem, doc.document_element().unwrap());
+        for child in doc.get_elements_by_tag_name("root").iter() {
+            assert_eq!(root, child);
+        }
+    }
+
+    #[test]
+    fn test_document_document_mut_create_element_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // DocumentMut
+        let elem = doc.create_element("e").unwrap();
+        assert_eq!("e", elem.tag_name());
+        assert_eq!(None, elem.parent_node());
+        assert_eq!(Some(doc.clone()), elem.owner_document());
+        assert_ne!(0, elem.element.borrow().id());
+        assert_eq!(0, elem.element.borrow().order());
+    }
+
+    #[test]
+    fn test_document_document_mut_create_element_err4() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // DocumentMut
+        let err = doc.create_element("<").err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::InvalidCharacterErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_document_document_mut_create_document_fragment_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // DocumentMut
+        let node = doc.create_document_fragment();
+        assert_eq!(None, node.parent_node());
+        assert_eq!(Some(doc.clone()), node.owner_document());
+        assert_ne!(0, node.document.borrow().id());
+        // FIXME:
+        //assert_eq!(0, node.document.borrow().order());
+    }
+
+    #[test]
+    fn test_document_document_mut_create_text_node_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // DocumentMut
+        let text = doc.create_text_node("t");
+        assert_eq!("t", text.data().unwrap());
+        assert_eq!(None, text.parent_node());
+        assert_eq!(Some(doc.clone()), text.owner_document());
+        assert_ne!(0, text.data.borrow().id());
+        assert_eq!(0, text.data.borrow().order());
+    }
+
+    #[test]
+    fn test_document_document_mut_create_text_node_escape() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+        let root = doc.document_element().unwrap();
+
+        let text = doc.create_text_node("a < b && c ]]> d");
+        assert_eq!("a < b && c ]]> d", text.data().unwrap());
+        root.append_child(text.as_node()).unwrap();
+        root.set_attribute("v", "x\"y<&\n'z").unwrap();
+        assert_eq!("x\"y<&\n'z", root.get_attribute("v"));
+
+        let xml = root.to_string();
+        assert_eq!(
+            "<root v=\"x&quot;y&lt;&amp;&#xA;'z\">a &lt; b &amp;&amp; c ]]&gt; d</root>",
+            xml
+        );
+
+        let (_, doc) = XmlDocument::from_raw(xml.as_str()).unwrap();
+        let root = doc.document_element().unwrap();
+        assert_eq!("x\"y<&\n'z", root.get_attribute("v"));
+        assert_eq!(xml, root.to_string());
+    }
+
+    #[test]
+    fn test_document_document_mut_create_comment_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // DocumentMut
+        let comment = doc.create_comment("c").unwrap();
+        assert_eq!("c", comment.data().unwrap());
+        assert_eq!(None, comment.parent_node());
+        assert_eq!(Some(doc.clone()), comment.owner_document());
+        assert_ne!(0, comment.data.borrow().id());
+        assert_eq!(0, comment.data.borrow().order());
+    }
+
+    #[test]
+    fn test_document_document_mut_create_comment_err() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        for data in ["a--b", "a-", "-"] {
+            let err = doc.create_comment(data).err().unwrap();
+            assert_eq!(
+                error::Error::Dom(error::DomException::InvalidCharacterErr),
+                err
+            );
+        }
+
+        let comment = doc
+            .create_comment(&XmlComment::sanitize("a--b---"))
+            .unwrap();
+        assert_eq!("a- -b- - - ", comment.data().unwrap());
+
+        let comment = doc.create_comment("a-x-b").unwrap();
+        let err = comment.delete_data(2, 1).err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::InvalidCharacterErr),
+            err
+        );
+        let err = comment.append_data("-").err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::InvalidCharacterErr),
+            err
+        );
+        comment.replace_data(2, 1, "y").unwrap();
+        comment.insert_data(1, "-").err().unwrap();
+        comment.insert_data(0, "-").unwrap();
+        assert_eq!("-a-y-b", comment.data().unwrap());
+    }
+
+    #[test]
+    fn test_document_document_mut_create_cdata_section_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // DocumentMut
+        let cdata = doc.create_cdata_section("d");
+        assert_eq!("d", cdata.data().unwrap());
+        assert_eq!(None, cdata.parent_node());
+        assert_eq!(Some(doc.clone()), cdata.owner_document());
+        assert_ne!(0, cdata.data.borrow().id());
+        assert_eq!(0, cdata.data.borrow().order());
+    }
+
+    #[test]
+    fn test_document_document_mut_create_cdata_section_split() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+        let root = doc.document_element().unwrap();
+
+        let cdata = doc.create_cdata_section("a]]>b");
+        assert_eq!("a]]>b", cdata.data().unwrap());
+        root.append_child(cdata.as_node()).unwrap();
+        assert_eq!(
+            "<root><![CDATA[a]]]]><![CDATA[>b]]></root>",
+            root.to_string()
+        );
+
+        cdata.set_data("]]").unwrap();
+        cdata.append_data(">").unwrap();
+        assert_eq!("<root><![CDATA[]]]]><![CDATA[>]]></root>", root.to_string());
+
+        let (_, doc) = XmlDocument::from_raw(root.to_string().as_str()).unwrap();
+        let data = doc
+            .document_element()
+            .unwrap()
+            .child_nodes()
+            .iter()
+            .map(|v| v.as_cdata().unwrap().data().unwrap())
+            .collect::<String>();
+        assert_eq!("]]>", data);
+    }
+
+    #[test]
+    fn test_document_document_mut_create_processing_instruction_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // DocumentMut
+        let pi = doc.create_processing_instruction("t", "c").unwrap();
+        assert_eq!("t", pi.target());
+        assert_eq!("c", pi.data());
+        assert_eq!(None, pi.parent_node());
+        assert_eq!(Some(doc.clone()), pi.owner_document());
+        assert_ne!(0, pi.pi.borrow().id());
+        assert_eq!(0, pi.pi.borrow().order());
+    }
+
+    #[test]
+    fn test_document_document_mut_create_processing_instruction_err4_target() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // DocumentMut
+        let err = doc.create_processing_instruction("xml", "c").err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::InvalidCharacterErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_document_document_mut_create_processing_instruction_err4_content() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // DocumentMut
+        let err = doc.create_processing_instruction("t", "?>").err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::InvalidCharacterErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_document_document_mut_create_attribute_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // DocumentMut
+        let attr = doc.create_attribute("a").unwrap();
+        assert_eq!("a", attr.name());
+        assert_eq!(None, attr.parent_node());
+        assert_eq!(Some(doc.clone()), attr.owner_document());
+        assert_ne!(0, attr.attribute.borrow().id());
+        assert_eq!(0, attr.attribute.borrow().order());
+    }
+
+    #[test]
+    fn test_document_document_mut_create_attribute_err4() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // DocumentMut
+        let err = doc.create_attribute("<").err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::InvalidCharacterErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_document_document_mut_create_entity_reference_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // DocumentMut
+        let eref = doc.create_entity_reference("amp").unwrap();
+        assert_eq!("amp", eref.node_name());
+        assert_eq!(None, eref.parent_node());
+        assert_eq!(Some(doc.clone()), eref.owner_document());
+        assert_ne!(0, eref.inner().id());
+        assert_eq!(0, eref.inner().order());
+    }
+
+    #[test]
+    fn test_document_document_mut_create_entity_reference_err4() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // DocumentMut
+        let err = doc.create_entity_reference("<").err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::InvalidCharacterErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_document_node() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+        let elem = doc.root_element().unwrap();
+        let root = elem.as_node();
+
+        // Node
+        assert_eq!("#document", doc.node_name());
+        assert_eq!(None, doc.node_value().unwrap());
+        assert_eq!(NodeType::Document, doc.node_type());
+        assert_eq!(None, doc.parent_node());
+        for child in doc.child_nodes().iter() {
+            assert_eq!(root, child);
+        }
+        assert_eq!(Some(root.clone()), doc.first_child());
+        assert_eq!(Some(root.clone()), doc.last_child());
+        assert_eq!(None, doc.previous_sibling());
+        assert_eq!(None, doc.next_sibling());
+        assert_eq!(None, doc.attributes());
+        assert_eq!(None, doc.owner_document());
+        assert!(doc.has_child());
+    }
+
+    #[test]
+    fn test_document_node_mut_set_node_value_err5() {
+        let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
+
+        // NodeMut
+        let err = doc.set_node_value("a").err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::NoDataAllowedErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_document_node_mut_insert_before_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
+
+        // NodeMut
+        let a = doc
+            .insert_before(doc.create_comment("a").unwrap().as_node(), None)
+            .unwrap();
+        assert_eq!("<root /><!--a-->", format!("{}", doc));
+        assert_eq!(Some(doc.as_node()), a.parent_node());
+        assert_eq!(Some(doc.clone()), a.owner_document());
+        assert_ne!(0, a.as_comment().unwrap().data.borrow().id());
+        assert_ne!(0, a.as_comment().unwrap().data.borrow().order());
+        let b = doc
+            .insert_before(doc.create_comment("b").unwrap().as_node(), Some(&a))
+            .unwrap();
+        assert_eq!("<root /><!--b--><!--a-->", format!("{}", doc));
+        assert_eq!(Some(doc.as_node()), b.parent_node());
+        assert_eq!(Some(doc.clone()), b.owner_document());
+        assert_ne!(0, b.as_comment().unwrap().data.borrow().id());
+        assert_ne!(0, b.as_comment().unwrap().data.borrow().order());
+    }
+
+    #[test]
+    fn test_document_node_mut_insert_before_err2() {
+        let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
+
+        // NodeMut
+        let err = doc
+            .insert_before(doc.create_attribute("a").unwrap().as_node(), None)
+            .err()
+            .unwrap();
+        assert_eq!("<root />", format!("{}", doc));
+        assert_eq!(
+            error::Error::Dom(error::DomException::HierarchyRequestErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_document_node_mut_insert_before_err3() {
+        let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
+        let (_, doc2) = XmlDocument::from_raw("<r />").unwrap();
+
+        // NodeMut
+        let err = doc
+            .insert_before(doc2.create_comment("a").unwrap().as_node(), None)
+            .err()
+            .unwrap();
+        assert_eq!("<root />", format!("{}", doc));
+        assert_eq!(
+            error::Error::Dom(error::DomException::WrongDocumentErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_document_node_mut_insert_before_err7() {
+        let (_, doc) = XmlDocument::from_raw("<root><e><ee /></e></root>").unwrap();
+        let ee = doc.get_elements_by_tag_name("ee").item(0).unwrap();
+
+        // NodeMut
+        let err = doc
+            .insert_before(doc.create_comment("a").unwrap().as_node(), Some(&ee))
+            .err()
+            .unwrap();
+        assert_eq!("<root><e><ee /></e></root>", format!("{}", doc));
+        assert_eq!(error::Error::Dom(error::DomException::NotFoundErr), err);
+    }
+
+    #[test]
+    fn test_document_node_mut_replace_child_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root /><!--b--><!--a-->").unwrap();
+        let b = doc.child_nodes().iter().nth(1).unwrap();
+
+        // NodeMut
+        let b = doc
+            .replace_child(doc.create_comment("c").unwrap().as_node(), &b)
+            .unwrap();
+        assert_eq!("<root /><!--c--><!--a-->", format!("{}", doc));
+        assert_eq!(None, b.parent_node());
+        assert_eq!(Some(doc.clone()), b.owner_document());
+        assert_ne!(0, b.as_comment().unwrap().data.borrow().id());
+        assert_eq!(0, b.as_comment().unwrap().data.borrow().order());
+
+        let c = doc.child_nodes().iter().nth(1).unwrap();
+        assert_eq!(Some(doc.as_node()), c.parent_node());
+        assert_eq!(Some(doc.clone()), c.owner_document());
+        assert_ne!(0, c.as_comment().unwrap().data.borrow().id());
+        assert_ne!(0, c.as_comment().unwrap().data.borrow().order());
+    }
+
+    #[test]
+    fn test_document_node_mut_replace_child_err2() {
+        let (_, doc) = XmlDocument::from_raw("<root /><!--b--><!--a-->").unwrap();
+        let b = doc.child_nodes().iter().nth(1).unwrap();
+
+        // NodeMut
+        let err = doc
+            .replace_child(doc.create_attribute("c").unwrap().as_node(), &b)
+            .err()
+            .unwrap();
+        assert_eq!("<root /><!--b--><!--a-->", format!("{}", doc));
+        assert_eq!(
+            error::Error::Dom(error::DomException::HierarchyRequestErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_document_node_mut_replace_child_err3() {
+        let (_, doc) = XmlDocument::from_raw("<root /><!--b--><!--a-->").unwrap();
+        let b = doc.child_nodes().iter().nth(1).unwrap();
+        let (_, doc2) = XmlDocument::from_raw("<r />").unwrap();
+
+        // NodeMut
+        let err = doc
+            .replace_child(doc2.create_comment("c").unwrap().as_node(), &b)
+            .err()
+            .unwrap();
+        assert_eq!("<root /><!--b--><!--a-->", format!("{}", doc));
+        assert_eq!(
+            error::Error::Dom(error::DomException::WrongDocumentErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_document_node_mut_replace_child_err7() {
+        let (_, doc) = XmlDocument::from_raw("<root><e><ee /></e></root><!--b--><!--a-->").unwrap();
+        let ee = doc.get_elements_by_tag_name("ee").item(0).unwrap();
+
+        // NodeMut
+        let err = doc
+            .replace_child(doc.create_comment("c").unwrap().as_node(), &ee)
+            .err()
+            .unwrap();
+        assert_eq!(
+            "<root><e><ee /></e></root><!--b--><!--a-->",
+            format!("{}", doc)
+        );
+        assert_eq!(error::Error::Dom(error::DomException::NotFoundErr), err);
+    }
+
+    #[test]
+    fn test_document_node_mut_remove_child_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root /><!--c--><!--a-->").unwrap();
+        let a = doc.child_nodes().iter().last().unwrap();
+
+        // NodeMut
+        let a = doc.remove_child(&a).unwrap();
+        assert_eq!("<root /><!--c-->", format!("{}", doc));
+        assert_eq!(None, a.parent_node());
+        assert_eq!(Some(doc.clone()), a.owner_document());
+        assert_ne!(0, a.as_comment().unwrap().data.borrow().id());
+        assert_eq!(0, a.as_comment().unwrap().data.borrow().order());
+    }
+
+    #[test]
+    fn test_document_node_mut_remove_child_err7() {
+        let (_, doc) = XmlDocument::from_raw("<root><e><ee /></e></root><!--c--><!--a-->").unwrap();
+        let ee = doc.get_elements_by_tag_name("ee").item(0).unwrap();
+
+        // NodeMut
+        let err = doc.remove_child(&ee).err().unwrap();
+        assert_eq!(
+            "<root><e><ee /></e></root><!--c--><!--a-->",
+            format!("{}", doc)
+        );
+        assert_eq!(error::Error::Dom(error::DomException::NotFoundErr), err);
+    }
+
+    #[test]
+    fn test_document_node_mut_append_child_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
+
+        // NodeMut
+        let a = doc
+            .append_child(doc.create_comment("a").unwrap().as_node())
+            .unwrap();
+        assert_eq!("<root /><!--a-->", format!("{}", doc));
+        assert_eq!(Some(doc.as_node()), a.parent_node());
+        assert_eq!(Some(doc.clone()), a.owner_document());
+        assert_ne!(0, a.as_comment().unwrap().data.borrow().id());
+        assert_ne!(0, a.as_comment().unwrap().data.borrow().order());
+    }
+
+    #[test]
+    fn test_document_node_mut_append_child_err2() {
+        let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
+
+        // NodeMut
+        let err = doc
+            .append_child(doc.create_attribute("a").unwrap().as_node())
+            .err()
+            .unwrap();
+        assert_eq!("<root />", format!("{}", doc));
+        assert_eq!(
+            error::Error::Dom(error::DomException::HierarchyRequestErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_document_node_mut_append_child_err3() {
+        let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
+        let (_, doc2) = XmlDocument::from_raw("<r />").unwrap();
+
+        // NodeMut
+        let err = doc
+            .append_child(doc2.create_comment("a").unwrap().as_node())
+            .err()
+            .unwrap();
+        assert_eq!("<root />", format!("{}", doc));
+        assert_eq!(
+            error::Error::Dom(error::DomException::WrongDocumentErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_document_as_node() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+        let elem = doc.root_element().unwrap();
+        let root = elem.as_node();
+
+        // AsNode
+        let node = doc.as_node();
+        assert_eq!("#document", node.node_name());
+        assert_eq!(None, node.node_value().unwrap());
+        assert_eq!(NodeType::Document, node.node_type());
+        assert_eq!(None, node.parent_node());
+        for child in node.child_nodes().iter() {
+            assert_eq!(root, child);
+        }
+        assert_eq!(Some(root.clone()), node.first_child());
+        assert_eq!(Some(root.clone()), node.last_child());
+        assert_eq!(None, node.previous_sibling());
+        assert_eq!(None, node.next_sibling());
+        assert_eq!(None, node.attributes());
+        assert_eq!(None, node.owner_document());
+        assert!(node.has_child());
+    }
+
+    #[test]
+    fn test_document_as_string_value() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // AsStringValue
+        assert_eq!("", doc.as_string_value().unwrap());
+    }
+
+    #[test]
+    fn test_document_children() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+        let elem = doc.root_element().unwrap();
+        let root = elem.as_node();
+
+        // HasChild
+        assert_eq!(vec![root], doc.child_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_document_debug() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // fmt::Debug
+        assert_eq!(
+            "XmlDocument { Ok(XmlElement { root }) }",
+            format!("{:?}", doc)
+        );
+    }
+
+    #[test]
+    fn test_document_display() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+
+        // fmt::Display
+        assert_eq!("<root />", format!("{}", doc));
+    }
+
+    #[test]
+    fn test_document_impl() {
+        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+        let elem = doc.root_element().unwrap();
+
+        // XmlDocument
+        assert_eq!(elem, doc.root_element().unwrap());
+    }
+
+    #[test]
+    fn test_document_line_ending() {
+        let xml = "<root>\r\n<a>b\rc</a>\r\n</root>\r\nd";
+
+        let (rest, doc) = XmlDocument::from_raw(xml).unwrap();
+        assert_eq!("d", rest);
+        assert_eq!("<root>\r\n<a>b\rc</a>\r\n</root>", format!("{}", doc));
+
+        let mut context = Context::default();
+        context.set_eol_normalized(true);
+        let (rest, doc) = XmlDocument::from_raw_with_context(xml, context.clone()).unwrap();
+        assert_eq!("d", rest);
+        assert_eq!("<root>\n<a>b\nc</a>\n</root>", format!("{}", doc));
+
+        context.set_line_ending_preserved(true);
+        let (rest, doc) = XmlDocument::from_raw_with_context(xml, context.clone()).unwrap();
+        assert_eq!("d", rest);
+        assert_eq!("<root>\r\n<a>b\r\nc</a>\r\n</root>", format!("{}", doc));
+
+        context.set_eol_normalized(false);
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context).unwrap();
+        assert_eq!("<root>\r\n<a>b\rc</a>\r\n</root>", format!("{}", doc));
+    }
+
+    #[test]
+    fn test_document_context_builder() {
+        struct Resolver;
+
+        impl EntityResolver for Resolver {
+            fn resolve(&self, _: Option<&str>, system_id: &str) -> error::Result<Option<String>> {
+                Ok(Some(format!("<{}>", system_id)))
+            }
+        }
+
+        let xml = "<!DOCTYPE root [\
+                   <!ATTLIST root d CDATA 'e'>\
+                   <!ENTITY f SYSTEM 'g'>\
+                   ]><root xmlns='http://a' a:b='c' xmlns:a='http://b'> <h> &f; </h> </root>";
+
+        let context = Context::builder().build();
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context).unwrap();
+        let root = doc.document_element().unwrap();
+        assert_eq!("e", root.get_attribute("d"));
+        assert_eq!(3, root.child_nodes().length());
+        assert_eq!(
+            Some("http://b".to_string()),
+            root.attributes()
+                .unwrap()
+                .get_named_item_ns(Some("http://b"), "b")
+                .and_then(|v| v.namespace_uri())
+        );
+        assert_eq!(
+            "  ",
+            root.get_elements_by_tag_name("h")
+                .item(0)
+                .unwrap()
+                .as_string_value()
+                .unwrap()
+        );
+
+        let context = Context::builder()
+            .namespace_aware(false)
+            .whitespace_stripped(true)
+            .attributes_defaulted(false)
+            .resolver(Resolver)
+            .build();
+        assert!(context.resolver().is_some());
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context).unwrap();
+        let root = doc.document_element().unwrap();
+        assert_eq!(None, root.get_attribute_node("d"));
+        assert_eq!(1, root.child_nodes().length());
+        assert_eq!(
+            None,
+            root.attributes()
+                .unwrap()
+                .get_named_item_ns(None, "b")
+                .unwrap()
+                .namespace_uri()
+        );
+        assert_eq!(
+            "",
+            root.get_elements_by_tag_name("h")
+                .item(0)
+                .unwrap()
+                .as_string_value()
+                .unwrap()
+        );
+        let entity = doc
+            .doc_type()
+            .unwrap()
+            .entities()
+            .get_named_item("f")
+            .unwrap();
+        assert_eq!(
+            Some(&[info::XmlEntityValue::Text("<g>".to_string())][..]),
+            entity.entity.borrow().values()
+        );
+
+        let context = Context::builder().max_length(Some(10)).build();
+        let err = XmlDocument::from_raw_with_context(xml, context)
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::LengthLimitExceeded(10), err);
+    }
+
+    #[test]
+    fn test_document_context_secure() {
+        let context = Context::secure();
+        assert!(context.resolver().is_none());
+        assert_eq!(Some(256), context.max_nesting());
+        assert_eq!(Some(64 * 1024), context.max_entity_expansion());
+        assert!(!context.doctype_rejected());
+        assert!(!context.external_subset_loaded());
+
+        let mut xml = "<!DOCTYPE root [<!ENTITY a0 'aaaaaaaaaa'>".to_string();
+        for i in 1..10 {
+            let refs = format!("&a{};", i - 1).repeat(10);
+            xml.push_str(&format!("<!ENTITY a{} '{}'>", i, refs));
+        }
+        xml.push_str("]><root>&a9;</root>");
+        let (_, doc) = XmlDocument::from_raw(xml.as_str()).unwrap();
+        assert!(doc.document_element().is_ok());
+        let err = XmlDocument::from_raw_with_context(xml.as_str(), Context::secure())
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::EntityExpansionLimitExceeded(64 * 1024), err);
+
+        let xml = "<!DOCTYPE root [<!ENTITY a '&b;'><!ENTITY b '&a;'>]><root />";
+        let err = XmlDocument::from_raw_with_context(xml, Context::secure())
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::EntityExpansionLimitExceeded(64 * 1024), err);
+
+        let context = ContextBuilder::secure().max_nesting(Some(32)).build();
+        let xml = format!("{}{}", "<a>".repeat(33), "</a>".repeat(33));
+        let err = XmlDocument::from_raw_with_context(xml.as_str(), context.clone())
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::DepthLimitExceeded(32), err);
+
+        let xml = format!("{}{}", "<a>".repeat(32), "</a>".repeat(32));
+        assert!(XmlDocument::from_raw_with_context(xml.as_str(), context.clone()).is_ok());
+
+        let xml = format!("{}<b/>{}", "<a>".repeat(32), "</a>".repeat(32));
+        let err = XmlDocument::from_raw_with_context(xml.as_str(), context)
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::DepthLimitExceeded(32), err);
+
+        // Deep enough to exhaust the stack if the limit were checked after parsing.
+        for depth in [5_000, 50_000] {
+            let xml = format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+            let err = XmlDocument::from_raw_with_context(xml.as_str(), Context::secure())
+                .err()
+                .unwrap();
+            assert_eq!(error::Error::DepthLimitExceeded(256), err);
+            let err = chunk::parse_parallel(xml.as_str(), Context::secure(), 2)
+                .err()
+                .unwrap();
+            assert_eq!(error::Error::DepthLimitExceeded(256), err);
+        }
+
+        let mut xml = "<!DOCTYPE root [<!ENTITY a0 'a'>".to_string();
+        for i in 1..20_000 {
+            xml.push_str(&format!("<!ENTITY a{} '&a{};'>", i, i - 1));
+        }
+        xml.push_str("]><root />");
+        assert!(XmlDocument::from_raw_with_context(xml.as_str(), Context::secure()).is_ok());
+
+        let xml = "<!DOCTYPE root [<!ENTITY a 'b'>]><root>&a;</root>";
+        assert!(XmlDocument::from_raw_with_context(xml, Context::secure()).is_ok());
+        let context = ContextBuilder::secure().doctype_rejected(true).build();
+        let err = XmlDocument::from_raw_with_context(xml, context.clone())
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::DoctypeRejected, err);
+        assert!(XmlDocument::from_raw_with_context("<root />", context).is_ok());
+    }
+
+    #[test]
+    fn test_document_context_entity_expansion_resolver() {
+        struct Resolver;
+
+        impl EntityResolver for Resolver {
+            fn resolve(&self, _: Option<&str>, system_id: &str) -> error::Result<Option<String>> {
+                let count = system_id
+                    .trim_end_matches(".ent")
+                    .parse()
+                    .unwrap_or_default();
+                Ok(Some("a".repeat(count)))
+            }
+        }
+
+        let xml =
+            "<!DOCTYPE root [<!ENTITY a SYSTEM '8.ent'><!ENTITY b '&a;&a;'>]><root>&b;</root>";
+        let context = Context::builder()
+            .max_entity_expansion(Some(16))
+            .resolver(Resolver)
+            .build();
+        assert!(XmlDocument::from_raw_with_context(xml, context.clone()).is_ok());
+
+        let xml = "<!DOCTYPE root [<!ENTITY a SYSTEM '17.ent'>]><root>&a;</root>";
+        let err = XmlDocument::from_raw_with_context(xml, context.clone())
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::EntityExpansionLimitExceeded(16), err);
+
+        let xml =
+            "<!DOCTYPE root [<!ENTITY a SYSTEM '9.ent'><!ENTITY b '&a;&a;'>]><root>&b;</root>";
+        let err = XmlDocument::from_raw_with_context(xml, context)
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::EntityExpansionLimitExceeded(16), err);
+    }
+
+    #[test]
+    fn test_document_context_dtd_skipped() {
+        let context = Context::builder().dtd_skipped(true).build();
+        assert!(context.dtd_skipped());
+
+        let xml = "<?xml version='1.0'?><!-- a --><root a='b'>c</root><?d?>";
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context.clone()).unwrap();
+        assert!(doc.doc_type().is_none());
+        assert_eq!(
+            "<?xml version=\"1.0\"?><!-- a --><root a=\"b\">c</root><?d?>",
+            format!("{}", doc)
+        );
+
+        let xml = "<!DOCTYPE root [<!ENTITY a 'b'>]><root>&a;</root>";
+        let err = XmlDocument::from_raw_with_context(xml, context.clone())
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::DoctypeRejected, err);
+
+        let err = XmlDocument::from_raw_with_context("<root>", context)
+            .err()
+            .unwrap();
+        assert!(matches!(err, error::Error::Parse(_)));
+    }
+
+    #[test]
+    fn test_document_context_skipped_elements() {
+        let xml = "<root><a:b xmlns:a='http://a'><c>1</c></a:b><d><c>2</c></d><c>3</c></root>";
+
+        let context = Context::builder()
+            .skipped_element_names(&["a:b", "d"])
+            .build();
+        assert!(context.skipped_elements().is_some());
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context).unwrap();
+        assert_eq!(
+            "<root><a:b xmlns:a=\"http://a\" /><d /><c>3</c></root>",
+            format!("{}", doc)
+        );
+
+        let context = Context::builder()
+            .skipped_elements(|v| v == "root")
+            .dtd_skipped(true)
+            .build();
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context.clone()).unwrap();
+        assert_eq!("<root />", format!("{}", doc));
+
+        let err = XmlDocument::from_raw_with_context("<!DOCTYPE root><root />", context)
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::DoctypeRejected, err);
+    }
+
+    #[test]
+    fn test_document_events() {
+        let xml = "<?a b?><root c=\"d\"><!-- e --><f>g<![CDATA[h]]></f><i /></root>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let events: Vec<Event> = doc.as_node().events().collect();
+        assert_eq!(
+            vec![
+                Event::StartDocument,
+                Event::PI("a".to_string(), "b".to_string()),
+                Event::StartElement("root".to_string(), vec![("c".to_string(), "d".to_string())]),
+                Event::Comment(" e ".to_string()),
+                Event::StartElement("f".to_string(), vec![]),
+                Event::Text("g".to_string()),
+                Event::CData("h".to_string()),
+                Event::EndElement("f".to_string()),
+                Event::StartElement("i".to_string(), vec![]),
+                Event::EndElement("i".to_string()),
+                Event::EndElement("root".to_string()),
+                Event::EndDocument,
+            ],
+            events
+        );
+
+        let f = doc.get_elements_by_tag_name("f").item(0).unwrap();
+        let events: Vec<Event> = f.events().collect();
+        assert_eq!(4, events.len());
+
+        let doc2 = XmlDocument::from_events(events.into_iter()).unwrap();
+        assert_eq!("<f>g<![CDATA[h]]></f>", format!("{}", doc2));
+
+        let events = doc
+            .as_node()
+            .events()
+            .filter(|v| !matches!(v, Event::Comment(_)));
+        let doc2 = XmlDocument::from_events(events).unwrap();
+        assert_eq!(
+            "<?a b?><root c=\"d\"><f>g<![CDATA[h]]></f><i /></root>",
+            format!("{}", doc2)
+        );
+
+        let events = vec![
+            Event::StartElement("a".to_string(), vec![]),
+            Event::EndElement("b".to_string()),
+        ];
+        let err = XmlDocument::from_events(events.into_iter()).err().unwrap();
+        assert_eq!(error::Error::Parse("b".to_string()), err);
+
+        let events = vec![Event::StartElement("a".to_string(), vec![])];
+        let err = XmlDocument::from_events(events.into_iter()).err().unwrap();
+        assert_eq!(error::Error::Parse("a".to_string()), err);
+
+        let events = vec![
+            Event::StartElement("a".to_string(), vec![("b".to_string(), "c&".to_string())]),
+            Event::EndElement("a".to_string()),
+        ];
+        let doc2 = XmlDocument::from_events(events.into_iter()).unwrap();
+        assert_eq!("<a b=\"c&amp;\" />", format!("{}", doc2));
+    }
+
+    #[test]
+    fn test_event_writer() {
+        let xml = "<?a b?><root c=\"d&amp;&quot;\"><!-- e --><f>g&amp;&#x41;<![CDATA[h]]></f><i /></root>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let mut writer = XmlEventWriter::new(vec![]);
+        assert!(!writer.pretty());
+        writer.write_events(doc.as_node().events()).unwrap();
+        assert_eq!(xml, String::from_utf8(writer.into_inner()).unwrap());
+
+        let xml = "<?a b?><root c=\"d\">\n  <!-- e --><f>g</f><h><i /></h>\n</root>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let mut writer = XmlEventWriter::new(vec![]);
+        writer.set_pretty(true);
+        writer.write_events(doc.as_node().events()).unwrap();
+        let mut expected = vec![];
+        doc.pretty(&mut expected).unwrap();
+        assert_eq!(
+            String::from_utf8(expected).unwrap(),
+            String::from_utf8(writer.into_inner()).unwrap()
+        );
+
+        let mut writer = XmlEventWriter::new(vec![]);
+        let events = vec![
+            Event::StartElement("a".to_string(), vec![]),
+            Event::Text("<b>".to_string()),
+        ];
+        writer.write_events(events.into_iter()).unwrap();
+        assert!(writer.write(&Event::EndElement("b".to_string())).is_err());
+        assert_eq!(
+            "<a>&lt;b&gt;",
+            String::from_utf8(writer.into_inner()).unwrap()
+        );
+
+        let mut writer = XmlEventWriter::new(vec![]);
+        writer.set_html(true);
+        assert!(writer.html());
+        let events = vec![
+            Event::StartElement(
+                "input".to_string(),
+                vec![
+                    ("checked".to_string(), "checked".to_string()),
+                    ("value".to_string(), "".to_string()),
+                ],
+            ),
+            Event::EndElement("input".to_string()),
+            Event::StartElement("div".to_string(), vec![]),
+            Event::EndElement("div".to_string()),
+            Event::StartElement("style".to_string(), vec![]),
+            Event::Text("a > b {}".to_string()),
+            Event::EndElement("style".to_string()),
+            Event::CData("<c>".to_string()),
+        ];
+        writer.write_events(events.into_iter()).unwrap();
+        assert_eq!(
+            "<input checked value=\"\"><div></div><style>a > b {}</style>&lt;c&gt;",
+            String::from_utf8(writer.into_inner()).unwrap()
+        );
+
+        let mut writer = XmlEventWriter::new(vec![]);
+        writer.write(&Event::CData("a]]>b".to_string())).unwrap();
+        assert_eq!(
+            "<![CDATA[a]]]]><![CDATA[>b]]>",
+            String::from_utf8(writer.into_inner()).unwrap()
+        );
+
+        let xml = "<r a=\"\u{E9}\u{3042}\">\u{E9}&amp;\u{1F600}<![CDATA[<\u{3042}>]]></r>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let mut writer = XmlEventWriter::new(vec![]);
+        assert_eq!(Repertoire::Unicode, writer.repertoire());
+        writer.set_repertoire(Repertoire::Ascii);
+        writer.write_events(doc.as_node().events()).unwrap();
+        assert_eq!(
+            "<r a=\"&#xE9;&#x3042;\">&#xE9;&amp;&#x1F600;<![CDATA[<]]>&#x3042;<![CDATA[>]]></r>",
+            String::from_utf8(writer.into_inner()).unwrap()
+        );
+
+        let mut writer = XmlEventWriter::new(vec![]);
+        writer.set_repertoire(Repertoire::Latin1);
+        writer.set_hex_char_ref(false);
+        assert!(!writer.hex_char_ref());
+        writer.write_events(doc.as_node().events()).unwrap();
+        assert_eq!(
+            "<r a=\"\u{E9}&#12354;\">\u{E9}&amp;&#128512;<![CDATA[<]]>&#12354;<![CDATA[>]]></r>",
+            String::from_utf8(writer.into_inner()).unwrap()
+        );
+
+        let mut writer = XmlEventWriter::new(vec![]);
+        writer.set_repertoire(Repertoire::Ascii);
+        let err = writer
+            .write(&Event::StartElement("\u{E9}".to_string(), vec![]))
+            .err()
+            .unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        let err = writer
+            .write(&Event::Comment("\u{E9}".to_string()))
+            .err()
+            .unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn test_document_histogram() {
+        let xml = "<f:feed xmlns:f=\"urn:f\" xmlns:x=\"urn:x\"><f:entry id=\"1\" x:a=\"2\"><title /></f:entry><f:entry id=\"2\"><title /><x:title /></f:entry></f:feed>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let name = |ns: Option<&str>, name: &str| QName::new(name, None, ns);
+
+        let tags = doc.tag_histogram().unwrap();
+        assert_eq!(4, tags.len());
+        assert_eq!(Some(&1), tags.get(&name(Some("urn:f"), "feed")));
+        assert_eq!(Some(&2), tags.get(&name(Some("urn:f"), "entry")));
+        assert_eq!(Some(&2), tags.get(&name(None, "title")));
+        assert_eq!(Some(&1), tags.get(&name(Some("urn:x"), "title")));
+
+        let attrs = doc.attribute_histogram().unwrap();
+        assert_eq!(2, attrs.len());
+        assert_eq!(Some(&2), attrs.get(&name(None, "id")));
+        assert_eq!(Some(&1), attrs.get(&name(Some("urn:x"), "a")));
+
+        assert!(XmlDocument::new().tag_histogram().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_document_to_dot() {
+        let (_, doc) = XmlDocument::from_raw("<r a=\"1\">t\"x<!--c--><e /></r>").unwrap();
+        let expected = r#"digraph xml {
+    node [shape=box];
+    n0 [label="Document\n#document\nid=1 order=1"];
+    n1 [label="Element\nr\nid=2 order=2"];
+    n0 -> n1;
+    n2 [label="Attribute\na\nid=3 order=3\n1"];
+    n1 -> n2 [style=dashed];
+    n3 [label="Text\n#text\nid=5 order=5\nt\"x"];
+    n1 -> n3;
+    n4 [label="Comment\n#comment\nid=6 order=6\nc"];
+    n1 -> n4;
+    n5 [label="Element\ne\nid=7 order=7"];
+    n1 -> n5;
+}
+"#;
+        assert_eq!(expected, doc.to_dot().unwrap());
+
+        let (_, doc) = XmlDocument::from_raw("<r>0123456789012345678901</r>").unwrap();
+        assert!(doc
+            .to_dot()
+            .unwrap()
+            .contains("\\n01234567890123456789...\"]"));
+    }
+
+    #[test]
+    fn test_document_from_html() {
+        let doc = XmlDocument::from_html(
+            "<!DOCTYPE html><HTML><Body><P CLASS=x>a<p>b&amp;c&nbsp;d<ul><li>1<li>2</ul>\
+             <br><img src=\"i.png\"><input disabled>\
+             <script>if (a < b) {}</script><!-- x -- y --></body></html>",
+        )
+        .unwrap();
+        assert_eq!(
+            "<html><body><p class=\"x\">a</p><p>b&amp;c\u{a0}d</p><ul><li>1</li><li>2</li></ul>\
+             <br /><img src=\"i.png\" /><input disabled=\"\" />\
+             <script>if (a &lt; b) {}</script><!-- x - - y --></body></html>",
+            doc.to_string()
+        );
+
+        let doc = XmlDocument::from_html("text <b>bold</i> &#x41;&unknown;").unwrap();
+        assert_eq!(
+            "<html>text <b>bold A&amp;unknown;</b></html>",
+            doc.to_string()
+        );
+
+        let doc = XmlDocument::from_html("").unwrap();
+        assert_eq!("<html />", doc.to_string());
+    }
+
+    #[test]
+    fn test_document_to_html() {
+        let html = "<html><body><p class=\"x\">a&amp;b<br><input disabled></p>\
+                    <script>if (a < b) {}</script><div></div></body></html>";
+        let doc = XmlDocument::from_html(html).unwrap();
+        assert_eq!(html, doc.to_html().unwrap());
+
+        let (_, doc) = XmlDocument::from_raw("<a selected=\"no\"><b /></a>").unwrap();
+        assert_eq!("<a selected=\"no\"><b></b></a>", doc.to_html().unwrap());
+    }
+
+    #[test]
+    fn test_template() {
+        let t = template::Template::new(
+            "<a href=\"/u/{{ id }}\" title=\"{{name}}\">Hi {{name}}!{{body}}{{}}</a>",
+        )
+        .unwrap();
+        assert_eq!(vec!["body", "id", "name"], t.placeholders());
+
+        let (_, body) = XmlDocument::from_raw("<b>x</b>").unwrap();
+        let mut bindings = HashMap::new();
+        bindings.insert("id".to_string(), template::Binding::from("1&2"));
+        bindings.insert("name".to_string(), "<i>\"a\"</i>".into());
+        bindings.insert(
+            "body".to_string(),
+            body.document_element().unwrap().as_node().into(),
+        );
+        let doc = t.instantiate(&bindings).unwrap();
+        assert_eq!(
+            "<a href=\"/u/1&amp;2\" title=\"&lt;i>&quot;a&quot;&lt;/i>\">\
+             Hi &lt;i>\"a\"&lt;/i>!<b>x</b>{{}}</a>",
+            doc.to_string()
+        );
+
+        bindings.remove("body");
+        assert_eq!(
+            Err(error::Error::UnboundPlaceholder("body".to_string())),
+            t.instantiate(&bindings).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_document_type_allowed_children() {
+        let (_, doc) = XmlDocument::from_raw(
+            "<!DOCTYPE r [<!ELEMENT r (h, (a | b)*, f?)><!ELEMENT a (#PCDATA | i | e)*>\
+             <!ELEMENT e EMPTY><!ELEMENT x ANY>]><r><h /><a /><f /></r>",
+        )
+        .unwrap();
+        let doctype = doc.doc_type().unwrap();
+        let root = doc.document_element().unwrap();
+
+        let allowed = |e: &XmlElement, i: usize| doctype.allowed_children(e, i).unwrap();
+        let names = |v: &[&str]| Some(v.iter().map(|v| v.to_string()).collect::<Vec<_>>());
+        assert_eq!(names(&[]), allowed(&root, 0));
+        assert_eq!(names(&["a", "b"]), allowed(&root, 1));
+        assert_eq!(names(&["a", "b"]), allowed(&root, 2));
+        assert_eq!(names(&[]), allowed(&root, 3));
+
+        let r = doc.create_element("r").unwrap();
+        assert_eq!(names(&["h"]), allowed(&r, 0));
+        r.append_element("h").unwrap();
+        assert_eq!(names(&["a", "b", "f"]), allowed(&r, 1));
+
+        let a = root.child_elements()[1].clone();
+        assert_eq!(names(&["e", "i"]), allowed(&a, 0));
+
+        let e = doc.create_element("e").unwrap();
+        assert_eq!(names(&[]), allowed(&e, 0));
+
+        let x = doc.create_element("x").unwrap();
+        assert_eq!(names(&["a", "e", "r", "x"]), allowed(&x, 0));
+
+        let y = doc.create_element("y").unwrap();
+        assert_eq!(None, allowed(&y, 0));
+
+        assert_eq!(
+            Err(error::Error::Dom(error::DomException::IndexSizeErr)),
+            doctype.allowed_children(&root, 4)
+        );
+    }
+
+    #[test]
+    fn test_document_type_allowed_attributes() {
+        let (_, doc) = XmlDocument::from_raw(
+            "<!DOCTYPE r [\
+             <!ATTLIST r id ID #REQUIRED k (a | b) 'a' v CDATA #FIXED 'x&#x41;'>\
+             <!ATTLIST r k CDATA #IMPLIED n NOTATION (g) #IMPLIED>\
+             <!ATTLIST s t CDATA #IMPLIED>]><r id=\"i\" />",
+        )
+        .unwrap();
+        let doctype = doc.doc_type().unwrap();
+
+        let attributes = doctype.allowed_attributes("r").unwrap();
+        let names = attributes.iter().map(|v| v.name()).collect::<Vec<&str>>();
+        assert_eq!(vec!["id", "k", "v", "n"], names);
+
+        assert_eq!(
+            &info::XmlDeclarationAttType::Id,
+            attributes[0].attribute_type()
+        );
+        assert_eq!(None, attributes[0].enumerated_values());
+        assert_eq!(&AttributeDefault::Required, attributes[0].default());
+
+        let values = ["a", "b"].map(|v| v.to_string());
+        assert_eq!(Some(&values[..]), attributes[1].enumerated_values());
+        assert_eq!(
+            &AttributeDefault::Value("a".to_string()),
+            attributes[1].default()
+        );
+
+        assert_eq!(
+            &info::XmlDeclarationAttType::CData,
+            attributes[2].attribute_type()
+        );
+        assert_eq!(
+            &AttributeDefault::Fixed("xA".to_string()),
+            attributes[2].default()
+        );
+
+        let values = ["g".to_string()];
+        assert_eq!(Some(&values[..]), attributes[3].enumerated_values());
+        assert_eq!(&AttributeDefault::Implied, attributes[3].default());
+
+        assert_eq!(1, doctype.allowed_attributes("s").unwrap().len());
+        assert!(doctype.allowed_attributes("u").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_document_node_at_offset() {
+        let xml = "<?a b?><root x=\"1\" y='2'>t&amp;<e/><!--c--><![CDATA[d]]>&#x41;</root><!--z-->";
+        let context = Context::builder().spans_tracked(true).build();
+        assert!(context.spans_tracked());
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context).unwrap();
+
+        let at = |offset: usize| {
+            doc.node_at_offset(offset)
+                .map(|(n, a)| (n.node_name(), a.map(|v| v.name())))
+        };
+        assert_eq!(Some(("a".to_string(), None)), at(0));
+        assert_eq!(Some(("root".to_string(), None)), at(7));
+        assert_eq!(Some(("root".to_string(), Some("x".to_string()))), at(13));
+        assert_eq!(Some(("root".to_string(), Some("y".to_string()))), at(19));
+        assert_eq!(Some(("root".to_string(), None)), at(24));
+        assert_eq!(Some(("#text".to_string(), None)), at(25));
+        assert_eq!(Some(("amp".to_string(), None)), at(27));
+        assert_eq!(Some(("e".to_string(), None)), at(32));
+        assert_eq!(Some(("#comment".to_string(), None)), at(36));
+        assert_eq!(Some(("#cdata-section".to_string(), None)), at(45));
+        assert_eq!(Some(("root".to_string(), None)), at(66));
+        assert_eq!(Some(("#comment".to_string(), None)), at(xml.len() - 1));
+        assert_eq!(None, at(xml.len()));
+
+        let context = Context::builder()
+            .spans_tracked(true)
+            .eol_normalized(true)
+            .build();
+        let (_, doc) = XmlDocument::from_raw_with_context("<r>\r\n<e />\r\n</r>", context).unwrap();
+        let name = |offset: usize| doc.node_at_offset(offset).unwrap().0.node_name();
+        assert_eq!("e", name(5));
+        assert_eq!("e", name(9));
+        assert_eq!("#text", name(10));
+        assert_eq!("r", name(15));
+
+        let context = Context::builder()
+            .spans_tracked(true)
+            .skipped_element_names(&["e"])
+            .build();
+        let (_, doc) =
+            XmlDocument::from_raw_with_context("<r><e><e/>x</e><f/></r>", context).unwrap();
+        let name = |offset: usize| doc.node_at_offset(offset).unwrap().0.node_name();
+        assert_eq!("e", name(14));
+        assert_eq!("f", name(15));
+
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        assert_eq!(None, doc.node_at_offset(0));
+    }
+
+    #[test]
+    fn test_node_source_span() {
+        let xml = "<?pi x?>\n<p:root a = '1' xmlns:p='u'>\n  <e/>t<?q?><!--c--></p:root >";
+        let context = Context::builder().spans_tracked(true).build();
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context).unwrap();
+
+        let text = |span: Option<info::SourceSpan>| span.map(|v| &xml[v.range()]);
+        let name = |span: Option<info::SourceSpan>| span.and_then(|v| v.name()).map(|v| &xml[v]);
+
+        let span = doc.as_node().source_span();
+        assert_eq!(Some(xml), text(span));
+
+        let pi = doc.child_nodes().item(0).unwrap();
+        assert_eq!(Some("<?pi x?>"), text(pi.source_span()));
+        assert_eq!(Some("pi"), name(pi.source_span()));
+
+        let root = doc.document_element().unwrap();
+        let span = root.as_node().source_span();
+        assert_eq!(Some(&xml[9..]), text(span.clone()));
+        assert_eq!(Some("p:root"), name(span.clone()));
+        assert_eq!(9, span.as_ref().unwrap().start());
+        assert_eq!(xml.len(), span.unwrap().end());
+
+        let attr = root.get_attribute_node("a").unwrap().as_node();
+        assert_eq!(Some("a = '1'"), text(attr.source_span()));
+        assert_eq!(Some("a"), name(attr.source_span()));
+
+        let children = root.child_nodes();
+        let spans = (0..children.length())
+            .map(|i| text(children.item(i).unwrap().source_span()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Some("\n  "),
+                Some("<e/>"),
+                Some("t"),
+                Some("<?q?>"),
+                Some("<!--c-->")
+            ],
+            spans
+        );
+        assert_eq!(Some("e"), name(children.item(1).unwrap().source_span()));
+        assert_eq!(None, name(children.item(4).unwrap().source_span()));
+
+        let e = doc.create_element("n").unwrap();
+        assert_eq!(None, e.as_node().source_span());
+
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        assert_eq!(None, doc.as_node().source_span());
+    }
+
+    #[test]
+    fn test_document_to_preserved_string() {
+        let xml = "<?xml version='1.0'?>\n<!DOCTYPE root [ <!ENTITY e 'v'> ]>\n<root  a = '1' >\n  <x b='2'>t</x>\n  <y   c=\"3\"/>\n  <z><w/></z>\n</root >\n";
+        let context = Context::builder().spans_tracked(true).build();
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context).unwrap();
+        assert_eq!(xml, doc.to_preserved_string());
+
+        let root = doc.document_element().unwrap();
+        let x = root
+            .get_elements_by_tag_name("x")
+            .item(0)
+            .unwrap()
+            .as_element()
+            .unwrap();
+        x.set_attribute("b", "4").unwrap();
+        let expected = xml.replace("<x b='2'>", "<x b=\"4\">");
+        assert_eq!(expected, doc.to_preserved_string());
+
+        let w = root
+            .get_elements_by_tag_name("w")
+            .item(0)
+            .unwrap()
+            .as_element()
+            .unwrap();
+        w.append_child(doc.create_text_node("n").as_node()).unwrap();
+        let expected = expected.replace("<w/>", "<w>n</w>");
+        assert_eq!(expected, doc.to_preserved_string());
+
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        assert_eq!(doc.to_string(), doc.to_preserved_string());
+    }
+
+    #[test]
+    fn test_node_language() {
+        let xml = "<root xml:lang='en'><a><b/></a><c xml:lang='en-US' d='1'/><e xml:lang='fr'>t</e><f xml:lang=''/></root>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+
+        let root = doc.document_element().unwrap();
+        assert_eq!(Some("en".to_string()), root.as_node().language());
+        assert_eq!(None, doc.as_node().language());
+
+        let find = |name: &str| root.get_elements_by_tag_name(name).item(0).unwrap();
+        assert_eq!(Some("en".to_string()), find("b").language());
+        assert_eq!(Some("en-US".to_string()), find("c").language());
+        assert_eq!(None, find("f").language());
+
+        let e = find("e");
+        assert_eq!(Some("fr".to_string()), e.first_child().unwrap().language());
+
+        let c = find("c").as_element().unwrap();
+        let d = c.get_attribute_node("d").unwrap();
+        assert_eq!(Some("en-US".to_string()), d.as_node().language());
+
+        let names = |tag: &str| {
+            doc.elements_by_language(tag)
+                .unwrap()
+                .iter()
+                .map(|v| v.tag_name())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(vec!["root", "a", "b", "c"], names("EN"));
+        assert_eq!(vec!["c"], names("en-us"));
+        assert_eq!(vec!["e"], names("fr"));
+        assert!(names("e").is_empty());
+
+        let c = c.elements_by_language("en").unwrap();
+        assert_eq!(1, c.len());
+    }
+
+    #[test]
+    fn test_node_inherited_attribute() {
+        let xml = "<root xmlns:p='urn:p' xml:space='preserve' p:mode='a' mode='x'><a xml:space='default'><b p:mode='b'/></a><c><d/></c></root>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+
+        let root = doc.document_element().unwrap();
+        let find = |name: &str| root.get_elements_by_tag_name(name).item(0).unwrap();
+        assert_eq!(
+            Some("preserve".to_string()),
+            find("d").inherited_attribute("xml:space")
+        );
+        assert_eq!(
+            Some("default".to_string()),
+            find("b").inherited_attribute("xml:space")
+        );
+        assert!(find("d").space_preserved());
+        assert!(!find("b").space_preserved());
+        assert!(!doc.as_node().space_preserved());
+
+        assert_eq!(
+            Some("b".to_string()),
+            find("b").inherited_attribute("p:mode")
+        );
+        assert_eq!(
+            Some("a".to_string()),
+            find("d").inherited_attribute("p:mode")
+        );
+        assert_eq!(Some("x".to_string()), find("b").inherited_attribute("mode"));
+        assert_eq!(None, find("b").inherited_attribute("q:mode"));
+        assert_eq!(None, find("b").inherited_attribute("1mode"));
+
+        let b = find("b").as_element().unwrap();
+        let mode = b.get_attribute_node("mode").unwrap();
+        assert_eq!(
+            Some("default".to_string()),
+            mode.as_node().inherited_attribute("xml:space")
+        );
+    }
+
+    #[test]
+    fn test_node_typed_value() {
+        use schema::{SchemaType, TypedValue};
+
+        let xml =
+            "<root n=' 42 ' f='true'><d>1.5e2</d><t> 2024-02-29T10:00:00Z </t><c><x/></c></root>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let root = doc.document_element().unwrap();
+        let n = root.get_attribute_node("n").unwrap();
+        let f = root.get_attribute_node("f").unwrap();
+        let find = |name: &str| root.get_elements_by_tag_name(name).item(0).unwrap();
+
+        assert_eq!(None, n.as_node().type_annotation());
+        assert_eq!(
+            TypedValue::String(" 42 ".to_string()),
+            n.as_node().typed_value().unwrap()
+        );
+
+        n.set_type_annotation(Some(SchemaType::Int));
+        f.set_type_annotation(Some(SchemaType::Boolean));
+        assert_eq!(Some(SchemaType::Int), n.as_node().type_annotation());
+        assert_eq!(TypedValue::Integer(42), n.as_node().typed_value().unwrap());
+        assert_eq!(
+            TypedValue::Boolean(true),
+            f.as_node().typed_value().unwrap()
+        );
+
+        n.set_type_annotation(Some(SchemaType::UnsignedByte));
+        assert_eq!(TypedValue::Integer(42), n.as_node().typed_value().unwrap());
+        n.set_type_annotation(Some(SchemaType::NegativeInteger));
+        assert!(n.as_node().typed_value().is_err());
+
+        let d = find("d").as_element().unwrap();
+        d.set_type_annotation(Some(SchemaType::Double));
+        assert_eq!(
+            TypedValue::Double(150f64),
+            d.as_node().typed_value().unwrap()
+        );
+        d.set_type_annotation(Some(SchemaType::Decimal));
+        assert!(d.as_node().typed_value().is_err());
+
+        let t = find("t").as_element().unwrap();
+        t.set_type_annotation(Some(SchemaType::DateTime));
+        assert_eq!(
+            TypedValue::DateTime(schema::DateTime {
+                year: 2024,
+                month: 2,
+                day: 29,
+                hour: 10,
+                minute: 0,
+                second: 0,
+                nanosecond: 0,
+                timezone: Some(0),
+            }),
+            t.as_node().typed_value().unwrap()
+        );
+        t.set_type_annotation(Some(SchemaType::Date));
+        assert!(t.as_node().typed_value().is_err());
+
+        let c = find("c").as_element().unwrap();
+        c.set_type_annotation(Some(SchemaType::String));
+        assert!(c.as_node().typed_value().is_err());
+        c.set_type_annotation(None);
+        assert_eq!(None, c.as_node().type_annotation());
+
+        assert_eq!(" 42 ", root.get_attribute("n"));
+        n.set_type_annotation(Some(SchemaType::Int));
+        assert_eq!("42", n.as_node().normalized_value().unwrap());
+        let (_, other) = XmlDocument::from_raw("<root n='\t+42\n'/>").unwrap();
+        let other = other
+            .document_element()
+            .unwrap()
+            .get_attribute_node("n")
+            .unwrap();
+        other.set_type_annotation(Some(SchemaType::Int));
+        assert_eq!(
+            n.as_node().typed_value().unwrap(),
+            other.as_node().typed_value().unwrap()
+        );
+
+        let (_, other) = XmlDocument::from_raw("<v>\ta\n b </v>").unwrap();
+        let v = other.document_element().unwrap();
+        v.set_type_annotation(Some(SchemaType::NormalizedString));
+        assert_eq!(" a  b ", v.as_node().normalized_value().unwrap());
+        v.set_type_annotation(Some(SchemaType::Token));
+        assert_eq!("a b", v.as_node().normalized_value().unwrap());
+        v.set_type_annotation(Some(SchemaType::String));
+        assert_eq!("\ta\n b ", v.as_node().normalized_value().unwrap());
+        assert_eq!(
+            SchemaType::Token.white_space(),
+            schema::WhiteSpace::Collapse
+        );
+        assert_eq!("a  b", schema::WhiteSpace::Replace.apply("a\t\nb"));
+        assert_eq!("a b", schema::WhiteSpace::Collapse.apply(" a\t\nb "));
+
+        assert_eq!(Some(SchemaType::Time), SchemaType::from_name("time"));
+        assert_eq!("unsignedLong", SchemaType::UnsignedLong.name());
+        assert_eq!(
+            TypedValue::String("a b".to_string()),
+            SchemaType::Token.parse(" a \n b ").unwrap()
+        );
+        assert_eq!(
+            TypedValue::Time(schema::Time {
+                hour: 24,
+                minute: 0,
+                second: 0,
+                nanosecond: 0,
+                timezone: Some(540),
+            }),
+            SchemaType::Time.parse("24:00:00+09:00").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_schema_date_time() {
+        use schema::{SchemaType, TypedValue};
+
+        let parse = |ty: SchemaType, value: &str| match ty.parse(value) {
+            Ok(TypedValue::Date(v)) => Some(v.to_string()),
+            Ok(TypedValue::DateTime(v)) => Some(v.to_string()),
+            Ok(TypedValue::Time(v)) => Some(v.to_string()),
+            _ => None,
+        };
+
+        let date = match SchemaType::Date.parse("-0044-03-15-05:30").unwrap() {
+            TypedValue::Date(v) => v,
+            v => unreachable!("{:?}", v),
+        };
+        assert_eq!(-44, date.year);
+        assert_eq!((3, 15), (date.month, date.day));
+        assert_eq!(Some(-330), date.timezone);
+        assert_eq!("-0044-03-15-05:30", date.to_string());
+        assert_eq!(
+            Some("12345-01-01".to_string()),
+            parse(SchemaType::Date, "12345-01-01")
+        );
+        assert_eq!(None, parse(SchemaType::Date, "2023-02-29"));
+        assert_eq!(None, parse(SchemaType::Date, "2024-01-01+15:00"));
+
+        let time = match SchemaType::Time.parse("10:20:30.1234567891+00:00").unwrap() {
+            TypedValue::Time(v) => v,
+            v => unreachable!("{:?}", v),
+        };
+        assert_eq!((10, 20, 30), (time.hour, time.minute, time.second));
+        assert_eq!(123456789, time.nanosecond);
+        assert_eq!(Some(0), time.timezone);
+        assert_eq!("10:20:30.123456789Z", time.to_string());
+        assert_eq!(
+            Some("10:20:30.5".to_string()),
+            parse(SchemaType::Time, "10:20:30.500")
+        );
+        assert_eq!(None, parse(SchemaType::Time, "24:00:00.1"));
+
+        let date_time = match SchemaType::DateTime
+            .parse("2024-02-29T23:59:60")
+            .unwrap_err()
+        {
+            error::Error::InvalidTypedValue(v) => v,
+            v => unreachable!("{:?}", v),
+        };
+        assert_eq!("dateTime: 2024-02-29T23:59:60", date_time);
+        let date_time = match SchemaType::DateTime
+            .parse("2024-02-29T10:00:00-09:00")
+            .unwrap()
+        {
+            TypedValue::DateTime(v) => v,
+            v => unreachable!("{:?}", v),
+        };
+        assert_eq!("2024-02-29-09:00", date_time.date().to_string());
+        assert_eq!("10:00:00-09:00", date_time.time().to_string());
+        assert_eq!("2024-02-29T10:00:00-09:00", date_time.to_string());
+        assert_eq!(None, parse(SchemaType::DateTime, "2024-02-29ZT10:00:00"));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_schema_date_time_chrono() {
+        use schema::{SchemaType, TypedValue};
+
+        let date_time = match SchemaType::DateTime
+            .parse("2024-02-29T24:00:00+09:00")
+            .unwrap()
+        {
+            TypedValue::DateTime(v) => v,
+            v => unreachable!("{:?}", v),
+        };
+        assert_eq!(
+            chrono::NaiveDate::from_ymd_opt(2024, 2, 29).unwrap(),
+            chrono::NaiveDate::try_from(date_time.date()).unwrap()
+        );
+        assert_eq!(
+            chrono::NaiveTime::MIN,
+            chrono::NaiveTime::try_from(date_time.time()).unwrap()
+        );
+        let value = chrono::DateTime::<chrono::FixedOffset>::try_from(date_time).unwrap();
+        assert_eq!(9 * 3600, value.offset().local_minus_utc());
+        assert_eq!(
+            chrono::NaiveDate::from_ymd_opt(2024, 2, 29)
+                .unwrap()
+                .and_hms_opt(15, 0, 0)
+                .unwrap(),
+            value.naive_utc()
+        );
+
+        let date_time = match SchemaType::DateTime.parse("2024-02-29T10:00:00").unwrap() {
+            TypedValue::DateTime(v) => v,
+            v => unreachable!("{:?}", v),
+        };
+        assert_eq!(
+            "2024-02-29 10:00:00",
+            chrono::NaiveDateTime::try_from(date_time)
+                .unwrap()
+                .to_string()
+        );
+        assert!(chrono::DateTime::<chrono::FixedOffset>::try_from(date_time).is_err());
+    }
+
+    #[test]
+    fn test_deserialize() {
+        use deserialize::{XmlDeserialize, XmlElementReader};
+
+        #[derive(Debug, PartialEq)]
+        struct Item {
+            sku: String,
+            qty: u32,
+            note: Option<String>,
+        }
+
+        impl XmlDeserialize for Item {
+            fn deserialize(reader: &XmlElementReader) -> error::Result<Self> {
+                Ok(Item {
+                    sku: reader.attribute("sku")?,
+                    qty: reader.attribute("qty")?,
+                    note: reader.optional_child("note")?,
+                })
+            }
+        }
+
+        #[derive(Debug, PartialEq)]
+        struct Order {
+            id: u64,
+            customer: String,
+            items: Vec<Item>,
+        }
+
+        impl XmlDeserialize for Order {
+            fn deserialize(reader: &XmlElementReader) -> error::Result<Self> {
+                Ok(Order {
+                    id: reader.attribute("id")?,
+                    customer: reader.child("customer")?,
+                    items: reader.children("item")?,
+                })
+            }
+        }
+
+        let xml = "<order id='7'><customer>c</customer><item sku='a' qty='1'><note>n</note></item><item sku='b' qty='2'/></order>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let order: Order = deserialize::from_element(&doc.document_element().unwrap()).unwrap();
+        assert_eq!(
+            Order {
+                id: 7,
+                customer: "c".to_string(),
+                items: vec![
+                    Item {
+                        sku: "a".to_string(),
+                        qty: 1,
+                        note: Some("n".to_string()),
+                    },
+                    Item {
+                        sku: "b".to_string(),
+                        qty: 2,
+                        note: None,
+                    },
+                ],
+            },
+            order
+        );
+
+        let xml = "<order id='7'><customer>c</customer><item sku='a' qty='1'/><item sku='b' qty='x'/></order>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let err = deserialize::from_element::<Order>(&doc.document_element().unwrap());
+        assert_eq!(
+            Err(error::Error::Deserialize(
+                "/order[1]/item[2]/@qty: invalid digit found in string".to_string()
+            )),
+            err
+        );
+
+        let xml = "<r><order id='7'/><order id='8'/></r>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let order = doc.get_elements_by_tag_name("order").item(1).unwrap();
+        let err = deserialize::from_element::<Order>(&order.as_element().unwrap());
+        assert_eq!(
+            Err(error::Error::Deserialize(
+                "/r[1]/order[2]: missing element `customer`".to_string()
+            )),
+            err
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        use deserialize::{XmlDeserialize, XmlElementReader};
+        use serialize::{XmlElementWriter, XmlSerialize};
+
+        #[derive(Debug, PartialEq)]
+        struct Item {
+            sku: String,
+            qty: u32,
+            note: Option<String>,
+        }
+
+        impl XmlSerialize for Item {
+            fn serialize(&self, writer: &XmlElementWriter) -> error::Result<()> {
+                writer.attribute("sku", &self.sku)?;
+                writer.attribute("qty", self.qty)?;
+                writer.optional_child("note", self.note.as_ref())
+            }
+        }
+
+        impl XmlDeserialize for Item {
+            fn deserialize(reader: &XmlElementReader) -> error::Result<Self> {
+                Ok(Item {
+                    sku: reader.attribute("sku")?,
+                    qty: reader.attribute("qty")?,
+                    note: reader.optional_child("note")?,
+                })
+            }
+        }
+
+        struct Order {
+            items: Vec<Item>,
+        }
+
+        impl XmlSerialize for Order {
+            fn serialize(&self, writer: &XmlElementWriter) -> error::Result<()> {
+                writer.attribute_ns("http://e/", "e:id", 7)?;
+                writer.children("item", &self.items)?;
+                writer.child_ns("http://o/", "total", "3")
+            }
+        }
+
+        let items = vec![
+            Item {
+                sku: "a".to_string(),
+                qty: 1,
+                note: Some("x & y".to_string()),
+            },
+            Item {
+                sku: "b".to_string(),
+                qty: 2,
+                note: None,
+            },
+        ];
+        let order = Order { items };
+
+        let doc = serialize::to_document_ns(Some("http://o/"), "order", &order).unwrap();
+        assert_eq!(
+            "<order xmlns=\"http://o/\" xmlns:e=\"http://e/\" e:id=\"7\"><item sku=\"a\" qty=\"1\"><note>x &amp; y</note></item><item sku=\"b\" qty=\"2\" /><total>3</total></order>",
+            doc.to_string()
+        );
+
+        let root = doc.document_element().unwrap();
+        let reader = XmlElementReader::new(root);
+        let items: Vec<Item> = reader.children("item").unwrap();
+        assert_eq!(order.items, items);
+
+        let doc = serialize::to_document("n", &5u8).unwrap();
+        assert_eq!("<n>5</n>", doc.to_string());
+        assert_eq!(
+            Err(error::Error::Dom(error::DomException::NamespaceErr)),
+            serialize::to_document("p:n", &5u8).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_validate_dtd() {
+        let xml = "<!DOCTYPE order [\
+            <!ELEMENT order (item+, total)>\
+            <!ELEMENT item EMPTY>\
+            <!ATTLIST item sku CDATA #REQUIRED qty (1|2|3) '1' v CDATA #FIXED 'x'>\
+            <!ELEMENT total (#PCDATA)>]>\
+            <order><item sku='a'/><item qty='9' v='y' w='z'/>t<total><b/></total><?p d?><!--c--></order>";
+        let context = Context::builder().spans_tracked(true).build();
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context).unwrap();
+
+        let diagnostics = validation::validate_dtd(&doc).unwrap();
+        let messages = diagnostics
+            .iter()
+            .map(|v| format!("{} {}", v.path(), v.message()))
+            .collect::<Vec<String>>();
+        assert_eq!(
+            vec![
+                "/order[1]/text()[1] character data is not allowed here",
+                "/order[1]/item[2] missing required attribute `sku`",
+                "/order[1]/item[2]/@qty value `9` is not one of [\"1\", \"2\", \"3\"]",
+                "/order[1]/item[2]/@v value must be `x`",
+                "/order[1]/item[2]/@w attribute `w` is not declared",
+                "/order[1]/total[1]/b[1] element `b` is not allowed here",
+                "/order[1]/total[1]/b[1] element `b` is not declared",
+            ],
+            messages
+        );
+
+        let root = doc.document_element().unwrap();
+        let item = root.get_elements_by_tag_name("item").item(1).unwrap();
+        assert_eq!(item.handle(), diagnostics[1].node());
+        assert_eq!(item.source_span().as_ref(), diagnostics[1].span());
+        assert!(diagnostics.iter().all(|v| v.span().is_some()));
+
+        let last = root.as_node().last_child().unwrap();
+        assert_eq!("/order[1]/comment()[1]", last.location_path());
+        let pi = last.previous_sibling().unwrap();
+        assert_eq!(
+            "/order[1]/processing-instruction('p')[1]",
+            pi.location_path()
+        );
+        assert_eq!("/", doc.as_node().location_path());
+
+        let (_, doc) = XmlDocument::from_raw("<order/>").unwrap();
+        assert!(validation::validate_dtd(&doc).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_prefix_undeclaration() {
+        let xml = "<?xml version='1.1'?><r xmlns:p='http://p/'><a xmlns:p=''><b /></a></r>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        assert_eq!(
+            "<?xml version=\"1.1\"?><r xmlns:p=\"http://p/\"><a xmlns:p=\"\"><b /></a></r>",
+            doc.to_string()
+        );
+
+        let root = doc.document_element().unwrap();
+        let a = root.first_element_child().unwrap();
+        let b = a.first_element_child().unwrap();
+        let names = |e: &XmlElement| {
+            e.in_scope_namespace()
+                .unwrap()
+                .iter()
+                .map(|v| v.node_name())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(vec!["p", "xml"], names(&root));
+        assert_eq!(vec!["xml"], names(&b));
+        let err = b.append_element_ns(None, "p:c").err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NamespaceErr), err);
+
+        let c = root.append_element("c").unwrap();
+        let writer = serialize::XmlElementWriter::new(c.clone());
+        writer.undeclare_prefix("p").unwrap();
+        assert_eq!("<c xmlns:p=\"\" />", c.to_string());
+        let err = c.undeclare_namespace_prefix("xml").err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NamespaceErr), err);
+
+        let context = Context::default();
+        let err = XmlDocument::from_raw_with_context(&xml[21..], context)
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::PrefixUndeclared("p".to_string()), err);
+
+        let context = Context::builder().prefix_undeclaration(false).build();
+        let err = XmlDocument::from_raw_with_context(xml, context)
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::PrefixUndeclared("p".to_string()), err);
+
+        let context = Context::builder().namespace_aware(false).build();
+        assert!(XmlDocument::from_raw_with_context(&xml[21..], context).is_ok());
+
+        let (_, doc) = XmlDocument::from_raw("<r xmlns:p='http://p/' />").unwrap();
+        let root = doc.document_element().unwrap();
+        let err = root.undeclare_namespace_prefix("p").err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NamespaceErr), err);
+        let xmlns = info::NamespaceUri::xmlns();
+        let err = root
+            .set_attribute_ns(Some(xmlns.value()), "xmlns:p", "")
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NamespaceErr), err);
+    }
+
+    #[test]
+    fn test_node_base_uri() {
+        let xml = "<root xml:base='http://a/b/'><c xml:base='d/' href='e f.xml'><g src='/é.png' /></c><h href='#i' /></root>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        assert_eq!(None, doc.as_node().base_uri());
+
+        let root = doc.document_element().unwrap();
+        let c = root.first_element_child().unwrap();
+        let g = c.first_element_child().unwrap();
+        let h = c.next_element_sibling().unwrap();
+        assert_eq!(Some("http://a/b/d/".to_string()), c.as_node().base_uri());
+        assert_eq!(
+            Some("http://a/b/d/e%20f.xml".to_string()),
+            c.get_attribute_uri("href").unwrap()
+        );
         assert_eq!(
-            Some(XmlDocument::from(document.clone())),
-            flag.owner_document()
+            Some("http://a/%C3%A9.png".to_string()),
+            g.get_attribute_uri("src").unwrap()
         );
-        assert!(flag.has_child());
+        assert_eq!(
+            Some("http://a/b/#i".to_string()),
+            h.get_attribute_uri("href").unwrap()
+        );
+        assert_eq!(None, h.get_attribute_uri("src").unwrap());
+
+        let (_, doc) =
+            XmlDocument::from_raw("<root><c xml:base='d/' href='e.xml' /></root>").unwrap();
+        doc.set_document_uri(Some("file:///a/b.xml"));
+        let c = doc
+            .document_element()
+            .unwrap()
+            .first_element_child()
+            .unwrap();
+        let href = c.get_attribute_node("href").unwrap();
+        assert_eq!(Some("file:///a/d/".to_string()), href.as_node().base_uri());
+        assert_eq!("file:///a/d/e.xml", href.resolve_uri().unwrap());
+
+        let context = Context::builder()
+            .base_uri(Some("http://x/y/z.xml"))
+            .build();
+        let xml = "<root><c xml:base='d/'>t</c><!--e--></root>";
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context).unwrap();
+        let root = doc.document_element().unwrap();
+        let c = root.first_element_child().unwrap();
+        let text = c.first_child().unwrap().as_text().unwrap();
+        assert_eq!(Some("http://x/y/z.xml".to_string()), doc.base_uri());
+        assert_eq!(Some("http://x/y/z.xml".to_string()), root.base_uri());
+        assert_eq!(Some("http://x/y/d/".to_string()), c.base_uri());
+        assert_eq!(Some("http://x/y/d/".to_string()), text.base_uri());
+        assert_eq!(
+            Some("http://x/y/z.xml".to_string()),
+            root.last_child().unwrap().base_uri()
+        );
+
+        doc.set_document_uri(Some("http://w/v.xml"));
+        assert_eq!(Some("http://w/d/".to_string()), c.base_uri());
     }
 
     #[test]
-    fn test_document_fragment_as_node() {
-        let (_, tree) = xml_parser::document("<root></root>").unwrap();
-        let document = info::XmlDocument::new(&tree).unwrap();
+    fn test_unicode_normalized() {
+        let xml = "<r\u{301} a\u{301}='A\u{30A}'>e\u{301}<![CDATA[\u{212B}]]>\u{1100}\u{1161}\u{11A8} a\u{302}\u{323}<?p\u{301} d?></r\u{301}>";
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, Context::default()).unwrap();
+        assert_eq!(xml.replace('\'', "\""), doc.to_string());
+
+        let warnings = Rc::new(RefCell::new(vec![]));
+        let handler = warnings.clone();
+        let context = Context::builder()
+            .unicode_normalized(true)
+            .warning_handler(move |v| handler.borrow_mut().push(v.to_string()))
+            .build();
+        assert!(context.unicode_normalized());
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context).unwrap();
+        assert_eq!(
+            "<r\u{301} a\u{301}=\"\u{C5}\">\u{E9}<![CDATA[\u{C5}]]>\u{AC01} \u{1EAD}<?p\u{301} d?></r\u{301}>",
+            doc.to_string()
+        );
+        assert_eq!(
+            vec![
+                "/r\u{301}[1]: name `r\u{301}` is not in normalization form C",
+                "/r\u{301}[1]/@a\u{301}: name `a\u{301}` is not in normalization form C",
+                "/r\u{301}[1]/processing-instruction('p\u{301}')[1]: name `p\u{301}` is not in normalization form C",
+            ],
+            *warnings.borrow()
+        );
 
-        let root = XmlNode::Element(XmlElement {
-            element: document.borrow().document_element().unwrap(),
-        });
+        assert_eq!("\u{1E69}", normalization::to_nfc("s\u{307}\u{323}"));
+        assert_eq!("\u{1E0D}\u{307}", normalization::to_nfc("\u{1E0B}\u{323}"));
+        assert_eq!("\u{1E0D}\u{307}", normalization::to_nfc("d\u{323}\u{307}"));
+        assert!(normalization::is_nfc("\u{E9}t\u{E9}"));
+        assert!(!normalization::is_nfc("\u{212B}"));
+    }
 
-        let flag = XmlDocumentFragment {
-            document: document.clone(),
-            parent: Some(document.clone()),
-        };
+    #[test]
+    fn test_document_to_encoded() {
+        use encoding::{Encoding, EncodingWriter};
 
-        // AsNode
-        let node = flag.as_node();
-        assert_eq!("#document-fragment", node.node_name());
-        assert_eq!(None, node.node_value().unwrap());
-        assert_eq!(NodeType::DocumentFragment, node.node_type());
-        assert_eq!(None, node.parent_node());
-        for child in node.child_nodes().iter() {
-            assert_eq!(root, child);
-        }
-        assert_eq!(Some(root.clone()), node.first_child());
-        assert_eq!(Some(root.clone()), node.last_child());
-        assert_eq!(None, node.previous_sibling());
-        assert_eq!(None, node.next_sibling());
-        assert_eq!(None, node.attributes());
+        let xml = "<?xml version='1.0' standalone='yes'?><r a='\u{E9}'>\u{3042}<!--c--></r>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+
+        let bytes = doc.to_encoded(Encoding::Latin1).unwrap();
         assert_eq!(
-            Some(XmlDocument::from(document.clone())),
-            node.owner_document()
+            b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\" standalone=\"yes\"?><r a=\"\xE9\">&#x3042;<!--c--></r>".to_vec(),
+            bytes
         );
-        assert!(node.has_child());
+
+        let bytes = doc.to_encoded(Encoding::Utf16Le).unwrap();
+        assert_eq!([0xFF, 0xFE], bytes[..2]);
+        let units = bytes[2..]
+            .chunks(2)
+            .map(|v| u16::from_le_bytes([v[0], v[1]]))
+            .collect::<Vec<u16>>();
+        assert_eq!(
+            "<?xml version=\"1.0\" encoding=\"UTF-16\" standalone=\"yes\"?><r a=\"\u{E9}\">\u{3042}<!--c--></r>",
+            String::from_utf16(&units).unwrap()
+        );
+
+        let bytes = doc.to_encoded(Encoding::Utf16Be).unwrap();
+        assert_eq!([0xFE, 0xFF, 0x00, b'<'], bytes[..4]);
+
+        let (_, doc) = XmlDocument::from_raw("<r><!--\u{E9}--></r>").unwrap();
+        let err = doc.to_encoded(Encoding::Ascii).err().unwrap();
+        assert!(matches!(err, error::Error::Io(_)));
+
+        assert_eq!(Some(Encoding::Utf16Be), Encoding::from_name("utf-16be"));
+        assert_eq!(Some(Encoding::Latin1), Encoding::from_name("ISO-8859-1"));
+        assert_eq!(None, Encoding::from_name("Shift_JIS"));
+
+        let mut writer = EncodingWriter::new(vec![], Encoding::Latin1);
+        let bytes = "\u{E9}".as_bytes();
+        io::Write::write_all(&mut writer, &bytes[..1]).unwrap();
+        io::Write::write_all(&mut writer, &bytes[1..]).unwrap();
+        io::Write::flush(&mut writer).unwrap();
+        assert_eq!(vec![0xE9], writer.into_inner());
     }
 
     #[test]
-    fn test_document_fragment_as_string_value() {
-        let (_, tree) = xml_parser::document("<root></root>").unwrap();
-        let document = info::XmlDocument::new(&tree).unwrap();
+    fn test_chunk_child_ranges() {
+        let xml = "<?xml version='1.0'?><!DOCTYPE r [<!-- don't --><!ENTITY e '>'>]><r a='>'><a>1<b/></a><!--<c>--><![CDATA[<d>]]><e f=\"/>\"/> <?g <h>?><i></i></r>";
+        let ranges = chunk::child_ranges(xml).unwrap();
+        let chunks = ranges
+            .iter()
+            .map(|v| &xml[v.clone()])
+            .collect::<Vec<&str>>();
+        assert_eq!(vec!["<a>1<b/></a>", "<e f=\"/>\"/>", "<i></i>"], chunks);
 
-        let flag = XmlDocumentFragment {
-            document: document.clone(),
-            parent: Some(document.clone()),
-        };
+        assert!(chunk::child_ranges("<r/>").unwrap().is_empty());
+        assert!(chunk::child_ranges("<r><a></r>").is_err());
+        assert!(chunk::child_ranges("<r><!-- a </r>").is_err());
+        assert!(chunk::child_ranges("<r><a></b></r>").is_err());
+        assert!(chunk::child_ranges("<r><a><b></a></b></r>").is_err());
+    }
 
-        // AsStringValue
-        assert_eq!("", flag.as_string_value().unwrap());
+    #[test]
+    fn test_chunk_parse_parallel() {
+        let xml = "<?xml version='1.0'?>\r\n<!DOCTYPE r [<!ENTITY e 'ent'><!ATTLIST x:b c CDATA 'd'>]>\r\n<r xmlns='urn:r' xmlns:x='urn:x'>head<a>&e;<b/></a>mid<!--c--><x:b/><?p d?><a><![CDATA[<z>]]></a>tail</r>\r\n<!--end-->";
+        let context = ContextBuilder::default().eol_normalized(true).build();
+        let (_, expected) = XmlDocument::from_raw_with_context(xml, context.clone()).unwrap();
+
+        for threads in [0, 1, 2, 8] {
+            let doc = chunk::parse_parallel(xml, context.clone(), threads).unwrap();
+            assert_eq!(expected.to_string(), doc.to_string());
+
+            let root = doc.document_element().unwrap();
+            let b = root.get_elements_by_tag_name_ns("urn:x", "b");
+            assert_eq!(1, b.length());
+            assert_eq!(
+                "d",
+                b.item(0).unwrap().as_element().unwrap().get_attribute("c")
+            );
+            assert_eq!(
+                Some("urn:r".to_string()),
+                root.child_nodes().item(1).unwrap().namespace_uri()
+            );
+        }
+
+        assert!(chunk::parse_parallel("<r><a></a><b><c></b></r>", Context::default(), 2).is_err());
+        assert!(chunk::parse_parallel("<r><a x=1/></r>", Context::default(), 2).is_err());
+        assert!(chunk::parse_parallel("<r/><r/>", Context::default(), 2).is_err());
+        assert!(chunk::parse_parallel("<r/>", Context::default(), 2).is_ok());
     }
 
     #[test]
-    fn test_document_fragment_children() {
-        let (_, tree) = xml_parser::document("<root></root>").unwrap();
-        let document = info::XmlDocument::new(&tree).unwrap();
+    fn test_chunk_split_records() {
+        let xml = "<?xml version='1.0'?>\n<dump xmlns='urn:d' xmlns:x='urn:x'>\n<!-- <record/> --><record id='1'><x:a>1</x:a><record/></record>\n<other><record xmlns:x = \"urn:y\" id='2'/></other><record id='3'>&amp;</record></dump>\n";
+        let reader = io::BufReader::with_capacity(3, xml.as_bytes());
+        let docs = chunk::split_records(reader, "record")
+            .map(|v| v.map(|d| d.to_string()))
+            .collect::<error::Result<Vec<String>>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                "<record xmlns=\"urn:d\" xmlns:x=\"urn:x\" id=\"1\"><x:a>1</x:a><record /></record>",
+                "<record xmlns=\"urn:d\" xmlns:x=\"urn:y\" id=\"2\" />",
+                "<record xmlns=\"urn:d\" xmlns:x=\"urn:x\" id=\"3\">&amp;</record>",
+            ],
+            docs
+        );
 
-        let root = XmlNode::Element(XmlElement {
-            element: document.borrow().document_element().unwrap(),
-        });
+        let reader = io::BufReader::new(xml.as_bytes());
+        let doc = chunk::split_records(reader, "record")
+            .next()
+            .unwrap()
+            .unwrap();
+        let root = doc.document_element().unwrap();
+        assert_eq!(3, root.in_scope_namespace().unwrap().len());
 
-        let flag = XmlDocumentFragment {
-            document: document.clone(),
-            parent: Some(document.clone()),
-        };
+        let results = chunk::split_records("<dump><record><a>".as_bytes(), "record")
+            .collect::<Vec<error::Result<XmlDocument>>>();
+        assert_eq!(1, results.len());
+        assert!(results[0].is_err());
 
-        // HasChild
-        assert_eq!(vec![root], flag.children());
+        let mut records = chunk::split_records("<dump></dump></dump>".as_bytes(), "record");
+        assert!(records.next().unwrap().is_err());
+        assert!(records.next().is_none());
     }
 
     #[test]
-    fn test_document_fragment_debug() {
-        let (_, tree) = xml_parser::document("<root></root>").unwrap();
-        let document = info::XmlDocument::new(&tree).unwrap();
-
-        let flag = XmlDocumentFragment {
-            document: document.clone(),
-            parent: Some(document.clone()),
-        };
+    fn test_chunk_find_streaming() {
+        let xml = "<log xmlns:x='urn:x'>\n<entry level='error' msg='a &amp; b'><x:code>1</x:code></entry>\n<entry level='info'/>\n<group><entry level=\"error\"/></group>\n<!-- <entry level='error'/> --></log>\n";
+        let reader = io::BufReader::with_capacity(3, xml.as_bytes());
+        let matches = chunk::find_streaming(reader, "entry", |attrs| {
+            attrs.iter().any(|(n, v)| n == "level" && v == "error")
+        })
+        .collect::<error::Result<Vec<chunk::XmlMatch>>>()
+        .unwrap();
+        assert_eq!(2, matches.len());
+        assert_eq!("/log/entry", matches[0].path());
+        assert_eq!("entry", matches[0].name());
+        assert_eq!(Some("a & b"), matches[0].attribute("msg"));
+        assert_eq!(
+            "<entry xmlns:x='urn:x' level='error' msg='a &amp; b'><x:code>1</x:code></entry>",
+            matches[0].as_str()
+        );
+        assert_eq!("/log/group/entry", matches[1].path());
+        assert_eq!(
+            vec![("level".to_string(), "error".to_string())],
+            matches[1].attributes()
+        );
 
-        // fmt::Debug
+        let doc = matches[0].to_document().unwrap();
         assert_eq!(
-            "XmlDocumentFragment { Ok(XmlElement { root }) }",
-            format!("{:?}", flag)
+            "<entry xmlns:x=\"urn:x\" level=\"error\" msg=\"a &amp; b\"><x:code>1</x:code></entry>",
+            doc.to_string()
         );
+
+        let paths = chunk::find_streaming(xml.as_bytes(), "/log/entry", |_| true)
+            .map(|v| v.map(|m| m.attribute("level").unwrap_or_default().to_string()))
+            .collect::<error::Result<Vec<String>>>()
+            .unwrap();
+        assert_eq!(vec!["error", "info"], paths);
+
+        let count = chunk::find_streaming(xml.as_bytes(), "group/*", |_| true).count();
+        assert_eq!(1, count);
+
+        let mut matches = chunk::find_streaming("<log><entry>".as_bytes(), "entry", |_| true);
+        assert!(matches.next().unwrap().is_err());
+        assert!(matches.next().is_none());
+
+        for xml in [
+            "<<あ>",
+            "<log><<あ a='1'/></log>",
+            "<log></<あ></log>",
+            "<1a/>",
+        ] {
+            let mut matches = chunk::find_streaming(xml.as_bytes(), "entry", |_| true);
+            assert!(matches.next().unwrap().is_err(), "{}", xml);
+            assert!(matches.next().is_none());
+
+            let mut records = chunk::split_records(xml.as_bytes(), "record");
+            assert!(records.next().unwrap().is_err(), "{}", xml);
+            assert!(records.next().is_none());
+        }
     }
 
     #[test]
-    fn test_document_fragment_display() {
-        let (_, tree) = xml_parser::document("<root></root>").unwrap();
-        let document = info::XmlDocument::new(&tree).unwrap();
+    fn test_merge_documents() {
+        let (_, a) = XmlDocument::from_raw(
+            "<!DOCTYPE a [<!ENTITY e 'ent'><!ATTLIST a v CDATA 'def'>]><a>&e;<!--c--></a>",
+        )
+        .unwrap();
+        let (_, b) = XmlDocument::from_raw(
+            "<p:b xmlns:p=\"urn:p\"><p:c><![CDATA[<x>]]></p:c><?pi data?></p:b>",
+        )
+        .unwrap();
+        let (_, c) = XmlDocument::from_raw("<c xmlns=\"urn:c\"><d/></c>").unwrap();
 
-        let flag = XmlDocumentFragment {
-            document: document.clone(),
-            parent: Some(document.clone()),
-        };
+        let doc = merge::documents(None, "all", &[a.clone(), b.clone()]).unwrap();
+        assert_eq!(
+            "<all><a v=\"def\">ent<!--c--></a><p:b xmlns:p=\"urn:p\"><p:c><![CDATA[<x>]]></p:c><?pi data?></p:b></all>",
+            doc.to_string()
+        );
 
-        // fmt::Display
-        assert_eq!("<root />", format!("{}", flag));
+        let doc = merge::documents(Some("urn:w"), "all", &[a, c.clone()]).unwrap();
+        assert_eq!(
+            "<all xmlns=\"urn:w\"><a v=\"def\" xmlns=\"\">ent<!--c--></a><c xmlns=\"urn:c\"><d /></c></all>",
+            doc.to_string()
+        );
+
+        let inner = c.document_element().unwrap().first_element_child().unwrap();
+        let doc = merge::elements(Some("urn:p"), "p:all", &[inner]).unwrap();
+        assert_eq!(
+            "<p:all xmlns:p=\"urn:p\"><d xmlns=\"urn:c\" /></p:all>",
+            doc.to_string()
+        );
+        let root = doc.document_element().unwrap();
+        let d = root.first_element_child().unwrap();
+        assert_eq!(3, d.in_scope_namespace().unwrap().len());
     }
 
     #[test]
-    fn test_document_fragment_impl() {
-        let (_, tree) = xml_parser::document("<root></root>").unwrap();
-        let document = info::XmlDocument::new(&tree).unwrap();
+    fn test_parser_reuse() {
+        let context = Context::builder().eol_normalized(true).build();
+        let mut parser = parser::Parser::new(context);
+        let doc = parser.parse("<root>a\r\nb</root>").unwrap();
+        assert_eq!("<root>a\nb</root>", doc.to_string());
+        let capacity = parser.capacity();
+        assert!(capacity > 0);
+
+        let doc = parser.parse("<r>c\rd</r>").unwrap();
+        assert_eq!("<r>c\nd</r>", doc.to_string());
+        assert_eq!(capacity, parser.capacity());
+
+        let doc = parser.parse_reader("<root a='1' />".as_bytes()).unwrap();
+        assert_eq!("<root a=\"1\" />", doc.to_string());
+
+        assert!(parser.parse("<root /><root />").is_err());
+
+        let context = Context::builder().max_length(Some(8)).build();
+        let mut parser = parser::Parser::new(context.clone());
+        assert_eq!(&context, parser.context());
+        match parser.parse("<root>long</root>") {
+            Err(error::Error::LengthLimitExceeded(8)) => {}
+            e => unreachable!("{:?}", e),
+        }
 
-        let root = XmlElement {
-            element: document.borrow().document_element().unwrap(),
-        };
+        parser.set_context(Context::default());
+        parser.parse("<root>long</root>").unwrap();
+        parser.shrink_to(0);
+        assert_eq!(0, parser.capacity());
+    }
 
-        let flag = XmlDocumentFragment {
-            document: document.clone(),
-            parent: Some(document.clone()),
-        };
+    #[test]
+    fn test_parser_recycle() {
+        let context = Context::builder().spans_tracked(true).build();
+        let mut parser = parser::Parser::new(context);
+        let doc = parser.parse("<root><a/><b/></root>").unwrap();
+        assert!(parser.recycle(doc));
+        assert_eq!(1, parser.pooled());
+
+        let doc = parser.parse("<r><c/><d/></r>").unwrap();
+        assert_eq!(0, parser.pooled());
+        let root = doc.document_element().unwrap();
+        let c = root.first_child().unwrap();
+        let d = root.last_child().unwrap();
+        assert_eq!(cmp::Ordering::Less, c.cmp_document_order(&d));
+        assert_eq!(Some(7..11), d.source_span().map(|v| v.start()..v.end()));
+        assert_eq!(
+            Some("d".to_string()),
+            doc.node_at_offset(8).map(|(v, _)| v.node_name())
+        );
 
-        // XmlDocumentFragment
-        assert_eq!(root, flag.root_element().unwrap());
+        assert!(!parser.recycle(doc));
+        assert_eq!(0, parser.pooled());
+        assert_eq!("<r><c /><d /></r>", root.to_string());
+        drop((root, c, d));
+
+        let doc = parser.parse("<r />").unwrap();
+        assert!(parser.recycle(doc));
+        parser.shrink_to(0);
+        assert_eq!(0, parser.pooled());
     }
 
     #[test]
-    fn test_document_document() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
-        let elem = doc.root_element().unwrap();
-        let root = elem.as_node();
+    fn test_document_fork() {
+        let (_, doc) = XmlDocument::from_raw_with_context(
+            "<!DOCTYPE r [<!ENTITY e 'ent'>]><r a='1'><c>&e;</c></r>",
+            Context::builder().text_expanded(true).build(),
+        )
+        .unwrap();
+        doc.set_document_uri(Some("http://example.com/base.xml"));
 
-        // Document
-        assert_eq!(None, doc.doc_type());
-        assert_eq!(XmlDomImplementation {}, doc.implementation());
-        assert_eq!(elem, doc.document_element().unwrap());
-        for child in doc.get_elements_by_tag_name("root").iter() {
-            assert_eq!(root, child);
-        }
+        let fork = doc.fork().unwrap();
+        assert_eq!(doc.to_string(), fork.to_string());
+        assert_eq!(doc.document_uri(), fork.document_uri());
+
+        let root = fork.document_element().unwrap();
+        root.set_attribute("a", "2").unwrap();
+        let c = root.first_element_child().unwrap();
+        assert_eq!(
+            "ent",
+            c.child_nodes()
+                .item(0)
+                .unwrap()
+                .node_value()
+                .unwrap()
+                .unwrap()
+        );
+        root.remove_child(&c.as_node()).unwrap();
+
+        assert_eq!(
+            "<!DOCTYPE r [<!ENTITY e 'ent'>]><r a=\"1\"><c>&e;</c></r>",
+            doc.to_string()
+        );
+        assert_eq!(
+            "<!DOCTYPE r [<!ENTITY e 'ent'>]><r a=\"2\" />",
+            fork.to_string()
+        );
     }
 
     #[test]
-    fn test_document_document_mut_create_element_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+    fn test_document_fork_shared() {
+        let (_, doc) = XmlDocument::from_raw("<r a='1'><c>t</c></r>").unwrap();
+        let (tree, _) = doc.frozen();
+        let fork = doc.fork().unwrap();
+        assert!(Arc::ptr_eq(&tree, &doc.frozen().0));
+        assert!(Arc::ptr_eq(&tree, &doc.snapshot().tree));
+
+        // Changes to a fork leave the frozen tree of the original valid.
+        let root = fork.document_element().unwrap();
+        root.set_attribute("a", "2").unwrap();
+        assert!(Arc::ptr_eq(&tree, &doc.frozen().0));
 
-        // DocumentMut
-        let elem = doc.create_element("e").unwrap();
-        assert_eq!("e", elem.tag_name());
-        assert_eq!(None, elem.parent_node());
-        assert_eq!(Some(doc.clone()), elem.owner_document());
-        assert_ne!(0, elem.element.borrow().id());
-        assert_eq!(0, elem.element.borrow().order());
+        let root = doc.document_element().unwrap();
+        let c = root.first_element_child().unwrap();
+        let text = c.first_child().unwrap().as_text().unwrap();
+        let changes: Vec<Box<dyn Fn()>> = vec![
+            Box::new(|| root.set_attribute("a", "3").unwrap()),
+            Box::new(|| text.set_data("u").unwrap()),
+            Box::new(|| {
+                let b = doc.create_element("b").unwrap();
+                root.append_child(b.as_node()).unwrap();
+            }),
+            Box::new(|| {
+                c.as_node().set_user_data("k", Some(Rc::new(1)), None);
+            }),
+        ];
+        let mut tree = tree;
+        for change in changes {
+            change();
+            let (changed, _) = doc.frozen();
+            assert!(!Arc::ptr_eq(&tree, &changed));
+            assert_eq!(doc.to_string(), doc.fork().unwrap().to_string());
+            tree = changed;
+        }
+        assert_eq!("<r a=\"3\"><c>u</c><b /></r>", doc.to_string());
     }
 
     #[test]
-    fn test_document_document_mut_create_element_err4() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
-
-        // DocumentMut
-        let err = doc.create_element("<").err().unwrap();
+    fn test_document_fork_annotations() {
+        let xml = "<r><a x='1'>t</a><b/></r>";
+        let context = Context::builder().spans_tracked(true).build();
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context).unwrap();
+        let root = doc.document_element().unwrap();
+        let a = root.first_element_child().unwrap();
+        let x = a.get_attribute_node("x").unwrap();
+        x.set_type_annotation(Some(schema::SchemaType::Int));
+        a.as_node().set_user_data("k", Some(Rc::new(1)), None);
+
+        let fork = doc.fork().unwrap();
+        let root = fork.document_element().unwrap();
+        let fork_a = root.first_element_child().unwrap();
+        let fork_x = fork_a.get_attribute_node("x").unwrap();
+        assert_eq!(a.as_node().source_span(), fork_a.as_node().source_span());
+        assert_eq!(x.as_node().source_span(), fork_x.as_node().source_span());
         assert_eq!(
-            error::Error::Dom(error::DomException::InvalidCharacterErr),
-            err
+            Some(schema::SchemaType::Int),
+            fork_x.as_node().type_annotation()
         );
+        assert_eq!(
+            Some(1),
+            fork_a
+                .as_node()
+                .get_user_data("k")
+                .map(|v| *v.downcast_ref::<i32>().unwrap())
+        );
+        assert_eq!(
+            Some("b".to_string()),
+            fork.node_at_offset(19).map(|(v, _)| v.node_name())
+        );
+
+        fork_a.set_attribute("x", "2").unwrap();
+        a.append_child(doc.create_element("n").unwrap().as_node())
+            .unwrap();
+        assert_eq!("<r><a x=\"1\">t<n /></a><b /></r>", doc.to_string());
+        assert_eq!("<r><a x=\"2\">t</a><b /></r>", fork.to_string());
+        assert!(!fork_a.as_node().ptr_eq(&a.as_node()));
     }
 
     #[test]
-    fn test_document_document_mut_create_document_fragment_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+    fn test_document_snapshot() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
 
-        // DocumentMut
-        let node = doc.create_document_fragment();
-        assert_eq!(None, node.parent_node());
-        assert_eq!(Some(doc.clone()), node.owner_document());
-        assert_ne!(0, node.document.borrow().id());
-        // FIXME:
-        //assert_eq!(0, node.document.borrow().order());
+        let (_, doc) = XmlDocument::from_raw("<r><c>1</c><c>2</c></r>").unwrap();
+        doc.set_document_uri(Some("http://example.com/r.xml"));
+        let snapshot = doc.snapshot();
+        assert_send_sync(&snapshot);
+
+        let root = doc.document_element().unwrap();
+        root.append_child(doc.create_element("c").unwrap().as_node())
+            .unwrap();
+        assert_eq!(3, root.get_elements_by_tag_name("c").length());
+
+        let reader = snapshot.clone();
+        let (count, uri) = std::thread::spawn(move || {
+            let doc = reader.to_document().unwrap();
+            let count = doc.get_elements_by_tag_name("c").length();
+            (count, doc.document_uri())
+        })
+        .join()
+        .unwrap();
+        assert_eq!(2, count);
+        assert_eq!(Some("http://example.com/r.xml".to_string()), uri);
+        assert_eq!("<r><c>1</c><c>2</c></r>", snapshot.to_string());
     }
 
     #[test]
-    fn test_document_document_mut_create_text_node_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+    fn test_document_snapshot_query() {
+        let (_, doc) = XmlDocument::from_raw(
+            "<?xml version='1.0'?><!--c--><r xmlns:p='u' a='&#65;'><p:c>1</p:c><c>2<![CDATA[3]]></c><?pi d?></r>",
+        )
+        .unwrap();
+        let root = doc.document_element().unwrap();
+        let snapshot = doc.snapshot();
+        root.set_attribute("a", "changed").unwrap();
+
+        let reader = snapshot.clone();
+        let handle = std::thread::spawn(move || {
+            let root = reader.document_element().unwrap();
+            let names = root
+                .child_nodes()
+                .map(|v| v.node_name().to_string())
+                .collect::<Vec<_>>();
+            let c = reader.get_elements_by_tag_name("c");
+            (
+                names,
+                root.get_attribute("a").map(|v| v.to_string()),
+                c.len(),
+                c[1].text_content(),
+                root.text_content(),
+                root.handle(),
+            )
+        });
+        let (names, a, count, text, all, handle) = handle.join().unwrap();
+        assert_eq!(vec!["c", "c", "pi"], names);
+        assert_eq!(Some("A".to_string()), a);
+        assert_eq!(2, count);
+        assert_eq!("23", text);
+        assert_eq!("123", all);
+        assert_eq!(root.as_node().handle(), handle);
+
+        let document = snapshot.document();
+        assert_eq!(NodeType::Document, document.node_type());
+        assert_eq!(
+            Some(NodeType::Comment),
+            document.first_child().map(|v| v.node_type())
+        );
+        let root = snapshot.document_element().unwrap();
+        assert_eq!(Some(document), root.parent_node());
+        assert_eq!(document.first_child(), root.previous_sibling());
+        let pc = root.first_child().unwrap();
+        assert_eq!(Some("p"), pc.prefix());
+        assert_eq!(Some("c"), pc.local_name());
+        assert_eq!(Some("u"), pc.namespace_uri());
+        assert_eq!(
+            Some("pi"),
+            pc.next_sibling()
+                .unwrap()
+                .next_sibling()
+                .map(|v| v.node_name())
+        );
+        assert_eq!(root.last_child(), pc.next_sibling().unwrap().next_sibling());
+        assert_eq!(Some("d"), root.last_child().unwrap().node_value());
+        assert_eq!(2, root.attributes().count());
+        assert!(root.attributes().all(|v| v.next_sibling().is_none()));
+        assert_eq!(4, snapshot.get_elements_by_tag_name("*").len() + 1);
 
-        // DocumentMut
-        let text = doc.create_text_node("t");
-        assert_eq!("t", text.data().unwrap());
-        assert_eq!(None, text.parent_node());
-        assert_eq!(Some(doc.clone()), text.owner_document());
-        assert_ne!(0, text.data.borrow().id());
-        assert_eq!(0, text.data.borrow().order());
+        let fork = snapshot.to_document().unwrap();
+        assert_eq!(
+            "<?xml version=\"1.0\"?><!--c--><r xmlns:p=\"u\" a=\"&#65;\"><p:c>1</p:c><c>2<![CDATA[3]]></c><?pi d?></r>",
+            fork.to_string()
+        );
+        assert_eq!(fork.to_string(), snapshot.as_str());
     }
 
     #[test]
-    fn test_document_document_mut_create_comment_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+    fn test_document_drop() {
+        let (_, doc) = XmlDocument::from_raw("<root><c>1</c></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let c = root.first_element_child().unwrap();
+        drop(doc);
 
-        // DocumentMut
-        let comment = doc.create_comment("c");
-        assert_eq!("c", comment.data().unwrap());
-        assert_eq!(None, comment.parent_node());
-        assert_eq!(Some(doc.clone()), comment.owner_document());
-        assert_ne!(0, comment.data.borrow().id());
-        assert_eq!(0, comment.data.borrow().order());
+        assert!(root.owner_document().is_none());
+        assert!(root.parent_node().is_none());
+        assert!(root.set_attribute("a", "1").is_err());
+        assert_eq!(c, root.first_element_child().unwrap());
+        assert_eq!("<c>1</c>", c.to_string());
     }
 
     #[test]
-    fn test_document_document_mut_create_cdata_section_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
-
-        // DocumentMut
-        let cdata = doc.create_cdata_section("d");
-        assert_eq!("d", cdata.data().unwrap());
-        assert_eq!(None, cdata.parent_node());
-        assert_eq!(Some(doc.clone()), cdata.owner_document());
-        assert_ne!(0, cdata.data.borrow().id());
-        assert_eq!(0, cdata.data.borrow().order());
+    fn test_document_drop_keep_root() {
+        let (_, doc) = XmlDocument::from_raw("<root xmlns='u'><c>1</c></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let c = root.first_element_child().unwrap();
+        assert_eq!(Some("u".to_string()), root.namespace_uri());
+        drop(doc);
+
+        assert_eq!(Some("u".to_string()), root.namespace_uri());
+        assert_eq!(Some("u".to_string()), c.namespace_uri());
+        assert_eq!(Some(root.as_node()), c.parent_node());
+        assert_eq!(Some(c.as_node()), c.first_child().unwrap().parent_node());
+        assert!(root.parent_node().is_none());
     }
 
     #[test]
-    fn test_document_document_mut_create_processing_instruction_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
-
-        // DocumentMut
-        let pi = doc.create_processing_instruction("t", "c").unwrap();
-        assert_eq!("t", pi.target());
-        assert_eq!("c", pi.data());
-        assert_eq!(None, pi.parent_node());
-        assert_eq!(Some(doc.clone()), pi.owner_document());
-        assert_ne!(0, pi.pi.borrow().id());
-        assert_eq!(0, pi.pi.borrow().order());
+    fn test_document_drop_deep() {
+        let depth = 2000;
+        let dropped = std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(move || {
+                let doc = XmlDocument::new();
+                let mut parent = doc.create_element("e").unwrap();
+                doc.append_child(parent.as_node()).unwrap();
+                for _ in 0..depth {
+                    let child = doc.create_element("e").unwrap();
+                    parent.append_child(child.as_node()).unwrap();
+                    parent = child;
+                }
+                drop(parent);
+                drop(doc);
+                true
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(dropped);
     }
 
     #[test]
-    fn test_document_document_mut_create_processing_instruction_err4_target() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+    fn test_document_parse_deep() {
+        // The parser recurses once per level, so this is about the deepest document it reads
+        // on the stack of a test thread.
+        let depth = 5000;
+        let text = std::thread::Builder::new()
+            .stack_size(2 * 1024 * 1024)
+            .spawn(move || {
+                let xml = format!("{}t{}", "<e a=\"1\">".repeat(depth), "</e>".repeat(depth));
+                let context = Context::builder().spans_tracked(true).build();
+                let (_, doc) = XmlDocument::from_raw_with_context(xml.as_str(), context).unwrap();
+                let leaf = doc
+                    .get_elements_by_tag_name("e")
+                    .items()
+                    .unwrap()
+                    .pop()
+                    .unwrap();
+                assert_eq!(depth, leaf.ancestors().count());
+                let text = leaf.as_string_value().unwrap();
+                assert_eq!(xml, doc.to_string());
+                drop(doc);
+                text
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!("t", text);
+    }
 
-        // DocumentMut
-        let err = doc.create_processing_instruction("xml", "c").err().unwrap();
+    #[test]
+    fn test_document_save_atomic() {
+        let dir = std::env::temp_dir().join("xml-dom-test-document-save-atomic");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.xml");
+        let backup = dir.join("config.xml.bak");
+        let _ = std::fs::remove_file(&backup);
+        std::fs::write(&path, "<old />").unwrap();
+
+        let (_, doc) = XmlDocument::from_raw("<root>\u{e9}</root>").unwrap();
+        doc.save_atomic(&path, &SaveOptions::default()).unwrap();
         assert_eq!(
-            error::Error::Dom(error::DomException::InvalidCharacterErr),
-            err
+            "<root>\u{e9}</root>",
+            std::fs::read_to_string(&path).unwrap()
+        );
+        assert!(!backup.exists());
+
+        let mut options = SaveOptions::default();
+        options.set_backup(true);
+        options.set_encoding(Some(encoding::Encoding::Ascii));
+        assert!(options.backup());
+        assert_eq!(Some(encoding::Encoding::Ascii), options.encoding());
+        doc.save_atomic(&path, &options).unwrap();
+        assert_eq!(
+            "<?xml version=\"1.0\" encoding=\"US-ASCII\"?><root>&#xE9;</root>",
+            std::fs::read_to_string(&path).unwrap()
+        );
+        assert_eq!(
+            "<root>\u{e9}</root>",
+            std::fs::read_to_string(&backup).unwrap()
         );
+        assert_eq!(2, std::fs::read_dir(&dir).unwrap().count());
+
+        let missing = dir.join("missing").join("config.xml");
+        assert!(doc.save_atomic(&missing, &options).is_err());
+
+        #[cfg(unix)]
+        {
+            let link = dir.join("link.xml");
+            std::os::unix::fs::symlink("config.xml", &link).unwrap();
+            doc.save_atomic(&link, &SaveOptions::default()).unwrap();
+            assert!(std::fs::symlink_metadata(&link)
+                .unwrap()
+                .file_type()
+                .is_symlink());
+            assert_eq!(
+                "<root>\u{e9}</root>",
+                std::fs::read_to_string(&path).unwrap()
+            );
+
+            let cycle = dir.join("cycle.xml");
+            std::os::unix::fs::symlink("cycle.xml", &cycle).unwrap();
+            assert!(doc.save_atomic(&cycle, &SaveOptions::default()).is_err());
+        }
+
+        let threads = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let (_, doc) = XmlDocument::from_raw(&format!("<root>{}</root>", i)).unwrap();
+                    doc.save_atomic(&path, &SaveOptions::default())
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap().unwrap();
+        }
+        assert!(std::fs::read_dir(&dir).unwrap().all(|v| !v
+            .unwrap()
+            .path()
+            .to_string_lossy()
+            .ends_with(".tmp")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn test_document_document_mut_create_processing_instruction_err4_content() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+    fn test_canonicalize() {
+        let (_, doc) = XmlDocument::from_raw(
+            "<?xml version=\"1.0\"?>\n<?xml-stylesheet href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n<!DOCTYPE doc [<!ATTLIST e9 attr CDATA \"default\"><!ENTITY ent \"v&#38;\">]>\n<!-- c1 -->\n<doc xmlns:b=\"http://www.ietf.org\" xmlns:a=\"http://www.w3.org\">\n   <e1   />\n   <e2 b:attr=\"sorted\" attr2=\"all\" a:attr=\"out\"  attr=\"I'm\"></e2>\n   <e3 xmlns=\"http://example.org\"><e4 xmlns=\"\"/></e3>\n   <e5><![CDATA[<x> & y]]>&ent;</e5>\n   <e9/>\n</doc>\n<?pi?>",
+        )
+        .unwrap();
 
-        // DocumentMut
-        let err = doc.create_processing_instruction("t", "?>").err().unwrap();
         assert_eq!(
-            error::Error::Dom(error::DomException::InvalidCharacterErr),
-            err
+            "<?xml-stylesheet href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n<doc xmlns:a=\"http://www.w3.org\" xmlns:b=\"http://www.ietf.org\">\n   <e1></e1>\n   <e2 attr=\"I'm\" attr2=\"all\" b:attr=\"sorted\" a:attr=\"out\"></e2>\n   <e3 xmlns=\"http://example.org\"><e4 xmlns=\"\"></e4></e3>\n   <e5>&lt;x&gt; &amp; yv&amp;</e5>\n   <e9 attr=\"default\"></e9>\n</doc>\n<?pi?>",
+            canonical::canonicalize(&doc, false).unwrap()
         );
+
+        let value = canonical::canonicalize(&doc, true).unwrap();
+        assert!(value.starts_with(
+            "<?xml-stylesheet href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n<!-- c1 -->\n<doc"
+        ));
     }
 
     #[test]
-    fn test_document_document_mut_create_attribute_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+    fn test_qname() {
+        let a = QName::new("a", Some("p"), Some("urn:x"));
+        let b = QName::new("a", Some("q"), Some("urn:x"));
+        let c = QName::new("a", None, None);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!("p:a", a.to_string());
+        assert_eq!("a", c.to_string());
+        assert_eq!(cmp::Ordering::Less, c.cmp(&a));
+
+        let set = [a.clone(), b, c.clone()]
+            .into_iter()
+            .collect::<std::collections::HashSet<QName>>();
+        assert_eq!(2, set.len());
+
+        let name = "p:a".parse::<QName>().unwrap();
+        assert_eq!(Some("p"), name.prefix());
+        assert_eq!("a", name.local_name());
+        assert_eq!(None, name.namespace_uri());
+
+        assert_eq!(a, "{urn:x}a".parse::<QName>().unwrap());
+        assert_eq!(c, "{}a".parse::<QName>().unwrap());
+        assert!("1a".parse::<QName>().is_err());
+        assert!("a b".parse::<QName>().is_err());
+        assert!("a:b:c".parse::<QName>().is_err());
+        assert!("p:".parse::<QName>().is_err());
+        assert!("{urn:x".parse::<QName>().is_err());
 
-        // DocumentMut
-        let attr = doc.create_attribute("a").unwrap();
-        assert_eq!("a", attr.name());
-        assert_eq!(None, attr.parent_node());
-        assert_eq!(Some(doc.clone()), attr.owner_document());
-        assert_ne!(0, attr.attribute.borrow().id());
-        assert_eq!(0, attr.attribute.borrow().order());
+        let (_, doc) =
+            XmlDocument::from_raw("<p:root xmlns:p='urn:x'><q:a xmlns:q='urn:x'/></p:root>")
+                .unwrap();
+        let root = doc.document_element().unwrap();
+        let child = root.first_child().unwrap();
+        assert_eq!(
+            root.as_expanded_name().unwrap().unwrap(),
+            "{urn:x}root".parse::<QName>().unwrap()
+        );
+        assert_eq!(
+            Some(a),
+            child.as_expanded_name().unwrap().map(|v| {
+                assert_eq!("q:a", v.to_string());
+                v
+            })
+        );
     }
 
     #[test]
-    fn test_document_document_mut_create_attribute_err4() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+    fn test_document_create_ns() {
+        let (_, doc) = XmlDocument::from_raw("<root xmlns:q='http://q/' />").unwrap();
+        let root = doc.document_element().unwrap();
 
-        // DocumentMut
-        let err = doc.create_attribute("<").err().unwrap();
+        let ns = |e: &XmlElement| {
+            let name = e.as_expanded_name().unwrap().unwrap();
+            name.namespace_uri().map(|v| v.to_string())
+        };
+        let a = doc.create_element_ns(Some("http://p/"), "p:a").unwrap();
+        assert_eq!(Some("http://p/".to_string()), ns(&a));
+        let b = doc.create_element_ns(Some("http://d/"), "b").unwrap();
+        let c = doc.create_element_ns(None, "c").unwrap();
+        assert_eq!(None, ns(&c));
+        a.append_child(b.as_node()).unwrap();
+        a.append_child(c.as_node()).unwrap();
+        root.append_child(a.as_node()).unwrap();
+
+        let attr = doc.create_attribute_ns(Some("http://r/"), "r:x").unwrap();
+        assert_eq!(Some("http://r/".to_string()), attr.namespace_uri());
+        attr.set_value("1").unwrap();
+        b.set_attribute_node(attr).unwrap();
+        let attr = doc.create_attribute_ns(Some("http://q/"), "q:y").unwrap();
+        attr.set_value("2").unwrap();
+        b.attributes().unwrap().set_named_item_ns(attr).unwrap();
+        let attr = doc.create_attribute_ns(None, "z").unwrap();
+        c.set_attribute_node(attr).unwrap();
         assert_eq!(
-            error::Error::Dom(error::DomException::InvalidCharacterErr),
-            err
+            "<root xmlns:q=\"http://q/\"><p:a xmlns:p=\"http://p/\"><b xmlns=\"http://d/\" xmlns:r=\"http://r/\" r:x=\"1\" q:y=\"2\" /><c xmlns=\"\" z=\"\" /></p:a></root>",
+            doc.to_string()
         );
-    }
 
-    #[test]
-    fn test_document_document_mut_create_entity_reference_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+        let (_, default) = XmlDocument::from_raw("<root xmlns='http://d/' />").unwrap();
+        let root = default.document_element().unwrap();
+        let d = default.create_element_ns(None, "d").unwrap();
+        root.append_child(d.as_node()).unwrap();
+        assert_eq!(None, d.namespace_uri());
+        assert_eq!(None, ns(&d));
+        assert_eq!(
+            "<root xmlns=\"http://d/\"><d xmlns=\"\" /></root>",
+            default.to_string()
+        );
+        let (_, reparsed) = XmlDocument::from_raw(default.to_string().as_str()).unwrap();
+        let d = reparsed.document_element().unwrap().first_child().unwrap();
+        assert_eq!(None, d.namespace_uri());
+        let x = b.get_attribute_node("x").unwrap();
+        assert_eq!(Some("http://r/".to_string()), x.namespace_uri());
 
-        // DocumentMut
-        let eref = doc.create_entity_reference("amp").unwrap();
-        assert_eq!("amp", eref.node_name());
-        assert_eq!(None, eref.parent_node());
-        assert_eq!(Some(doc.clone()), eref.owner_document());
-        assert_ne!(0, eref.inner().id());
-        assert_eq!(0, eref.inner().order());
+        let attr = doc
+            .create_attribute_ns(Some("http://other/"), "q:w")
+            .unwrap();
+        let err = b.set_attribute_node(attr).err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NamespaceErr), err);
+
+        let xml = info::NamespaceUri::xml();
+        let xmlns = info::NamespaceUri::xmlns();
+        let attr = doc
+            .create_attribute_ns(Some(xml.value()), "xml:lang")
+            .unwrap();
+        assert_eq!(Some(xml.value().to_string()), attr.namespace_uri());
+        assert!(doc
+            .create_attribute_ns(Some(xmlns.value()), "xmlns:p")
+            .is_ok());
+        assert!(doc
+            .create_attribute_ns(Some(xmlns.value()), "xmlns")
+            .is_ok());
+
+        let errors = [
+            (
+                Some("http://p/"),
+                "1p:a",
+                error::DomException::InvalidCharacterErr,
+            ),
+            (
+                Some("http://p/"),
+                "p:a:b",
+                error::DomException::NamespaceErr,
+            ),
+            (None, "p:a", error::DomException::NamespaceErr),
+            (
+                Some("http://p/"),
+                "xml:a",
+                error::DomException::NamespaceErr,
+            ),
+            (
+                Some("http://p/"),
+                "xmlns:a",
+                error::DomException::NamespaceErr,
+            ),
+            (Some(xmlns.value()), "a", error::DomException::NamespaceErr),
+        ];
+        for (uri, name, expected) in errors {
+            let err = doc.create_element_ns(uri, name).err().unwrap();
+            assert_eq!(error::Error::Dom(expected), err, "{}", name);
+        }
+
+        let errors = [
+            (
+                Some("http://p/"),
+                "1p:a",
+                error::DomException::InvalidCharacterErr,
+            ),
+            (None, "p:a", error::DomException::NamespaceErr),
+            (Some("http://p/"), "a", error::DomException::NamespaceErr),
+            (
+                Some("http://p/"),
+                "xmlns:a",
+                error::DomException::NamespaceErr,
+            ),
+            (None, "xmlns", error::DomException::NamespaceErr),
+            (
+                Some(xmlns.value()),
+                "p:a",
+                error::DomException::NamespaceErr,
+            ),
+        ];
+        for (uri, name, expected) in errors {
+            let err = doc.create_attribute_ns(uri, name).err().unwrap();
+            assert_eq!(error::Error::Dom(expected), err, "{}", name);
+        }
     }
 
     #[test]
-    fn test_document_document_mut_create_entity_reference_err4() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+    fn test_node_namespace_accessors() {
+        let xml = "<root xmlns='urn:d' xmlns:a='urn:a' xmlns:b='urn:a'><a:e a:x='1' y='2'>t</a:e><!--c--></root>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let root = doc.document_element().unwrap();
+        let e = root.first_element_child().unwrap();
+        let x = e.get_attribute_node("x").unwrap();
+        let y = e.get_attribute_node("y").unwrap();
+
+        let names = |node: &XmlNode| (node.namespace_uri(), node.prefix(), node.local_name());
+        let some = |v: &str| Some(v.to_string());
+        assert_eq!((some("urn:d"), None, some("root")), names(&root.as_node()));
+        assert_eq!((some("urn:a"), some("a"), some("e")), names(&e.as_node()));
+        assert_eq!((some("urn:a"), some("a"), some("x")), names(&x.as_node()));
+        assert_eq!((None, None, some("y")), names(&y.as_node()));
+        assert_eq!((None, None, None), names(&e.first_child().unwrap()));
+        assert_eq!((None, None, None), names(&root.last_child().unwrap()));
+        assert_eq!((None, None, None), names(&doc.as_node()));
+
+        e.set_prefix(Some("b")).unwrap();
+        x.set_prefix(Some("c")).unwrap();
+        assert_eq!((some("urn:a"), some("b"), some("e")), names(&e.as_node()));
+        assert_eq!((some("urn:a"), some("c"), some("x")), names(&x.as_node()));
+        assert_eq!(
+            "<b:e c:x=\"1\" y=\"2\" xmlns:c=\"urn:a\">t</b:e>",
+            e.to_string()
+        );
 
-        // DocumentMut
-        let err = doc.create_entity_reference("<").err().unwrap();
+        let namespace_err = error::Error::Dom(error::DomException::NamespaceErr);
+        assert_eq!(namespace_err, e.set_prefix(None).err().unwrap());
+        assert_eq!(namespace_err, e.set_prefix(Some("xml")).err().unwrap());
+        assert_eq!(namespace_err, e.set_prefix(Some("xmlns")).err().unwrap());
+        assert_eq!(namespace_err, e.set_prefix(Some("a:b")).err().unwrap());
+        assert_eq!(namespace_err, x.set_prefix(None).err().unwrap());
+        assert_eq!(namespace_err, y.set_prefix(Some("a")).err().unwrap());
+        let err = e.set_prefix(Some("1")).err().unwrap();
         assert_eq!(
             error::Error::Dom(error::DomException::InvalidCharacterErr),
             err
         );
+
+        let d = doc.create_element_ns(Some("urn:d"), "d:f").unwrap();
+        root.append_child(d.as_node()).unwrap();
+        d.set_prefix(None).unwrap();
+        assert_eq!((some("urn:d"), None, some("f")), names(&d.as_node()));
+        assert_eq!("<f />", d.to_string());
+
+        let g = doc.create_element_ns(Some("urn:p"), "p:g").unwrap();
+        root.append_child(g.as_node()).unwrap();
+        let k = doc.create_attribute_ns(Some("urn:p"), "p:k").unwrap();
+        g.set_attribute_node(k.clone()).unwrap();
+        g.set_prefix(Some("q")).unwrap();
+        assert_eq!(
+            "<q:g xmlns:p=\"urn:p\" p:k=\"\" xmlns:q=\"urn:p\" />",
+            g.to_string()
+        );
+        k.set_prefix(Some("q")).unwrap();
+        assert_eq!("<q:g q:k=\"\" xmlns:q=\"urn:p\" />", g.to_string());
+
+        let attr = doc.create_attribute_ns(Some("urn:g"), "g:z").unwrap();
+        attr.set_prefix(Some("h")).unwrap();
+        assert_eq!(
+            (some("urn:g"), some("h"), some("z")),
+            names(&attr.as_node())
+        );
+
+        let text = doc.create_text_node("t");
+        text.set_prefix(Some("a")).unwrap();
+        assert_eq!(None, text.as_node().prefix());
     }
 
     #[test]
-    fn test_document_node() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
-        let elem = doc.root_element().unwrap();
-        let root = elem.as_node();
-
-        // Node
-        assert_eq!("#document", doc.node_name());
-        assert_eq!(None, doc.node_value().unwrap());
-        assert_eq!(NodeType::Document, doc.node_type());
-        assert_eq!(None, doc.parent_node());
-        for child in doc.child_nodes().iter() {
-            assert_eq!(root, child);
-        }
-        assert_eq!(Some(root.clone()), doc.first_child());
-        assert_eq!(Some(root.clone()), doc.last_child());
-        assert_eq!(None, doc.previous_sibling());
-        assert_eq!(None, doc.next_sibling());
-        assert_eq!(None, doc.attributes());
-        assert_eq!(None, doc.owner_document());
-        assert!(doc.has_child());
+    fn test_node_lookup_namespace() {
+        let xml = "<root xmlns='urn:d' xmlns:a='urn:a'><a:e xmlns:b='urn:b' xmlns:a='urn:c' x='1'>t<!--c--></a:e><f xmlns=''/></root>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let root = doc.document_element().unwrap();
+        let e = root.first_element_child().unwrap();
+        let f = root.last_element_child().unwrap();
+        let x = e.get_attribute_node("x").unwrap();
+        let text = e.first_child().unwrap();
+        let comment = e.last_child().unwrap();
+
+        let some = |v: &str| Some(v.to_string());
+        assert_eq!(some("urn:d"), root.lookup_namespace_uri(None));
+        assert_eq!(some("urn:a"), root.lookup_namespace_uri(Some("a")));
+        assert_eq!(None, root.lookup_namespace_uri(Some("b")));
+        assert_eq!(some("urn:c"), e.lookup_namespace_uri(Some("a")));
+        assert_eq!(some("urn:b"), e.lookup_namespace_uri(Some("b")));
+        assert_eq!(some("urn:d"), e.lookup_namespace_uri(None));
+        assert_eq!(None, f.lookup_namespace_uri(None));
+        assert_eq!(
+            some("http://www.w3.org/XML/1998/namespace"),
+            f.lookup_namespace_uri(Some("xml"))
+        );
+        assert_eq!(
+            some("http://www.w3.org/2000/xmlns/"),
+            f.lookup_namespace_uri(Some("xmlns"))
+        );
+        assert_eq!(some("urn:b"), x.lookup_namespace_uri(Some("b")));
+        assert_eq!(some("urn:c"), text.lookup_namespace_uri(Some("a")));
+        assert_eq!(some("urn:c"), comment.lookup_namespace_uri(Some("a")));
+        assert_eq!(some("urn:d"), doc.lookup_namespace_uri(None));
+
+        assert_eq!(some("a"), root.lookup_prefix("urn:a"));
+        assert_eq!(None, e.lookup_prefix("urn:a"));
+        assert_eq!(some("a"), e.lookup_prefix("urn:c"));
+        assert_eq!(some("b"), text.lookup_prefix("urn:b"));
+        assert_eq!(some("b"), x.lookup_prefix("urn:b"));
+        assert_eq!(None, root.lookup_prefix("urn:d"));
+        assert_eq!(None, root.lookup_prefix(""));
+        assert_eq!(some("a"), doc.lookup_prefix("urn:a"));
+
+        assert!(root.is_default_namespace(Some("urn:d")));
+        assert!(!root.is_default_namespace(Some("urn:a")));
+        assert!(comment.is_default_namespace(Some("urn:d")));
+        assert!(f.is_default_namespace(None));
+        assert!(f.is_default_namespace(Some("")));
+        assert!(!f.is_default_namespace(Some("urn:d")));
+
+        let detached = doc.create_attribute("y").unwrap();
+        assert_eq!(None, detached.lookup_namespace_uri(None));
+        assert_eq!(None, detached.lookup_prefix("urn:a"));
+        assert!(detached.is_default_namespace(None));
     }
 
     #[test]
-    fn test_document_node_mut_set_node_value_err5() {
-        let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
+    fn test_node_clone_node() {
+        let xml = "<!DOCTYPE root [<!ENTITY e 'v'><!ATTLIST p:a d CDATA 'x'>]><root xmlns:p='urn:p'><p:a b='1&amp;&e;' p:c='2'>t&e;&#x41;<![CDATA[c]]><!--m--><?pi d?><b/></p:a></root>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let root = doc.document_element().unwrap();
+        let a = root.first_child().unwrap();
 
-        // NodeMut
-        let err = doc.set_node_value("a").err().unwrap();
+        let shallow = a.clone_node(false).unwrap();
+        assert_ne!(a.id(), shallow.id());
+        assert_eq!(0, shallow.order());
+        assert!(shallow.parent_node().is_none());
+        assert!(!shallow.has_child());
+        assert_eq!("<p:a b=\"1&amp;&e;\" p:c=\"2\" />", shallow.to_string());
+
+        let deep = a.clone_node(true).unwrap();
+        root.append_child(deep.clone()).unwrap();
         assert_eq!(
-            error::Error::Dom(error::DomException::NoDataAllowedErr),
-            err
+            "<p:a b=\"1&amp;&e;\" p:c=\"2\">t&e;A<![CDATA[c]]><!--m--><?pi d?><b /></p:a>",
+            deep.to_string()
+        );
+        assert_eq!(Some("urn:p".to_string()), deep.namespace_uri());
+        let element = deep.as_element().unwrap();
+        assert_eq!("1&v", element.get_attribute("b"));
+        assert_eq!("x", element.get_attribute("d"));
+        assert_eq!(
+            1,
+            element
+                .attributes()
+                .unwrap()
+                .iter()
+                .filter(|v| v.name() == "d")
+                .count()
         );
+
+        // The copy is independent of the original.
+        element.set_attribute("b", "3").unwrap();
+        assert_eq!("1&v", a.as_element().unwrap().get_attribute("b"));
+
+        let c = a
+            .attributes()
+            .unwrap()
+            .get_named_item_ns(Some("urn:p"), "c")
+            .unwrap();
+        let copy = c.clone_node(false).unwrap();
+        assert!(copy.parent_node().is_none());
+        assert_eq!(Some("urn:p".to_string()), copy.namespace_uri());
+        assert_eq!(Some("2".to_string()), copy.node_value().unwrap());
+
+        let text = a.first_child().unwrap().clone_node(true).unwrap();
+        assert_eq!("t", text.to_string());
+
+        let err = doc.as_node().clone_node(true).err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NotSupportErr), err);
     }
 
     #[test]
-    fn test_document_node_mut_insert_before_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
+    fn test_document_get_element_by_id() {
+        let xml = "<!DOCTYPE root [<!ATTLIST a key ID #IMPLIED><!ATTLIST b id CDATA #IMPLIED>]><root><a key=' k1 '><a key='k2'/></a><b id='k3'/></root>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+
+        let k1 = doc.get_element_by_id("k1").unwrap();
+        assert_eq!("a", k1.tag_name());
+        let k2 = doc.get_element_by_id("k2").unwrap();
+        assert!(k1
+            .as_node()
+            .child_nodes()
+            .item(0)
+            .unwrap()
+            .ptr_eq(&k2.as_node()));
+        assert!(doc.get_element_by_id("k3").is_none());
+        assert!(doc.get_element_by_id(" k1 ").is_none());
 
-        // NodeMut
-        let a = doc
-            .insert_before(doc.create_comment("a").as_node(), None)
-            .unwrap();
-        assert_eq!("<root /><!--a-->", format!("{}", doc));
-        assert_eq!(Some(doc.as_node()), a.parent_node());
-        assert_eq!(Some(doc.clone()), a.owner_document());
-        assert_ne!(0, a.as_comment().unwrap().data.borrow().id());
-        assert_ne!(0, a.as_comment().unwrap().data.borrow().order());
-        let b = doc
-            .insert_before(doc.create_comment("b").as_node(), Some(&a))
-            .unwrap();
-        assert_eq!("<root /><!--b--><!--a-->", format!("{}", doc));
-        assert_eq!(Some(doc.as_node()), b.parent_node());
-        assert_eq!(Some(doc.clone()), b.owner_document());
-        assert_ne!(0, b.as_comment().unwrap().data.borrow().id());
-        assert_ne!(0, b.as_comment().unwrap().data.borrow().order());
+        // The index follows changes to the tree.
+        let root = doc.document_element().unwrap();
+        let a = root.append_element("a").unwrap();
+        a.set_attribute("key", "k4").unwrap();
+        assert!(doc
+            .get_element_by_id("k4")
+            .unwrap()
+            .as_node()
+            .ptr_eq(&a.as_node()));
+        a.set_attribute("key", "k5").unwrap();
+        assert!(doc.get_element_by_id("k4").is_none());
+        assert!(doc.get_element_by_id("k5").is_some());
+        root.remove_child(&k1.as_node()).unwrap();
+        assert!(doc.get_element_by_id("k1").is_none());
+        assert!(doc.get_element_by_id("k2").is_none());
+
+        let (_, doc) = XmlDocument::from_raw("<root id='a' />").unwrap();
+        assert!(doc.get_element_by_id("a").is_none());
     }
 
     #[test]
-    fn test_document_node_mut_insert_before_err2() {
-        let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
+    fn test_document_adopt_node() {
+        let (_, doc) = XmlDocument::from_raw("<root><a/><b/></root>").unwrap();
+        let (_, other) =
+            XmlDocument::from_raw("<p:r xmlns:p='urn:p'><p:c x='1' p:y='2'>t<d/></p:c>u</p:r>")
+                .unwrap();
+        let root = doc.document_element().unwrap();
+        let source = other.document_element().unwrap().first_child().unwrap();
 
-        // NodeMut
-        let err = doc
-            .insert_before(doc.create_attribute("a").unwrap().as_node(), None)
-            .err()
-            .unwrap();
-        assert_eq!("<root />", format!("{}", doc));
+        let node = doc.adopt_node(&source).unwrap();
+        assert!(same_document(Some(doc.clone()), node.owner_document()));
+        assert!(node.parent_node().is_none());
+        assert_eq!("<p:r xmlns:p=\"urn:p\">u</p:r>", other.to_string());
+        root.append_child(node.clone()).unwrap();
         assert_eq!(
-            error::Error::Dom(error::DomException::HierarchyRequestErr),
-            err
+            "<root><a /><b /><p:c x=\"1\" p:y=\"2\" xmlns:p=\"urn:p\">t<d /></p:c></root>",
+            doc.to_string()
         );
+        assert_eq!(Some("urn:p".to_string()), node.namespace_uri());
+
+        let a = root.first_child().unwrap();
+        let adopted = doc.adopt_node(&a).unwrap();
+        assert!(adopted.ptr_eq(&a));
+        assert!(a.parent_node().is_none());
+        assert_eq!("b", root.first_child().unwrap().node_name());
+
+        let x = node.attributes().unwrap().get_named_item("x").unwrap();
+        let attr = other.adopt_node(&x.as_node()).unwrap();
+        assert!(x.owner_element().is_none());
+        assert_eq!(Some("1".to_string()), attr.node_value().unwrap());
+        assert!(same_document(Some(other.clone()), attr.owner_document()));
+
+        let err = doc.adopt_node(&other.as_node()).err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NotSupportErr), err);
     }
 
     #[test]
-    fn test_document_node_mut_insert_before_err3() {
-        let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
-        let (_, doc2) = XmlDocument::from_raw("<r />").unwrap();
-
-        // NodeMut
-        let err = doc
-            .insert_before(doc2.create_comment("a").as_node(), None)
-            .err()
+    fn test_document_normalize_document() {
+        let xml = "<!DOCTYPE r [<!ELEMENT r (a)*><!ENTITY e 'x'><!ENTITY f 'z&e;'>]><r xmlns:p='urn:p'>\n<a xmlns:p='urn:p' xmlns:q='urn:q'>1<![CDATA[2]]>&f;&#51;<!--c--></a>\n</r>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        doc.normalize_document(&DomConfiguration::default())
             .unwrap();
-        assert_eq!("<root />", format!("{}", doc));
         assert_eq!(
-            error::Error::Dom(error::DomException::WrongDocumentErr),
-            err
+            "<r xmlns:p=\"urn:p\">\n<a xmlns:q=\"urn:q\">1<![CDATA[2]]>&f;&#51;<!--c--></a>\n</r>",
+            doc.document_element().unwrap().to_string()
+        );
+
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let config = DomConfiguration::default()
+            .cdata_sections(false)
+            .comments(false)
+            .entities(false)
+            .namespaces(false)
+            .element_content_whitespace(false);
+        doc.normalize_document(&config).unwrap();
+        let root = doc.document_element().unwrap();
+        assert_eq!(
+            "<r xmlns:p=\"urn:p\"><a xmlns:p=\"urn:p\" xmlns:q=\"urn:q\">12zx3</a></r>",
+            root.to_string()
+        );
+        let a = root.first_element_child().unwrap();
+        assert_eq!(1, a.child_nodes().length());
+        assert_eq!(
+            Some("12zx3".to_string()),
+            a.first_child().unwrap().node_value().unwrap()
         );
     }
 
     #[test]
-    fn test_document_node_mut_insert_before_err7() {
-        let (_, doc) = XmlDocument::from_raw("<root><e><ee /></e></root>").unwrap();
-        let ee = doc.get_elements_by_tag_name("ee").item(0).unwrap();
+    fn test_document_dom_error_handler() {
+        let xml = "<!DOCTYPE r [<!ELEMENT r (a)><!ELEMENT a EMPTY>]><r><a/><b/></r>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let root = doc.document_element().unwrap();
+        root.append_child(doc.create_element("p:c").unwrap().as_node())
+            .unwrap();
+        root.append_child(doc.create_cdata_section("d]]>e").as_node())
+            .unwrap();
+
+        let errors = Rc::new(RefCell::new(vec![]));
+        let handler = {
+            let errors = errors.clone();
+            move |e: &DomError| {
+                let name = e.related_node().node_name();
+                errors
+                    .borrow_mut()
+                    .push((e.severity(), e.kind().to_string(), name));
+                e.severity() != DomErrorSeverity::Error || e.kind() != "validation"
+            }
+        };
+        let config = DomConfiguration::default().error_handler(handler);
+
+        let value = doc.write_to_string(&config).unwrap();
+        assert_eq!(doc.to_string(), value);
+        assert_eq!(
+            vec![
+                (
+                    DomErrorSeverity::Error,
+                    "unbound-prefix".to_string(),
+                    "c".to_string()
+                ),
+                (
+                    DomErrorSeverity::Warning,
+                    "cdata-sections-splitted".to_string(),
+                    "#cdata-section".to_string()
+                ),
+            ],
+            *errors.borrow()
+        );
+        errors.borrow_mut().clear();
 
-        // NodeMut
         let err = doc
-            .insert_before(doc.create_comment("a").as_node(), Some(&ee))
+            .normalize_document(&config.clone().validate(true))
             .err()
             .unwrap();
-        assert_eq!("<root><e><ee /></e></root>", format!("{}", doc));
-        assert_eq!(error::Error::Dom(error::DomException::NotFoundErr), err);
-    }
-
-    #[test]
-    fn test_document_node_mut_replace_child_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root /><!--b--><!--a-->").unwrap();
-        let b = doc.child_nodes().iter().nth(1).unwrap();
+        assert!(matches!(err, error::Error::Aborted(_)));
+        assert_eq!(
+            vec![
+                (
+                    DomErrorSeverity::Error,
+                    "unbound-prefix".to_string(),
+                    "c".to_string()
+                ),
+                (
+                    DomErrorSeverity::Error,
+                    "validation".to_string(),
+                    "b".to_string()
+                ),
+            ],
+            *errors.borrow()
+        );
+        errors.borrow_mut().clear();
 
-        // NodeMut
-        let b = doc
-            .replace_child(doc.create_comment("c").as_node(), &b)
-            .unwrap();
-        assert_eq!("<root /><!--c--><!--a-->", format!("{}", doc));
-        assert_eq!(None, b.parent_node());
-        assert_eq!(Some(doc.clone()), b.owner_document());
-        assert_ne!(0, b.as_comment().unwrap().data.borrow().id());
-        assert_eq!(0, b.as_comment().unwrap().data.borrow().order());
+        doc.normalize_document(&config).unwrap();
+        assert_eq!(1, errors.borrow().len());
 
-        let c = doc.child_nodes().iter().nth(1).unwrap();
-        assert_eq!(Some(doc.as_node()), c.parent_node());
-        assert_eq!(Some(doc.clone()), c.owner_document());
-        assert_ne!(0, c.as_comment().unwrap().data.borrow().id());
-        assert_ne!(0, c.as_comment().unwrap().data.borrow().order());
+        let err = doc
+            .write_to_string(&DomConfiguration::default())
+            .err()
+            .unwrap();
+        assert!(matches!(err, error::Error::Aborted(_)));
+        let err = err.to_string();
+        assert!(err.contains("unbound-prefix"), "{}", err);
+        assert!(err.contains("prefix `p` is not bound"), "{}", err);
     }
 
     #[test]
-    fn test_document_node_mut_replace_child_err2() {
-        let (_, doc) = XmlDocument::from_raw("<root /><!--b--><!--a-->").unwrap();
-        let b = doc.child_nodes().iter().nth(1).unwrap();
+    fn test_document_rename_node() {
+        let xml = "<root xmlns:p='urn:p'><a x='1' y='2'>t</a><b/></root>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let root = doc.document_element().unwrap();
+        let a = root.first_child().unwrap();
+        assert_eq!(1, root.get_elements_by_tag_name("a").length());
+
+        let renamed = doc.rename_node(&a, None, "c").unwrap();
+        assert!(renamed.ptr_eq(&a));
+        assert_eq!("c", a.node_name());
+        assert_eq!(0, root.get_elements_by_tag_name("a").length());
+        assert_eq!(1, root.get_elements_by_tag_name("c").length());
+
+        doc.rename_node(&a, Some("urn:p"), "p:d").unwrap();
+        assert_eq!(Some("urn:p".to_string()), a.namespace_uri());
+        assert_eq!(Some("d".to_string()), a.local_name());
+        doc.rename_node(&a, Some("urn:q"), "q:e").unwrap();
+        assert_eq!(Some("urn:q".to_string()), a.namespace_uri());
+        assert_eq!(
+            "<root xmlns:p=\"urn:p\"><q:e x=\"1\" y=\"2\" xmlns:q=\"urn:q\">t</q:e><b /></root>",
+            doc.to_string()
+        );
 
-        // NodeMut
+        let x = a.attributes().unwrap().get_named_item("x").unwrap();
+        doc.rename_node(&x.as_node(), Some("urn:p"), "p:z").unwrap();
+        assert_eq!(Some("urn:p".to_string()), x.as_node().namespace_uri());
+        assert_eq!("z", x.node_name());
+        assert_eq!(
+            x.as_node(),
+            a.attributes().unwrap().item(0).unwrap().as_node()
+        );
+
+        let y = a.attributes().unwrap().get_named_item("y").unwrap();
         let err = doc
-            .replace_child(doc.create_attribute("c").unwrap().as_node(), &b)
+            .rename_node(&y.as_node(), Some("urn:p"), "p:z")
             .err()
             .unwrap();
-        assert_eq!("<root /><!--b--><!--a-->", format!("{}", doc));
         assert_eq!(
-            error::Error::Dom(error::DomException::HierarchyRequestErr),
+            error::Error::Dom(error::DomException::InuseAttributeErr),
             err
         );
-    }
-
-    #[test]
-    fn test_document_node_mut_replace_child_err3() {
-        let (_, doc) = XmlDocument::from_raw("<root /><!--b--><!--a-->").unwrap();
-        let b = doc.child_nodes().iter().nth(1).unwrap();
-        let (_, doc2) = XmlDocument::from_raw("<r />").unwrap();
-
-        // NodeMut
         let err = doc
-            .replace_child(doc2.create_comment("c").as_node(), &b)
+            .rename_node(&y.as_node(), Some("urn:p"), "z")
             .err()
             .unwrap();
-        assert_eq!("<root /><!--b--><!--a-->", format!("{}", doc));
+        assert_eq!(error::Error::Dom(error::DomException::NamespaceErr), err);
+        let err = doc.rename_node(&a, None, "p:f").err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NamespaceErr), err);
+        let err = doc.rename_node(&a, None, "1").err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::InvalidCharacterErr),
+            err
+        );
+        let text = a.first_child().unwrap();
+        let err = doc.rename_node(&text, None, "f").err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NotSupportErr), err);
+
+        let (_, other) = XmlDocument::from_raw("<root/>").unwrap();
+        let err = other.rename_node(&a, None, "f").err().unwrap();
         assert_eq!(
             error::Error::Dom(error::DomException::WrongDocumentErr),
             err
@@ -4170,164 +12956,507 @@ mod tests {
     }
 
     #[test]
-    fn test_document_node_mut_replace_child_err7() {
-        let (_, doc) = XmlDocument::from_raw("<root><e><ee /></e></root><!--b--><!--a-->").unwrap();
-        let ee = doc.get_elements_by_tag_name("ee").item(0).unwrap();
+    fn test_attr_owner_element() {
+        let (_, doc) = XmlDocument::from_raw("<root a='1'><e /></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let e = root.first_element_child().unwrap();
+        let inuse_err = error::Error::Dom(error::DomException::InuseAttributeErr);
 
-        // NodeMut
-        let err = doc
-            .replace_child(doc.create_comment("c").as_node(), &ee)
-            .err()
-            .unwrap();
-        assert_eq!(
-            "<root><e><ee /></e></root><!--b--><!--a-->",
-            format!("{}", doc)
-        );
-        assert_eq!(error::Error::Dom(error::DomException::NotFoundErr), err);
+        let a = root.get_attribute_node("a").unwrap();
+        assert!(a.owner_element().unwrap().as_node().ptr_eq(&root.as_node()));
+        assert_eq!(inuse_err, e.set_attribute_node(a.clone()).err().unwrap());
+        let attrs = e.attributes().unwrap();
+        assert_eq!(inuse_err, attrs.set_named_item_ns(a.clone()).err().unwrap());
+
+        let same = root.set_attribute_node(a.clone()).unwrap().unwrap();
+        assert!(same.as_node().ptr_eq(&a.as_node()));
+        assert_eq!("<root a=\"1\"><e /></root>", root.to_string());
+
+        let b = doc.create_attribute("b").unwrap();
+        assert!(b.owner_element().is_none());
+        e.set_attribute_node(b.clone()).unwrap();
+        assert!(b.owner_element().unwrap().as_node().ptr_eq(&e.as_node()));
+
+        let a = root.remove_attribute_node(a).unwrap();
+        assert!(a.owner_element().is_none());
+        e.set_attribute_node(a.clone()).unwrap();
+        assert!(a.owner_element().unwrap().as_node().ptr_eq(&e.as_node()));
+        assert_eq!("<root><e b=\"\" a=\"1\" /></root>", root.to_string());
     }
 
     #[test]
-    fn test_document_node_mut_remove_child_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root /><!--c--><!--a-->").unwrap();
-        let a = doc.child_nodes().iter().last().unwrap();
+    fn test_element_has_attribute() {
+        let xml = "<!DOCTYPE root [<!ATTLIST c d CDATA 'v'>]><root xmlns:a='urn:a' a:x='1' y='2'><b>t</b><c /></root>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let root = doc.document_element().unwrap();
+        let b = root.first_element_child().unwrap();
+        let c = b.next_element_sibling().unwrap();
 
-        // NodeMut
-        let a = doc.remove_child(&a).unwrap();
-        assert_eq!("<root /><!--c-->", format!("{}", doc));
-        assert_eq!(None, a.parent_node());
-        assert_eq!(Some(doc.clone()), a.owner_document());
-        assert_ne!(0, a.as_comment().unwrap().data.borrow().id());
-        assert_eq!(0, a.as_comment().unwrap().data.borrow().order());
+        assert!(root.has_attribute("x"));
+        assert!(root.has_attribute("y"));
+        assert!(!root.has_attribute("z"));
+        assert!(root.has_attribute_ns(Some("urn:a"), "x"));
+        assert!(!root.has_attribute_ns(None, "x"));
+        assert!(root.has_attribute_ns(None, "y"));
+        assert!(c.has_attribute("d"));
+
+        assert!(root.has_attributes());
+        assert!(!b.has_attributes());
+        assert!(c.has_attributes());
+        assert!(!b.first_child().unwrap().has_attributes());
+        assert!(!doc.as_node().has_attributes());
+
+        root.remove_attribute("y").unwrap();
+        assert!(!root.has_attribute("y"));
     }
 
     #[test]
-    fn test_document_node_mut_remove_child_err7() {
-        let (_, doc) = XmlDocument::from_raw("<root><e><ee /></e></root><!--c--><!--a-->").unwrap();
-        let ee = doc.get_elements_by_tag_name("ee").item(0).unwrap();
+    fn test_document_from_file() {
+        let path = std::env::temp_dir().join("xml-dom-test-document-from-file.xml");
+        std::fs::write(&path, "<root> <a /> </root>").unwrap();
 
-        // NodeMut
-        let err = doc.remove_child(&ee).err().unwrap();
-        assert_eq!(
-            "<root><e><ee /></e></root><!--c--><!--a-->",
-            format!("{}", doc)
-        );
-        assert_eq!(error::Error::Dom(error::DomException::NotFoundErr), err);
+        let context = Context::builder().whitespace_stripped(true).build();
+        let doc = XmlDocument::from_file(&path, context).unwrap();
+        assert_eq!("<root><a /></root>", format!("{}", doc));
+        std::fs::remove_file(&path).unwrap();
+
+        let err = XmlDocument::from_file(&path, Context::default())
+            .err()
+            .unwrap();
+        assert!(matches!(err, error::Error::Io(_)));
     }
 
     #[test]
-    fn test_document_node_mut_append_child_ok() {
+    fn test_document_uri() {
+        struct Resolver;
+
+        impl EntityResolver for Resolver {
+            fn resolve(&self, _: Option<&str>, system_id: &str) -> error::Result<Option<String>> {
+                Ok(Some(system_id.to_string()))
+            }
+        }
+
+        let dir = std::env::temp_dir().join("xml-dom-test-document-uri");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.xml");
+        std::fs::write(
+            &path,
+            "<!DOCTYPE root [<!ENTITY b SYSTEM '../c/b.xml'>]><root />",
+        )
+        .unwrap();
+
+        let context = Context::builder().resolver(Resolver).build();
+        let doc = XmlDocument::from_file(&path, context).unwrap();
+        let dir = std::fs::canonicalize(&dir).unwrap();
+        let uri = format!("file://{}/a.xml", dir.to_string_lossy());
+        assert_eq!(Some(uri), doc.document_uri());
+
+        let entity = doc
+            .doc_type()
+            .unwrap()
+            .entities()
+            .get_named_item("b")
+            .unwrap();
+        let expected = format!("file://{}/c/b.xml", dir.parent().unwrap().to_string_lossy());
+        assert_eq!(
+            Some(&[info::XmlEntityValue::Text(expected)][..]),
+            entity.entity.borrow().values()
+        );
+        std::fs::remove_file(&path).unwrap();
+
         let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
+        assert_eq!(None, doc.document_uri());
+        doc.set_document_uri(Some("http://a/b.xml"));
+        assert_eq!(Some("http://a/b.xml".to_string()), doc.document_uri());
+        doc.set_document_uri(None);
+        assert_eq!(None, doc.document_uri());
+    }
 
-        // NodeMut
-        let a = doc.append_child(doc.create_comment("a").as_node()).unwrap();
-        assert_eq!("<root /><!--a-->", format!("{}", doc));
-        assert_eq!(Some(doc.as_node()), a.parent_node());
-        assert_eq!(Some(doc.clone()), a.owner_document());
-        assert_ne!(0, a.as_comment().unwrap().data.borrow().id());
-        assert_ne!(0, a.as_comment().unwrap().data.borrow().order());
+    #[test]
+    fn test_document_from_reader() {
+        let doc = XmlDocument::from_reader("<root />".as_bytes(), Context::default()).unwrap();
+        assert_eq!("<root />", format!("{}", doc));
+        assert_eq!(None, doc.document_uri());
     }
 
     #[test]
-    fn test_document_node_mut_append_child_err2() {
-        let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
+    fn test_external_subset() {
+        let dir = std::env::temp_dir().join("xml-dom-test-external-subset");
+        std::fs::create_dir_all(dir.join("doc")).unwrap();
+        std::fs::write(
+            dir.join("doc").join("order.dtd"),
+            "<?xml encoding='UTF-8'?>\n\
+            <!ELEMENT order (item+, note)>\n\
+            <!ELEMENT item EMPTY>\n\
+            <!ATTLIST item qty CDATA '1'>\n\
+            <!ELEMENT note (#PCDATA)>\n\
+            <!ENTITY e 'external'>\n\
+            <!ENTITY f 'f'>\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("outside.dtd"),
+            "<!ELEMENT order ANY><!ELEMENT note EMPTY>",
+        )
+        .unwrap();
+        let path = dir.join("doc").join("a.xml");
+        std::fs::write(
+            &path,
+            "<!DOCTYPE order SYSTEM 'order.dtd' [<!ENTITY e 'internal'>]>\
+            <order><item/><note>&e;&f;</note></order>",
+        )
+        .unwrap();
 
-        // NodeMut
-        let err = doc
-            .append_child(doc.create_attribute("a").unwrap().as_node())
+        let context = Context::builder()
+            .text_expanded(true)
+            .external_subset_loaded(true)
+            .build();
+        let doc = XmlDocument::from_file(&path, context).unwrap();
+        assert!(validation::validate_dtd(&doc).unwrap().is_empty());
+        let root = doc.document_element().unwrap();
+        let item = root.first_element_child().unwrap();
+        assert_eq!("1", item.get_attribute("qty"));
+        let note = root.last_element_child().unwrap();
+        let text = note.child_nodes().item(0).unwrap().node_value().unwrap();
+        assert_eq!(Some("internalf".to_string()), text);
+        assert_eq!(
+            "<!DOCTYPE order SYSTEM \"order.dtd\" [<!ENTITY e 'internal'>]>",
+            doc.doc_type().unwrap().to_string()
+        );
+
+        let err = XmlDocument::from_file(&path, Context::default())
             .err()
             .unwrap();
-        assert_eq!("<root />", format!("{}", doc));
+        assert!(matches!(err, error::Error::Info(_)));
+
+        std::fs::write(
+            &path,
+            "<!DOCTYPE order SYSTEM '../outside.dtd'><order><note/></order>",
+        )
+        .unwrap();
+        let context = Context::builder().external_subset_loaded(true).build();
+        let doc = XmlDocument::from_file(&path, context).unwrap();
+        assert!(!validation::validate_dtd(&doc).unwrap().is_empty());
+
+        let resolver = resolver::FileResolver::new(&[dir.join("doc")]).unwrap();
+        let context = Context::builder()
+            .external_subset_loaded(true)
+            .resolver(resolver)
+            .build();
+        let err = XmlDocument::from_file(&path, context).err().unwrap();
+        assert!(matches!(err, error::Error::AccessDenied(_)));
+
+        let resolver = resolver::FileResolver::new(&[&dir]).unwrap();
+        let context = Context::builder()
+            .external_subset_loaded(true)
+            .resolver(resolver)
+            .build();
+        let doc = XmlDocument::from_file(&path, context).unwrap();
+        assert!(validation::validate_dtd(&doc).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_external_subset_conditional_section() {
+        let dir = std::env::temp_dir().join("xml-dom-test-external-subset-conditional");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("a.dtd"),
+            "<!ENTITY % common 'INCLUDE'><![%common;[<!ELEMENT root ANY>]]>",
+        )
+        .unwrap();
+        let path = dir.join("a.xml");
+        std::fs::write(&path, "<!DOCTYPE root SYSTEM 'a.dtd'><root />").unwrap();
+
+        let doc = XmlDocument::from_file(&path, Context::default()).unwrap();
         assert_eq!(
-            error::Error::Dom(error::DomException::HierarchyRequestErr),
-            err
+            "<!DOCTYPE root SYSTEM \"a.dtd\"><root />",
+            format!("{}", doc)
         );
+
+        let warnings = Rc::new(RefCell::new(vec![]));
+        let handler = warnings.clone();
+        let context = Context::builder()
+            .external_subset_loaded(true)
+            .warning_handler(move |v| handler.borrow_mut().push(v.to_string()))
+            .build();
+        let doc = XmlDocument::from_file(&path, context).unwrap();
+        assert_eq!("root", doc.document_element().unwrap().tag_name());
+        assert_eq!(1, warnings.borrow().len());
+        assert!(warnings.borrow()[0].contains("external subset skipped"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn test_document_node_mut_append_child_err3() {
-        let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
-        let (_, doc2) = XmlDocument::from_raw("<r />").unwrap();
+    fn test_resolver_file() {
+        let dir = std::env::temp_dir().join("xml-dom-test-resolver-file");
+        std::fs::create_dir_all(dir.join("dtd")).unwrap();
+        std::fs::write(dir.join("dtd").join("b.ent"), "c").unwrap();
+        std::fs::write(dir.join("d.ent"), "e").unwrap();
+        let path = dir.join("dtd").join("a.xml");
+        std::fs::write(
+            &path,
+            "<!DOCTYPE root [<!ENTITY b SYSTEM 'b.ent'><!ENTITY f SYSTEM 'http://a/f.ent'>]><root />",
+        )
+        .unwrap();
 
-        // NodeMut
-        let err = doc
-            .append_child(doc2.create_comment("a").as_node())
-            .err()
+        let resolver = resolver::FileResolver::new(&[dir.join("dtd")]).unwrap();
+        assert_eq!(1, resolver.roots().len());
+        let context = Context::builder().resolver(resolver.clone()).build();
+        let doc = XmlDocument::from_file(&path, context).unwrap();
+        let entities = doc.doc_type().unwrap().entities();
+        let b = entities.get_named_item("b").unwrap();
+        assert_eq!(
+            Some(&[info::XmlEntityValue::Text("c".to_string())][..]),
+            b.entity.borrow().values()
+        );
+        let f = entities.get_named_item("f").unwrap();
+        assert_eq!(None, f.entity.borrow().values());
+
+        std::fs::write(
+            &path,
+            "<!DOCTYPE root [<!ENTITY d SYSTEM '../d.ent'>]><root />",
+        )
+        .unwrap();
+        let context = Context::builder().resolver(resolver).build();
+        let err = XmlDocument::from_file(&path, context).err().unwrap();
+        assert!(matches!(err, error::Error::AccessDenied(_)));
+
+        let resolver = resolver::FileResolver::new(&[&dir]).unwrap();
+        let context = Context::builder().resolver(resolver).build();
+        let doc = XmlDocument::from_file(&path, context).unwrap();
+        let d = doc
+            .doc_type()
+            .unwrap()
+            .entities()
+            .get_named_item("d")
             .unwrap();
-        assert_eq!("<root />", format!("{}", doc));
         assert_eq!(
-            error::Error::Dom(error::DomException::WrongDocumentErr),
-            err
+            Some(&[info::XmlEntityValue::Text("e".to_string())][..]),
+            d.entity.borrow().values()
         );
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
+    #[cfg(feature = "quick-xml")]
     #[test]
-    fn test_document_as_node() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
-        let elem = doc.root_element().unwrap();
-        let root = elem.as_node();
+    fn test_quick_xml() {
+        let xml = "<?xml version=\"1.0\"?><!DOCTYPE root><?a b?><root c=\"d&amp;\"><!-- e --><f>g<![CDATA[i]]></f><j /></root>";
+        let events = quick::QuickXmlEvents::new(xml.as_bytes())
+            .collect::<error::Result<Vec<Event>>>()
+            .unwrap();
+        assert_eq!(Event::StartDocument, events[0]);
+        assert_eq!(Event::EndDocument, events[events.len() - 1]);
 
-        // AsNode
-        let node = doc.as_node();
-        assert_eq!("#document", node.node_name());
-        assert_eq!(None, node.node_value().unwrap());
-        assert_eq!(NodeType::Document, node.node_type());
-        assert_eq!(None, node.parent_node());
-        for child in node.child_nodes().iter() {
-            assert_eq!(root, child);
+        let doc = XmlDocument::from_events(events.into_iter()).unwrap();
+        assert_eq!(
+            "<?a b?><root c=\"d&amp;\"><!-- e --><f>g<![CDATA[i]]></f><j /></root>",
+            format!("{}", doc)
+        );
+
+        let (_, doc) =
+            XmlDocument::from_raw("<root a=\"b&quot;\">c&amp;<d /><?e?></root>").unwrap();
+        let mut writer = quick_xml::Writer::new(vec![]);
+        for event in doc.as_node().events().skip(1) {
+            let event = quick_xml::events::Event::try_from(&event).unwrap();
+            writer.write_event(event).unwrap();
         }
-        assert_eq!(Some(root.clone()), node.first_child());
-        assert_eq!(Some(root.clone()), node.last_child());
-        assert_eq!(None, node.previous_sibling());
-        assert_eq!(None, node.next_sibling());
-        assert_eq!(None, node.attributes());
-        assert_eq!(None, node.owner_document());
-        assert!(node.has_child());
+        assert_eq!(
+            "<root a=\"b&quot;\">c&amp;<d></d><?e?></root>",
+            String::from_utf8(writer.into_inner()).unwrap()
+        );
+
+        let err = quick_xml::events::Event::try_from(&Event::StartDocument)
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NotSupportErr), err);
+
+        let empty = quick_xml::events::BytesStart::new("a");
+        let err = Event::try_from(quick_xml::events::Event::Empty(empty))
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NotSupportErr), err);
+
+        let mut events = quick::QuickXmlEvents::new("<a></b>".as_bytes());
+        assert_eq!(Some(Ok(Event::StartDocument)), events.next());
+        assert!(events.next().unwrap().is_ok());
+        assert!(events.next().unwrap().is_err());
+        assert!(events.next().is_none());
     }
 
+    #[cfg(feature = "testutil")]
     #[test]
-    fn test_document_as_string_value() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+    fn test_testutil() {
+        use testutil::Options;
 
-        // AsStringValue
-        assert_eq!("", doc.as_string_value().unwrap());
+        assert_xml_eq!(
+            "<root b='2' a=\"1\"><c/><!-- d --></root>",
+            "<?xml version='1.0'?><root a='1' b='2'><c></c></root>"
+        );
+        assert_xml_eq!(
+            "<p:root xmlns:p='urn:p'><p:a/></p:root>",
+            "<p:root xmlns:p='urn:p'>\n  <p:a/>\n</p:root>",
+            Options::default().whitespace_stripped(true),
+        );
+
+        let err = testutil::compare(
+            "<root><a/><b>x</b></root>",
+            "<root><a/><b>y</b></root>",
+            Options::default(),
+        )
+        .err()
+        .unwrap();
+        assert!(err.ends_with("  <root>\n  <a>\n  </a>\n  <b>\n- x\n+ y\n  </b>\n  </root>\n"));
+
+        let err = testutil::compare(
+            "<root><!--a--></root>",
+            "<root/>",
+            Options::default().with_comments(true),
+        )
+        .err()
+        .unwrap();
+        assert!(err.contains("- <!--a-->\n"));
+
+        let err = testutil::compare("<root>", "<root/>", Options::default())
+            .err()
+            .unwrap();
+        assert!(err.starts_with("expected document is not well-formed"));
+
+        let doc = testutil::load_fixture("testutil.xml");
+        assert_xml_eq!("<root><a>b</a></root>", doc.to_string(), Options::default());
+        assert!(testutil::fixture_path("testutil.xml").ends_with("tests/fixtures/testutil.xml"));
     }
 
+    #[cfg(feature = "net")]
     #[test]
-    fn test_document_children() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
-        let elem = doc.root_element().unwrap();
-        let root = elem.as_node();
+    fn test_resolver_http() {
+        struct Client;
 
-        // HasChild
-        assert_eq!(vec![root], doc.children());
+        impl resolver::HttpClient for Client {
+            fn get(&self, url: &str, _: std::time::Duration, _: usize) -> error::Result<Vec<u8>> {
+                Ok(url.as_bytes().to_vec())
+            }
+        }
+
+        let mut resolver = resolver::HttpResolver::new(Client, &["A.example"]);
+        assert_eq!(&["a.example".to_string()], resolver.hosts());
+        assert_eq!(
+            Some("http://a.example:80/b.ent".to_string()),
+            resolver.resolve(None, "http://a.example:80/b.ent").unwrap()
+        );
+        assert_eq!(
+            Some("https://u@a.example/b.ent".to_string()),
+            resolver.resolve(None, "https://u@a.example/b.ent").unwrap()
+        );
+        assert_eq!(None, resolver.resolve(None, "file:///b.ent").unwrap());
+
+        let err = resolver
+            .resolve(None, "http://b.example/a.example")
+            .err()
+            .unwrap();
+        assert!(matches!(err, error::Error::AccessDenied(_)));
+
+        resolver.set_max_size(10);
+        let err = resolver
+            .resolve(None, "http://a.example/b.ent")
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::LengthLimitExceeded(10), err);
     }
 
     #[test]
-    fn test_document_debug() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
-
-        // fmt::Debug
+    fn test_resolve_uri() {
+        assert_eq!("b.xml", uri::resolve("", "b.xml"));
+        assert_eq!("http://b/c", uri::resolve("http://a/b", "http://b/c"));
         assert_eq!(
-            "XmlDocument { Ok(XmlElement { root }) }",
-            format!("{:?}", doc)
+            "http://a/b/d.xml",
+            uri::resolve("http://a/b/c.xml", "d.xml")
+        );
+        assert_eq!(
+            "http://a/d.xml",
+            uri::resolve("http://a/b/c.xml", "../d.xml")
+        );
+        assert_eq!("http://a/d.xml", uri::resolve("http://a/b/c.xml", "/d.xml"));
+        assert_eq!("http://a/b/d/", uri::resolve("http://a/b/c.xml", "./d/."));
+        assert_eq!("file:///a/d.xml", uri::resolve("file:///a/b.xml", "d.xml"));
+        assert_eq!("/a/d.xml", uri::resolve("/a/b.xml", "d.xml"));
+        assert_eq!("http://a/b?p#f", uri::resolve("http://a/b?p#e", "#f"));
+        assert_eq!("http://a/b?q", uri::resolve("http://a/b?p#e", "?q"));
+        assert_eq!("http://c/d", uri::resolve("http://a/b", "//c/d"));
+        assert_eq!("http://a/c/e?f#g", uri::resolve("http://a/c/d", "./e?f#g"));
+        assert_eq!(
+            "http://a/%C3%A9t%C3%A9%20a.xml",
+            uri::resolve("http://a/b", uri::iri_to_uri("été a.xml").as_str())
         );
     }
 
     #[test]
-    fn test_document_display() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
+    fn test_document_new() {
+        let doc = XmlDocument::new();
+        assert_eq!("", format!("{}", doc));
+        assert!(doc.document_element().is_err());
+        assert!(!doc.has_child());
+        assert_ne!(
+            doc.as_node().handle(),
+            XmlDocument::default().as_node().handle()
+        );
 
-        // fmt::Display
-        assert_eq!("<root />", format!("{}", doc));
+        let root = doc.create_element("root").unwrap();
+        doc.append_child(root.as_node()).unwrap();
+        root.append_child(doc.create_text_node("a").as_node())
+            .unwrap();
+        assert_eq!("<root>a</root>", format!("{}", doc));
+        assert_eq!(root, doc.document_element().unwrap());
+        assert_eq!(Some(doc.clone()), root.owner_document());
+        assert_eq!(
+            cmp::Ordering::Less,
+            doc.as_node().cmp_document_order(&root.as_node())
+        );
     }
 
     #[test]
-    fn test_document_impl() {
-        let (_, doc) = XmlDocument::from_raw("<root></root>").unwrap();
-        let elem = doc.root_element().unwrap();
+    fn test_document_stylesheets() {
+        let (_, doc) = XmlDocument::from_raw(
+            "<?xml-stylesheet href='a.css' type='text/css'?>\
+             <?xml-stylesheet href='b.css' title='B' media='print' charset='utf-8' alternate='yes'?>\
+             <?xml-stylesheet type='text/css'?>\
+             <?xml-stylesheet href='c.css' alternate='maybe'?>\
+             <?other href='d.css'?>\
+             <root><?xml-stylesheet href='e.css'?></root>\
+             <?xml-stylesheet href='f.css'?>",
+        )
+        .unwrap();
 
         // XmlDocument
-        assert_eq!(elem, doc.root_element().unwrap());
+        let stylesheets = doc.stylesheets();
+        assert_eq!(2, stylesheets.len());
+
+        assert_eq!(
+            XmlStylesheet {
+                href: "a.css".to_string(),
+                ty: Some("text/css".to_string()),
+                title: None,
+                media: None,
+                charset: None,
+                alternate: false,
+            },
+            stylesheets[0]
+        );
+
+        assert_eq!(
+            XmlStylesheet {
+                href: "b.css".to_string(),
+                ty: None,
+                title: Some("B".to_string()),
+                media: Some("print".to_string()),
+                charset: Some("utf-8".to_string()),
+                alternate: true,
+            },
+            stylesheets[1]
+        );
     }
 
     #[test]
@@ -4389,6 +13518,26 @@ mod tests {
         assert_eq!(2, children.iter().count());
     }
 
+    #[test]
+    fn test_node_list_live_iter() {
+        let (_, doc) = XmlDocument::from_raw("<root><a /><b /><c /></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let children = root.child_nodes();
+
+        // The snapshot keeps the children taken before the removal.
+        let mut iter = children.iter();
+        let a = iter.next().unwrap();
+        root.remove_child(&a).unwrap();
+        assert_eq!(2, iter.count());
+
+        // The live iterator sees the removal.
+        let mut iter = children.live_iter();
+        let b = iter.next().unwrap();
+        assert_eq!("b", b.node_name());
+        root.remove_child(&b).unwrap();
+        assert_eq!(0, iter.count());
+    }
+
     #[test]
     fn test_named_node_map_named_node_map() {
         let (_, doc) = XmlDocument::from_raw("<root a='1' b='2'/>").unwrap();
@@ -4411,6 +13560,23 @@ mod tests {
         assert_eq!(3, attrs.length());
     }
 
+    #[test]
+    fn test_named_node_map_clone() {
+        let (_, doc) = XmlDocument::from_raw("<root a='1' b='2'/>").unwrap();
+        let root = doc.root_element().unwrap();
+        let attrs = root.attributes().unwrap();
+        let cloned = attrs.clone();
+        assert_eq!(attrs, cloned);
+
+        root.set_attribute("c", "3").unwrap();
+        assert_eq!(3, cloned.length());
+        assert_eq!(attrs, cloned);
+        assert_eq!(
+            "[(\"a\", XmlAttr { a }), (\"b\", XmlAttr { b }), (\"c\", XmlAttr { c })]",
+            format!("{:?}", cloned)
+        );
+    }
+
     #[test]
     fn test_named_node_map_named_node_map_mut_set_named_item_ok() {
         let (_, doc) = XmlDocument::from_raw("<root a='1' b='2'/>").unwrap();
@@ -4553,7 +13719,7 @@ mod tests {
         // AttrMut
         attr.set_value("c").unwrap();
         assert_eq!("c", attr.value().unwrap());
-        for v in attr.children() {
+        for v in attr.child_iter() {
             assert_eq!(Some(attr.as_node()), v.parent_node());
             assert_eq!(Some(doc.clone()), v.owner_document());
             assert_ne!(0, v.as_text().unwrap().data.borrow().id());
@@ -4599,9 +13765,9 @@ mod tests {
         let attr = root.get_attribute_node("a").unwrap();
 
         // NodeMut
-        attr.set_node_value("a&amp;b&amp;c").unwrap();
+        attr.set_node_value("a&b&c").unwrap();
         assert_eq!("a&b&c", attr.value().unwrap());
-        for v in attr.children() {
+        for v in attr.child_iter() {
             match v {
                 XmlNode::EntityReference(v) => {
                     assert_eq!(Some(attr.as_node()), v.parent_node());
@@ -4653,7 +13819,7 @@ mod tests {
 
         // NodeMut
         let err = attr
-            .insert_before(doc.create_comment("d").as_node(), None)
+            .insert_before(doc.create_comment("d").unwrap().as_node(), None)
             .err()
             .unwrap();
         assert_eq!("<root a=\"b\" />", format!("{}", doc));
@@ -4703,7 +13869,7 @@ mod tests {
         let (_, doc) = XmlDocument::from_raw("<root a='b&amp;e'></root>").unwrap();
         let root = doc.document_element().unwrap();
         let attr = root.get_attribute_node("a").unwrap();
-        let e = attr.children().last().cloned().unwrap();
+        let e = attr.child_iter().last().unwrap();
 
         // NodeMut
         let e = attr
@@ -4715,7 +13881,7 @@ mod tests {
         assert_ne!(0, e.as_text().unwrap().data.borrow().id());
         assert_eq!(0, e.as_text().unwrap().data.borrow().order());
 
-        let f = attr.children().last().cloned().unwrap();
+        let f = attr.child_iter().last().unwrap();
         assert_eq!(Some(attr.as_node()), f.parent_node());
         assert_eq!(Some(doc.clone()), f.owner_document());
         assert_ne!(0, f.as_text().unwrap().data.borrow().id());
@@ -4727,11 +13893,11 @@ mod tests {
         let (_, doc) = XmlDocument::from_raw("<root a='b&amp;e'></root>").unwrap();
         let root = doc.document_element().unwrap();
         let attr = root.get_attribute_node("a").unwrap();
-        let e = attr.children().last().cloned().unwrap();
+        let e = attr.child_iter().last().unwrap();
 
         // NodeMut
         let err = attr
-            .replace_child(doc.create_comment("f").as_node(), &e)
+            .replace_child(doc.create_comment("f").unwrap().as_node(), &e)
             .err()
             .unwrap();
         assert_eq!("<root a=\"b&amp;e\" />", format!("{}", doc));
@@ -4746,7 +13912,7 @@ mod tests {
         let (_, doc) = XmlDocument::from_raw("<root a='b&amp;e'></root>").unwrap();
         let root = doc.document_element().unwrap();
         let attr = root.get_attribute_node("a").unwrap();
-        let e = attr.children().last().cloned().unwrap();
+        let e = attr.child_iter().last().unwrap();
         let (_, doc2) = XmlDocument::from_raw("<r />").unwrap();
 
         // NodeMut
@@ -4782,7 +13948,7 @@ mod tests {
         let (_, doc) = XmlDocument::from_raw("<root a='b&amp;d'></root>").unwrap();
         let root = doc.document_element().unwrap();
         let attr = root.get_attribute_node("a").unwrap();
-        let d = attr.children().last().cloned().unwrap();
+        let d = attr.child_iter().last().unwrap();
 
         // NodeMut
         let d = attr.remove_child(&d).unwrap();
@@ -4831,7 +13997,7 @@ mod tests {
 
         // NodeMut
         let err = attr
-            .append_child(doc.create_comment("d").as_node())
+            .append_child(doc.create_comment("d").unwrap().as_node())
             .err()
             .unwrap();
         assert_eq!("<root a=\"b\" />", format!("{}", doc));
@@ -4903,14 +14069,10 @@ mod tests {
             .unwrap();
 
         // AsExpandedName
-        assert_eq!(
-            (
-                "a".to_string(),
-                Some("c".to_string()),
-                Some("http://test/c".to_string())
-            ),
-            attr.as_expanded_name().unwrap().unwrap()
-        );
+        let name = attr.as_expanded_name().unwrap().unwrap();
+        assert_eq!(QName::new("a", Some("c"), Some("http://test/c")), name);
+        assert_eq!(Some("c"), name.prefix());
+        assert_eq!("c:a", name.to_string());
     }
 
     #[test]
@@ -4923,10 +14085,9 @@ mod tests {
             .unwrap();
 
         // AsExpandedName
-        assert_eq!(
-            ("a".to_string(), Some("xmlns".to_string()), None),
-            attr.as_expanded_name().unwrap().unwrap()
-        );
+        let name = attr.as_expanded_name().unwrap().unwrap();
+        assert_eq!(QName::new("a", None, None), name);
+        assert_eq!(None, name.prefix());
     }
 
     #[test]
@@ -4957,7 +14118,7 @@ mod tests {
         });
 
         // HasChild
-        assert_eq!(vec![text], attr.children());
+        assert_eq!(vec![text], attr.child_iter().collect::<Vec<_>>());
     }
 
     #[test]
@@ -5101,6 +14262,23 @@ mod tests {
         assert_eq!(Some(doc.clone()), a.owner_document());
         assert_ne!(0, a.attribute.borrow().id());
         assert_eq!(0, a.attribute.borrow().order());
+        assert_eq!("<elem1 a=\"\" d=\"\">data1</elem1>", format!("{}", elem1));
+    }
+
+    #[test]
+    fn test_element_element_mut_set_attribute_node_spaces_preserved() {
+        let mut context = Context::default();
+        context.set_spaces_preserved(true);
+        let (_, doc) =
+            XmlDocument::from_raw_with_context("<root\n  a='1'\tb='2' />", context).unwrap();
+        let root = doc.document_element().unwrap();
+
+        // ElementMut
+        root.set_attribute_node(doc.create_attribute("a").unwrap())
+            .unwrap();
+        root.set_attribute_node(doc.create_attribute("c").unwrap())
+            .unwrap();
+        assert_eq!("<root\n  a=\"\"\tb=\"2\" c=\"\" />", format!("{}", root));
     }
 
     #[test]
@@ -5198,6 +14376,45 @@ mod tests {
         assert_eq!(error::Error::Dom(error::DomException::NotFoundErr), err);
     }
 
+    #[test]
+    fn test_element_element_mut_normalize() {
+        let (_, doc) = XmlDocument::from_raw("<root>a<elem1>b<!--c-->d</elem1> </root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let elem1 = root.first_element_child().unwrap();
+        root.insert_before(doc.create_text_node("").as_node(), Some(&elem1.as_node()))
+            .unwrap();
+        root.insert_before(doc.create_text_node("e").as_node(), Some(&elem1.as_node()))
+            .unwrap();
+        elem1
+            .append_child(doc.create_text_node("f").as_node())
+            .unwrap();
+        root.append_child(doc.create_text_node("g").as_node())
+            .unwrap();
+        assert_eq!(6, root.child_nodes().length());
+        assert_eq!(4, elem1.child_nodes().length());
+
+        // ElementMut
+        root.normalize();
+        assert_eq!(3, root.child_nodes().length());
+        assert_eq!(
+            Some("ae".to_string()),
+            root.first_child().unwrap().node_value().unwrap()
+        );
+        assert_eq!(
+            Some(" g".to_string()),
+            root.last_child().unwrap().node_value().unwrap()
+        );
+        assert_eq!(3, elem1.child_nodes().length());
+        assert_eq!(
+            Some("df".to_string()),
+            elem1.last_child().unwrap().node_value().unwrap()
+        );
+        assert_eq!(
+            "<root>ae<elem1>b<!--c-->df</elem1> g</root>",
+            root.to_string()
+        );
+    }
+
     #[test]
     fn test_element_node() {
         let (_, doc) = XmlDocument::from_raw(
@@ -5759,7 +14976,7 @@ mod tests {
         .as_node();
 
         // HasChild
-        assert_eq!(vec![data1], elem1.children());
+        assert_eq!(vec![data1], elem1.child_iter().collect::<Vec<_>>());
     }
 
     #[test]
@@ -6462,244 +15679,593 @@ mod tests {
     }
 
     #[test]
-    fn test_cdata_text_mut_split_text_err0() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[cdata]]></root>").unwrap();
+    fn test_cdata_text_mut_split_text_err0() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[cdata]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // TextMut
+        let err = cdata.split_text(6).err().unwrap();
+        assert_eq!("cdata", cdata.node_value().unwrap().unwrap());
+        assert_eq!(error::Error::Dom(error::DomException::IndexSizeErr), err);
+    }
+
+    #[test]
+    fn test_cdata_character_data() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // CharacterData
+        assert_eq!("&<>\"", cdata.data().unwrap());
+        assert_eq!(4, cdata.length());
+    }
+
+    #[test]
+    fn test_cdata_character_data_substring_data_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // CharacterData
+        assert_eq!("<>", cdata.substring_data(1, 2).unwrap());
+    }
+
+    #[test]
+    fn test_cdata_character_data_substring_data_err0() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // CharacterData
+        let err = cdata.substring_data(5, 1).err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::IndexSizeErr), err);
+    }
+
+    #[test]
+    fn test_cdata_character_data_mut_set_data_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // CharacterDataMut
+        cdata.set_data("あいう").unwrap();
+        assert_eq!(Some("あいう"), cdata.node_value().unwrap().as_deref());
+    }
+
+    #[test]
+    fn test_cdata_character_data_mut_append_data_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // CharacterDataMut
+        cdata.append_data("えお").unwrap();
+        assert_eq!(Some("&<>\"えお"), cdata.node_value().unwrap().as_deref());
+    }
+
+    #[test]
+    fn test_cdata_character_data_mut_insert_data_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // CharacterDataMut
+        cdata.insert_data(1, "abc").unwrap();
+        assert_eq!(Some("&abc<>\""), cdata.node_value().unwrap().as_deref());
+    }
+
+    #[test]
+    fn test_cdata_character_data_mut_insert_data_err0() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // CharacterDataMut
+        let err = cdata.insert_data(5, "abc").err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::IndexSizeErr), err);
+    }
+
+    #[test]
+    fn test_cdata_character_data_mut_delete_data_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // CharacterDataMut
+        cdata.delete_data(1, 2).unwrap();
+        assert_eq!(Some("&\""), cdata.node_value().unwrap().as_deref());
+    }
+
+    #[test]
+    fn test_cdata_character_data_mut_delete_data_err0() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // CharacterDataMut
+        let err = cdata.delete_data(5, 1).err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::IndexSizeErr), err);
+    }
+
+    #[test]
+    fn test_cdata_character_data_mut_replace_data_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // CharacterDataMut
+        cdata.replace_data(1, 3, "いう").unwrap();
+        assert_eq!(Some("&いう"), cdata.node_value().unwrap().as_deref());
+    }
+
+    #[test]
+    fn test_cdata_character_data_mut_replace_data_err0() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // CharacterDataMut
+        let err = cdata.replace_data(5, 1, "いう").err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::IndexSizeErr), err);
+    }
+
+    #[test]
+    fn test_cdata_node() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // TODO: CDATA Section Test
+
+        // Node
+        assert_eq!("#cdata-section", cdata.node_name());
+        assert_eq!(Some("&<>\"".to_string()), cdata.node_value().unwrap());
+        assert_eq!(NodeType::CData, cdata.node_type());
+        assert_eq!(Some(root.as_node()), cdata.parent_node());
+        assert_eq!(0, cdata.child_nodes().length());
+        assert_eq!(None, cdata.first_child());
+        assert_eq!(None, cdata.last_child());
+        assert_eq!(None, cdata.previous_sibling());
+        assert_eq!(None, cdata.next_sibling());
+        assert_eq!(None, cdata.attributes());
+        assert_eq!(Some(doc.clone()), cdata.owner_document());
+        assert!(!cdata.has_child());
+    }
+
+    // TODO: more case.
+    #[test]
+    fn test_cdata_node_mut_set_node_value_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // Nodemut
+        cdata.set_node_value("abc").unwrap();
+        assert_eq!(Some("abc"), cdata.node_value().unwrap().as_deref());
+    }
+
+    #[test]
+    fn test_cdata_node_mut_insert_before_err2() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // Nodemut
+        let e = root.as_node();
+        let err = cdata.insert_before(e.clone(), Some(&e)).err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::HierarchyRequestErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_cdata_node_mut_replace_child_err2() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // Nodemut
+        let e = root.as_node();
+        let err = cdata.replace_child(e.clone(), &e).err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::HierarchyRequestErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_cdata_node_mut_remove_child_err2() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+
+        // Nodemut
+        let e = root.as_node();
+        let err = cdata.remove_child(&e).err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::HierarchyRequestErr),
+            err
+        );
+    }
+
+    #[test]
+    fn test_cdata_node_mut_append_child_err2() {
+        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
         let root = doc.document_element().unwrap();
         let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
 
-        // TextMut
-        let err = cdata.split_text(6).err().unwrap();
-        assert_eq!("cdata", cdata.node_value().unwrap().unwrap());
-        assert_eq!(error::Error::Dom(error::DomException::IndexSizeErr), err);
+        // Nodemut
+        let e = root.as_node();
+        let err = cdata.append_child(e.clone()).err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::HierarchyRequestErr),
+            err
+        );
     }
 
     #[test]
-    fn test_cdata_character_data() {
+    fn test_cdata_as_node() {
         let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
         let root = doc.document_element().unwrap();
         let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
 
-        // CharacterData
-        assert_eq!("&<>\"", cdata.data().unwrap());
-        assert_eq!(4, cdata.length());
+        // AsNode
+        let node = cdata.as_node();
+        assert_eq!("#cdata-section", node.node_name());
+        assert_eq!(Some("&<>\"".to_string()), node.node_value().unwrap());
+        assert_eq!(NodeType::CData, node.node_type());
+        assert_eq!(Some(root.as_node()), node.parent_node());
+        assert_eq!(0, node.child_nodes().length());
+        assert_eq!(None, node.first_child());
+        assert_eq!(None, node.last_child());
+        assert_eq!(None, node.previous_sibling());
+        assert_eq!(None, node.next_sibling());
+        assert_eq!(None, node.attributes());
+        assert_eq!(Some(doc.clone()), node.owner_document());
+        assert!(!node.has_child());
     }
 
     #[test]
-    fn test_cdata_character_data_substring_data_ok() {
+    fn test_cdata_as_string_value() {
         let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
         let root = doc.document_element().unwrap();
         let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
 
-        // CharacterData
-        assert_eq!("<>", cdata.substring_data(1, 2).unwrap());
+        // AsStringValue
+        assert_eq!("&<>\"", cdata.as_string_value().unwrap());
     }
 
     #[test]
-    fn test_cdata_character_data_substring_data_err0() {
+    fn test_cdata_debug() {
         let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
         let root = doc.document_element().unwrap();
         let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
 
-        // CharacterData
-        let err = cdata.substring_data(5, 1).err().unwrap();
-        assert_eq!(error::Error::Dom(error::DomException::IndexSizeErr), err);
+        // fmt::Debug
+        assert_eq!("XmlCDataSection { &<>\" }", format!("{:?}", cdata));
     }
 
     #[test]
-    fn test_cdata_character_data_mut_set_data_ok() {
+    fn test_cdata_display() {
         let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
         let root = doc.document_element().unwrap();
         let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
 
-        // CharacterDataMut
-        cdata.set_data("あいう").unwrap();
-        assert_eq!(Some("あいう"), cdata.node_value().unwrap().as_deref());
+        // fmt::Display
+        assert_eq!("<![CDATA[&<>\"]]>", format!("{}", cdata));
     }
 
     #[test]
-    fn test_cdata_character_data_mut_append_data_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+    fn test_doctype_document_type() {
+        let (_, doc) = XmlDocument::from_raw(
+            "<!DOCTYPE root [<!NOTATION a SYSTEM 'b'><!ENTITY c 'd'>]><root />",
+        )
+        .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
 
-        // CharacterDataMut
-        cdata.append_data("えお").unwrap();
-        assert_eq!(Some("&<>\"えお"), cdata.node_value().unwrap().as_deref());
+        // DocumentType
+        assert_eq!("root", doctype.name());
+        assert_eq!(1, doctype.entities().length());
+        assert_eq!(1, doctype.notations().length());
     }
 
     #[test]
-    fn test_cdata_character_data_mut_insert_data_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+    fn test_doctype_node() {
+        let (_, doc) = XmlDocument::from_raw(
+            "<!DOCTYPE root [<!NOTATION a SYSTEM 'b'><!ENTITY c 'd'>]><root />",
+        )
+        .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
         let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
 
-        // CharacterDataMut
-        cdata.insert_data(1, "abc").unwrap();
-        assert_eq!(Some("&abc<>\""), cdata.node_value().unwrap().as_deref());
+        // Node
+        assert_eq!("root", doctype.node_name());
+        assert_eq!(None, doctype.node_value().unwrap());
+        assert_eq!(NodeType::DocumentType, doctype.node_type());
+        assert_eq!(Some(doc.as_node()), doctype.parent_node());
+        assert_eq!(0, doctype.child_nodes().length());
+        assert_eq!(None, doctype.first_child());
+        assert_eq!(None, doctype.last_child());
+        assert_eq!(None, doctype.previous_sibling());
+        assert_eq!(Some(root.as_node()), doctype.next_sibling());
+        assert_eq!(None, doctype.attributes());
+        assert_eq!(Some(doc.clone()), doctype.owner_document());
+        assert!(!doctype.has_child());
     }
 
     #[test]
-    fn test_cdata_character_data_mut_insert_data_err0() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+    fn test_doctype_as_node() {
+        let (_, doc) = XmlDocument::from_raw(
+            "<!DOCTYPE root [<!NOTATION a SYSTEM 'b'><!ENTITY c 'd'>]><root />",
+        )
+        .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
         let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
 
-        // CharacterDataMut
-        let err = cdata.insert_data(5, "abc").err().unwrap();
-        assert_eq!(error::Error::Dom(error::DomException::IndexSizeErr), err);
+        // AsNode
+        let node = doctype.as_node();
+        assert_eq!("root", node.node_name());
+        assert_eq!(None, node.node_value().unwrap());
+        assert_eq!(NodeType::DocumentType, node.node_type());
+        assert_eq!(Some(doc.as_node()), node.parent_node());
+        assert_eq!(0, node.child_nodes().length());
+        assert_eq!(None, node.first_child());
+        assert_eq!(None, node.last_child());
+        assert_eq!(None, node.previous_sibling());
+        assert_eq!(Some(root.as_node()), node.next_sibling());
+        assert_eq!(None, node.attributes());
+        assert_eq!(Some(doc.clone()), node.owner_document());
+        assert!(!node.has_child());
     }
 
     #[test]
-    fn test_cdata_character_data_mut_delete_data_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+    fn test_doctype_debug() {
+        let (_, doc) = XmlDocument::from_raw(
+            "<!DOCTYPE root [<!NOTATION a SYSTEM 'b'><!ENTITY c 'd'>]><root />",
+        )
+        .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
 
-        // CharacterDataMut
-        cdata.delete_data(1, 2).unwrap();
-        assert_eq!(Some("&\""), cdata.node_value().unwrap().as_deref());
+        // fmt::Debug
+        assert_eq!("XmlDocumentType { root }", format!("{:?}", doctype));
     }
 
     #[test]
-    fn test_cdata_character_data_mut_delete_data_err0() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+    fn test_doctype_display() {
+        let (_, doc) = XmlDocument::from_raw(
+            "<!DOCTYPE root [<!NOTATION a SYSTEM 'b'><!ENTITY c 'd'>]><root />",
+        )
+        .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
 
-        // CharacterDataMut
-        let err = cdata.delete_data(5, 1).err().unwrap();
-        assert_eq!(error::Error::Dom(error::DomException::IndexSizeErr), err);
+        // fmt::Display
+        assert_eq!(
+            "<!DOCTYPE root [<!NOTATION a SYSTEM 'b'><!ENTITY c 'd'>]>",
+            format!("{}", doctype)
+        );
     }
 
     #[test]
-    fn test_cdata_character_data_mut_replace_data_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+    fn test_notation_notation() {
+        let (_, doc) =
+            XmlDocument::from_raw("<!DOCTYPE root [<!NOTATION a PUBLIC 'b' 'c'>]><root />")
+                .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+        let notation = doctype.notations().item(0).unwrap();
 
-        // CharacterDataMut
-        cdata.replace_data(1, 3, "いう").unwrap();
-        assert_eq!(Some("&いう"), cdata.node_value().unwrap().as_deref());
+        // Notation
+        assert_eq!("b", notation.public_id().unwrap());
+        assert_eq!("c", notation.system_id().unwrap());
     }
 
     #[test]
-    fn test_cdata_character_data_mut_replace_data_err0() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+    fn test_notation_node() {
+        let (_, doc) =
+            XmlDocument::from_raw("<!DOCTYPE root [<!NOTATION a PUBLIC 'b' 'c'>]><root />")
+                .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+        let notation = doctype.notations().item(0).unwrap();
 
-        // CharacterDataMut
-        let err = cdata.replace_data(5, 1, "いう").err().unwrap();
-        assert_eq!(error::Error::Dom(error::DomException::IndexSizeErr), err);
+        // Node
+        assert_eq!("a", notation.node_name());
+        assert_eq!(None, notation.node_value().unwrap());
+        assert_eq!(NodeType::Notation, notation.node_type());
+        assert_eq!(None, notation.parent_node());
+        assert_eq!(0, notation.child_nodes().length());
+        assert_eq!(None, notation.first_child());
+        assert_eq!(None, notation.last_child());
+        assert_eq!(None, notation.previous_sibling());
+        assert_eq!(None, notation.next_sibling());
+        assert_eq!(None, notation.attributes());
+        assert_eq!(Some(doc.clone()), notation.owner_document());
+        assert!(!notation.has_child());
+    }
+
+    #[test]
+    fn test_notation_as_node() {
+        let (_, doc) =
+            XmlDocument::from_raw("<!DOCTYPE root [<!NOTATION a PUBLIC 'b' 'c'>]><root />")
+                .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+        let notation = doctype.notations().item(0).unwrap();
+
+        // AsNode
+        let node = notation.as_node();
+        assert_eq!("a", node.node_name());
+        assert_eq!(None, node.node_value().unwrap());
+        assert_eq!(NodeType::Notation, node.node_type());
+        assert_eq!(None, node.parent_node());
+        assert_eq!(0, node.child_nodes().length());
+        assert_eq!(None, node.first_child());
+        assert_eq!(None, node.last_child());
+        assert_eq!(None, node.previous_sibling());
+        assert_eq!(None, node.next_sibling());
+        assert_eq!(None, node.attributes());
+        assert_eq!(Some(doc.clone()), node.owner_document());
+        assert!(!node.has_child());
+    }
+
+    #[test]
+    fn test_notation_debug() {
+        let (_, doc) =
+            XmlDocument::from_raw("<!DOCTYPE root [<!NOTATION a PUBLIC 'b' 'c'>]><root />")
+                .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+        let notation = doctype.notations().item(0).unwrap();
+
+        // fmt::Debug
+        assert_eq!("XmlNotation { a }", format!("{:?}", notation));
+    }
+
+    #[test]
+    fn test_notation_display() {
+        let (_, doc) =
+            XmlDocument::from_raw("<!DOCTYPE root [<!NOTATION a PUBLIC 'b' 'c'>]><root />")
+                .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+        let notation = doctype.notations().item(0).unwrap();
+
+        // fmt::Display
+        assert_eq!("<!NOTATION a PUBLIC \"b\" \"c\">", format!("{}", notation));
+    }
+
+    #[test]
+    fn test_entity_entity() {
+        let (_, doc) =
+            XmlDocument::from_raw("<!DOCTYPE root [<!ENTITY a PUBLIC 'b' 'c' NDATA d>]><root />")
+                .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+        let entity = doctype.entities().item(0).unwrap();
+
+        // Notation
+        assert_eq!("b", entity.public_id().unwrap());
+        assert_eq!("c", entity.system_id().unwrap());
+        assert_eq!("d", entity.notation_name().unwrap());
     }
 
     #[test]
-    fn test_cdata_node() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
-
-        // TODO: CDATA Section Test
+    fn test_entity_node() {
+        let (_, doc) =
+            XmlDocument::from_raw("<!DOCTYPE root [<!ENTITY a PUBLIC 'b' 'c' NDATA d>]><root />")
+                .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+        let entity = doctype.entities().item(0).unwrap();
 
         // Node
-        assert_eq!("#cdata-section", cdata.node_name());
-        assert_eq!(Some("&<>\"".to_string()), cdata.node_value().unwrap());
-        assert_eq!(NodeType::CData, cdata.node_type());
-        assert_eq!(Some(root.as_node()), cdata.parent_node());
-        assert_eq!(0, cdata.child_nodes().length());
-        assert_eq!(None, cdata.first_child());
-        assert_eq!(None, cdata.last_child());
-        assert_eq!(None, cdata.previous_sibling());
-        assert_eq!(None, cdata.next_sibling());
-        assert_eq!(None, cdata.attributes());
-        assert_eq!(Some(doc.clone()), cdata.owner_document());
-        assert!(!cdata.has_child());
+        assert_eq!("a", entity.node_name());
+        assert_eq!(None, entity.node_value().unwrap());
+        assert_eq!(NodeType::Entity, entity.node_type());
+        assert_eq!(None, entity.parent_node());
+        assert_eq!(0, entity.child_nodes().length());
+        assert_eq!(None, entity.first_child());
+        assert_eq!(None, entity.last_child());
+        assert_eq!(None, entity.previous_sibling());
+        assert_eq!(None, entity.next_sibling());
+        assert_eq!(None, entity.attributes());
+        assert_eq!(Some(doc.clone()), entity.owner_document());
+        assert!(!entity.has_child());
     }
 
-    // TODO: more case.
     #[test]
-    fn test_cdata_node_mut_set_node_value_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+    fn test_entity_as_node() {
+        let (_, doc) =
+            XmlDocument::from_raw("<!DOCTYPE root [<!ENTITY a PUBLIC 'b' 'c' NDATA d>]><root />")
+                .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+        let entity = doctype.entities().item(0).unwrap();
 
-        // Nodemut
-        cdata.set_node_value("abc").unwrap();
-        assert_eq!(Some("abc"), cdata.node_value().unwrap().as_deref());
+        // AsNode
+        let node = entity.as_node();
+        assert_eq!("a", node.node_name());
+        assert_eq!(None, node.node_value().unwrap());
+        assert_eq!(NodeType::Entity, node.node_type());
+        assert_eq!(None, node.parent_node());
+        assert_eq!(0, node.child_nodes().length());
+        assert_eq!(None, node.first_child());
+        assert_eq!(None, node.last_child());
+        assert_eq!(None, node.previous_sibling());
+        assert_eq!(None, node.next_sibling());
+        assert_eq!(None, node.attributes());
+        assert_eq!(Some(doc.clone()), node.owner_document());
+        assert!(!node.has_child());
     }
 
     #[test]
-    fn test_cdata_node_mut_insert_before_err2() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+    fn test_entity_children() {
+        let (_, doc) =
+            XmlDocument::from_raw("<!DOCTYPE root [<!ENTITY a PUBLIC 'b' 'c' NDATA d>]><root />")
+                .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+        let entity = doctype.entities().item(0).unwrap();
 
-        // Nodemut
-        let e = root.as_node();
-        let err = cdata.insert_before(e.clone(), Some(&e)).err().unwrap();
-        assert_eq!(
-            error::Error::Dom(error::DomException::HierarchyRequestErr),
-            err
-        );
+        // HasChild
+        assert_eq!(0, entity.child_iter().count());
     }
 
     #[test]
-    fn test_cdata_node_mut_replace_child_err2() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+    fn test_entity_debug() {
+        let (_, doc) =
+            XmlDocument::from_raw("<!DOCTYPE root [<!ENTITY a PUBLIC 'b' 'c' NDATA d>]><root />")
+                .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+        let entity = doctype.entities().item(0).unwrap();
 
-        // Nodemut
-        let e = root.as_node();
-        let err = cdata.replace_child(e.clone(), &e).err().unwrap();
-        assert_eq!(
-            error::Error::Dom(error::DomException::HierarchyRequestErr),
-            err
-        );
+        // fmt::Debug
+        assert_eq!("XmlEntity { a }", format!("{:?}", entity));
     }
 
     #[test]
-    fn test_cdata_node_mut_remove_child_err2() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+    fn test_entity_display() {
+        let (_, doc) =
+            XmlDocument::from_raw("<!DOCTYPE root [<!ENTITY a PUBLIC 'b' 'c' NDATA d>]><root />")
+                .unwrap();
+        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+        let entity = doctype.entities().item(0).unwrap();
 
-        // Nodemut
-        let e = root.as_node();
-        let err = cdata.remove_child(&e).err().unwrap();
+        // fmt::Display
         assert_eq!(
-            error::Error::Dom(error::DomException::HierarchyRequestErr),
-            err
+            "<!ENTITY a PUBLIC \"b\" \"c\" NDATA d>",
+            format!("{}", entity)
         );
     }
 
     #[test]
-    fn test_cdata_node_mut_append_child_err2() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+    fn test_ref_node() {
+        let (_, doc) = XmlDocument::from_raw("<root a='&amp;' />").unwrap();
         let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+        let attr = root.get_attribute_node("a").unwrap();
+        let eref = attr.child_nodes().item(0).unwrap().as_entity_ref().unwrap();
 
-        // Nodemut
-        let e = root.as_node();
-        let err = cdata.append_child(e.clone()).err().unwrap();
-        assert_eq!(
-            error::Error::Dom(error::DomException::HierarchyRequestErr),
-            err
-        );
+        // Node
+        assert_eq!("amp", eref.node_name());
+        assert_eq!(None, eref.node_value().unwrap());
+        assert_eq!(NodeType::EntityReference, eref.node_type());
+        assert_eq!(Some(attr.as_node()), eref.parent_node());
+        assert_eq!(0, eref.child_nodes().length());
+        assert_eq!(None, eref.first_child());
+        assert_eq!(None, eref.last_child());
+        assert_eq!(None, eref.previous_sibling());
+        assert_eq!(None, eref.next_sibling());
+        assert_eq!(None, eref.attributes());
+        assert_eq!(Some(doc.clone()), eref.owner_document());
+        assert!(!eref.has_child());
     }
 
     #[test]
-    fn test_cdata_as_node() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+    fn test_ref_as_node() {
+        let (_, doc) = XmlDocument::from_raw("<root a='&amp;' />").unwrap();
         let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+        let attr = root.get_attribute_node("a").unwrap();
+        let eref = attr.child_nodes().item(0).unwrap().as_entity_ref().unwrap();
 
         // AsNode
-        let node = cdata.as_node();
-        assert_eq!("#cdata-section", node.node_name());
-        assert_eq!(Some("&<>\"".to_string()), node.node_value().unwrap());
-        assert_eq!(NodeType::CData, node.node_type());
-        assert_eq!(Some(root.as_node()), node.parent_node());
+        let node = eref.as_node();
+        assert_eq!("amp", node.node_name());
+        assert_eq!(None, node.node_value().unwrap());
+        assert_eq!(NodeType::EntityReference, node.node_type());
+        assert_eq!(Some(attr.as_node()), node.parent_node());
         assert_eq!(0, node.child_nodes().length());
         assert_eq!(None, node.first_child());
         assert_eq!(None, node.last_child());
@@ -6711,175 +16277,192 @@ mod tests {
     }
 
     #[test]
-    fn test_cdata_as_string_value() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+    fn test_ref_children() {
+        let (_, doc) = XmlDocument::from_raw("<root a='&amp;' />").unwrap();
         let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+        let attr = root.get_attribute_node("a").unwrap();
+        let eref = attr.child_nodes().item(0).unwrap().as_entity_ref().unwrap();
 
-        // AsStringValue
-        assert_eq!("&<>\"", cdata.as_string_value().unwrap());
+        // HasChild
+        assert_eq!(0, eref.child_iter().count());
     }
 
     #[test]
-    fn test_cdata_debug() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+    fn test_ref_debug() {
+        let (_, doc) = XmlDocument::from_raw("<root a='&amp;' />").unwrap();
         let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+        let attr = root.get_attribute_node("a").unwrap();
+        let eref = attr.child_nodes().item(0).unwrap().as_entity_ref().unwrap();
 
         // fmt::Debug
-        assert_eq!("XmlCDataSection { &<>\" }", format!("{:?}", cdata));
+        assert_eq!("XmlEntityReference { amp }", format!("{:?}", eref));
     }
 
     #[test]
-    fn test_cdata_display() {
-        let (_, doc) = XmlDocument::from_raw("<root><![CDATA[&<>\"]]></root>").unwrap();
+    fn test_ref_display() {
+        let (_, doc) = XmlDocument::from_raw("<root a='&amp;' />").unwrap();
         let root = doc.document_element().unwrap();
-        let cdata = root.child_nodes().item(0).unwrap().as_cdata().unwrap();
+        let attr = root.get_attribute_node("a").unwrap();
+        let eref = attr.child_nodes().item(0).unwrap().as_entity_ref().unwrap();
 
         // fmt::Display
-        assert_eq!("<![CDATA[&<>\"]]>", format!("{}", cdata));
+        assert_eq!("&amp;", format!("{}", eref));
     }
 
     #[test]
-    fn test_doctype_document_type() {
-        let (_, doc) = XmlDocument::from_raw(
-            "<!DOCTYPE root [<!NOTATION a SYSTEM 'b'><!ENTITY c 'd'>]><root />",
-        )
-        .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+    fn test_ref_impl() {
+        let (_, doc) = XmlDocument::from_raw("<root a='&amp;' />").unwrap();
+        let root = doc.document_element().unwrap();
+        let attr = root.get_attribute_node("a").unwrap();
+        let eref = attr.child_nodes().item(0).unwrap().as_entity_ref().unwrap();
 
-        // DocumentType
-        assert_eq!("root", doctype.name());
-        assert_eq!(1, doctype.entities().length());
-        assert_eq!(1, doctype.notations().length());
+        // XmlEntityReference
+        assert_eq!("&", eref.value().unwrap());
     }
 
     #[test]
-    fn test_doctype_node() {
-        let (_, doc) = XmlDocument::from_raw(
-            "<!DOCTYPE root [<!NOTATION a SYSTEM 'b'><!ENTITY c 'd'>]><root />",
-        )
-        .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+    fn test_pi_pi() {
+        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
         let root = doc.document_element().unwrap();
+        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
 
-        // Node
-        assert_eq!("root", doctype.node_name());
-        assert_eq!(None, doctype.node_value().unwrap());
-        assert_eq!(NodeType::DocumentType, doctype.node_type());
-        assert_eq!(Some(doc.as_node()), doctype.parent_node());
-        assert_eq!(0, doctype.child_nodes().length());
-        assert_eq!(None, doctype.first_child());
-        assert_eq!(None, doctype.last_child());
-        assert_eq!(None, doctype.previous_sibling());
-        assert_eq!(Some(root.as_node()), doctype.next_sibling());
-        assert_eq!(None, doctype.attributes());
-        assert_eq!(Some(doc.clone()), doctype.owner_document());
-        assert!(!doctype.has_child());
+        // ProcessingInstruction
+        assert_eq!("a", pi.target());
+        assert_eq!("b", pi.data());
+    }
+
+    #[test]
+    fn test_pi_pi_mut() {
+        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
+
+        // ProcessingInstructionMut
+        pi.set_data("c").unwrap();
+        assert_eq!("c", pi.data());
+    }
+
+    #[test]
+    fn test_pi_pseudo_attributes() {
+        let (_, doc) = XmlDocument::from_raw("<root><?a b='1' c=\"2\"?></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
+
+        // XmlProcessingInstruction
+        let attributes = pi.pseudo_attributes().unwrap();
+        assert_eq!(
+            vec![
+                ("b".to_string(), "1".to_string()),
+                ("c".to_string(), "2".to_string())
+            ],
+            attributes
+        );
+    }
+
+    #[test]
+    fn test_pi_node() {
+        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
+
+        // Node
+        assert_eq!("a", pi.node_name());
+        assert_eq!(Some("b".to_string()), pi.node_value().unwrap());
+        assert_eq!(NodeType::PI, pi.node_type());
+        assert_eq!(Some(root.as_node()), pi.parent_node());
+        assert_eq!(0, pi.child_nodes().length());
+        assert_eq!(None, pi.first_child());
+        assert_eq!(None, pi.last_child());
+        assert_eq!(None, pi.previous_sibling());
+        assert_eq!(None, pi.next_sibling());
+        assert_eq!(None, pi.attributes());
+        assert_eq!(Some(doc.clone()), pi.owner_document());
+        assert!(!pi.has_child());
     }
 
     #[test]
-    fn test_doctype_as_node() {
-        let (_, doc) = XmlDocument::from_raw(
-            "<!DOCTYPE root [<!NOTATION a SYSTEM 'b'><!ENTITY c 'd'>]><root />",
-        )
-        .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+    fn test_pi_node_mut_set_node_value_ok() {
+        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
         let root = doc.document_element().unwrap();
+        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
 
-        // AsNode
-        let node = doctype.as_node();
-        assert_eq!("root", node.node_name());
-        assert_eq!(None, node.node_value().unwrap());
-        assert_eq!(NodeType::DocumentType, node.node_type());
-        assert_eq!(Some(doc.as_node()), node.parent_node());
-        assert_eq!(0, node.child_nodes().length());
-        assert_eq!(None, node.first_child());
-        assert_eq!(None, node.last_child());
-        assert_eq!(None, node.previous_sibling());
-        assert_eq!(Some(root.as_node()), node.next_sibling());
-        assert_eq!(None, node.attributes());
-        assert_eq!(Some(doc.clone()), node.owner_document());
-        assert!(!node.has_child());
+        // NodeMut
+        pi.set_node_value("c").unwrap();
+        assert_eq!("c", pi.data());
     }
 
     #[test]
-    fn test_doctype_debug() {
-        let (_, doc) = XmlDocument::from_raw(
-            "<!DOCTYPE root [<!NOTATION a SYSTEM 'b'><!ENTITY c 'd'>]><root />",
-        )
-        .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+    fn test_pi_node_mut_insert_before_err2() {
+        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
 
-        // fmt::Debug
-        assert_eq!("XmlDocumentType { root }", format!("{:?}", doctype));
+        // NodeMut
+        let e = root.as_node();
+        let err = pi.insert_before(e.clone(), Some(&e)).err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::HierarchyRequestErr),
+            err
+        );
     }
 
     #[test]
-    fn test_doctype_display() {
-        let (_, doc) = XmlDocument::from_raw(
-            "<!DOCTYPE root [<!NOTATION a SYSTEM 'b'><!ENTITY c 'd'>]><root />",
-        )
-        .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
+    fn test_pi_node_mut_replace_child_err2() {
+        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
 
-        // fmt::Display
+        // NodeMut
+        let e = root.as_node();
+        let err = pi.replace_child(e.clone(), &e).err().unwrap();
         assert_eq!(
-            "<!DOCTYPE root [<!NOTATION a SYSTEM \"b\"><!ENTITY c \"d\">]>",
-            format!("{}", doctype)
+            error::Error::Dom(error::DomException::HierarchyRequestErr),
+            err
         );
     }
 
     #[test]
-    fn test_notation_notation() {
-        let (_, doc) =
-            XmlDocument::from_raw("<!DOCTYPE root [<!NOTATION a PUBLIC 'b' 'c'>]><root />")
-                .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
-        let notation = doctype.notations().item(0).unwrap();
+    fn test_pi_node_mut_remove_child_err2() {
+        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
 
-        // Notation
-        assert_eq!("b", notation.public_id().unwrap());
-        assert_eq!("c", notation.system_id().unwrap());
+        // NodeMut
+        let e = root.as_node();
+        let err = pi.remove_child(&e).err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::HierarchyRequestErr),
+            err
+        );
     }
 
     #[test]
-    fn test_notation_node() {
-        let (_, doc) =
-            XmlDocument::from_raw("<!DOCTYPE root [<!NOTATION a PUBLIC 'b' 'c'>]><root />")
-                .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
-        let notation = doctype.notations().item(0).unwrap();
+    fn test_pi_node_mut_append_child_err2() {
+        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
 
-        // Node
-        assert_eq!("a", notation.node_name());
-        assert_eq!(None, notation.node_value().unwrap());
-        assert_eq!(NodeType::Notation, notation.node_type());
-        assert_eq!(None, notation.parent_node());
-        assert_eq!(0, notation.child_nodes().length());
-        assert_eq!(None, notation.first_child());
-        assert_eq!(None, notation.last_child());
-        assert_eq!(None, notation.previous_sibling());
-        assert_eq!(None, notation.next_sibling());
-        assert_eq!(None, notation.attributes());
-        assert_eq!(Some(doc.clone()), notation.owner_document());
-        assert!(!notation.has_child());
+        // NodeMut
+        let e = root.as_node();
+        let err = pi.append_child(e.clone()).err().unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::HierarchyRequestErr),
+            err
+        );
     }
 
     #[test]
-    fn test_notation_as_node() {
-        let (_, doc) =
-            XmlDocument::from_raw("<!DOCTYPE root [<!NOTATION a PUBLIC 'b' 'c'>]><root />")
-                .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
-        let notation = doctype.notations().item(0).unwrap();
+    fn test_pi_as_node() {
+        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
 
         // AsNode
-        let node = notation.as_node();
+        let node = pi.as_node();
         assert_eq!("a", node.node_name());
-        assert_eq!(None, node.node_value().unwrap());
-        assert_eq!(NodeType::Notation, node.node_type());
-        assert_eq!(None, node.parent_node());
+        assert_eq!(Some("b".to_string()), node.node_value().unwrap());
+        assert_eq!(NodeType::PI, node.node_type());
+        assert_eq!(Some(root.as_node()), node.parent_node());
         assert_eq!(0, node.child_nodes().length());
         assert_eq!(None, node.first_child());
         assert_eq!(None, node.last_child());
@@ -6891,79 +16474,85 @@ mod tests {
     }
 
     #[test]
-    fn test_notation_debug() {
-        let (_, doc) =
-            XmlDocument::from_raw("<!DOCTYPE root [<!NOTATION a PUBLIC 'b' 'c'>]><root />")
-                .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
-        let notation = doctype.notations().item(0).unwrap();
+    fn test_pi_as_expanded_name() {
+        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
 
-        // fmt::Debug
-        assert_eq!("XmlNotation { a }", format!("{:?}", notation));
+        // AsExpandedName
+        assert_eq!(
+            Some(QName::new("a", None, None)),
+            pi.as_expanded_name().unwrap()
+        );
     }
 
     #[test]
-    fn test_notation_display() {
-        let (_, doc) =
-            XmlDocument::from_raw("<!DOCTYPE root [<!NOTATION a PUBLIC 'b' 'c'>]><root />")
-                .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
-        let notation = doctype.notations().item(0).unwrap();
+    fn test_pi_as_string_value() {
+        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
 
-        // fmt::Display
-        assert_eq!("<!NOTATION a PUBLIC \"b\" \"c\">", format!("{}", notation));
+        // AsStringValue
+        assert_eq!("b", pi.as_string_value().unwrap());
     }
 
     #[test]
-    fn test_entity_entity() {
-        let (_, doc) =
-            XmlDocument::from_raw("<!DOCTYPE root [<!ENTITY a PUBLIC 'b' 'c' NDATA d>]><root />")
-                .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
-        let entity = doctype.entities().item(0).unwrap();
+    fn test_pi_debug() {
+        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
 
-        // Notation
-        assert_eq!("b", entity.public_id().unwrap());
-        assert_eq!("c", entity.system_id().unwrap());
-        assert_eq!("d", entity.notation_name().unwrap());
+        // fmt::Debug
+        assert_eq!("XmlProcessingInstruction { a }", format!("{:?}", pi));
     }
 
     #[test]
-    fn test_entity_node() {
-        let (_, doc) =
-            XmlDocument::from_raw("<!DOCTYPE root [<!ENTITY a PUBLIC 'b' 'c' NDATA d>]><root />")
-                .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
-        let entity = doctype.entities().item(0).unwrap();
+    fn test_pi_display() {
+        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
+
+        // fmt::Display
+        assert_eq!("<?a b?>", format!("{}", pi));
+    }
+
+    #[test]
+    fn test_namespace_node() {
+        let (_, doc) = XmlDocument::from_raw("<root xmlns:a='http://test/a'></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let namespaces = root.in_scope_namespace().unwrap();
+        let ns = namespaces.first().unwrap();
 
         // Node
-        assert_eq!("a", entity.node_name());
-        assert_eq!(None, entity.node_value().unwrap());
-        assert_eq!(NodeType::Entity, entity.node_type());
-        assert_eq!(None, entity.parent_node());
-        assert_eq!(0, entity.child_nodes().length());
-        assert_eq!(None, entity.first_child());
-        assert_eq!(None, entity.last_child());
-        assert_eq!(None, entity.previous_sibling());
-        assert_eq!(None, entity.next_sibling());
-        assert_eq!(None, entity.attributes());
-        assert_eq!(Some(doc.clone()), entity.owner_document());
-        assert!(!entity.has_child());
+        assert_eq!("a", ns.node_name());
+        assert_eq!(Some("http://test/a".to_string()), ns.node_value().unwrap());
+        assert_eq!(NodeType::Attribute, ns.node_type());
+        assert_eq!(None, ns.parent_node());
+        assert_eq!(0, ns.child_nodes().length());
+        assert_eq!(None, ns.first_child());
+        assert_eq!(None, ns.last_child());
+        assert_eq!(None, ns.previous_sibling());
+        assert_eq!(None, ns.next_sibling());
+        assert_eq!(None, ns.attributes());
+        assert_eq!(None, ns.owner_document());
+        assert!(!ns.has_child());
     }
 
     #[test]
-    fn test_entity_as_node() {
-        let (_, doc) =
-            XmlDocument::from_raw("<!DOCTYPE root [<!ENTITY a PUBLIC 'b' 'c' NDATA d>]><root />")
-                .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
-        let entity = doctype.entities().item(0).unwrap();
+    fn test_namespace_as_node() {
+        let (_, doc) = XmlDocument::from_raw("<root xmlns:a='http://test/a'></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let namespaces = root.in_scope_namespace().unwrap();
+        let ns = namespaces.first().unwrap();
 
         // AsNode
-        let node = entity.as_node();
-        assert_eq!("a", node.node_name());
-        assert_eq!(None, node.node_value().unwrap());
-        assert_eq!(NodeType::Entity, node.node_type());
+        let node = ns.as_node();
+        assert_eq!("a", node.node_name());
+        assert_eq!(
+            Some("http://test/a".to_string()),
+            node.node_value().unwrap()
+        );
+        assert_eq!(NodeType::Attribute, node.node_type());
         assert_eq!(None, node.parent_node());
         assert_eq!(0, node.child_nodes().length());
         assert_eq!(None, node.first_child());
@@ -6971,428 +16560,1078 @@ mod tests {
         assert_eq!(None, node.previous_sibling());
         assert_eq!(None, node.next_sibling());
         assert_eq!(None, node.attributes());
-        assert_eq!(Some(doc.clone()), node.owner_document());
+        assert_eq!(None, node.owner_document());
         assert!(!node.has_child());
     }
 
     #[test]
-    fn test_entity_children() {
-        let (_, doc) =
-            XmlDocument::from_raw("<!DOCTYPE root [<!ENTITY a PUBLIC 'b' 'c' NDATA d>]><root />")
-                .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
-        let entity = doctype.entities().item(0).unwrap();
+    fn test_namespace_as_expanded_name() {
+        let (_, doc) = XmlDocument::from_raw("<root xmlns:a='http://test/a'></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let namespaces = root.in_scope_namespace().unwrap();
+        let ns = namespaces.first().unwrap();
 
-        // HasChild
-        assert_eq!(0, entity.children().len());
+        // AsStringValue
+        assert_eq!(
+            Some(QName::new("a", None, None)),
+            ns.as_expanded_name().unwrap()
+        );
     }
 
     #[test]
-    fn test_entity_debug() {
-        let (_, doc) =
-            XmlDocument::from_raw("<!DOCTYPE root [<!ENTITY a PUBLIC 'b' 'c' NDATA d>]><root />")
-                .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
-        let entity = doctype.entities().item(0).unwrap();
+    fn test_namespace_as_string_value() {
+        let (_, doc) = XmlDocument::from_raw("<root xmlns:a='http://test/a'></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let namespaces = root.in_scope_namespace().unwrap();
+        let ns = namespaces.first().unwrap();
+
+        // AsStringValue
+        assert_eq!("http://test/a", ns.as_string_value().unwrap());
+    }
+
+    #[test]
+    fn test_namespace_debug() {
+        let (_, doc) = XmlDocument::from_raw("<root xmlns:a='http://test/a'></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let namespaces = root.in_scope_namespace().unwrap();
+        let ns = namespaces.first().unwrap();
 
         // fmt::Debug
-        assert_eq!("XmlEntity { a }", format!("{:?}", entity));
+        assert_eq!("XmlNamespace { http://test/a }", format!("{:?}", ns));
     }
 
     #[test]
-    fn test_entity_display() {
-        let (_, doc) =
-            XmlDocument::from_raw("<!DOCTYPE root [<!ENTITY a PUBLIC 'b' 'c' NDATA d>]><root />")
-                .unwrap();
-        let doctype = doc.child_nodes().item(0).unwrap().as_doctype().unwrap();
-        let entity = doctype.entities().item(0).unwrap();
+    fn test_namespace_display() {
+        let (_, doc) = XmlDocument::from_raw("<root xmlns:a='http://test/a'></root>").unwrap();
+        let root = doc.document_element().unwrap();
+        let namespaces = root.in_scope_namespace().unwrap();
+        let ns = namespaces.first().unwrap();
 
         // fmt::Display
-        assert_eq!(
-            "<!ENTITY a PUBLIC \"b\" \"c\" NDATA d>",
-            format!("{}", entity)
-        );
+        assert_eq!("xmlns:a=\"http://test/a\"", format!("{}", ns));
     }
 
     #[test]
-    fn test_ref_node() {
-        let (_, doc) = XmlDocument::from_raw("<root a='&amp;' />").unwrap();
+    fn test_namespace_impl() {
+        let (_, doc) = XmlDocument::from_raw("<root xmlns:a='http://test/a'></root>").unwrap();
         let root = doc.document_element().unwrap();
-        let attr = root.get_attribute_node("a").unwrap();
-        let eref = attr.child_nodes().item(0).unwrap().as_entity_ref().unwrap();
+        let namespaces = root.in_scope_namespace().unwrap();
+        let ns = namespaces.first().unwrap();
 
-        // Node
-        assert_eq!("amp", eref.node_name());
-        assert_eq!(None, eref.node_value().unwrap());
-        assert_eq!(NodeType::EntityReference, eref.node_type());
-        assert_eq!(Some(attr.as_node()), eref.parent_node());
-        assert_eq!(0, eref.child_nodes().length());
-        assert_eq!(None, eref.first_child());
-        assert_eq!(None, eref.last_child());
-        assert_eq!(None, eref.previous_sibling());
-        assert_eq!(None, eref.next_sibling());
-        assert_eq!(None, eref.attributes());
-        assert_eq!(Some(doc.clone()), eref.owner_document());
-        assert!(!eref.has_child());
+        // XmlNamespace
+        assert!(!ns.implicit());
     }
 
     #[test]
-    fn test_ref_as_node() {
-        let (_, doc) = XmlDocument::from_raw("<root a='&amp;' />").unwrap();
+    fn test_element_get_elements_by_tag_name_wildcard() {
+        let (_, doc) = XmlDocument::from_raw(
+            "<root xmlns:a='http://a' xmlns:b='http://b'><a:e /><b:e /><e /><a:f /></root>",
+        )
+        .unwrap();
         let root = doc.document_element().unwrap();
-        let attr = root.get_attribute_node("a").unwrap();
-        let eref = attr.child_nodes().item(0).unwrap().as_entity_ref().unwrap();
 
-        // AsNode
-        let node = eref.as_node();
-        assert_eq!("amp", node.node_name());
-        assert_eq!(None, node.node_value().unwrap());
-        assert_eq!(NodeType::EntityReference, node.node_type());
-        assert_eq!(Some(attr.as_node()), node.parent_node());
-        assert_eq!(0, node.child_nodes().length());
-        assert_eq!(None, node.first_child());
-        assert_eq!(None, node.last_child());
-        assert_eq!(None, node.previous_sibling());
-        assert_eq!(None, node.next_sibling());
-        assert_eq!(None, node.attributes());
-        assert_eq!(Some(doc.clone()), node.owner_document());
-        assert!(!node.has_child());
+        // Element
+        assert_eq!(3, root.get_elements_by_tag_name("*:e").length());
+        assert_eq!(2, root.get_elements_by_tag_name("a:*").length());
+        assert_eq!(1, root.get_elements_by_tag_name("b:e").length());
+        assert_eq!(5, root.get_elements_by_tag_name("*:*").length());
+
+        // XmlElement
+        let elems = root.get_elements_by_tag_name_ns("http://a", "*");
+        assert_eq!(2, elems.length());
+        assert_eq!(Some("f".to_string()), elems.item(1).map(|v| v.node_name()));
+        assert_eq!(3, root.get_elements_by_tag_name_ns("*", "e").length());
+        assert_eq!(
+            1,
+            root.get_elements_by_tag_name_ns("http://b", "e").length()
+        );
+        assert_eq!(
+            0,
+            root.get_elements_by_tag_name_ns("http://b", "f").length()
+        );
+
+        // XmlDocument
+        assert_eq!(5, doc.get_elements_by_tag_name_ns("*", "*").length());
+
+        // No namespace and default namespace
+        let (_, doc) = XmlDocument::from_raw(
+            "<root xmlns:a='http://a'><e /><f xmlns='http://a'><e /></f></root>",
+        )
+        .unwrap();
+        assert_eq!(2, doc.get_elements_by_tag_name_ns("", "*").length());
+        let elems = doc.get_elements_by_tag_name_ns("http://a", "*");
+        assert_eq!(2, elems.length());
+        let e = elems.item(1).unwrap().as_element().unwrap();
+        assert_eq!(1, e.get_elements_by_tag_name_ns("http://a", "e").length());
+        assert_eq!(0, e.get_elements_by_tag_name_ns("", "e").length());
     }
 
     #[test]
-    fn test_ref_children() {
-        let (_, doc) = XmlDocument::from_raw("<root a='&amp;' />").unwrap();
+    fn test_named_node_map_ns() {
+        let (_, doc) = XmlDocument::from_raw(
+            "<root xmlns:a='http://a' xmlns:b='http://b' a:c='1' b:c='2' c='3' />",
+        )
+        .unwrap();
         let root = doc.document_element().unwrap();
-        let attr = root.get_attribute_node("a").unwrap();
-        let eref = attr.child_nodes().item(0).unwrap().as_entity_ref().unwrap();
+        let attrs = root.attributes().unwrap();
 
-        // HasChild
-        assert_eq!(0, eref.children().len());
+        // XmlNamedNodeMap
+        let c = attrs.get_named_item_ns(Some("http://b"), "c").unwrap();
+        assert_eq!("2", c.value().unwrap());
+        let c = attrs.get_named_item_ns(None, "c").unwrap();
+        assert_eq!("3", c.value().unwrap());
+        assert_eq!(None, attrs.get_named_item_ns(Some("http://c"), "c"));
+
+        let c = attrs.remove_named_item_ns(Some("http://a"), "c").unwrap();
+        assert_eq!("1", c.value().unwrap());
+        assert_eq!(None, attrs.get_named_item_ns(Some("http://a"), "c"));
+        assert_eq!(
+            error::Error::Dom(error::DomException::NotFoundErr),
+            attrs
+                .remove_named_item_ns(Some("http://a"), "c")
+                .err()
+                .unwrap()
+        );
+
+        let (_, tree) = xml_parser::attribute("b:c='4'").unwrap();
+        let new = info::XmlAttribute::node(&tree, None, doc.document.borrow().context()).unwrap();
+        let new = XmlAttr::from(new.as_attribute().unwrap());
+        let old = attrs.set_named_item_ns(new).unwrap().unwrap();
+        assert_eq!("2", old.value().unwrap());
+        assert_eq!(
+            "4",
+            attrs
+                .get_named_item_ns(Some("http://b"), "c")
+                .unwrap()
+                .value()
+                .unwrap()
+        );
+        assert_eq!(
+            "3",
+            attrs.get_named_item_ns(None, "c").unwrap().value().unwrap()
+        );
+
+        // An attribute is in use only while another element owns it.
+        let own = attrs.get_named_item_ns(None, "c").unwrap();
+        assert!(attrs.set_named_item_ns(own.clone()).unwrap() == Some(own));
+        let other = doc.create_element("other").unwrap();
+        other.set_attribute("d", "5").unwrap();
+        let d = other.get_attribute_node("d").unwrap();
+        assert_eq!(
+            error::Error::Dom(error::DomException::InuseAttributeErr),
+            attrs.set_named_item_ns(d.clone()).err().unwrap()
+        );
+        other.remove_attribute_node(d.clone()).unwrap();
+        assert_eq!(None, attrs.set_named_item_ns(d).unwrap());
+        assert_eq!(
+            "5",
+            attrs.get_named_item_ns(None, "d").unwrap().value().unwrap()
+        );
     }
 
     #[test]
-    fn test_ref_debug() {
-        let (_, doc) = XmlDocument::from_raw("<root a='&amp;' />").unwrap();
-        let root = doc.document_element().unwrap();
-        let attr = root.get_attribute_node("a").unwrap();
-        let eref = attr.child_nodes().item(0).unwrap().as_entity_ref().unwrap();
+    fn test_element_max_depth() {
+        let mut context = Context::default();
+        context.set_max_depth(Some(3));
+        let (_, doc) =
+            XmlDocument::from_raw_with_context("<a>1<b>2<c>3<d>4</d></c></b></a>", context)
+                .unwrap();
+        let a = doc.document_element().unwrap();
+        let b = a.first_child().unwrap().next_sibling().unwrap();
+        let b = b.as_element().unwrap();
 
-        // fmt::Debug
-        assert_eq!("XmlEntityReference { amp }", format!("{:?}", eref));
+        // ElementList
+        assert_eq!(
+            error::Error::DepthLimitExceeded(3),
+            a.get_elements_by_tag_name("d").items().err().unwrap()
+        );
+        assert_eq!(0, a.get_elements_by_tag_name("d").length());
+        assert_eq!(1, b.get_elements_by_tag_name("d").length());
+
+        // AsStringValue
+        assert_eq!(
+            error::Error::DepthLimitExceeded(3),
+            a.as_string_value().err().unwrap()
+        );
+        assert_eq!("234", b.as_string_value().unwrap());
     }
 
     #[test]
-    fn test_ref_display() {
-        let (_, doc) = XmlDocument::from_raw("<root a='&amp;' />").unwrap();
+    fn test_element_list_cached() {
+        let (_, doc) = XmlDocument::from_raw("<r xmlns:p='http://a'><p:a/><a/></r>").unwrap();
         let root = doc.document_element().unwrap();
-        let attr = root.get_attribute_node("a").unwrap();
-        let eref = attr.child_nodes().item(0).unwrap().as_entity_ref().unwrap();
+        let list = doc.get_elements_by_tag_name_ns("http://a", "a");
+        assert_eq!(1, list.length());
+        let cached = list.cache.borrow().clone().unwrap();
+        assert_eq!(1, list.length());
+        assert_eq!(Some(cached), list.cache.borrow().clone());
 
-        // fmt::Display
-        assert_eq!("&amp;", format!("{}", eref));
+        let b = doc.create_element_ns(Some("http://a"), "p:a").unwrap();
+        root.append_child(b.as_node()).unwrap();
+        assert_eq!(2, list.length());
+
+        // Changing a namespace declaration in place changes the matches.
+        root.set_attribute("xmlns:p", "http://b").unwrap();
+        let items = list.items().unwrap();
+        assert_eq!(1, items.len());
+        assert!(items[0].as_node().is_same_node(&b.as_node()));
     }
 
     #[test]
-    fn test_ref_impl() {
-        let (_, doc) = XmlDocument::from_raw("<root a='&amp;' />").unwrap();
-        let root = doc.document_element().unwrap();
-        let attr = root.get_attribute_node("a").unwrap();
-        let eref = attr.child_nodes().item(0).unwrap().as_entity_ref().unwrap();
+    fn test_node_from_declaration_att_list() {
+        let xml = "<!DOCTYPE root [<!ATTLIST root a CDATA 'b'><!-- c -->]><root />";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let doctype = doc.doc_type().unwrap();
+        assert!(doctype.first_child().is_none());
+        assert_eq!("b", doc.document_element().unwrap().get_attribute("a"));
+
+        let att_list = doctype.declaration.borrow().attributes()[0].clone();
+        let err = XmlNode::try_from(Rc::new(info::XmlItem::from(att_list)))
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NotSupportErr), err);
+    }
 
-        // XmlEntityReference
-        assert_eq!("&", eref.value().unwrap());
+    #[test]
+    fn test_node_child_nodes_filtered() {
+        let (_, doc) =
+            XmlDocument::from_raw("<root>a<b /><!-- c --><?d?><e /><![CDATA[f]]></root>").unwrap();
+        let root = doc.document_element().unwrap().as_node();
+
+        let elements = root.child_nodes_filtered(WhatToShow::ELEMENT, |_| true);
+        assert_eq!(2, elements.length());
+        assert_eq!("b", elements.item(0).unwrap().node_name());
+        assert_eq!("e", elements.item(1).unwrap().node_name());
+        assert_eq!(None, elements.item(2));
+
+        let texts =
+            root.child_nodes_filtered(WhatToShow::TEXT | WhatToShow::CDATA_SECTION, |_| true);
+        assert_eq!(
+            vec![NodeType::Text, NodeType::CData],
+            texts.iter().map(|v| v.node_type()).collect::<Vec<_>>()
+        );
+
+        let nodes = root.child_nodes_filtered(WhatToShow::ALL, |v| v.node_name() != "e");
+        assert_eq!(5, nodes.length());
+
+        let elements = root.child_nodes_filtered(WhatToShow::ELEMENT, |_| true);
+        root.as_element()
+            .unwrap()
+            .append_child(doc.create_element("g").unwrap().as_node())
+            .unwrap();
+        assert_eq!(3, elements.length());
+
+        assert!(WhatToShow::ALL.contains(NodeType::Notation));
+        assert!(!WhatToShow::COMMENT.contains(NodeType::PI));
     }
 
     #[test]
-    fn test_pi_pi() {
-        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
+    fn test_node_tree_walker() {
+        let (_, doc) = XmlDocument::from_raw("<r><a><b/>c<!--d--></a><e><f/></e>g</r>").unwrap();
+        let root = doc.document_element().unwrap().as_node();
+
+        let mut walker = root.tree_walker(WhatToShow::ELEMENT, None);
+        let mut names = vec![];
+        while let Some(node) = walker.next_node() {
+            names.push(node.node_name());
+        }
+        assert_eq!(vec!["a", "b", "e", "f"], names);
+        assert_eq!("f", walker.current_node().node_name());
 
-        // ProcessingInstruction
-        assert_eq!("a", pi.target());
-        assert_eq!("b", pi.data());
+        let mut names = vec![];
+        while let Some(node) = walker.previous_node() {
+            names.push(node.node_name());
+        }
+        assert_eq!(vec!["e", "b", "a", "r"], names);
+        assert!(walker.current_node().ptr_eq(&root));
+
+        assert_eq!("a", walker.first_child().unwrap().node_name());
+        assert_eq!("e", walker.next_sibling().unwrap().node_name());
+        assert_eq!(None, walker.next_sibling());
+        assert_eq!("a", walker.previous_sibling().unwrap().node_name());
+        assert_eq!("b", walker.last_child().unwrap().node_name());
+        assert_eq!("a", walker.parent_node().unwrap().node_name());
+        assert_eq!("r", walker.parent_node().unwrap().node_name());
+        assert_eq!(None, walker.parent_node());
+        assert_eq!("e", walker.last_child().unwrap().node_name());
+
+        let filter: Rc<dyn NodeFilter> = Rc::new(|v: &XmlNode| v.node_name() != "a");
+        let mut walker = root.tree_walker(WhatToShow::ALL, Some(filter));
+        let mut types = vec![];
+        while let Some(node) = walker.next_node() {
+            types.push((node.node_type(), node.node_name()));
+        }
+        assert_eq!(
+            vec![
+                (NodeType::Element, "b".to_string()),
+                (NodeType::Text, "#text".to_string()),
+                (NodeType::Comment, "#comment".to_string()),
+                (NodeType::Element, "e".to_string()),
+                (NodeType::Element, "f".to_string()),
+                (NodeType::Text, "#text".to_string()),
+            ],
+            types
+        );
+
+        walker.set_current_node(root.clone());
+        assert_eq!("b", walker.first_child().unwrap().node_name());
+        assert_eq!(NodeType::Text, walker.next_sibling().unwrap().node_type());
+        assert_eq!(
+            NodeType::Comment,
+            walker.next_sibling().unwrap().node_type()
+        );
+        assert_eq!("e", walker.next_sibling().unwrap().node_name());
+        assert_eq!("r", walker.parent_node().unwrap().node_name());
+
+        let e = root.last_child().unwrap().previous_sibling().unwrap();
+        let mut walker = e.tree_walker(WhatToShow::ALL, None);
+        assert_eq!("f", walker.next_node().unwrap().node_name());
+        assert_eq!(None, walker.next_node());
+        assert_eq!(None, walker.next_sibling());
     }
 
     #[test]
-    fn test_pi_pi_mut() {
-        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+    fn test_node_node_iterator() {
+        let (_, doc) = XmlDocument::from_raw("<r><a><b/>c<!--d--></a><e><f/></e>g</r>").unwrap();
+        let root = doc.document_element().unwrap().as_node();
+
+        let names = root
+            .node_iterator(WhatToShow::ELEMENT, None)
+            .map(|v| v.node_name())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["r", "a", "b", "e", "f"], names);
+
+        let mut iterator = root.node_iterator(WhatToShow::ELEMENT, None);
+        while iterator.next_node().is_some() {}
+        assert_eq!("f", iterator.reference_node().node_name());
+        assert!(!iterator.pointer_before_reference_node());
+        assert_eq!("f", iterator.previous_node().unwrap().node_name());
+        assert_eq!("e", iterator.previous_node().unwrap().node_name());
+        assert!(iterator.pointer_before_reference_node());
+        assert_eq!("e", iterator.next_node().unwrap().node_name());
+
+        struct Reject;
+        impl NodeFilter for Reject {
+            fn accept_node(&self, node: &XmlNode) -> FilterResult {
+                match node.node_name().as_str() {
+                    "a" => FilterResult::Reject,
+                    "#text" => FilterResult::Skip,
+                    _ => FilterResult::Accept,
+                }
+            }
+        }
+        let names = root
+            .node_iterator(WhatToShow::ALL, Some(Rc::new(Reject)))
+            .map(|v| v.node_name())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["r", "b", "#comment", "e", "f"], names);
+
+        let mut walker = root.tree_walker(WhatToShow::ALL, Some(Rc::new(Reject)));
+        let mut names = vec![];
+        while let Some(node) = walker.next_node() {
+            names.push(node.node_name());
+        }
+        assert_eq!(vec!["e", "f"], names);
+        assert_eq!("e", walker.previous_node().unwrap().node_name());
+        assert_eq!("r", walker.previous_node().unwrap().node_name());
+        assert_eq!("e", walker.first_child().unwrap().node_name());
+
+        let mut iterator = root.node_iterator(WhatToShow::ELEMENT, None);
+        assert_eq!("r", iterator.next_node().unwrap().node_name());
+        assert_eq!("a", iterator.next_node().unwrap().node_name());
+        assert_eq!("b", iterator.next_node().unwrap().node_name());
+        let a = root.first_child().unwrap();
+        root.as_element().unwrap().remove_child(&a).unwrap();
+        assert_eq!("e", iterator.next_node().unwrap().node_name());
+        assert_eq!("f", iterator.next_node().unwrap().node_name());
+
+        let e = root.first_child().unwrap();
+        assert_eq!("f", iterator.previous_node().unwrap().node_name());
+        root.as_element().unwrap().remove_child(&e).unwrap();
+        assert_eq!(NodeType::Text, root.first_child().unwrap().node_type());
+        assert_eq!(None, iterator.next_node());
+        assert_eq!("r", iterator.previous_node().unwrap().node_name());
+    }
+
+    #[test]
+    fn test_element_traversal() {
+        let (_, doc) =
+            XmlDocument::from_raw("<root>a<b /><!-- c --><d>e</d>f<g /></root>").unwrap();
         let root = doc.document_element().unwrap();
-        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
+        assert_eq!(3, root.child_element_count());
 
-        // ProcessingInstructionMut
-        pi.set_data("c").unwrap();
-        assert_eq!("c", pi.data());
+        let b = root.first_element_child().unwrap();
+        assert_eq!("b", b.tag_name());
+        assert_eq!(None, b.previous_element_sibling());
+
+        let d = b.next_element_sibling().unwrap();
+        assert_eq!("d", d.tag_name());
+        assert_eq!(0, d.child_element_count());
+        assert_eq!(None, d.first_element_child());
+        assert_eq!(Some(b), d.previous_element_sibling());
+
+        let g = root.last_element_child().unwrap();
+        assert_eq!("g", g.tag_name());
+        assert_eq!(Some(d), g.previous_element_sibling());
+        assert_eq!(None, g.next_element_sibling());
+
+        assert_eq!(None, root.next_element_sibling());
+        assert_eq!(None, root.previous_element_sibling());
     }
 
     #[test]
-    fn test_pi_node() {
-        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+    fn test_node_iterator_adapters() {
+        let (_, doc) = XmlDocument::from_raw("<r><a><b/>c<!--d--></a><e><f/></e>g</r>").unwrap();
         let root = doc.document_element().unwrap();
-        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
 
-        // Node
-        assert_eq!("a", pi.node_name());
-        assert_eq!(Some("b".to_string()), pi.node_value().unwrap());
-        assert_eq!(NodeType::PI, pi.node_type());
-        assert_eq!(Some(root.as_node()), pi.parent_node());
-        assert_eq!(0, pi.child_nodes().length());
-        assert_eq!(None, pi.first_child());
-        assert_eq!(None, pi.last_child());
-        assert_eq!(None, pi.previous_sibling());
-        assert_eq!(None, pi.next_sibling());
-        assert_eq!(None, pi.attributes());
-        assert_eq!(Some(doc.clone()), pi.owner_document());
-        assert!(!pi.has_child());
+        let names = root
+            .descendants()
+            .map(|v| v.node_name())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec!["a", "b", "#text", "#comment", "e", "f", "#text"],
+            names
+        );
+        let names = root
+            .descendant_elements()
+            .map(|v| v.tag_name())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["a", "b", "e", "f"], names);
+
+        let a = root.first_element_child().unwrap();
+        assert_eq!(
+            vec!["b", "#text", "#comment"],
+            a.descendants().map(|v| v.node_name()).collect::<Vec<_>>()
+        );
+        let b = a.first_element_child().unwrap();
+        assert_eq!(0, b.descendants().count());
+
+        let names = b.ancestors().map(|v| v.node_name()).collect::<Vec<_>>();
+        assert_eq!(vec!["a", "r", "#document"], names);
+
+        let e = a.next_element_sibling().unwrap();
+        assert_eq!(
+            vec!["a"],
+            e.preceding_siblings()
+                .map(|v| v.node_name())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["e", "#text"],
+            a.following_siblings()
+                .map(|v| v.node_name())
+                .collect::<Vec<_>>()
+        );
+        let comment = a.as_node().last_child().unwrap();
+        assert_eq!(
+            vec!["#text", "b"],
+            comment
+                .preceding_siblings()
+                .map(|v| v.node_name())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(0, comment.following_siblings().count());
+    }
+
+    #[test]
+    fn test_text_whitespace() {
+        let xml = "<!DOCTYPE r [<!ENTITY e 'x'>]><r>\n\t<a> a &amp; &e; </a>\n</r>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
+        let root = doc.document_element().unwrap();
+
+        let ws = root.first_child().unwrap().as_text().unwrap();
+        assert!(ws.is_whitespace());
+        assert_eq!("", ws.trimmed());
+
+        let a = root.first_element_child().unwrap();
+        let text = a.first_child().unwrap().as_text().unwrap();
+        assert!(!text.is_whitespace());
+        assert_eq!("a", text.trimmed());
+        assert_eq!("a & x", a.text_trimmed().unwrap());
+        assert_eq!("a & x", root.text_trimmed().unwrap());
+
+        let context = Context::builder().text_expanded(true).build();
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context).unwrap();
+        let a = doc
+            .document_element()
+            .unwrap()
+            .first_element_child()
+            .unwrap();
+        let text = a.first_child().unwrap().as_expanded_text().unwrap();
+        assert!(!text.is_whitespace().unwrap());
+        assert_eq!("a & x", text.trimmed().unwrap());
+        assert_eq!("a & x", a.text_trimmed().unwrap());
     }
 
     #[test]
-    fn test_pi_node_mut_set_node_value_ok() {
-        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+    fn test_text_whole_text() {
+        let xml = "<!DOCTYPE r [<!ELEMENT r (x)*><!ELEMENT x ANY><!ENTITY e 'x'>]><r>\n<x>a<![CDATA[b]]>c<y/>d &e;</x>\n</r>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
         let root = doc.document_element().unwrap();
-        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
+        let x = root.first_element_child().unwrap();
+        let children = x.child_nodes();
+        let a = children.item(0).unwrap().as_text().unwrap();
+        let c = children.item(2).unwrap().as_text().unwrap();
+        let d = children.item(4).unwrap().as_text().unwrap();
+
+        assert_eq!("abc", a.whole_text().unwrap());
+        assert_eq!("abc", c.whole_text().unwrap());
+        assert_eq!("d ", d.whole_text().unwrap());
+
+        let ws = root.first_child().unwrap().as_text().unwrap();
+        assert!(ws.is_element_content_whitespace());
+        assert!(!a.is_element_content_whitespace());
+        let (_, other) = XmlDocument::from_raw("<r>\n<x/>\n</r>").unwrap();
+        let ws = other
+            .document_element()
+            .unwrap()
+            .first_child()
+            .unwrap()
+            .as_text()
+            .unwrap();
+        assert!(!ws.is_element_content_whitespace());
 
-        // NodeMut
-        pi.set_node_value("c").unwrap();
-        assert_eq!("c", pi.data());
+        let text = c.replace_whole_text("z").unwrap().unwrap();
+        assert!(text.as_node().ptr_eq(&c.as_node()));
+        assert_eq!("<x>z<y />d &e;</x>", x.to_string());
+        assert!(d.replace_whole_text("").unwrap().is_none());
+        assert_eq!("<x>z<y />&e;</x>", x.to_string());
+
+        let context = Context::builder().text_expanded(true).build();
+        let (_, doc) = XmlDocument::from_raw_with_context(xml, context).unwrap();
+        let x = doc
+            .document_element()
+            .unwrap()
+            .first_element_child()
+            .unwrap();
+        let expanded = x.last_child().unwrap().as_expanded_text().unwrap();
+        assert_eq!("d x", expanded.whole_text().unwrap());
+        assert!(!expanded.is_element_content_whitespace().unwrap());
+
+        let text = expanded.replace_whole_text("w").unwrap().unwrap();
+        assert_eq!(Some("w".to_string()), text.node_value().unwrap());
+        assert_eq!("<x>a<![CDATA[b]]>c<y />w</x>", x.to_string());
+
+        let expanded = x.first_child().unwrap().as_expanded_text().unwrap();
+        assert_eq!("abc", expanded.whole_text().unwrap());
+        let text = expanded.replace_whole_text("v").unwrap().unwrap();
+        assert_eq!("<x>v<y />w</x>", x.to_string());
+        assert_eq!("v", text.whole_text().unwrap());
     }
 
     #[test]
-    fn test_pi_node_mut_insert_before_err2() {
-        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+    fn test_element_append_element() {
+        let (_, doc) = XmlDocument::from_raw("<root />").unwrap();
         let root = doc.document_element().unwrap();
-        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
 
-        // NodeMut
-        let e = root.as_node();
-        let err = pi.insert_before(e.clone(), Some(&e)).err().unwrap();
+        let a = root.append_element("a").unwrap();
+        assert_eq!(Some(root.as_node()), a.parent_node());
+
+        let b = a.append_element_ns(Some("urn:x"), "x:b").unwrap();
+        b.append_element_ns(Some("urn:x"), "x:c").unwrap();
+        root.append_element_ns(Some("urn:d"), "d").unwrap();
         assert_eq!(
-            error::Error::Dom(error::DomException::HierarchyRequestErr),
-            err
+            "<root><a><x:b xmlns:x=\"urn:x\"><x:c /></x:b></a><d xmlns=\"urn:d\" /></root>",
+            root.to_string()
         );
+
+        let err = root
+            .append_element_ns(Some("urn:x"), "xmlns:x")
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NamespaceErr), err);
     }
 
     #[test]
-    fn test_pi_node_mut_replace_child_err2() {
-        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+    fn test_element_set_attribute_ns() {
+        let (_, doc) = XmlDocument::from_raw("<root xmlns:a=\"urn:a\" />").unwrap();
         let root = doc.document_element().unwrap();
-        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
+        let namespace_err = error::Error::Dom(error::DomException::NamespaceErr);
+
+        root.set_attribute_ns(None, "a:x", "1").unwrap();
+        root.set_attribute_ns(Some("urn:b"), "b:y", "2").unwrap();
+        root.set_attribute_ns(None, "x", "4").unwrap();
+        root.set_attribute_ns(None, "a:x", "5").unwrap();
+
+        let err = root.set_attribute_ns(None, "c:x", "1").err().unwrap();
+        assert_eq!(namespace_err, err);
+        let err = root
+            .set_attribute_ns(Some("urn:c"), "a:z", "3")
+            .err()
+            .unwrap();
+        assert_eq!(namespace_err, err);
+        let err = root
+            .set_attribute_ns(Some("urn:c"), "z", "3")
+            .err()
+            .unwrap();
+        assert_eq!(namespace_err, err);
+        let err = root
+            .set_attribute_ns(Some("urn:c"), "xmlns:c", "urn:c")
+            .err()
+            .unwrap();
+        assert_eq!(namespace_err, err);
+
+        root.append_element_ns(None, "a:e").unwrap();
+        let err = root.append_element_ns(None, "c:e").err().unwrap();
+        assert_eq!(namespace_err, err);
 
-        // NodeMut
-        let e = root.as_node();
-        let err = pi.replace_child(e.clone(), &e).err().unwrap();
         assert_eq!(
-            error::Error::Dom(error::DomException::HierarchyRequestErr),
-            err
+            "<root xmlns:a=\"urn:a\" a:x=\"5\" xmlns:b=\"urn:b\" b:y=\"2\" x=\"4\"><a:e /></root>",
+            root.to_string()
         );
     }
 
     #[test]
-    fn test_pi_node_mut_remove_child_err2() {
-        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+    fn test_element_attribute_ns() {
+        let xml = "<!DOCTYPE root [<!ATTLIST root d CDATA 'v'>]><root xmlns:a='urn:a' xmlns:b='urn:b' a:x='1' b:x='2' x='3' />";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
         let root = doc.document_element().unwrap();
-        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
 
-        // NodeMut
-        let e = root.as_node();
-        let err = pi.remove_child(&e).err().unwrap();
+        assert_eq!("1", root.get_attribute_ns(Some("urn:a"), "x"));
+        assert_eq!("2", root.get_attribute_ns(Some("urn:b"), "x"));
+        assert_eq!("3", root.get_attribute_ns(None, "x"));
+        assert_eq!("", root.get_attribute_ns(Some("urn:c"), "x"));
+        assert_eq!("v", root.get_attribute_ns(None, "d"));
+        assert!(root.has_attribute_ns(Some("urn:b"), "x"));
+        assert!(!root.has_attribute_ns(Some("urn:a"), "y"));
+
+        let attr = root.get_attribute_node_ns(Some("urn:b"), "x").unwrap();
+        assert_eq!(Some("b".to_string()), attr.prefix());
+
+        root.set_attribute_ns(Some("urn:b"), "b:x", "4").unwrap();
+        assert_eq!("4", root.get_attribute_ns(Some("urn:b"), "x"));
+        assert_eq!("1", root.get_attribute_ns(Some("urn:a"), "x"));
+
+        root.remove_attribute_ns(Some("urn:a"), "x").unwrap();
+        root.remove_attribute_ns(Some("urn:c"), "x").unwrap();
+        assert!(!root.has_attribute_ns(Some("urn:a"), "x"));
         assert_eq!(
-            error::Error::Dom(error::DomException::HierarchyRequestErr),
-            err
+            "<root xmlns:a=\"urn:a\" xmlns:b=\"urn:b\" b:x=\"4\" x=\"3\" />",
+            root.to_string()
         );
     }
 
     #[test]
-    fn test_pi_node_mut_append_child_err2() {
-        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+    fn test_element_rename_prefix() {
+        let (_, doc) = XmlDocument::from_raw(
+            "<a:root xmlns:a=\"http://a\" a:x=\"1\"><a:b a:y=\"2\"/><c xmlns:a=\"http://c\"><a:d/></c></a:root>",
+        )
+        .unwrap();
         let root = doc.document_element().unwrap();
-        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
+        root.rename_prefix("a", "n").unwrap();
+        assert_eq!(
+            "<n:root xmlns:n=\"http://a\" n:x=\"1\"><n:b n:y=\"2\" /><c xmlns:a=\"http://c\"><a:d /></c></n:root>",
+            root.to_string()
+        );
 
-        // NodeMut
-        let e = root.as_node();
-        let err = pi.append_child(e.clone()).err().unwrap();
+        let b = root.first_element_child().unwrap();
+        let ns = b.element.borrow().namespace_name().unwrap();
+        assert_eq!(Some("http://a"), ns.as_deref());
+
+        let c = b.next_element_sibling().unwrap();
+        c.rename_namespace_prefix("http://a", "m").unwrap();
         assert_eq!(
-            error::Error::Dom(error::DomException::HierarchyRequestErr),
-            err
+            "<c xmlns:a=\"http://c\" xmlns:m=\"http://a\"><a:d /></c>",
+            c.to_string()
         );
-    }
 
-    #[test]
-    fn test_pi_as_node() {
-        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
+        let err = root.rename_prefix("n", "xmlns").err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NamespaceErr), err);
 
-        // AsNode
-        let node = pi.as_node();
-        assert_eq!("a", node.node_name());
-        assert_eq!(Some("b".to_string()), node.node_value().unwrap());
-        assert_eq!(NodeType::PI, node.node_type());
-        assert_eq!(Some(root.as_node()), node.parent_node());
-        assert_eq!(0, node.child_nodes().length());
-        assert_eq!(None, node.first_child());
-        assert_eq!(None, node.last_child());
-        assert_eq!(None, node.previous_sibling());
-        assert_eq!(None, node.next_sibling());
-        assert_eq!(None, node.attributes());
-        assert_eq!(Some(doc.clone()), node.owner_document());
-        assert!(!node.has_child());
+        let err = root.rename_prefix("z", "y").err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NamespaceErr), err);
+
+        let err = c.rename_prefix("n", "a").err().unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NamespaceErr), err);
     }
 
     #[test]
-    fn test_pi_as_expanded_name() {
-        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
+    fn test_element_set_default_namespace() {
+        let (_, doc) = XmlDocument::from_raw(
+            "<e:env xmlns:e=\"urn:e\"><e:body><a><b /></a><c xmlns=\"urn:c\"><d /></c><f xmlns=\"\" /></e:body></e:env>",
+        )
+        .unwrap();
+        let body = doc
+            .document_element()
+            .unwrap()
+            .first_element_child()
+            .unwrap();
+        body.set_default_namespace("urn:n").unwrap();
+        assert_eq!(
+            "<e:body xmlns=\"urn:n\"><a><b /></a><c xmlns=\"urn:c\"><d /></c><f /></e:body>",
+            body.to_string()
+        );
 
-        // AsExpandedName
+        let b = body
+            .first_element_child()
+            .unwrap()
+            .first_element_child()
+            .unwrap();
+        let ns = b.element.borrow().namespace_name().unwrap();
+        assert_eq!(Some("urn:n"), ns.as_deref());
+
+        let (_, doc) = XmlDocument::from_raw(
+            "<root xmlns=\"urn:o\"><a><b /></a><p:c xmlns:p=\"urn:p\"><d /></p:c></root>",
+        )
+        .unwrap();
+        let root = doc.document_element().unwrap();
+        root.declare_default_namespace("urn:n").unwrap();
         assert_eq!(
-            Some(("a".to_string(), None, None)),
-            pi.as_expanded_name().unwrap()
+            "<root xmlns=\"urn:n\"><a xmlns=\"urn:o\"><b /></a><p:c xmlns:p=\"urn:p\"><d xmlns=\"urn:o\" /></p:c></root>",
+            root.to_string()
         );
+
+        let err = root
+            .set_default_namespace(info::NamespaceUri::xml().value())
+            .err()
+            .unwrap();
+        assert_eq!(error::Error::Dom(error::DomException::NamespaceErr), err);
     }
 
     #[test]
-    fn test_pi_as_string_value() {
-        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
-
-        // AsStringValue
-        assert_eq!("b", pi.as_string_value().unwrap());
+    fn test_node_handle() {
+        let (_, doc1) = XmlDocument::from_raw("<root><a /><a /></root>").unwrap();
+        let (_, doc2) = XmlDocument::from_raw("<root><a /><a /></root>").unwrap();
+        let root1 = doc1.document_element().unwrap().as_node();
+        let root2 = doc2.document_element().unwrap().as_node();
+        assert_eq!(root1, root2);
+        assert!(!root1.ptr_eq(&root2));
+        assert_eq!(root1.id(), root2.id());
+        assert_ne!(root1.handle(), root2.handle());
+
+        let a = root1.first_child().unwrap();
+        let b = root1.last_child().unwrap();
+        assert_eq!(a, b);
+        assert!(!a.ptr_eq(&b));
+        assert!(a.ptr_eq(&root1.child_nodes().item(0).unwrap()));
+
+        let mut map = std::collections::HashMap::new();
+        map.insert(a.handle(), "a");
+        map.insert(b.handle(), "b");
+        map.insert(root1.child_nodes().item(0).unwrap().handle(), "c");
+        assert_eq!(2, map.len());
+        assert_eq!(Some(&"c"), map.get(&a.handle()));
+        assert_eq!(Some(&"b"), map.get(&b.handle()));
     }
 
     #[test]
-    fn test_pi_debug() {
-        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+    fn test_node_cmp_document_order() {
+        let (_, doc) = XmlDocument::from_raw("<root a='1'><b><c /></b><d /></root>").unwrap();
         let root = doc.document_element().unwrap();
-        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
-
-        // fmt::Debug
-        assert_eq!("XmlProcessingInstruction { a }", format!("{:?}", pi));
+        let b = root.get_elements_by_tag_name("b").item(0).unwrap();
+        let c = root.get_elements_by_tag_name("c").item(0).unwrap();
+        let d = root.get_elements_by_tag_name("d").item(0).unwrap();
+
+        // move b (with c) after d.
+        root.append_child(b.clone()).unwrap();
+        let e = root
+            .insert_before(doc.create_element("e").unwrap().as_node(), Some(&d))
+            .unwrap();
+        root.set_attribute("f", "2").unwrap();
+        let a = root.get_attribute_node("a").unwrap().as_node();
+        let f = root.get_attribute_node("f").unwrap().as_node();
+
+        let mut nodes = vec![
+            DocumentOrdered(c.clone()),
+            DocumentOrdered(b.clone()),
+            DocumentOrdered(d.clone()),
+            DocumentOrdered(f.clone()),
+            DocumentOrdered(e.clone()),
+            DocumentOrdered(root.as_node()),
+            DocumentOrdered(a.clone()),
+            DocumentOrdered(doc.as_node()),
+        ];
+        nodes.sort();
+        let expected = vec![
+            doc.as_node(),
+            root.as_node(),
+            a,
+            f,
+            e,
+            d.clone(),
+            b.clone(),
+            c.clone(),
+        ];
+        assert_eq!(expected, nodes.into_iter().map(|v| v.0).collect::<Vec<_>>());
+        assert_eq!(
+            cmp::Ordering::Equal,
+            c.clone().cmp_document_order(&c.clone())
+        );
     }
 
     #[test]
-    fn test_pi_display() {
-        let (_, doc) = XmlDocument::from_raw("<root><?a b?></root>").unwrap();
+    fn test_node_compare_document_position() {
+        let (_, doc) = XmlDocument::from_raw("<root a='1' b='2'><c><d /></c><e /></root>").unwrap();
         let root = doc.document_element().unwrap();
-        let pi = root.child_nodes().item(0).unwrap().as_pi().unwrap();
-
-        // fmt::Display
-        assert_eq!("<?a b?>", format!("{}", pi));
+        let find = |name: &str| root.get_elements_by_tag_name(name).item(0).unwrap();
+        let (c, d, e) = (find("c"), find("d"), find("e"));
+        let a = root.get_attribute_node("a").unwrap().as_node();
+        let b = root.get_attribute_node("b").unwrap().as_node();
+        let root = root.as_node();
+
+        let following = DocumentPosition::FOLLOWING;
+        let preceding = DocumentPosition::PRECEDING;
+        let contains = DocumentPosition::CONTAINS | DocumentPosition::PRECEDING;
+        let contained_by = DocumentPosition::CONTAINED_BY | DocumentPosition::FOLLOWING;
+        assert_eq!(DocumentPosition::default(), c.compare_document_position(&c));
+        assert_eq!(contained_by, root.compare_document_position(&d));
+        assert_eq!(contains, d.compare_document_position(&root));
+        assert_eq!(contains, d.compare_document_position(&doc.as_node()));
+        assert_eq!(following, d.compare_document_position(&e));
+        assert_eq!(preceding, e.compare_document_position(&c));
+        assert_eq!(contained_by, root.compare_document_position(&a));
+        assert_eq!(contains, b.compare_document_position(&root));
+        assert_eq!(following, a.compare_document_position(&b));
+        assert_eq!(following, b.compare_document_position(&c));
+        assert_eq!(preceding, d.compare_document_position(&a));
+        assert_eq!(0x14, root.compare_document_position(&d).bits());
+        assert!(e.compare_document_position(&c).contains(preceding));
+
+        let detached = doc.create_element("f").unwrap().as_node();
+        let position = root.compare_document_position(&detached);
+        assert!(position.contains(DocumentPosition::DISCONNECTED));
+        assert!(position.contains(DocumentPosition::IMPLEMENTATION_SPECIFIC));
+        let reverse = detached.compare_document_position(&root);
+        assert_eq!(position.contains(following), reverse.contains(preceding));
+
+        let (_, other) = XmlDocument::from_raw("<root />").unwrap();
+        let position = root.compare_document_position(&other.document_element().unwrap().as_node());
+        assert!(position.contains(DocumentPosition::DISCONNECTED));
     }
 
     #[test]
-    fn test_namespace_node() {
-        let (_, doc) = XmlDocument::from_raw("<root xmlns:a='http://test/a'></root>").unwrap();
+    fn test_node_is_equal_node() {
+        let xml = "<!DOCTYPE root [<!ENTITY e 'v'>]><root><a x='1' y='2'>t<!--c--></a><a y='2' x='1'>t<!--c--></a><a x='1'>t</a><p:a xmlns:p='urn:p' x='1' y='2'>t<!--c--></p:a></root>";
+        let (_, doc) = XmlDocument::from_raw(xml).unwrap();
         let root = doc.document_element().unwrap();
-        let namespaces = root.in_scope_namespace().unwrap();
-        let ns = namespaces.first().unwrap();
-
-        // Node
-        assert_eq!("a", ns.node_name());
-        assert_eq!(Some("http://test/a".to_string()), ns.node_value().unwrap());
-        assert_eq!(NodeType::Attribute, ns.node_type());
-        assert_eq!(None, ns.parent_node());
-        assert_eq!(0, ns.child_nodes().length());
-        assert_eq!(None, ns.first_child());
-        assert_eq!(None, ns.last_child());
-        assert_eq!(None, ns.previous_sibling());
-        assert_eq!(None, ns.next_sibling());
-        assert_eq!(None, ns.attributes());
-        assert_eq!(None, ns.owner_document());
-        assert!(!ns.has_child());
+        let children = root.child_nodes();
+        let a1 = children.item(0).unwrap();
+        let a2 = children.item(1).unwrap();
+        let a3 = children.item(2).unwrap();
+        let a4 = children.item(3).unwrap();
+
+        assert!(a1.is_same_node(&a1));
+        assert!(a1.is_same_node(&root.first_child().unwrap()));
+        assert!(!a1.is_same_node(&a2));
+
+        assert!(a1.is_equal_node(&a1));
+        assert!(a1.is_equal_node(&a2));
+        assert!(a2.is_equal_node(&a1));
+        assert!(!a1.is_equal_node(&a3));
+        assert!(!a1.is_equal_node(&a4));
+        assert!(!a1.is_equal_node(&a1.first_child().unwrap()));
+
+        let x1 = a1.attributes().unwrap().get_named_item("x").unwrap();
+        let x3 = a3.attributes().unwrap().get_named_item("x").unwrap();
+        assert!(x1.as_node().is_equal_node(&x3.as_node()));
+        assert!(!x1.as_node().is_same_node(&x3.as_node()));
+
+        let clone = root.clone_node(true).unwrap();
+        assert!(root.as_node().is_equal_node(&clone));
+        assert!(!root.as_node().is_same_node(&clone));
+        a2.first_child()
+            .unwrap()
+            .as_text()
+            .unwrap()
+            .set_data("u")
+            .unwrap();
+        assert!(!a1.is_equal_node(&a2));
+        assert!(!root.as_node().is_equal_node(&clone));
+
+        let (_, same) = XmlDocument::from_raw("<!DOCTYPE root [<!ENTITY e 'v'>]><root/>").unwrap();
+        let (_, diff) = XmlDocument::from_raw("<!DOCTYPE root [<!ENTITY e 'w'>]><root/>").unwrap();
+        let doctype = |d: &XmlDocument| d.doc_type().unwrap().as_node();
+        assert!(doctype(&doc).is_equal_node(&doctype(&same)));
+        assert!(!doctype(&doc).is_equal_node(&doctype(&diff)));
+
+        let (_, other) = XmlDocument::from_raw(xml).unwrap();
+        assert!(!doc.as_node().is_equal_node(&other.as_node()));
+        a2.first_child()
+            .unwrap()
+            .as_text()
+            .unwrap()
+            .set_data("t")
+            .unwrap();
+        assert!(doc.as_node().is_equal_node(&other.as_node()));
     }
 
     #[test]
-    fn test_namespace_as_node() {
-        let (_, doc) = XmlDocument::from_raw("<root xmlns:a='http://test/a'></root>").unwrap();
+    fn test_node_user_data() {
+        let (_, doc) = XmlDocument::from_raw("<root><a x='1'>t</a></root>").unwrap();
         let root = doc.document_element().unwrap();
-        let namespaces = root.in_scope_namespace().unwrap();
-        let ns = namespaces.first().unwrap();
+        let a = root.first_child().unwrap();
+        let x = a
+            .attributes()
+            .unwrap()
+            .get_named_item("x")
+            .unwrap()
+            .as_node();
+
+        let log = Rc::new(RefCell::new(vec![]));
+        let handler: Rc<dyn UserDataHandler> = {
+            let log = log.clone();
+            Rc::new(
+                move |op: UserDataOperation,
+                      key: &str,
+                      data: &UserData,
+                      src: Option<&XmlNode>,
+                      dst: Option<&XmlNode>| {
+                    let data = *data.downcast_ref::<i32>().unwrap();
+                    let names = (src.map(|v| v.node_name()), dst.map(|v| v.node_name()));
+                    log.borrow_mut().push((op, key.to_string(), data, names));
+                },
+            )
+        };
+        let value = |v: Option<UserData>| v.map(|v| *v.downcast_ref::<i32>().unwrap());
 
-        // AsNode
-        let node = ns.as_node();
-        assert_eq!("a", node.node_name());
+        assert_eq!(None, value(a.get_user_data("k")));
+        assert_eq!(None, value(a.set_user_data("k", Some(Rc::new(1)), None)));
+        assert_eq!(Some(1), value(a.get_user_data("k")));
         assert_eq!(
-            Some("http://test/a".to_string()),
-            node.node_value().unwrap()
+            Some(1),
+            value(a.set_user_data("k", Some(Rc::new(2)), Some(handler.clone())))
         );
-        assert_eq!(NodeType::Attribute, node.node_type());
-        assert_eq!(None, node.parent_node());
-        assert_eq!(0, node.child_nodes().length());
-        assert_eq!(None, node.first_child());
-        assert_eq!(None, node.last_child());
-        assert_eq!(None, node.previous_sibling());
-        assert_eq!(None, node.next_sibling());
-        assert_eq!(None, node.attributes());
-        assert_eq!(None, node.owner_document());
-        assert!(!node.has_child());
-    }
+        assert_eq!(Some(2), value(a.get_user_data("k")));
+        assert_eq!(None, value(root.first_child().unwrap().get_user_data("j")));
+        assert_eq!(
+            Some(2),
+            value(root.first_child().unwrap().get_user_data("k"))
+        );
+        x.set_user_data("k", Some(Rc::new(3)), Some(handler.clone()));
 
-    #[test]
-    fn test_namespace_as_expanded_name() {
-        let (_, doc) = XmlDocument::from_raw("<root xmlns:a='http://test/a'></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let namespaces = root.in_scope_namespace().unwrap();
-        let ns = namespaces.first().unwrap();
+        let some = |v: &str| Some(v.to_string());
+        let copy = a.clone_node(true).unwrap();
+        assert_eq!(None, value(copy.get_user_data("k")));
+        assert_eq!(
+            vec![
+                (
+                    UserDataOperation::NodeCloned,
+                    "k".to_string(),
+                    3,
+                    (some("x"), some("x"))
+                ),
+                (
+                    UserDataOperation::NodeCloned,
+                    "k".to_string(),
+                    2,
+                    (some("a"), some("a"))
+                ),
+            ],
+            *log.borrow()
+        );
+        log.borrow_mut().clear();
 
-        // AsStringValue
+        doc.rename_node(&a, None, "b").unwrap();
+        doc.adopt_node(&a).unwrap();
+        let (_, other) = XmlDocument::from_raw("<r/>").unwrap();
+        other.adopt_node(&x).unwrap();
         assert_eq!(
-            Some(("a".to_string(), None, None)),
-            ns.as_expanded_name().unwrap()
+            vec![
+                (
+                    UserDataOperation::NodeRenamed,
+                    "k".to_string(),
+                    2,
+                    (some("b"), some("b"))
+                ),
+                (
+                    UserDataOperation::NodeAdopted,
+                    "k".to_string(),
+                    2,
+                    (some("b"), None)
+                ),
+                (
+                    UserDataOperation::NodeImported,
+                    "k".to_string(),
+                    3,
+                    (some("x"), some("x"))
+                ),
+            ],
+            *log.borrow()
         );
-    }
+        log.borrow_mut().clear();
 
-    #[test]
-    fn test_namespace_as_string_value() {
-        let (_, doc) = XmlDocument::from_raw("<root xmlns:a='http://test/a'></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let namespaces = root.in_scope_namespace().unwrap();
-        let ns = namespaces.first().unwrap();
+        assert_eq!(Some(2), value(a.set_user_data("k", None, None)));
+        assert_eq!(None, value(a.get_user_data("k")));
+        assert!(log.borrow().is_empty());
 
-        // AsStringValue
-        assert_eq!("http://test/a", ns.as_string_value().unwrap());
+        drop((root, a, copy, x, doc));
+        assert_eq!(
+            vec![(
+                UserDataOperation::NodeDeleted,
+                "k".to_string(),
+                3,
+                (None, None)
+            )],
+            *log.borrow()
+        );
     }
 
     #[test]
-    fn test_namespace_debug() {
-        let (_, doc) = XmlDocument::from_raw("<root xmlns:a='http://test/a'></root>").unwrap();
+    fn test_node_list_cache() {
+        let (_, doc) = XmlDocument::from_raw("<root><a /><b /></root>").unwrap();
         let root = doc.document_element().unwrap();
-        let namespaces = root.in_scope_namespace().unwrap();
-        let ns = namespaces.first().unwrap();
+        let nodes = root.child_nodes();
+        assert_eq!(2, nodes.length());
+
+        let c = doc.create_element("c").unwrap().as_node();
+        root.append_child(c.clone()).unwrap();
+        assert_eq!(3, nodes.length());
+        assert_eq!(Some(c.clone()), nodes.item(2));
+
+        let a = nodes.item(0).unwrap();
+        root.remove_child(&a).unwrap();
+        assert_eq!(2, nodes.length());
+        assert_eq!(Some(c), nodes.item(1));
+
+        let e = doc.create_element("e").unwrap();
+        let nodes = e.child_nodes();
+        assert_eq!(0, nodes.length());
+        e.append_child(doc.create_element("f").unwrap().as_node())
+            .unwrap();
+        assert_eq!(1, nodes.length());
+        let g = doc.create_text_node("g").as_node();
+        e.append_child(g.clone()).unwrap();
+        assert_eq!(Some(g.clone()), nodes.item(1));
+        e.remove_child(&g).unwrap();
+        assert_eq!(1, nodes.length());
 
-        // fmt::Debug
-        assert_eq!("XmlNamespace { http://test/a }", format!("{:?}", ns));
+        let root = a.as_element().unwrap();
+        let nodes = root.child_nodes();
+        assert_eq!(0, nodes.length());
+        drop(doc);
+        root.append_child(e.as_node()).unwrap();
+        assert_eq!(1, nodes.length());
     }
 
     #[test]
-    fn test_namespace_display() {
-        let (_, doc) = XmlDocument::from_raw("<root xmlns:a='http://test/a'></root>").unwrap();
+    fn test_node_list_text_expanded() {
+        let context = Context::from_text_expanded(true);
+        let (_, doc) = XmlDocument::from_raw_with_context(
+            "<root>a<![CDATA[b]]>c<a />&#x3042;d&amp;d<b /></root>",
+            context,
+        )
+        .unwrap();
         let root = doc.document_element().unwrap();
-        let namespaces = root.in_scope_namespace().unwrap();
-        let ns = namespaces.first().unwrap();
-
-        // fmt::Display
-        assert_eq!("xmlns:a=\"http://test/a\"", format!("{}", ns));
-    }
+        let nodes = root.child_nodes();
 
-    #[test]
-    fn test_namespace_impl() {
-        let (_, doc) = XmlDocument::from_raw("<root xmlns:a='http://test/a'></root>").unwrap();
-        let root = doc.document_element().unwrap();
-        let namespaces = root.in_scope_namespace().unwrap();
-        let ns = namespaces.first().unwrap();
+        // NodeList
+        assert_eq!(4, nodes.length());
+        assert_eq!(NodeType::Text, nodes.item(0).unwrap().node_type());
+        assert_eq!("a", nodes.item(1).unwrap().node_name());
+        assert_eq!(NodeType::Text, nodes.item(2).unwrap().node_type());
+        assert_eq!("b", nodes.item(3).unwrap().node_name());
+        assert_eq!(None, nodes.item(4));
 
-        // XmlNamespace
-        assert!(!ns.implicit());
+        // Node
+        let a = nodes.item(1).unwrap();
+        assert_eq!(nodes.item(0), a.previous_sibling());
+        assert_eq!(nodes.item(2), a.next_sibling());
+        assert_eq!(nodes.item(3), root.last_child());
     }
 
     #[test]
     fn test_resolved_text_character_data() {
-        let context = Context {
-            text_expanded: true,
-        };
+        let context = Context::from_text_expanded(true);
         let (_, doc) = XmlDocument::from_raw_with_context(
             "<root>a<![CDATA[b]]>c<a />&#x3042;d&amp;d</root>",
             context,
@@ -7425,9 +17664,7 @@ mod tests {
 
     #[test]
     fn test_resolved_text_node() {
-        let context = Context {
-            text_expanded: true,
-        };
+        let context = Context::from_text_expanded(true);
         let (_, doc) = XmlDocument::from_raw_with_context(
             "<root>a<![CDATA[b]]>c<a />&#x3042;d&amp;d</root>",
             context,
@@ -7480,9 +17717,7 @@ mod tests {
 
     #[test]
     fn test_resolved_text_as_node() {
-        let context = Context {
-            text_expanded: true,
-        };
+        let context = Context::from_text_expanded(true);
         let (_, doc) = XmlDocument::from_raw_with_context(
             "<root>a<![CDATA[b]]>c<a />&#x3042;d&amp;d</root>",
             context,
@@ -7537,9 +17772,7 @@ mod tests {
 
     #[test]
     fn test_resolved_text_as_string_value() {
-        let context = Context {
-            text_expanded: true,
-        };
+        let context = Context::from_text_expanded(true);
         let (_, doc) = XmlDocument::from_raw_with_context(
             "<root>a<![CDATA[b]]>c<a />&#x3042;d&amp;d</root>",
             context,
@@ -7569,9 +17802,7 @@ mod tests {
 
     #[test]
     fn test_resolved_text_display() {
-        let context = Context {
-            text_expanded: true,
-        };
+        let context = Context::from_text_expanded(true);
         let (_, doc) = XmlDocument::from_raw_with_context(
             "<root>a<![CDATA[b]]>c<a />&#x3042;d&amp;d</root>",
             context,
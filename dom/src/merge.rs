@@ -0,0 +1,115 @@
+use super::error;
+use super::{
+    Attr, CharacterData, Document, DocumentMut, Element, ElementMut, Node, NodeList, NodeMut,
+    ProcessingInstruction, XmlDocument, XmlElement, XmlNode,
+};
+
+pub fn documents(
+    namespace_uri: Option<&str>,
+    qualified_name: &str,
+    documents: &[XmlDocument],
+) -> error::Result<XmlDocument> {
+    let mut roots = vec![];
+    for document in documents {
+        roots.push(document.document_element()?);
+    }
+    elements(namespace_uri, qualified_name, &roots)
+}
+
+pub fn elements(
+    namespace_uri: Option<&str>,
+    qualified_name: &str,
+    elements: &[XmlElement],
+) -> error::Result<XmlDocument> {
+    let document = XmlDocument::new();
+    let wrapper = document.create_element(qualified_name)?;
+    if let Some(namespace_uri) = namespace_uri {
+        let name = match qualified_name.split_once(':') {
+            Some((prefix, _)) => format!("xmlns:{}", prefix),
+            None => "xmlns".to_string(),
+        };
+        wrapper.set_attribute(name.as_str(), namespace_uri)?;
+    }
+    document.append_child(XmlNode::Element(wrapper.clone()))?;
+
+    let default_namespace = namespace_uri.is_some() && !qualified_name.contains(':');
+    for element in elements {
+        let root = import_element(&document, element)?;
+
+        // Re-declare bindings inherited from the source ancestors so that each
+        // merged element keeps its namespaces independent of the wrapper.
+        let mut has_default = false;
+        for namespace in element.in_scope_namespace()? {
+            if namespace.implicit() {
+                continue;
+            }
+
+            let name = match namespace.node_name().as_str() {
+                "xmlns" => "xmlns".to_string(),
+                prefix => format!("xmlns:{}", prefix),
+            };
+            has_default |= name == "xmlns";
+            if root.get_attribute_node(name.as_str()).is_none() {
+                let uri = namespace.node_value()?.unwrap_or_default();
+                root.set_attribute(name.as_str(), uri.as_str())?;
+            }
+        }
+        if default_namespace && !has_default {
+            root.set_attribute("xmlns", "")?;
+        }
+
+        wrapper.append_child(XmlNode::Element(root))?;
+    }
+
+    Ok(document)
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn import_element(document: &XmlDocument, element: &XmlElement) -> error::Result<XmlElement> {
+    let name = match element.prefix() {
+        Some(prefix) => format!("{}:{}", prefix, element.tag_name()),
+        None => element.tag_name(),
+    };
+    let copy = document.create_element(name.as_str())?;
+    if let Some(attributes) = element.attributes() {
+        // Defaulted attributes are kept because the source DTD is dropped.
+        for attr in attributes.iter() {
+            copy.set_attribute(attr.name().as_str(), attr.value()?.as_str())?;
+        }
+    }
+
+    for child in element.child_nodes().iter() {
+        import_node(document, &copy, &child)?;
+    }
+
+    Ok(copy)
+}
+
+fn import_node(document: &XmlDocument, parent: &XmlElement, node: &XmlNode) -> error::Result<()> {
+    let copy = match node {
+        XmlNode::Element(v) => XmlNode::Element(import_element(document, v)?),
+        XmlNode::Text(v) => XmlNode::Text(document.create_text_node(v.data()?.as_str())),
+        XmlNode::ExpandedText(v) => XmlNode::Text(document.create_text_node(v.data()?.as_str())),
+        XmlNode::CData(v) => XmlNode::CData(document.create_cdata_section(v.data()?.as_str())),
+        XmlNode::EntityReference(v) => {
+            let children = v.child_nodes();
+            if children.length() == 0 {
+                XmlNode::Text(document.create_text_node(v.value()?.as_str()))
+            } else {
+                for child in children.iter() {
+                    import_node(document, parent, &child)?;
+                }
+                return Ok(());
+            }
+        }
+        XmlNode::PI(v) => XmlNode::PI(
+            document.create_processing_instruction(v.target().as_str(), v.data().as_str())?,
+        ),
+        XmlNode::Comment(v) => XmlNode::Comment(document.create_comment(v.data()?.as_str())?),
+        _ => return Ok(()),
+    };
+
+    parent.append_child(copy)?;
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use super::error;
+use super::info;
+use super::{Context, XmlDocument};
+use std::io;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Parser {
+    context: Context,
+    input: String,
+    buffer: String,
+    pool: info::NodePool,
+}
+
+impl Parser {
+    pub fn new(context: Context) -> Self {
+        Parser {
+            context,
+            input: String::new(),
+            buffer: String::new(),
+            pool: info::NodePool::default(),
+        }
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub fn set_context(&mut self, value: Context) {
+        self.context = value;
+    }
+
+    pub fn parse(&mut self, value: &str) -> error::Result<XmlDocument> {
+        let (rest, document) = XmlDocument::from_raw_with_buffer(
+            value,
+            self.context.clone(),
+            None,
+            &mut self.buffer,
+            &mut self.pool,
+        )?;
+        if rest.is_empty() {
+            Ok(document)
+        } else {
+            Err(error::Error::Parse(rest.to_string()))
+        }
+    }
+
+    pub fn parse_reader(&mut self, mut reader: impl io::Read) -> error::Result<XmlDocument> {
+        self.input.clear();
+        reader.read_to_string(&mut self.input)?;
+        let (rest, document) = XmlDocument::from_raw_with_buffer(
+            self.input.as_str(),
+            self.context.clone(),
+            None,
+            &mut self.buffer,
+            &mut self.pool,
+        )?;
+        if rest.is_empty() {
+            Ok(document)
+        } else {
+            Err(error::Error::Parse(rest.to_string()))
+        }
+    }
+
+    /// Hands a parsed document back so that the next parse reuses its node tables.
+    ///
+    /// Returns `false` when a node of the document is still referenced, in which case the
+    /// tables are left with it.
+    pub fn recycle(&mut self, document: XmlDocument) -> bool {
+        self.pool.recycle(document.document)
+    }
+
+    pub fn pooled(&self) -> usize {
+        self.pool.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.input.capacity() + self.buffer.capacity()
+    }
+
+    pub fn shrink_to(&mut self, capacity: usize) {
+        self.input.clear();
+        self.input.shrink_to(capacity);
+        self.buffer.clear();
+        self.buffer.shrink_to(capacity);
+        self.pool.clear();
+    }
+}
@@ -0,0 +1,134 @@
+use super::error;
+use super::Event;
+use quick_xml::events::{self as quick, BytesCData, BytesEnd, BytesStart, BytesText};
+use std::io;
+use std::str;
+
+// -----------------------------------------------------------------------------------------------
+
+impl<'a> TryFrom<&'a Event> for quick::Event<'a> {
+    type Error = error::Error;
+
+    fn try_from(value: &'a Event) -> Result<Self, Self::Error> {
+        let event = match value {
+            Event::StartDocument => Err(error::DomException::NotSupportErr)?,
+            Event::EndDocument => quick::Event::Eof,
+            Event::StartElement(name, attributes) => {
+                let mut start = BytesStart::new(name.as_str());
+                for (name, value) in attributes {
+                    start.push_attribute((name.as_str(), value.as_str()));
+                }
+                quick::Event::Start(start)
+            }
+            Event::EndElement(name) => quick::Event::End(BytesEnd::new(name.as_str())),
+            Event::Text(data) => quick::Event::Text(BytesText::new(data.as_str())),
+            Event::CData(data) => quick::Event::CData(BytesCData::new(data.as_str())),
+            Event::EntityReference(name) if name.starts_with("&#") => {
+                quick::Event::Text(BytesText::from_escaped(name.as_str()))
+            }
+            Event::EntityReference(name) => {
+                quick::Event::Text(BytesText::from_escaped(format!("&{};", name)))
+            }
+            Event::PI(target, data) if data.is_empty() => {
+                quick::Event::PI(BytesText::from_escaped(target.as_str()))
+            }
+            Event::PI(target, data) => {
+                quick::Event::PI(BytesText::from_escaped(format!("{} {}", target, data)))
+            }
+            Event::Comment(data) => quick::Event::Comment(BytesText::from_escaped(data.as_str())),
+        };
+        Ok(event)
+    }
+}
+
+/// `Empty` is not accepted; read with `expand_empty_elements(true)` or use [`QuickXmlEvents`].
+impl<'a> TryFrom<quick::Event<'a>> for Event {
+    type Error = error::Error;
+
+    fn try_from(value: quick::Event<'a>) -> Result<Self, Self::Error> {
+        let event = match value {
+            quick::Event::Start(v) => {
+                let mut attributes = vec![];
+                for attr in v.attributes() {
+                    let attr = attr.map_err(quick_xml::Error::from)?;
+                    let name = utf8(attr.key.as_ref())?;
+                    attributes.push((name, attr.unescape_value()?.to_string()));
+                }
+                Event::StartElement(utf8(v.name().as_ref())?, attributes)
+            }
+            quick::Event::End(v) => Event::EndElement(utf8(v.name().as_ref())?),
+            quick::Event::Text(v) => Event::Text(v.unescape()?.to_string()),
+            quick::Event::CData(v) => Event::CData(utf8(&v.into_inner())?),
+            quick::Event::Comment(v) => Event::Comment(utf8(&v)?),
+            quick::Event::PI(v) => {
+                let value = utf8(&v)?;
+                match value.split_once(char::is_whitespace) {
+                    Some((target, data)) => Event::PI(target.to_string(), data.to_string()),
+                    None => Event::PI(value, String::new()),
+                }
+            }
+            quick::Event::Eof => Event::EndDocument,
+            quick::Event::Empty(_) | quick::Event::Decl(_) | quick::Event::DocType(_) => {
+                Err(error::DomException::NotSupportErr)?
+            }
+        };
+        Ok(event)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+pub struct QuickXmlEvents<R: io::BufRead> {
+    reader: quick_xml::Reader<R>,
+    buffer: Vec<u8>,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: io::BufRead> Iterator for QuickXmlEvents<R> {
+    type Item = error::Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        if !self.started {
+            self.started = true;
+            return Some(Ok(Event::StartDocument));
+        }
+
+        loop {
+            self.buffer.clear();
+            let event = match self.reader.read_event_into(&mut self.buffer) {
+                Ok(quick::Event::Decl(_)) | Ok(quick::Event::DocType(_)) => continue,
+                Ok(v) => Event::try_from(v),
+                Err(e) => Err(error::Error::from(e)),
+            };
+
+            self.finished = !matches!(event, Ok(ref v) if v != &Event::EndDocument);
+            return Some(event);
+        }
+    }
+}
+
+impl<R: io::BufRead> QuickXmlEvents<R> {
+    pub fn new(reader: R) -> Self {
+        let mut reader = quick_xml::Reader::from_reader(reader);
+        reader.expand_empty_elements(true);
+        QuickXmlEvents {
+            reader,
+            buffer: vec![],
+            started: false,
+            finished: false,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn utf8(value: &[u8]) -> error::Result<String> {
+    str::from_utf8(value)
+        .map(|v| v.to_string())
+        .map_err(|e| error::Error::Parse(e.to_string()))
+}
@@ -0,0 +1,181 @@
+use super::error;
+use super::EntityResolver;
+use std::fs;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "net")]
+use std::time::Duration;
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileResolver {
+    roots: Vec<PathBuf>,
+}
+
+impl EntityResolver for FileResolver {
+    fn resolve(&self, _: Option<&str>, system_id: &str) -> error::Result<Option<String>> {
+        let path = match file_path(system_id) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let path = fs::canonicalize(path)?;
+        if !self.roots.iter().any(|v| path.starts_with(v)) {
+            return Err(error::Error::AccessDenied(system_id.to_string()));
+        }
+
+        Ok(Some(fs::read_to_string(path)?))
+    }
+}
+
+impl FileResolver {
+    pub fn new(roots: &[impl AsRef<Path>]) -> error::Result<Self> {
+        let mut resolver = FileResolver::default();
+        for root in roots {
+            resolver.add_root(root)?;
+        }
+        Ok(resolver)
+    }
+
+    /// A resolver rooted at the directory of a local document, `None` for other documents.
+    pub fn for_document(document_uri: &str) -> Option<Self> {
+        let path = file_path(document_uri)?;
+        FileResolver::new(&[path.parent()?]).ok()
+    }
+
+    pub fn add_root(&mut self, root: impl AsRef<Path>) -> error::Result<()> {
+        self.roots.push(fs::canonicalize(root)?);
+        Ok(())
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        self.roots.as_slice()
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(feature = "net")]
+pub trait HttpClient {
+    fn get(&self, url: &str, timeout: Duration, max_size: usize) -> error::Result<Vec<u8>>;
+}
+
+#[cfg(feature = "net")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpResolver<C: HttpClient> {
+    client: C,
+    hosts: Vec<String>,
+    timeout: Duration,
+    max_size: usize,
+}
+
+#[cfg(feature = "net")]
+impl<C: HttpClient> EntityResolver for HttpResolver<C> {
+    fn resolve(&self, _: Option<&str>, system_id: &str) -> error::Result<Option<String>> {
+        let host = match http_host(system_id) {
+            Some(v) => v.to_ascii_lowercase(),
+            None => return Ok(None),
+        };
+
+        if !self.hosts.iter().any(|v| v == &host) {
+            return Err(error::Error::AccessDenied(system_id.to_string()));
+        }
+
+        let body = self.client.get(system_id, self.timeout, self.max_size)?;
+        if body.len() > self.max_size {
+            return Err(error::Error::LengthLimitExceeded(self.max_size));
+        }
+
+        let body = String::from_utf8(body).map_err(|e| error::Error::Parse(e.to_string()))?;
+        Ok(Some(body))
+    }
+}
+
+#[cfg(feature = "net")]
+impl<C: HttpClient> HttpResolver<C> {
+    pub fn new(client: C, hosts: &[&str]) -> Self {
+        HttpResolver {
+            client,
+            hosts: hosts.iter().map(|v| v.to_ascii_lowercase()).collect(),
+            timeout: Duration::from_secs(30),
+            max_size: 1024 * 1024,
+        }
+    }
+
+    pub fn hosts(&self) -> &[String] {
+        self.hosts.as_slice()
+    }
+
+    pub fn set_timeout(&mut self, value: Duration) {
+        self.timeout = value;
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn set_max_size(&mut self, value: usize) {
+        self.max_size = value;
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn file_path(system_id: &str) -> Option<PathBuf> {
+    if let Some(path) = system_id.strip_prefix("file://") {
+        let path = path.strip_prefix("localhost").unwrap_or(path);
+        return Some(PathBuf::from(percent_decode(path)));
+    }
+
+    let scheme = system_id
+        .split_once(':')
+        .map(|(v, _)| v.len() > 1 && !v.contains(['/', '\\', '?', '#']))
+        .unwrap_or_default();
+    if scheme {
+        None
+    } else {
+        Some(PathBuf::from(system_id))
+    }
+}
+
+#[cfg(feature = "net")]
+fn http_host(url: &str) -> Option<&str> {
+    let rest = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"))?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    let host = if host.starts_with('[') {
+        &host[..host.find(']').map(|v| v + 1).unwrap_or(host.len())]
+    } else {
+        host.split(':').next().unwrap_or_default()
+    };
+    Some(host)
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = value
+            .get(i + 1..i + 3)
+            .filter(|v| v.chars().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|v| u8::from_str_radix(v, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(v)) => {
+                decoded.push(v);
+                i += 3;
+            }
+            (c, _) => {
+                decoded.push(c);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
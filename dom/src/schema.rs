@@ -0,0 +1,525 @@
+use super::error;
+use std::fmt;
+
+pub const XSD_NS: &str = "http://www.w3.org/2001/XMLSchema";
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemaType {
+    AnyUri,
+    Boolean,
+    Byte,
+    Date,
+    DateTime,
+    Decimal,
+    Double,
+    Float,
+    Int,
+    Integer,
+    Long,
+    NegativeInteger,
+    NonNegativeInteger,
+    NonPositiveInteger,
+    NormalizedString,
+    PositiveInteger,
+    Short,
+    String,
+    Time,
+    Token,
+    UnsignedByte,
+    UnsignedInt,
+    UnsignedLong,
+    UnsignedShort,
+}
+
+impl SchemaType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let value = match name {
+            "anyURI" => SchemaType::AnyUri,
+            "boolean" => SchemaType::Boolean,
+            "byte" => SchemaType::Byte,
+            "date" => SchemaType::Date,
+            "dateTime" => SchemaType::DateTime,
+            "decimal" => SchemaType::Decimal,
+            "double" => SchemaType::Double,
+            "float" => SchemaType::Float,
+            "int" => SchemaType::Int,
+            "integer" => SchemaType::Integer,
+            "long" => SchemaType::Long,
+            "negativeInteger" => SchemaType::NegativeInteger,
+            "nonNegativeInteger" => SchemaType::NonNegativeInteger,
+            "nonPositiveInteger" => SchemaType::NonPositiveInteger,
+            "normalizedString" => SchemaType::NormalizedString,
+            "positiveInteger" => SchemaType::PositiveInteger,
+            "short" => SchemaType::Short,
+            "string" => SchemaType::String,
+            "time" => SchemaType::Time,
+            "token" => SchemaType::Token,
+            "unsignedByte" => SchemaType::UnsignedByte,
+            "unsignedInt" => SchemaType::UnsignedInt,
+            "unsignedLong" => SchemaType::UnsignedLong,
+            "unsignedShort" => SchemaType::UnsignedShort,
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SchemaType::AnyUri => "anyURI",
+            SchemaType::Boolean => "boolean",
+            SchemaType::Byte => "byte",
+            SchemaType::Date => "date",
+            SchemaType::DateTime => "dateTime",
+            SchemaType::Decimal => "decimal",
+            SchemaType::Double => "double",
+            SchemaType::Float => "float",
+            SchemaType::Int => "int",
+            SchemaType::Integer => "integer",
+            SchemaType::Long => "long",
+            SchemaType::NegativeInteger => "negativeInteger",
+            SchemaType::NonNegativeInteger => "nonNegativeInteger",
+            SchemaType::NonPositiveInteger => "nonPositiveInteger",
+            SchemaType::NormalizedString => "normalizedString",
+            SchemaType::PositiveInteger => "positiveInteger",
+            SchemaType::Short => "short",
+            SchemaType::String => "string",
+            SchemaType::Time => "time",
+            SchemaType::Token => "token",
+            SchemaType::UnsignedByte => "unsignedByte",
+            SchemaType::UnsignedInt => "unsignedInt",
+            SchemaType::UnsignedLong => "unsignedLong",
+            SchemaType::UnsignedShort => "unsignedShort",
+        }
+    }
+
+    pub fn white_space(&self) -> WhiteSpace {
+        match self {
+            SchemaType::String => WhiteSpace::Preserve,
+            SchemaType::NormalizedString => WhiteSpace::Replace,
+            _ => WhiteSpace::Collapse,
+        }
+    }
+
+    pub fn parse(&self, value: &str) -> error::Result<TypedValue> {
+        let invalid = || error::Error::InvalidTypedValue(format!("{}: {}", self.name(), value));
+
+        let collapsed = self.white_space().apply(value);
+        let typed = match self {
+            SchemaType::String | SchemaType::NormalizedString => TypedValue::String(collapsed),
+            SchemaType::Token | SchemaType::AnyUri => TypedValue::String(collapsed),
+            SchemaType::Boolean => match collapsed.as_str() {
+                "true" | "1" => TypedValue::Boolean(true),
+                "false" | "0" => TypedValue::Boolean(false),
+                _ => return Err(invalid()),
+            },
+            SchemaType::Decimal => {
+                let digits = collapsed.trim_start_matches(['+', '-']);
+                if digits.is_empty()
+                    || digits == "."
+                    || !digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+                    || 1 < digits.matches('.').count()
+                {
+                    return Err(invalid());
+                }
+                TypedValue::Double(collapsed.parse::<f64>().map_err(|_| invalid())?)
+            }
+            SchemaType::Double | SchemaType::Float => {
+                TypedValue::Double(match collapsed.as_str() {
+                    "INF" | "+INF" => f64::INFINITY,
+                    "-INF" => f64::NEG_INFINITY,
+                    "NaN" => f64::NAN,
+                    v if v.contains(|c: char| c.is_ascii_alphabetic() && c != 'e' && c != 'E') => {
+                        return Err(invalid());
+                    }
+                    v => v.parse::<f64>().map_err(|_| invalid())?,
+                })
+            }
+            SchemaType::Date => TypedValue::Date(parse_date(&collapsed).ok_or_else(invalid)?),
+            SchemaType::DateTime => {
+                TypedValue::DateTime(parse_date_time(&collapsed).ok_or_else(invalid)?)
+            }
+            SchemaType::Time => TypedValue::Time(parse_time(&collapsed).ok_or_else(invalid)?),
+            _ => {
+                let (min, max) = self.range();
+                let v = collapsed.parse::<i128>().map_err(|_| invalid())?;
+                if v < min || max < v {
+                    return Err(invalid());
+                }
+                TypedValue::Integer(i64::try_from(v).map_err(|_| invalid())?)
+            }
+        };
+        Ok(typed)
+    }
+
+    fn range(&self) -> (i128, i128) {
+        match self {
+            SchemaType::Byte => (i8::MIN.into(), i8::MAX.into()),
+            SchemaType::Short => (i16::MIN.into(), i16::MAX.into()),
+            SchemaType::Int => (i32::MIN.into(), i32::MAX.into()),
+            SchemaType::NegativeInteger => (i64::MIN.into(), -1),
+            SchemaType::NonPositiveInteger => (i64::MIN.into(), 0),
+            SchemaType::NonNegativeInteger => (0, i64::MAX.into()),
+            SchemaType::PositiveInteger => (1, i64::MAX.into()),
+            SchemaType::UnsignedByte => (0, u8::MAX.into()),
+            SchemaType::UnsignedShort => (0, u16::MAX.into()),
+            SchemaType::UnsignedInt => (0, u32::MAX.into()),
+            _ => (i64::MIN.into(), i64::MAX.into()),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+/// [whiteSpace](https://www.w3.org/TR/xmlschema-2/#rf-whiteSpace) facet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WhiteSpace {
+    Preserve,
+    Replace,
+    Collapse,
+}
+
+impl WhiteSpace {
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            WhiteSpace::Preserve => value.to_string(),
+            WhiteSpace::Replace => value.replace(is_whitespace, " "),
+            WhiteSpace::Collapse => value
+                .split(is_whitespace)
+                .filter(|v| !v.is_empty())
+                .collect::<Vec<&str>>()
+                .join(" "),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypedValue {
+    Boolean(bool),
+    Date(Date),
+    DateTime(DateTime),
+    Double(f64),
+    Integer(i64),
+    String(String),
+    Time(Time),
+}
+
+// -----------------------------------------------------------------------------------------------
+
+/// [date](https://www.w3.org/TR/xmlschema-2/#date) value.
+///
+/// `timezone` is the offset in minutes east of UTC, if one was given.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub timezone: Option<i32>,
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.year < 0 {
+            write!(f, "-")?;
+        }
+        write!(
+            f,
+            "{:04}-{:02}-{:02}",
+            self.year.unsigned_abs(),
+            self.month,
+            self.day
+        )?;
+        fmt_zone(f, self.timezone)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<Date> for chrono::NaiveDate {
+    type Error = error::Error;
+
+    fn try_from(value: Date) -> Result<Self, Self::Error> {
+        i32::try_from(value.year)
+            .ok()
+            .and_then(|year| chrono::NaiveDate::from_ymd_opt(year, value.month, value.day))
+            .ok_or_else(|| error::Error::InvalidTypedValue(value.to_string()))
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+/// [time](https://www.w3.org/TR/xmlschema-2/#time) value.
+///
+/// `hour` is 24 only for `24:00:00`, the end of the day. `timezone` is the
+/// offset in minutes east of UTC, if one was given.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Time {
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub nanosecond: u32,
+    pub timezone: Option<i32>,
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.hour, self.minute, self.second)?;
+        if self.nanosecond != 0 {
+            let fraction = format!("{:09}", self.nanosecond);
+            write!(f, ".{}", fraction.trim_end_matches('0'))?;
+        }
+        fmt_zone(f, self.timezone)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<Time> for chrono::NaiveTime {
+    type Error = error::Error;
+
+    /// `24:00:00` becomes midnight.
+    fn try_from(value: Time) -> Result<Self, Self::Error> {
+        chrono::NaiveTime::from_hms_nano_opt(
+            value.hour % 24,
+            value.minute,
+            value.second,
+            value.nanosecond,
+        )
+        .ok_or_else(|| error::Error::InvalidTypedValue(value.to_string()))
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+/// [dateTime](https://www.w3.org/TR/xmlschema-2/#dateTime) value.
+///
+/// `timezone` is the offset in minutes east of UTC, if one was given.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub nanosecond: u32,
+    pub timezone: Option<i32>,
+}
+
+impl DateTime {
+    pub fn date(&self) -> Date {
+        Date {
+            year: self.year,
+            month: self.month,
+            day: self.day,
+            timezone: self.timezone,
+        }
+    }
+
+    pub fn time(&self) -> Time {
+        Time {
+            hour: self.hour,
+            minute: self.minute,
+            second: self.second,
+            nanosecond: self.nanosecond,
+            timezone: self.timezone,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let date = Date {
+            timezone: None,
+            ..self.date()
+        };
+        write!(f, "{}T{}", date, self.time())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<DateTime> for chrono::NaiveDateTime {
+    type Error = error::Error;
+
+    /// The timezone is ignored, and `24:00:00` becomes the start of the next day.
+    fn try_from(value: DateTime) -> Result<Self, Self::Error> {
+        let date = chrono::NaiveDate::try_from(value.date())?;
+        let time = chrono::NaiveTime::try_from(value.time())?;
+        let date = if value.hour == 24 {
+            date.succ_opt()
+                .ok_or_else(|| error::Error::InvalidTypedValue(value.to_string()))?
+        } else {
+            date
+        };
+        Ok(date.and_time(time))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<DateTime> for chrono::DateTime<chrono::FixedOffset> {
+    type Error = error::Error;
+
+    /// Fails when the value has no timezone.
+    fn try_from(value: DateTime) -> Result<Self, Self::Error> {
+        let offset = value
+            .timezone
+            .and_then(|v| chrono::FixedOffset::east_opt(v * 60))
+            .ok_or_else(|| error::Error::InvalidTypedValue(value.to_string()))?;
+        let local = chrono::NaiveDateTime::try_from(value)?;
+        local
+            .and_local_timezone(offset)
+            .single()
+            .ok_or_else(|| error::Error::InvalidTypedValue(value.to_string()))
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn is_whitespace(value: char) -> bool {
+    matches!(value, ' ' | '\t' | '\r' | '\n')
+}
+
+fn parse_date(value: &str) -> Option<Date> {
+    let (value, timezone) = split_zone(value)?;
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(v) => (true, v),
+        None => (false, value),
+    };
+    let mut fields = value.split('-');
+    let (year, month, day) = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+        (Some(year), Some(month), Some(day), None) => (year, month, day),
+        _ => return None,
+    };
+
+    if year.len() < 4 || !is_digits(year) || !is_digits(month) || !is_digits(day) {
+        return None;
+    }
+    if month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+
+    let year = year.parse::<i64>().ok()?;
+    let year = if negative { -year } else { year };
+    let month = month.parse::<u32>().ok()?;
+    let day = day.parse::<u32>().ok()?;
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => 0,
+    };
+    if !(1..=days).contains(&day) {
+        return None;
+    }
+
+    Some(Date {
+        year,
+        month,
+        day,
+        timezone,
+    })
+}
+
+fn parse_time(value: &str) -> Option<Time> {
+    let (value, timezone) = split_zone(value)?;
+    let mut fields = value.split(':');
+    let (hour, minute, second) = match (fields.next(), fields.next(), fields.next(), fields.next())
+    {
+        (Some(hour), Some(minute), Some(second), None) => (hour, minute, second),
+        _ => return None,
+    };
+
+    let (whole, fraction) = second.split_once('.').unwrap_or((second, "0"));
+    if [hour, minute, whole]
+        .iter()
+        .any(|v| v.len() != 2 || !is_digits(v))
+    {
+        return None;
+    }
+    if fraction.is_empty() || !is_digits(fraction) {
+        return None;
+    }
+
+    let hour = hour.parse::<u32>().ok()?;
+    let minute = minute.parse::<u32>().ok()?;
+    let second = whole.parse::<u32>().ok()?;
+    // Digits below a nanosecond are dropped.
+    let nanosecond = format!("{:0<9.9}", fraction).parse::<u32>().ok()?;
+    let midnight = hour == 24 && minute == 0 && second == 0 && nanosecond == 0;
+    let valid = hour < 24 && minute < 60 && second < 60;
+    if !valid && !midnight {
+        return None;
+    }
+
+    Some(Time {
+        hour,
+        minute,
+        second,
+        nanosecond,
+        timezone,
+    })
+}
+
+fn parse_date_time(value: &str) -> Option<DateTime> {
+    let (date, time) = value.split_once('T')?;
+    let date = parse_date(date).filter(|v| v.timezone.is_none())?;
+    let time = parse_time(time)?;
+    Some(DateTime {
+        year: date.year,
+        month: date.month,
+        day: date.day,
+        hour: time.hour,
+        minute: time.minute,
+        second: time.second,
+        nanosecond: time.nanosecond,
+        timezone: time.timezone,
+    })
+}
+
+/// Split the timezone from `value`, as minutes east of UTC.
+///
+/// Returns `None` when the timezone is out of range.
+fn split_zone(value: &str) -> Option<(&str, Option<i32>)> {
+    if let Some(v) = value.strip_suffix('Z') {
+        return Some((v, Some(0)));
+    }
+
+    let len = value.len();
+    if len > 6 && value.is_char_boundary(len - 6) {
+        let (head, zone) = value.split_at(len - 6);
+        let bytes = zone.as_bytes();
+        if matches!(bytes[0], b'+' | b'-')
+            && bytes[3] == b':'
+            && bytes[1..3].iter().all(u8::is_ascii_digit)
+            && bytes[4..].iter().all(u8::is_ascii_digit)
+        {
+            let hour = zone[1..3].parse::<i32>().ok()?;
+            let minute = zone[4..].parse::<i32>().ok()?;
+            if 14 < hour || 59 < minute || (hour == 14 && minute != 0) {
+                return None;
+            }
+            let offset = hour * 60 + minute;
+            return Some((head, Some(if bytes[0] == b'-' { -offset } else { offset })));
+        }
+    }
+
+    Some((value, None))
+}
+
+fn fmt_zone(f: &mut fmt::Formatter<'_>, timezone: Option<i32>) -> fmt::Result {
+    match timezone {
+        None => Ok(()),
+        Some(0) => write!(f, "Z"),
+        Some(v) => {
+            let sign = if v < 0 { '-' } else { '+' };
+            write!(f, "{}{:02}:{:02}", sign, v.abs() / 60, v.abs() % 60)
+        }
+    }
+}
+
+fn is_digits(value: &str) -> bool {
+    value.chars().all(|c| c.is_ascii_digit())
+}
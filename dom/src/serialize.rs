@@ -0,0 +1,152 @@
+use super::error;
+use super::{info, AsNode, DocumentMut, Node, NodeMut, XmlDocument, XmlElement};
+use std::fmt;
+
+pub trait XmlSerialize {
+    fn serialize(&self, writer: &XmlElementWriter) -> error::Result<()>;
+}
+
+macro_rules! impl_display {
+    ($($t:ty),*) => {
+        $(
+            impl XmlSerialize for $t {
+                fn serialize(&self, writer: &XmlElementWriter) -> error::Result<()> {
+                    writer.text(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_display!(
+    String, str, bool, char, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64
+);
+
+pub fn to_document<T: XmlSerialize + ?Sized>(
+    qualified_name: &str,
+    value: &T,
+) -> error::Result<XmlDocument> {
+    to_document_ns(None, qualified_name, value)
+}
+
+pub fn to_document_ns<T: XmlSerialize + ?Sized>(
+    namespace_uri: Option<&str>,
+    qualified_name: &str,
+    value: &T,
+) -> error::Result<XmlDocument> {
+    if namespace_uri.is_none() && qualified_name.contains(':') {
+        return Err(error::DomException::NamespaceErr.into());
+    }
+
+    let document = XmlDocument::new();
+    let element = document.create_element(qualified_name)?;
+    document.append_child(element.as_node())?;
+
+    if let Some(namespace_uri) = namespace_uri {
+        let name = match qualified_name.split_once(':') {
+            Some((prefix, _)) => format!("xmlns:{}", prefix),
+            None => "xmlns".to_string(),
+        };
+        let xmlns = info::NamespaceUri::xmlns();
+        element.set_attribute_ns(Some(xmlns.value()), &name, namespace_uri)?;
+    }
+
+    value.serialize(&XmlElementWriter::new(element))?;
+    Ok(document)
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug)]
+pub struct XmlElementWriter {
+    element: XmlElement,
+}
+
+impl XmlElementWriter {
+    pub fn new(element: XmlElement) -> Self {
+        XmlElementWriter { element }
+    }
+
+    pub fn element(&self) -> &XmlElement {
+        &self.element
+    }
+
+    pub fn attribute(&self, name: &str, value: impl fmt::Display) -> error::Result<()> {
+        self.element
+            .set_attribute_ns(None, name, value.to_string().as_str())
+    }
+
+    pub fn attribute_ns(
+        &self,
+        namespace_uri: &str,
+        qualified_name: &str,
+        value: impl fmt::Display,
+    ) -> error::Result<()> {
+        self.element.set_attribute_ns(
+            Some(namespace_uri),
+            qualified_name,
+            value.to_string().as_str(),
+        )
+    }
+
+    pub fn undeclare_prefix(&self, prefix: &str) -> error::Result<()> {
+        self.element.undeclare_namespace_prefix(prefix)
+    }
+
+    pub fn optional_attribute<T: fmt::Display>(
+        &self,
+        name: &str,
+        value: Option<&T>,
+    ) -> error::Result<()> {
+        match value {
+            Some(v) => self.attribute(name, v),
+            None => Ok(()),
+        }
+    }
+
+    pub fn text(&self, value: impl fmt::Display) -> error::Result<()> {
+        let document = self.element.owner_document().unwrap();
+        let text = document.create_text_node(value.to_string().as_str());
+        self.element.append_child(text.as_node())?;
+        Ok(())
+    }
+
+    pub fn child<T: XmlSerialize + ?Sized>(&self, name: &str, value: &T) -> error::Result<()> {
+        let element = self.element.append_element_ns(None, name)?;
+        value.serialize(&XmlElementWriter::new(element))
+    }
+
+    pub fn child_ns<T: XmlSerialize + ?Sized>(
+        &self,
+        namespace_uri: &str,
+        qualified_name: &str,
+        value: &T,
+    ) -> error::Result<()> {
+        let element = self
+            .element
+            .append_element_ns(Some(namespace_uri), qualified_name)?;
+        value.serialize(&XmlElementWriter::new(element))
+    }
+
+    pub fn optional_child<T: XmlSerialize>(
+        &self,
+        name: &str,
+        value: Option<&T>,
+    ) -> error::Result<()> {
+        match value {
+            Some(v) => self.child(name, v),
+            None => Ok(()),
+        }
+    }
+
+    pub fn children<'a, T: XmlSerialize + 'a>(
+        &self,
+        name: &str,
+        values: impl IntoIterator<Item = &'a T>,
+    ) -> error::Result<()> {
+        for value in values {
+            self.child(name, value)?;
+        }
+        Ok(())
+    }
+}
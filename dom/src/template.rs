@@ -0,0 +1,164 @@
+use super::error;
+use super::{AsNode, AsStringValue, Event, XmlDocument, XmlNode};
+use std::collections::{BTreeSet, HashMap};
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug)]
+pub enum Binding {
+    Text(String),
+    Node(XmlNode),
+}
+
+impl From<&str> for Binding {
+    fn from(value: &str) -> Self {
+        Binding::Text(value.to_string())
+    }
+}
+
+impl From<String> for Binding {
+    fn from(value: String) -> Self {
+        Binding::Text(value)
+    }
+}
+
+impl From<XmlNode> for Binding {
+    fn from(value: XmlNode) -> Self {
+        Binding::Node(value)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    events: Vec<Event>,
+}
+
+impl Template {
+    pub fn new(value: &str) -> error::Result<Self> {
+        let (rest, document) = XmlDocument::from_raw(value)?;
+        if !rest.is_empty() {
+            return Err(error::Error::Parse(rest.to_string()));
+        }
+        Ok(Template::from_document(&document))
+    }
+
+    pub fn from_document(document: &XmlDocument) -> Self {
+        let events = document.as_node().events().collect();
+        Template { events }
+    }
+
+    pub fn placeholders(&self) -> Vec<String> {
+        let mut names = BTreeSet::new();
+        for event in &self.events {
+            match event {
+                Event::StartElement(_, attributes) => {
+                    for (_, value) in attributes {
+                        names.extend(placeholders(value));
+                    }
+                }
+                Event::Text(data) => names.extend(placeholders(data)),
+                _ => {}
+            }
+        }
+        names.into_iter().map(|v| v.to_string()).collect()
+    }
+
+    pub fn instantiate(&self, bindings: &HashMap<String, Binding>) -> error::Result<XmlDocument> {
+        let mut events = vec![];
+        for event in &self.events {
+            match event {
+                Event::StartElement(name, attributes) => {
+                    let mut values = vec![];
+                    for (name, value) in attributes {
+                        values.push((name.clone(), interpolate(value, bindings)?));
+                    }
+                    events.push(Event::StartElement(name.clone(), values));
+                }
+                Event::Text(data) => {
+                    for segment in segments(data) {
+                        match segment {
+                            Segment::Literal(v) => events.push(Event::Text(v.to_string())),
+                            Segment::Placeholder(name) => match lookup(bindings, name)? {
+                                Binding::Text(v) => events.push(Event::Text(v.clone())),
+                                Binding::Node(v) => events.extend(v.events().filter(|e| {
+                                    !matches!(e, Event::StartDocument | Event::EndDocument)
+                                })),
+                            },
+                        }
+                    }
+                }
+                _ => events.push(event.clone()),
+            }
+        }
+        XmlDocument::from_events(events.into_iter())
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+enum Segment<'a> {
+    Literal(&'a str),
+    Placeholder(&'a str),
+}
+
+fn interpolate(value: &str, bindings: &HashMap<String, Binding>) -> error::Result<String> {
+    let mut interpolated = String::new();
+    for segment in segments(value) {
+        match segment {
+            Segment::Literal(v) => interpolated.push_str(v),
+            Segment::Placeholder(name) => match lookup(bindings, name)? {
+                Binding::Text(v) => interpolated.push_str(v),
+                Binding::Node(v) => interpolated.push_str(&v.as_string_value()?),
+            },
+        }
+    }
+    Ok(interpolated)
+}
+
+fn lookup<'a>(bindings: &'a HashMap<String, Binding>, name: &str) -> error::Result<&'a Binding> {
+    bindings
+        .get(name)
+        .ok_or_else(|| error::Error::UnboundPlaceholder(name.to_string()))
+}
+
+fn placeholders(value: &str) -> impl Iterator<Item = &str> {
+    segments(value).into_iter().filter_map(|v| match v {
+        Segment::Placeholder(name) => Some(name),
+        Segment::Literal(_) => None,
+    })
+}
+
+fn segments(value: &str) -> Vec<Segment<'_>> {
+    let mut segments = vec![];
+    let mut rest = value;
+    while let Some(start) = rest.find(OPEN) {
+        let tail = &rest[start + OPEN.len()..];
+        let end = match tail.find(CLOSE) {
+            Some(v) => v,
+            None => break,
+        };
+
+        let name = tail[..end].trim();
+        if name.is_empty() || name.contains(OPEN) {
+            segments.push(Segment::Literal(&rest[..start + OPEN.len()]));
+            rest = tail;
+            continue;
+        }
+
+        if start > 0 {
+            segments.push(Segment::Literal(&rest[..start]));
+        }
+        segments.push(Segment::Placeholder(name));
+        rest = &tail[end + CLOSE.len()..];
+    }
+
+    if !rest.is_empty() {
+        segments.push(Segment::Literal(rest));
+    }
+    segments
+}
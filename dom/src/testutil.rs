@@ -0,0 +1,149 @@
+use super::canonical;
+use super::error;
+use super::{Context, XmlDocument};
+use std::env;
+use std::path;
+
+#[macro_export]
+macro_rules! assert_xml_eq {
+    ($expected:expr, $actual:expr $(,)?) => {
+        $crate::assert_xml_eq!($expected, $actual, $crate::testutil::Options::default())
+    };
+    ($expected:expr, $actual:expr, $options:expr $(,)?) => {
+        if let Err(message) = $crate::testutil::compare(&$expected, &$actual, $options) {
+            panic!("{}", message);
+        }
+    };
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Options {
+    pub with_comments: bool,
+    pub whitespace_stripped: bool,
+}
+
+impl Options {
+    pub fn with_comments(mut self, value: bool) -> Self {
+        self.with_comments = value;
+        self
+    }
+
+    pub fn whitespace_stripped(mut self, value: bool) -> Self {
+        self.whitespace_stripped = value;
+        self
+    }
+}
+
+pub fn compare(
+    expected: impl AsRef<str>,
+    actual: impl AsRef<str>,
+    options: Options,
+) -> Result<(), String> {
+    let expected = canonical_string(expected.as_ref(), options)
+        .map_err(|e| format!("expected document is not well-formed: {}", e))?;
+    let actual = canonical_string(actual.as_ref(), options)
+        .map_err(|e| format!("actual document is not well-formed: {}", e))?;
+    if expected == actual {
+        return Ok(());
+    }
+
+    let mut message = "assertion `expected == actual` failed: XML documents differ\n".to_string();
+    message.push_str("--- expected\n+++ actual\n");
+    message.push_str(diff(expected.as_str(), actual.as_str()).as_str());
+    Err(message)
+}
+
+pub fn diff(expected: &str, actual: &str) -> String {
+    let expected = split_markup(expected);
+    let actual = split_markup(actual);
+
+    // Longest common subsequence table of the remaining suffixes.
+    let mut lengths = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lengths[i][j] = if expected[i] == actual[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut value = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            value.push_str(format!("  {}\n", expected[i]).as_str());
+            i += 1;
+            j += 1;
+        } else if i < expected.len()
+            && (j == actual.len() || lengths[i + 1][j] >= lengths[i][j + 1])
+        {
+            value.push_str(format!("- {}\n", expected[i]).as_str());
+            i += 1;
+        } else {
+            value.push_str(format!("+ {}\n", actual[j]).as_str());
+            j += 1;
+        }
+    }
+    value
+}
+
+pub fn fixture_path(name: impl AsRef<path::Path>) -> path::PathBuf {
+    let name = name.as_ref();
+    if name.is_absolute() {
+        return name.to_path_buf();
+    }
+
+    let root = env::var_os("CARGO_MANIFEST_DIR")
+        .map(path::PathBuf::from)
+        .unwrap_or_default();
+    root.join("tests").join("fixtures").join(name)
+}
+
+pub fn load_fixture(name: impl AsRef<path::Path>) -> XmlDocument {
+    load_fixture_with_context(name, Context::default())
+}
+
+pub fn load_fixture_with_context(name: impl AsRef<path::Path>, context: Context) -> XmlDocument {
+    let path = fixture_path(name);
+    match XmlDocument::from_file(&path, context) {
+        Ok(document) => document,
+        Err(e) => panic!("failed to load fixture {}: {}", path.display(), e),
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn canonical_string(value: &str, options: Options) -> error::Result<String> {
+    let context = Context::builder()
+        .whitespace_stripped(options.whitespace_stripped)
+        .build();
+    let (rest, document) = XmlDocument::from_raw_with_context(value, context)?;
+    if !rest.is_empty() {
+        return Err(error::Error::Parse(rest.to_string()));
+    }
+    canonical::canonicalize(&document, options.with_comments)
+}
+
+fn split_markup(value: &str) -> Vec<&str> {
+    let mut lines = vec![];
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '<' if start < i => {
+                lines.push(&value[start..i]);
+                start = i;
+            }
+            '>' => {
+                lines.push(&value[start..=i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < value.len() {
+        lines.push(&value[start..]);
+    }
+    lines
+}
@@ -0,0 +1,84 @@
+pub fn resolve(base: &str, reference: &str) -> String {
+    let has_scheme = reference
+        .split_once(':')
+        .map(|(v, _)| !v.is_empty() && !v.contains(['/', '?', '#']))
+        .unwrap_or_default();
+    if base.is_empty() || has_scheme {
+        return reference.to_string();
+    }
+
+    let (base, _) = base.split_once('#').unwrap_or((base, ""));
+    if reference.is_empty() || reference.starts_with('#') {
+        return format!("{}{}", base, reference);
+    }
+
+    let (scheme, rest) = match base.split_once("://") {
+        Some((scheme, rest)) => (format!("{}://", scheme), rest),
+        None => (String::new(), base),
+    };
+    if let Some(reference) = reference.strip_prefix("//") {
+        return format!("{}{}", scheme, reference);
+    }
+
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (path, _) = path.split_once('?').unwrap_or((path, ""));
+    if reference.starts_with('?') {
+        return format!("{}{}{}{}", scheme, authority, path, reference);
+    }
+
+    let (reference, suffix) = match reference.find(['?', '#']) {
+        Some(i) => reference.split_at(i),
+        None => (reference, ""),
+    };
+    let path = if reference.starts_with('/') {
+        reference.to_string()
+    } else {
+        let directory = &path[..path.rfind('/').map(|v| v + 1).unwrap_or_default()];
+        format!("{}{}", directory, reference)
+    };
+
+    let mut segments: Vec<&str> = vec![];
+    for segment in path.split('/').skip(1) {
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    if path.ends_with("/.") || path.ends_with("/..") {
+        segments.push("");
+    }
+
+    format!("{}{}/{}{}", scheme, authority, segments.join("/"), suffix)
+}
+
+pub fn iri_to_uri(value: &str) -> String {
+    let mut uri = String::new();
+    for c in value.chars() {
+        if c.is_ascii() && !is_excluded(c) {
+            uri.push(c);
+            continue;
+        }
+
+        let mut buf = [0; 4];
+        for b in c.encode_utf8(&mut buf).bytes() {
+            uri.push_str(format!("%{:02X}", b).as_str());
+        }
+    }
+    uri
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn is_excluded(value: char) -> bool {
+    value.is_ascii_control()
+        || matches!(
+            value,
+            ' ' | '<' | '>' | '"' | '{' | '}' | '|' | '\\' | '^' | '`'
+        )
+}
@@ -0,0 +1,224 @@
+use super::content_model::Automaton;
+use super::error;
+use super::{declared_name, info};
+use super::{
+    AsNode, Attr, AttributeDefault, Document, DocumentType, Element, HasChild, Node, NodeHandle,
+    XmlDocument, XmlDocumentType, XmlElement, XmlNode,
+};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    path: String,
+    node: NodeHandle,
+    span: Option<info::SourceSpan>,
+    message: String,
+}
+
+impl Diagnostic {
+    pub fn new(node: &XmlNode, message: impl fmt::Display) -> Self {
+        Diagnostic {
+            path: node.location_path(),
+            node: node.handle(),
+            span: node.source_span(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    pub fn node(&self) -> NodeHandle {
+        self.node
+    }
+
+    pub fn span(&self) -> Option<&info::SourceSpan> {
+        self.span.as_ref()
+    }
+
+    pub fn message(&self) -> &str {
+        self.message.as_str()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.path)?;
+        if let Some(span) = self.span.as_ref() {
+            write!(f, " ({}..{})", span.start(), span.end())?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+pub fn validate_dtd(document: &XmlDocument) -> error::Result<Vec<Diagnostic>> {
+    let mut diagnostics = vec![];
+
+    let doc_type = match document.doc_type() {
+        Some(v) => v,
+        None => return Ok(diagnostics),
+    };
+
+    let root = match document.document_element() {
+        Ok(v) => v,
+        Err(_) => return Ok(diagnostics),
+    };
+    if root.node_name() != doc_type.name() {
+        let message = format!("root element must be `{}`", doc_type.name());
+        diagnostics.push(Diagnostic::new(&root.as_node(), message));
+    }
+
+    let mut stack = vec![root];
+    while let Some(element) = stack.pop() {
+        validate_content(&doc_type, &element, &mut diagnostics)?;
+        validate_attributes(&doc_type, &element, &mut diagnostics)?;
+
+        let children = element.child_iter().filter_map(|v| v.as_element());
+        let mut children = children.collect::<Vec<XmlElement>>();
+        children.reverse();
+        stack.extend(children);
+    }
+
+    Ok(diagnostics)
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn validate_content(
+    doc_type: &XmlDocumentType,
+    element: &XmlElement,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> error::Result<()> {
+    let node = element.as_node();
+    let name = element.node_name();
+    let declaration = doc_type.declaration.borrow();
+    let content = match declaration
+        .elements()
+        .iter()
+        .find(|&v| declared_name(v) == name)
+    {
+        Some(v) => v.content().clone(),
+        None => {
+            let message = format!("element `{}` is not declared", name);
+            diagnostics.push(Diagnostic::new(&node, message));
+            return Ok(());
+        }
+    };
+
+    match content {
+        info::XmlDeclarationContent::Empty => {
+            if node.child_iter().next().is_some() {
+                let message = format!("element `{}` must be empty", name);
+                diagnostics.push(Diagnostic::new(&node, message));
+            }
+        }
+        info::XmlDeclarationContent::Any => {}
+        info::XmlDeclarationContent::Mixed(names) => {
+            for child in node.child_iter() {
+                if let Some(child) = child.as_element() {
+                    let child_name = child.node_name();
+                    if !names.contains(&child_name) {
+                        let message = format!("element `{}` is not allowed here", child_name);
+                        diagnostics.push(Diagnostic::new(&child.as_node(), message));
+                    }
+                }
+            }
+        }
+        info::XmlDeclarationContent::Children(model) => {
+            let automaton = Automaton::new(&model);
+            let mut states = automaton.start();
+            for child in node.child_iter() {
+                match child {
+                    XmlNode::Element(child) => {
+                        let child_name = child.node_name();
+                        let next = automaton.step(&states, child_name.as_str());
+                        if next.is_empty() {
+                            let message = format!("element `{}` is not allowed here", child_name);
+                            diagnostics.push(Diagnostic::new(&child.as_node(), message));
+                            return Ok(());
+                        }
+                        states = next;
+                    }
+                    XmlNode::CData(_) | XmlNode::ExpandedText(_) | XmlNode::Text(_) => {
+                        let text = child.node_value()?.unwrap_or_default();
+                        if !text.chars().all(|c| matches!(c, ' ' | '\t' | '\r' | '\n')) {
+                            let message = "character data is not allowed here";
+                            diagnostics.push(Diagnostic::new(&child, message));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if !automaton.accepts(&states) {
+                let message = format!("content of element `{}` is incomplete", name);
+                diagnostics.push(Diagnostic::new(&node, message));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_attributes(
+    doc_type: &XmlDocumentType,
+    element: &XmlElement,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> error::Result<()> {
+    let name = element.node_name();
+    let declarations = doc_type.allowed_attributes(name.as_str())?;
+
+    for declaration in declarations.iter() {
+        if *declaration.default() == AttributeDefault::Required
+            && !element
+                .get_attribute_node(declaration.name())
+                .is_some_and(|v| v.specified())
+        {
+            let message = format!("missing required attribute `{}`", declaration.name());
+            diagnostics.push(Diagnostic::new(&element.as_node(), message));
+        }
+    }
+
+    let attributes = match element.attributes() {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    for attribute in attributes.iter() {
+        let attribute_name = attribute.name();
+        if !attribute.specified()
+            || attribute_name == "xmlns"
+            || attribute_name.starts_with("xmlns:")
+        {
+            continue;
+        }
+
+        let node = attribute.as_node();
+        let declaration = match declarations.iter().find(|v| v.name() == attribute_name) {
+            Some(v) => v,
+            None => {
+                let message = format!("attribute `{}` is not declared", attribute_name);
+                diagnostics.push(Diagnostic::new(&node, message));
+                continue;
+            }
+        };
+
+        let value = attribute.value()?;
+        if let Some(values) = declaration.enumerated_values() {
+            if !values.contains(&value) {
+                let message = format!("value `{}` is not one of {:?}", value, values);
+                diagnostics.push(Diagnostic::new(&node, message));
+            }
+        }
+        if let AttributeDefault::Fixed(fixed) = declaration.default() {
+            if *fixed != value {
+                let message = format!("value must be `{}`", fixed);
+                diagnostics.push(Diagnostic::new(&node, message));
+            }
+        }
+    }
+
+    Ok(())
+}
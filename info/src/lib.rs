@@ -1,13 +1,17 @@
 pub mod error;
 
+use std::any::Any;
 use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::convert;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::io;
 use std::iter::Iterator;
 use std::ops::{Deref, Range};
 use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use xml_parser::model as parser;
 
 // TODO: Reduce memory consumption.
@@ -22,6 +26,10 @@ pub type XmlNode<T> = Rc<RefCell<T>>;
 
 pub type Singleton<T> = Rc<RefCell<T>>;
 
+type UserDataEntries = Vec<(String, Rc<dyn Any>)>;
+
+static DOCUMENT_ID: AtomicUsize = AtomicUsize::new(0);
+
 // -----------------------------------------------------------------------------------------------
 
 pub trait IndentedDisplay: fmt::Display {
@@ -76,6 +84,13 @@ pub trait HasChildren: HasContext {
 
 // -----------------------------------------------------------------------------------------------
 
+/// Supplies the children of an element when they are first accessed.
+pub trait ChildSource {
+    fn load(&self, parent: &XmlElement) -> Vec<Rc<XmlItem>>;
+}
+
+// -----------------------------------------------------------------------------------------------
+
 pub trait HasContext {
     fn context(&self) -> &Context;
 
@@ -91,6 +106,10 @@ pub trait HasContext {
         }
     }
 
+    fn document_id(&self) -> usize {
+        self.context().document_id()
+    }
+
     fn id(&self) -> usize {
         self.context().info.borrow().id
     }
@@ -119,8 +138,8 @@ pub trait HasContext {
         }
     }
 
-    fn owner(&self) -> XmlNode<XmlDocument> {
-        self.context().document().clone()
+    fn owner(&self) -> error::Result<XmlNode<XmlDocument>> {
+        self.context().document()
     }
 
     fn set_order_after(&self, id: usize) -> Option<usize> {
@@ -277,7 +296,7 @@ pub trait DocumentTypeDeclaration: HasContext {
 
     fn children(&self) -> OrderedList<XmlNode<XmlProcessingInstruction>>;
 
-    fn parent(&self) -> XmlNode<XmlDocument> {
+    fn parent(&self) -> error::Result<XmlNode<XmlDocument>> {
         self.context().document()
     }
 }
@@ -391,6 +410,7 @@ pub trait UnparsedEntity {
 pub struct XmlAttribute {
     local_name: String,
     prefix: Option<String>,
+    namespace_uri: Option<String>,
     values: Singleton<Vec<XmlAttributeValue>>,
     from_dtd: bool,
     parent_id: Option<usize>,
@@ -421,6 +441,7 @@ impl HasChildren for XmlAttribute {
         if let Some(index) = self.child_index(id) {
             let value = self.values.borrow_mut().remove(index);
             value.set_parent_id(None);
+            self.context().invalidate();
             match value {
                 XmlAttributeValue::Char(v) => Some(v.clone()),
                 XmlAttributeValue::Entity(v) => Some(v.clone()),
@@ -453,6 +474,7 @@ impl HasChildren for XmlAttribute {
         } else {
             self.values.borrow_mut().push(v);
         }
+        self.context().invalidate();
         Ok(value)
     }
 }
@@ -497,16 +519,22 @@ impl HasQName for XmlAttribute {
 
 impl Attribute for XmlAttribute {
     fn namespace_name(&self) -> error::Result<Option<NamespaceUri>> {
+        if !self.context().namespace_aware() {
+            return Ok(None);
+        }
+
         if self.namespace() {
             return Ok(Some(NamespaceUri::xmlns()));
         }
 
         if let Some(prefix) = self.prefix.as_deref() {
-            self.element()
-                .as_ref()
-                .ok_or(error::Error::IsolatedNode)?
-                .borrow()
-                .find_nameapce_uri(prefix)
+            match self.element() {
+                Some(element) => element.borrow().find_nameapce_uri(prefix),
+                None => match self.namespace_uri.as_deref() {
+                    Some(v) => Ok(Some(NamespaceUri::from(v))),
+                    None => Err(error::Error::IsolatedNode),
+                },
+            }
         } else {
             Ok(None)
         }
@@ -569,7 +597,7 @@ impl Attribute for XmlAttribute {
                 | XmlDeclarationAttType::Enumeration(_) => Ok(Value::V(None)),
                 XmlDeclarationAttType::IdRef => {
                     let value = self.normalized_value()?;
-                    let root = self.context.document().borrow().document_element()?;
+                    let root = self.context.document()?.borrow().document_element()?;
                     let names = vec![value.as_str()];
                     let e = retrieve_element_by_id(&root, names.as_slice())?;
                     if e.is_empty() {
@@ -581,7 +609,7 @@ impl Attribute for XmlAttribute {
                 }
                 XmlDeclarationAttType::IdRefs => {
                     let value = self.normalized_value()?;
-                    let root = self.context.document().borrow().document_element()?;
+                    let root = self.context.document()?.borrow().document_element()?;
                     let names = value.split_whitespace().collect::<Vec<&str>>();
                     let e = retrieve_element_by_id(&root, names.as_slice())?;
                     if e.is_empty() {
@@ -595,7 +623,7 @@ impl Attribute for XmlAttribute {
                     let value = self.normalized_value()?;
                     let entity = self
                         .context
-                        .document()
+                        .document()?
                         .borrow()
                         .unparsed_entities()
                         .iter()
@@ -608,7 +636,7 @@ impl Attribute for XmlAttribute {
                 }
                 XmlDeclarationAttType::Entities => {
                     let value = self.normalized_value()?;
-                    let unparsed = self.context.document().borrow().unparsed_entities();
+                    let unparsed = self.context.document()?.borrow().unparsed_entities();
                     let mut entities = vec![];
                     for value in value.split_whitespace() {
                         let entity = unparsed.iter().find(|v| v.borrow().name() == value);
@@ -624,7 +652,7 @@ impl Attribute for XmlAttribute {
                 }
                 XmlDeclarationAttType::Notation(_) => {
                     let value = self.normalized_value()?;
-                    if let Some(notations) = self.context.document().borrow().notations() {
+                    if let Some(notations) = self.context.document()?.borrow().notations() {
                         if let Some(e) = notations.iter().find(|v| v.borrow().name() == value) {
                             Ok(Value::V(Some(OrderedList::new(vec![Rc::new(e.into())]))))
                         } else {
@@ -677,6 +705,7 @@ impl XmlAttribute {
         let attribute = node(XmlAttribute {
             local_name,
             prefix,
+            namespace_uri: None,
             values: singleton(vec![]),
             from_dtd: false,
             parent_id,
@@ -699,6 +728,7 @@ impl XmlAttribute {
         let attribute = node(XmlAttribute {
             local_name: value.local_name().to_string(),
             prefix: value.prefix().map(|v| v.to_string()),
+            namespace_uri: None,
             values: singleton(vec![]),
             from_dtd: true,
             parent_id: None,
@@ -729,6 +759,7 @@ impl XmlAttribute {
     }
 
     pub fn set_values(&self, value: &str) -> error::Result<()> {
+        self.context().touch();
         // TODO: `from_dtd`` update false to true.
         let xml = format!("{}={}", self.local_name(), escape(value));
         let (rest, tree) = xml_parser::attribute(xml.as_str())?;
@@ -756,6 +787,26 @@ impl XmlAttribute {
         self.values.clone()
     }
 
+    pub fn set_local_name(&mut self, local_name: &str) {
+        self.context().touch();
+        self.local_name = local_name.to_string();
+    }
+
+    pub fn set_prefix(&mut self, prefix: Option<&str>) {
+        self.context().touch();
+        self.prefix = prefix.map(|v| v.to_string());
+    }
+
+    /// Namespace of a detached attribute, used until it has an owner element.
+    pub fn declared_namespace_uri(&self) -> Option<&str> {
+        self.namespace_uri.as_deref()
+    }
+
+    pub fn set_declared_namespace_uri(&mut self, value: Option<&str>) {
+        self.context().touch();
+        self.namespace_uri = value.map(|v| v.to_string());
+    }
+
     fn declaration_def(&self) -> Option<XmlDeclarationAttDef> {
         self.element()
             .as_ref()?
@@ -942,7 +993,7 @@ impl PartialEq<XmlCData> for XmlCData {
 
 impl fmt::Display for XmlCData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "<![CDATA[{}]]>", self.data.as_str())
+        write!(f, "<![CDATA[{}]]>", escape_cdata(self.data.as_str()))
     }
 }
 
@@ -966,14 +1017,21 @@ impl XmlCData {
     }
 
     pub fn delete(&mut self, offset: usize, count: usize) {
+        self.context().touch();
         self.data = delete_char_range(self.data.as_str(), offset, count);
     }
 
     pub fn insert(&mut self, offset: usize, data: &str) -> error::Result<()> {
+        self.context().touch();
         fn check(value: &str) -> error::Result<bool> {
-            let new = format!("<![CDATA[{}]]>", value);
-            let (rest, _) = xml_parser::cdsect(new.as_str())?;
-            Ok(rest.is_empty())
+            for part in value.split("]]>") {
+                let new = format!("<![CDATA[{}]]>", part);
+                let (rest, _) = xml_parser::cdsect(new.as_str())?;
+                if !rest.is_empty() {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
         }
 
         self.data = insert_char_at(self.data.as_str(), offset, data, check)?;
@@ -989,6 +1047,7 @@ impl XmlCData {
     }
 
     pub fn split_at(&mut self, offset: usize) -> XmlNode<Self> {
+        self.context().touch();
         let mut chars = self.data.chars().collect::<Vec<char>>();
         let at = if offset < chars.len() {
             offset
@@ -1195,18 +1254,25 @@ impl XmlComment {
     }
 
     pub fn delete(&mut self, offset: usize, count: usize) {
+        self.context().touch();
         self.comment = delete_char_range(self.comment.as_str(), offset, count);
     }
 
     pub fn insert(&mut self, offset: usize, comment: &str) -> error::Result<()> {
+        self.context().touch();
         fn check(value: &str) -> error::Result<bool> {
             let new = format!("<!--{}-->", value);
             let (rest, _) = xml_parser::comment(new.as_str())?;
             Ok(rest.is_empty())
         }
 
-        self.comment = insert_char_at(self.comment.as_str(), offset, comment, check)?;
-        Ok(())
+        let value = insert_char_at(self.comment.as_str(), offset, comment, |_| Ok(true))?;
+        if check(value.as_str()).unwrap_or_default() {
+            self.comment = value;
+            Ok(())
+        } else {
+            Err(error::Error::InvalidData(comment.to_string()))
+        }
     }
 
     pub fn is_empty(&self) -> bool {
@@ -1268,6 +1334,14 @@ impl XmlDeclarationAttDef {
             value,
         })
     }
+
+    pub fn attribute_type(&self) -> &XmlDeclarationAttType {
+        &self.ty
+    }
+
+    pub fn default_declaration(&self) -> &XmlDeclarationAttDefault {
+        &self.value
+    }
 }
 
 // -----------------------------------------------------------------------------------------------
@@ -1381,6 +1455,10 @@ impl XmlDeclarationAttList {
         att_list.borrow().context.add_item(&node);
         Ok(node)
     }
+
+    pub fn definitions(&self) -> &[XmlDeclarationAttDef] {
+        self.atts.as_slice()
+    }
 }
 
 // -----------------------------------------------------------------------------------------------
@@ -1422,6 +1500,94 @@ impl From<&parser::DeclarationAttType<'_>> for XmlDeclarationAttType {
 
 // -----------------------------------------------------------------------------------------------
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct XmlDeclarationElement {
+    local_name: String,
+    prefix: Option<String>,
+    content: XmlDeclarationContent,
+}
+
+impl HasQName for XmlDeclarationElement {
+    fn local_name(&self) -> &str {
+        self.local_name.as_str()
+    }
+
+    fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+}
+
+impl XmlDeclarationElement {
+    pub fn new(value: &parser::DeclarationElement<'_>) -> Self {
+        let (local_name, prefix) = qname(&value.name);
+        let content = XmlDeclarationContent::from(&value.content);
+        XmlDeclarationElement {
+            local_name,
+            prefix,
+            content,
+        }
+    }
+
+    pub fn content(&self) -> &XmlDeclarationContent {
+        &self.content
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum XmlDeclarationContent {
+    Empty,
+    Any,
+    Mixed(Vec<String>),
+    Children(XmlDeclarationContentItem),
+}
+
+impl From<&parser::DeclarationContent<'_>> for XmlDeclarationContent {
+    fn from(value: &parser::DeclarationContent<'_>) -> Self {
+        match value {
+            parser::DeclarationContent::Empty => XmlDeclarationContent::Empty,
+            parser::DeclarationContent::Any => XmlDeclarationContent::Any,
+            parser::DeclarationContent::Mixed(v) => {
+                XmlDeclarationContent::Mixed(v.iter().flatten().map(qualified_name).collect())
+            }
+            parser::DeclarationContent::Children(v) => {
+                XmlDeclarationContent::Children(XmlDeclarationContentItem::from(v))
+            }
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum XmlDeclarationContentItem {
+    Name(String, Option<char>),
+    Choice(Vec<XmlDeclarationContentItem>, Option<char>),
+    Seq(Vec<XmlDeclarationContentItem>, Option<char>),
+}
+
+impl From<&parser::DeclarationContentItem<'_>> for XmlDeclarationContentItem {
+    fn from(value: &parser::DeclarationContentItem<'_>) -> Self {
+        let occurrence = |v: &Option<&str>| v.and_then(|v| v.chars().next());
+        match value {
+            parser::DeclarationContentItem::Name(n, o) => {
+                XmlDeclarationContentItem::Name(qualified_name(n), occurrence(o))
+            }
+            parser::DeclarationContentItem::Choice(v, o) => XmlDeclarationContentItem::Choice(
+                v.iter().map(XmlDeclarationContentItem::from).collect(),
+                occurrence(o),
+            ),
+            parser::DeclarationContentItem::Seq(v, o) => XmlDeclarationContentItem::Seq(
+                v.iter().map(XmlDeclarationContentItem::from).collect(),
+                occurrence(o),
+            ),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
 #[derive(Clone, Debug)]
 pub struct XmlDocument {
     children: Singleton<Vec<Rc<XmlItem>>>,
@@ -1475,6 +1641,7 @@ impl HasChildren for XmlDocument {
         if let Some(index) = self.child_index(id) {
             let value = self.children.borrow_mut().remove(index);
             value.set_parent_id(None);
+            self.context().invalidate();
             Some(value)
         } else {
             None
@@ -1493,12 +1660,14 @@ impl HasChildren for XmlDocument {
         fn add_or_insert(doc: &XmlDocument, value: Rc<XmlItem>, id: Option<usize>) {
             value.remove_from_parent();
             value.set_parent_id(Some(doc.id()));
+            XmlItem::register(&value);
             if let Some(id) = id {
                 let index = doc.child_index(id).unwrap();
                 doc.children.borrow_mut().insert(index, value);
             } else {
                 doc.children.borrow_mut().push(value);
             }
+            doc.context().invalidate();
         }
 
         match &*value {
@@ -1620,6 +1789,26 @@ impl PartialEq<XmlDocument> for XmlDocument {
 
 impl fmt::Display for XmlDocument {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        if let Some(line_ending) = self.context().line_ending() {
+            self.write(&mut LineEnding::new(f, line_ending))
+        } else {
+            self.write(f)
+        }
+    }
+}
+
+impl XmlDocument {
+    fn write(&self, f: &mut impl fmt::Write) -> Result<(), fmt::Error> {
+        self.write_declaration(f)?;
+
+        for child in self.children.borrow().as_slice() {
+            write!(f, "{}", child)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_declaration(&self, f: &mut impl fmt::Write) -> Result<(), fmt::Error> {
         if let Some(version) = self.version.as_deref() {
             write!(f, "<?xml version=\"{}\"", version)?;
 
@@ -1635,16 +1824,18 @@ impl fmt::Display for XmlDocument {
             write!(f, "?>")?;
         }
 
-        for child in self.children.borrow().as_slice() {
-            child.fmt(f)?;
-        }
-
         Ok(())
     }
-}
 
-impl XmlDocument {
     pub fn new(value: &parser::Document<'_>) -> error::Result<XmlNode<Self>> {
+        XmlDocument::new_in(value, &mut NodePool::default())
+    }
+
+    /// Builds the document with node tables taken from `pool`.
+    pub fn new_in(
+        value: &parser::Document<'_>,
+        pool: &mut NodePool,
+    ) -> error::Result<XmlNode<Self>> {
         let document = node(XmlDocument {
             children: singleton(vec![]),
             base_uri: String::new(),
@@ -1655,12 +1846,13 @@ impl XmlDocument {
             context: None,
         });
 
-        let context = Context::new(document.clone());
+        let context = Context::new(document.clone(), pool.take());
         document.borrow_mut().context = Some(context.clone());
 
-        fn add_misc(context: &Context, misc: &parser::Misc<'_>) {
-            let doc = context.document().clone();
-            let doc_id = Some(context.document().borrow().id());
+        fn add_misc(doc: &XmlNode<XmlDocument>, misc: &parser::Misc<'_>) {
+            let context = doc.borrow().context().clone();
+            let context = &context;
+            let doc_id = Some(doc.borrow().id());
             match misc {
                 parser::Misc::Comment(c) => {
                     let c = XmlComment::node(c.value, doc_id, context);
@@ -1675,7 +1867,7 @@ impl XmlDocument {
         }
 
         for h in value.prolog.heads.as_slice() {
-            add_misc(&context, h);
+            add_misc(&document, h);
         }
 
         if let Some(d) = value.prolog.declaration_doc.as_ref() {
@@ -1684,14 +1876,14 @@ impl XmlDocument {
         }
 
         for t in value.prolog.tails.as_slice() {
-            add_misc(&context, t);
+            add_misc(&document, t);
         }
 
         let element = XmlElement::node(&value.element, Some(document.borrow().id()), &context)?;
         document.borrow_mut().push_child(element);
 
         for h in value.miscs.as_slice() {
-            add_misc(&context, h);
+            add_misc(&document, h);
         }
 
         document.borrow().init_order_recursive();
@@ -1699,16 +1891,137 @@ impl XmlDocument {
         Ok(document)
     }
 
+    pub fn new_with_spans(
+        value: &parser::Document<'_>,
+        source: &str,
+    ) -> error::Result<XmlNode<Self>> {
+        XmlDocument::new_with_spans_in(value, source, &mut NodePool::default())
+    }
+
+    pub fn new_with_spans_in(
+        value: &parser::Document<'_>,
+        source: &str,
+        pool: &mut NodePool,
+    ) -> error::Result<XmlNode<Self>> {
+        let document = XmlDocument::new_in(value, pool)?;
+        let context = document.borrow().context().clone();
+        context.add_span(document.borrow().id(), 0..source.len(), None);
+
+        let children = document.borrow().children.borrow().clone();
+        let mut children = children.iter();
+        let miscs = value.prolog.heads.iter().chain(value.prolog.tails.iter());
+        for misc in miscs.chain(value.miscs.iter()) {
+            let (range, name) = match misc {
+                parser::Misc::Comment(v) => {
+                    let range = source_offset(source, v.value) - 4..end_of_comment(source, v);
+                    (range, None)
+                }
+                parser::Misc::PI(v) => {
+                    let range = source_offset(source, v.target) - 2..end_of_pi(source, v);
+                    (range, Some(source_range(source, v.target)))
+                }
+                parser::Misc::Whitespace(_) => continue,
+            };
+
+            // skip document type declaration and root element.
+            for child in children.by_ref() {
+                if child.as_comment().is_some() || child.as_pi().is_some() {
+                    context.add_span(child.id(), range, name);
+                    break;
+                }
+            }
+        }
+
+        if let (Some(d), Some(doc_type)) = (
+            value.prolog.declaration_doc.as_ref(),
+            document.borrow().document_declaration(),
+        ) {
+            let name = match &d.name {
+                xml_nom::model::QName::Prefixed(v) => v.prefix,
+                xml_nom::model::QName::Unprefixed(v) => v,
+            };
+            let offset = source_offset(source, name);
+            let start = source[..offset].rfind("<!DOCTYPE").unwrap_or(offset);
+            let tail = d.internal_subset_literal.unwrap_or(name);
+            let end = end_of_doctype(source, source_end(source, tail));
+            context.add_span(
+                doc_type.borrow().id(),
+                start..end,
+                Some(name_range(source, offset)),
+            );
+        }
+
+        if let Ok(root) = document.borrow().document_element() {
+            span_element(source, &value.element, &root, &context);
+        }
+
+        Ok(document)
+    }
+
+    pub fn record_digests(&self) {
+        let context = self.context();
+        let mut digests = HashMap::new();
+        for child in self.children.borrow().iter() {
+            digest(child, &mut digests);
+        }
+
+        let mut declaration = String::new();
+        let _ = self.write_declaration(&mut declaration);
+        digests.insert(self.id(), digest_str(&declaration));
+
+        *context.digests.borrow_mut() = digests;
+    }
+
+    pub fn write_preserving(&self, f: &mut impl fmt::Write) -> Result<(), fmt::Error> {
+        let context = self.context();
+        let source = match context.source() {
+            Some(v) => v,
+            None => return self.write(f),
+        };
+
+        let mut digests = HashMap::new();
+        for child in self.children.borrow().iter() {
+            digest(child, &mut digests);
+        }
+
+        let mut declaration = String::new();
+        self.write_declaration(&mut declaration)?;
+        let mut pos = if source.starts_with("<?xml") {
+            source.find("?>").map(|v| v + 2).unwrap_or_default()
+        } else {
+            0
+        };
+        if context.digest(self.id()) == Some(digest_str(&declaration)) {
+            f.write_str(&source[..pos])?;
+        } else {
+            f.write_str(&declaration)?;
+        }
+
+        for child in self.children.borrow().iter() {
+            if let Some(span) = context.span(child.id()).filter(|v| v.start() >= pos) {
+                write_space(f, &source[pos..span.start()])?;
+                pos = span.end();
+            }
+            write_preserving(child, context, &source, &digests, f)?;
+        }
+
+        write_space(f, &source[pos..])
+    }
+
     pub fn empty() -> XmlNode<Self> {
         let (_, tree) = xml_parser::document("<r />").unwrap();
         let doc = XmlDocument::new(&tree).unwrap();
-        doc.borrow_mut()
-            .children
-            .borrow_mut()
-            .retain(|v| v.as_element().is_none());
+        let element = doc.borrow().document_element().unwrap();
+        let id = element.borrow().id();
+        doc.borrow().delete(id);
         doc
     }
 
+    pub fn set_base_uri(&mut self, value: &str) {
+        self.context().touch();
+        self.base_uri = value.to_string();
+    }
+
     pub fn document_declaration(&self) -> Option<XmlNode<XmlDocumentTypeDeclaration>> {
         self.children
             .borrow()
@@ -1716,6 +2029,27 @@ impl XmlDocument {
             .find_map(|v| v.as_document_type())
     }
 
+    pub fn element_by_id(&self, id: &str) -> error::Result<Option<XmlNode<XmlElement>>> {
+        let context = self.context();
+        let generation = context.generation();
+        let stale = !matches!(&*context.element_ids.borrow(), Some((g, _)) if *g == generation);
+        if stale {
+            let mut ids = HashMap::new();
+            if let Ok(root) = self.document_element() {
+                collect_element_ids(&root, &mut ids)?;
+            }
+            *context.element_ids.borrow_mut() = Some((generation, ids));
+        }
+
+        let node_id = match &*context.element_ids.borrow() {
+            Some((_, ids)) => ids.get(id).copied(),
+            None => None,
+        };
+        Ok(node_id
+            .and_then(|v| context.node(v))
+            .and_then(|v| v.as_element()))
+    }
+
     fn push_child(&self, child: Rc<XmlItem>) {
         self.children.borrow_mut().push(child);
     }
@@ -1730,6 +2064,10 @@ pub struct XmlDocumentTypeDeclaration {
     system_identifier: Option<String>,
     public_identifier: Option<String>,
     children: Singleton<Vec<Rc<XmlItem>>>,
+    external: Singleton<Vec<Rc<XmlItem>>>,
+    elements: Vec<XmlDeclarationElement>,
+    internal_subset: Option<String>,
+    internal_subset_space: String,
     context: Context,
 }
 
@@ -1753,7 +2091,9 @@ impl IndentedDisplay for XmlDocumentTypeDeclaration {
             write!(f, " SYSTEM {}", escape(sys_id))?;
         }
 
-        if !self.children.borrow().is_empty() {
+        if let Some(internal_subset) = self.internal_subset.as_deref() {
+            write!(f, " [{}]{}", internal_subset, self.internal_subset_space)?;
+        } else if !self.children.borrow().is_empty() {
             write!(f, "\n[")?;
 
             for child in self.children.borrow().as_slice() {
@@ -1779,7 +2119,7 @@ impl HasContext for XmlDocumentTypeDeclaration {
     fn init_order_recursive(&self) {
         self.init_order();
 
-        for v in self.children.borrow().as_slice() {
+        for v in self.declarations() {
             v.init_order_recursive();
         }
     }
@@ -1814,8 +2154,8 @@ impl DocumentTypeDeclaration for XmlDocumentTypeDeclaration {
         OrderedList::new(pis)
     }
 
-    fn parent(&self) -> XmlNode<XmlDocument> {
-        self.context.document().clone()
+    fn parent(&self) -> error::Result<XmlNode<XmlDocument>> {
+        self.context.document()
     }
 }
 
@@ -1849,7 +2189,9 @@ impl fmt::Display for XmlDocumentTypeDeclaration {
             write!(f, " SYSTEM {}", escape(sys_id))?;
         }
 
-        if !self.children.borrow().is_empty() {
+        if let Some(internal_subset) = self.internal_subset.as_deref() {
+            write!(f, " [{}]{}", internal_subset, self.internal_subset_space)?;
+        } else if !self.children.borrow().is_empty() {
             write!(f, " [")?;
 
             for child in self.children.borrow().as_slice() {
@@ -1883,6 +2225,10 @@ impl XmlDocumentTypeDeclaration {
             system_identifier,
             public_identifier,
             children: singleton(vec![]),
+            external: singleton(vec![]),
+            elements: vec![],
+            internal_subset: None,
+            internal_subset_space: String::new(),
             context: context.next(),
         });
         let declaration_id = declaration.borrow().id();
@@ -1894,11 +2240,13 @@ impl XmlDocumentTypeDeclaration {
                         let attribute = XmlDeclarationAttList::node(v, declaration_id, context);
                         declaration.borrow_mut().push_child(attribute?);
                     }
-                    parser::DeclarationMarkup::Commnect(_) => {
-                        // drop
+                    parser::DeclarationMarkup::Commnect(v) => {
+                        let comment = XmlComment::node(v.value, Some(declaration_id), context);
+                        declaration.borrow_mut().push_child(comment);
                     }
-                    parser::DeclarationMarkup::Element(_) => {
-                        // drop
+                    parser::DeclarationMarkup::Element(v) => {
+                        let element = XmlDeclarationElement::new(v);
+                        declaration.borrow_mut().elements.push(element);
                     }
                     parser::DeclarationMarkup::Entity(v) => match v {
                         parser::DeclarationEntity::GeneralEntity(v) => {
@@ -1927,6 +2275,37 @@ impl XmlDocumentTypeDeclaration {
             }
         }
 
+        // The external subset is kept apart so that it is not serialized with the declaration.
+        for subset in &value.external_subset {
+            let markup = match subset {
+                parser::InternalSubset::Markup(v) => v,
+                _ => continue,
+            };
+            let external = match markup {
+                parser::DeclarationMarkup::Attributes(v) => {
+                    XmlDeclarationAttList::node(v, declaration_id, context)?
+                }
+                parser::DeclarationMarkup::Element(v) => {
+                    let element = XmlDeclarationElement::new(v);
+                    declaration.borrow_mut().elements.push(element);
+                    continue;
+                }
+                parser::DeclarationMarkup::Entity(parser::DeclarationEntity::GeneralEntity(v)) => {
+                    XmlEntity::node(v, declaration_id, context)
+                }
+                parser::DeclarationMarkup::Notation(v) => {
+                    XmlNotation::node(v, declaration_id, context)
+                }
+                // Parameter entities, comments and processing instructions are not kept.
+                _ => continue,
+            };
+            declaration.borrow().external.borrow_mut().push(external);
+        }
+
+        declaration.borrow_mut().internal_subset =
+            value.internal_subset_literal.map(|v| v.to_string());
+        declaration.borrow_mut().internal_subset_space = value.internal_subset_space.to_string();
+
         let node: Rc<XmlItem> = Rc::new(declaration.clone().into());
         declaration.borrow().context.add_item(&node);
         Ok(node)
@@ -1939,6 +2318,10 @@ impl XmlDocumentTypeDeclaration {
             system_identifier: None,
             public_identifier: None,
             children: singleton(vec![]),
+            external: singleton(vec![]),
+            elements: vec![],
+            internal_subset: None,
+            internal_subset_space: String::new(),
             context: context.next(),
         });
         let node = Rc::new(declaration.clone().into());
@@ -1947,33 +2330,55 @@ impl XmlDocumentTypeDeclaration {
         node
     }
 
+    pub fn set_public_identifier(&mut self, value: Option<&str>) {
+        self.context().touch();
+        self.public_identifier = value.map(|v| v.to_string());
+    }
+
+    pub fn set_system_identifier(&mut self, value: Option<&str>) {
+        self.context().touch();
+        self.system_identifier = value.map(|v| v.to_string());
+    }
+
     pub fn attributes(&self) -> Vec<XmlNode<XmlDeclarationAttList>> {
-        self.children
-            .borrow()
+        self.declarations()
             .iter()
             .filter_map(|v| v.as_declaration_att_list())
             .collect()
     }
 
-    pub fn entities(&self) -> Vec<XmlNode<XmlEntity>> {
+    pub fn comments(&self) -> Vec<XmlNode<XmlComment>> {
         self.children
             .borrow()
+            .iter()
+            .filter_map(|v| v.as_comment())
+            .collect()
+    }
+
+    pub fn elements(&self) -> &[XmlDeclarationElement] {
+        self.elements.as_slice()
+    }
+
+    pub fn entities(&self) -> Vec<XmlNode<XmlEntity>> {
+        self.declarations()
             .iter()
             .filter_map(|v| v.as_entity())
             .collect()
     }
 
+    pub fn internal_subset(&self) -> Option<&str> {
+        self.internal_subset.as_deref()
+    }
+
     pub fn notations(&self) -> Vec<XmlNode<XmlNotation>> {
-        self.children
-            .borrow()
+        self.declarations()
             .iter()
             .filter_map(|v| v.as_notation())
             .collect()
     }
 
     pub fn unparsed_entities(&self) -> Vec<XmlNode<XmlUnparsedEntity>> {
-        self.children
-            .borrow()
+        self.declarations()
             .iter()
             .filter_map(|v| v.as_entity())
             .filter(|v| v.borrow().notation_name.is_some())
@@ -1981,19 +2386,40 @@ impl XmlDocumentTypeDeclaration {
             .collect()
     }
 
-    fn push_child(&self, child: Rc<XmlItem>) {
+    /// Declarations of the internal subset followed by those of the external subset.
+    fn declarations(&self) -> Vec<Rc<XmlItem>> {
+        let children = self.children.borrow();
+        let external = self.external.borrow();
+        children.iter().chain(external.iter()).cloned().collect()
+    }
+
+    fn push_child(&mut self, child: Rc<XmlItem>) {
+        self.internal_subset = None;
         self.children.borrow_mut().push(child);
     }
 }
 
 // -----------------------------------------------------------------------------------------------
 
+#[derive(Clone, Default)]
+struct PendingChildren(Singleton<Option<Rc<dyn ChildSource>>>);
+
+impl fmt::Debug for PendingChildren {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "PendingChildren({})", self.0.borrow().is_some())
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
 #[derive(Clone, Debug)]
 pub struct XmlElement {
     local_name: String,
     prefix: Option<String>,
     children: Singleton<Vec<Rc<XmlItem>>>,
+    source: PendingChildren,
     attributes: Vec<Rc<XmlItem>>,
+    spaces: Vec<String>,
     base_uri: String,
     parent_id: Option<usize>,
     context: Context,
@@ -2001,59 +2427,63 @@ pub struct XmlElement {
 
 impl IndentedDisplay for XmlElement {
     fn indented(&self, indent: usize, f: &mut impl io::Write) -> io::Result<()> {
-        let space = " ".repeat(indent);
-
-        write!(f, "{}<", space)?;
-        if let Some(prefix) = self.prefix.as_deref() {
-            write!(f, "{}:", prefix)?;
-        }
-        write!(f, "{}", self.local_name.as_str())?;
-
-        for attr in self.attributes.as_slice() {
-            write!(f, " {}", attr)?;
+        if !self.write_indented_start_tag(indent, f)? {
+            return Ok(());
         }
 
-        if self.children.borrow().is_empty() {
-            write!(f, " />")
-        } else {
-            write!(f, ">")?;
+        let mut stack = vec![(self.child_items().clone(), 0, None, indent)];
+        while let Some((children, index, _, indent)) = stack.last_mut() {
+            let child = children.borrow().get(*index).cloned();
+            *index += 1;
 
-            let mut has_element = false;
-            for child in self.children.borrow().as_slice() {
-                if child.as_element().is_some() {
-                    has_element = true;
+            match child {
+                Some(child) if child.as_text().is_some() => {}
+                Some(child) => {
                     writeln!(f)?;
+                    let indent = *indent + 4;
+                    match child.as_element() {
+                        Some(element) => {
+                            if element.borrow().write_indented_start_tag(indent, f)? {
+                                let children = element.borrow().child_items().clone();
+                                stack.push((children, 0, Some(element), indent));
+                            }
+                        }
+                        None => child.indented(indent, f)?,
+                    }
+                }
+                None => {
+                    if let Some((_, _, element, indent)) = stack.pop() {
+                        writeln!(f)?;
+                        match element {
+                            Some(element) => element.borrow().write_indented_end_tag(indent, f)?,
+                            None => self.write_indented_end_tag(indent, f)?,
+                        }
+                    }
                 }
-
-                child.indented(indent + 4, f)?;
-            }
-
-            if has_element {
-                write!(f, "\n{}", space)?;
-            }
-
-            write!(f, "</")?;
-            if let Some(prefix) = self.prefix.as_deref() {
-                write!(f, "{}:", prefix)?;
             }
-            write!(f, "{}>", self.local_name.as_str())
         }
+
+        Ok(())
     }
 }
 
 impl HasChildren for XmlElement {
     fn child_index(&self, id: usize) -> Option<usize> {
-        self.children.borrow().iter().position(|v| v.id() == id)
+        self.child_items()
+            .borrow()
+            .iter()
+            .position(|v| v.id() == id)
     }
 
     fn child_by_index(&self, index: usize) -> Option<Rc<XmlItem>> {
-        self.children.borrow().get(index).cloned()
+        self.child_items().borrow().get(index).cloned()
     }
 
     fn delete_by_id(&self, id: usize) -> Option<Rc<XmlItem>> {
         if let Some(index) = self.child_index(id) {
-            let value = self.children.borrow_mut().remove(index);
+            let value = self.child_items().borrow_mut().remove(index);
             value.set_parent_id(None);
+            self.context().invalidate();
             Some(value)
         } else {
             None
@@ -2061,7 +2491,7 @@ impl HasChildren for XmlElement {
     }
 
     fn last_child_or_self_id(&self) -> usize {
-        if let Some(last) = self.children.borrow().iter().last() {
+        if let Some(last) = self.child_items().borrow().iter().last() {
             last.id()
         } else {
             self.id()
@@ -2083,12 +2513,14 @@ impl HasChildren for XmlElement {
             | XmlItem::Unexpanded(_) => {
                 value.remove_from_parent();
                 value.set_parent_id(Some(self.id()));
+                XmlItem::register(&value);
                 if let Some(id) = id {
                     let index = self.child_index(id).unwrap();
-                    self.children.borrow_mut().insert(index, value.clone());
+                    self.child_items().borrow_mut().insert(index, value.clone());
                 } else {
-                    self.children.borrow_mut().push(value.clone());
+                    self.child_items().borrow_mut().push(value.clone());
                 }
+                self.context().invalidate();
                 Ok(value)
             }
             _ => Err(error::Error::InvalidType),
@@ -2106,18 +2538,27 @@ impl HasContext for XmlElement {
     }
 
     fn init_order_recursive(&self) {
-        self.init_order();
-
-        for child in self.namespace_attributes().iter() {
-            child.borrow().init_order_recursive();
-        }
-
-        for child in self.attributes_specified().iter() {
-            child.borrow().init_order_recursive();
-        }
-
-        for child in self.children.borrow().as_slice() {
-            child.init_order_recursive();
+        self.init_order_with_attributes();
+
+        // Children that are not loaded yet are ordered when they are loaded. Descendant
+        // elements are visited with an explicit stack, so that a deep tree does not overflow it.
+        let mut stack = vec![(self.children.clone(), 0)];
+        while let Some((children, index)) = stack.last_mut() {
+            let child = children.borrow().get(*index).cloned();
+            *index += 1;
+
+            match child {
+                Some(child) => match child.as_element() {
+                    Some(element) => {
+                        element.borrow().init_order_with_attributes();
+                        stack.push((element.borrow().children.clone(), 0));
+                    }
+                    None => child.init_order_recursive(),
+                },
+                None => {
+                    stack.pop();
+                }
+            }
         }
     }
 }
@@ -2144,13 +2585,17 @@ impl HasQName for XmlElement {
 
 impl Element for XmlElement {
     fn namespace_name(&self) -> error::Result<Option<NamespaceUri>> {
+        if !self.context().namespace_aware() {
+            return Ok(None);
+        }
+
         let prefix = self.prefix().unwrap_or("xmlns");
         self.find_nameapce_uri(prefix)
     }
 
     fn children(&self) -> OrderedList<Rc<XmlItem>> {
         let mut items = vec![];
-        for item in self.children.borrow().iter() {
+        for item in self.child_items().borrow().iter() {
             items.push(item.clone());
         }
         OrderedList::new(items)
@@ -2158,6 +2603,9 @@ impl Element for XmlElement {
 
     fn attributes(&self) -> UnorderedSet<XmlNode<XmlAttribute>> {
         let mut items = self.attributes_specified();
+        if !self.context().attributes_defaulted() {
+            return UnorderedSet::new(items);
+        }
 
         if let Some(attrs) = self.declaration_att_list() {
             for attr in attrs.borrow().atts.as_slice() {
@@ -2222,38 +2670,59 @@ impl PartialEq<XmlElement> for XmlElement {
     fn eq(&self, other: &XmlElement) -> bool {
         self.local_name == other.local_name
             && self.prefix == other.prefix
-            && self.children == other.children
+            && self.child_items() == other.child_items()
             && self.attributes == other.attributes
     }
 }
 
+impl Drop for XmlElement {
+    fn drop(&mut self) {
+        // Detach descendants iteratively so that dropping a deeply nested tree
+        // does not recurse once per level.
+        let mut stack = take_children(&self.children);
+        while let Some(item) = stack.pop() {
+            if let Ok(XmlItem::Element(element)) = Rc::try_unwrap(item) {
+                if let Ok(element) = Rc::try_unwrap(element) {
+                    stack.append(&mut take_children(&element.borrow().children));
+                }
+            }
+        }
+    }
+}
+
 impl fmt::Display for XmlElement {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "<")?;
-        if let Some(prefix) = self.prefix.as_deref() {
-            write!(f, "{}:", prefix)?;
+        self.write_start_tag(f)?;
+        if self.child_items().borrow().is_empty() {
+            return Ok(());
         }
-        write!(f, "{}", self.local_name.as_str())?;
 
-        for attr in self.attributes.as_slice() {
-            write!(f, " {}", attr)?;
-        }
+        let mut stack = vec![(self.child_items().clone(), 0, None)];
+        while let Some((children, index, _)) = stack.last_mut() {
+            let child = children.borrow().get(*index).cloned();
+            *index += 1;
 
-        if self.children.borrow().is_empty() {
-            write!(f, " />")
-        } else {
-            write!(f, ">")?;
+            match child {
+                Some(child) => match child.as_element() {
+                    Some(element) => {
+                        element.borrow().write_start_tag(f)?;
 
-            for child in self.children.borrow().as_slice() {
-                child.fmt(f)?;
+                        let children = element.borrow().child_items().clone();
+                        if !children.borrow().is_empty() {
+                            stack.push((children, 0, Some(element)));
+                        }
+                    }
+                    None => child.fmt(f)?,
+                },
+                None => {
+                    if let Some((_, _, Some(element))) = stack.pop() {
+                        element.borrow().write_end_tag(f)?;
+                    }
+                }
             }
-
-            write!(f, "</")?;
-            if let Some(prefix) = self.prefix.as_deref() {
-                write!(f, "{}:", prefix)?;
-            }
-            write!(f, "{}>", self.local_name.as_str())
         }
+
+        self.write_end_tag(f)
     }
 }
 
@@ -2263,13 +2732,104 @@ impl XmlElement {
         parent_id: Option<usize>,
         context: &Context,
     ) -> error::Result<Rc<XmlItem>> {
+        let push_tail = |element: &XmlNode<XmlElement>, cell: &parser::ContentCell<'_>| {
+            if let Some(tail) = cell.tail.filter(|v| !v.is_empty()) {
+                let text = XmlText::node(tail, Some(element.borrow().id()), context);
+                element.borrow().push_child(text);
+            }
+        };
+
+        // Descendants are built with an explicit stack, so that a deeply nested element does
+        // not overflow it. Ids are still given in document order.
+        let mut stack = vec![(XmlElement::open(value, parent_id, context)?, value, 0)];
+        while let Some((element, value, index)) = stack.last_mut() {
+            let (element, value) = (element.clone(), *value);
+            *index += 1;
+            let cells = value.content.as_ref().map(|v| v.children.as_slice());
+            let cell = match cells.unwrap_or_default().get(*index - 1) {
+                Some(v) => v,
+                None => {
+                    stack.pop();
+                    let node: Rc<XmlItem> = Rc::new(element.clone().into());
+                    element.borrow().context.add_item(&node);
+                    match stack.last() {
+                        Some((parent, value, index)) => {
+                            parent.borrow().push_child(node);
+                            if let Some(content) = &value.content {
+                                push_tail(parent, &content.children[*index - 1]);
+                            }
+                        }
+                        None => return Ok(node),
+                    }
+                    continue;
+                }
+            };
+
+            let element_id = Some(element.borrow().id());
+            match &cell.child {
+                parser::Contents::Element(v) => {
+                    // The tail follows once the child is complete.
+                    stack.push((XmlElement::open(v, element_id, context)?, v, 0));
+                    continue;
+                }
+                parser::Contents::Reference(v) => match v {
+                    parser::Reference::Character(ch, radix) => {
+                        let reference = XmlCharReference::node(ch, *radix, element_id, context)?;
+                        element.borrow().push_child(reference);
+                    }
+                    parser::Reference::Entity(v) => {
+                        let entity = context.entity(v)?;
+                        let entity =
+                            XmlUnexpandedEntityReference::node(entity, element_id, context);
+                        element.borrow().push_child(entity);
+                    }
+                },
+                parser::Contents::CData(v) => {
+                    let cdata = XmlCData::node(v.value, element_id, context);
+                    element.borrow().push_child(cdata);
+                }
+                parser::Contents::PI(v) => {
+                    let pi = XmlProcessingInstruction::node(v, element_id, context);
+                    element.borrow().push_child(pi);
+                }
+                parser::Contents::Comment(v) => {
+                    let comment = XmlComment::node(v.value, element_id, context);
+                    element.borrow().push_child(comment);
+                }
+            }
+            push_tail(&element, cell);
+        }
+
+        unreachable!()
+    }
+
+    fn init_order_with_attributes(&self) {
+        self.init_order();
+
+        for child in self.namespace_attributes().iter() {
+            child.borrow().init_order_recursive();
+        }
+
+        for child in self.attributes_specified().iter() {
+            child.borrow().init_order_recursive();
+        }
+    }
+
+    /// Creates the element with its attributes and leading text.
+    fn open(
+        value: &parser::Element<'_>,
+        parent_id: Option<usize>,
+        context: &Context,
+    ) -> error::Result<XmlNode<XmlElement>> {
         let (local_name, prefix) = qname(&value.name);
 
         let element = node(XmlElement {
             local_name,
             prefix,
             children: singleton(vec![]),
+            source: PendingChildren::default(),
             attributes: vec![],
+            spaces: value.spaces.iter().map(|v| v.to_string()).collect(),
             base_uri: String::new(),
             parent_id,
             context: context.next(),
@@ -2281,59 +2841,14 @@ impl XmlElement {
             element.borrow_mut().push_attribute(attr);
         }
 
-        if let Some(content) = &value.content {
-            if let Some(head) = content.head {
-                if !head.is_empty() {
-                    let text = XmlText::node(head, element_id, context);
-                    element.borrow_mut().push_child(text);
-                }
-            }
-
-            for cell in content.children.as_slice() {
-                match &cell.child {
-                    parser::Contents::Element(v) => {
-                        let child = XmlElement::node(v, element_id, context)?;
-                        element.borrow_mut().push_child(child);
-                    }
-                    parser::Contents::Reference(v) => match v {
-                        parser::Reference::Character(ch, radix) => {
-                            let reference =
-                                XmlCharReference::node(ch, *radix, element_id, context)?;
-                            element.borrow_mut().push_child(reference);
-                        }
-                        parser::Reference::Entity(v) => {
-                            let entity = context.entity(v)?;
-                            let entity =
-                                XmlUnexpandedEntityReference::node(entity, element_id, context);
-                            element.borrow_mut().push_child(entity);
-                        }
-                    },
-                    parser::Contents::CData(v) => {
-                        let cdata = XmlCData::node(v.value, element_id, context);
-                        element.borrow_mut().push_child(cdata);
-                    }
-                    parser::Contents::PI(v) => {
-                        let pi = XmlProcessingInstruction::node(v, element_id, context);
-                        element.borrow_mut().push_child(pi);
-                    }
-                    parser::Contents::Comment(v) => {
-                        let comment = XmlComment::node(v.value, element_id, context);
-                        element.borrow_mut().push_child(comment);
-                    }
-                }
-
-                if let Some(tail) = cell.tail {
-                    if !tail.is_empty() {
-                        let text = XmlText::node(tail, element_id, context);
-                        element.borrow_mut().push_child(text);
-                    }
-                }
+        if let Some(head) = value.content.as_ref().and_then(|v| v.head) {
+            if !head.is_empty() {
+                let text = XmlText::node(head, element_id, context);
+                element.borrow().push_child(text);
             }
         }
 
-        let node: Rc<XmlItem> = Rc::new(element.clone().into());
-        element.borrow().context.add_item(&node);
-        Ok(node)
+        Ok(element)
     }
 
     pub fn empty(name: &str, context: &Context) -> error::Result<Rc<XmlItem>> {
@@ -2347,10 +2862,23 @@ impl XmlElement {
     }
 
     pub fn append_attribute(&mut self, attr: Rc<XmlItem>) {
+        self.context().touch();
+        attr.set_parent_id(Some(self.id()));
         attr.init_order_recursive();
+        if !self.spaces.is_empty() {
+            self.spaces.insert(self.attributes.len(), " ".to_string());
+        }
         self.attributes.push(attr);
     }
 
+    /// Specified attributes and namespace declarations in document order.
+    pub fn attribute_nodes(&self) -> Vec<XmlNode<XmlAttribute>> {
+        self.attributes
+            .iter()
+            .filter_map(|v| v.as_attribute())
+            .collect()
+    }
+
     pub fn namespaces(&self) -> error::Result<Vec<XmlNode<XmlNamespace>>> {
         let mut items = vec![];
 
@@ -2378,25 +2906,97 @@ impl XmlElement {
     }
 
     pub fn remove_attribute(&mut self, name: &str) -> Option<Rc<XmlItem>> {
-        if let Some(v) = self
-            .attributes
-            .iter()
-            .find(|v| v.as_attribute().unwrap().borrow().local_name() == name)
-            .cloned()
-        {
-            self.attributes
-                .retain(|v| v.as_attribute().unwrap().borrow().local_name() != name);
+        let index = self.attribute_index(name)?;
+        Some(self.remove_attribute_at(index))
+    }
+
+    pub fn replace_attribute(&mut self, attr: Rc<XmlItem>) -> Option<Rc<XmlItem>> {
+        let name = attr.as_attribute()?.borrow().local_name().to_string();
+        let index = self.attribute_index(name.as_str());
+        self.replace_attribute_at(index, attr)
+    }
+
+    pub fn attribute_index_ns(
+        &self,
+        namespace_uri: Option<&str>,
+        local_name: &str,
+    ) -> Option<usize> {
+        self.attributes.iter().position(|v| {
+            let attr = v.as_attribute().unwrap();
+            let attr = attr.borrow();
+            if attr.local_name() != local_name {
+                return false;
+            }
+
+            let uri = if attr.namespace() {
+                Some(NamespaceUri::xmlns())
+            } else if let Some(prefix) = attr.prefix() {
+                self.find_nameapce_uri(prefix).ok().flatten()
+            } else {
+                None
+            };
+            uri.as_deref() == namespace_uri
+        })
+    }
+
+    pub fn lookup_namespace_uri(&self, prefix: &str) -> error::Result<Option<NamespaceUri>> {
+        self.find_nameapce_uri(prefix)
+    }
+
+    pub fn remove_attribute_at(&mut self, index: usize) -> Rc<XmlItem> {
+        self.context().touch();
+        let v = self.attributes.remove(index);
+        if !self.spaces.is_empty() {
+            self.spaces.remove(index);
+        }
+        v.set_parent_id(None);
+        v.clear_order();
+        v
+    }
+
+    pub fn replace_attribute_at(
+        &mut self,
+        index: Option<usize>,
+        attr: Rc<XmlItem>,
+    ) -> Option<Rc<XmlItem>> {
+        self.context().touch();
+        if let Some(index) = index {
+            attr.set_parent_id(Some(self.id()));
+            attr.init_order_recursive();
+            let v = std::mem::replace(&mut self.attributes[index], attr);
+            v.set_parent_id(None);
             v.clear_order();
             Some(v)
         } else {
+            self.append_attribute(attr);
             None
         }
     }
 
+    pub fn strip_whitespace(&self) {
+        let mut stack = vec![];
+        self.strip_whitespace_children(&mut stack);
+        while let Some(element) = stack.pop() {
+            element.borrow().strip_whitespace_children(&mut stack);
+        }
+    }
+
     pub fn set_local_name(&mut self, local_name: &str) {
+        self.context().touch();
         self.local_name = local_name.to_string();
     }
 
+    pub fn set_prefix(&mut self, prefix: Option<&str>) {
+        self.context().touch();
+        self.prefix = prefix.map(|v| v.to_string());
+    }
+
+    fn attribute_index(&self, name: &str) -> Option<usize> {
+        self.attributes
+            .iter()
+            .position(|v| v.as_attribute().unwrap().borrow().local_name() == name)
+    }
+
     fn attributes_id(&self) -> Vec<XmlNode<XmlAttribute>> {
         if let Some(attlist) = self.declaration_att_list() {
             let ids = attlist
@@ -2431,6 +3031,7 @@ impl XmlElement {
     fn declaration_att_list(&self) -> Option<XmlNode<XmlDeclarationAttList>> {
         self.context
             .document()
+            .ok()?
             .borrow()
             .document_declaration()?
             .borrow()
@@ -2443,12 +3044,20 @@ impl XmlElement {
     fn find_nameapce_uri(&self, prefix: &str) -> error::Result<Option<NamespaceUri>> {
         for namespace in self.namespace_attributes().iter() {
             if prefix == namespace.borrow().local_name() {
-                return Ok(Some(NamespaceUri::try_from(&namespace)?));
+                let uri = NamespaceUri::try_from(&namespace)?;
+                // An empty default namespace declaration puts the element in no namespace.
+                if uri.value().is_empty()
+                    && (namespace.borrow().prefix().is_none()
+                        || self.context().prefix_undeclaration())
+                {
+                    return Ok(None);
+                }
+                return Ok(Some(uri));
             }
         }
 
         for namespace in self.in_scope_namespace()?.iter() {
-            if prefix == namespace.borrow().prefix().unwrap_or_default() {
+            if prefix == namespace.borrow().prefix().unwrap_or("xmlns") {
                 return Ok(Some(NamespaceUri::from(&namespace)));
             }
         }
@@ -2456,6 +3065,144 @@ impl XmlElement {
         Ok(None)
     }
 
+    fn is_text_content(&self) -> bool {
+        let children = self.child_items().borrow();
+        children.iter().all(|v| v.as_text().is_some())
+            || children.iter().any(|v| match v.as_ref() {
+                XmlItem::Text(v) => !v.borrow().text.chars().all(is_whitespace),
+                XmlItem::CData(_) | XmlItem::CharReference(_) | XmlItem::Unexpanded(_) => true,
+                _ => false,
+            })
+    }
+
+    fn is_space_preserved(&self) -> bool {
+        let space = self.attributes.iter().find_map(|v| {
+            let attr = v.as_attribute()?;
+            let attr = attr.borrow();
+            if attr.prefix() == Some("xml") && attr.local_name() == "space" {
+                attr.normalized_value().ok()
+            } else {
+                None
+            }
+        });
+
+        match space.as_deref() {
+            Some(v) => v == "preserve",
+            None => self
+                .parent()
+                .ok()
+                .and_then(|v| v.as_element())
+                .map(|v| v.borrow().is_space_preserved())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn strip_whitespace_children(&self, stack: &mut Vec<XmlNode<XmlElement>>) {
+        if !self.is_space_preserved() {
+            let ids: Vec<usize> = self
+                .child_items()
+                .borrow()
+                .iter()
+                .filter(|v| match v.as_text() {
+                    Some(v) => v.borrow().text.chars().all(is_whitespace),
+                    None => false,
+                })
+                .map(|v| v.id())
+                .collect();
+            for id in ids {
+                self.delete(id);
+            }
+        }
+
+        for child in self.child_items().borrow().iter() {
+            if let Some(element) = child.as_element() {
+                stack.push(element);
+            }
+        }
+    }
+
+    pub fn set_child_source(&self, source: Option<Rc<dyn ChildSource>>) {
+        *self.source.0.borrow_mut() = source;
+    }
+
+    fn child_items(&self) -> &Singleton<Vec<Rc<XmlItem>>> {
+        let source = self.source.0.borrow_mut().take();
+        if let Some(source) = source {
+            for child in source.load(self) {
+                // Appending cannot fail, the loaded children are detached content nodes.
+                let _ = self.append(child);
+            }
+        }
+        &self.children
+    }
+
+    /// Writes the start tag at `indent`, and returns whether the children still have to be
+    /// written one per line.
+    ///
+    /// Text content and preserved spaces are written inline, together with the end tag.
+    fn write_indented_start_tag(&self, indent: usize, f: &mut impl io::Write) -> io::Result<bool> {
+        let mut tag = " ".repeat(indent);
+        // Writing to a `String` cannot fail.
+        let _ = self.write_start_tag(&mut tag);
+        let children = self.child_items().borrow();
+        let indented =
+            !children.is_empty() && !self.is_text_content() && !self.is_space_preserved();
+        if !indented && !children.is_empty() {
+            for child in children.iter() {
+                let _ = fmt::Write::write_fmt(&mut tag, format_args!("{}", child));
+            }
+            let _ = self.write_end_tag(&mut tag);
+        }
+        f.write_all(tag.as_bytes())?;
+        Ok(indented)
+    }
+
+    fn write_indented_end_tag(&self, indent: usize, f: &mut impl io::Write) -> io::Result<()> {
+        let mut tag = " ".repeat(indent);
+        let _ = self.write_end_tag(&mut tag);
+        f.write_all(tag.as_bytes())
+    }
+
+    fn write_end_tag(&self, f: &mut impl fmt::Write) -> Result<(), fmt::Error> {
+        write!(f, "</")?;
+        if let Some(prefix) = self.prefix.as_deref() {
+            write!(f, "{}:", prefix)?;
+        }
+        write!(f, "{}>", self.local_name.as_str())
+    }
+
+    fn write_start_tag(&self, f: &mut impl fmt::Write) -> Result<(), fmt::Error> {
+        write!(f, "<")?;
+        if let Some(prefix) = self.prefix.as_deref() {
+            write!(f, "{}:", prefix)?;
+        }
+        write!(f, "{}", self.local_name.as_str())?;
+
+        let spaces = self.preserved_spaces();
+        for (i, attr) in self.attributes.iter().enumerate() {
+            let space = spaces.map(|v| v[i].as_str()).unwrap_or(" ");
+            write!(f, "{}{}", space, attr)?;
+        }
+
+        if self.child_items().borrow().is_empty() {
+            let space = spaces.and_then(|v| v.last()).map(|v| v.as_str());
+            write!(f, "{}/>", space.unwrap_or(" "))
+        } else {
+            if let Some(space) = spaces.and_then(|v| v.last()) {
+                write!(f, "{}", space)?;
+            }
+            write!(f, ">")
+        }
+    }
+
+    fn preserved_spaces(&self) -> Option<&[String]> {
+        if self.context().spaces_preserved() && self.spaces.len() == self.attributes.len() + 1 {
+            Some(self.spaces.as_slice())
+        } else {
+            None
+        }
+    }
+
     fn push_attribute(&mut self, attr: Rc<XmlItem>) {
         self.attributes.push(attr);
     }
@@ -2609,6 +3356,11 @@ impl XmlEntity {
         self.values.as_deref()
     }
 
+    pub fn set_values(&mut self, values: Option<Vec<XmlEntityValue>>) {
+        self.context().touch();
+        self.values = values;
+    }
+
     pub fn system_identifier(&self) -> Option<&str> {
         self.system_identifier.as_deref()
     }
@@ -3014,6 +3766,57 @@ impl XmlItem {
         }
     }
 
+    pub fn document_position(&self) -> Vec<usize> {
+        let context = self.context();
+        let mut position = vec![];
+        let mut id = self.id();
+        let mut parent_id = self.parent_id();
+        if self.as_document_type().is_some() {
+            parent_id = Some(context.document_node_id);
+        }
+
+        while let Some(parent) = parent_id.and_then(|v| context.node(v)) {
+            position.push(parent.child_position(id).unwrap_or_default());
+            id = parent.id();
+            parent_id = parent.parent_id();
+            if parent.as_document_type().is_some() {
+                parent_id = Some(context.document_node_id);
+            }
+        }
+
+        if id != context.document_node_id {
+            position.push(id);
+        } else {
+            position.push(0);
+        }
+
+        position.reverse();
+        position
+    }
+
+    fn child_position(&self, id: usize) -> Option<usize> {
+        match self {
+            XmlItem::Attribute(v) => v.borrow().child_index(id),
+            XmlItem::Document(v) => v.borrow().child_index(id),
+            XmlItem::DocumentType(v) => v
+                .borrow()
+                .children
+                .borrow()
+                .iter()
+                .position(|v| v.id() == id),
+            XmlItem::Element(v) => {
+                let element = v.borrow();
+                if let Some(index) = element.attributes.iter().position(|v| v.id() == id) {
+                    Some(index)
+                } else {
+                    let index = element.child_index(id)?;
+                    Some(element.attributes.len() + index)
+                }
+            }
+            _ => None,
+        }
+    }
+
     fn context(&self) -> Context {
         match self {
             XmlItem::Attribute(v) => v.borrow().context().clone(),
@@ -3074,6 +3877,13 @@ impl XmlItem {
         }
     }
 
+    fn register(item: &Rc<XmlItem>) {
+        let context = item.context();
+        if context.node(item.id()).is_none() {
+            context.add_item(item);
+        }
+    }
+
     fn remove_from_parent(&self) {
         if let Some(parent_id) = self.parent_id() {
             if let Some(parent) = self.context().node(parent_id) {
@@ -3156,6 +3966,73 @@ impl XmlItem {
 
 // -----------------------------------------------------------------------------------------------
 
+// Refers to the node itself rather than to the `XmlItem` holding it, so that a node is
+// found as long as it is alive, even after the item it was registered with is dropped.
+enum WeakItem {
+    Attribute(Weak<RefCell<XmlAttribute>>),
+    CData(Weak<RefCell<XmlCData>>),
+    CharReference(Weak<RefCell<XmlCharReference>>),
+    Comment(Weak<RefCell<XmlComment>>),
+    DeclarationAttList(Weak<RefCell<XmlDeclarationAttList>>),
+    Document(Weak<RefCell<XmlDocument>>),
+    DocumentType(Weak<RefCell<XmlDocumentTypeDeclaration>>),
+    Element(Weak<RefCell<XmlElement>>),
+    Entity(Weak<RefCell<XmlEntity>>),
+    Namespace(Weak<RefCell<XmlNamespace>>),
+    Notation(Weak<RefCell<XmlNotation>>),
+    PI(Weak<RefCell<XmlProcessingInstruction>>),
+    Text(Weak<RefCell<XmlText>>),
+    Unexpanded(Weak<RefCell<XmlUnexpandedEntityReference>>),
+    Unparsed(Weak<RefCell<XmlUnparsedEntity>>),
+}
+
+impl WeakItem {
+    fn upgrade(&self) -> Option<Rc<XmlItem>> {
+        let item = match self {
+            WeakItem::Attribute(v) => XmlItem::Attribute(v.upgrade()?),
+            WeakItem::CData(v) => XmlItem::CData(v.upgrade()?),
+            WeakItem::CharReference(v) => XmlItem::CharReference(v.upgrade()?),
+            WeakItem::Comment(v) => XmlItem::Comment(v.upgrade()?),
+            WeakItem::DeclarationAttList(v) => XmlItem::DeclarationAttList(v.upgrade()?),
+            WeakItem::Document(v) => XmlItem::Document(v.upgrade()?),
+            WeakItem::DocumentType(v) => XmlItem::DocumentType(v.upgrade()?),
+            WeakItem::Element(v) => XmlItem::Element(v.upgrade()?),
+            WeakItem::Entity(v) => XmlItem::Entity(v.upgrade()?),
+            WeakItem::Namespace(v) => XmlItem::Namespace(v.upgrade()?),
+            WeakItem::Notation(v) => XmlItem::Notation(v.upgrade()?),
+            WeakItem::PI(v) => XmlItem::PI(v.upgrade()?),
+            WeakItem::Text(v) => XmlItem::Text(v.upgrade()?),
+            WeakItem::Unexpanded(v) => XmlItem::Unexpanded(v.upgrade()?),
+            WeakItem::Unparsed(v) => XmlItem::Unparsed(v.upgrade()?),
+        };
+        Some(Rc::new(item))
+    }
+}
+
+impl From<&XmlItem> for WeakItem {
+    fn from(value: &XmlItem) -> Self {
+        match value {
+            XmlItem::Attribute(v) => WeakItem::Attribute(Rc::downgrade(v)),
+            XmlItem::CData(v) => WeakItem::CData(Rc::downgrade(v)),
+            XmlItem::CharReference(v) => WeakItem::CharReference(Rc::downgrade(v)),
+            XmlItem::Comment(v) => WeakItem::Comment(Rc::downgrade(v)),
+            XmlItem::DeclarationAttList(v) => WeakItem::DeclarationAttList(Rc::downgrade(v)),
+            XmlItem::Document(v) => WeakItem::Document(Rc::downgrade(v)),
+            XmlItem::DocumentType(v) => WeakItem::DocumentType(Rc::downgrade(v)),
+            XmlItem::Element(v) => WeakItem::Element(Rc::downgrade(v)),
+            XmlItem::Entity(v) => WeakItem::Entity(Rc::downgrade(v)),
+            XmlItem::Namespace(v) => WeakItem::Namespace(Rc::downgrade(v)),
+            XmlItem::Notation(v) => WeakItem::Notation(Rc::downgrade(v)),
+            XmlItem::PI(v) => WeakItem::PI(Rc::downgrade(v)),
+            XmlItem::Text(v) => WeakItem::Text(Rc::downgrade(v)),
+            XmlItem::Unexpanded(v) => WeakItem::Unexpanded(Rc::downgrade(v)),
+            XmlItem::Unparsed(v) => WeakItem::Unparsed(Rc::downgrade(v)),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct XmlNamespace {
     prefix: Option<String>,
@@ -3456,7 +4333,43 @@ impl XmlProcessingInstruction {
         }
     }
 
+    pub fn pseudo_attributes(&self) -> error::Result<Vec<(String, String)>> {
+        let content = self.content();
+        let (rest, atts) = xml_parser::pseudo_atts(content)?;
+        if !rest.is_empty() {
+            return Err(error::Error::InvalidData(rest.to_string()));
+        }
+
+        let mut attributes: Vec<(String, String)> = vec![];
+        for att in atts {
+            if attributes.iter().any(|(n, _)| n == att.name) {
+                return Err(error::Error::InvalidData(att.name.to_string()));
+            }
+
+            let mut value = String::new();
+            for v in att.value {
+                match v {
+                    parser::AttributeValue::Reference(parser::Reference::Character(c, 10)) => {
+                        value.push(char_from_char10(c)?)
+                    }
+                    parser::AttributeValue::Reference(parser::Reference::Character(c, _)) => {
+                        value.push(char_from_char16(c)?)
+                    }
+                    parser::AttributeValue::Reference(parser::Reference::Entity(e)) => {
+                        value.push_str(predefined_entity(e)?)
+                    }
+                    parser::AttributeValue::Text(t) => value.push_str(t),
+                }
+            }
+
+            attributes.push((att.name.to_string(), value));
+        }
+
+        Ok(attributes)
+    }
+
     pub fn set_content(&mut self, content: &str) -> error::Result<()> {
+        self.context().touch();
         let xml = format!("<?{} {}?>", self.target, content);
         let (rest, tree) = xml_parser::pi(xml.as_str())?;
         if rest.is_empty() {
@@ -3524,7 +4437,7 @@ impl PartialEq<XmlText> for XmlText {
 
 impl fmt::Display for XmlText {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "{}", self.text.as_str())
+        write!(f, "{}", escape_text(self.text.as_str()))
     }
 }
 
@@ -3548,13 +4461,22 @@ impl XmlText {
     }
 
     pub fn delete(&mut self, offset: usize, count: usize) {
+        self.context().touch();
         self.text = delete_char_range(self.text.as_str(), offset, count);
     }
 
     pub fn insert(&mut self, offset: usize, text: &str) -> error::Result<()> {
+        self.context().touch();
         fn check(value: &str) -> error::Result<bool> {
-            let (rest, content) = xml_parser::content(value)?;
-            Ok(rest.is_empty() && content.children.is_empty())
+            let value = escape_text(value);
+            let (rest, content) = xml_parser::content(value.as_str())?;
+            Ok(rest.is_empty()
+                && content.children.iter().all(|v| {
+                    matches!(
+                        v.child,
+                        parser::Contents::Reference(parser::Reference::Entity(_))
+                    )
+                }))
         }
 
         self.text = insert_char_at(self.text.as_str(), offset, text, check)?;
@@ -3570,6 +4492,7 @@ impl XmlText {
     }
 
     pub fn split_at(&mut self, offset: usize) -> XmlNode<Self> {
+        self.context().touch();
         let mut chars = self.text.chars().collect::<Vec<char>>();
         let at = if offset < chars.len() {
             offset
@@ -3831,6 +4754,10 @@ impl TryFrom<&XmlNode<XmlAttribute>> for NamespaceUri {
 }
 
 impl NamespaceUri {
+    pub fn xml() -> Self {
+        NamespaceUri::from("http://www.w3.org/XML/1998/namespace")
+    }
+
     pub fn xmlns() -> Self {
         NamespaceUri::from("http://www.w3.org/2000/xmlns/")
     }
@@ -3985,10 +4912,20 @@ where
 pub struct Context {
     info: Singleton<ContextInfo>,
     idm: Singleton<IdManager>,
-    document: Rc<XmlItem>,
+    document: Weak<RefCell<XmlDocument>>,
+    document_node_id: usize,
     ordering: Singleton<DocumentOrder>,
-    id_map: Singleton<HashMap<usize, Weak<XmlItem>>>,
-    text_expanded: bool,
+    id_map: Singleton<HashMap<usize, WeakItem>>,
+    spans: Singleton<HashMap<usize, SourceSpan>>,
+    source: Singleton<Option<Rc<str>>>,
+    digests: Singleton<HashMap<usize, u64>>,
+    types: Singleton<HashMap<usize, String>>,
+    user_data: Singleton<HashMap<usize, UserDataEntries>>,
+    element_ids: Singleton<Option<(usize, HashMap<String, usize>)>>,
+    frozen: Singleton<Option<(usize, Rc<dyn Any>)>>,
+    flags: Singleton<ContextFlags>,
+    line_ending: Option<String>,
+    max_depth: Option<usize>,
 }
 
 impl PartialEq<Context> for Context {
@@ -4004,41 +4941,47 @@ impl fmt::Debug for Context {
 }
 
 impl Context {
-    fn new(value: XmlNode<XmlDocument>) -> Self {
-        let idm = singleton(IdManager::default());
+    fn new(value: XmlNode<XmlDocument>, tables: NodeTables) -> Self {
+        let idm = singleton(IdManager::new());
         let id = idm.borrow_mut().next();
 
         let info = singleton(ContextInfo::from(id));
 
-        let document = Rc::new(value.into());
-
-        let id_map = singleton(HashMap::new());
-        id_map
-            .borrow_mut()
-            .insert(info.borrow().id, Rc::downgrade(&document));
-
         Context {
             info,
             idm,
-            document,
-            ordering: singleton(DocumentOrder::default()),
-            id_map,
-            text_expanded: false,
+            document: Rc::downgrade(&value),
+            document_node_id: id,
+            ordering: singleton(DocumentOrder {
+                order: tables.order,
+                ..Default::default()
+            }),
+            id_map: singleton(tables.id_map),
+            spans: singleton(tables.spans),
+            source: singleton(None),
+            digests: singleton(HashMap::new()),
+            types: singleton(HashMap::new()),
+            user_data: singleton(HashMap::new()),
+            element_ids: singleton(None),
+            frozen: singleton(None),
+            flags: singleton(ContextFlags::default()),
+            line_ending: None,
+            max_depth: None,
         }
     }
 
     fn add_item(&self, node: &Rc<XmlItem>) {
         self.id_map
             .borrow_mut()
-            .insert(self.info.borrow().id, Rc::downgrade(node));
+            .insert(self.info.borrow().id, WeakItem::from(node.as_ref()));
     }
 
-    fn document(&self) -> XmlNode<XmlDocument> {
-        self.document.as_document().unwrap()
+    fn document(&self) -> error::Result<XmlNode<XmlDocument>> {
+        self.document.upgrade().ok_or(error::Error::IsolatedNode)
     }
 
     pub fn entity(&self, name: &str) -> error::Result<XmlNode<XmlEntity>> {
-        if let Some(declaration) = self.document().borrow().document_declaration() {
+        if let Some(declaration) = self.document()?.borrow().document_declaration() {
             if let Some(v) = declaration
                 .borrow()
                 .entities()
@@ -4050,98 +4993,435 @@ impl Context {
             }
         }
 
-        match name {
-            "lt" => Ok(node(XmlEntity::from(("lt", "<", self)))),
-            "gt" => Ok(node(XmlEntity::from(("gt", ">", self)))),
-            "amp" => Ok(node(XmlEntity::from(("amp", "&", self)))),
-            "apos" => Ok(node(XmlEntity::from(("apos", "'", self)))),
-            "quot" => Ok(node(XmlEntity::from(("quot", "\"", self)))),
-            _ => Err(error::Error::NotFoundReference(name.to_string())),
-        }
+        let value = predefined_entity(name)?;
+        Ok(node(XmlEntity::from((name, value, self))))
     }
 
-    pub fn set_text_expanded(&mut self, value: bool) {
-        self.text_expanded = value;
+    pub fn document_id(&self) -> usize {
+        self.idm.borrow().document
     }
 
-    pub fn text_expanded(&self) -> bool {
-        self.text_expanded
+    pub fn generation(&self) -> usize {
+        self.ordering.borrow().generation
     }
 
-    fn next(&self) -> Context {
-        let info = singleton(ContextInfo::from(self.idm.borrow_mut().next()));
+    /// Discards caches derived from the tree, e.g. after a node is renamed in place.
+    pub fn invalidate(&self) {
+        self.ordering.borrow_mut().generation += 1;
+    }
 
-        Context {
-            info,
-            idm: self.idm.clone(),
-            document: self.document.clone(),
-            ordering: self.ordering.clone(),
-            id_map: self.id_map.clone(),
-            text_expanded: self.text_expanded,
-        }
+    /// Counts every change to the document, including those to character data and values
+    /// that leave the `generation` as is.
+    pub fn revision(&self) -> usize {
+        let ordering = self.ordering.borrow();
+        ordering.generation + ordering.edits
     }
 
-    fn node(&self, id: usize) -> Option<Rc<XmlItem>> {
-        self.id_map.borrow().get(&id).and_then(|v| v.upgrade())
+    fn touch(&self) {
+        self.ordering.borrow_mut().edits += 1;
     }
 
-    fn zero(&self) -> Context {
-        Context {
-            info: singleton(ContextInfo::default()),
-            idm: self.idm.clone(),
-            document: self.document.clone(),
-            ordering: self.ordering.clone(),
-            id_map: self.id_map.clone(),
-            text_expanded: self.text_expanded,
+    /// Returns the value stored by `set_frozen`, unless the document changed since.
+    pub fn frozen(&self) -> Option<Rc<dyn Any>> {
+        let revision = self.revision();
+        match &*self.frozen.borrow() {
+            Some((r, value)) if *r == revision => Some(value.clone()),
+            _ => None,
         }
     }
-}
-
-// -----------------------------------------------------------------------------------------------
-
-#[derive(Debug, Default)]
-struct IdManager {
-    number: usize,
-}
 
-impl IdManager {
-    fn next(&mut self) -> usize {
-        self.number += 1;
-        self.number
+    /// Keeps a copy of the whole document, valid until the next change.
+    pub fn set_frozen(&self, value: Option<Rc<dyn Any>>) {
+        *self.frozen.borrow_mut() = value.map(|v| (self.revision(), v));
     }
-}
 
-// -----------------------------------------------------------------------------------------------
+    pub fn span(&self, id: usize) -> Option<SourceSpan> {
+        self.spans.borrow().get(&id).cloned()
+    }
 
-#[derive(Default)]
-struct ContextInfo {
-    id: usize,
-    order_cache: usize,
-    order_version: usize,
-}
+    pub fn set_span(&self, id: usize, value: Option<SourceSpan>) {
+        self.touch();
+        match value {
+            Some(v) => self.spans.borrow_mut().insert(id, v),
+            None => self.spans.borrow_mut().remove(&id),
+        };
+    }
 
-impl From<usize> for ContextInfo {
-    fn from(value: usize) -> Self {
-        ContextInfo {
-            id: value,
-            order_cache: 0,
-            order_version: 0,
+    pub fn map_spans(&self, f: impl Fn(usize) -> usize) {
+        for span in self.spans.borrow_mut().values_mut() {
+            span.range = f(span.range.start)..f(span.range.end);
+            span.name = span.name.as_ref().map(|v| f(v.start)..f(v.end));
         }
     }
-}
 
-impl fmt::Debug for ContextInfo {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "ContextInfo({:?},{:?})", self.id, self.order_cache)
+    pub fn set_source(&self, value: Option<&str>) {
+        self.touch();
+        *self.source.borrow_mut() = value.map(Rc::from);
     }
-}
 
-// -----------------------------------------------------------------------------------------------
+    pub fn source(&self) -> Option<Rc<str>> {
+        self.source.borrow().clone()
+    }
 
-#[derive(Debug, Default)]
-struct DocumentOrder {
+    pub fn digest(&self, id: usize) -> Option<u64> {
+        self.digests.borrow().get(&id).copied()
+    }
+
+    pub fn set_type_annotation(&self, id: usize, value: Option<&str>) {
+        self.touch();
+        match value {
+            Some(v) => self.types.borrow_mut().insert(id, v.to_string()),
+            None => self.types.borrow_mut().remove(&id),
+        };
+    }
+
+    pub fn type_annotation(&self, id: usize) -> Option<String> {
+        self.types.borrow().get(&id).cloned()
+    }
+
+    pub fn set_user_data(
+        &self,
+        id: usize,
+        key: &str,
+        value: Option<Rc<dyn Any>>,
+    ) -> Option<Rc<dyn Any>> {
+        self.touch();
+        let mut user_data = self.user_data.borrow_mut();
+        let entries = user_data.entry(id).or_default();
+        let old = entries
+            .iter()
+            .position(|(k, _)| k == key)
+            .map(|i| entries.remove(i).1);
+        if let Some(value) = value {
+            entries.push((key.to_string(), value));
+        }
+        if entries.is_empty() {
+            user_data.remove(&id);
+        }
+        old
+    }
+
+    pub fn user_data(&self, id: usize, key: &str) -> Option<Rc<dyn Any>> {
+        let user_data = self.user_data.borrow();
+        let (_, value) = user_data.get(&id)?.iter().find(|(k, _)| k == key)?;
+        Some(value.clone())
+    }
+
+    pub fn user_data_entries(&self, id: usize) -> UserDataEntries {
+        self.user_data
+            .borrow()
+            .get(&id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn set_text_expanded(&mut self, value: bool) {
+        self.touch();
+        self.flags.borrow_mut().text_expanded = value;
+    }
+
+    pub fn text_expanded(&self) -> bool {
+        self.flags.borrow().text_expanded
+    }
+
+    pub fn set_spaces_preserved(&mut self, value: bool) {
+        self.touch();
+        self.flags.borrow_mut().spaces_preserved = value;
+    }
+
+    pub fn spaces_preserved(&self) -> bool {
+        self.flags.borrow().spaces_preserved
+    }
+
+    pub fn set_line_ending(&mut self, value: Option<&str>) {
+        self.line_ending = value.map(|v| v.to_string());
+    }
+
+    pub fn line_ending(&self) -> Option<&str> {
+        self.line_ending.as_deref()
+    }
+
+    pub fn set_max_depth(&mut self, value: Option<usize>) {
+        self.max_depth = value;
+    }
+
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    pub fn set_namespace_aware(&mut self, value: bool) {
+        self.touch();
+        self.flags.borrow_mut().namespace_aware = value;
+    }
+
+    pub fn namespace_aware(&self) -> bool {
+        self.flags.borrow().namespace_aware
+    }
+
+    pub fn set_attributes_defaulted(&mut self, value: bool) {
+        self.touch();
+        self.flags.borrow_mut().attributes_defaulted = value;
+    }
+
+    pub fn attributes_defaulted(&self) -> bool {
+        self.flags.borrow().attributes_defaulted
+    }
+
+    pub fn set_prefix_undeclaration(&mut self, value: bool) {
+        self.touch();
+        self.flags.borrow_mut().prefix_undeclaration = value;
+    }
+
+    pub fn prefix_undeclaration(&self) -> bool {
+        self.flags.borrow().prefix_undeclaration
+    }
+
+    fn next(&self) -> Context {
+        let info = singleton(ContextInfo::from(self.idm.borrow_mut().next()));
+
+        Context {
+            info,
+            idm: self.idm.clone(),
+            document: self.document.clone(),
+            document_node_id: self.document_node_id,
+            ordering: self.ordering.clone(),
+            id_map: self.id_map.clone(),
+            spans: self.spans.clone(),
+            source: self.source.clone(),
+            digests: self.digests.clone(),
+            types: self.types.clone(),
+            user_data: self.user_data.clone(),
+            element_ids: self.element_ids.clone(),
+            frozen: self.frozen.clone(),
+            flags: self.flags.clone(),
+            line_ending: self.line_ending.clone(),
+            max_depth: self.max_depth,
+        }
+    }
+
+    fn node(&self, id: usize) -> Option<Rc<XmlItem>> {
+        if id == self.document_node_id {
+            return self.document.upgrade().map(|v| Rc::new(v.into()));
+        }
+        self.id_map.borrow().get(&id).and_then(|v| v.upgrade())
+    }
+
+    fn add_span(&self, id: usize, range: Range<usize>, name: Option<Range<usize>>) {
+        self.spans
+            .borrow_mut()
+            .insert(id, SourceSpan { range, name });
+    }
+
+    fn zero(&self) -> Context {
+        Context {
+            info: singleton(ContextInfo::default()),
+            idm: self.idm.clone(),
+            document: self.document.clone(),
+            document_node_id: self.document_node_id,
+            ordering: self.ordering.clone(),
+            id_map: self.id_map.clone(),
+            spans: self.spans.clone(),
+            source: self.source.clone(),
+            digests: self.digests.clone(),
+            types: self.types.clone(),
+            user_data: self.user_data.clone(),
+            element_ids: self.element_ids.clone(),
+            frozen: self.frozen.clone(),
+            flags: self.flags.clone(),
+            line_ending: self.line_ending.clone(),
+            max_depth: self.max_depth,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceSpan {
+    range: Range<usize>,
+    name: Option<Range<usize>>,
+}
+
+impl SourceSpan {
+    pub fn start(&self) -> usize {
+        self.range.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.range.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    pub fn name(&self) -> Option<Range<usize>> {
+        self.name.clone()
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        self.range.contains(&offset)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Debug)]
+struct IdManager {
+    document: usize,
+    number: usize,
+}
+
+impl IdManager {
+    fn new() -> Self {
+        IdManager {
+            document: DOCUMENT_ID.fetch_add(1, Ordering::Relaxed) + 1,
+            number: 0,
+        }
+    }
+
+    fn next(&mut self) -> usize {
+        self.number += 1;
+        self.number
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+// Shared by every node of a document, so that a node sees the settings of its document even
+// after the document itself is dropped.
+#[derive(Clone, Copy, Debug)]
+struct ContextFlags {
+    text_expanded: bool,
+    spaces_preserved: bool,
+    namespace_aware: bool,
+    attributes_defaulted: bool,
+    prefix_undeclaration: bool,
+}
+
+impl Default for ContextFlags {
+    fn default() -> Self {
+        ContextFlags {
+            text_expanded: false,
+            spaces_preserved: false,
+            namespace_aware: true,
+            attributes_defaulted: true,
+            prefix_undeclaration: true,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+const NODE_POOL_SIZE: usize = 4;
+
+/// Node tables of recycled documents, reused by the documents built next.
+///
+/// The tables index every node of a document, so reusing them saves growing them again for
+/// each document. A clone starts empty.
+#[derive(Default)]
+pub struct NodePool {
+    tables: Vec<NodeTables>,
+}
+
+impl Clone for NodePool {
+    fn clone(&self) -> Self {
+        NodePool::default()
+    }
+}
+
+impl fmt::Debug for NodePool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "NodePool({})", self.tables.len())
+    }
+}
+
+impl PartialEq<NodePool> for NodePool {
+    fn eq(&self, other: &NodePool) -> bool {
+        self.tables.len() == other.tables.len()
+    }
+}
+
+impl NodePool {
+    /// Drops `document` and takes back its tables if none of its nodes is alive any more.
+    pub fn recycle(&mut self, document: XmlNode<XmlDocument>) -> bool {
+        let context = document.borrow().context().clone();
+        drop(document);
+
+        // Every live node holds the tables through its context.
+        if Rc::strong_count(&context.id_map) > 1 || self.tables.len() >= NODE_POOL_SIZE {
+            return false;
+        }
+
+        let mut tables = NodeTables {
+            id_map: std::mem::take(&mut *context.id_map.borrow_mut()),
+            order: std::mem::take(&mut context.ordering.borrow_mut().order),
+            spans: std::mem::take(&mut *context.spans.borrow_mut()),
+        };
+        tables.id_map.clear();
+        tables.order.clear();
+        tables.spans.clear();
+        self.tables.push(tables);
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.tables.clear();
+    }
+
+    fn take(&mut self) -> NodeTables {
+        self.tables.pop().unwrap_or_default()
+    }
+}
+
+#[derive(Default)]
+struct NodeTables {
+    id_map: HashMap<usize, WeakItem>,
+    order: Vec<Weak<RefCell<ContextInfo>>>,
+    spans: HashMap<usize, SourceSpan>,
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Default)]
+struct ContextInfo {
+    id: usize,
+    order_cache: usize,
+    order_version: usize,
+}
+
+impl From<usize> for ContextInfo {
+    fn from(value: usize) -> Self {
+        ContextInfo {
+            id: value,
+            order_cache: 0,
+            order_version: 0,
+        }
+    }
+}
+
+impl fmt::Debug for ContextInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "ContextInfo({:?},{:?})", self.id, self.order_cache)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Debug, Default)]
+struct DocumentOrder {
     order: Vec<Weak<RefCell<ContextInfo>>>,
     version: usize,
+    generation: usize,
+    edits: usize,
 }
 
 impl DocumentOrder {
@@ -4160,6 +5440,7 @@ impl DocumentOrder {
         if order > 0 {
             self.order.insert(order, Rc::downgrade(info));
             self.version += 1;
+            self.generation += 1;
             Some(self.version)
         } else {
             None
@@ -4173,6 +5454,7 @@ impl DocumentOrder {
         if order > 0 {
             self.order.insert(order - 1, Rc::downgrade(info));
             self.version += 1;
+            self.generation += 1;
             Some(self.version)
         } else {
             None
@@ -4181,6 +5463,7 @@ impl DocumentOrder {
 
     fn push(&mut self, info: &Singleton<ContextInfo>) -> (usize, usize) {
         self.order.push(Rc::downgrade(info));
+        self.generation += 1;
         (self.order.len(), self.version)
     }
 
@@ -4189,6 +5472,7 @@ impl DocumentOrder {
         if order > 0 {
             self.order.remove(order - 1);
             self.version += 1;
+            self.generation += 1;
             Some(self.version)
         } else {
             None
@@ -4198,6 +5482,40 @@ impl DocumentOrder {
 
 // -----------------------------------------------------------------------------------------------
 
+struct LineEnding<'a, W: fmt::Write> {
+    inner: &'a mut W,
+    line_ending: &'a str,
+    cr: bool,
+}
+
+impl<'a, W: fmt::Write> LineEnding<'a, W> {
+    fn new(inner: &'a mut W, line_ending: &'a str) -> Self {
+        LineEnding {
+            inner,
+            line_ending,
+            cr: false,
+        }
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for LineEnding<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if c == '\n' && !self.cr {
+                self.inner.write_str(self.line_ending)?;
+            } else {
+                self.inner.write_char(c)?;
+            }
+
+            self.cr = c == '\r';
+        }
+
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
 fn attribute_name(name: &parser::AttributeName) -> (String, Option<String>) {
     match name {
         parser::AttributeName::DefaultNamespace => ("xmlns".to_string(), None),
@@ -4285,6 +5603,17 @@ fn escape(value: &str) -> String {
     }
 }
 
+fn escape_cdata(value: &str) -> String {
+    value.replace("]]>", "]]]]><![CDATA[>")
+}
+
+fn escape_text(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace("]]>", "]]&gt;")
+}
+
 fn external_id(id: &parser::ExternalId) -> (String, Option<String>) {
     match id {
         parser::ExternalId::Public(p, s) => (s.to_string(), Some(p.to_string())),
@@ -4308,78 +5637,487 @@ where
         let mut tail = chars.split_off(index);
         let mut middle = new.chars().collect::<Vec<char>>();
 
-        chars.append(&mut middle);
-        chars.append(&mut tail);
+        chars.append(&mut middle);
+        chars.append(&mut tail);
+
+        Ok(chars.iter().collect())
+    } else {
+        Err(error::Error::InvalidData(new.to_string()))
+    }
+}
+
+fn is_whitespace(value: char) -> bool {
+    matches!(value, ' ' | '\t' | '\r' | '\n')
+}
+
+fn node<T>(value: T) -> XmlNode<T> {
+    Rc::new(RefCell::new(value))
+}
+
+fn normalize_ws(value: &str) -> String {
+    let mut v = value.to_string();
+    unsafe {
+        v = v.replace(char::from_u32_unchecked(0x20), " ");
+        v = v.replace(char::from_u32_unchecked(0x0D), " ");
+        v = v.replace(char::from_u32_unchecked(0x0A), " ");
+        v = v.replace(char::from_u32_unchecked(0x09), " ");
+    }
+    v
+}
+
+fn notation(context: &Context, name: &str) -> Value<Option<XmlNode<XmlNotation>>> {
+    let notations = context.document().ok().and_then(|v| v.borrow().notations());
+    match notations {
+        Some(notations) => {
+            let mut matches = notations
+                .iter()
+                .filter(|n| n.borrow().name() == name)
+                .collect::<Vec<XmlNode<XmlNotation>>>();
+            match matches.len() {
+                1 => Value::V(Some(matches.remove(0))),
+                _ => Value::V(None),
+            }
+        }
+        _ => Value::V(None),
+    }
+}
+
+fn predefined_entity(name: &str) -> error::Result<&'static str> {
+    match name {
+        "lt" => Ok("<"),
+        "gt" => Ok(">"),
+        "amp" => Ok("&"),
+        "apos" => Ok("'"),
+        "quot" => Ok("\""),
+        _ => Err(error::Error::NotFoundReference(name.to_string())),
+    }
+}
+
+fn qname(name: &xml_nom::model::QName<'_>) -> (String, Option<String>) {
+    match name {
+        xml_nom::model::QName::Prefixed(n) => {
+            (n.local_part.to_string(), Some(n.prefix.to_string()))
+        }
+        xml_nom::model::QName::Unprefixed(n) => (n.to_string(), None),
+    }
+}
+
+fn qualified_name(name: &xml_nom::model::QName<'_>) -> String {
+    match name {
+        xml_nom::model::QName::Prefixed(n) => format!("{}:{}", n.prefix, n.local_part),
+        xml_nom::model::QName::Unprefixed(n) => n.to_string(),
+    }
+}
+
+fn retrieve_element_by_id(
+    element: &XmlNode<XmlElement>,
+    names: &[&str],
+) -> error::Result<Vec<XmlNode<XmlElement>>> {
+    let mut elements = vec![];
+    for id in element.borrow().attributes_id() {
+        let value = id.borrow().normalized_value()?;
+        if names.iter().any(|n| value == *n) {
+            elements.push(element.clone());
+        }
+    }
+
+    for child in element.borrow().children().iter() {
+        if let Some(child_element) = child.as_element() {
+            elements.append(&mut retrieve_element_by_id(&child_element, names)?);
+        }
+    }
+
+    Ok(elements)
+}
+
+fn collect_element_ids(
+    element: &XmlNode<XmlElement>,
+    ids: &mut HashMap<String, usize>,
+) -> error::Result<()> {
+    for id in element.borrow().attributes_id() {
+        let value = id.borrow().normalized_value()?;
+        ids.entry(value).or_insert(element.borrow().id());
+    }
+
+    for child in element.borrow().children().iter() {
+        if let Some(child_element) = child.as_element() {
+            collect_element_ids(&child_element, ids)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn end_of_comment(source: &str, value: &parser::Comment) -> usize {
+    source_offset(source, value.value) + value.value.len() + 3
+}
+
+fn digest(item: &Rc<XmlItem>, digests: &mut HashMap<usize, u64>) -> u64 {
+    // Elements are hashed after all of their descendants, in reverse document order, so that a
+    // deep tree does not need recursion.
+    let mut elements = vec![];
+    let mut stack = vec![item.clone()];
+    while let Some(item) = stack.pop() {
+        match item.as_element() {
+            Some(element) => {
+                stack.extend(element.borrow().attributes.iter().cloned());
+                stack.extend(element.borrow().child_items().borrow().iter().cloned());
+                elements.push(element);
+            }
+            None => {
+                let mut hasher = DefaultHasher::new();
+                item.to_string().hash(&mut hasher);
+                digests.insert(item.id(), hasher.finish());
+            }
+        }
+    }
+
+    for element in elements.iter().rev() {
+        let element = element.borrow();
+        let mut hasher = DefaultHasher::new();
+        element.prefix.hash(&mut hasher);
+        element.local_name.hash(&mut hasher);
+        for attribute in element.attributes.iter() {
+            digests.get(&attribute.id()).hash(&mut hasher);
+        }
+        for child in element.child_items().borrow().iter() {
+            digests.get(&child.id()).hash(&mut hasher);
+        }
+        digests.insert(element.id(), hasher.finish());
+    }
+
+    digests.get(&item.id()).copied().unwrap_or_default()
+}
+
+fn digest_str(value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn end_of_doctype(source: &str, start: usize) -> usize {
+    let mut quote = None;
+    for (i, c) in source[start..].char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return start + i + 1,
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    source.len()
+}
+
+fn end_of_pi(source: &str, value: &parser::PI) -> usize {
+    let tail = value.value.unwrap_or(value.target);
+    let offset = source_offset(source, tail) + tail.len();
+    offset + source[offset..].find("?>").unwrap_or_default() + 2
+}
+
+fn name_range(source: &str, start: usize) -> Range<usize> {
+    let end = source[start..]
+        .find(|c: char| is_whitespace(c) || matches!(c, '=' | '/' | '>'))
+        .unwrap_or(source.len() - start);
+    start..start + end
+}
+
+fn source_offset(source: &str, value: &str) -> usize {
+    value.as_ptr() as usize - source.as_ptr() as usize
+}
+
+fn source_end(source: &str, value: &str) -> usize {
+    source_offset(source, value) + value.len()
+}
+
+fn source_range(source: &str, value: &str) -> Range<usize> {
+    source_offset(source, value)..source_end(source, value)
+}
+
+fn span_element(
+    source: &str,
+    value: &parser::Element,
+    element: &XmlNode<XmlElement>,
+    context: &Context,
+) -> usize {
+    // Descendants are visited with an explicit stack, so that a deep tree does not overflow it.
+    let mut stack = match SpanFrame::open(source, value, element, context) {
+        Ok(v) => vec![v],
+        Err(end) => return end,
+    };
+
+    loop {
+        let frame = match stack.last_mut() {
+            Some(v) => v,
+            None => unreachable!(),
+        };
+        let cells = frame.value.content.as_ref().map(|v| v.children.as_slice());
+        let cell = cells.unwrap_or_default().get(frame.cell);
+        let (cell, child) = match (cell, cell.and_then(|_| frame.children.next())) {
+            (Some(cell), Some(child)) => (cell, child),
+            _ => {
+                let end = match stack.pop() {
+                    Some(v) => v.close(source, context),
+                    None => unreachable!(),
+                };
+                match stack.last_mut() {
+                    Some(parent) => {
+                        parent.pos = end;
+                        parent.close_cell(source, context);
+                        continue;
+                    }
+                    None => return end,
+                }
+            }
+        };
+        frame.cell += 1;
+
+        let name = match &cell.child {
+            parser::Contents::PI(v) => Some(source_range(source, v.target)),
+            _ => None,
+        };
+        let end = match &cell.child {
+            parser::Contents::Element(v) => {
+                match child.as_element() {
+                    Some(child) => match SpanFrame::open(source, v, &child, context) {
+                        Ok(v) => stack.push(v),
+                        Err(end) => {
+                            frame.pos = end;
+                            frame.close_cell(source, context);
+                        }
+                    },
+                    // The content does not match the tree, so the rest is not spanned.
+                    None => frame.cell = usize::MAX,
+                }
+                continue;
+            }
+            parser::Contents::Reference(parser::Reference::Character(v, _)) => {
+                source_end(source, v) + 1
+            }
+            parser::Contents::Reference(parser::Reference::Entity(v)) => source_end(source, v) + 1,
+            parser::Contents::CData(v) => source_end(source, v.value) + 3,
+            parser::Contents::PI(v) => end_of_pi(source, v),
+            parser::Contents::Comment(v) => end_of_comment(source, v),
+        };
+        if child.as_element().is_none() {
+            context.add_span(child.id(), frame.pos..end, name);
+        }
+        frame.pos = end;
+        frame.close_cell(source, context);
+    }
+}
+
+struct SpanFrame<'a, 'b> {
+    value: &'a parser::Element<'b>,
+    element: XmlNode<XmlElement>,
+    children: std::vec::IntoIter<Rc<XmlItem>>,
+    // Index of the next content cell.
+    cell: usize,
+    start: usize,
+    pos: usize,
+}
+
+impl<'a, 'b> SpanFrame<'a, 'b> {
+    /// Spans the start tag and the attributes, or the whole element when it is empty, in which
+    /// case its end is returned as the error.
+    fn open(
+        source: &str,
+        value: &'a parser::Element<'b>,
+        element: &XmlNode<XmlElement>,
+        context: &Context,
+    ) -> Result<Self, usize> {
+        let name = match &value.name {
+            xml_nom::model::QName::Prefixed(v) => v.prefix,
+            xml_nom::model::QName::Unprefixed(v) => v,
+        };
+        let start = source_offset(source, name) - 1;
+
+        let borrowed = element.borrow();
+        for (i, attribute) in borrowed.attributes.iter().enumerate() {
+            if let (Some(head), Some(tail)) = (value.spaces.get(i), value.spaces.get(i + 1)) {
+                let start = source_end(source, head);
+                let end = source_offset(source, tail);
+                context.add_span(attribute.id(), start..end, Some(name_range(source, start)));
+            }
+        }
+
+        let mut pos = value
+            .spaces
+            .last()
+            .map(|v| source_end(source, v))
+            .unwrap_or_else(|| source_end(source, name));
+        if source[pos..].starts_with("/>") {
+            let name = name_range(source, start + 1);
+            context.add_span(borrowed.id(), start..pos + 2, Some(name));
+            return Err(pos + 2);
+        }
+        pos += 1;
+
+        let children = match &value.content {
+            Some(_) => borrowed.child_items().borrow().clone(),
+            None => vec![],
+        };
+        let mut children = children.into_iter();
+        if let Some(head) = value.content.as_ref().and_then(|v| v.head) {
+            if !head.is_empty() {
+                if let Some(child) = children.next() {
+                    context.add_span(child.id(), pos..source_end(source, head), None);
+                }
+                pos = source_end(source, head);
+            }
+        }
+
+        Ok(SpanFrame {
+            value,
+            element: element.clone(),
+            children,
+            cell: 0,
+            start,
+            pos,
+        })
+    }
+
+    /// Spans the text that follows the last visited content cell.
+    fn close_cell(&mut self, source: &str, context: &Context) {
+        let cells = self.value.content.as_ref().map(|v| v.children.as_slice());
+        let cell = match self.cell.checked_sub(1) {
+            Some(i) => cells.unwrap_or_default().get(i),
+            None => None,
+        };
+        if let Some(tail) = cell.and_then(|v| v.tail).filter(|v| !v.is_empty()) {
+            if let Some(child) = self.children.next() {
+                context.add_span(child.id(), self.pos..source_end(source, tail), None);
+            }
+            self.pos = source_end(source, tail);
+        }
+    }
+
+    /// Spans the whole element and returns its end.
+    fn close(self, source: &str, context: &Context) -> usize {
+        let (start, pos) = (self.start, self.pos);
+        let end = if source[pos..].starts_with("</") {
+            pos + source[pos..].find('>').unwrap_or_default() + 1
+        } else {
+            // content is skipped.
+            let skip = |_: &xml_nom::model::QName| true;
+            match xml_parser::element_with_skip(&source[start..], &skip) {
+                Ok((rest, _)) => source.len() - rest.len(),
+                Err(_) => source.len(),
+            }
+        };
+        let name = name_range(source, start + 1);
+        context.add_span(self.element.borrow().id(), start..end, Some(name));
+        end
+    }
+}
 
-        Ok(chars.iter().collect())
+fn take_children(children: &Singleton<Vec<Rc<XmlItem>>>) -> Vec<Rc<XmlItem>> {
+    if Rc::strong_count(children) == 1 {
+        std::mem::take(&mut *children.borrow_mut())
     } else {
-        Err(error::Error::InvalidData(new.to_string()))
+        vec![]
     }
 }
 
-fn node<T>(value: T) -> XmlNode<T> {
+fn singleton<T>(value: T) -> Singleton<T> {
     Rc::new(RefCell::new(value))
 }
 
-fn normalize_ws(value: &str) -> String {
-    let mut v = value.to_string();
-    unsafe {
-        v = v.replace(char::from_u32_unchecked(0x20), " ");
-        v = v.replace(char::from_u32_unchecked(0x0D), " ");
-        v = v.replace(char::from_u32_unchecked(0x0A), " ");
-        v = v.replace(char::from_u32_unchecked(0x09), " ");
+fn write_preserving(
+    item: &Rc<XmlItem>,
+    context: &Context,
+    source: &str,
+    digests: &HashMap<usize, u64>,
+    f: &mut impl fmt::Write,
+) -> Result<(), fmt::Error> {
+    let span = match context.span(item.id()) {
+        Some(v) => v,
+        None => return write!(f, "{}", item),
+    };
+
+    if context.digest(item.id()) == digests.get(&item.id()).copied() {
+        return f.write_str(&source[span.range()]);
     }
-    v
-}
 
-fn notation(context: &Context, name: &str) -> Value<Option<XmlNode<XmlNotation>>> {
-    match context.document().borrow().notations() {
-        Some(notations) => {
-            let mut matches = notations
-                .iter()
-                .filter(|n| n.borrow().name() == name)
-                .collect::<Vec<XmlNode<XmlNotation>>>();
-            match matches.len() {
-                1 => Value::V(Some(matches.remove(0))),
-                _ => Value::V(None),
-            }
-        }
-        _ => Value::V(None),
+    let element = match item.as_element() {
+        Some(v) => v,
+        None => return write!(f, "{}", item),
+    };
+    let element = element.borrow();
+    if element.child_items().borrow().is_empty() || source[span.range()].ends_with("/>") {
+        return write!(f, "{}", item);
     }
-}
 
-fn qname(name: &xml_nom::model::QName<'_>) -> (String, Option<String>) {
-    match name {
-        xml_nom::model::QName::Prefixed(n) => {
-            (n.local_part.to_string(), Some(n.prefix.to_string()))
+    let name = element_name(&element);
+    match unchanged_start_tag(&element, &name, &span, context, source, digests) {
+        Some(end) => f.write_str(&source[span.start()..end])?,
+        None => element.write_start_tag(f)?,
+    }
+
+    for child in element.child_items().borrow().iter() {
+        write_preserving(child, context, source, digests, f)?;
+    }
+
+    let original = span.name().map(|v| &source[v]);
+    match source[span.range()].rfind("</") {
+        Some(v) if original == Some(name.as_str()) => {
+            f.write_str(&source[span.start() + v..span.end()])
         }
-        xml_nom::model::QName::Unprefixed(n) => (n.to_string(), None),
+        _ => element.write_end_tag(f),
     }
 }
 
-fn retrieve_element_by_id(
-    element: &XmlNode<XmlElement>,
-    names: &[&str],
-) -> error::Result<Vec<XmlNode<XmlElement>>> {
-    let mut elements = vec![];
-    for id in element.borrow().attributes_id() {
-        let value = id.borrow().normalized_value()?;
-        if names.iter().any(|n| value == *n) {
-            elements.push(element.clone());
+fn unchanged_start_tag(
+    element: &XmlElement,
+    qname: &str,
+    span: &SourceSpan,
+    context: &Context,
+    source: &str,
+    digests: &HashMap<usize, u64>,
+) -> Option<usize> {
+    let name = span.name()?;
+    if &source[name.clone()] != qname {
+        return None;
+    }
+
+    let mut spans = vec![];
+    for attribute in element.attributes.iter() {
+        let id = attribute.id();
+        if context.digest(id)? != *digests.get(&id)? {
+            return None;
         }
+        spans.push(context.span(id)?);
     }
+    spans.sort_by_key(|v| v.start());
 
-    for child in element.borrow().children().iter() {
-        if let Some(child_element) = child.as_element() {
-            elements.append(&mut retrieve_element_by_id(&child_element, names)?);
+    let mut pos = name.end;
+    for attribute in spans {
+        if attribute.start() < pos || !is_space(&source[pos..attribute.start()]) {
+            return None;
         }
+        pos = attribute.end();
     }
 
-    Ok(elements)
+    let rest = &source[pos..span.end()];
+    let close = pos + rest.len() - rest.trim_start_matches(is_whitespace).len();
+    source[close..].starts_with('>').then_some(close + 1)
 }
 
-fn singleton<T>(value: T) -> Singleton<T> {
-    Rc::new(RefCell::new(value))
+fn element_name(element: &XmlElement) -> String {
+    match element.prefix.as_deref() {
+        Some(prefix) => format!("{}:{}", prefix, element.local_name),
+        None => element.local_name.clone(),
+    }
+}
+
+fn write_space(f: &mut impl fmt::Write, value: &str) -> Result<(), fmt::Error> {
+    if is_space(value) {
+        f.write_str(value)?;
+    }
+    Ok(())
+}
+
+fn is_space(value: &str) -> bool {
+    value.chars().all(is_whitespace)
 }
 
 fn xml_encoding(value: &parser::Document) -> String {
@@ -4687,7 +6425,7 @@ mod tests {
 
         assert_eq!(0, declaration.borrow().children().iter().len());
 
-        assert_eq!(doc, declaration.borrow().parent());
+        assert_eq!(doc, declaration.borrow().parent().unwrap());
 
         // HasQName
         assert_eq!("root", declaration.borrow().local_name());
@@ -4722,7 +6460,7 @@ mod tests {
 
         assert_eq!(0, declaration.borrow().children().iter().len());
 
-        assert_eq!(doc, declaration.borrow().parent());
+        assert_eq!(doc, declaration.borrow().parent().unwrap());
 
         // HasQName
         assert_eq!("root", declaration.borrow().local_name());
@@ -4739,6 +6477,119 @@ mod tests {
         assert_eq!(declaration, declaration);
     }
 
+    #[test]
+    fn test_doc_type_internal_subset() {
+        let xml = "<!DOCTYPE root [\n  <!-- a -->\n  <?b c?>\n  <!ENTITY d 'e'>\n] \n>";
+        let input = format!("{}<root />", xml);
+        let (rest, tree) = xml_parser::document(input.as_str()).unwrap();
+        assert_eq!("", rest);
+
+        let doc = XmlDocument::new(&tree).unwrap();
+        let declaration = doc.borrow().document_declaration().unwrap();
+
+        // XmlDocumentTypeDeclaration
+        assert_eq!(
+            Some("\n  <!-- a -->\n  <?b c?>\n  <!ENTITY d 'e'>\n"),
+            declaration.borrow().internal_subset()
+        );
+
+        // fmt::Display
+        assert_eq!(xml, format!("{}", declaration.borrow()));
+        assert_eq!(input, format!("{}", doc.borrow()));
+
+        // IndentedDisplay
+        let mut buf = vec![];
+        declaration.borrow().indented(0, &mut buf).unwrap();
+        assert_eq!(xml, String::from_utf8(buf).unwrap());
+    }
+
+    #[test]
+    fn test_doc_type_comments() {
+        let (rest, tree) = xml_parser::document(
+            "<!DOCTYPE root [<!-- a --><!ENTITY b 'c'><!-- d --><?e f?>]><root />",
+        )
+        .unwrap();
+        assert_eq!("", rest);
+
+        let doc = XmlDocument::new(&tree).unwrap();
+        let declaration = doc.borrow().document_declaration().unwrap();
+
+        // XmlDocumentTypeDeclaration
+        let comments = declaration.borrow().comments();
+        assert_eq!(2, comments.len());
+        assert_eq!(" a ", comments[0].borrow().comment());
+        assert_eq!(" d ", comments[1].borrow().comment());
+        assert_eq!(
+            Rc::new(declaration.clone().into()),
+            comments[0].borrow().parent().unwrap()
+        );
+
+        // fmt::Display
+        declaration.borrow_mut().internal_subset = None;
+        assert_eq!(
+            "<!DOCTYPE root [<!-- a --><!ENTITY b \"c\"><!-- d --><?e f?>]>",
+            format!("{}", declaration.borrow())
+        );
+    }
+
+    #[test]
+    fn test_doc_type_elements() {
+        let (rest, tree) = xml_parser::document(
+            "<!DOCTYPE root [<!ELEMENT root (a, (b | c)*, d?)><!ELEMENT a (#PCDATA | p:e)*>\
+             <!ELEMENT b EMPTY><!ELEMENT c ANY>]><root />",
+        )
+        .unwrap();
+        assert_eq!("", rest);
+
+        let doc = XmlDocument::new(&tree).unwrap();
+        let declaration = doc.borrow().document_declaration().unwrap();
+
+        // XmlDocumentTypeDeclaration
+        let declaration = declaration.borrow();
+        let elements = declaration.elements();
+        assert_eq!(4, elements.len());
+        assert_eq!("root", elements[0].local_name());
+        assert_eq!(
+            &XmlDeclarationContent::Children(XmlDeclarationContentItem::Seq(
+                vec![
+                    XmlDeclarationContentItem::Name("a".to_string(), None),
+                    XmlDeclarationContentItem::Choice(
+                        vec![
+                            XmlDeclarationContentItem::Name("b".to_string(), None),
+                            XmlDeclarationContentItem::Name("c".to_string(), None),
+                        ],
+                        Some('*')
+                    ),
+                    XmlDeclarationContentItem::Name("d".to_string(), Some('?')),
+                ],
+                None
+            )),
+            elements[0].content()
+        );
+        assert_eq!(
+            &XmlDeclarationContent::Mixed(vec!["p:e".to_string()]),
+            elements[1].content()
+        );
+        assert_eq!(&XmlDeclarationContent::Empty, elements[2].content());
+        assert_eq!(&XmlDeclarationContent::Any, elements[3].content());
+    }
+
+    #[test]
+    fn test_doc_type_internal_subset_none() {
+        let (rest, tree) = xml_parser::document("<!DOCTYPE root><root />").unwrap();
+        assert_eq!("", rest);
+
+        let doc = XmlDocument::new(&tree).unwrap();
+        let declaration = doc.borrow().document_declaration().unwrap();
+
+        // XmlDocumentTypeDeclaration
+        assert_eq!(None, declaration.borrow().internal_subset());
+
+        let declaration = XmlDocumentTypeDeclaration::empty("root", doc.borrow().context());
+        let declaration = declaration.as_document_type().unwrap();
+        assert_eq!(None, declaration.borrow().internal_subset());
+    }
+
     #[test]
     fn test_doc_type_display_system_id() {
         let (rest, tree) = xml_parser::document("<!DOCTYPE root SYSTEM 'e'><root />").unwrap();
@@ -5046,6 +6897,142 @@ mod tests {
         assert_eq!(root, root);
     }
 
+    #[test]
+    fn test_element_display_deep() {
+        let (rest, tree) = xml_parser::document("<root />").unwrap();
+        assert_eq!("", rest);
+
+        let doc = XmlDocument::new(&tree).unwrap();
+        let root = doc.borrow().document_element().unwrap();
+        let context = doc.borrow().context().clone();
+        let (_, tree) = xml_parser::element("<a b='c' />").unwrap();
+
+        let depth = 100000;
+        let mut parent = root.clone();
+        for _ in 0..depth {
+            let child = XmlElement::node(&tree, None, &context).unwrap();
+            parent.borrow().children.borrow_mut().push(child.clone());
+            parent = child.as_element().unwrap();
+        }
+
+        // fmt::Display
+        let expected = format!(
+            "<root>{}<a b=\"c\" />{}</root>",
+            "<a b=\"c\">".repeat(depth - 1),
+            "</a>".repeat(depth - 1)
+        );
+        assert_eq!(expected, format!("{}", doc.borrow()));
+
+        // Drop iteratively to avoid overflowing the stack in the test itself.
+        let mut children = root.borrow().children.take();
+        while let Some(child) = children.pop() {
+            children.append(&mut child.as_element().unwrap().borrow().children.take());
+        }
+    }
+
+    #[test]
+    fn test_element_indented_deep() {
+        let (rest, tree) = xml_parser::document("<root />").unwrap();
+        assert_eq!("", rest);
+
+        let doc = XmlDocument::new(&tree).unwrap();
+        let root = doc.borrow().document_element().unwrap();
+        let context = doc.borrow().context().clone();
+        let (_, tree) = xml_parser::element("<a b='c' />").unwrap();
+
+        let depth = 20000;
+        let mut parent = root.clone();
+        for _ in 0..depth {
+            let child = XmlElement::node(&tree, None, &context).unwrap();
+            parent.borrow().children.borrow_mut().push(child.clone());
+            parent = child.as_element().unwrap();
+        }
+
+        // IndentedDisplay
+        let mut buf = vec![];
+        root.borrow().indented(0, &mut io::sink()).unwrap();
+        let first = root.borrow().child_items().borrow()[0]
+            .as_element()
+            .unwrap();
+        first.borrow().children.borrow_mut().clear();
+        root.borrow().indented(0, &mut buf).unwrap();
+        assert_eq!(
+            "<root>\n    <a b=\"c\" />\n</root>",
+            String::from_utf8(buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_element_indented() {
+        let (rest, tree) = xml_parser::document(
+            "<root>  <a>x<b>y</b> z</a><!--c--> <d xml:space='preserve'><e> <f/></e></d><g> </g></root>",
+        )
+        .unwrap();
+        assert_eq!("", rest);
+
+        let doc = XmlDocument::new(&tree).unwrap();
+        let root = doc.borrow().document_element().unwrap();
+
+        // IndentedDisplay
+        let mut buf = vec![];
+        root.borrow().indented(0, &mut buf).unwrap();
+        assert_eq!(
+            "<root>\n    <a>x<b>y</b> z</a>\n    <!--c-->\n    <d xml:space=\"preserve\"><e> <f /></e></d>\n    <g> </g>\n</root>",
+            String::from_utf8(buf).unwrap()
+        );
+
+        let d = root.borrow().children().get(4).unwrap().as_element();
+        let e = d.unwrap().borrow().children().get(0).unwrap().as_element();
+        let e = e.unwrap();
+        let mut buf = vec![];
+        e.borrow().indented(0, &mut buf).unwrap();
+        assert_eq!("<e> <f /></e>", String::from_utf8(buf).unwrap());
+    }
+
+    #[test]
+    fn test_element_spaces_preserved() {
+        let xml = "<root\n  b='1'\tc='2' ><a  d='3'/></root>";
+        let (rest, tree) = xml_parser::document(xml).unwrap();
+        assert_eq!("", rest);
+
+        let doc = XmlDocument::new(&tree).unwrap();
+        let root = doc.borrow().document_element().unwrap();
+
+        // fmt::Display
+        assert_eq!(
+            "<root b=\"1\" c=\"2\"><a d=\"3\" /></root>",
+            format!("{}", root.borrow())
+        );
+
+        doc.borrow_mut().context_mut().set_spaces_preserved(true);
+        assert_eq!(
+            "<root\n  b=\"1\"\tc=\"2\" ><a  d=\"3\"/></root>",
+            format!("{}", root.borrow())
+        );
+
+        let context = doc.borrow().context().clone();
+        let b = XmlAttribute::empty("b", &context).unwrap();
+        let old = root.borrow_mut().replace_attribute(b).unwrap();
+        assert_eq!("b", old.as_attribute().unwrap().borrow().local_name());
+        assert_eq!(
+            "<root\n  b=\"\"\tc=\"2\" ><a  d=\"3\"/></root>",
+            format!("{}", root.borrow())
+        );
+
+        let e = XmlAttribute::empty("e", &context).unwrap();
+        assert!(root.borrow_mut().replace_attribute(e).is_none());
+        assert_eq!(
+            "<root\n  b=\"\"\tc=\"2\" e=\"\" ><a  d=\"3\"/></root>",
+            format!("{}", root.borrow())
+        );
+
+        root.borrow_mut().remove_attribute("b");
+        assert_eq!(
+            "<root\tc=\"2\" e=\"\" ><a  d=\"3\"/></root>",
+            format!("{}", root.borrow())
+        );
+    }
+
     #[test]
     fn test_element_namespace_attribute() {
         let (rest, tree) = xml_parser::document(
@@ -5346,6 +7333,17 @@ mod tests {
         // Element[namespaces inherit]
         let in_scope_namespace = e2.borrow().in_scope_namespace().unwrap();
         assert_eq!(1, in_scope_namespace.iter().len());
+        assert_eq!(None, e1.borrow().lookup_namespace_uri("ns").unwrap());
+        assert_eq!(None, e2.borrow().lookup_namespace_uri("ns").unwrap());
+
+        doc.borrow_mut()
+            .context_mut()
+            .set_prefix_undeclaration(false);
+        let uri = e1.borrow().lookup_namespace_uri("ns").unwrap();
+        assert_eq!(Some(""), uri.as_ref().map(|v| v.value()));
+        doc.borrow_mut()
+            .context_mut()
+            .set_prefix_undeclaration(true);
 
         let mut i = in_scope_namespace.iter();
         let s = i.next().unwrap();
@@ -6379,6 +8377,51 @@ mod tests {
         assert_eq!(pi, pi);
     }
 
+    #[test]
+    fn test_pi_pseudo_attributes() {
+        let (rest, tree) = xml_parser::document(
+            "<?xml-stylesheet href='a.xsl' type=\"text/xsl\" title='&lt;&#65;&#x42;&gt;'?><root />",
+        )
+        .unwrap();
+        assert_eq!("", rest);
+
+        let doc = XmlDocument::new(&tree).unwrap();
+        let pi = doc.borrow().children().get(0).unwrap().as_pi().unwrap();
+
+        let attributes = pi.borrow().pseudo_attributes().unwrap();
+        assert_eq!(
+            vec![
+                ("href".to_string(), "a.xsl".to_string()),
+                ("type".to_string(), "text/xsl".to_string()),
+                ("title".to_string(), "<AB>".to_string()),
+            ],
+            attributes
+        );
+    }
+
+    #[test]
+    fn test_pi_pseudo_attributes_err() {
+        let (rest, tree) =
+            xml_parser::document("<root><?a b='1' b='2'?><?a b='1'c='2'?><?a b?></root>").unwrap();
+        assert_eq!("", rest);
+
+        let doc = XmlDocument::new(&tree).unwrap();
+        let root = doc.borrow().document_element().unwrap();
+        let children = root.borrow().children();
+
+        let pi = children.get(0).unwrap().as_pi().unwrap();
+        let err = pi.borrow().pseudo_attributes().err().unwrap();
+        assert_eq!(error::Error::InvalidData("b".to_string()), err);
+
+        let pi = children.get(1).unwrap().as_pi().unwrap();
+        let err = pi.borrow().pseudo_attributes().err().unwrap();
+        assert_eq!(error::Error::InvalidData("c='2'".to_string()), err);
+
+        let pi = children.get(2).unwrap().as_pi().unwrap();
+        let err = pi.borrow().pseudo_attributes().err().unwrap();
+        assert_eq!(error::Error::InvalidData("b".to_string()), err);
+    }
+
     #[test]
     fn test_unexpanded_min() {
         let (rest, tree) = xml_parser::document("<root>&amp;</root>").unwrap();
@@ -6637,7 +8680,7 @@ mod tests {
         let root = doc.borrow().document_element().unwrap();
         let cdata = root.borrow().children().get(0).unwrap().as_cdata().unwrap();
 
-        cdata.borrow_mut().insert(1, "a]]>b").err().unwrap();
+        cdata.borrow_mut().insert(1, "a\u{1}b").err().unwrap();
     }
 
     #[test]
@@ -6914,7 +8957,7 @@ mod tests {
         let root = doc.borrow().document_element().unwrap();
         let text = root.borrow().children().get(0).unwrap().as_text().unwrap();
 
-        text.borrow_mut().insert(1, "a&amp;b").err().unwrap();
+        text.borrow_mut().insert(1, "a\u{1}b").err().unwrap();
     }
 
     #[test]
@@ -7604,4 +9647,81 @@ mod tests {
         // PartialEq
         assert_eq!(ns, ns);
     }
+
+    #[test]
+    fn test_document_drop() {
+        let (rest, tree) =
+            xml_parser::document("<!DOCTYPE root [<!ENTITY e 'e'>]><root a='1'><c>&e;</c></root>")
+                .unwrap();
+        assert_eq!("", rest);
+
+        let doc = XmlDocument::new(&tree).unwrap();
+        let weak = Rc::downgrade(&doc);
+        let root = doc.borrow().document_element().unwrap();
+        let child = root.borrow().children().iter().next().unwrap();
+        let weak_root = Rc::downgrade(&root);
+        drop(root);
+        drop(doc);
+
+        assert!(weak.upgrade().is_none());
+        assert!(weak_root.upgrade().is_none());
+        let child = child.as_element().unwrap();
+        assert!(child.borrow().parent().is_err());
+    }
+
+    #[test]
+    fn test_element_child_source() {
+        struct Source(Rc<RefCell<usize>>);
+
+        impl ChildSource for Source {
+            fn load(&self, parent: &XmlElement) -> Vec<Rc<XmlItem>> {
+                *self.0.borrow_mut() += 1;
+                let child = XmlElement::empty("c", parent.context()).unwrap();
+                vec![child, XmlText::node("t", None, parent.context())]
+            }
+        }
+
+        let (_, tree) = xml_parser::document("<root />").unwrap();
+        let doc = XmlDocument::new(&tree).unwrap();
+        let root = doc.borrow().document_element().unwrap();
+        let loads = Rc::new(RefCell::new(0));
+        root.borrow()
+            .set_child_source(Some(Rc::new(Source(loads.clone()))));
+        assert_eq!(0, *loads.borrow());
+
+        let children = root.borrow().children();
+        assert_eq!(2, children.iter().len());
+        assert_eq!(1, *loads.borrow());
+        let c = children.iter().next().unwrap().as_element().unwrap();
+        assert_eq!(
+            Some(root.clone()),
+            c.borrow().parent().unwrap().as_element()
+        );
+        assert_eq!("<root><c />t</root>", doc.borrow().to_string());
+        assert_eq!(1, *loads.borrow());
+    }
+
+    #[test]
+    fn test_node_pool() {
+        let (_, tree) = xml_parser::document("<root><a /><b /></root>").unwrap();
+        let mut pool = NodePool::default();
+        let doc = XmlDocument::new_in(&tree, &mut pool).unwrap();
+        assert!(pool.recycle(doc));
+        assert_eq!(1, pool.len());
+        let capacity = pool.tables[0].id_map.capacity();
+        assert!(capacity > 0);
+        assert!(pool.tables[0].id_map.is_empty());
+
+        let doc = XmlDocument::new_in(&tree, &mut pool).unwrap();
+        assert!(pool.is_empty());
+        let context = doc.borrow().context().clone();
+        assert_eq!(capacity, context.id_map.borrow().capacity());
+        assert_eq!(3, context.id_map.borrow().len());
+
+        let root = doc.borrow().document_element().unwrap();
+        drop(context);
+        assert!(!pool.recycle(doc));
+        assert!(pool.is_empty());
+        assert_eq!("root", root.borrow().local_name());
+    }
 }
@@ -29,7 +29,7 @@ pub fn qname(input: &str) -> IResult<&str, model::QName<'_>> {
 /// Prefix ':' LocalPart
 ///
 /// [\[8\] PrefixedName](https://www.w3.org/TR/2009/REC-xml-names-20091208/#NT-PrefixedName)
-fn prefixed_name(input: &str) -> IResult<&str, model::PrefixedName> {
+fn prefixed_name(input: &str) -> IResult<&str, model::PrefixedName<'_>> {
     map(
         tuple((ncname, preceded(tag(":"), ncname))),
         model::PrefixedName::from,
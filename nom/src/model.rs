@@ -39,4 +39,29 @@ impl<'a> From<&'a str> for QName<'a> {
     }
 }
 
+impl<'a> std::fmt::Display for QName<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            QName::Prefixed(v) => write!(f, "{}:{}", v.prefix, v.local_part),
+            QName::Unprefixed(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+impl<'a> QName<'a> {
+    pub fn prefix(&self) -> Option<&'a str> {
+        match self {
+            QName::Prefixed(v) => Some(v.prefix),
+            QName::Unprefixed(_) => None,
+        }
+    }
+
+    pub fn local_part(&self) -> &'a str {
+        match self {
+            QName::Prefixed(v) => v.local_part,
+            QName::Unprefixed(v) => v,
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------------------------
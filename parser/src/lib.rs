@@ -1,17 +1,21 @@
 pub mod model;
+pub mod visit;
 
 pub use nom;
 
 use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::character::complete::{alpha1, digit1, hex_digit1, multispace0, multispace1};
-use nom::combinator::{map, opt, recognize};
+use nom::combinator::{consumed, map, not, opt, recognize};
 use nom::error::{ErrorKind, ParseError};
-use nom::multi::{many0, many1};
+use nom::multi::{many0, many1, separated_list0};
 use nom::sequence::{delimited, preceded, terminated, tuple};
 use nom::{AsChar, IResult, InputTakeAtPosition};
+use xml_nom::model::QName;
 use xml_nom::{helper, ncname, qname, xmlchar};
 
+pub use xml_nom::xmlchar::{is_char, is_name_char, is_name_start_char};
+
 // TODO: Reduce memory consumption.
 
 // -----------------------------------------------------------------------------------------------
@@ -23,6 +27,98 @@ pub fn document(input: &str) -> IResult<&str, model::Document<'_>> {
     map(tuple((prolog, element, many0(misc))), model::Document::from)(input)
 }
 
+/// prolog element Misc* (prolog without doctypedecl)
+///
+/// Fails at '\<!DOCTYPE' instead of parsing the document type declaration.
+pub fn document_without_dtd(input: &str) -> IResult<&str, model::Document<'_>> {
+    let options = Options {
+        dtd_rejected: true,
+        ..Options::default()
+    };
+    document_with_options(input, &options)
+}
+
+/// prolog element Misc*
+///
+/// The content of elements matched by `skip` is scanned to the matching end tag without being
+/// parsed, and those elements are returned with empty content.
+pub fn document_with_skip<'a>(
+    input: &'a str,
+    skip: &dyn Fn(&QName) -> bool,
+) -> IResult<&'a str, model::Document<'a>> {
+    let options = Options {
+        skip: Some(skip),
+        ..Options::default()
+    };
+    document_with_options(input, &options)
+}
+
+/// prolog element Misc*
+///
+/// Parses with the restrictions of `options`.
+pub fn document_with_options<'a>(
+    input: &'a str,
+    options: &Options<'_>,
+) -> IResult<&'a str, model::Document<'a>> {
+    let prolog: fn(&str) -> IResult<&str, model::Prolog<'_>> = if options.dtd_rejected {
+        prolog_without_dtd
+    } else {
+        prolog
+    };
+    map(
+        tuple((prolog, |i| element_impl(i, options, 1), many0(misc))),
+        model::Document::from,
+    )(input)
+}
+
+/// Restrictions applied by [`document_with_options`].
+#[derive(Clone, Copy, Default)]
+pub struct Options<'a> {
+    /// Fails at '\<!DOCTYPE' instead of parsing the document type declaration.
+    pub dtd_rejected: bool,
+    /// Elements whose content is scanned to the matching end tag without being parsed.
+    pub skip: Option<&'a dyn Fn(&QName) -> bool>,
+    /// Fails with `ErrorKind::TooLarge` at the first element nested deeper than this, before
+    /// its content is parsed. The document element is at depth 1.
+    pub max_depth: Option<usize>,
+}
+
+/// Reads the XML declaration and the root element name from the head of a document.
+///
+/// The content of the root element is not parsed, so `input` may be a truncated prefix.
+pub fn sniff(input: &[u8]) -> Option<model::Sniff<'_>> {
+    let input = input.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(input);
+    let input = match std::str::from_utf8(input) {
+        Ok(v) => v,
+        Err(e) => std::str::from_utf8(&input[..e.valid_up_to()]).ok()?,
+    };
+
+    let (_, (prolog, (root, attributes))) = tuple((
+        prolog,
+        preceded(
+            tag("<"),
+            tuple((qname, many0(preceded(multispace1, attribute)))),
+        ),
+    ))(input)
+    .ok()?;
+
+    let ns = match root {
+        QName::Prefixed(ref v) => model::AttributeName::Namespace(v.prefix),
+        QName::Unprefixed(_) => model::AttributeName::DefaultNamespace,
+    };
+    let namespace = attributes
+        .iter()
+        .find(|v| v.name == ns)
+        .map(|v| attribute_text(&v.value))
+        .filter(|v| !v.is_empty());
+
+    Some(model::Sniff {
+        declaration_xml: prolog.declaration_xml,
+        root,
+        namespace,
+    })
+}
+
 /// Recognizes zero or more XML characters.
 ///
 /// #x9 | #xA | #xD | [#x20-#xD7FF] | [#xE000-#xFFFD] | [#x10000-#x10FFFF]
@@ -82,10 +178,45 @@ where
     input.split_at_position1_complete(|i| !xmlchar::is_name_char(i.as_char()), ErrorKind::Fail)
 }
 
+/// Whether `value` is a [\[5\] Name](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-Name).
+pub fn is_name(value: &str) -> bool {
+    let mut chars = value.chars();
+    match chars.next() {
+        Some(c) if xmlchar::is_name_start_char(c) => chars.all(xmlchar::is_name_char),
+        _ => false,
+    }
+}
+
+/// Whether `value` is a [\[7\] Nmtoken](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-Nmtoken).
+pub fn is_nmtoken(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(xmlchar::is_name_char)
+}
+
+/// Whether `value` is a [\[4\] NCName](https://www.w3.org/TR/2009/REC-xml-names-20091208/#NT-NCName).
+pub fn is_ncname(value: &str) -> bool {
+    is_name(value) && !value.contains(':')
+}
+
+/// Whether `value` is a [\[7\] QName](https://www.w3.org/TR/2009/REC-xml-names-20091208/#NT-QName).
+pub fn is_qname(value: &str) -> bool {
+    split_qname(value).is_some()
+}
+
+/// Split a QName into its prefix and local part, `None` if `value` is not a QName.
+pub fn split_qname(value: &str) -> Option<(Option<&str>, &str)> {
+    match value.split_once(':') {
+        Some((prefix, local_part)) if is_ncname(prefix) && is_ncname(local_part) => {
+            Some((Some(prefix), local_part))
+        }
+        None if is_ncname(value) => Some((None, value)),
+        _ => None,
+    }
+}
+
 /// '"' ([^%&"] | PEReference | Reference)* '"' | "'" ([^%&'] | PEReference | Reference)* "'"
 ///
 /// [\[9\] EntityValue](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-EntityValue)
-fn entity_value(input: &str) -> IResult<&str, Vec<model::EntityValue>> {
+fn entity_value(input: &str) -> IResult<&str, Vec<model::EntityValue<'_>>> {
     alt((
         delimited(
             tag("\""),
@@ -238,6 +369,19 @@ fn prolog(input: &str) -> IResult<&str, model::Prolog<'_>> {
     )(input)
 }
 
+/// XMLDecl? Misc*
+fn prolog_without_dtd(input: &str) -> IResult<&str, model::Prolog<'_>> {
+    map(
+        terminated(tuple((opt(xml_decl), many0(misc))), not(tag("<!DOCTYPE"))),
+        |(declaration_xml, heads)| model::Prolog {
+            declaration_xml,
+            heads,
+            declaration_doc: None,
+            tails: vec![],
+        },
+    )(input)
+}
+
 /// '\<?xml' VersionInfo EncodingDecl? SDDecl? S? '?>'
 ///
 /// [\[23\] XMLDecl](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-XMLDecl)
@@ -301,10 +445,9 @@ fn doctype_decl(input: &str) -> IResult<&str, model::DeclarationDoc<'_>> {
             preceded(tuple((tag("<!DOCTYPE"), multispace1)), qname),
             terminated(opt(preceded(multispace1, external_id)), multispace0),
             terminated(
-                opt(delimited(
-                    tag("["),
-                    int_subset,
-                    tuple((tag("]"), multispace0)),
+                opt(map(
+                    tuple((tag("["), consumed(int_subset), tag("]"), multispace0)),
+                    |(_, (literal, subsets), _, space)| (literal, subsets, space),
                 )),
                 tag(">"),
             ),
@@ -333,6 +476,26 @@ fn int_subset(input: &str) -> IResult<&str, Vec<model::InternalSubset<'_>>> {
     )))(input)
 }
 
+/// TextDecl? (markupdecl | DeclSep)*
+///
+/// [\[30\] extSubset](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-extSubset)
+///
+/// Conditional sections are not supported.
+pub fn ext_subset(input: &str) -> IResult<&str, Vec<model::InternalSubset<'_>>> {
+    preceded(opt(text_decl), int_subset)(input)
+}
+
+/// '\<?xml' VersionInfo? EncodingDecl S? '?>'
+///
+/// [\[77\] TextDecl](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-TextDecl)
+fn text_decl(input: &str) -> IResult<&str, &str> {
+    delimited(
+        tag("<?xml"),
+        preceded(opt(version_info), encoding_decl),
+        tuple((multispace0, tag("?>"))),
+    )(input)
+}
+
 /// elementdecl | AttlistDecl | EntityDecl | NotationDecl | PI | Comment
 ///
 /// [\[29\] markupdecl](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-markupdecl)
@@ -369,10 +532,97 @@ fn sd_decl(input: &str) -> IResult<&str, bool> {
 ///
 /// [\[39\] element](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-element)
 pub fn element(input: &str) -> IResult<&str, model::Element<'_>> {
-    alt((
-        empty_entity_tag,
-        map(tuple((stag, content, etag)), |(s, c, _)| s.set_content(c)),
-    ))(input)
+    element_impl(input, &Options::default(), 1)
+}
+
+/// EmptyElemTag | STag content ETag
+///
+/// The content of elements matched by `skip` is not parsed.
+pub fn element_with_skip<'a>(
+    input: &'a str,
+    skip: &dyn Fn(&QName) -> bool,
+) -> IResult<&'a str, model::Element<'a>> {
+    let options = Options {
+        skip: Some(skip),
+        ..Options::default()
+    };
+    element_impl(input, &options, 1)
+}
+
+/// EmptyElemTag | STag content ETag
+///
+/// Parses with the restrictions of `options`, `input` being at depth 1.
+pub fn element_with_options<'a>(
+    input: &'a str,
+    options: &Options<'_>,
+) -> IResult<&'a str, model::Element<'a>> {
+    element_impl(input, options, 1)
+}
+
+fn element_impl<'a>(
+    input: &'a str,
+    options: &Options<'_>,
+    depth: usize,
+) -> IResult<&'a str, model::Element<'a>> {
+    let (i, (s, complete)) = start_tag(input, options, depth)?;
+    if complete {
+        return Ok((i, s));
+    }
+
+    let (i, c) = content_impl(i, options, depth + 1)?;
+    let (i, _) = matching_etag(i, &s.name)?;
+    Ok((i, s.set_content(c)))
+}
+
+/// ETag whose Name matches the Name in the start tag.
+///
+/// A mismatch is fatal and reported at the start of the end tag.
+///
+/// [WFC: Element Type Match](https://www.w3.org/TR/2008/REC-xml-20081126/#GIMatch)
+fn matching_etag<'a>(input: &'a str, name: &QName<'_>) -> IResult<&'a str, QName<'a>> {
+    let (rest, end) = etag(input)?;
+    if end != *name {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            input,
+            ErrorKind::Verify,
+        )));
+    }
+    Ok((rest, end))
+}
+
+/// EmptyElemTag | STag
+///
+/// Returns `true` with an empty or skipped element, `false` when the content follows.
+fn start_tag<'a>(
+    input: &'a str,
+    options: &Options<'_>,
+    depth: usize,
+) -> IResult<&'a str, (model::Element<'a>, bool)> {
+    // Checked before the content is parsed so that the depth never exceeds the limit.
+    let limit = || match options.max_depth {
+        Some(max_depth) if depth > max_depth => Err(nom::Err::Failure(nom::error::Error::new(
+            input,
+            ErrorKind::TooLarge,
+        ))),
+        _ => Ok(()),
+    };
+
+    match empty_entity_tag(input) {
+        Ok((rest, e)) => {
+            limit()?;
+            return Ok((rest, (e, true)));
+        }
+        Err(nom::Err::Error(_)) => {}
+        Err(e) => return Err(e),
+    }
+
+    let (rest, s) = stag(input)?;
+    limit()?;
+    if options.skip.map(|f| f(&s.name)).unwrap_or_default() {
+        let (rest, _) = skip_content(rest)?;
+        return Ok((rest, (s.set_content(model::Content::default()), true)));
+    }
+    Ok((rest, (s, false)))
 }
 
 /// '\<' Name (S Attribute)* S? '>'
@@ -384,8 +634,8 @@ fn stag(input: &str) -> IResult<&str, model::Element<'_>> {
     map(
         delimited(
             tag("<"),
-            tuple((qname, many0(preceded(multispace1, attribute)))),
-            tuple((multispace0, tag(">"))),
+            tuple((qname, many0(tuple((multispace1, attribute))), multispace0)),
+            tag(">"),
         ),
         model::Element::from,
     )(input)
@@ -411,38 +661,105 @@ pub fn attribute(input: &str) -> IResult<&str, model::Attribute<'_>> {
 /// [\[42\] ETag](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-ETag)
 ///
 /// [\[13\] ETag](https://www.w3.org/TR/2009/REC-xml-names-20091208/#NT-ETag)
-fn etag(input: &str) -> IResult<&str, ()> {
-    map(
-        delimited(tag("</"), qname, tuple((multispace0, tag(">")))),
-        |_| (),
-    )(input)
+fn etag(input: &str) -> IResult<&str, QName<'_>> {
+    delimited(tag("</"), qname, tuple((multispace0, tag(">"))))(input)
 }
 
 /// CharData? ((element | Reference | CDSect | PI | Comment) CharData?)*
 ///
 /// [\[43\] content](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-content)
 pub fn content(input: &str) -> IResult<&str, model::Content<'_>> {
-    map(
-        tuple((
-            opt(char_data),
-            many0(tuple((
-                alt((
-                    map(element, model::Contents::from),
-                    map(reference, model::Contents::from),
-                    map(cdsect, model::Contents::from),
-                    map(pi, model::Contents::from),
-                    map(comment, model::Contents::from),
-                )),
-                opt(char_data),
-            ))),
-        )),
-        |(head, children)| {
-            model::Content::from((
-                head,
-                children.into_iter().map(model::ContentCell::from).collect(),
-            ))
-        },
-    )(input)
+    content_impl(input, &Options::default(), 1)
+}
+
+fn content_impl<'a>(
+    input: &'a str,
+    options: &Options<'_>,
+    depth: usize,
+) -> IResult<&'a str, model::Content<'a>> {
+    // Child elements are kept on an explicit stack instead of recursing, so that deep input
+    // can not exhaust the call stack. Each entry is the start of the element, its start tag
+    // and the content of its parent.
+    let mut open: Vec<(&'a str, model::Element<'a>, model::Content<'a>)> = vec![];
+    let (mut i, head) = opt(char_data)(input)?;
+    let mut content = model::Content::from((head, vec![]));
+    let mut backtracked = false;
+
+    loop {
+        if !backtracked {
+            match start_tag(i, options, depth + open.len()) {
+                Ok((rest, (element, true))) => {
+                    let (rest, tail) = opt(char_data)(rest)?;
+                    let child = model::Contents::from(element);
+                    content
+                        .children
+                        .push(model::ContentCell::from((child, tail)));
+                    i = rest;
+                    continue;
+                }
+                Ok((rest, (element, false))) => {
+                    let (rest, head) = opt(char_data)(rest)?;
+                    let parent = std::mem::replace(&mut content, (head, vec![]).into());
+                    open.push((i, element, parent));
+                    i = rest;
+                    continue;
+                }
+                Err(nom::Err::Error(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        backtracked = false;
+
+        let other = alt((
+            map(reference, model::Contents::from),
+            map(cdsect, model::Contents::from),
+            map(pi, model::Contents::from),
+            map(comment, model::Contents::from),
+        ))(i);
+        match other {
+            Ok((rest, child)) => {
+                let (rest, tail) = opt(char_data)(rest)?;
+                content
+                    .children
+                    .push(model::ContentCell::from((child, tail)));
+                i = rest;
+                continue;
+            }
+            Err(nom::Err::Error(_)) => {}
+            Err(e) => return Err(e),
+        }
+
+        let (start, element, parent) = match open.pop() {
+            Some(v) => v,
+            None => return Ok((i, content)),
+        };
+
+        match etag(i) {
+            Ok((rest, name)) if name == element.name => {
+                let (rest, tail) = opt(char_data)(rest)?;
+                let child = model::Contents::from(element.set_content(content));
+                content = parent;
+                content
+                    .children
+                    .push(model::ContentCell::from((child, tail)));
+                i = rest;
+            }
+            Ok(_) => {
+                // Element Type Match is fatal, so report the position of the end tag.
+                return Err(nom::Err::Failure(nom::error::Error::new(
+                    i,
+                    ErrorKind::Verify,
+                )));
+            }
+            Err(nom::Err::Error(_)) => {
+                // The element is not well-formed, so it is not content of the parent.
+                content = parent;
+                i = start;
+                backtracked = true;
+            }
+            Err(e) => return Err(e),
+        }
+    }
 }
 
 /// '\<' Name (S Attribute)* S? '/>'
@@ -454,8 +771,8 @@ fn empty_entity_tag(input: &str) -> IResult<&str, model::Element<'_>> {
     map(
         delimited(
             tag("<"),
-            tuple((qname, many0(preceded(multispace1, attribute)))),
-            tuple((multispace0, tag("/>"))),
+            tuple((qname, many0(tuple((multispace1, attribute))), multispace0)),
+            tag("/>"),
         ),
         model::Element::from,
     )(input)
@@ -901,10 +1218,159 @@ fn ns_att_name(input: &str) -> IResult<&str, model::AttributeName<'_>> {
 
 // -----------------------------------------------------------------------------------------------
 
+/// (S PseudoAtt)* S?
+///
+/// [\[3\] PseudoAtts](https://www.w3.org/TR/xml-stylesheet/#NT-PseudoAtts)
+pub fn pseudo_atts(input: &str) -> IResult<&str, Vec<model::PseudoAttribute<'_>>> {
+    delimited(
+        multispace0,
+        separated_list0(multispace1, pseudo_att),
+        multispace0,
+    )(input)
+}
+
+/// Name S? '=' S? PseudoAttValue
+///
+/// [\[4\] PseudoAtt](https://www.w3.org/TR/xml-stylesheet/#NT-PseudoAtt)
+fn pseudo_att(input: &str) -> IResult<&str, model::PseudoAttribute<'_>> {
+    map(
+        tuple((
+            name,
+            preceded(
+                tuple((multispace0, tag("="), multispace0)),
+                pseudo_att_value,
+            ),
+        )),
+        model::PseudoAttribute::from,
+    )(input)
+}
+
+/// ('"' ([^"<&] | CharRef | PredefEntityRef)* '"' | "'" ([^'<&] | CharRef | PredefEntityRef)* "'")
+///
+/// [\[5\] PseudoAttValue](https://www.w3.org/TR/xml-stylesheet/#NT-PseudoAttValue)
+fn pseudo_att_value(input: &str) -> IResult<&str, Vec<model::AttributeValue<'_>>> {
+    alt((
+        delimited(
+            tag("\""),
+            many0(alt((
+                map(xmlchar::char_except1("<&\""), model::AttributeValue::from),
+                map(char_ref, model::AttributeValue::from),
+                map(predef_entity_ref, model::AttributeValue::from),
+            ))),
+            tag("\""),
+        ),
+        delimited(
+            tag("'"),
+            many0(alt((
+                map(xmlchar::char_except1("<&'"), model::AttributeValue::from),
+                map(char_ref, model::AttributeValue::from),
+                map(predef_entity_ref, model::AttributeValue::from),
+            ))),
+            tag("'"),
+        ),
+    ))(input)
+}
+
+/// '&amp;' | '&lt;' | '&gt;' | '&quot;' | '&apos;'
+///
+/// [\[6\] PredefEntityRef](https://www.w3.org/TR/xml-stylesheet/#NT-PredefEntityRef)
+fn predef_entity_ref(input: &str) -> IResult<&str, model::Reference<'_>> {
+    map(
+        delimited(
+            tag("&"),
+            alt((tag("amp"), tag("lt"), tag("gt"), tag("quot"), tag("apos"))),
+            tag(";"),
+        ),
+        model::Reference::entity,
+    )(input)
+}
+
+// -----------------------------------------------------------------------------------------------
+
+/// Skips element content up to and including the end tag that closes it.
+fn skip_content(input: &str) -> IResult<&str, &str> {
+    fn end_of(value: &str, pattern: &str) -> Option<usize> {
+        value.find(pattern).map(|v| v + pattern.len())
+    }
+
+    fn end_of_tag(value: &str) -> Option<usize> {
+        let mut quote = None;
+        for (i, c) in value.char_indices() {
+            match (quote, c) {
+                (None, '"' | '\'') => quote = Some(c),
+                (Some(q), _) if q == c => quote = None,
+                (None, '>') => return Some(i + 1),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    let mut depth = 1;
+    let mut pos = 0;
+    while let Some(start) = input[pos..].find('<').map(|v| pos + v) {
+        let rest = &input[start..];
+        let (end, nested) = if rest.starts_with("<!--") {
+            (end_of(rest, "-->"), 0)
+        } else if rest.starts_with("<![CDATA[") {
+            (end_of(rest, "]]>"), 0)
+        } else if rest.starts_with("<?") {
+            (end_of(rest, "?>"), 0)
+        } else if rest.starts_with("</") {
+            (end_of(rest, ">"), -1)
+        } else {
+            let end = end_of_tag(rest);
+            let empty = end.map(|v| rest[..v].ends_with("/>")).unwrap_or_default();
+            (end, if empty { 0 } else { 1 })
+        };
+
+        let end = match end {
+            Some(v) => start + v,
+            None => break,
+        };
+
+        depth += nested;
+        if depth == 0 {
+            return Ok((&input[end..], &input[..start]));
+        }
+        pos = end;
+    }
+
+    Err(nom::Err::Error(nom::error::Error::new(
+        input,
+        ErrorKind::TakeUntil,
+    )))
+}
+
+fn attribute_text(value: &[model::AttributeValue]) -> String {
+    let mut text = String::new();
+    for v in value {
+        match v {
+            model::AttributeValue::Text(v) => text.push_str(v),
+            model::AttributeValue::Reference(model::Reference::Character(v, radix)) => {
+                if let Some(c) = u32::from_str_radix(v, *radix).ok().and_then(char::from_u32) {
+                    text.push(c);
+                }
+            }
+            model::AttributeValue::Reference(model::Reference::Entity(v)) => match *v {
+                "amp" => text.push('&'),
+                "lt" => text.push('<'),
+                "gt" => text.push('>'),
+                "quot" => text.push('"'),
+                "apos" => text.push('\''),
+                _ => {}
+            },
+        }
+    }
+    text
+}
+
+// -----------------------------------------------------------------------------------------------
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use xml_nom::model::QName;
+    use xml_nom::model::PrefixedName;
 
     #[test]
     fn test_document() {
@@ -913,6 +1379,155 @@ mod tests {
         assert_eq!(QName::from("root"), ret.element.name);
     }
 
+    #[test]
+    fn test_document_without_dtd() {
+        let (rest, ret) = document_without_dtd("<?xml version='1.0'?><!-- a --><root />").unwrap();
+        assert_eq!("", rest);
+        assert_eq!(QName::from("root"), ret.element.name);
+        assert_eq!(None, ret.prolog.declaration_doc);
+        assert_eq!(1, ret.prolog.heads.len());
+
+        let err = document_without_dtd("<!DOCTYPE root><root />")
+            .err()
+            .unwrap();
+        assert_eq!(
+            nom::Err::Error(nom::error::Error::new(
+                "<!DOCTYPE root><root />",
+                ErrorKind::Not
+            )),
+            err
+        );
+    }
+
+    #[test]
+    fn test_sniff() {
+        let ret =
+            sniff(b"<?xml version='1.0' encoding='UTF-8' standalone='yes'?><root><a>").unwrap();
+        assert_eq!(Some("1.0"), ret.version());
+        assert_eq!(Some("UTF-8"), ret.encoding());
+        assert_eq!(Some(true), ret.standalone());
+        assert_eq!(QName::from("root"), ret.root);
+        assert_eq!(None, ret.namespace);
+
+        let ret = sniff(
+            b"\xEF\xBB\xBF<!-- a --><!DOCTYPE b:root><b:root xmlns='http://a' xmlns:b='http://b&amp;c' c=",
+        )
+        .unwrap();
+        assert_eq!(None, ret.version());
+        assert_eq!(QName::from(PrefixedName::from(("b", "root"))), ret.root);
+        assert_eq!(Some("http://b&c".to_string()), ret.namespace);
+
+        let ret = sniff(b"<root xmlns='http://a'/>\xFF").unwrap();
+        assert_eq!(Some("http://a".to_string()), ret.namespace);
+
+        assert_eq!(None, sniff(b"<?xml version='1.0'?>"));
+        assert_eq!(None, sniff(b"\xFF<root />"));
+    }
+
+    #[test]
+    fn test_document_with_skip() {
+        let skip = |v: &QName| v == &QName::from("b");
+        let (rest, ret) = document_with_skip(
+            "<root><a>1</a><b c='>'><b /><!-- </b> --><![CDATA[</b>]]><d><?e </b>?></d></b><a>2</a></root>",
+            &skip,
+        )
+        .unwrap();
+        assert_eq!("", rest);
+
+        let children = &ret.element.content.as_ref().unwrap().children;
+        assert_eq!(3, children.len());
+        match &children[1].child {
+            model::Contents::Element(v) => {
+                assert_eq!(QName::from("b"), v.name);
+                assert_eq!(Some(model::Content::default()), v.content);
+            }
+            _ => unreachable!(),
+        }
+
+        let err = element_with_skip("<b><c></b>", &skip).err().unwrap();
+        assert_eq!(
+            nom::Err::Error(nom::error::Error::new("<c></b>", ErrorKind::TakeUntil)),
+            err
+        );
+    }
+
+    #[test]
+    fn test_document_with_options() {
+        let options = Options {
+            max_depth: Some(2),
+            ..Options::default()
+        };
+        let (rest, ret) = document_with_options("<a><b/><b><!--c--></b></a>", &options).unwrap();
+        assert_eq!("", rest);
+        assert_eq!(2, ret.element.content.unwrap().children.len());
+
+        let err = document_with_options("<a><b><c/></b></a>", &options)
+            .err()
+            .unwrap();
+        assert_eq!(
+            nom::Err::Failure(nom::error::Error::new("<c/></b></a>", ErrorKind::TooLarge)),
+            err
+        );
+
+        let options = Options {
+            dtd_rejected: true,
+            ..Options::default()
+        };
+        assert!(document_with_options("<!DOCTYPE a><a/>", &options).is_err());
+
+        // Nesting does not consume the call stack.
+        let xml = format!("{}{}", "<a>".repeat(5_000), "</a>".repeat(5_000));
+        let options = Options {
+            max_depth: Some(5_000),
+            ..Options::default()
+        };
+        let (rest, _) = document_with_options(xml.as_str(), &options).unwrap();
+        assert_eq!("", rest);
+
+        let options = Options {
+            max_depth: Some(4_999),
+            ..Options::default()
+        };
+        let err = document_with_options(xml.as_str(), &options).err().unwrap();
+        assert!(matches!(err, nom::Err::Failure(e) if e.code == ErrorKind::TooLarge));
+    }
+
+    #[test]
+    fn test_is_name() {
+        assert!(is_name("a"));
+        assert!(is_name("_a-1.b"));
+        assert!(is_name("a:b"));
+        assert!(is_name(":a"));
+        assert!(is_name("\u{3042}"));
+        assert!(!is_name(""));
+        assert!(!is_name("1a"));
+        assert!(!is_name("-a"));
+        assert!(!is_name("a b"));
+
+        assert!(is_nmtoken("1a"));
+        assert!(is_nmtoken("-a:b"));
+        assert!(!is_nmtoken(""));
+        assert!(!is_nmtoken("a b"));
+
+        assert!(is_ncname("a"));
+        assert!(!is_ncname("a:b"));
+        assert!(!is_ncname("1a"));
+
+        assert!(is_qname("a:b"));
+        assert!(!is_qname("a:b:c"));
+        assert_eq!(Some((Some("a"), "b")), split_qname("a:b"));
+        assert_eq!(Some((None, "b")), split_qname("b"));
+        assert_eq!(None, split_qname(":b"));
+        assert_eq!(None, split_qname("a:"));
+        assert_eq!(None, split_qname("a:1"));
+
+        assert!(is_char('\t'));
+        assert!(!is_char('\u{0}'));
+        assert!(is_name_start_char('_'));
+        assert!(!is_name_start_char('1'));
+        assert!(is_name_char('1'));
+    }
+
     #[test]
     fn test_entity_value() {
         let (rest, ret) = entity_value("\"aaa\"").unwrap();
@@ -1210,25 +1825,31 @@ mod tests {
             ret
         );
 
-        let (rest, ret) = doctype_decl("<!DOCTYPE aaa [ <!ELEMENT aaa ANY > ]>").unwrap();
+        let (rest, ret) = doctype_decl("<!DOCTYPE aaa [ <!ELEMENT aaa ANY > ]\n>").unwrap();
         assert_eq!("", rest);
         assert_eq!(
             model::DeclarationDoc::from((
                 QName::from("aaa"),
                 None,
-                Some(vec![
-                    model::InternalSubset::Whitespace(" "),
-                    model::InternalSubset::from(model::DeclarationMarkup::element(
-                        model::DeclarationElement::from((
-                            QName::from("aaa"),
-                            model::DeclarationContent::Any,
-                        ))
-                    )),
-                    model::InternalSubset::Whitespace(" "),
-                ])
+                Some((
+                    " <!ELEMENT aaa ANY > ",
+                    vec![
+                        model::InternalSubset::Whitespace(" "),
+                        model::InternalSubset::from(model::DeclarationMarkup::element(
+                            model::DeclarationElement::from((
+                                QName::from("aaa"),
+                                model::DeclarationContent::Any,
+                            ))
+                        )),
+                        model::InternalSubset::Whitespace(" "),
+                    ],
+                    "\n"
+                ))
             )),
             ret
         );
+        assert_eq!(Some(" <!ELEMENT aaa ANY > "), ret.internal_subset_literal);
+        assert_eq!("\n", ret.internal_subset_space);
     }
 
     #[test]
@@ -1250,6 +1871,32 @@ mod tests {
         assert_eq!(vec![model::InternalSubset::from("aaa")], ret);
     }
 
+    #[test]
+    fn test_ext_subset() {
+        let (rest, ret) =
+            ext_subset("<?xml version='1.0' encoding='UTF-8'?>\n<!ELEMENT aaa ANY >").unwrap();
+        assert_eq!("", rest);
+        assert_eq!(
+            vec![
+                model::InternalSubset::Whitespace("\n"),
+                model::InternalSubset::from(model::DeclarationMarkup::element(
+                    model::DeclarationElement::from((
+                        QName::from("aaa"),
+                        model::DeclarationContent::Any,
+                    ))
+                ))
+            ],
+            ret
+        );
+
+        let (rest, ret) = ext_subset("<?xml encoding='UTF-8'?><!--c-->").unwrap();
+        assert_eq!("", rest);
+        assert_eq!(1, ret.len());
+
+        let (rest, _) = ext_subset("<![INCLUDE[<!ELEMENT aaa ANY >]]>").unwrap();
+        assert_eq!("<![INCLUDE[<!ELEMENT aaa ANY >]]>", rest);
+    }
+
     #[test]
     fn test_markup_decl() {
         let (rest, ret) = markup_decl("<!ELEMENT aaa ANY >").unwrap();
@@ -1332,32 +1979,102 @@ mod tests {
     fn test_element() {
         let (rest, ret) = element("<a/>").unwrap();
         assert_eq!("", rest);
-        assert_eq!(model::Element::from((QName::from("a"), vec![])), ret);
+        assert_eq!(model::Element::from((QName::from("a"), vec![], "")), ret);
 
         let (rest, ret) = element("<a></a>").unwrap();
         assert_eq!("", rest);
         assert_eq!(
-            model::Element::from((QName::from("a"), vec![]))
+            model::Element::from((QName::from("a"), vec![], ""))
                 .set_content(model::Content::from((Some(""), vec![]))),
             ret
         );
+
+        let (rest, ret) = element("<a\n  b='c'\t d='e' />").unwrap();
+        assert_eq!("", rest);
+        assert_eq!(vec!["\n  ", "\t ", " "], ret.spaces);
+    }
+
+    #[test]
+    fn test_element_type_match() {
+        let (rest, ret) = element("<p:a><b></b ></p:a\n>").unwrap();
+        assert_eq!("", rest);
+        assert_eq!(QName::from(PrefixedName::from(("p", "a"))), ret.name);
+
+        let err = element("<a><b></a></b>").err().unwrap();
+        assert_eq!(
+            nom::Err::Failure(nom::error::Error::new("</a></b>", ErrorKind::Verify)),
+            err
+        );
+
+        let err = element("<p:a></q:a>").err().unwrap();
+        assert_eq!(
+            nom::Err::Failure(nom::error::Error::new("</q:a>", ErrorKind::Verify)),
+            err
+        );
+
+        let err = document("<a><b><c/>t</b><d>u</e></a>").err().unwrap();
+        assert_eq!(
+            nom::Err::Failure(nom::error::Error::new("</e></a>", ErrorKind::Verify)),
+            err
+        );
+
+        let err = content("x<a>y</b>z").err().unwrap();
+        assert_eq!(
+            nom::Err::Failure(nom::error::Error::new("</b>z", ErrorKind::Verify)),
+            err
+        );
+    }
+
+    #[test]
+    fn test_matching_etag() {
+        let name = QName::from(PrefixedName::from(("p", "a")));
+        let (rest, ret) = matching_etag("</p:a >b", &name).unwrap();
+        assert_eq!("b", rest);
+        assert_eq!(name, ret);
+
+        let err = matching_etag("</a>", &name).err().unwrap();
+        assert_eq!(
+            nom::Err::Failure(nom::error::Error::new("</a>", ErrorKind::Verify)),
+            err
+        );
+
+        let err = matching_etag("<a>", &name).err().unwrap();
+        assert!(matches!(err, nom::Err::Error(_)));
+    }
+
+    #[test]
+    fn test_etag() {
+        let (rest, ret) = etag("</a>").unwrap();
+        assert_eq!("", rest);
+        assert_eq!(QName::from("a"), ret);
+
+        let (rest, ret) = etag("</p:a \t>b").unwrap();
+        assert_eq!("b", rest);
+        assert_eq!(QName::from(PrefixedName::from(("p", "a"))), ret);
+
+        assert!(etag("</ a>").is_err());
+        assert!(etag("</a").is_err());
     }
 
     #[test]
     fn test_stag() {
         let (rest, ret) = stag("<a>").unwrap();
         assert_eq!("", rest);
-        assert_eq!(model::Element::from((QName::from("a"), vec![])), ret);
+        assert_eq!(model::Element::from((QName::from("a"), vec![], "")), ret);
 
         let (rest, ret) = stag("<a b='c'>").unwrap();
         assert_eq!("", rest);
         assert_eq!(
             model::Element::from((
                 QName::from("a"),
-                vec![model::Attribute::from((
-                    model::AttributeName::QName(QName::Unprefixed("b")),
-                    vec![model::AttributeValue::from("c")]
-                ))]
+                vec![(
+                    " ",
+                    model::Attribute::from((
+                        model::AttributeName::QName(QName::Unprefixed("b")),
+                        vec![model::AttributeValue::from("c")]
+                    ))
+                )],
+                ""
             )),
             ret
         );
@@ -1375,7 +2092,7 @@ mod tests {
             model::Content::from((
                 Some(""),
                 vec![model::ContentCell::from((
-                    model::Contents::from(model::Element::from((QName::from("a"), vec![]))),
+                    model::Contents::from(model::Element::from((QName::from("a"), vec![], ""))),
                     Some("")
                 )),]
             )),
@@ -1388,7 +2105,7 @@ mod tests {
             model::Content::from((
                 Some("a"),
                 vec![model::ContentCell::from((
-                    model::Contents::from(model::Element::from((QName::from("a"), vec![]))),
+                    model::Contents::from(model::Element::from((QName::from("a"), vec![], ""))),
                     Some("")
                 )),]
             )),
@@ -1401,7 +2118,7 @@ mod tests {
             model::Content::from((
                 Some(""),
                 vec![model::ContentCell::from((
-                    model::Contents::from(model::Element::from((QName::from("a"), vec![]))),
+                    model::Contents::from(model::Element::from((QName::from("a"), vec![], ""))),
                     Some("a"),
                 )),]
             )),
@@ -1415,11 +2132,11 @@ mod tests {
                 Some(""),
                 vec![
                     model::ContentCell::from((
-                        model::Contents::from(model::Element::from((QName::from("a"), vec![]))),
+                        model::Contents::from(model::Element::from((QName::from("a"), vec![], ""))),
                         Some(""),
                     )),
                     model::ContentCell::from((
-                        model::Contents::from(model::Element::from((QName::from("b"), vec![]))),
+                        model::Contents::from(model::Element::from((QName::from("b"), vec![], ""))),
                         Some(""),
                     )),
                 ]
@@ -1795,6 +2512,122 @@ mod tests {
         assert_eq!("aaa", ret.name);
         assert_eq!(model::DeclarationNotationId::PublicId("ccc"), ret.id);
     }
+
+    #[test]
+    fn test_pseudo_atts() {
+        let (rest, ret) = pseudo_atts("").unwrap();
+        assert_eq!("", rest);
+        assert_eq!(Vec::<model::PseudoAttribute>::new(), ret);
+
+        let (rest, ret) = pseudo_atts("a='1' b = \"2\" ").unwrap();
+        assert_eq!("", rest);
+        assert_eq!(
+            vec![
+                model::PseudoAttribute::from(("a", vec![model::AttributeValue::from("1")])),
+                model::PseudoAttribute::from(("b", vec![model::AttributeValue::from("2")])),
+            ],
+            ret
+        );
+
+        let (rest, ret) = pseudo_atts("a='1'b='2'").unwrap();
+        assert_eq!("b='2'", rest);
+        assert_eq!(1, ret.len());
+
+        let (rest, ret) = pseudo_atts("a=1").unwrap();
+        assert_eq!("a=1", rest);
+        assert_eq!(0, ret.len());
+    }
+
+    #[test]
+    fn test_pseudo_att_value() {
+        let (rest, ret) = pseudo_att_value("\"a&amp;&#x41;\"").unwrap();
+        assert_eq!("", rest);
+        assert_eq!(
+            vec![
+                model::AttributeValue::from("a"),
+                model::AttributeValue::from(model::Reference::Entity("amp")),
+                model::AttributeValue::from(model::Reference::Character("41", 16)),
+            ],
+            ret
+        );
+
+        let (rest, ret) = pseudo_att_value("'\"'").unwrap();
+        assert_eq!("", rest);
+        assert_eq!(vec![model::AttributeValue::from("\"")], ret);
+
+        let err = pseudo_att_value("'&aaa;'").err().unwrap();
+        assert_eq!(
+            nom::Err::Error(nom::error::Error::new("&aaa;'", ErrorKind::Tag)),
+            err
+        );
+    }
+
+    #[test]
+    fn test_visitor() {
+        #[derive(Default)]
+        struct Collector<'a> {
+            events: Vec<String>,
+            texts: Vec<&'a str>,
+        }
+
+        impl<'a> visit::Visitor<'a> for Collector<'a> {
+            fn visit_declaration_att(&mut self, value: &model::DeclarationAtt<'a>) {
+                self.events.push(format!("attlist {}", value.name));
+            }
+
+            fn visit_declaration_entity(&mut self, value: &model::DeclarationEntity<'a>) {
+                self.events.push(format!("entity {}", value.name()));
+            }
+
+            fn visit_element(&mut self, value: &model::Element<'a>) {
+                self.events.push(format!("<{}>", value.name));
+                visit::walk_element(self, value);
+                self.events.push(format!("</{}>", value.name));
+            }
+
+            fn visit_attribute(&mut self, value: &model::Attribute<'a>) {
+                self.events.push(format!("@{}", value.qualified_name()));
+            }
+
+            fn visit_text(&mut self, value: &'a str) {
+                self.texts.push(value);
+            }
+
+            fn visit_comment(&mut self, value: &model::Comment<'a>) {
+                self.events.push(format!("<!--{}-->", value.value));
+            }
+        }
+
+        let xml = "<!DOCTYPE r [<!ATTLIST r a CDATA #IMPLIED><!ENTITY e 'v'>]><r xmlns:p='u' p:a='1'>t<p:c/>&e;u</r><!--z-->";
+        let (_, doc) = document(xml).unwrap();
+
+        let mut collector = Collector::default();
+        visit::Visitor::visit_document(&mut collector, &doc);
+        assert_eq!(
+            vec![
+                "attlist r",
+                "entity e",
+                "<r>",
+                "@xmlns:p",
+                "@p:a",
+                "<p:c>",
+                "</p:c>",
+                "</r>",
+                "<!--z-->",
+            ],
+            collector.events
+        );
+        assert_eq!(vec!["t", "u"], collector.texts);
+
+        let root = doc.root();
+        assert_eq!(None, root.name.prefix());
+        assert_eq!("r", root.name.local_part());
+        assert!(root.attribute("p:a").is_some());
+        assert!(root.attribute("a").is_none());
+        assert_eq!(2, root.children().count());
+        assert!(!root.is_empty_tag());
+        assert_eq!(2, doc.doctype().unwrap().markups().count());
+    }
 }
 
 // -----------------------------------------------------------------------------------------------
@@ -1,7 +1,14 @@
+//! Syntax tree produced by the parser.
+//!
+//! Each type mirrors a production of the XML 1.0 and Namespaces in XML 1.0
+//! grammars. Slices borrow the input verbatim, references are not resolved and
+//! whitespace is kept, so tools can work on the source as written.
+
 use xml_nom::model::QName;
 
 // -----------------------------------------------------------------------------------------------
 
+/// [\[41\] Attribute](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-Attribute)
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct Attribute<'a> {
     pub name: AttributeName<'a>,
@@ -15,6 +22,16 @@ impl<'a> From<(AttributeName<'a>, Vec<AttributeValue<'a>>)> for Attribute<'a> {
     }
 }
 
+impl<'a> Attribute<'a> {
+    pub fn qualified_name(&self) -> String {
+        match &self.name {
+            AttributeName::DefaultNamespace => "xmlns".to_string(),
+            AttributeName::Namespace(v) => format!("xmlns:{}", v),
+            AttributeName::QName(v) => v.to_string(),
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------------------------
 
 #[derive(Clone, Debug, Default, PartialEq)]
@@ -91,6 +108,10 @@ impl<'a> From<&'a str> for Comment<'a> {
 
 // -----------------------------------------------------------------------------------------------
 
+/// [\[43\] content](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-content)
+///
+/// `head` is the character data before the first child, `tail` of each cell
+/// the character data following it.
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct Content<'a> {
     pub head: Option<&'a str>,
@@ -168,6 +189,7 @@ impl<'a> From<Comment<'a>> for Contents<'a> {
 
 // -----------------------------------------------------------------------------------------------
 
+/// [\[52\] AttlistDecl](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-AttlistDecl)
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct DeclarationAtt<'a> {
     pub name: QName<'a>,
@@ -277,39 +299,61 @@ impl<'a> Default for DeclarationContentItem<'a> {
 
 // -----------------------------------------------------------------------------------------------
 
+/// [\[28\] doctypedecl](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-doctypedecl)
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct DeclarationDoc<'a> {
     pub name: QName<'a>,
     pub external_id: Option<ExternalId<'a>>,
     pub internal_subset: Vec<InternalSubset<'a>>,
+    pub internal_subset_literal: Option<&'a str>,
+    /// Whitespace between ']' and '>'.
+    pub internal_subset_space: &'a str,
+    /// Declarations of the external subset, set by the caller that loads it.
+    pub external_subset: Vec<InternalSubset<'a>>,
 }
 
 impl<'a>
     From<(
         QName<'a>,
         Option<ExternalId<'a>>,
-        Option<Vec<InternalSubset<'a>>>,
+        Option<(&'a str, Vec<InternalSubset<'a>>, &'a str)>,
     )> for DeclarationDoc<'a>
 {
     fn from(
         value: (
             QName<'a>,
             Option<ExternalId<'a>>,
-            Option<Vec<InternalSubset<'a>>>,
+            Option<(&'a str, Vec<InternalSubset<'a>>, &'a str)>,
         ),
     ) -> Self {
         let (name, external_id, int_subsets) = value;
-        let internal_subset = int_subsets.unwrap_or_default();
+        let (internal_subset_literal, internal_subset, internal_subset_space) = match int_subsets {
+            Some((literal, subsets, space)) => (Some(literal), subsets, space),
+            None => (None, vec![], ""),
+        };
         DeclarationDoc {
             name,
             external_id,
             internal_subset,
+            internal_subset_literal,
+            internal_subset_space,
+            external_subset: vec![],
         }
     }
 }
 
+impl<'a> DeclarationDoc<'a> {
+    pub fn markups(&self) -> impl Iterator<Item = &DeclarationMarkup<'a>> {
+        self.internal_subset.iter().filter_map(|v| match v {
+            InternalSubset::Markup(v) => Some(v),
+            _ => None,
+        })
+    }
+}
+
 // -----------------------------------------------------------------------------------------------
 
+/// [\[70\] EntityDecl](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-EntityDecl)
 #[derive(Clone, Debug, PartialEq)]
 pub enum DeclarationEntity<'a> {
     GeneralEntity(DeclarationGeneralEntity<'a>),
@@ -334,8 +378,18 @@ impl<'a> From<DeclarationParameterEntity<'a>> for DeclarationEntity<'a> {
     }
 }
 
+impl<'a> DeclarationEntity<'a> {
+    pub fn name(&self) -> &'a str {
+        match self {
+            DeclarationEntity::GeneralEntity(v) => v.name,
+            DeclarationEntity::ParameterEntity(v) => v.name,
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------------------------
 
+/// [\[45\] elementdecl](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-elementdecl)
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct DeclarationElement<'a> {
     pub name: QName<'a>,
@@ -445,6 +499,7 @@ impl<'a> DeclarationMarkup<'a> {
 
 // -----------------------------------------------------------------------------------------------
 
+/// [\[82\] NotationDecl](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-NotationDecl)
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct DeclarationNotation<'a> {
     pub name: &'a str,
@@ -547,6 +602,7 @@ impl<'a> From<(&'a str, Option<&'a str>, Option<bool>)> for DeclarationXml<'a> {
 
 // -----------------------------------------------------------------------------------------------
 
+/// [\[1\] document](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-document)
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct Document<'a> {
     pub prolog: Prolog<'a>,
@@ -565,13 +621,28 @@ impl<'a> From<(Prolog<'a>, Element<'a>, Vec<Misc<'a>>)> for Document<'a> {
     }
 }
 
+impl<'a> Document<'a> {
+    pub fn doctype(&self) -> Option<&DeclarationDoc<'a>> {
+        self.prolog.declaration_doc.as_ref()
+    }
+
+    pub fn root(&self) -> &Element<'a> {
+        &self.element
+    }
+}
+
 // -----------------------------------------------------------------------------------------------
 
+/// [\[39\] element](https://www.w3.org/TR/2008/REC-xml-20081126/#NT-element)
+///
+/// `content` is `None` for an empty-element tag. `spaces` holds the whitespace
+/// before each attribute followed by the one before the closing `>`.
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct Element<'a> {
     pub name: QName<'a>,
     pub attributes: Vec<Attribute<'a>>,
     pub content: Option<Content<'a>>,
+    pub spaces: Vec<&'a str>,
 }
 
 impl<'a> From<(QName<'a>, Vec<Attribute<'a>>)> for Element<'a> {
@@ -581,15 +652,47 @@ impl<'a> From<(QName<'a>, Vec<Attribute<'a>>)> for Element<'a> {
             name,
             attributes,
             content: None,
+            spaces: vec![],
         }
     }
 }
 
+impl<'a> From<(QName<'a>, Vec<(&'a str, Attribute<'a>)>, &'a str)> for Element<'a> {
+    fn from(value: (QName<'a>, Vec<(&'a str, Attribute<'a>)>, &'a str)) -> Self {
+        let (name, attributes, tail) = value;
+        let (mut spaces, attributes): (Vec<&'a str>, Vec<Attribute<'a>>) =
+            attributes.into_iter().unzip();
+        spaces.push(tail);
+        Element::from((name, attributes)).set_spaces(spaces)
+    }
+}
+
 impl<'a> Element<'a> {
     pub fn set_content(mut self, content: Content<'a>) -> Self {
         self.content = Some(content);
         self
     }
+
+    pub fn set_spaces(mut self, spaces: Vec<&'a str>) -> Self {
+        self.spaces = spaces;
+        self
+    }
+
+    pub fn attribute(&self, qualified_name: &str) -> Option<&Attribute<'a>> {
+        self.attributes
+            .iter()
+            .find(|v| v.qualified_name() == qualified_name)
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = &Contents<'a>> {
+        self.content
+            .iter()
+            .flat_map(|v| v.children.iter().map(|v| &v.child))
+    }
+
+    pub fn is_empty_tag(&self) -> bool {
+        self.content.is_none()
+    }
 }
 
 // -----------------------------------------------------------------------------------------------
@@ -762,6 +865,21 @@ impl<'a>
 
 // -----------------------------------------------------------------------------------------------
 
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PseudoAttribute<'a> {
+    pub name: &'a str,
+    pub value: Vec<AttributeValue<'a>>,
+}
+
+impl<'a> From<(&'a str, Vec<AttributeValue<'a>>)> for PseudoAttribute<'a> {
+    fn from(value: (&'a str, Vec<AttributeValue<'a>>)) -> Self {
+        let (name, value) = value;
+        PseudoAttribute { name, value }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Reference<'a> {
     Character(&'a str, u32),
@@ -789,3 +907,26 @@ impl<'a> Reference<'a> {
 }
 
 // -----------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sniff<'a> {
+    pub declaration_xml: Option<DeclarationXml<'a>>,
+    pub root: QName<'a>,
+    pub namespace: Option<String>,
+}
+
+impl<'a> Sniff<'a> {
+    pub fn version(&self) -> Option<&'a str> {
+        self.declaration_xml.as_ref().map(|v| v.version)
+    }
+
+    pub fn encoding(&self) -> Option<&'a str> {
+        self.declaration_xml.as_ref().and_then(|v| v.encoding)
+    }
+
+    pub fn standalone(&self) -> Option<bool> {
+        self.declaration_xml.as_ref().and_then(|v| v.standalone)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
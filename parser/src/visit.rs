@@ -0,0 +1,120 @@
+//! Depth-first traversal of the syntax tree in document order.
+//!
+//! Override the `visit_*` methods of interest and call the matching `walk_*`
+//! function to keep descending.
+
+use super::model;
+
+pub trait Visitor<'a> {
+    fn visit_document(&mut self, value: &model::Document<'a>) {
+        walk_document(self, value);
+    }
+
+    fn visit_declaration_xml(&mut self, _: &model::DeclarationXml<'a>) {}
+
+    fn visit_declaration_doc(&mut self, value: &model::DeclarationDoc<'a>) {
+        walk_declaration_doc(self, value);
+    }
+
+    fn visit_declaration_element(&mut self, _: &model::DeclarationElement<'a>) {}
+
+    fn visit_declaration_att(&mut self, _: &model::DeclarationAtt<'a>) {}
+
+    fn visit_declaration_entity(&mut self, _: &model::DeclarationEntity<'a>) {}
+
+    fn visit_declaration_notation(&mut self, _: &model::DeclarationNotation<'a>) {}
+
+    fn visit_pe_reference(&mut self, _: &'a str) {}
+
+    fn visit_element(&mut self, value: &model::Element<'a>) {
+        walk_element(self, value);
+    }
+
+    fn visit_attribute(&mut self, _: &model::Attribute<'a>) {}
+
+    fn visit_text(&mut self, _: &'a str) {}
+
+    fn visit_reference(&mut self, _: &model::Reference<'a>) {}
+
+    fn visit_cdata(&mut self, _: &model::CData<'a>) {}
+
+    fn visit_pi(&mut self, _: &model::PI<'a>) {}
+
+    fn visit_comment(&mut self, _: &model::Comment<'a>) {}
+
+    fn visit_whitespace(&mut self, _: &'a str) {}
+}
+
+// -----------------------------------------------------------------------------------------------
+
+pub fn walk_document<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, value: &model::Document<'a>) {
+    if let Some(declaration) = value.prolog.declaration_xml.as_ref() {
+        visitor.visit_declaration_xml(declaration);
+    }
+    walk_miscs(visitor, value.prolog.heads.as_slice());
+    if let Some(declaration) = value.prolog.declaration_doc.as_ref() {
+        visitor.visit_declaration_doc(declaration);
+    }
+    walk_miscs(visitor, value.prolog.tails.as_slice());
+    visitor.visit_element(&value.element);
+    walk_miscs(visitor, value.miscs.as_slice());
+}
+
+pub fn walk_declaration_doc<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    value: &model::DeclarationDoc<'a>,
+) {
+    for subset in value.internal_subset.iter() {
+        match subset {
+            model::InternalSubset::Markup(markup) => match markup {
+                model::DeclarationMarkup::Element(v) => visitor.visit_declaration_element(v),
+                model::DeclarationMarkup::Attributes(v) => visitor.visit_declaration_att(v),
+                model::DeclarationMarkup::Entity(v) => visitor.visit_declaration_entity(v),
+                model::DeclarationMarkup::Notation(v) => visitor.visit_declaration_notation(v),
+                model::DeclarationMarkup::PI(v) => visitor.visit_pi(v),
+                model::DeclarationMarkup::Commnect(v) => visitor.visit_comment(v),
+            },
+            model::InternalSubset::ParameterEntityReference(v) => visitor.visit_pe_reference(v),
+            model::InternalSubset::Whitespace(v) => visitor.visit_whitespace(v),
+        }
+    }
+}
+
+pub fn walk_element<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, value: &model::Element<'a>) {
+    for attribute in value.attributes.iter() {
+        visitor.visit_attribute(attribute);
+    }
+
+    let content = match value.content.as_ref() {
+        Some(v) => v,
+        None => return,
+    };
+
+    walk_text(visitor, content.head);
+    for cell in content.children.iter() {
+        match &cell.child {
+            model::Contents::Element(v) => visitor.visit_element(v),
+            model::Contents::Reference(v) => visitor.visit_reference(v),
+            model::Contents::CData(v) => visitor.visit_cdata(v),
+            model::Contents::PI(v) => visitor.visit_pi(v),
+            model::Contents::Comment(v) => visitor.visit_comment(v),
+        }
+        walk_text(visitor, cell.tail);
+    }
+}
+
+fn walk_miscs<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, value: &[model::Misc<'a>]) {
+    for misc in value {
+        match misc {
+            model::Misc::Comment(v) => visitor.visit_comment(v),
+            model::Misc::PI(v) => visitor.visit_pi(v),
+            model::Misc::Whitespace(v) => visitor.visit_whitespace(v),
+        }
+    }
+}
+
+fn walk_text<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, value: Option<&'a str>) {
+    if let Some(text) = value.filter(|v| !v.is_empty()) {
+        visitor.visit_text(text);
+    }
+}
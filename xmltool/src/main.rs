@@ -0,0 +1,321 @@
+use std::error::Error;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+use xml_dom::PrettyPrint;
+use xml_parser::nom;
+
+enum Command {
+    Format,
+    Check,
+    Validate,
+    Canonicalize,
+    Query,
+}
+
+enum Output {
+    Value,
+    Node,
+    Location,
+}
+
+struct Argument {
+    command: Command,
+    files: Vec<PathBuf>,
+    minify: bool,
+    with_comments: bool,
+    expr: Option<String>,
+    ns: Vec<(Option<String>, String)>,
+    output: Output,
+    no_indent: bool,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let arg = args()?;
+
+    if let Command::Query = arg.command {
+        return query(&arg);
+    }
+
+    if arg.files.len() > 1 {
+        return Err("Specify `file path` only once.".into());
+    }
+    let contents = read(arg.files.first().map(|v| v.as_path()))?;
+
+    match arg.command {
+        Command::Format => format(contents.as_str(), arg.minify)?,
+        Command::Check => check(contents.as_str())?,
+        Command::Validate => validate(contents.as_str())?,
+        Command::Canonicalize => canonicalize(contents.as_str(), arg.with_comments)?,
+        Command::Query => unreachable!(),
+    }
+
+    Ok(())
+}
+
+fn format(contents: &str, minify: bool) -> Result<(), Box<dyn Error>> {
+    let context = xml_dom::Context::builder()
+        .text_expanded(true)
+        .whitespace_stripped(minify)
+        .build();
+    let dom = document(contents, context)?;
+
+    let mut buf = io::BufWriter::new(io::stdout().lock());
+    if minify {
+        buf.write_fmt(format_args!("{}\n", dom))?;
+    } else {
+        dom.pretty(&mut buf)?;
+        buf.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+fn check(contents: &str) -> Result<(), Box<dyn Error>> {
+    let rest = match xml_parser::document(contents) {
+        Ok((rest, _)) => rest,
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => e.input,
+        Err(nom::Err::Incomplete(_)) => "",
+    };
+    if !rest.is_empty() {
+        let (line, column) = position(contents, contents.len() - rest.len());
+        return Err(format!("{}:{}: not well-formed", line, column).into());
+    }
+
+    // Namespace and entity constraints are checked while building the tree.
+    document(contents, xml_dom::Context::default())?;
+
+    println!("well-formed");
+    Ok(())
+}
+
+fn validate(contents: &str) -> Result<(), Box<dyn Error>> {
+    let context = xml_dom::Context::builder().spans_tracked(true).build();
+    let dom = document(contents, context)?;
+
+    let diagnostics = xml_dom::validation::validate_dtd(&dom)?;
+    if diagnostics.is_empty() {
+        println!("valid");
+        return Ok(());
+    }
+
+    let mut buf = io::BufWriter::new(io::stdout().lock());
+    for diagnostic in diagnostics.iter() {
+        let (line, column) = diagnostic
+            .span()
+            .map(|v| position(contents, v.start()))
+            .unwrap_or_default();
+        buf.write_fmt(format_args!(
+            "{}:{}: {}: {}\n",
+            line,
+            column,
+            diagnostic.path(),
+            diagnostic.message()
+        ))?;
+    }
+    buf.flush()?;
+
+    Err(format!("{} error(s)", diagnostics.len()).into())
+}
+
+fn canonicalize(contents: &str, with_comments: bool) -> Result<(), Box<dyn Error>> {
+    let context = xml_dom::Context::builder().text_expanded(true).build();
+    let dom = document(contents, context)?;
+
+    let value = xml_dom::canonical::canonicalize(&dom, with_comments)?;
+    print!("{}", value);
+    Ok(())
+}
+
+fn query(arg: &Argument) -> Result<(), Box<dyn Error>> {
+    let expr = arg.expr.as_deref().ok_or("Specify `--xpath`")?;
+
+    let mut targets = vec![];
+    if arg.files.is_empty() {
+        targets.push(None);
+    }
+    targets.extend(arg.files.iter().map(|v| Some(v.as_path())));
+
+    let mut buf = io::BufWriter::new(io::stdout().lock());
+    for target in targets {
+        // Prefix each match with its file name when searching more than one file.
+        let label = match target {
+            Some(path) if arg.files.len() > 1 => format!("{}:", path.display()),
+            _ => String::new(),
+        };
+
+        let contents = read(target)?;
+        let context = xml_dom::Context::builder()
+            .text_expanded(true)
+            .spans_tracked(true)
+            .build();
+        let dom = document(contents.as_str(), context)?;
+
+        let mut context = xml_xpath::eval::model::Context::default();
+        for n in arg.ns.as_slice() {
+            context.add_ns(n.0.as_deref(), n.1.as_str());
+        }
+
+        let value = xml_xpath::query(&dom, expr, &mut context).map_err(|v| v.to_string())?;
+
+        let nodes = match value {
+            xml_xpath::eval::model::Value::Node(nodes) => nodes,
+            v => {
+                buf.write_fmt(format_args!("{}{}\n", label, v))?;
+                continue;
+            }
+        };
+
+        for node in nodes {
+            buf.write_all(label.as_bytes())?;
+            match arg.output {
+                Output::Value => {
+                    let value = xml_xpath::eval::model::Value::Node(vec![node]);
+                    let text = String::try_from(&value).map_err(|v| v.to_string())?;
+                    buf.write_fmt(format_args!("{}\n", text))?;
+                }
+                Output::Node => {
+                    if arg.no_indent {
+                        buf.write_fmt(format_args!("{}\n", node))?;
+                    } else {
+                        node.pretty(&mut buf)?;
+                        buf.write_all(b"\n")?;
+                    }
+                }
+                Output::Location => {
+                    let (line, column) = node
+                        .source_span()
+                        .map(|v| position(contents.as_str(), v.start()))
+                        .unwrap_or_default();
+                    buf.write_fmt(format_args!(
+                        "{}:{}: {}\n",
+                        line,
+                        column,
+                        node.location_path()
+                    ))?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn args() -> Result<Argument, Box<dyn Error>> {
+    let mut files = vec![];
+    let mut minify = false;
+    let mut with_comments = false;
+    let mut expr = None;
+    let mut ns = vec![];
+    let mut output = Output::Node;
+    let mut no_indent = false;
+
+    let mut args = env::args();
+    args.next(); // skip exe.
+
+    let command = match args.next().as_deref() {
+        Some("format") => Command::Format,
+        Some("check") => Command::Check,
+        Some("validate") => Command::Validate,
+        Some("c14n") => Command::Canonicalize,
+        Some("query") => Command::Query,
+        _ => return Err("Specify `format`, `check`, `validate`, `c14n` or `query`.".into()),
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--minify" => {
+                minify = true;
+            }
+            "--with-comments" => {
+                with_comments = true;
+            }
+            "--xpath" => {
+                if expr.is_some() {
+                    return Err("Specify `--xpath` only once.".into());
+                }
+
+                expr = Some(args.next().ok_or("Specify value of `--xpath`.")?);
+            }
+            "--setns" => {
+                let n = args.next().ok_or("Specify value of `--setns`.")?;
+
+                let (prefix, uri) = n
+                    .split_once('=')
+                    .ok_or("Specify `xmlns:prefix=uri` format of `--setns`.")?;
+                let (prefix, local_part) = prefix.split_once(':').unwrap_or(("", prefix));
+
+                if prefix.is_empty() && local_part != "xmlns" {
+                    return Err("Specify `xmlns` of `--setns` for default namespace.".into());
+                } else if !prefix.is_empty() && prefix != "xmlns" {
+                    return Err("Specify value of `--setns` with `xmlns` prefix.".into());
+                }
+
+                let name = if local_part == "xmlns" {
+                    None
+                } else {
+                    Some(local_part.to_string())
+                };
+
+                ns.push((name, uri.to_string()));
+            }
+            "--output" => {
+                output = match args.next().as_deref() {
+                    Some("value") => Output::Value,
+                    Some("node") => Output::Node,
+                    Some("location") => Output::Location,
+                    _ => return Err("Specify `value`, `node` or `location` of `--output`.".into()),
+                };
+            }
+            "--no-indent" => {
+                no_indent = true;
+            }
+            _ => {
+                files.push(PathBuf::from(arg));
+            }
+        }
+    }
+
+    Ok(Argument {
+        command,
+        files,
+        minify,
+        with_comments,
+        expr,
+        ns,
+        output,
+        no_indent,
+    })
+}
+
+fn read(path: Option<&Path>) -> Result<String, Box<dyn Error>> {
+    let contents = match path {
+        Some(path) => fs::read_to_string(path)?,
+        _ => {
+            let mut contents = vec![];
+            io::stdin().read_to_end(&mut contents)?;
+            String::from_utf8(contents)?
+        }
+    };
+
+    Ok(contents)
+}
+
+fn document(
+    contents: &str,
+    context: xml_dom::Context,
+) -> Result<xml_dom::XmlDocument, Box<dyn Error>> {
+    let (rest, dom) = xml_dom::XmlDocument::from_raw_with_context(contents, context)?;
+    if !rest.is_empty() {
+        return Err("invalid format XML".into());
+    }
+
+    Ok(dom)
+}
+
+fn position(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
@@ -0,0 +1,97 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_xmltool"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The command may exit before reading its input, e.g. on a usage error.
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn test_format() {
+    let output = run(&["format"], "<root><a>x</a><b/></root>");
+    assert!(output.status.success());
+    assert_eq!(
+        "<root>\n    <a>x</a>\n    <b />\n</root>\n",
+        stdout(&output)
+    );
+
+    let output = run(
+        &["format", "--minify"],
+        "<root>\n  <a>x</a>\n  <b/>\n</root>",
+    );
+    assert!(output.status.success());
+    assert_eq!("<root><a>x</a><b /></root>\n", stdout(&output));
+
+    let output = run(&["format"], "<root>");
+    assert!(!output.status.success());
+    assert_eq!("", stdout(&output));
+}
+
+#[test]
+fn test_check() {
+    let output = run(&["check"], "<root><a/></root>");
+    assert!(output.status.success());
+    assert_eq!("well-formed\n", stdout(&output));
+
+    let output = run(&["check"], "<root>\n  <a></b>\n</root>");
+    assert!(!output.status.success());
+    assert_eq!("", stdout(&output));
+    assert!(stderr(&output).contains("2:6: not well-formed"));
+}
+
+#[test]
+fn test_validate() {
+    let dtd = "<!DOCTYPE root [<!ELEMENT root (a)><!ELEMENT a EMPTY>]>";
+
+    let output = run(&["validate"], &format!("{}<root><a/></root>", dtd));
+    assert!(output.status.success());
+    assert_eq!("valid\n", stdout(&output));
+
+    let output = run(&["validate"], &format!("{}\n<root><b/></root>", dtd));
+    assert!(!output.status.success());
+    assert_eq!(
+        "2:7: /root[1]/b[1]: element `b` is not allowed here\n\
+        2:7: /root[1]/b[1]: element `b` is not declared\n",
+        stdout(&output)
+    );
+    assert!(stderr(&output).contains("2 error(s)"));
+}
+
+#[test]
+fn test_c14n() {
+    let input = "<?xml version='1.0'?><!--c--><root b='2' a='1'><e/></root>";
+
+    let output = run(&["c14n"], input);
+    assert!(output.status.success());
+    assert_eq!("<root a=\"1\" b=\"2\"><e></e></root>", stdout(&output));
+
+    let output = run(&["c14n", "--with-comments"], input);
+    assert!(output.status.success());
+    assert_eq!(
+        "<!--c-->\n<root a=\"1\" b=\"2\"><e></e></root>",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn test_unknown_command() {
+    let output = run(&["lint"], "<root />");
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Specify `format`"));
+}
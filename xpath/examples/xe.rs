@@ -26,8 +26,8 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let dom = document(arg.file.as_deref())?;
 
-    let value = xml_xpath::query(dom.clone(), arg.expr.as_str(), &mut context)
-        .map_err(|v| v.to_string())?;
+    let value =
+        xml_xpath::query(&dom, arg.expr.as_str(), &mut context).map_err(|v| v.to_string())?;
 
     match value {
         xml_xpath::eval::model::Value::Boolean(_) => {
@@ -238,7 +238,7 @@ where
             let n = node
                 .owner_document()
                 .unwrap()
-                .create_comment(v.data()?.as_str());
+                .create_comment(v.data()?.as_str())?;
             node.append_child(n.as_node())?;
         }
         xml_dom::XmlNode::Element(v) => {
@@ -22,7 +22,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     let dom = document(arg.file.as_deref())?;
 
     let value =
-        xml_xpath::query(dom, arg.expr.as_str(), &mut context).map_err(|v| v.to_string())?;
+        xml_xpath::query(&dom, arg.expr.as_str(), &mut context).map_err(|v| v.to_string())?;
 
     match value {
         xml_xpath::eval::model::Value::Boolean(v) => {
@@ -35,7 +35,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                     buf.write_fmt(format_args!("{}\n", node))?;
                 } else {
                     node.pretty(&mut buf)?;
-                    buf.write_all(&[b'\n'])?;
+                    buf.write_all(b"\n")?;
                 }
             }
         }
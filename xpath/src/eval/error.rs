@@ -2,6 +2,7 @@
 pub enum Error {
     Dom(xml_dom::error::Error),
     InvalidType,
+    InvalidArgument(String),
     InvalidArgumentCount(String),
     NotFoundFunction(String),
     NotFoundNamespace(String),
@@ -0,0 +1,747 @@
+use super::error;
+use super::func::Entry;
+use super::model::{self, AsValue};
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+use xml_dom::{self as dom, AsNode, DocumentMut, NodeMut};
+use xml_nom as nom;
+
+pub const STRINGS: &str = "http://exslt.org/strings";
+pub const MATH: &str = "http://exslt.org/math";
+pub const DATES_AND_TIMES: &str = "http://exslt.org/dates-and-times";
+pub const SETS: &str = "http://exslt.org/sets";
+
+/// Upper bound on the number of characters `str:padding` will produce.
+pub const MAX_PADDING_LENGTH: usize = 1 << 24;
+
+pub fn table() -> Vec<Entry> {
+    vec![
+        Entry::new("tokenize", STRINGS, 1..2, tokenize),
+        Entry::new("split", STRINGS, 1..2, split),
+        Entry::new("concat", STRINGS, 1..1, str_concat),
+        Entry::new("padding", STRINGS, 1..2, padding),
+        Entry::new("align", STRINGS, 2..3, align),
+        Entry::new("min", MATH, 1..1, min),
+        Entry::new("max", MATH, 1..1, max),
+        Entry::new("highest", MATH, 1..1, highest),
+        Entry::new("lowest", MATH, 1..1, lowest),
+        Entry::new("abs", MATH, 1..1, |a, n, c| unary(a, n, c, f64::abs)),
+        Entry::new("sqrt", MATH, 1..1, |a, n, c| unary(a, n, c, f64::sqrt)),
+        Entry::new("power", MATH, 2..2, |a, n, c| binary(a, n, c, f64::powf)),
+        Entry::new("log", MATH, 1..1, |a, n, c| unary(a, n, c, f64::ln)),
+        Entry::new("exp", MATH, 1..1, |a, n, c| unary(a, n, c, f64::exp)),
+        Entry::new("sin", MATH, 1..1, |a, n, c| unary(a, n, c, f64::sin)),
+        Entry::new("cos", MATH, 1..1, |a, n, c| unary(a, n, c, f64::cos)),
+        Entry::new("tan", MATH, 1..1, |a, n, c| unary(a, n, c, f64::tan)),
+        Entry::new("asin", MATH, 1..1, |a, n, c| unary(a, n, c, f64::asin)),
+        Entry::new("acos", MATH, 1..1, |a, n, c| unary(a, n, c, f64::acos)),
+        Entry::new("atan", MATH, 1..1, |a, n, c| unary(a, n, c, f64::atan)),
+        Entry::new("atan2", MATH, 2..2, |a, n, c| binary(a, n, c, f64::atan2)),
+        Entry::new("random", MATH, 0..0, random),
+        Entry::new("constant", MATH, 2..2, constant),
+        Entry::new("date-time", DATES_AND_TIMES, 0..0, date_time),
+        Entry::new("date", DATES_AND_TIMES, 0..1, date),
+        Entry::new("time", DATES_AND_TIMES, 0..1, time),
+        Entry::new("year", DATES_AND_TIMES, 0..1, year),
+        Entry::new("leap-year", DATES_AND_TIMES, 0..1, leap_year),
+        Entry::new("month-in-year", DATES_AND_TIMES, 0..1, month_in_year),
+        Entry::new("day-in-month", DATES_AND_TIMES, 0..1, day_in_month),
+        Entry::new("day-in-year", DATES_AND_TIMES, 0..1, day_in_year),
+        Entry::new("day-in-week", DATES_AND_TIMES, 0..1, day_in_week),
+        Entry::new("hour-in-day", DATES_AND_TIMES, 0..1, hour_in_day),
+        Entry::new("minute-in-hour", DATES_AND_TIMES, 0..1, minute_in_hour),
+        Entry::new("second-in-minute", DATES_AND_TIMES, 0..1, second_in_minute),
+        Entry::new("difference", SETS, 2..2, difference),
+        Entry::new("intersection", SETS, 2..2, intersection),
+        Entry::new("distinct", SETS, 1..1, distinct),
+        Entry::new("has-same-node", SETS, 2..2, has_same_node),
+        Entry::new("leading", SETS, 2..2, leading),
+        Entry::new("trailing", SETS, 2..2, trailing),
+    ]
+}
+
+pub fn expanded_name(qname: &nom::model::QName) -> Option<dom::QName> {
+    if let nom::model::QName::Prefixed(p) = qname {
+        let uri = match p.prefix {
+            "str" => STRINGS,
+            "math" => MATH,
+            "date" => DATES_AND_TIMES,
+            "set" => SETS,
+            _ => return None,
+        };
+        Some(dom::QName::new(p.local_part, Some(p.prefix), Some(uri)))
+    } else {
+        None
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn tokenize(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    context: &mut model::Context,
+) -> error::Result<model::Value> {
+    let mut args = args.iter();
+    let s = String::try_from(args.next().unwrap())?;
+    let delimiters = match args.next() {
+        Some(v) => String::try_from(v)?,
+        None => " \t\n\r".to_string(),
+    };
+
+    let tokens = if delimiters.is_empty() {
+        s.chars().map(|v| v.to_string()).collect()
+    } else {
+        s.split(|c| delimiters.contains(c))
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string())
+            .collect()
+    };
+    tokens_value(tokens, context)
+}
+
+fn split(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    context: &mut model::Context,
+) -> error::Result<model::Value> {
+    let mut args = args.iter();
+    let s = String::try_from(args.next().unwrap())?;
+    let pattern = match args.next() {
+        Some(v) => String::try_from(v)?,
+        None => " ".to_string(),
+    };
+
+    let tokens = if pattern.is_empty() {
+        s.chars().map(|v| v.to_string()).collect()
+    } else {
+        s.split(pattern.as_str())
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string())
+            .collect()
+    };
+    tokens_value(tokens, context)
+}
+
+fn str_concat(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let mut s = String::new();
+    for node in node_set(args.first().unwrap())? {
+        s.push_str(&String::try_from(&model::Value::Node(vec![node.clone()]))?);
+    }
+    Ok(model::Value::Text(s))
+}
+
+fn padding(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let mut args = args.iter();
+    let length = f64::try_from(args.next().unwrap())?;
+    let s = match args.next() {
+        Some(v) => String::try_from(v)?,
+        None => " ".to_string(),
+    };
+
+    if length.is_nan() || length < 0f64 || length > MAX_PADDING_LENGTH as f64 {
+        return Err(error::Error::InvalidArgument(format!(
+            "padding({})",
+            length
+        )));
+    }
+
+    if s.is_empty() || length < 1f64 {
+        return Ok(model::Value::Text(String::new()));
+    }
+    let r = s.chars().cycle().take(length as usize).collect();
+    Ok(model::Value::Text(r))
+}
+
+fn align(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let mut args = args.iter();
+    let s = String::try_from(args.next().unwrap())?
+        .chars()
+        .collect::<Vec<char>>();
+    let padding = String::try_from(args.next().unwrap())?
+        .chars()
+        .collect::<Vec<char>>();
+    let alignment = match args.next() {
+        Some(v) => String::try_from(v)?,
+        None => "left".to_string(),
+    };
+
+    if padding.len() <= s.len() {
+        let r = s[..padding.len()].iter().collect();
+        return Ok(model::Value::Text(r));
+    }
+
+    let offset = match alignment.as_str() {
+        "right" => padding.len() - s.len(),
+        "center" => (padding.len() - s.len()) / 2,
+        _ => 0,
+    };
+    let mut r = padding.clone();
+    r[offset..offset + s.len()].copy_from_slice(&s);
+    Ok(model::Value::Text(r.iter().collect()))
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn min(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let numbers = numbers(node_set(args.first().unwrap())?)?;
+    Ok(extremum(&numbers, |a, b| a < b).as_value())
+}
+
+fn max(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let numbers = numbers(node_set(args.first().unwrap())?)?;
+    Ok(extremum(&numbers, |a, b| a > b).as_value())
+}
+
+fn highest(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let nodes = node_set(args.first().unwrap())?;
+    let numbers = numbers(nodes)?;
+    let value = extremum(&numbers, |a, b| a > b);
+    Ok(select(nodes, &numbers, value).as_value())
+}
+
+fn lowest(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let nodes = node_set(args.first().unwrap())?;
+    let numbers = numbers(nodes)?;
+    let value = extremum(&numbers, |a, b| a < b);
+    Ok(select(nodes, &numbers, value).as_value())
+}
+
+fn unary(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+    f: fn(f64) -> f64,
+) -> error::Result<model::Value> {
+    let arg = f64::try_from(args.first().unwrap())?;
+    Ok(model::Value::Number(f(arg)))
+}
+
+fn binary(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+    f: fn(f64, f64) -> f64,
+) -> error::Result<model::Value> {
+    let mut args = args.iter();
+    let arg1 = f64::try_from(args.next().unwrap())?;
+    let arg2 = f64::try_from(args.next().unwrap())?;
+    Ok(model::Value::Number(f(arg1, arg2)))
+}
+
+fn random(
+    _: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(now().as_nanos());
+    let value = (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64;
+    Ok(model::Value::Number(value))
+}
+
+fn constant(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let mut args = args.iter();
+    let name = String::try_from(args.next().unwrap())?;
+    let precision = f64::try_from(args.next().unwrap())?;
+
+    let value = match name.as_str() {
+        "PI" => std::f64::consts::PI,
+        "E" => std::f64::consts::E,
+        "SQRRT2" | "SQRT2" => std::f64::consts::SQRT_2,
+        "LN2" => std::f64::consts::LN_2,
+        "LN10" => std::f64::consts::LN_10,
+        "LOG2E" => std::f64::consts::LOG2_E,
+        "SQRT1_2" => std::f64::consts::FRAC_1_SQRT_2,
+        _ => return Ok(model::Value::Number(f64::NAN)),
+    };
+
+    if precision.is_nan() || precision < 1f64 {
+        return Ok(model::Value::Number(value.trunc()));
+    }
+    let r = format!("{:.*}", precision as usize, value);
+    Ok(model::Value::Number(r.parse::<f64>().unwrap_or(value)))
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn date_time(
+    _: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let seconds = now().as_secs() as i64;
+    let (year, month, day) = civil(seconds.div_euclid(86400));
+    let time = seconds.rem_euclid(86400);
+    Ok(model::Value::Text(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )))
+}
+
+fn date(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let r = date_time_arg(&args)?
+        .filter(|v| v.has_date)
+        .map(|v| format!("{:04}-{:02}-{:02}{}", v.year, v.month, v.day, v.zone))
+        .unwrap_or_default();
+    Ok(model::Value::Text(r))
+}
+
+fn time(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let r = date_time_arg(&args)?
+        .filter(|v| v.has_time)
+        .map(|v| format!("{:02}:{:02}:{}{}", v.hour, v.minute, v.second, v.zone))
+        .unwrap_or_default();
+    Ok(model::Value::Text(r))
+}
+
+fn year(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let r = date_time_arg(&args)?.filter(|v| v.has_date);
+    Ok(number(r.map(|v| v.year as f64)))
+}
+
+fn leap_year(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    match date_time_arg(&args)?.filter(|v| v.has_date) {
+        Some(v) => Ok(model::Value::Boolean(is_leap_year(v.year))),
+        None => Ok(model::Value::Number(f64::NAN)),
+    }
+}
+
+fn month_in_year(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let r = date_time_arg(&args)?.filter(|v| v.has_date);
+    Ok(number(r.map(|v| v.month as f64)))
+}
+
+fn day_in_month(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let r = date_time_arg(&args)?.filter(|v| v.has_date);
+    Ok(number(r.map(|v| v.day as f64)))
+}
+
+fn day_in_year(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let r = date_time_arg(&args)?.filter(|v| v.has_date);
+    Ok(number(r.map(|v| {
+        (days(v.year, v.month, v.day) - days(v.year, 1, 1) + 1) as f64
+    })))
+}
+
+fn day_in_week(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    // 1970-01-01 is Thursday, Sunday is 1.
+    let r = date_time_arg(&args)?.filter(|v| v.has_date);
+    Ok(number(r.map(|v| {
+        ((days(v.year, v.month, v.day) + 4).rem_euclid(7) + 1) as f64
+    })))
+}
+
+fn hour_in_day(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let r = date_time_arg(&args)?.filter(|v| v.has_time);
+    Ok(number(r.map(|v| v.hour as f64)))
+}
+
+fn minute_in_hour(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let r = date_time_arg(&args)?.filter(|v| v.has_time);
+    Ok(number(r.map(|v| v.minute as f64)))
+}
+
+fn second_in_minute(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let r = date_time_arg(&args)?.filter(|v| v.has_time);
+    Ok(number(r.and_then(|v| v.second.parse::<f64>().ok())))
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn difference(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let mut args = args.iter();
+    let nodes1 = node_set(args.next().unwrap())?;
+    let nodes2 = orders(node_set(args.next().unwrap())?);
+    let r = nodes1.iter().filter(|v| !nodes2.contains(&v.order()));
+    Ok(r.cloned().collect::<Vec<dom::XmlNode>>().as_value())
+}
+
+fn intersection(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let mut args = args.iter();
+    let nodes1 = node_set(args.next().unwrap())?;
+    let nodes2 = orders(node_set(args.next().unwrap())?);
+    let r = nodes1.iter().filter(|v| nodes2.contains(&v.order()));
+    Ok(r.cloned().collect::<Vec<dom::XmlNode>>().as_value())
+}
+
+fn distinct(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let mut values = HashSet::new();
+    let mut r = vec![];
+    for node in node_set(args.first().unwrap())? {
+        let value = String::try_from(&model::Value::Node(vec![node.clone()]))?;
+        if values.insert(value) {
+            r.push(node.clone());
+        }
+    }
+    Ok(r.as_value())
+}
+
+fn has_same_node(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let mut args = args.iter();
+    let nodes1 = node_set(args.next().unwrap())?;
+    let nodes2 = orders(node_set(args.next().unwrap())?);
+    let r = nodes1.iter().any(|v| nodes2.contains(&v.order()));
+    Ok(model::Value::Boolean(r))
+}
+
+fn leading(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let mut args = args.iter();
+    let nodes1 = node_set(args.next().unwrap())?;
+    let nodes2 = node_set(args.next().unwrap())?;
+    let r = match nodes2.first() {
+        Some(first) => match nodes1.iter().position(|v| v.order() == first.order()) {
+            Some(i) => nodes1[..i].to_vec(),
+            None => vec![],
+        },
+        None => nodes1.clone(),
+    };
+    Ok(r.as_value())
+}
+
+fn trailing(
+    args: Vec<model::Value>,
+    _: dom::XmlNode,
+    _: &mut model::Context,
+) -> error::Result<model::Value> {
+    let mut args = args.iter();
+    let nodes1 = node_set(args.next().unwrap())?;
+    let nodes2 = node_set(args.next().unwrap())?;
+    let r = match nodes2.first() {
+        Some(first) => match nodes1.iter().position(|v| v.order() == first.order()) {
+            Some(i) => nodes1[i + 1..].to_vec(),
+            None => vec![],
+        },
+        None => nodes1.clone(),
+    };
+    Ok(r.as_value())
+}
+
+// -----------------------------------------------------------------------------------------------
+
+struct DateTime {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: String,
+    zone: String,
+    has_date: bool,
+    has_time: bool,
+}
+
+impl DateTime {
+    fn parse(value: &str) -> Option<Self> {
+        let (date, time) = match value.split_once('T') {
+            Some((date, time)) => (Some(date), Some(time)),
+            None if value.contains(':') && !value.starts_with('-') => (None, Some(value)),
+            None => (Some(value), None),
+        };
+
+        let mut r = DateTime {
+            year: 0,
+            month: 1,
+            day: 1,
+            hour: 0,
+            minute: 0,
+            second: "0".to_string(),
+            zone: String::new(),
+            has_date: date.is_some(),
+            has_time: time.is_some(),
+        };
+
+        let rest = match time {
+            Some(time) => {
+                let (time, zone) = split_zone(time);
+                let mut fields = time.split(':');
+                r.hour = fixed(fields.next()?, 2)?;
+                r.minute = fixed(fields.next()?, 2)?;
+                let second = fields.next()?;
+                let whole = second.split('.').next()?;
+                fixed(whole, 2)?;
+                second.parse::<f64>().ok()?;
+                if fields.next().is_some() || r.hour > 24 || r.minute > 59 {
+                    return None;
+                }
+                r.second = second.to_string();
+                r.zone = zone.to_string();
+                date
+            }
+            None => {
+                let (date, zone) = split_zone(date?);
+                r.zone = zone.to_string();
+                Some(date)
+            }
+        };
+
+        if let Some(date) = rest {
+            let (negative, date) = match date.strip_prefix('-') {
+                Some(v) => (true, v),
+                None => (false, date),
+            };
+            let mut fields = date.split('-');
+            let year = fields.next()?;
+            if year.len() < 4 {
+                return None;
+            }
+            r.year = fixed(year, year.len())?;
+            if negative {
+                r.year = -r.year;
+            }
+            r.month = fixed(fields.next()?, 2)?;
+            r.day = fixed(fields.next()?, 2)?;
+            if fields.next().is_some()
+                || !(1..=12).contains(&r.month)
+                || r.day < 1
+                || days_in_month(r.year, r.month) < r.day
+            {
+                return None;
+            }
+        }
+
+        Some(r)
+    }
+}
+
+fn split_zone(value: &str) -> (&str, &str) {
+    if let Some(v) = value.strip_suffix('Z') {
+        return (v, "Z");
+    }
+
+    let len = value.len();
+    if len > 6 && matches!(&value[len - 6..len - 5], "+" | "-") && &value[len - 3..len - 2] == ":" {
+        return value.split_at(len - 6);
+    }
+
+    (value, "")
+}
+
+fn fixed(value: &str, len: usize) -> Option<i64> {
+    if value.len() == len && value.chars().all(|c| c.is_ascii_digit()) {
+        value.parse::<i64>().ok()
+    } else {
+        None
+    }
+}
+
+fn date_time_arg(args: &[model::Value]) -> error::Result<Option<DateTime>> {
+    match args.first() {
+        Some(v) => Ok(DateTime::parse(String::try_from(v)?.trim())),
+        None => {
+            let mut context = model::Context::default();
+            let now = date_time(vec![], dom::XmlDocument::new().as_node(), &mut context)?;
+            Ok(DateTime::parse(&String::try_from(&now)?))
+        }
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// days from 1970-01-01.
+fn days(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn now() -> std::time::Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn node_set(value: &model::Value) -> error::Result<&Vec<dom::XmlNode>> {
+    if let model::Value::Node(nodes) = value {
+        Ok(nodes)
+    } else {
+        Err(error::Error::InvalidType)
+    }
+}
+
+fn numbers(nodes: &[dom::XmlNode]) -> error::Result<Vec<f64>> {
+    nodes
+        .iter()
+        .map(|v| f64::try_from(&model::Value::Node(vec![v.clone()])))
+        .collect()
+}
+
+fn extremum(numbers: &[f64], better: fn(f64, f64) -> bool) -> f64 {
+    let mut r = match numbers.first() {
+        Some(v) => *v,
+        None => return f64::NAN,
+    };
+    for v in numbers {
+        if v.is_nan() {
+            return f64::NAN;
+        }
+        if better(*v, r) {
+            r = *v;
+        }
+    }
+    r
+}
+
+fn select(nodes: &[dom::XmlNode], numbers: &[f64], value: f64) -> Vec<dom::XmlNode> {
+    nodes
+        .iter()
+        .zip(numbers)
+        .filter(|(_, v)| **v == value)
+        .map(|(n, _)| n.clone())
+        .collect()
+}
+
+fn orders(nodes: &[dom::XmlNode]) -> HashSet<usize> {
+    nodes.iter().map(|v| v.order()).collect()
+}
+
+fn number(value: Option<f64>) -> model::Value {
+    model::Value::Number(value.unwrap_or(f64::NAN))
+}
+
+fn tokens_value(tokens: Vec<String>, context: &mut model::Context) -> error::Result<model::Value> {
+    let document = dom::XmlDocument::new();
+    let root = document.create_element("tokens")?;
+    document.append_child(root.as_node())?;
+
+    let mut nodes = vec![];
+    for token in tokens {
+        let element = document.create_element("token")?;
+        element.append_child(document.create_text_node(&token).as_node())?;
+        nodes.push(root.append_child(element.as_node())?);
+    }
+    context.push_document(document);
+    Ok(nodes.as_value())
+}
@@ -7,7 +7,7 @@ pub type XPathFunc =
     dyn Fn(Vec<model::Value>, dom::XmlNode, &mut model::Context) -> error::Result<model::Value>;
 
 pub fn table() -> Vec<Entry> {
-    vec![
+    let mut table = vec![
         Entry {
             local_part: "last".to_string(),
             namespace_uri: None,
@@ -170,7 +170,9 @@ pub fn table() -> Vec<Entry> {
             args: (1..1),
             call: Box::new(round),
         },
-    ]
+    ];
+    table.extend(super::exslt::table());
+    table
 }
 
 // -----------------------------------------------------------------------------------------------
@@ -183,6 +185,21 @@ pub struct Entry {
 }
 
 impl Entry {
+    pub fn new(
+        local_part: &str,
+        namespace_uri: &str,
+        args: Range<usize>,
+        call: impl Fn(Vec<model::Value>, dom::XmlNode, &mut model::Context) -> error::Result<model::Value>
+            + 'static,
+    ) -> Self {
+        Entry {
+            local_part: local_part.to_string(),
+            namespace_uri: Some(namespace_uri.to_string()),
+            args,
+            call: Box::new(call),
+        }
+    }
+
     pub fn local_part(&self) -> &str {
         self.local_part.as_str()
     }
@@ -268,8 +285,8 @@ fn local_name(
     };
 
     if let Some(node) = arg.first() {
-        if let Some((local_name, _, _)) = node.as_expanded_name()? {
-            Ok(model::Value::Text(local_name))
+        if let Some(name) = node.as_expanded_name()? {
+            Ok(model::Value::Text(name.local_name().to_string()))
         } else {
             Ok(model::Value::Text(String::new()))
         }
@@ -294,8 +311,10 @@ fn namespace_uri(
     };
 
     if let Some(node) = arg.first() {
-        if let Some((_, _, uri)) = node.as_expanded_name()? {
-            Ok(model::Value::Text(uri.unwrap_or_default()))
+        if let Some(name) = node.as_expanded_name()? {
+            Ok(model::Value::Text(
+                name.namespace_uri().unwrap_or_default().to_string(),
+            ))
         } else {
             Ok(model::Value::Text(String::new()))
         }
@@ -321,14 +340,7 @@ fn name(
 
     if let Some(node) = arg.first() {
         match node.as_expanded_name()? {
-            Some((local_name, Some(prefix), _)) => {
-                if prefix == "xmlns" {
-                    Ok(model::Value::Text(local_name))
-                } else {
-                    Ok(model::Value::Text(format!("{}:{}", prefix, local_name)))
-                }
-            }
-            Some((local_name, _, _)) => Ok(model::Value::Text(local_name)),
+            Some(name) => Ok(model::Value::Text(name.qualified_name())),
             _ => Ok(model::Value::Text(String::new())),
         }
     } else {
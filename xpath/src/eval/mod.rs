@@ -1,4 +1,5 @@
 pub mod error;
+pub mod exslt;
 pub mod func;
 pub mod model;
 
@@ -10,12 +11,20 @@ use xml_nom as nom;
 
 pub fn document(
     expr: &expr::Expr,
-    document: dom::XmlDocument,
+    document: &dom::XmlDocument,
     context: &mut model::Context,
 ) -> error::Result<model::Value> {
     eval_expr(expr, document.as_node(), context)
 }
 
+pub fn node(
+    expr: &expr::Expr,
+    node: dom::XmlNode,
+    context: &mut model::Context,
+) -> error::Result<model::Value> {
+    eval_expr(expr, node, context)
+}
+
 // -----------------------------------------------------------------------------------------------
 
 fn eval_expr(
@@ -206,10 +215,7 @@ fn eval_path_expr(
         expr::PathExpr::Path(filter, location) => {
             eval_filtered_loc_expr(filter, location, node.clone(), context)?.as_value()
         }
-        expr::PathExpr::Root => match node {
-            dom::XmlNode::Document(_) => vec![node].as_value(),
-            _ => vec![node.owner_document().unwrap().as_node()].as_value(),
-        },
+        expr::PathExpr::Root => vec![root_node(node)].as_value(),
     };
 
     Ok(nodes)
@@ -284,10 +290,7 @@ fn eval_filtered_loc_expr(
                     .collect(),
             }
         } else {
-            let root = match node {
-                dom::XmlNode::Document(_) => node,
-                _ => node.owner_document().unwrap().as_node(),
-            };
+            let root = root_node(node);
             match op {
                 expr::LocationPathOperator::Current => vec![root],
                 expr::LocationPathOperator::DescendantOrSelfNode => descendant_and_self(root),
@@ -438,8 +441,8 @@ fn eval_node_test(
                 let uri_a = context
                     .get_ns_uri(Some(prefix))
                     .ok_or_else(|| error::Error::NotFoundNamespace(prefix.to_string()))?;
-                if let Some((_, _, uri_b)) = node.as_expanded_name()? {
-                    Ok(Some(uri_a) == uri_b.as_deref())
+                if let Some(name) = node.as_expanded_name()? {
+                    Ok(Some(uri_a) == name.namespace_uri())
                 } else {
                     Ok(false)
                 }
@@ -475,12 +478,16 @@ fn eval_func_expr(
     node: dom::XmlNode,
     context: &mut model::Context,
 ) -> error::Result<model::Value> {
-    let (local_part, _, uri) = context.expanded_name(func.name())?;
+    let name = match context.expanded_name(func.name()) {
+        Ok(v) => v,
+        Err(e) => exslt::expanded_name(func.name()).ok_or(e)?,
+    };
+    let local_part = name.local_name();
 
     let table = func::table();
     let entry = table
         .iter()
-        .find(|v| v.local_part() == local_part && v.namespace_uri() == uri.as_deref())
+        .find(|v| v.local_part() == local_part && v.namespace_uri() == name.namespace_uri())
         .ok_or_else(|| error::Error::NotFoundFunction(local_part.to_string()))?;
 
     if func.args().len() < entry.min_args() || entry.max_args() < func.args().len() {
@@ -556,6 +563,22 @@ fn descendant_and_self(node: dom::XmlNode) -> Vec<dom::XmlNode> {
     nodes
 }
 
+fn root_node(node: dom::XmlNode) -> dom::XmlNode {
+    if let dom::XmlNode::Document(_) = node {
+        return node;
+    }
+
+    if let Some(document) = node.owner_document() {
+        return document.as_node();
+    }
+
+    let mut root = node;
+    while let Some(parent) = root.parent_node() {
+        root = parent;
+    }
+    root
+}
+
 fn following(node: dom::XmlNode) -> Vec<dom::XmlNode> {
     let mut nodes = vec![];
 
@@ -961,9 +984,8 @@ fn equal_qname(
     node: dom::XmlNode,
     context: &model::Context,
 ) -> error::Result<bool> {
-    if let Some((local_part_a, _, uri_a)) = node.as_expanded_name()? {
-        let (local_part_b, _, uri_b) = context.expanded_name(qname)?;
-        Ok(local_part_a == local_part_b && uri_a == uri_b)
+    if let Some(name) = node.as_expanded_name()? {
+        Ok(name == context.expanded_name(qname)?)
     } else {
         Ok(false)
     }
@@ -987,7 +1009,7 @@ mod tests {
         );
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1008,7 +1030,7 @@ mod tests {
 
         let root = doc.get_elements_by_tag_name("root").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1029,7 +1051,7 @@ mod tests {
 
         let root = doc.get_elements_by_tag_name("root").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1050,7 +1072,7 @@ mod tests {
 
         let root = doc.get_elements_by_tag_name("root").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1071,7 +1093,7 @@ mod tests {
 
         let e2 = doc.get_elements_by_tag_name("e2").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1092,7 +1114,7 @@ mod tests {
 
         let ee2 = doc.get_elements_by_tag_name("ee2").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1112,7 +1134,7 @@ mod tests {
         let root = doc.document_element().unwrap();
         let e1 = root.child_nodes().item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1131,7 +1153,7 @@ mod tests {
 
         let ee1 = doc.get_elements_by_tag_name("ee1").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1152,7 +1174,7 @@ mod tests {
 
         let root = doc.get_elements_by_tag_name("root").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1173,7 +1195,7 @@ mod tests {
 
         let root = doc.get_elements_by_tag_name("root").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1194,7 +1216,7 @@ mod tests {
 
         let e2 = doc.get_elements_by_tag_name("e2").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1215,7 +1237,7 @@ mod tests {
 
         let e2 = doc.get_elements_by_tag_name("e2").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1236,7 +1258,7 @@ mod tests {
 
         let ee2 = doc.get_elements_by_tag_name("ee2").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1257,7 +1279,7 @@ mod tests {
 
         let ee2 = doc.get_elements_by_tag_name("ee2").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1278,7 +1300,7 @@ mod tests {
 
         let ee3 = doc.get_elements_by_tag_name("ee3").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1299,7 +1321,7 @@ mod tests {
 
         let e3 = doc.get_elements_by_tag_name("e3").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1321,7 +1343,7 @@ mod tests {
 
         let e2 = doc.get_elements_by_tag_name("e2").item(1).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut context).unwrap();
+        let r = document(&expr, &doc, &mut context).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1342,7 +1364,7 @@ mod tests {
 
         let root = doc.get_elements_by_tag_name("root").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1363,7 +1385,7 @@ mod tests {
 
         let ee1 = doc.get_elements_by_tag_name("ee1").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1384,7 +1406,7 @@ mod tests {
 
         let e1 = doc.get_elements_by_tag_name("e1").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1405,7 +1427,7 @@ mod tests {
 
         let e2 = doc.get_elements_by_tag_name("e2").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1426,7 +1448,7 @@ mod tests {
 
         let ee2 = doc.get_elements_by_tag_name("ee2").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1447,7 +1469,7 @@ mod tests {
 
         let e2 = doc.get_elements_by_tag_name("e2").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1468,7 +1490,7 @@ mod tests {
 
         let e2 = doc.get_elements_by_tag_name("e2").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1489,7 +1511,7 @@ mod tests {
 
         let root = doc.get_elements_by_tag_name("root").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1508,7 +1530,7 @@ mod tests {
 
         let mut ctx = model::Context::default();
         ctx.push_size(1);
-        let r = document(&expr, doc.clone(), &mut ctx).unwrap();
+        let r = document(&expr, &doc, &mut ctx).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -1527,7 +1549,7 @@ mod tests {
 
         let mut ctx = model::Context::default();
         ctx.push_position(1);
-        let r = document(&expr, doc.clone(), &mut ctx).unwrap();
+        let r = document(&expr, &doc, &mut ctx).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -1546,7 +1568,7 @@ mod tests {
         );
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -1563,7 +1585,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Text(n) = r {
             n
         } else {
@@ -1583,7 +1605,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root xmlns='http://test/'></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut context).unwrap();
+        let r = document(&expr, &doc, &mut context).unwrap();
         let ret = if let model::Value::Text(n) = r {
             n
         } else {
@@ -1603,7 +1625,7 @@ mod tests {
         let (rest, doc) = parse_xml("<a:root xmlns:a='http://test/a'></a:root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut context).unwrap();
+        let r = document(&expr, &doc, &mut context).unwrap();
         let ret = if let model::Value::Text(n) = r {
             n
         } else {
@@ -1620,7 +1642,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root>text1</root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Text(n) = r {
             n
         } else {
@@ -1637,7 +1659,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root>text1</root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Text(n) = r {
             n
         } else {
@@ -1654,7 +1676,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root>text1</root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(n) = r {
             n
         } else {
@@ -1671,7 +1693,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root>text1</root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(n) = r {
             n
         } else {
@@ -1688,7 +1710,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root>text1</root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Text(n) = r {
             n
         } else {
@@ -1705,7 +1727,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root>text1</root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Text(n) = r {
             n
         } else {
@@ -1722,7 +1744,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root>text1</root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Text(n) = r {
             n
         } else {
@@ -1739,7 +1761,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root>text1</root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -1756,7 +1778,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root> te  x t   1 </root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Text(n) = r {
             n
         } else {
@@ -1773,7 +1795,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root>--abcd--</root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Text(n) = r {
             n
         } else {
@@ -1790,7 +1812,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root />");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(n) = r {
             n
         } else {
@@ -1807,7 +1829,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root />");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(n) = r {
             n
         } else {
@@ -1824,7 +1846,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root />");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(n) = r {
             n
         } else {
@@ -1841,7 +1863,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root />");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(n) = r {
             n
         } else {
@@ -1860,7 +1882,7 @@ mod tests {
 
         let root = doc.get_elements_by_tag_name("root").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -1877,7 +1899,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root />");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -1894,7 +1916,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root><e>1</e><e>3</e><e>5</e></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -1911,7 +1933,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root />");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -1928,7 +1950,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root />");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -1945,7 +1967,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root />");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -1954,6 +1976,124 @@ mod tests {
         assert_eq!(4f64, ret);
     }
 
+    #[test]
+    fn test_func_exslt_strings() {
+        let (rest, doc) = parse_xml("<root><a>x</a><a>y</a></root>");
+        assert_eq!("", rest);
+
+        let eval = |v: &str| {
+            let (rest, expr) = parse(v).unwrap();
+            assert_eq!("", rest);
+            let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
+            String::try_from(&r).unwrap()
+        };
+
+        assert_eq!("3", eval("count(str:tokenize('a, b c', ', '))"));
+        assert_eq!("b", eval("str:tokenize('a, b c', ', ')[2]"));
+        assert_eq!("2", eval("count(str:split('a--b', '--'))"));
+        assert_eq!("xy", eval("str:concat(//a)"));
+        assert_eq!("-=-=-", eval("str:padding(5, '-=')"));
+        assert_eq!("", eval("str:padding(0, '-')"));
+        assert_eq!("--ab", eval("str:align('ab', '----', 'right')"));
+        assert_eq!("-ab-", eval("str:align('ab', '----', 'center')"));
+
+        let err = |v: &str| {
+            let (rest, expr) = parse(v).unwrap();
+            assert_eq!("", rest);
+            document(&expr, &doc, &mut model::Context::default()).unwrap_err()
+        };
+
+        for v in [
+            "str:padding(1000000000000000, 'x')",
+            "str:padding(-1, 'x')",
+            "str:padding(number('a'), 'x')",
+        ] {
+            assert!(matches!(err(v), error::Error::InvalidArgument(_)), "{}", v);
+        }
+    }
+
+    #[test]
+    fn test_func_exslt_math() {
+        let (rest, doc) = parse_xml("<root><a>3</a><a>1</a><a>3</a></root>");
+        assert_eq!("", rest);
+
+        let eval = |v: &str| {
+            let (rest, expr) = parse(v).unwrap();
+            assert_eq!("", rest);
+            let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
+            String::try_from(&r).unwrap()
+        };
+
+        assert_eq!("1", eval("math:min(//a)"));
+        assert_eq!("3", eval("math:max(//a)"));
+        assert_eq!("2", eval("count(math:highest(//a))"));
+        assert_eq!("1", eval("count(math:lowest(//a))"));
+        assert_eq!("NaN", eval("math:max(/none)"));
+        assert_eq!("8", eval("math:power(2, 3)"));
+        assert_eq!("2", eval("math:abs(-2)"));
+        assert_eq!("3.14", eval("math:constant('PI', 2)"));
+        assert_eq!("true", eval("math:random() < 1"));
+    }
+
+    #[test]
+    fn test_func_exslt_dates_and_times() {
+        let (rest, doc) = parse_xml("<root />");
+        assert_eq!("", rest);
+
+        let eval = |v: &str| {
+            let (rest, expr) = parse(v).unwrap();
+            assert_eq!("", rest);
+            let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
+            String::try_from(&r).unwrap()
+        };
+
+        let v = "'2024-02-29T13:05:09.5+09:00'";
+        assert_eq!("2024-02-29+09:00", eval(&format!("date:date({})", v)));
+        assert_eq!("13:05:09.5+09:00", eval(&format!("date:time({})", v)));
+        assert_eq!("2024", eval(&format!("date:year({})", v)));
+        assert_eq!("2", eval(&format!("date:month-in-year({})", v)));
+        assert_eq!("29", eval(&format!("date:day-in-month({})", v)));
+        assert_eq!("60", eval(&format!("date:day-in-year({})", v)));
+        assert_eq!("5", eval(&format!("date:day-in-week({})", v)));
+        assert_eq!("13", eval(&format!("date:hour-in-day({})", v)));
+        assert_eq!("5", eval(&format!("date:minute-in-hour({})", v)));
+        assert_eq!("9.5", eval(&format!("date:second-in-minute({})", v)));
+        assert_eq!("true", eval(&format!("date:leap-year({})", v)));
+        assert_eq!("false", eval("date:leap-year('1900-01-01')"));
+        assert_eq!("NaN", eval("date:year('2023-02-29')"));
+        assert_eq!("", eval("date:date('10:00:00')"));
+        assert_eq!("20", eval("string-length(date:date-time())"));
+    }
+
+    #[test]
+    fn test_func_exslt_sets() {
+        let (rest, doc) = parse_xml("<root><a>x</a><b>y</b><c>x</c><d>z</d></root>");
+        assert_eq!("", rest);
+
+        let eval = |v: &str| {
+            let (rest, expr) = parse(v).unwrap();
+            assert_eq!("", rest);
+            let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
+            r.to_string()
+        };
+
+        assert_eq!(
+            "<a>x</a><d>z</d>",
+            eval("set:difference(/root/*, /root/b | /root/c)")
+        );
+        assert_eq!("<b>y</b>", eval("set:intersection(/root/*, /root/b)"));
+        assert_eq!("<a>x</a><b>y</b><d>z</d>", eval("set:distinct(/root/*)"));
+        assert_eq!("true", eval("set:has-same-node(/root/*, /root/d)"));
+        assert_eq!("<a>x</a><b>y</b>", eval("set:leading(/root/*, /root/c)"));
+        assert_eq!("<d>z</d>", eval("set:trailing(/root/*, /root/c)"));
+
+        let mut context = model::Context::default();
+        context.add_ns(Some("s"), exslt::SETS);
+        let (_, expr) = parse("count(s:distinct(/root/*))").unwrap();
+        let r = document(&expr, &doc, &mut context).unwrap();
+        assert_eq!(3f64, f64::try_from(&r).unwrap());
+    }
+
     #[test]
     fn test_or_expr_true() {
         let (rest, expr) = parse("1 or 0").unwrap();
@@ -1962,7 +2102,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -1979,7 +2119,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -1996,7 +2136,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -2013,7 +2153,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -2030,7 +2170,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -2047,7 +2187,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -2064,7 +2204,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -2081,7 +2221,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -2098,7 +2238,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -2115,7 +2255,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -2132,7 +2272,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -2149,7 +2289,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -2166,7 +2306,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -2183,7 +2323,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -2200,7 +2340,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -2217,7 +2357,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Boolean(b) = r {
             b
         } else {
@@ -2234,7 +2374,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -2251,7 +2391,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -2268,7 +2408,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -2285,7 +2425,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -2302,7 +2442,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -2319,7 +2459,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Number(n) = r {
             n
         } else {
@@ -2336,7 +2476,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let ret = if let model::Value::Text(t) = r {
             t
         } else {
@@ -2361,7 +2501,7 @@ mod tests {
         let e1 = children.next().unwrap();
         let e2 = children.next().unwrap();
 
-        let r = document(&expr, doc.clone(), &mut context).unwrap();
+        let r = document(&expr, &doc, &mut context).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -2389,7 +2529,7 @@ mod tests {
             .first_child()
             .unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -2415,7 +2555,7 @@ mod tests {
             .first_child()
             .unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -2441,7 +2581,7 @@ mod tests {
             .first_child()
             .unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -2467,7 +2607,7 @@ mod tests {
             .first_child()
             .unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -2491,7 +2631,7 @@ mod tests {
 
         let ee2 = doc.get_elements_by_tag_name("ee2").item(0).unwrap();
 
-        let r = document(&expr, doc.clone(), &mut model::Context::default()).unwrap();
+        let r = document(&expr, &doc, &mut model::Context::default()).unwrap();
         let nodes = if let model::Value::Node(n) = r {
             n
         } else {
@@ -2,7 +2,7 @@ use super::error;
 use std::cmp;
 use std::fmt;
 use std::ops;
-use xml_dom::{AsStringValue, ExpandedName, XmlNode};
+use xml_dom::{AsNode, AsStringValue, QName, XmlDocument, XmlNode};
 use xml_nom as nom;
 
 // -----------------------------------------------------------------------------------------------
@@ -12,9 +12,23 @@ pub struct Context {
     size: Vec<usize>,
     position: Vec<usize>,
     namespaces: Vec<(Option<String>, String)>,
+    documents: Vec<XmlDocument>,
 }
 
 impl Context {
+    pub fn push_document(&mut self, document: XmlDocument) {
+        // Nodes only hold their document weakly, so result trees built during
+        // evaluation are kept alive for the lifetime of the context.
+        let node = document.as_node();
+        if !self
+            .documents
+            .iter()
+            .any(|v| v.as_node().is_same_node(&node))
+        {
+            self.documents.push(document);
+        }
+    }
+
     pub fn get_position(&self) -> usize {
         *self.position.last().unwrap_or(&0)
     }
@@ -56,21 +70,19 @@ impl Context {
         self.namespaces.retain(|v| v.0.as_deref() != prefix);
     }
 
-    pub fn expanded_name(&self, qname: &nom::model::QName) -> error::Result<ExpandedName> {
+    pub fn expanded_name(&self, qname: &nom::model::QName) -> error::Result<QName> {
         match qname {
             nom::model::QName::Prefixed(p) => {
-                let local_part = p.local_part.to_string();
-                let prefix = Some(p.prefix.to_string());
                 let (_, uri) = self
                     .namespaces
                     .iter()
-                    .find(|v| v.0 == prefix)
+                    .find(|v| v.0.as_deref() == Some(p.prefix))
                     .ok_or_else(|| error::Error::NotFoundNamespace(p.prefix.to_string()))?;
-                Ok((local_part, prefix, Some(uri.to_string())))
+                Ok(QName::new(p.local_part, Some(p.prefix), Some(uri.as_str())))
             }
             nom::model::QName::Unprefixed(u) => {
                 let uri = self.namespaces.iter().find(|v| v.0.is_none());
-                Ok((u.to_string(), None, uri.map(|v| v.1.to_string())))
+                Ok(QName::new(u, None, uri.map(|v| v.1.as_str())))
             }
         }
     }
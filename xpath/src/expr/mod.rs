@@ -12,7 +12,7 @@ use xml_nom::{ncname, qname};
 
 // -----------------------------------------------------------------------------------------------
 
-pub fn parse(input: &str) -> IResult<&str, model::Expr> {
+pub fn parse(input: &str) -> IResult<&str, model::Expr<'_>> {
     expr(input)
 }
 
@@ -23,7 +23,7 @@ pub fn parse(input: &str) -> IResult<&str, model::Expr> {
 /// [\[3\] RelativeLocationPath](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-RelativeLocationPath)
 ///
 /// [\[11\] AbbreviatedRelativeLocationPath](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-AbbreviatedRelativeLocationPath)
-fn relative_location_path(input: &str) -> IResult<&str, model::RelativeLocationPath> {
+fn relative_location_path(input: &str) -> IResult<&str, model::RelativeLocationPath<'_>> {
     map(
         tuple((
             step,
@@ -48,7 +48,7 @@ fn relative_location_path(input: &str) -> IResult<&str, model::RelativeLocationP
 /// [\[4\] Step](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-Step)
 ///
 /// [\[12\] AbbreviatedStep](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-AbbreviatedStep)
-fn step(input: &str) -> IResult<&str, model::Step> {
+fn step(input: &str) -> IResult<&str, model::Step<'_>> {
     alt((
         map(tag(".."), |_| model::Step::Parent),
         map(char('.'), |_| model::Step::Current),
@@ -113,7 +113,7 @@ fn axis_name(input: &str) -> IResult<&str, model::AxisName> {
 /// NameTest | NodeType '(' ')' | 'processing-instruction' '(' Literal ')'
 ///
 /// [\[7\] NodeTest](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-NodeTest)
-fn node_test(input: &str) -> IResult<&str, model::NodeTest> {
+fn node_test(input: &str) -> IResult<&str, model::NodeTest<'_>> {
     alt((
         map(
             delimited(
@@ -142,7 +142,7 @@ fn node_test(input: &str) -> IResult<&str, model::NodeTest> {
 /// '[' PredicateExpr ']'
 ///
 /// [\[8\] Predicate](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-Predicate)
-fn predicate(input: &str) -> IResult<&str, model::PredicateExpr> {
+fn predicate(input: &str) -> IResult<&str, model::PredicateExpr<'_>> {
     delimited(
         tuple((char('['), multispace0)),
         predicate_expr,
@@ -153,21 +153,21 @@ fn predicate(input: &str) -> IResult<&str, model::PredicateExpr> {
 /// Expr
 ///
 /// [\[9\] PredicateExpr](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-PredicateExpr)
-fn predicate_expr(input: &str) -> IResult<&str, model::PredicateExpr> {
+fn predicate_expr(input: &str) -> IResult<&str, model::PredicateExpr<'_>> {
     expr(input)
 }
 
 /// OrExpr
 ///
 /// [[14] Expr](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-Expr)
-fn expr(input: &str) -> IResult<&str, model::Expr> {
+fn expr(input: &str) -> IResult<&str, model::Expr<'_>> {
     or_expr(input)
 }
 
 /// VariableReference | '(' Expr ')' | Literal | Number | FunctionCall
 ///
 /// [\[15\] PrimaryExpr](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-PrimaryExpr)
-fn primary_expr(input: &str) -> IResult<&str, model::PrimaryExpr> {
+fn primary_expr(input: &str) -> IResult<&str, model::PrimaryExpr<'_>> {
     alt((
         map(variable_reference, model::PrimaryExpr::from),
         map(
@@ -187,7 +187,7 @@ fn primary_expr(input: &str) -> IResult<&str, model::PrimaryExpr> {
 /// FunctionName '(' ( Argument ( ',' Argument )* )? ')'
 ///
 /// [\[16\] FunctionCall](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-FunctionCall)
-fn function_call(input: &str) -> IResult<&str, model::FunctionCall> {
+fn function_call(input: &str) -> IResult<&str, model::FunctionCall<'_>> {
     map(
         tuple((
             function_name,
@@ -204,14 +204,14 @@ fn function_call(input: &str) -> IResult<&str, model::FunctionCall> {
 /// Expr
 ///
 /// [\[17\] Argument](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-Argument)
-fn argument(input: &str) -> IResult<&str, model::Argument> {
+fn argument(input: &str) -> IResult<&str, model::Argument<'_>> {
     expr(input)
 }
 
 /// PathExpr | UnionExpr '|' PathExpr
 ///
 /// [\[18\] UnionExpr](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-UnionExpr)
-fn union_expr(input: &str) -> IResult<&str, model::UnionExpr> {
+fn union_expr(input: &str) -> IResult<&str, model::UnionExpr<'_>> {
     map(
         separated_list1(tuple((multispace0, tag("|"), multispace0)), path_expr),
         model::UnionExpr::from,
@@ -232,7 +232,7 @@ fn union_expr(input: &str) -> IResult<&str, model::UnionExpr> {
 /// [\[10\] AbbreviatedAbsoluteLocationPath](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-AbbreviatedAbsoluteLocationPath)
 ///
 /// [\[19\] PathExpr](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-UnionExpr)
-fn path_expr(input: &str) -> IResult<&str, model::PathExpr> {
+fn path_expr(input: &str) -> IResult<&str, model::PathExpr<'_>> {
     alt((
         map(
             tuple((
@@ -272,7 +272,7 @@ fn path_expr(input: &str) -> IResult<&str, model::PathExpr> {
 /// PrimaryExpr | FilterExpr Predicate
 ///
 /// [\[20\] FilterExpr](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-FilterExpr)
-fn filter_expr(input: &str) -> IResult<&str, model::FilterExpr> {
+fn filter_expr(input: &str) -> IResult<&str, model::FilterExpr<'_>> {
     map(
         tuple((primary_expr, many0(preceded(multispace0, predicate)))),
         model::FilterExpr::from,
@@ -282,7 +282,7 @@ fn filter_expr(input: &str) -> IResult<&str, model::FilterExpr> {
 /// AndExpr | OrExpr 'or' AndExpr
 ///
 /// [\[21\] OrExpr](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-OrExpr)
-fn or_expr(input: &str) -> IResult<&str, model::OrExpr> {
+fn or_expr(input: &str) -> IResult<&str, model::OrExpr<'_>> {
     map(
         separated_list1(tuple((multispace0, tag("or"), multispace0)), and_expr),
         model::OrExpr::from,
@@ -292,7 +292,7 @@ fn or_expr(input: &str) -> IResult<&str, model::OrExpr> {
 /// EqualityExpr | AndExpr 'and' EqualityExpr
 ///
 /// [\[22\] AndExpr](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-AndExpr)
-fn and_expr(input: &str) -> IResult<&str, model::AndExpr> {
+fn and_expr(input: &str) -> IResult<&str, model::AndExpr<'_>> {
     map(
         separated_list1(tuple((multispace0, tag("and"), multispace0)), equality_expr),
         model::AndExpr::from,
@@ -302,7 +302,7 @@ fn and_expr(input: &str) -> IResult<&str, model::AndExpr> {
 /// RelationalExpr | EqualityExpr '=' RelationalExpr | EqualityExpr '!=' RelationalExpr
 ///
 /// [\[23\] EqualityExpr](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-EqualityExpr)
-fn equality_expr(input: &str) -> IResult<&str, model::EqualityExpr> {
+fn equality_expr(input: &str) -> IResult<&str, model::EqualityExpr<'_>> {
     map(
         tuple((
             relation_expr,
@@ -324,7 +324,7 @@ fn equality_expr(input: &str) -> IResult<&str, model::EqualityExpr> {
 /// RelationalExpr '<=' AdditiveExpr | RelationalExpr '>=' AdditiveExpr
 ///
 /// [\[24\] RelationalExpr](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-RelationalExpr)
-fn relation_expr(input: &str) -> IResult<&str, model::RelationalExpr> {
+fn relation_expr(input: &str) -> IResult<&str, model::RelationalExpr<'_>> {
     map(
         tuple((
             additive_expr,
@@ -347,7 +347,7 @@ fn relation_expr(input: &str) -> IResult<&str, model::RelationalExpr> {
 /// MultiplicativeExpr | AdditiveExpr '+' MultiplicativeExpr | AdditiveExpr '-' MultiplicativeExpr
 ///
 /// [\[25\] AdditiveExpr](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-AdditiveExpr)
-fn additive_expr(input: &str) -> IResult<&str, model::AdditiveExpr> {
+fn additive_expr(input: &str) -> IResult<&str, model::AdditiveExpr<'_>> {
     map(
         tuple((
             multiplicative_expr,
@@ -370,7 +370,7 @@ fn additive_expr(input: &str) -> IResult<&str, model::AdditiveExpr> {
 /// MultiplicativeExpr 'mod' UnaryExpr
 ///
 /// [\[26\] MultiplicativeExpr](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-MultiplicativeExpr)
-fn multiplicative_expr(input: &str) -> IResult<&str, model::MultiplicativeExpr> {
+fn multiplicative_expr(input: &str) -> IResult<&str, model::MultiplicativeExpr<'_>> {
     map(
         tuple((
             unary_expr,
@@ -393,7 +393,7 @@ fn multiplicative_expr(input: &str) -> IResult<&str, model::MultiplicativeExpr>
 /// UnionExpr | '-' UnaryExpr
 ///
 /// [\[27\] UnaryExpr](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-MultiplicativeExpr)
-fn unary_expr(input: &str) -> IResult<&str, model::UnaryExpr> {
+fn unary_expr(input: &str) -> IResult<&str, model::UnaryExpr<'_>> {
     map(
         tuple((many0(terminated(tag("-"), multispace0)), union_expr)),
         model::UnaryExpr::from,
@@ -425,7 +425,7 @@ fn number(input: &str) -> IResult<&str, &str> {
 /// QName - NodeType
 ///
 /// [\[35\] FunctionName](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-FunctionName)
-fn function_name(input: &str) -> IResult<&str, QName> {
+fn function_name(input: &str) -> IResult<&str, QName<'_>> {
     // TODO:
     qname(input)
 }
@@ -433,14 +433,14 @@ fn function_name(input: &str) -> IResult<&str, QName> {
 /// '$' QName
 ///
 /// [\[36\] VariableReference](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-VariableReference)
-fn variable_reference(input: &str) -> IResult<&str, QName> {
+fn variable_reference(input: &str) -> IResult<&str, QName<'_>> {
     preceded(char('$'), qname)(input)
 }
 
 /// '*' | NCName ':' '*' | QName
 ///
 /// [\[37\] NameTest](https://triple-underscore.github.io/XML/xpath10-ja.html#NT-NameTest)
-fn name_test(input: &str) -> IResult<&str, model::NameTest> {
+fn name_test(input: &str) -> IResult<&str, model::NameTest<'_>> {
     alt((
         map(char('*'), |_| model::NameTest::All),
         map(terminated(ncname, tag(":*")), model::NameTest::from),
@@ -0,0 +1,114 @@
+use super::error;
+use super::eval;
+use super::expr;
+use std::io;
+use xml_dom as dom;
+
+pub fn rows<'a>(
+    dom: dom::XmlDocument,
+    row_path: &'a str,
+    column_paths: &[&'a str],
+    mut context: eval::model::Context,
+) -> error::Result<'a, XmlRowIter<'a>> {
+    let row = parse(row_path)?;
+    let mut columns = vec![];
+    for column_path in column_paths {
+        columns.push(parse(column_path)?);
+    }
+
+    let nodes = match eval::document(&row, &dom, &mut context)? {
+        eval::model::Value::Node(nodes) => nodes,
+        _ => Err(eval::error::Error::InvalidType)?,
+    };
+
+    // Keep the document alive, the row nodes only refer to it weakly.
+    context.push_document(dom);
+
+    Ok(XmlRowIter {
+        rows: nodes.into_iter(),
+        columns,
+        context,
+    })
+}
+
+// -----------------------------------------------------------------------------------------------
+
+pub struct XmlRowIter<'a> {
+    rows: std::vec::IntoIter<dom::XmlNode>,
+    columns: Vec<expr::model::Expr<'a>>,
+    context: eval::model::Context,
+}
+
+impl<'a> Iterator for XmlRowIter<'a> {
+    type Item = eval::error::Result<Vec<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.rows.next()?;
+        Some(self.cells(row))
+    }
+}
+
+impl<'a> XmlRowIter<'a> {
+    pub fn write_csv(
+        self,
+        mut writer: impl io::Write,
+        header: Option<&[&str]>,
+    ) -> eval::error::Result<()> {
+        if let Some(header) = header {
+            write_csv_row(&mut writer, header)?;
+        }
+
+        for row in self {
+            write_csv_row(&mut writer, row?.as_slice())?;
+        }
+
+        writer.flush().map_err(dom::error::Error::from)?;
+        Ok(())
+    }
+
+    fn cells(&mut self, row: dom::XmlNode) -> eval::error::Result<Vec<String>> {
+        let mut cells = vec![];
+        for column in self.columns.iter() {
+            let value = eval::node(column, row.clone(), &mut self.context)?;
+            cells.push(String::try_from(&value)?);
+        }
+        Ok(cells)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+
+fn parse(path: &str) -> error::Result<'_, expr::model::Expr<'_>> {
+    let (rest, expr) = expr::parse(path).map_err(|v| error::Error::ExprSyntax(v.to_string()))?;
+    if !rest.is_empty() {
+        return Err(error::Error::ExprRemain(rest));
+    }
+    Ok(expr)
+}
+
+fn write_csv_row(
+    writer: &mut impl io::Write,
+    cells: &[impl AsRef<str>],
+) -> eval::error::Result<()> {
+    let mut line = String::new();
+    for (i, cell) in cells.iter().enumerate() {
+        if i > 0 {
+            line.push(',');
+        }
+
+        let cell = cell.as_ref();
+        if cell.contains([',', '"', '\r', '\n']) {
+            line.push('"');
+            line.push_str(cell.replace('"', "\"\"").as_str());
+            line.push('"');
+        } else {
+            line.push_str(cell);
+        }
+    }
+    line.push_str("\r\n");
+
+    writer
+        .write_all(line.as_bytes())
+        .map_err(dom::error::Error::from)?;
+    Ok(())
+}
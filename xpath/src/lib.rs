@@ -1,9 +1,14 @@
 pub mod error;
 pub mod eval;
 pub mod expr;
+pub mod extract;
 
+/// Evaluates `expr` against `dom`.
+///
+/// Nodes only hold their document weakly, so the document is registered on
+/// `context` and matched nodes keep their ancestors for as long as it lives.
 pub fn query<'a>(
-    dom: xml_dom::XmlDocument,
+    dom: &xml_dom::XmlDocument,
     expr: &'a str,
     context: &mut eval::model::Context,
 ) -> error::Result<'a, eval::model::Value> {
@@ -13,6 +18,7 @@ pub fn query<'a>(
     }
 
     let v = eval::document(&q, dom, context)?;
+    context.push_document(dom.clone());
 
     Ok(v)
 }
@@ -26,7 +32,7 @@ mod tests {
         let (rest, doc) = parse_xml("<para />");
         assert_eq!("", rest);
 
-        let r = query(doc, "child::para", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "child::para", &mut eval::model::Context::default()).unwrap();
         assert_eq!("<para />", format!("{}", r));
     }
 
@@ -35,7 +41,7 @@ mod tests {
         let (rest, doc) = parse_xml("<a />");
         assert_eq!("", rest);
 
-        let r = query(doc, "child::*", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "child::*", &mut eval::model::Context::default()).unwrap();
         assert_eq!("<a />", format!("{}", r));
     }
 
@@ -45,7 +51,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::text()",
             &mut eval::model::Context::default(),
         )
@@ -59,7 +65,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::node()",
             &mut eval::model::Context::default(),
         )
@@ -73,7 +79,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root[attribute::name]",
             &mut eval::model::Context::default(),
         )
@@ -87,7 +93,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root[attribute::*]",
             &mut eval::model::Context::default(),
         )
@@ -101,7 +107,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "descendant::para",
             &mut eval::model::Context::default(),
         )
@@ -114,7 +120,12 @@ mod tests {
         let (rest, doc) = parse_xml("<root><div><para /></div></root>");
         assert_eq!("", rest);
 
-        let r = query(doc, "//ancestor::div", &mut eval::model::Context::default()).unwrap();
+        let r = query(
+            &doc,
+            "//ancestor::div",
+            &mut eval::model::Context::default(),
+        )
+        .unwrap();
         assert_eq!("<div><para /></div>", format!("{}", r));
     }
 
@@ -124,7 +135,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "//ancestor-or-self::div",
             &mut eval::model::Context::default(),
         )
@@ -138,7 +149,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "descendant-or-self::para",
             &mut eval::model::Context::default(),
         )
@@ -152,7 +163,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/para/self::para",
             &mut eval::model::Context::default(),
         )
@@ -169,7 +180,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::chapter/descendant::para",
             &mut eval::model::Context::default(),
         )
@@ -183,7 +194,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::*/child::para",
             &mut eval::model::Context::default(),
         )
@@ -196,7 +207,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = query(doc, "/", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "/", &mut eval::model::Context::default()).unwrap();
         assert_eq!("<root />", format!("{}", r));
     }
 
@@ -206,7 +217,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "/descendant::para",
             &mut eval::model::Context::default(),
         )
@@ -220,7 +231,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "/descendant::olist/child::item",
             &mut eval::model::Context::default(),
         )
@@ -234,7 +245,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::para[position()=1]",
             &mut eval::model::Context::default(),
         )
@@ -248,7 +259,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::para[position()=last()]",
             &mut eval::model::Context::default(),
         )
@@ -262,7 +273,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::para[position()=last()-1]",
             &mut eval::model::Context::default(),
         )
@@ -276,7 +287,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::para[position()>1]",
             &mut eval::model::Context::default(),
         )
@@ -293,7 +304,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/para/following-sibling::chapter[position()=1]",
             &mut eval::model::Context::default(),
         )
@@ -310,7 +321,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/para/preceding-sibling::chapter[position()=1]",
             &mut eval::model::Context::default(),
         )
@@ -327,7 +338,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "(root/para/preceding-sibling::chapter)[position()=1]",
             &mut eval::model::Context::default(),
         )
@@ -343,7 +354,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "/descendant::figure[position()=42]",
             &mut eval::model::Context::default(),
         )
@@ -357,7 +368,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "/child::doc/child::chapter[position()=2]/child::section[position()=2]",
             &mut eval::model::Context::default(),
         )
@@ -374,7 +385,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::para[attribute::type=\"warning\"]",
             &mut eval::model::Context::default(),
         )
@@ -388,7 +399,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::para[attribute::type='warning'][position()=5]",
             &mut eval::model::Context::default(),
         )
@@ -402,7 +413,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::para[position()=5][attribute::type=\"warning\"]",
             &mut eval::model::Context::default(),
         )
@@ -416,7 +427,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::chapter[child::title='Introduction']",
             &mut eval::model::Context::default(),
         )
@@ -436,7 +447,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::chapter[child::title]",
             &mut eval::model::Context::default(),
         )
@@ -453,7 +464,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::*[self::chapter or self::appendix]",
             &mut eval::model::Context::default(),
         )
@@ -470,7 +481,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/child::*[self::chapter or self::appendix][position()=last()]",
             &mut eval::model::Context::default(),
         )
@@ -483,7 +494,7 @@ mod tests {
         let (rest, doc) = parse_xml("<para></para>");
         assert_eq!("", rest);
 
-        let r = query(doc, "para", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "para", &mut eval::model::Context::default()).unwrap();
         assert_eq!("<para />", format!("{}", r));
     }
 
@@ -492,7 +503,7 @@ mod tests {
         let (rest, doc) = parse_xml("<para></para>");
         assert_eq!("", rest);
 
-        let r = query(doc, "*", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "*", &mut eval::model::Context::default()).unwrap();
         assert_eq!("<para />", format!("{}", r));
     }
 
@@ -501,7 +512,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root>a</root>");
         assert_eq!("", rest);
 
-        let r = query(doc, "root/text()", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "root/text()", &mut eval::model::Context::default()).unwrap();
         assert_eq!("a", format!("{}", r));
     }
 
@@ -510,7 +521,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root name='a'></root>");
         assert_eq!("", rest);
 
-        let r = query(doc, "root/@name", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "root/@name", &mut eval::model::Context::default()).unwrap();
         assert_eq!("name=\"a\"", format!("{}", r));
     }
 
@@ -519,7 +530,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root name='a'></root>");
         assert_eq!("", rest);
 
-        let r = query(doc, "root/@*", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "root/@*", &mut eval::model::Context::default()).unwrap();
         assert_eq!("name=\"a\"", format!("{}", r));
     }
 
@@ -528,7 +539,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root><para>2</para><para>1</para></root>");
         assert_eq!("", rest);
 
-        let r = query(doc, "root/para[1]", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "root/para[1]", &mut eval::model::Context::default()).unwrap();
         assert_eq!("<para>2</para>", format!("{}", r));
     }
 
@@ -538,7 +549,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/para[last()]",
             &mut eval::model::Context::default(),
         )
@@ -551,7 +562,7 @@ mod tests {
         let (rest, doc) = parse_xml("<para></para>");
         assert_eq!("", rest);
 
-        let r = query(doc, "/para", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "/para", &mut eval::model::Context::default()).unwrap();
         assert_eq!("<para />", format!("{}", r));
     }
 
@@ -561,7 +572,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "/doc/chapter[5]/section[2]",
             &mut eval::model::Context::default(),
         )
@@ -577,7 +588,7 @@ mod tests {
         .unwrap();
         assert_eq!("", rest);
 
-        let r = query(doc, "chapter//para", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "chapter//para", &mut eval::model::Context::default()).unwrap();
         assert_eq!(
             "<para>1</para><para>2</para><para>3</para>",
             format!("{}", r)
@@ -592,7 +603,7 @@ mod tests {
         .unwrap();
         assert_eq!("", rest);
 
-        let r = query(doc, "//para", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "//para", &mut eval::model::Context::default()).unwrap();
         assert_eq!(
             "<para>1</para><para>2</para><para>3</para>",
             format!("{}", r)
@@ -607,7 +618,7 @@ mod tests {
         .unwrap();
         assert_eq!("", rest);
 
-        let r = query(doc, "//olist/item", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "//olist/item", &mut eval::model::Context::default()).unwrap();
         assert_eq!("<item>2</item>", format!("{}", r));
     }
 
@@ -616,7 +627,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root></root>");
         assert_eq!("", rest);
 
-        let r = query(doc, ".", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, ".", &mut eval::model::Context::default()).unwrap();
         assert_eq!("<root />", format!("{}", r));
     }
 
@@ -628,7 +639,7 @@ mod tests {
         .unwrap();
         assert_eq!("", rest);
 
-        let r = query(doc, ".//para", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, ".//para", &mut eval::model::Context::default()).unwrap();
         assert_eq!(
             "<para>1</para><para>2</para><para>3</para>",
             format!("{}", r)
@@ -640,7 +651,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root><para /></root>");
         assert_eq!("", rest);
 
-        let r = query(doc, "root/para/..", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "root/para/..", &mut eval::model::Context::default()).unwrap();
         assert_eq!("<root><para /></root>", format!("{}", r));
     }
 
@@ -649,7 +660,12 @@ mod tests {
         let (rest, doc) = parse_xml("<root lang='a'><para /></root>");
         assert_eq!("", rest);
 
-        let r = query(doc, "//para/../@lang", &mut eval::model::Context::default()).unwrap();
+        let r = query(
+            &doc,
+            "//para/../@lang",
+            &mut eval::model::Context::default(),
+        )
+        .unwrap();
         assert_eq!("lang=\"a\"", format!("{}", r));
     }
 
@@ -662,7 +678,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/para[@type=\"warning\"]",
             &mut eval::model::Context::default(),
         )
@@ -676,7 +692,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/para[@type=\"warning\"][5]",
             &mut eval::model::Context::default(),
         )
@@ -690,7 +706,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/para[5][@type=\"warning\"]",
             &mut eval::model::Context::default(),
         )
@@ -704,7 +720,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/chapter[title=\"Introduction\"]",
             &mut eval::model::Context::default(),
         )
@@ -724,7 +740,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/chapter[title]",
             &mut eval::model::Context::default(),
         )
@@ -738,7 +754,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/employee[@secretary and @assistant]",
             &mut eval::model::Context::default(),
         )
@@ -755,7 +771,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/e2[namespace::a]",
             &mut eval::model::Context::default(),
         )
@@ -769,7 +785,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/e2/namespace::a",
             &mut eval::model::Context::default(),
         )
@@ -783,7 +799,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/e2[namespace::xml]",
             &mut eval::model::Context::default(),
         )
@@ -796,7 +812,7 @@ mod tests {
         let (rest, doc) = parse_xml("<root>a&amp;b<e1/><![CDATA[c]]></root>");
         assert_eq!("", rest);
 
-        let r = query(doc, "root/text()", &mut eval::model::Context::default()).unwrap();
+        let r = query(&doc, "root/text()", &mut eval::model::Context::default()).unwrap();
         assert_eq!("a&amp;b<![CDATA[c]]>", format!("{}", r));
     }
 
@@ -806,7 +822,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/text()[. = 'c']",
             &mut eval::model::Context::default(),
         )
@@ -820,7 +836,7 @@ mod tests {
         assert_eq!("", rest);
 
         let r = query(
-            doc,
+            &doc,
             "root/text()[contains(., '<')]",
             &mut eval::model::Context::default(),
         )
@@ -843,6 +859,77 @@ mod tests {
         assert_eq!("", rest);
     }
 
+    #[test]
+    fn test_extract_rows() {
+        let (_, doc) = parse_xml(
+            "<feed><item id='1'><title>a, b</title><price>10</price></item><other/><item id='2'><title>say \"hi\"</title></item></feed>",
+        );
+
+        let rows = extract::rows(
+            doc.clone(),
+            "//item",
+            &["@id", "title", "price", "count(*)"],
+            eval::model::Context::default(),
+        )
+        .unwrap()
+        .collect::<eval::error::Result<Vec<Vec<String>>>>()
+        .unwrap();
+        assert_eq!(
+            vec![
+                vec!["1", "a, b", "10", "2"],
+                vec!["2", "say \"hi\"", "", "1"],
+            ],
+            rows
+        );
+
+        let mut csv = vec![];
+        extract::rows(
+            doc.clone(),
+            "//item",
+            &["@id", "title"],
+            eval::model::Context::default(),
+        )
+        .unwrap()
+        .write_csv(&mut csv, Some(&["id", "title"]))
+        .unwrap();
+        assert_eq!(
+            "id,title\r\n1,\"a, b\"\r\n2,\"say \"\"hi\"\"\"\r\n",
+            String::from_utf8(csv).unwrap()
+        );
+
+        let err = extract::rows(doc.clone(), "count(//item)", &[], Default::default());
+        assert!(err.is_err());
+
+        let err = extract::rows(doc, "//item", &["title]"], Default::default());
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_query_result_outlives_document_handle() {
+        use xml_dom::Node;
+
+        let (rest, doc) = parse_xml("<r><a><p:b/></a></r>");
+        assert_eq!("", rest);
+
+        let mut context = eval::model::Context::default();
+        let r = query(&doc, "//a", &mut context).unwrap();
+        drop(doc);
+
+        let a = match r {
+            eval::model::Value::Node(nodes) => nodes.into_iter().next().unwrap(),
+            v => panic!("{:?}", v),
+        };
+        assert_eq!("/r[1]/a[1]", a.location_path());
+        assert!(a.owner_document().is_some());
+        let parent = a.parent_node().unwrap();
+        assert_eq!("r", parent.node_name());
+        let names = a
+            .ancestors()
+            .map(|v| v.node_name())
+            .collect::<Vec<String>>();
+        assert_eq!(vec!["r", "#document"], names);
+    }
+
     fn parse_xml(xml: &str) -> (&str, xml_dom::XmlDocument) {
         let context = xml_dom::Context::from_text_expanded(true);
         xml_dom::XmlDocument::from_raw_with_context(xml, context).unwrap()